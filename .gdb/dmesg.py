@@ -0,0 +1,47 @@
+# .gdb/dmesg.py
+# SPDX-License-Identifier: JOSSL-1.0
+# Copyright (C) 2025 The Jotunheim Project
+#
+# `dmesg` for gdb: print the kernel log ring (src/logring.rs) straight from
+# memory, so it works with the kernel wedged or stopped anywhere, through the
+# kernel's own RSP stub or QEMU's gdbstub.
+#
+#   (gdb) source .gdb/dmesg.py
+#   (gdb) dmesg            # ring found by the JOTUNHEIM_LOG symbol
+#   (gdb) dmesg 0xffff...  # or at an explicit header address, e.g. tag 1 of
+#                          # `maint packet qXfer:jotunheim-log:read::0,40`
+
+import struct
+
+import gdb
+
+MAGIC = b"JOTNLOG1"
+HDR = struct.Struct("<8sQQQ")  # magic, size, head, tail; data follows
+
+
+class Dmesg(gdb.Command):
+    """Print the kernel log held in memory. Optional argument: header address."""
+
+    def __init__(self):
+        super().__init__("dmesg", gdb.COMMAND_DATA)
+
+    def invoke(self, arg, from_tty):
+        if arg.strip():
+            addr = int(gdb.parse_and_eval(arg))
+        else:
+            addr = int(gdb.parse_and_eval("&JOTUNHEIM_LOG").cast(gdb.lookup_type("long")))
+        inf = gdb.selected_inferior()
+        magic, size, head, tail = HDR.unpack(bytes(inf.read_memory(addr, HDR.size)))
+        if magic != MAGIC:
+            raise gdb.GdbError("no log ring at %#x (magic %r)" % (addr, magic))
+        tail = max(tail, head - size, 0)
+        data = addr + HDR.size
+        start, n = tail % size, head - tail
+        first = min(n, size - start)
+        raw = bytes(inf.read_memory(data + start, first))
+        if n > first:
+            raw += bytes(inf.read_memory(data, n - first))
+        gdb.write(raw.decode("utf-8", "replace"))
+
+
+Dmesg()
@@ -0,0 +1,15 @@
+// hosttest/src/arch.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Stand-ins for the arch paths the included modules name.
+
+pub mod x86_64 {
+    pub mod simd {
+        pub mod caps {
+            /// The kernel waits for XMM state saving; a host process has it.
+            pub fn simd_ready() -> bool {
+                std::is_x86_feature_detected!("sse2")
+            }
+        }
+    }
+}
@@ -0,0 +1,6 @@
+// hosttest/src/blockdev.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+
+#[path = "../../jotunheimkernel/src/blockdev/parttab.rs"]
+pub mod parttab;
@@ -0,0 +1,17 @@
+// hosttest/src/lib.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Host-side unit tests for the kernel modules that touch no hardware. Each
+// one is compiled here from its kernel source with `#[path]`, unchanged;
+// the modules below mirror the kernel's layout so the `crate::` and
+// `super::` paths in those files resolve, with small stand-ins where a file
+// reaches for a CPU feature. Run `cargo test` in this directory on an
+// x86_64 host (net::checksum carries an SSE2 path).
+//
+// Everything that needs the real machine stays with the boot self-tests.
+
+pub mod arch;
+pub mod blockdev;
+pub mod net;
+pub mod sched;
+pub mod util;
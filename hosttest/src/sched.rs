@@ -0,0 +1,36 @@
+// hosttest/src/sched.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+
+#[path = "../../jotunheimkernel/src/sched/bandwidth.rs"]
+pub mod bandwidth;
+#[path = "../../jotunheimkernel/src/sched/edf.rs"]
+pub mod edf;
+#[path = "../../jotunheimkernel/src/sched/prio.rs"]
+pub mod prio;
+
+/* ---------------------------- Scheduler stand-ins ---------------------------- */
+
+// util::ring names this.
+
+pub mod waitq {
+    /// No kernel tasks on the host: waiters spin on their condition instead.
+    #[derive(Default)]
+    pub struct WaitQueue;
+
+    impl WaitQueue {
+        pub const fn new() -> Self {
+            Self
+        }
+
+        pub fn wait_until(&self, mut cond: impl FnMut() -> bool) {
+            while !cond() {
+                std::hint::spin_loop();
+            }
+        }
+
+        pub fn notify_one(&self) -> bool {
+            false
+        }
+    }
+}
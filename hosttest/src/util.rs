@@ -0,0 +1,10 @@
+// hosttest/src/util.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+
+#[path = "../../jotunheimkernel/src/util/crc32.rs"]
+pub mod crc32;
+#[path = "../../jotunheimkernel/src/util/lz4.rs"]
+pub mod lz4;
+#[path = "../../jotunheimkernel/src/util/ring.rs"]
+pub mod ring;
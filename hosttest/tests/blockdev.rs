@@ -0,0 +1,115 @@
+// hosttest/tests/blockdev.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use hosttest::blockdev::parttab::{self, GPT_SIG, Guid, MBR_PROTECTIVE};
+use hosttest::util::crc32::crc32;
+
+const ESP: [u8; 16] = [
+    0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B,
+];
+
+fn mbr(parts: &[(u8, u32, u32)]) -> Vec<u8> {
+    let mut s = vec![0u8; 512];
+    for (i, &(id, start, count)) in parts.iter().enumerate() {
+        let e = &mut s[446 + i * 16..446 + (i + 1) * 16];
+        e[0] = if i == 0 { 0x80 } else { 0 };
+        e[4] = id;
+        e[8..12].copy_from_slice(&start.to_le_bytes());
+        e[12..16].copy_from_slice(&count.to_le_bytes());
+    }
+    s[510] = 0x55;
+    s[511] = 0xAA;
+    s
+}
+
+// A 512-byte GPT header at `lba` over a 128-entry array with the given slots.
+fn gpt(lba: u64, slots: &[(usize, [u8; 16], u64, u64, &str)]) -> (Vec<u8>, Vec<u8>) {
+    let mut table = vec![0u8; 128 * 128];
+    for &(slot, ty, first, last, name) in slots {
+        let e = &mut table[slot * 128..(slot + 1) * 128];
+        e[0..16].copy_from_slice(&ty);
+        e[16] = slot as u8 + 1;
+        e[32..40].copy_from_slice(&first.to_le_bytes());
+        e[40..48].copy_from_slice(&last.to_le_bytes());
+        for (i, u) in name.encode_utf16().enumerate() {
+            e[56 + i * 2..58 + i * 2].copy_from_slice(&u.to_le_bytes());
+        }
+    }
+    let mut h = vec![0u8; 512];
+    h[0..8].copy_from_slice(GPT_SIG);
+    h[12..16].copy_from_slice(&92u32.to_le_bytes());
+    h[24..32].copy_from_slice(&lba.to_le_bytes());
+    h[72..80].copy_from_slice(&2u64.to_le_bytes());
+    h[80..84].copy_from_slice(&128u32.to_le_bytes());
+    h[84..88].copy_from_slice(&128u32.to_le_bytes());
+    h[88..92].copy_from_slice(&crc32(&table).to_le_bytes());
+    let c = crc32(&h[..92]);
+    h[16..20].copy_from_slice(&c.to_le_bytes());
+    (h, table)
+}
+
+#[test]
+fn mbr_table() {
+    let t = parttab::mbr_entries(&mbr(&[(0x83, 2048, 4096), (0x05, 8192, 100)])).unwrap();
+    assert!(t[0].bootable && !t[1].bootable);
+    assert_eq!((t[0].system_id, t[0].start, t[0].count), (0x83, 2048, 4096));
+    assert_eq!((t[1].system_id, t[1].start), (0x05, 8192));
+    assert_eq!(t[2].system_id, 0);
+
+    let mut bad = mbr(&[(0x83, 1, 1)]);
+    bad[511] = 0;
+    assert!(parttab::mbr_entries(&bad).is_none());
+    assert!(parttab::mbr_entries(&[0u8; 100]).is_none());
+    let prot = parttab::mbr_entries(&mbr(&[(MBR_PROTECTIVE, 1, u32::MAX)])).unwrap();
+    assert_eq!(prot[0].system_id, MBR_PROTECTIVE);
+}
+
+#[test]
+fn gpt_header_and_entries() {
+    let (h, table) = gpt(
+        1,
+        &[(0, ESP, 34, 2081, "EFI system"), (3, ESP, 4096, 8191, "")],
+    );
+    let hdr = parttab::gpt_header(&h, 1).unwrap();
+    assert_eq!((hdr.entries_lba, hdr.count, hdr.entry_size), (2, 128, 128));
+    assert_eq!(hdr.table_bytes(), table.len());
+
+    let v = hdr.entries(&table).unwrap();
+    assert_eq!(v.len(), 2);
+    assert_eq!((v[0].index, v[0].first, v[0].last), (1, 34, 2081));
+    assert_eq!(v[0].label, "EFI system");
+    assert_eq!(
+        v[0].type_guid.to_string(),
+        "c12a7328-f81f-11d2-ba4b-00a0c93ec93b"
+    );
+    assert_eq!(v[1].index, 4);
+    assert_eq!(v[1].label, "");
+    assert_ne!(v[1].unique_guid, Guid::ZERO);
+}
+
+#[test]
+fn gpt_rejects_damage() {
+    let (h, table) = gpt(1, &[(0, ESP, 34, 2081, "x")]);
+    assert!(parttab::gpt_header(&h, 2).is_none()); // header names another LBA
+
+    let mut bad = h.clone();
+    bad[72] ^= 1; // covered by the header CRC
+    assert!(parttab::gpt_header(&bad, 1).is_none());
+
+    let hdr = parttab::gpt_header(&h, 1).unwrap();
+    let mut t = table.clone();
+    t[40] ^= 1;
+    assert!(hdr.entries(&t).is_none());
+    assert!(hdr.entries(&table[..1024]).is_none());
+}
+
+#[test]
+fn gpt_skips_inverted_ranges() {
+    let (h, table) = gpt(
+        1,
+        &[(0, ESP, 100, 50, "backwards"), (1, ESP, 200, 200, "one")],
+    );
+    let v = parttab::gpt_header(&h, 1).unwrap().entries(&table).unwrap();
+    assert_eq!(v.len(), 1);
+    assert_eq!((v[0].index, v[0].first, v[0].last), (2, 200, 200));
+}
@@ -0,0 +1,71 @@
+// hosttest/tests/net.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use hosttest::net::checksum::{checksum, finish, pseudo_v4, sum, update};
+
+// RFC 1071 straight from the text: big-endian words, end-around carry.
+fn reference(data: &[u8]) -> u16 {
+    let mut acc = 0u32;
+    for c in data.chunks(2) {
+        let w = u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]);
+        acc += w as u32;
+        acc = (acc & 0xFFFF) + (acc >> 16);
+    }
+    !(acc as u16)
+}
+
+fn bytes(len: usize) -> Vec<u8> {
+    (0..len as u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 11) as u8)
+        .collect()
+}
+
+#[test]
+fn ipv4_header_example() {
+    // Header from RFC 1071-style walkthroughs; checksum field zeroed.
+    let hdr = [
+        0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xC0, 0xA8, 0x00,
+        0x01, 0xC0, 0xA8, 0x00, 0xC7,
+    ];
+    assert_eq!(checksum(&hdr), 0xB861);
+}
+
+#[test]
+fn matches_reference_at_every_length() {
+    // Odd and even lengths on both sides of the SSE2 cutover (256 bytes).
+    for len in (0..600).chain([4095, 4096, 70_001]) {
+        let data = bytes(len);
+        assert_eq!(checksum(&data), reference(&data), "len {len}");
+    }
+}
+
+#[test]
+fn partial_sums_compose() {
+    let data = bytes(1500);
+    let acc = sum(&data[..600], 0);
+    assert_eq!(finish(sum(&data[600..], acc)), checksum(&data));
+}
+
+#[test]
+fn udp_pseudo_header() {
+    let (src, dst) = ([10, 0, 0, 1], [10, 0, 0, 2]);
+    let udp = [
+        0x30, 0x39, 0x00, 0x35, 0x00, 0x0C, 0x00, 0x00, b'p', b'i', b'n', b'g',
+    ];
+    let mut full = Vec::new();
+    full.extend_from_slice(&src);
+    full.extend_from_slice(&dst);
+    full.extend_from_slice(&[0, 17, 0, udp.len() as u8]);
+    full.extend_from_slice(&udp);
+    let got = finish(sum(&udp, pseudo_v4(src, dst, 17, udp.len() as u16)));
+    assert_eq!(got, reference(&full));
+}
+
+#[test]
+fn incremental_update_matches_recompute() {
+    let mut data = bytes(40);
+    let before = checksum(&data);
+    let old = u16::from_be_bytes([data[8], data[9]]);
+    data[8..10].copy_from_slice(&0x1234u16.to_be_bytes());
+    assert_eq!(update(before, old, 0x1234), checksum(&data));
+}
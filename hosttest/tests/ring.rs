@@ -0,0 +1,172 @@
+// hosttest/tests/ring.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use hosttest::util::ring::{BlockingRing, MpscRing, OverwriteRing, Ring};
+
+#[test]
+fn spsc_fifo_and_full() {
+    let ring: Ring<u32, 4> = Ring::new();
+    let (mut tx, mut rx) = ring.split().unwrap();
+    assert!(ring.split().is_none());
+    for i in 0..4 {
+        tx.push(i).unwrap();
+    }
+    assert!(tx.is_full());
+    assert_eq!(tx.push(9), Err(9));
+    assert_eq!(rx.pop(), Some(0));
+    tx.push(4).unwrap();
+    assert_eq!(rx.drain().collect::<Vec<_>>(), [1, 2, 3, 4]);
+    assert!(rx.is_empty());
+}
+
+#[test]
+fn spsc_wraps_many_times() {
+    let mut ring: Ring<usize, 3> = Ring::new();
+    for i in 0..1000 {
+        ring.push_mut(i).unwrap();
+        if i % 2 == 1 {
+            assert_eq!(ring.pop_mut(), Some(i - 1));
+            assert_eq!(ring.pop_mut(), Some(i));
+        }
+    }
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn spsc_drops_what_it_holds() {
+    let live = Arc::new(());
+    {
+        let ring: Ring<Arc<()>, 8> = Ring::new();
+        let (mut tx, mut rx) = ring.split().unwrap();
+        for _ in 0..5 {
+            tx.push(live.clone()).unwrap();
+        }
+        drop(rx.pop());
+        assert_eq!(Arc::strong_count(&live), 5);
+    }
+    assert_eq!(Arc::strong_count(&live), 1);
+}
+
+#[test]
+fn spsc_across_threads() {
+    const N: u64 = 10_000;
+    let ring: Ring<u64, 16> = Ring::new();
+    let (mut tx, mut rx) = ring.split().unwrap();
+    thread::scope(|s| {
+        s.spawn(move || {
+            for i in 0..N {
+                let mut v = i;
+                while let Err(back) = tx.push(v) {
+                    v = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0;
+        while next < N {
+            match rx.pop() {
+                Some(v) => {
+                    assert_eq!(v, next);
+                    next += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+    });
+}
+
+#[test]
+fn mpsc_keeps_each_producers_order() {
+    const PER: usize = 5_000;
+    let ring: MpscRing<(usize, usize), 32> = MpscRing::new();
+    let mut rx = ring.consumer().unwrap();
+    assert!(ring.consumer().is_none());
+    thread::scope(|s| {
+        for p in 0..4 {
+            let ring = &ring;
+            s.spawn(move || {
+                for i in 0..PER {
+                    while ring.push((p, i)).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+        }
+        let mut next = [0; 4];
+        let mut got = 0;
+        while got < 4 * PER {
+            match rx.pop() {
+                Some((p, i)) => {
+                    assert_eq!(i, next[p]);
+                    next[p] += 1;
+                    got += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+    });
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn blocking_consumer_waits_for_data() {
+    let ring: BlockingRing<u32, 8> = BlockingRing::new();
+    let mut rx = ring.consumer().unwrap();
+    let sum = AtomicUsize::new(0);
+    thread::scope(|s| {
+        s.spawn(|| {
+            for _ in 0..100 {
+                sum.fetch_add(rx.pop_wait() as usize, Ordering::Relaxed);
+            }
+        });
+        for i in 0..100 {
+            while ring.push(i).is_err() {
+                thread::yield_now();
+            }
+        }
+    });
+    assert_eq!(sum.load(Ordering::Relaxed), 4950);
+}
+
+#[test]
+fn overwrite_evicts_oldest() {
+    let ring: OverwriteRing<u32, 3> = OverwriteRing::new();
+    assert_eq!(ring.front(), None);
+    for i in 0..3 {
+        assert_eq!(ring.push(i), None);
+    }
+    assert_eq!(ring.push(3), Some(0));
+    assert_eq!(ring.try_push(4), Ok(Some(1)));
+    assert_eq!(ring.dropped(), 2);
+    assert_eq!(ring.front(), Some(2));
+
+    let mut seen = Vec::new();
+    assert!(ring.try_for_each(|&v| seen.push(v)));
+    assert_eq!(seen, [2, 3, 4]);
+    assert_eq!(ring.len(), 3);
+
+    assert_eq!(ring.pop(), Some(2));
+    ring.push(5);
+    seen.clear();
+    ring.try_for_each(|&v| seen.push(v));
+    assert_eq!(seen, [3, 4, 5]);
+    while ring.pop().is_some() {}
+    assert!(ring.is_empty());
+    assert_eq!(ring.front(), None);
+}
+
+#[test]
+fn overwrite_try_ops_back_off_when_locked() {
+    let ring: OverwriteRing<u32, 4> = OverwriteRing::new();
+    ring.push(1);
+    // A fault taken while the shell walks the ring must not deadlock.
+    assert!(ring.try_for_each(|_| {
+        assert_eq!(ring.try_push(2), Err(2));
+        assert!(!ring.try_for_each(|_| {}));
+    }));
+    assert_eq!(ring.len(), 1);
+}
@@ -0,0 +1,126 @@
+// hosttest/tests/sched.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use hosttest::sched::bandwidth::{Bandwidth, CpuLimit};
+use hosttest::sched::edf::{AdmissionError, DeadlineParams, DlEntity, UTIL_MAX, admit};
+use hosttest::sched::prio::Priority;
+
+fn dl(runtime: u64, deadline: u64, period: u64) -> DeadlineParams {
+    DeadlineParams {
+        runtime,
+        deadline,
+        period,
+    }
+}
+
+#[test]
+fn edf_rejects_bad_params() {
+    assert_eq!(dl(0, 10, 10).validate(), Err(AdmissionError::Invalid));
+    assert_eq!(dl(11, 10, 20).validate(), Err(AdmissionError::Invalid));
+    assert_eq!(dl(5, 30, 20).validate(), Err(AdmissionError::Invalid));
+    assert_eq!(dl(5, 10, 20).validate(), Ok(()));
+}
+
+#[test]
+fn edf_admission_stops_at_util_max() {
+    let half = dl(50, 100, 100);
+    let reserved = admit(0, &half).unwrap();
+    assert_eq!(reserved, UTIL_MAX * 5 / 9);
+    let reserved = admit(reserved, &dl(40, 100, 100)).unwrap();
+    assert_eq!(reserved, UTIL_MAX);
+    assert_eq!(
+        admit(reserved, &dl(1, 100, 100)),
+        Err(AdmissionError::Overloaded)
+    );
+}
+
+#[test]
+fn edf_budget_and_replenish() {
+    let mut e = DlEntity::new(dl(2, 5, 10), 100);
+    assert_eq!(e.abs_deadline, 105);
+    assert!(!e.charge());
+    assert!(e.charge());
+    assert!(!e.has_budget());
+    assert!(!e.replenish(109));
+    assert!(e.replenish(110));
+    assert!(e.has_budget());
+    assert_eq!(e.abs_deadline, 115);
+}
+
+#[test]
+fn edf_skips_periods_missed_while_blocked() {
+    let mut e = DlEntity::new(dl(2, 5, 10), 0);
+    assert!(e.replenish(47));
+    assert_eq!(e.abs_deadline, 52); // restarted at `now`, not caught up
+}
+
+#[test]
+fn edf_reports_a_miss_once_per_period() {
+    let mut e = DlEntity::new(dl(2, 5, 10), 0);
+    assert!(!e.check_miss(4));
+    assert!(e.check_miss(5));
+    assert!(!e.check_miss(6));
+    e.replenish(10);
+    e.charge();
+    e.charge();
+    assert!(!e.check_miss(15)); // budget spent, nothing missed
+}
+
+#[test]
+fn bandwidth_percent_limits() {
+    assert_eq!(
+        CpuLimit::percent(25, 100),
+        CpuLimit {
+            quota: 25,
+            period: 100
+        }
+    );
+    assert_eq!(CpuLimit::percent(0, 100).quota, 1);
+    assert_eq!(CpuLimit::percent(200, 100).quota, 100);
+    assert_eq!(CpuLimit::percent(1, 10).quota, 1);
+    assert!(
+        !CpuLimit {
+            quota: 0,
+            period: 10
+        }
+        .is_valid()
+    );
+    assert!(
+        !CpuLimit {
+            quota: 11,
+            period: 10
+        }
+        .is_valid()
+    );
+}
+
+#[test]
+fn bandwidth_throttles_and_refills() {
+    let mut b = Bandwidth::new(
+        CpuLimit {
+            quota: 2,
+            period: 10,
+        },
+        0,
+    );
+    assert!(!b.charge());
+    assert!(b.charge());
+    assert!(b.exhausted());
+    assert!(!b.refresh(9));
+    assert!(b.refresh(10));
+    assert!(!b.exhausted());
+    // Three idle periods later the next boundary is still on the grid.
+    assert!(b.refresh(45));
+    assert!(!b.refresh(49));
+    assert!(b.refresh(50));
+}
+
+#[test]
+fn prio_rank_puts_fifo_above_normal() {
+    assert!(Priority::Fifo(1).rank() > Priority::Normal(39).rank());
+    assert!(Priority::Fifo(50).rank() > Priority::Fifo(10).rank());
+    assert!(Priority::Normal(30).rank() > Priority::Normal(0).rank());
+    assert!(!Priority::Fifo(0).is_valid());
+    assert!(!Priority::Normal(40).is_valid());
+    assert_eq!(Priority::Normal(5).aged(25), 7);
+}
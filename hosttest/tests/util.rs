@@ -0,0 +1,144 @@
+// hosttest/tests/util.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use hosttest::util::crc32::crc32;
+use hosttest::util::lz4::{self, Lz4Error};
+
+// Compressible but not trivially so: repeated phrases with a counter mixed in.
+fn sample(len: usize) -> Vec<u8> {
+    let mut v = Vec::with_capacity(len);
+    let mut i = 0u32;
+    while v.len() < len {
+        v.extend_from_slice(b"jotunheim kernel log line ");
+        v.extend_from_slice(&(i % 7).to_le_bytes());
+        i += 1;
+    }
+    v.truncate(len);
+    v
+}
+
+fn roundtrip(src: &[u8]) {
+    let mut packed = vec![0u8; lz4::bound(src.len())];
+    let n = lz4::compress(src, &mut packed).unwrap();
+    let mut out = vec![0u8; src.len()];
+    assert_eq!(lz4::decompress(&packed[..n], &mut out), Ok(src.len()));
+    assert_eq!(out, src);
+}
+
+#[test]
+fn crc32_check_values() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(
+        crc32(b"The quick brown fox jumps over the lazy dog"),
+        0x414F_A339
+    );
+}
+
+#[test]
+fn lz4_roundtrips() {
+    roundtrip(b"");
+    roundtrip(b"a");
+    roundtrip(&sample(13));
+    roundtrip(&sample(4096));
+    roundtrip(&sample(100_000));
+    let noise: Vec<u8> = (0..5000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    roundtrip(&noise);
+}
+
+#[test]
+fn lz4_compresses_repetition() {
+    let src = sample(4096);
+    let mut packed = vec![0u8; lz4::bound(src.len())];
+    let n = lz4::compress(&src, &mut packed).unwrap();
+    assert!(n < src.len() / 4, "{n} bytes");
+}
+
+#[test]
+fn lz4_rejects_bad_input() {
+    let src = sample(4096);
+    let mut packed = vec![0u8; lz4::bound(src.len())];
+    let n = lz4::compress(&src, &mut packed).unwrap();
+
+    let mut small = vec![0u8; src.len() - 1];
+    assert_eq!(
+        lz4::decompress(&packed[..n], &mut small),
+        Err(Lz4Error::OutputFull)
+    );
+    // Cut mid-stream: an error, or at best a short prefix.
+    let mut out = vec![0u8; src.len()];
+    assert_ne!(lz4::decompress(&packed[..n / 2], &mut out), Ok(src.len()));
+    // A match reaching back before the start of the output.
+    assert_eq!(
+        lz4::decompress(&[0x04, 0xFF, 0x00], &mut out),
+        Err(Lz4Error::BadOffset)
+    );
+    let mut tiny = [0u8; 4];
+    assert_eq!(lz4::compress(&src, &mut tiny), Err(Lz4Error::OutputFull));
+}
+
+#[test]
+fn xxh32_check_values() {
+    assert_eq!(lz4::xxh32(b"", 0), 0x02CC_5D05);
+    assert_eq!(lz4::xxh32(b"abc", 0), 0x32D1_53FF);
+}
+
+// A frame as `lz4 -B4 --content-size` writes it: one compressed block with
+// a block checksum, then the content checksum.
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0u8; lz4::bound(data.len())];
+    let n = lz4::compress(data, &mut packed).unwrap();
+    let mut f = 0x184D_2204u32.to_le_bytes().to_vec();
+    let desc = f.len();
+    f.extend_from_slice(&[0x40 | 0x10 | 0x08 | 0x04, 0x40]);
+    f.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    f.push((lz4::xxh32(&f[desc..], 0) >> 8) as u8);
+    f.extend_from_slice(&(n as u32).to_le_bytes());
+    f.extend_from_slice(&packed[..n]);
+    f.extend_from_slice(&lz4::xxh32(&packed[..n], 0).to_le_bytes());
+    f.extend_from_slice(&0u32.to_le_bytes());
+    f.extend_from_slice(&lz4::xxh32(data, 0).to_le_bytes());
+    f
+}
+
+#[test]
+fn lz4_frames() {
+    let data = sample(3000);
+    let f = frame(&data);
+    assert!(lz4::is_frame(&f));
+    let mut out = vec![0u8; data.len()];
+    assert_eq!(lz4::decompress_frame(&f, &mut out), Ok(data.len()));
+    assert_eq!(out, data);
+
+    // Skippable frames in front are passed over.
+    let mut skip = 0x184D_2A53u32.to_le_bytes().to_vec();
+    skip.extend_from_slice(&3u32.to_le_bytes());
+    skip.extend_from_slice(b"xyz");
+    skip.extend_from_slice(&f);
+    assert!(!lz4::is_frame(&skip));
+    assert_eq!(lz4::decompress_frame(&skip, &mut out), Ok(data.len()));
+
+    let mut bad = f.clone();
+    let last = bad.len() - 1;
+    bad[last] ^= 1;
+    assert_eq!(
+        lz4::decompress_frame(&bad, &mut out),
+        Err(Lz4Error::BadChecksum)
+    );
+    let mut bad = f.clone();
+    bad[6] ^= 1; // header checksum byte covers the descriptor
+    assert_eq!(
+        lz4::decompress_frame(&bad, &mut out),
+        Err(Lz4Error::BadChecksum)
+    );
+    assert_eq!(
+        lz4::decompress_frame(&f[..20], &mut out),
+        Err(Lz4Error::Truncated)
+    );
+    assert_eq!(
+        lz4::decompress_frame(b"nope", &mut out),
+        Err(Lz4Error::BadMagic)
+    );
+}
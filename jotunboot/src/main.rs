@@ -9,6 +9,7 @@ extern crate alloc;
 mod simd;
 
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::{arch::asm, ptr};
 
 use log::{error, info};
@@ -34,7 +35,15 @@ static ALLOCATOR: uefi::allocator::Allocator = uefi::allocator::Allocator;
 /* ================================ Panic ================================== */
 
 #[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let _ = core::fmt::Write::write_fmt(
+        &mut SerialWriter,
+        format_args!("[serial][PANIC] {}\n", info),
+    );
+    // A panic while drawing the error screen must not recurse into it.
+    if BOOT_SERVICES_LIVE.swap(false, Ordering::SeqCst) {
+        error_screen(Status::ABORTED, &format_args!("panic: {}", info));
+    }
     unsafe {
         loop {
             asm!("hlt");
@@ -51,10 +60,19 @@ pub struct Framebuffer {
     pub width: u32,
     pub height: u32,
     pub pitch: u32,        // bytes per scanline
-    pub bpp: u32,          // bits per pixel (commonly 32)
-    pub pixel_format: u32, // kernel enum/discriminant
+    pub bpp: u32,          // bits per pixel: 32, 24 or 16
+    pub pixel_format: u32, // PIXEL_FORMAT_*
+    pub red_mask: u32,     // channel bits within a little-endian pixel
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
 }
 
+pub const PIXEL_FORMAT_RGB: u32 = 0; // bytes R, G, B, reserved
+pub const PIXEL_FORMAT_BGR: u32 = 1; // bytes B, G, R, reserved
+pub const PIXEL_FORMAT_BITMASK: u32 = 2; // layout given by the masks
+pub const PIXEL_FORMAT_BLT_ONLY: u32 = 3; // no linear framebuffer
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct MemoryRegion {
@@ -65,6 +83,20 @@ pub struct MemoryRegion {
     pub attr: u64, // attribute bits
 }
 
+/// A run of physical pages the loader set up for the handoff. The kernel can
+/// reclaim them once it runs on its own page tables, or reuse them for a
+/// kexec-style soft reboot.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LoaderRange {
+    pub phys: u64,
+    pub pages: u64,
+    pub kind: u32, // LOADER_RANGE_*
+}
+
+pub const LOADER_RANGE_PAGE_TABLES: u32 = 1;
+pub const LOADER_RANGE_TRAMPOLINE: u32 = 2;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct BootInfo {
@@ -79,6 +111,50 @@ pub struct BootInfo {
     pub hhdm_base: u64,
     pub low32_pool_paddr: u64,
     pub low32_pool_len: u64,
+    pub cmdline: *const u8, // ASCII, not NUL-terminated
+    pub cmdline_len: usize,
+    pub loader_log: *const u8, // our COM1 output up to the handoff
+    pub loader_log_len: usize,
+    pub framebuffers: [Framebuffer; MAX_FRAMEBUFFERS], // [0] == framebuffer
+    pub framebuffer_count: usize,
+    pub loader_ranges: *const LoaderRange, // every page-table page + trampoline
+    pub loader_ranges_len: usize,
+}
+
+/* =============================== Loader log =============================== */
+
+// Everything sent to COM1 is also kept here and handed to the kernel, so its
+// log and crash reports can include what happened before ExitBootServices.
+// Keeps the oldest bytes once full.
+const LOG_CAP: usize = 16 * 1024;
+
+struct LoaderLog {
+    buf: [u8; LOG_CAP],
+    len: usize,
+}
+
+static LOG: spin::Mutex<LoaderLog> = spin::Mutex::new(LoaderLog {
+    buf: [0; LOG_CAP],
+    len: 0,
+});
+
+fn log_byte(c: u8) {
+    if c == b'\r' {
+        return;
+    }
+    let mut l = LOG.lock();
+    if l.len < LOG_CAP {
+        let n = l.len;
+        l.buf[n] = c;
+        l.len += 1;
+    }
+}
+
+/// Copy the log into `dst` (LOG_CAP bytes); returns the length used.
+fn log_snapshot(dst: *mut u8) -> usize {
+    let l = LOG.lock();
+    unsafe { ptr::copy_nonoverlapping(l.buf.as_ptr(), dst, l.len) };
+    l.len
 }
 
 /* ========================== Serial (QEMU stdio) ========================== */
@@ -97,6 +173,7 @@ unsafe fn serial_init() {
 
 unsafe fn serial_putc(c: u8) {
     const COM1: u16 = 0x3F8;
+    log_byte(c);
     loop {
         let mut lsr: u8;
         asm!("in al, dx", out("al") lsr, in("dx") COM1 + 5);
@@ -115,10 +192,28 @@ fn serial_line(s: &str) {
         serial_putc(b'\n');
     }
 }
+/// Heap-free `fmt::Write` over COM1, usable before the allocator is up and
+/// after boot services (and with them the UEFI pool) are gone.
+struct SerialWriter;
+
+impl core::fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        unsafe {
+            for b in s.bytes() {
+                if b == b'\n' {
+                    serial_putc(b'\r');
+                }
+                serial_putc(b);
+            }
+        }
+        Ok(())
+    }
+}
+
 macro_rules! slog {
     ($($t:tt)*) => {{
-        let s = alloc::format!($($t)*);
-        serial_line(&s);
+        let _ = core::fmt::Write::write_fmt(&mut SerialWriter, format_args!($($t)*));
+        serial_line("");
     }};
 }
 
@@ -127,13 +222,23 @@ macro_rules! slog {
 
 fn log_step(msg: &str) {
     info!("[step] {msg}");
+    // The kernel turns the TSC stamps into step timings.
+    slog!("[step] {} @tsc={}", msg, unsafe {
+        core::arch::x86_64::_rdtsc()
+    });
     boot::stall(80_000);
 }
+// Cleared just before ExitBootServices: past that point there is no console,
+// no keyboard and no ResetSystem we may rely on.
+static BOOT_SERVICES_LIVE: AtomicBool = AtomicBool::new(true);
+
 #[cold]
-fn die(_: Status, msg: &core::fmt::Arguments) -> ! {
+fn die(status: Status, msg: &core::fmt::Arguments) -> ! {
     error!("[fatal] {}", msg);
-    serial_line("[serial][FATAL] abort");
-    boot::stall(1_000_000);
+    slog!("[serial][FATAL] {}", msg);
+    if BOOT_SERVICES_LIVE.swap(false, Ordering::SeqCst) {
+        error_screen(status, msg);
+    }
     unsafe {
         loop {
             asm!("hlt");
@@ -141,6 +246,35 @@ fn die(_: Status, msg: &core::fmt::Arguments) -> ! {
     }
 }
 
+/// Full-screen failure report on the UEFI text console (drawn on GOP by the
+/// firmware), then a cold reset through ResetSystem on any key.
+fn error_screen(status: Status, msg: &core::fmt::Arguments) -> ! {
+    use core::fmt::Write;
+    use uefi::proto::console::text::Color;
+    use uefi::runtime::ResetType;
+    use uefi::system;
+
+    system::with_stdout(|out| {
+        let _ = out.set_color(Color::White, Color::Red);
+        let _ = out.clear();
+        let _ = out.enable_cursor(false);
+        let _ = writeln!(out, "\n  JotunBoot could not start Jotunheim.\n");
+        let _ = writeln!(out, "  {}", msg);
+        let _ = writeln!(out, "  (status: {:?})\n", status);
+        let _ = writeln!(out, "  The serial log on COM1 has the details.");
+        let _ = writeln!(out, "  Press any key to reboot.");
+    });
+    system::with_stdin(|inp| {
+        let _ = inp.reset(false); // drop keys pressed before the failure
+        if let Some(ev) = inp.wait_for_key_event() {
+            let _ = boot::wait_for_event(&mut [ev]);
+        }
+        let _ = inp.read_key();
+    });
+    serial_line("[serial] rebooting");
+    uefi::runtime::reset(ResetType::COLD, status, None)
+}
+
 fn align_up(x: u64, a: u64) -> u64 {
     let m = a.max(1);
     (x + m - 1) & !(m - 1)
@@ -176,33 +310,125 @@ fn find_rsdp() -> u64 {
     rsdp.get()
 }
 
-fn get_framebuffer() -> Framebuffer {
-    use uefi::proto::console::gop::GraphicsOutput;
-
-    // Find & open GOP
-    let h = boot::get_handle_for_protocol::<GraphicsOutput>().expect("No GOP handle found");
-    let mut gop = boot::open_protocol_exclusive::<GraphicsOutput>(h).expect("Open GOP failed");
+const EMPTY_FB: Framebuffer = Framebuffer {
+    addr: 0,
+    width: 0,
+    height: 0,
+    pitch: 0,
+    bpp: 0,
+    pixel_format: 0,
+    red_mask: 0,
+    green_mask: 0,
+    blue_mask: 0,
+    reserved_mask: 0,
+};
 
-    let info = gop.current_mode_info();
-    let (w, h) = info.resolution();
-    let mut fb = gop.frame_buffer();
+/// Most GOP instances we hand over; firmware rarely exposes more than one
+/// per connected output.
+pub const MAX_FRAMEBUFFERS: usize = 4;
 
-    // Map PixelFormat to your kernel enum if you need; here 0=RGB,1=BGR,2=Bitmask,3=BltOnly
-    let pf = match info.pixel_format() {
-        uefi::proto::console::gop::PixelFormat::Rgb => 0,
-        uefi::proto::console::gop::PixelFormat::Bgr => 1,
-        uefi::proto::console::gop::PixelFormat::Bitmask => 2,
-        uefi::proto::console::gop::PixelFormat::BltOnly => 3,
-    };
+/// Bits per pixel implied by a bitmask layout: the highest used bit,
+/// rounded up to whole bytes.
+fn bitmask_bpp(red: u32, green: u32, blue: u32, reserved: u32) -> u32 {
+    let used = 32 - (red | green | blue | reserved).leading_zeros();
+    used.div_ceil(8) * 8
+}
 
-    Framebuffer {
-        addr: fb.as_mut_ptr() as u64,
-        width: w as u32,
-        height: h as u32,
-        pitch: (info.stride() as u32) * 4,
-        bpp: 32,
-        pixel_format: pf,
+/// Every GOP with a linear framebuffer, in handle order (the first one is
+/// normally the console output). Opened non-exclusively so ConOut keeps
+/// working for the error screen. GOPs sharing a framebuffer (the same
+/// device on several handles) are listed once. BltOnly GOPs have nothing
+/// to hand over; with none left the kernel runs serial-only.
+fn get_framebuffers() -> Vec<Framebuffer> {
+    use uefi::Identify;
+    use uefi::boot::{OpenProtocolAttributes, OpenProtocolParams, SearchType};
+    use uefi::proto::console::gop::{GraphicsOutput, PixelFormat};
+
+    let handles = boot::locate_handle_buffer(SearchType::ByProtocol(&GraphicsOutput::GUID))
+        .unwrap_or_else(|e| die(e.status(), &format_args!("no GOP handle found")));
+
+    let mut out: Vec<Framebuffer> = Vec::new();
+    for &h in handles.iter() {
+        let params = OpenProtocolParams {
+            handle: h,
+            agent: boot::image_handle(),
+            controller: None,
+        };
+        let Ok(mut gop) = (unsafe {
+            boot::open_protocol::<GraphicsOutput>(params, OpenProtocolAttributes::GetProtocol)
+        }) else {
+            continue;
+        };
+
+        let info = gop.current_mode_info();
+        // (format, [red, green, blue, reserved]) as the kernel sees them
+        let (pf, masks) = match info.pixel_format() {
+            PixelFormat::Rgb => (PIXEL_FORMAT_RGB, [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000]),
+            PixelFormat::Bgr => (PIXEL_FORMAT_BGR, [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]),
+            PixelFormat::Bitmask => {
+                let m = info.pixel_bitmask().unwrap();
+                (PIXEL_FORMAT_BITMASK, [m.red, m.green, m.blue, m.reserved])
+            }
+            PixelFormat::BltOnly => {
+                slog!("[serial] GOP in BltOnly mode: no linear framebuffer, skipped");
+                continue;
+            }
+        };
+        let bpp = bitmask_bpp(masks[0], masks[1], masks[2], masks[3]);
+        let (w, hgt) = info.resolution();
+        let fb = Framebuffer {
+            addr: gop.frame_buffer().as_mut_ptr() as u64,
+            width: w as u32,
+            height: hgt as u32,
+            pitch: info.stride() as u32 * (bpp / 8),
+            bpp,
+            pixel_format: pf,
+            red_mask: masks[0],
+            green_mask: masks[1],
+            blue_mask: masks[2],
+            reserved_mask: masks[3],
+        };
+        if fb.addr == 0 || out.iter().any(|o| o.addr == fb.addr) {
+            continue;
+        }
+        slog!(
+            "[serial] fb{}: {}x{} @ 0x{:x} fmt {} bpp {}",
+            out.len(),
+            fb.width,
+            fb.height,
+            fb.addr,
+            pf,
+            bpp
+        );
+        out.push(fb);
+        if out.len() == MAX_FRAMEBUFFERS {
+            break;
+        }
     }
+    if out.is_empty() {
+        slog!("[serial] no GOP with a linear framebuffer; serial console only");
+    }
+    out
+}
+
+/// Load options of our own image (UCS-2 from the shell or boot entry),
+/// squashed to ASCII. Non-ASCII code units become '?'.
+fn load_options_ascii() -> Vec<u8> {
+    use uefi::proto::loaded_image::LoadedImage;
+
+    let Ok(img) = boot::open_protocol_exclusive::<LoadedImage>(boot::image_handle()) else {
+        return Vec::new();
+    };
+    let Some(raw) = img.load_options_as_bytes() else {
+        return Vec::new();
+    };
+    raw.as_chunks::<2>()
+        .0
+        .iter()
+        .map(|c| u16::from_le_bytes(*c))
+        .take_while(|&u| u != 0)
+        .map(|u| if u < 0x80 { u as u8 } else { b'?' })
+        .collect()
 }
 
 fn uefi_type_to_kernel(t: boot::MemoryType) -> u32 {
@@ -216,6 +442,7 @@ fn uefi_type_to_kernel(t: boot::MemoryType) -> u32 {
         U::RUNTIME_SERVICES_CODE => 6,
         U::RUNTIME_SERVICES_DATA => 7,
         U::ACPI_RECLAIM => 8,
+        U::PERSISTENT_MEMORY => 9,
         _ => 0,
     }
 }
@@ -264,10 +491,15 @@ fn pt_index(va: u64) -> usize {
     ((va >> 12) & 0x1ff) as usize
 }
 
+// Every page handed out below holds a paging structure; they are recorded
+// for the kernel as LOADER_RANGE_PAGE_TABLES.
+static PT_PAGES: spin::Mutex<Vec<u64>> = spin::Mutex::new(Vec::new());
+
 fn alloc_zero_page_low(kind: MemoryType) -> Option<(*mut u64, u64)> {
     let p = boot::allocate_pages(AllocateType::MaxAddress(0x0000_FFFF_FFFF_F000), kind, 1).ok()?;
     let phys = p.as_ptr() as u64;
     unsafe { core::ptr::write_bytes(p.as_ptr(), 0, 4096) };
+    PT_PAGES.lock().push(phys);
     Some((p.as_ptr() as *mut u64, phys))
 }
 
@@ -275,9 +507,52 @@ fn alloc_zero_page(kind: MemoryType) -> Option<(*mut u64, u64)> {
     let p = boot::allocate_pages(AllocateType::AnyPages, kind, 1).ok()?;
     let phys = p.as_ptr() as usize as u64;
     unsafe { core::ptr::write_bytes(p.as_ptr(), 0, 4096) };
+    PT_PAGES.lock().push(phys);
     Some((p.as_ptr() as *mut u64, phys))
 }
 
+/// Page-table pages as merged runs, then the trampoline, written to `out`.
+/// Runs that do not fit are dropped; the kernel just never reclaims them.
+fn write_loader_ranges(out: &mut [LoaderRange], tramp_phys: u64) -> usize {
+    let mut pages = core::mem::take(&mut *PT_PAGES.lock());
+    pages.sort_unstable();
+    let mut n = 0;
+    let mut push = |r: LoaderRange| {
+        if n < out.len() {
+            out[n] = r;
+            n += 1;
+        }
+    };
+    let mut run: Option<LoaderRange> = None;
+    for p in pages {
+        match run.as_mut() {
+            Some(r) if r.phys + r.pages * 4096 == p => r.pages += 1,
+            _ => {
+                if let Some(r) = run.take() {
+                    push(r);
+                }
+                run = Some(LoaderRange {
+                    phys: p,
+                    pages: 1,
+                    kind: LOADER_RANGE_PAGE_TABLES,
+                });
+            }
+        }
+    }
+    if let Some(r) = run {
+        push(r);
+    }
+    push(LoaderRange {
+        phys: tramp_phys,
+        pages: 1,
+        kind: LOADER_RANGE_TRAMPOLINE,
+    });
+    if n == out.len() {
+        slog!("[serial] loader range list full; some pages stay reserved");
+    }
+    n
+}
+
 unsafe fn ensure_pdpt(pml4: *mut u64, pml4i: usize) -> Result<*mut u64, ()> {
     let e = *pml4.add(pml4i);
     if e & PTE_P == 0 {
@@ -651,6 +926,7 @@ fn main() -> Status {
 
     let bi_page = must_alloc_page(MemoryType::LOADER_DATA, "BootInfo");
     let tramp_page = must_alloc_page(MemoryType::LOADER_CODE, "trampoline");
+    let ranges_page = must_alloc_page(MemoryType::LOADER_DATA, "loader ranges");
 
     let stack_pages = 16usize;
     let stack_base =
@@ -713,20 +989,49 @@ fn main() -> Status {
     let memory_map_ptr = memmap_pages.as_ptr() as *const MemoryRegion;
     let memory_map_len = regions.len();
 
-    // GOP framebuffer & ACPI RSDP
-    let fb = get_framebuffer();
+    // GOP framebuffers & ACPI RSDP
+    let fbs = get_framebuffers();
+    let fb = fbs.first().copied().unwrap_or(EMPTY_FB);
     let rsdp_addr = find_rsdp();
 
+    // Kernel command line rides in the BootInfo page, right after the struct.
+    let mut cmdline = load_options_ascii();
+    cmdline.truncate(0x1000 - core::mem::size_of::<BootInfo>());
+    let cmdline_ptr =
+        unsafe { bi_page.as_ptr().add(core::mem::size_of::<BootInfo>()) } as *const u8;
+    slog!(
+        "[serial] cmdline = \"{}\"",
+        core::str::from_utf8(&cmdline).unwrap_or("?")
+    );
+
+    let log_pages = LOG_CAP / 4096;
+    let log_block =
+        boot::allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, log_pages)
+            .unwrap_or_else(|e| {
+                die(
+                    Status::OUT_OF_RESOURCES,
+                    &format_args!("loader log pages {:?}", e),
+                )
+            });
+
     // Identity coverage must include trampoline/bootinfo/stack/image span/early heap/memmap/fb.
     let tramp_end = tramp_page.as_ptr() as u64 + 0x1000;
     let bi_end = bi_page.as_ptr() as u64 + 0x1000;
+    let ranges_end = ranges_page.as_ptr() as u64 + 0x1000;
     let stack_end = stack_top_aligned;
     let image_end = load_base + (max_vaddr - min_vaddr);
     let early_heap_end = early_heap_paddr + early_heap_len;
     let memmap_end = memmap_pages.as_ptr() as u64 + (map_pages as u64) * 4096;
-    let fb_end = fb.addr + (fb.pitch as u64) * (fb.height as u64);
+    let fb_end = fbs
+        .iter()
+        .map(|f| f.addr + (f.pitch as u64) * (f.height as u64))
+        .max()
+        .unwrap_or(0);
+    let log_end = log_block.as_ptr() as u64 + LOG_CAP as u64;
 
     let mut ident_hi = *[
+        log_end,
+        ranges_end,
         tramp_end,
         bi_end,
         stack_end,
@@ -754,7 +1059,17 @@ fn main() -> Status {
     slog!("[serial] pml4_phys = 0x{:x}", pml4_phys);
     log_step("paging ready");
 
-    // Persist BootInfo
+    let ranges = unsafe {
+        core::slice::from_raw_parts_mut(
+            ranges_page.as_ptr() as *mut LoaderRange,
+            0x1000 / core::mem::size_of::<LoaderRange>(),
+        )
+    };
+    let loader_ranges_len = write_loader_ranges(ranges, tramp_page.as_ptr() as u64);
+    slog!("[serial] loader ranges = {}", loader_ranges_len);
+
+    // Persist BootInfo; the log snapshot is the last thing it gets.
+    let loader_log_len = log_snapshot(log_block.as_ptr());
     let bi_val = BootInfo {
         rsdp_addr,
         memory_map: memory_map_ptr,
@@ -767,13 +1082,23 @@ fn main() -> Status {
         hhdm_base: HHDM_BASE,
         low32_pool_len,
         low32_pool_paddr,
+        cmdline: cmdline_ptr,
+        cmdline_len: cmdline.len(),
+        loader_log: log_block.as_ptr(),
+        loader_log_len,
+        framebuffers: core::array::from_fn(|i| fbs.get(i).copied().unwrap_or(EMPTY_FB)),
+        framebuffer_count: fbs.len(),
+        loader_ranges: ranges.as_ptr(),
+        loader_ranges_len,
     };
     unsafe {
         (bi_page.as_ptr() as *mut BootInfo).write(bi_val);
+        ptr::copy_nonoverlapping(cmdline.as_ptr(), cmdline_ptr as *mut u8, cmdline.len());
     }
 
     // ExitBootServices and jump via low trampoline (identity mapped in both CR3s)
     serial_line("[serial] ExitBootServices …");
+    BOOT_SERVICES_LIVE.store(false, Ordering::SeqCst);
     let _ = unsafe { boot::exit_boot_services(None) };
 
     unsafe {
@@ -2,12 +2,33 @@
 ; SPDX-License-Identifier: JOSSL-1.0
 ; Copyright (C) 2025 The Jotunheim Project
 ; Position-independent AP trampoline: 16 -> 32 -> 64, no .data, no 16-bit relocs.
+; Progress goes to _ap_tramp_stage (AP_STAGE_* in smp.rs), which the BSP
+; reads when an AP does not come up, and as a digit to port 0xE9 (QEMU
+; -debugcon), which survives a triple fault that takes the machine down.
+
+; STAGEn code: record `code`; clobbers al. Each needs its mode's base register.
+%macro STAGE16 1
+    mov byte [cs:bx + (_ap_tramp_stage - start16)], %1
+    mov al, '0' + %1
+    out 0xE9, al
+%endmacro
+%macro STAGE32 1
+    mov byte [ebx + (_ap_tramp_stage - pmode)], %1
+    mov al, '0' + %1
+    out 0xE9, al
+%endmacro
+%macro STAGE64 1
+    mov byte [rel _ap_tramp_stage], %1
+    mov al, '0' + %1
+    out 0xE9, al
+%endmacro
 
 [bits 16]
 global _ap_tramp_start
 global _ap_tramp_end
 global _ap_tramp_apboot_ptr32
 global _ap_tramp_apboot_ptr64
+global _ap_tramp_stage
 
 section .text
 _ap_tramp_start:
@@ -23,6 +44,7 @@ start16:
 .getip16:
     pop bx
     sub bx, (.getip16 - start16)
+    STAGE16 1
 
     ; SI -> scratch gdtr buffer (6 bytes) in .text (PIE)
     mov si, bx
@@ -40,6 +62,7 @@ start16:
     mov [si+2], eax
 
     lgdt [si]
+    STAGE16 2
 
     ; enter protected mode
     mov eax, cr0
@@ -75,6 +98,7 @@ pmode:
 .getip32:
     pop ebx
     sub ebx, (.getip32 - pmode)
+    STAGE32 3
 
     ; enable PAE
     mov eax, cr4
@@ -86,17 +110,20 @@ pmode:
     mov esi, [esi]
     mov eax, [esi + 8]         ; ApBoot.cr3 (low 32)
     mov cr3, eax
+    STAGE32 4
 
     ; IA32_EFER.LME=1
     mov ecx, 0xC0000080
     rdmsr
     bts eax, 8
     wrmsr
+    STAGE32 5
 
     ; paging on
     mov eax, cr0
     bts eax, 31
     mov cr0, eax
+    STAGE32 6
 
     ; far jump into 64-bit CS
     push dword 0x0018
@@ -106,16 +133,19 @@ pmode:
     
 [bits 64]
 lm64:
+    STAGE64 7
     lea rdx, [rel _ap_tramp_apboot_ptr64]
     mov rax, [rdx]            ; rax = ApBoot* (PHYSICAL)
 
-    ; correct offsets for #[repr(C)] ApBoot:
-    ; stack_top @ +0x20, entry64 @ +0x28, hhdm @ +0x30
+    ; #[repr(C)] ApBoot offsets, asserted in smp.rs (APBOOT_*):
+    ; cr3 @ +0x08, stack_top @ +0x20, entry64 @ +0x28
+    ; stack_top -> [arg]; after the pop RSP%16 == 8, as after a CALL
 
     mov rsp, [rax + 0x20]     ; ApBoot.stack_top
     mov rcx, [rax + 0x28]     ; ApBoot.entry64
     
     pop rdi
+    STAGE64 8
     jmp rcx                   ; -> ap_entry()
 
 ; ---------- tiny flat GDT ----------
@@ -143,4 +173,7 @@ _ap_tramp_apboot_ptr32: dd 0
 align 8
 _ap_tramp_apboot_ptr64: dq 0
 
+; last stage reached; the BSP zeroes it before each AP
+_ap_tramp_stage: db 0
+
 _ap_tramp_end:
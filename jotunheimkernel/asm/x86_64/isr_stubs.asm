@@ -20,50 +20,45 @@ global isr_default_stub
 global isr_gp_stub
 global isr_pf_stub
 global isr_df_stub
+global isr_de_stub
 global isr_ud_stub
 global isr_bp_stub
 global isr_db_stub
 global isr_timer_stub
 global isr_spurious_stub
+global isr_call_ipi_stub
+global isr_shootdown_ipi_stub
+global isr_kbd_stub
+global isr_virtio_con_stub
+global isr_cp_stub
+global tf_selftest
+global tf_save_probe
 
 ; ---------------- External Rust handlers (all take *mut TrapFrame) ----------
 extern isr_default_rust        ; fn(*mut TrapFrame) -> !
 extern isr_gp_rust             ; fn(*mut TrapFrame) -> !
 extern isr_pf_rust             ; fn(*mut TrapFrame) -> !
 extern isr_df_rust             ; fn(*mut TrapFrame) -> !
-extern isr_ud_rust             ; fn(*mut TrapFrame) -> !
+extern isr_de_rust             ; fn(*mut TrapFrame) -> ()
+extern isr_ud_rust             ; fn(*mut TrapFrame) -> ()
 extern isr_bp_rust             ; fn(*mut TrapFrame) -> ()
 extern isr_db_rust             ; fn(*mut TrapFrame) -> ()
 extern isr_timer_rust          ; fn() -> ()
 extern isr_spurious_rust       ; fn() -> ()
+extern isr_call_ipi_rust       ; fn(*mut TrapFrame) -> ()
+extern isr_shootdown_ipi_rust  ; fn(*mut TrapFrame) -> ()
+extern isr_kbd_rust            ; fn(*mut TrapFrame) -> ()
+extern isr_virtio_con_rust     ; fn(*mut TrapFrame) -> ()
+extern isr_cp_rust             ; fn(*mut TrapFrame, u64) -> !
+extern CET_NEXT_SSP            ; u64, see arch/x86_64/cet.rs
+extern CET_SAVE_TO             ; u64
 
 %define RFLAGS_NT   (1<<14)
 %define RFLAGS_RF   (1<<16)
 %define RFLAGS_VM   (1<<17)
 ; ---------------- TrapFrame field offsets (bytes) ----------------
-%define TF_R15      (0*8)
-%define TF_R14      (1*8)
-%define TF_R13      (2*8)
-%define TF_R12      (3*8)
-%define TF_R11      (4*8)
-%define TF_R10      (5*8)
-%define TF_R9       (6*8)
-%define TF_R8       (7*8)
-%define TF_RSI      (8*8)
-%define TF_RDI      (9*8)
-%define TF_RBP     (10*8)
-%define TF_RDX     (11*8)
-%define TF_RCX     (12*8)
-%define TF_RBX     (13*8)
-%define TF_RAX     (14*8)
-%define TF_VEC     (15*8)
-%define TF_ERR     (16*8)
-%define TF_RIP     (17*8)
-%define TF_CS      (18*8)
-%define TF_RFLAGS  (19*8)
-%define TF_RSP     (20*8)
-%define TF_SS      (21*8)
-%define TF_SIZE    (22*8)
+; TF_R15 .. TF_SS, TF_SIZE: generated from context.rs by build.rs
+%include "trapframe.inc"
 
 ; ---------------- Helpers ----------------
 
@@ -188,6 +183,31 @@ extern isr_spurious_rust       ; fn() -> ()
     mov     [r12 + 16], rax 
 %endmacro
 
+; With CET shadow stacks on, the interrupt pushed [SSP][LIP][CS] onto the
+; shadow stack and IRET checks them. Point LIP at the (possibly new) RIP and,
+; when the scheduler switched tasks, swap in the next task's SSP, saving the
+; old one at CET_SAVE_TO. Encoded by hand for older assemblers.
+%macro SYNC_SHADOW 0
+    xor     eax, eax
+    db      0xF3, 0x48, 0x0F, 0x1E, 0xC8        ; rdsspq rax (NOP while off)
+    test    rax, rax
+    jz      %%done
+    mov     rcx, [rsp + TF_RIP]
+    db      0x48, 0x0F, 0x38, 0xF6, 0x48, 0x08  ; wrssq [rax + 8], rcx
+    mov     rdx, [rel CET_NEXT_SSP]
+    test    rdx, rdx
+    jz      %%done
+    mov     rcx, [rel CET_SAVE_TO]
+    test    rcx, rcx
+    jz      %%swap
+    mov     r12, [rax]
+    mov     [rcx], r12
+%%swap:
+    db      0x48, 0x0F, 0x38, 0xF6, 0x10        ; wrssq [rax], rdx
+    mov     qword [rel CET_NEXT_SSP], 0
+%%done:
+%endmacro
+
 ; =============================================================================
 ; Stubs
 ; =============================================================================
@@ -198,6 +218,7 @@ isr_default_stub:
     mov     rdi, rsp                ; &TrapFrame
     CALL_SYSV isr_default_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -207,6 +228,7 @@ isr_bp_stub:
     mov     rdi, rsp
     CALL_SYSV isr_bp_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -216,6 +238,17 @@ isr_db_stub:
     mov     rdi, rsp
     CALL_SYSV isr_db_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
+; #DE (0) — no error
+isr_de_stub:
+    BUILD_TF_NO_ERR 0
+    mov     rdi, rsp
+    CALL_SYSV isr_de_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -225,6 +258,7 @@ isr_ud_stub:
     mov     rdi, rsp
     CALL_SYSV isr_ud_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -234,6 +268,7 @@ isr_gp_stub:
     mov     rdi, rsp
     CALL_SYSV isr_gp_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -243,6 +278,7 @@ isr_pf_stub:
     mov     rdi, rsp
     CALL_SYSV isr_pf_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -252,9 +288,22 @@ isr_df_stub:
     mov     rdi, rsp
     CALL_SYSV isr_df_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
     
+; #CP (21) — with error; rsi = SSP of the interrupt's shadow-stack record
+isr_cp_stub:
+    BUILD_TF_WITH_ERR 21
+    mov     rdi, rsp
+    xor     esi, esi
+    db      0xF3, 0x48, 0x0F, 0x1E, 0xCE        ; rdsspq rsi
+    CALL_SYSV isr_cp_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
 ; LAPIC Timer (no error) — minimal edge (no TF). If you want TF-based preemption,
 ; convert to BUILD_TF_NO_ERR 0x20 and pass &TrapFrame instead.
 isr_timer_stub:
@@ -262,6 +311,47 @@ isr_timer_stub:
     mov     rdi, rsp
     CALL_SYSV isr_timer_rust
     WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
+; Cross-CPU call IPI (no error)
+isr_call_ipi_stub:
+    BUILD_TF_NO_ERR 0xF0
+    mov     rdi, rsp
+    CALL_SYSV isr_call_ipi_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
+; TLB shootdown IPI (no error)
+isr_shootdown_ipi_stub:
+    BUILD_TF_NO_ERR 0xF1
+    mov     rdi, rsp
+    CALL_SYSV isr_shootdown_ipi_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
+; PS/2 keyboard, IOAPIC GSI 1 (no error)
+isr_kbd_stub:
+    BUILD_TF_NO_ERR 0x50
+    mov     rdi, rsp
+    CALL_SYSV isr_kbd_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
+    RESTORE_GPRS_FROM_TF
+    iretq
+
+; virtio-console, its PCI INTx line (no error)
+isr_virtio_con_stub:
+    BUILD_TF_NO_ERR 0x51
+    mov     rdi, rsp
+    CALL_SYSV isr_virtio_con_rust
+    WRITE_BACK_HW
+    SYNC_SHADOW
     RESTORE_GPRS_FROM_TF
     iretq
 
@@ -269,3 +359,97 @@ isr_timer_stub:
 isr_spurious_stub:
     CALL_SYSV isr_spurious_rust
     iretq
+
+; =============================================================================
+; Self-test
+; =============================================================================
+
+; tf_selftest(src: *const TrapFrame, dst: *mut TrapFrame)
+; Loads every GPR from *src with RESTORE_GPRS_FROM_TF, stores them again with
+; SAVE_GPRS_TO_TF and copies the result to *dst. Rust compares the two to
+; catch offset drift between the macros and the struct.
+tf_selftest:
+    push    rbx
+    push    rbp
+    push    r12
+    push    r13
+    push    r14
+    push    r15
+    push    rsi                     ; dst, clobbered by the restore
+    sub     rsp, TF_SIZE
+
+    mov     rsi, rdi
+    mov     rdi, rsp
+    mov     rcx, TF_SIZE / 8
+    cld
+    rep movsq
+    lea     rax, [rsp + TF_SIZE]
+    mov     [rsp + TF_RSP], rax     ; "return" to just above the frame
+    RESTORE_GPRS_FROM_TF
+
+    sub     rsp, TF_SIZE
+    SAVE_GPRS_TO_TF
+
+    mov     rdi, [rsp + TF_SIZE]
+    mov     rsi, rsp
+    mov     rcx, TF_SIZE / 8
+    rep movsq
+
+    add     rsp, TF_SIZE
+    pop     rsi
+    pop     r15
+    pop     r14
+    pop     r13
+    pop     r12
+    pop     rbp
+    pop     rbx
+    ret
+
+; tf_save_probe(dst: *mut TrapFrame)
+; Puts TF_PROBE + n in the GPR with encoding n (rax = 0 ... r15 = 15, rsp
+; skipped), stores them with SAVE_GPRS_TO_TF and copies the frame to *dst.
+; Rust checks each field by name, which also catches two offsets swapped
+; in both macros alike (a round trip cannot see that).
+%define TF_PROBE 0x7E00000000000000
+tf_save_probe:
+    push    rbx
+    push    rbp
+    push    r12
+    push    r13
+    push    r14
+    push    r15
+    push    rdi                     ; dst, overwritten below
+    sub     rsp, TF_SIZE
+
+    mov     rax, TF_PROBE + 0
+    mov     rcx, TF_PROBE + 1
+    mov     rdx, TF_PROBE + 2
+    mov     rbx, TF_PROBE + 3
+    mov     rbp, TF_PROBE + 5
+    mov     rsi, TF_PROBE + 6
+    mov     rdi, TF_PROBE + 7
+    mov     r8,  TF_PROBE + 8
+    mov     r9,  TF_PROBE + 9
+    mov     r10, TF_PROBE + 10
+    mov     r11, TF_PROBE + 11
+    mov     r12, TF_PROBE + 12
+    mov     r13, TF_PROBE + 13
+    mov     r14, TF_PROBE + 14
+    mov     r15, TF_PROBE + 15
+    SAVE_GPRS_TO_TF
+
+    mov     rdi, [rsp + TF_SIZE]
+    mov     rsi, rsp
+    mov     rcx, TF_SIZE / 8
+    cld
+    rep movsq
+
+    add     rsp, TF_SIZE
+    pop     rdi
+    pop     r15
+    pop     r14
+    pop     r13
+    pop     r12
+    pop     rbp
+    pop     rbx
+    ret
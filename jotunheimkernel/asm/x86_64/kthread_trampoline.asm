@@ -1,17 +1,16 @@
 ; SPDX-License-Identifier: JOSSL-1.0
 ; Copyright (C) 2025 The Jotunheim Project
 ; kthread_trampoline:
+; Entered by iretq from the frame context::kthread_frame() builds.
 ; Stack layout expected at first run (top of stack):
 ;   [0] = arg
 ;   [1] = entry fn pointer
-; RSP -> [arg][entry]
+; RSP -> [arg][entry], RSP + 16 is 16-byte aligned (KFRAME_ARG)
 [BITS 64]
 extern sched_exit_current_trampoline
 global kthread_trampoline
 kthread_trampoline:
     pop rdi            ; rdi = arg
     pop rax            ; rax = entry
-    sub rsp, 8         ; keep 16-byte alignment before CALL
-    call rax           ; entry(arg) -> !
-    add  rsp, 8        ; (won’t run if entry is noreturn)
+    call rax           ; entry(arg) -> !, RSP 16-aligned at the CALL
     jmp  sched_exit_current_trampoline
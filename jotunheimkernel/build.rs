@@ -1,7 +1,36 @@
 // build.rs — force ELF64 so extern/relocs work
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
-use std::{env, path::PathBuf};
+use std::{env, fs, path::Path, path::PathBuf};
+
+// Turn the `pub const TF_*: usize = a * b;` lines of context.rs into NASM
+// %defines, so the stubs and the Rust struct share one set of offsets.
+fn write_trapframe_inc(out_dir: &Path) {
+    const SRC: &str = "src/arch/x86_64/context.rs";
+    println!("cargo:rerun-if-changed={SRC}");
+    let text = fs::read_to_string(SRC).expect("read context.rs");
+    let mut inc = String::from("; generated by build.rs from context.rs, do not edit\n");
+    for line in text.lines() {
+        let Some(rest) = line.trim().strip_prefix("pub const TF_") else {
+            continue;
+        };
+        let (name, expr) = rest
+            .split_once(": usize =")
+            .unwrap_or_else(|| panic!("bad TF_ constant: {line}"));
+        let value: usize = expr
+            .trim()
+            .trim_end_matches(';')
+            .split('*')
+            .map(|f| {
+                f.trim()
+                    .parse::<usize>()
+                    .unwrap_or_else(|_| panic!("bad TF_ value: {line}"))
+            })
+            .product();
+        inc += &format!("%define TF_{:<8} {}\n", name, value);
+    }
+    fs::write(out_dir.join("trapframe.inc"), inc).expect("write trapframe.inc");
+}
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
@@ -15,6 +44,9 @@ fn main() {
         return;
     }
 
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    write_trapframe_inc(&out_dir);
+
     let mut build = nasm_rs::Build::new();
 
     // ---- IMPORTANT: force ELF64 (don’t rely on defaults) ----
@@ -26,6 +58,7 @@ fn main() {
     // ---------------------------------------------------------
 
     build.include("asm/x86_64");
+    build.include(&out_dir);
 
     if env::var("PROFILE").as_deref() == Ok("debug") {
         build.debug(true);
@@ -41,7 +74,6 @@ fn main() {
         panic!("NASM build failed: {e}");
     }
 
-    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     println!("cargo:rustc-link-search=native={}", out_dir.display());
     println!("cargo:rustc-link-lib=static=arch_x86_64_asm");
 }
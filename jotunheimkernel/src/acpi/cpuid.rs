@@ -13,6 +13,9 @@ impl CpuId {
             apic: Some(lapic_id()),
         }
     }
+    pub fn of(apic: u32) -> Self {
+        Self { apic: Some(apic) }
+    }
     pub fn dummy() -> Self {
         Self { apic: None }
     }
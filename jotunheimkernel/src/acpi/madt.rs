@@ -150,9 +150,11 @@ fn find_sdt_by_sig_rsdt(hhdm: u64, rsdt_phys: u64, want: &[u8; 4]) -> Option<(u6
     None
 }
 
-// ───────────────────────── MADT discovery ─────────────────────────
+// ───────────────────────── Table lookup ─────────────────────────
 
-pub fn discover(boot: &BootInfo) -> Option<Box<MadtInfo>> {
+/// Physical address and length of the ACPI table with signature `sig`:
+/// through the XSDT if the RSDP has a valid one, else the RSDT.
+pub fn find_table(boot: &BootInfo, sig: &[u8; 4]) -> Option<(u64, u32)> {
     if boot.rsdp_addr == 0 {
         kprintln!("[acpi] RSDP address is 0");
         return None;
@@ -186,28 +188,21 @@ pub fn discover(boot: &BootInfo) -> Option<Box<MadtInfo>> {
     }
 
     // Prefer XSDT if present and valid; else use RSDT
-    let madt = if xsdt_addr != 0 {
-        if let Some((madt_phys, madt_len)) =
-            find_sdt_by_sig_xsdt(boot.hhdm_base, xsdt_addr, b"APIC")
-        {
-            Some((madt_phys, madt_len))
-        } else {
-            // XSDT path failed; try RSDT as fallback
-            if rsdp10.rsdt_addr != 0 {
-                find_sdt_by_sig_rsdt(boot.hhdm_base, rsdp10.rsdt_addr as u64, b"APIC")
-            } else {
-                None
-            }
-        }
+    let from_xsdt = if xsdt_addr != 0 {
+        find_sdt_by_sig_xsdt(boot.hhdm_base, xsdt_addr, sig)
     } else {
-        if rsdp10.rsdt_addr != 0 {
-            find_sdt_by_sig_rsdt(boot.hhdm_base, rsdp10.rsdt_addr as u64, b"APIC")
-        } else {
-            None
-        }
+        None
     };
+    if from_xsdt.is_some() || rsdp10.rsdt_addr == 0 {
+        return from_xsdt;
+    }
+    find_sdt_by_sig_rsdt(boot.hhdm_base, rsdp10.rsdt_addr as u64, sig)
+}
 
-    let (madt_phys, madt_len) = match madt {
+// ───────────────────────── MADT discovery ─────────────────────────
+
+pub fn discover(boot: &BootInfo) -> Option<Box<MadtInfo>> {
+    let (madt_phys, madt_len) = match find_table(boot, b"APIC") {
         Some(v) => v,
         None => {
             kprintln!("[acpi] MADT not found via XSDT/RSDT");
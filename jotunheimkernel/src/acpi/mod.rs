@@ -5,6 +5,9 @@ use alloc::{boxed::Box, vec::Vec};
 // src/acpi/mod.rs
 pub mod cpuid;
 pub mod madt;
+pub mod mp;
+pub mod nfit;
+pub mod topology;
 
 #[derive(Debug, Copy, Clone)]
 pub struct CpuEntry {
@@ -0,0 +1,169 @@
+// src/acpi/mp.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Intel MultiProcessor Specification 1.4 tables, the pre-ACPI way to find
+// the CPUs and I/O APICs. Only consulted when there is no usable MADT. The
+// floating pointer is searched for where the spec puts it: the first KiB of
+// the EBDA, the last KiB of base memory, then the BIOS ROM.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::acpi::{CpuEntry, IoApic, MadtInfo};
+use crate::bootinfo::BootInfo;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const FLOAT_SIG: &[u8; 4] = b"_MP_";
+const FLOAT_LEN: usize = 16;
+const TABLE_SIG: &[u8; 4] = b"PCMP";
+const TABLE_HDR_LEN: usize = 44;
+
+const EBDA_SEG_PTR: u64 = 0x40E;
+const BASE_MEM_KIB_PTR: u64 = 0x413;
+const BIOS_ROM: (u64, usize) = (0xF_0000, 0x1_0000);
+
+// Base table entry types and their sizes
+const ENTRY_CPU: u8 = 0; // 20 bytes
+const ENTRY_IOAPIC: u8 = 2; // 8 bytes; all others are 8 too
+const CPU_ENABLED: u8 = 1 << 0;
+
+// Default configurations (feature byte 1 != 0) have two CPUs and one
+// I/O APIC at the standard addresses.
+const DEFAULT_LAPIC: u64 = 0xFEE0_0000;
+const DEFAULT_IOAPIC: u64 = 0xFEC0_0000;
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn phys(boot: &BootInfo, pa: u64, len: usize) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts((boot.hhdm_base + pa) as *const u8, len) }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b)) == 0
+}
+
+fn le16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn le32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+// The floating pointer sits on a 16-byte boundary.
+fn scan(boot: &BootInfo, start: u64, len: usize) -> Option<&'static [u8]> {
+    let area = phys(boot, start, len);
+    area.as_chunks::<FLOAT_LEN>()
+        .0
+        .iter()
+        .find(|c| &c[0..4] == FLOAT_SIG && c[8] == 1 && checksum_ok(&c[..]))
+        .map(|c| &c[..])
+}
+
+fn find_float(boot: &BootInfo) -> Option<&'static [u8]> {
+    let ebda = (le16(phys(boot, EBDA_SEG_PTR, 2), 0) as u64) << 4;
+    let base_kib = le16(phys(boot, BASE_MEM_KIB_PTR, 2), 0) as u64;
+    let mut areas = [(0u64, 0usize); 3];
+    let mut n = 0;
+    if (0x8_0000..0xA_0000).contains(&ebda) {
+        areas[n] = (ebda, 1024);
+        n += 1;
+    }
+    if (512..=640).contains(&base_kib) {
+        areas[n] = (base_kib * 1024 - 1024, 1024);
+        n += 1;
+    }
+    areas[n] = BIOS_ROM;
+    n += 1;
+    areas[..n].iter().find_map(|&(s, l)| scan(boot, s, l))
+}
+
+/* --------------------------------- Parsing ---------------------------------- */
+
+fn default_config(kind: u8) -> MadtInfo {
+    kprintln!("[mp] default configuration {}", kind);
+    let cpus = (0..2)
+        .map(|id| {
+            Box::new(CpuEntry {
+                apic_id: id,
+                enabled: true,
+                _is_x2apic: false,
+            })
+        })
+        .collect();
+    MadtInfo {
+        _lapic_phys: Box::new(DEFAULT_LAPIC),
+        cpus: Box::new(cpus),
+        _ioapics: Box::new(alloc::vec![Box::new(IoApic {
+            _id: 2,
+            _mmio_base_phys: DEFAULT_IOAPIC,
+            _gsi_base: 0,
+        })]),
+    }
+}
+
+/// CPUs and I/O APICs from the MP tables, in the MADT's shape.
+pub fn discover(boot: &BootInfo) -> Option<Box<MadtInfo>> {
+    let float = find_float(boot)?;
+    let table_pa = le32(float, 4) as u64;
+    if float[11] != 0 {
+        return Some(Box::new(default_config(float[11])));
+    }
+    if table_pa == 0 {
+        return None;
+    }
+
+    let hdr = phys(boot, table_pa, TABLE_HDR_LEN);
+    if &hdr[0..4] != TABLE_SIG {
+        kprintln!("[mp] bad config table signature");
+        return None;
+    }
+    let len = le16(hdr, 4) as usize;
+    if len < TABLE_HDR_LEN {
+        return None;
+    }
+    let table = phys(boot, table_pa, len);
+    if !checksum_ok(table) {
+        kprintln!("[mp] config table checksum mismatch");
+        return None;
+    }
+    let count = le16(table, 34) as usize;
+    let lapic = le32(table, 36) as u64;
+
+    let mut cpus = Vec::new();
+    let mut ioapics = Vec::new();
+    let mut p = TABLE_HDR_LEN;
+    for _ in 0..count {
+        let Some(&typ) = table.get(p) else {
+            break;
+        };
+        let size = if typ == ENTRY_CPU { 20 } else { 8 };
+        let Some(e) = table.get(p..p + size) else {
+            break;
+        };
+        match typ {
+            ENTRY_CPU => cpus.push(Box::new(CpuEntry {
+                apic_id: e[1] as u32,
+                enabled: e[3] & CPU_ENABLED != 0,
+                _is_x2apic: false,
+            })),
+            ENTRY_IOAPIC if e[3] & 1 != 0 => ioapics.push(Box::new(IoApic {
+                _id: e[1],
+                _mmio_base_phys: le32(e, 4) as u64,
+                _gsi_base: 0,
+            })),
+            1..=4 => {}
+            _ => break, // unknown type: sizes past here are unknowable
+        }
+        p += size;
+    }
+
+    Some(Box::new(MadtInfo {
+        _lapic_phys: Box::new(lapic),
+        cpus: Box::new(cpus),
+        _ioapics: Box::new(ioapics),
+    }))
+}
@@ -0,0 +1,88 @@
+// src/acpi/nfit.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// NVDIMM Firmware Interface Table. Of its structures only the System
+// Physical Address (SPA) ranges matter to us, and of those only the ones
+// whose type GUID says byte-addressable persistent memory: the regions
+// `mem::pmem` turns into devices. Control regions, block windows and the
+// DIMM maps behind an interleave set are not read.
+
+use heapless::Vec;
+
+use super::madt::find_table;
+use crate::bootinfo::BootInfo;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_RANGES: usize = 16;
+
+const HDR_LEN: usize = 40; // SDT header + 4 reserved bytes
+const TYPE_SPA: u16 = 0;
+const SPA_LEN: usize = 56; // 64 from ACPI 6.4 (location cookie)
+const SPA_FLAG_PROXIMITY_VALID: u16 = 1 << 1;
+
+// 66F0D379-B4F3-4074-AC43-0D3318B78CDB, in table byte order.
+const PMEM_GUID: [u8; 16] = [
+    0x79, 0xd3, 0xf0, 0x66, 0xf3, 0xb4, 0x74, 0x40, 0xac, 0x43, 0x0d, 0x33, 0x18, 0xb7, 0x8c, 0xdb,
+];
+
+/// One persistent-memory SPA range.
+#[derive(Copy, Clone, Debug)]
+pub struct SpaRange {
+    pub base: u64,
+    pub len: u64,
+    pub proximity: Option<u32>, // NUMA proximity domain, if given
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn u16_at(b: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(b[off..off + 2].try_into().unwrap())
+}
+
+fn u32_at(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(b: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(b[off..off + 8].try_into().unwrap())
+}
+
+fn spa(s: &[u8]) -> Option<SpaRange> {
+    if s.len() < SPA_LEN || s[16..32] != PMEM_GUID {
+        return None;
+    }
+    let flags = u16_at(s, 6);
+    Some(SpaRange {
+        base: u64_at(s, 32),
+        len: u64_at(s, 40),
+        proximity: (flags & SPA_FLAG_PROXIMITY_VALID != 0).then(|| u32_at(s, 12)),
+    })
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Persistent-memory ranges the NFIT describes; empty without one.
+pub fn pmem_ranges(boot: &BootInfo) -> Vec<SpaRange, MAX_RANGES> {
+    let mut out = Vec::new();
+    let Some((phys, len)) = find_table(boot, b"NFIT") else {
+        return out;
+    };
+    let t =
+        unsafe { core::slice::from_raw_parts((boot.hhdm_base + phys) as *const u8, len as usize) };
+    let mut p = HDR_LEN;
+    while p + 4 <= t.len() {
+        let (typ, slen) = (u16_at(t, p), u16_at(t, p + 2) as usize);
+        if slen < 4 || p + slen > t.len() {
+            break;
+        }
+        if typ == TYPE_SPA
+            && let Some(r) = spa(&t[p..p + slen])
+            && r.len != 0
+        {
+            let _ = out.push(r);
+        }
+        p += slen;
+    }
+    out
+}
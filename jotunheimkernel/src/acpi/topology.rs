@@ -0,0 +1,108 @@
+// src/acpi/topology.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// The one answer to "which CPUs are there". Taken from the MADT, else the
+// MP tables, else the kernel runs uniprocessor on the BSP alone. Decided once
+// at boot; everything that sizes per-CPU state or counts CPUs asks here
+// rather than parsing firmware tables itself.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use spin::Once;
+
+use crate::acpi::{madt, mp};
+use crate::arch::x86_64::apic::lapic_id;
+use crate::bootinfo::BootInfo;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Madt,
+    MpTable,
+    Uniprocessor, // no usable firmware table
+}
+
+pub struct Topology {
+    pub source: Source,
+    pub bsp: u32,
+    /// Enabled CPUs by APIC id, BSP included.
+    pub cpus: Vec<u32>,
+}
+
+static TOPOLOGY: Once<Topology> = Once::new();
+
+/* -------------------------------- Public API -------------------------------- */
+
+fn build(boot: &BootInfo) -> Topology {
+    let bsp = lapic_id();
+    let (source, info) = if let Some(m) = madt::discover(boot) {
+        (Source::Madt, Some(m))
+    } else if let Some(m) = mp::discover(boot) {
+        kprintln!("[cpus] no MADT; using the MP configuration table");
+        (Source::MpTable, Some(m))
+    } else {
+        (Source::Uniprocessor, None)
+    };
+
+    let mut cpus: Vec<u32> = info
+        .iter()
+        .flat_map(|m| m.cpus.iter())
+        .filter(|c| c.enabled)
+        .map(|c| c.apic_id)
+        .collect();
+    if !cpus.contains(&bsp) {
+        // A table that omits the CPU we are running on cannot be trusted
+        // for the others either.
+        if source != Source::Uniprocessor {
+            kprintln!("[cpus] {:?} does not list the BSP; ignoring it", source);
+        }
+        return Topology {
+            source: Source::Uniprocessor,
+            bsp,
+            cpus: alloc::vec![bsp],
+        };
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Topology { source, bsp, cpus }
+}
+
+/// Discover the CPUs. Needs the heap; later calls are no-ops.
+pub fn init(boot: &BootInfo) {
+    let t = TOPOLOGY.call_once(|| build(boot));
+    match t.source {
+        Source::Uniprocessor => {
+            kprintln!("[cpus] uniprocessor mode: only the BSP (apic {})", t.bsp)
+        }
+        s => kprintln!(
+            "[cpus] {} CPUs from {:?}, BSP apic {}",
+            t.cpus.len(),
+            s,
+            t.bsp
+        ),
+    }
+}
+
+/// The topology, or None before `init()`.
+pub fn get() -> Option<&'static Topology> {
+    TOPOLOGY.get()
+}
+
+/// Enabled CPUs; 1 before `init()` and in uniprocessor mode.
+pub fn cpu_count() -> usize {
+    get().map_or(1, |t| t.cpus.len())
+}
+
+pub fn is_uniprocessor() -> bool {
+    get().is_none_or(|t| t.source == Source::Uniprocessor)
+}
+
+/// Enabled APIC ids other than the BSP's.
+pub fn application_processors() -> impl Iterator<Item = u32> {
+    get()
+        .into_iter()
+        .flat_map(|t| t.cpus.iter().copied().filter(move |&id| id != t.bsp))
+}
@@ -0,0 +1,191 @@
+// src/arch/x86_64/alternatives.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Boot-time instruction patching. `alternative!` emits the default sequence
+// inline and records a replacement in `.alternatives`; `apply()` copies the
+// replacement over the default on CPUs that have the feature, padding with
+// NOPs. The default must be at least as long as the replacement. Patching
+// runs under stop_machine with CR0.WP cleared, once, before the sites are hot.
+
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::smp;
+use crate::debug::breakpoint::with_wp_disabled;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[repr(u16)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Feature {
+    Mwait = 0,    // MONITOR/MWAIT
+    Erms = 1,     // enhanced REP MOVSB
+    XsaveOpt = 2, // XSAVEOPT
+}
+
+// Layout must match the directives in `alternative!`.
+#[repr(C)]
+struct AltEntry {
+    site: u64,
+    repl: u64,
+    feature: u16,
+    site_len: u8,
+    repl_len: u8,
+    _pad: u32,
+}
+
+unsafe extern "C" {
+    static __alt_start: AltEntry;
+    static __alt_end: AltEntry;
+}
+
+static APPLIED: AtomicBool = AtomicBool::new(false);
+
+/// `alternative!(["default", ...], ["replacement", ...], Feature::X, operands...)`
+/// Both sequences share the operand list, so they must agree on registers.
+#[macro_export]
+macro_rules! alternative {
+    ([$($orig:literal),+ $(,)?], [$($repl:literal),+ $(,)?], $feat:expr $(, $($ops:tt)*)?) => {
+        core::arch::asm!(
+            "661:",
+            $($orig,)+
+            "662:",
+            ".pushsection .altinstr_replacement, \"ax\"",
+            "663:",
+            $($repl,)+
+            "664:",
+            ".popsection",
+            ".pushsection .alternatives, \"a\"",
+            ".balign 8",
+            ".quad 661b",
+            ".quad 663b",
+            ".word {alt_feature}",
+            ".byte 662b - 661b",
+            ".byte 664b - 663b",
+            ".long 0",
+            ".popsection",
+            alt_feature = const ($feat as u16),
+            $($($ops)*)?
+        )
+    };
+}
+
+/* --------------------------------- Features --------------------------------- */
+
+pub fn has(f: Feature) -> bool {
+    match f {
+        Feature::Mwait => __cpuid(1).ecx & (1 << 3) != 0,
+        Feature::Erms => __cpuid_count(7, 0).ebx & (1 << 9) != 0,
+        Feature::XsaveOpt => __cpuid_count(0xD, 1).eax & 1 != 0,
+    }
+}
+
+fn feature_from(v: u16) -> Option<Feature> {
+    match v {
+        0 => Some(Feature::Mwait),
+        1 => Some(Feature::Erms),
+        2 => Some(Feature::XsaveOpt),
+        _ => None,
+    }
+}
+
+/* --------------------------------- Patching --------------------------------- */
+
+fn entries() -> &'static [AltEntry] {
+    unsafe {
+        let start = &raw const __alt_start;
+        let end = &raw const __alt_end;
+        let n = (end as usize - start as usize) / size_of::<AltEntry>();
+        core::slice::from_raw_parts(start, n)
+    }
+}
+
+/// Patch every recorded site whose feature this CPU has. Idempotent.
+pub fn apply() {
+    if APPLIED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let (mut patched, mut skipped) = (0, 0);
+    smp::stop_machine(|| {
+        with_wp_disabled(|| {
+            for e in entries() {
+                let Some(feat) = feature_from(e.feature) else {
+                    continue;
+                };
+                if !has(feat) {
+                    continue;
+                }
+                if e.repl_len > e.site_len {
+                    skipped += 1;
+                    continue;
+                }
+                unsafe {
+                    let site = e.site as *mut u8;
+                    let repl = e.repl as *const u8;
+                    core::ptr::copy_nonoverlapping(repl, site, e.repl_len as usize);
+                    core::ptr::write_bytes(
+                        site.add(e.repl_len as usize),
+                        0x90,
+                        (e.site_len - e.repl_len) as usize,
+                    );
+                }
+                patched += 1;
+            }
+        });
+        // Serialize before running anything we may just have rewritten.
+        __cpuid(0);
+    });
+    kprintln!(
+        "[alt] patched {} site(s), {} skipped (replacement too long)",
+        patched,
+        skipped
+    );
+}
+
+/* ------------------------------ Patched sites ------------------------------- */
+
+// Any cache line will do: the wakeup we wait for is an interrupt.
+static IDLE_LINE: AtomicU64 = AtomicU64::new(0);
+
+/// Wait for the next interrupt: `hlt`, or MONITOR/MWAIT where supported.
+pub fn idle_wait() {
+    unsafe {
+        alternative!(
+            ["hlt", ".skip 7, 0x90"],
+            ["monitor", "xor eax, eax", "mwait"],
+            Feature::Mwait,
+            inout("rax") IDLE_LINE.as_ptr() => _,
+            in("ecx") 0,
+            in("edx") 0,
+            options(nostack)
+        );
+    }
+}
+
+/// Forward byte copy: qword `rep movs` plus tail, or one `rep movsb` on
+/// CPUs with ERMS.
+///
+/// # Safety
+/// Same contract as `core::ptr::copy_nonoverlapping`.
+pub unsafe fn copy_bytes(dst: *mut u8, src: *const u8, n: usize) {
+    unsafe {
+        alternative!(
+            [
+                "mov rdx, rcx",
+                "shr rcx, 3",
+                "rep movsq",
+                "mov rcx, rdx",
+                "and rcx, 7",
+                "rep movsb"
+            ],
+            ["rep movsb"],
+            Feature::Erms,
+            inout("rdi") dst => _,
+            inout("rsi") src => _,
+            inout("rcx") n => _,
+            out("rdx") _,
+            options(nostack)
+        );
+    }
+}
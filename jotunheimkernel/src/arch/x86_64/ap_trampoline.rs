@@ -7,6 +7,7 @@ unsafe extern "C" {
     unsafe static _ap_tramp_end: u8;
     unsafe static _ap_tramp_apboot_ptr32: u8;
     unsafe static _ap_tramp_apboot_ptr64: u8;
+    unsafe static _ap_tramp_stage: u8;
 }
 
 pub fn blob() -> (&'static [u8], usize, usize) {
@@ -23,3 +24,8 @@ pub fn blob() -> (&'static [u8], usize, usize) {
         )
     }
 }
+
+/// Offset in the blob of the byte the trampoline records its progress in.
+pub fn stage_offset() -> usize {
+    unsafe { &_ap_tramp_stage as *const u8 as usize - &_ap_tramp_start as *const u8 as usize }
+}
@@ -46,6 +46,7 @@ const MSR_X2APIC_EOI: u32 = 0x0000_080B;
 const MSR_X2APIC_SIVR: u32 = 0x0000_080F;
 const MSR_X2APIC_ICR: u32 = 0x0000_0830; // Interrupt Command Register
 const MSR_X2APIC_LVT_TIMER: u32 = 0x0000_0832;
+const MSR_X2APIC_LVT_LINT0: u32 = 0x0000_0835;
 const MSR_X2APIC_INIT_COUNT: u32 = 0x0000_0838;
 
 //
@@ -60,6 +61,7 @@ const LAPIC_SIVR_OFF: usize = 0xF0 / 4;
 const LAPIC_ICRLO: usize = 0x300 / 4;
 const LAPIC_ICRHI: usize = 0x310 / 4;
 const LAPIC_LVT_TMR: usize = 0x320 / 4;
+const LAPIC_LVT_LINT0: usize = 0x350 / 4;
 const LAPIC_INITCNT: usize = 0x380 / 4;
 const LAPIC_DCR: usize = 0x3E0 / 4;
 
@@ -225,6 +227,16 @@ pub fn lapic_id() -> u32 {
     }
 }
 
+/// Length of the kernel's per-CPU tables, which are indexed by APIC id.
+/// CPUs with a higher id go untracked (and stay out of scheduling).
+pub const MAX_CPUS: usize = 64;
+
+/// This CPU's per-CPU table index: the RDTSCP tag if there is one (cheap,
+/// no APIC access), else `lapic_id()`. May be >= MAX_CPUS.
+pub fn cpu_index() -> usize {
+    crate::stats::cpu_tag().unwrap_or_else(lapic_id) as usize
+}
+
 /// Unmask all priorities on this CPU (TPR=0).
 pub fn open_all_irqs() {
     match load_mode() {
@@ -316,6 +328,32 @@ pub fn start_timer_hz(hz: u32) {
     }
 }
 
+/// Mask this CPU's local timer.
+pub fn stop_timer() {
+    let masked = (1 << 16) | TIMER_VECTOR as u32;
+    match load_mode() {
+        Mode::X2Apic => {
+            wrmsr(MSR_X2APIC_LVT_TIMER, masked as u64);
+            wrmsr(MSR_X2APIC_INIT_COUNT, 0);
+        }
+        Mode::XApic { .. } => {
+            mmio_write(LAPIC_LVT_TMR, masked);
+            mmio_write(LAPIC_INITCNT, 0);
+        }
+        _ => {}
+    }
+}
+
+/// Route the 8259's output in through LINT0 (virtual wire mode).
+pub fn lint0_extint() {
+    let lvt = 0b111 << 8; // ExtINT delivery, unmasked, edge
+    match load_mode() {
+        Mode::X2Apic => wrmsr(MSR_X2APIC_LVT_LINT0, lvt as u64),
+        Mode::XApic { .. } => mmio_write(LAPIC_LVT_LINT0, lvt),
+        _ => {}
+    }
+}
+
 // ===== INIT/SIPI helpers expected by smp.rs =====
 
 #[inline]
@@ -0,0 +1,114 @@
+// src/arch/x86_64/cache.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Cache maintenance and memory barriers for drivers.
+//
+// x86 DMA is cache-coherent for ordinary write-back memory, so the flushes
+// are for what snooping does not cover: buffers a device reads through a
+// non-coherent path (some GPUs, write-combining or UC aliases of the same
+// pages), persistent memory, and handing memory to firmware or another
+// machine. The barriers are what a driver needs between its own accesses to
+// device-visible memory:
+//   - `mfence`: every load and store before it is globally visible before
+//     any after it. The only x86 barrier that orders a store before a later
+//     load (publish an index, then read the device's).
+//   - `sfence`: stores before it (including non-temporal stores and
+//     CLFLUSHOPT/CLWB) are visible before stores after it; e.g. before the
+//     doorbell write that tells a device to look.
+// Each is also a compiler barrier.
+//
+// `flush_range` writes back and invalidates every line of a range;
+// `wb_range` only writes back, keeping the lines cached where CLWB exists.
+// Both use the fastest instruction the CPU has (CLWB, then CLFLUSHOPT,
+// then CLFLUSH) and return with the range written back.
+
+use core::arch::asm;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+use spin::Once;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, Debug)]
+struct Caps {
+    line: usize, // CLFLUSH line size in bytes
+    clflush: bool,
+    clflushopt: bool,
+    clwb: bool,
+}
+
+static CAPS: Once<Caps> = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn caps() -> &'static Caps {
+    CAPS.call_once(|| {
+        let l1 = __cpuid(1);
+        let l7 = __cpuid_count(7, 0).ebx;
+        let line = ((l1.ebx >> 8) & 0xff) as usize * 8;
+        Caps {
+            line: if line == 0 { 64 } else { line },
+            clflush: l1.edx & (1 << 19) != 0,
+            clflushopt: l7 & (1 << 23) != 0,
+            clwb: l7 & (1 << 24) != 0,
+        }
+    })
+}
+
+// Start of every cache line touching [addr, addr + len).
+fn lines(addr: *const u8, len: usize, line: usize) -> impl Iterator<Item = usize> {
+    let start = addr as usize & !(line - 1);
+    let end = addr as usize + len;
+    (start..end).step_by(line)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Full barrier: loads and stores before it, then loads and stores after.
+#[inline]
+pub fn mfence() {
+    unsafe { asm!("mfence", options(nostack, preserves_flags)) };
+}
+
+/// Store barrier: earlier stores and cache write-backs before later stores.
+#[inline]
+pub fn sfence() {
+    unsafe { asm!("sfence", options(nostack, preserves_flags)) };
+}
+
+/// Write back and invalidate the lines covering `len` bytes at `addr`.
+/// The range must be mapped. Without CLFLUSH (never seen on x86-64) this
+/// falls back to WBINVD of the whole cache.
+pub fn flush_range(addr: *const u8, len: usize) {
+    let c = caps();
+    if len == 0 {
+        return;
+    }
+    if c.clflushopt {
+        for l in lines(addr, len, c.line) {
+            unsafe { asm!("clflushopt [{}]", in(reg) l, options(nostack, preserves_flags)) };
+        }
+        sfence();
+    } else if c.clflush {
+        // Ordered against stores already; the fence orders it against loads.
+        for l in lines(addr, len, c.line) {
+            unsafe { asm!("clflush [{}]", in(reg) l, options(nostack, preserves_flags)) };
+        }
+        mfence();
+    } else {
+        unsafe { asm!("wbinvd", options(nostack, preserves_flags)) };
+    }
+}
+
+/// Write back the lines covering `len` bytes at `addr`, leaving them
+/// cached if the CPU can (CLWB); otherwise the same as `flush_range`.
+pub fn wb_range(addr: *const u8, len: usize) {
+    let c = caps();
+    if !c.clwb {
+        return flush_range(addr, len);
+    }
+    for l in lines(addr, len, c.line) {
+        unsafe { asm!("clwb [{}]", in(reg) l, options(nostack, preserves_flags)) };
+    }
+    sfence();
+}
@@ -0,0 +1,212 @@
+// src/arch/x86_64/cet.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// CET supervisor shadow stacks. Every CALL also pushes the return address
+// onto a second stack that ordinary stores cannot write, and RET faults
+// (#CP) when the two disagree, so a smashed return address no longer
+// redirects control flow.
+//
+// Set up in two steps. `init()` runs in the Arch stage: it allocates the
+// boot shadow stack and one per IST slot (each with a supervisor token),
+// points the MSRs at them and, from then on, every new kernel thread gets
+// a shadow stack of its own. `activate()` flips the switch. It must be
+// inlined into a frame that never returns (the tail of `_start`): once
+// shadow stacks are on, returning into a frame entered before that faults.
+//
+// Task switches happen on interrupt return. Interrupt delivery pushes
+// [SSP, LIP, CS] onto the shadow stack and IRET checks LIP against the RIP
+// it returns to, so the ISR stubs (SYNC_SHADOW) write the trap frame's RIP
+// into that record and, when the scheduler switched tasks, swap the saved
+// SSP for the next task's, keeping the old one in `CET_SAVE_TO`. Those two
+// are global and only the BSP turns shadow stacks on, so with CET enabled
+// the APs do not schedule (as with `nosmpsched`): a task never runs on a
+// CPU whose shadow stack does not match its stack.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use core::arch::asm;
+use core::arch::x86_64::__cpuid_count;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use x86_64::registers::model_specific::Msr;
+
+use crate::error::KResult;
+use crate::{cmdline, kprintln, mem};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const CPUID_CET_SS: u32 = 1 << 7; // leaf 7.0 ECX
+
+const IA32_S_CET: u32 = 0x6A2;
+const IA32_PL0_SSP: u32 = 0x6A4;
+const IA32_INTERRUPT_SSP_TABLE: u32 = 0x6A8;
+const S_CET_SH_STK_EN: u64 = 1 << 0;
+const S_CET_WR_SHSTK_EN: u64 = 1 << 1; // WRSS, used by the ISR stubs
+const CR4_CET: u64 = 1 << 23;
+
+const PAGE: usize = 4096;
+const BOOT_PAGES: usize = 4;
+const IST_PAGES: usize = 2;
+const IST_SLOTS: usize = 7;
+
+/// A shadow stack, mapped read-only + dirty below an unmapped guard page.
+#[derive(Debug)]
+pub struct ShadowStack {
+    base: *mut u8,
+    pages: usize,
+    /// Saved SSP while the owning task is switched out.
+    pub ssp: u64,
+}
+
+// Owned by its task; only touched under the run-queue lock.
+unsafe impl Send for ShadowStack {}
+
+static READY: AtomicBool = AtomicBool::new(false); // init() succeeded
+static ACTIVE: AtomicBool = AtomicBool::new(false); // activate() ran
+static BOOT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Read by the ISR stubs: the SSP to resume with, 0 for none. Cleared
+/// once consumed.
+#[unsafe(no_mangle)]
+pub static CET_NEXT_SSP: AtomicU64 = AtomicU64::new(0);
+/// Read by the ISR stubs: where to store the outgoing SSP, 0 for nowhere.
+#[unsafe(no_mangle)]
+pub static CET_SAVE_TO: AtomicU64 = AtomicU64::new(0);
+
+/* ------------------------------- Shadow stacks ------------------------------ */
+
+impl ShadowStack {
+    /// With `token`, the top slot holds a supervisor shadow-stack token so
+    /// the stack can be entered by SETSSBSY or through the ISST.
+    pub fn new(pages: usize, token: bool) -> KResult<Self> {
+        let base = mem::vmap_alloc_shadow(pages, token)?;
+        let top = base as u64 + (pages * PAGE) as u64;
+        Ok(Self {
+            base,
+            pages,
+            ssp: top,
+        })
+    }
+
+    /// Sized for a thread stack of `stack_bytes`: return addresses only,
+    /// so an eighth is plenty.
+    pub fn for_stack(stack_bytes: usize) -> KResult<Self> {
+        Self::new((stack_bytes / 8).div_ceil(PAGE).max(1), false)
+    }
+
+    pub fn top(&self) -> u64 {
+        self.base as u64 + (self.pages * PAGE) as u64
+    }
+
+    /// Address of the supervisor token (top slot).
+    pub fn token(&self) -> u64 {
+        self.top() - 8
+    }
+}
+
+impl Drop for ShadowStack {
+    fn drop(&mut self) {
+        mem::vmap_free_guarded(self.base, self.pages);
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn supported() -> bool {
+    let max = __cpuid_count(0, 0).eax;
+    max >= 7 && __cpuid_count(7, 0).ecx & CPUID_CET_SS != 0
+}
+
+/// Shadow stacks are set up and new threads get one.
+pub fn enabled() -> bool {
+    READY.load(Ordering::Relaxed)
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Allocate the boot and IST shadow stacks and program the MSRs. Opt-in
+/// with `cet` on the command line. Call on the BSP once the heap and vmap
+/// work, before the scheduler creates threads.
+pub fn init() {
+    if !cmdline::flag("cet") {
+        return;
+    }
+    if !supported() {
+        kprintln!("[cet] shadow stacks not supported by this CPU");
+        return;
+    }
+    let Ok(boot) = ShadowStack::new(BOOT_PAGES, true) else {
+        kprintln!("[cet] out of memory for the boot shadow stack");
+        return;
+    };
+    // ISST: entry 0 is unused, 1..=7 match the TSS IST slots.
+    let isst: &'static mut [u64; IST_SLOTS + 1] = Box::leak(Box::new([0; IST_SLOTS + 1]));
+    for slot in isst.iter_mut().skip(1) {
+        let Ok(s) = ShadowStack::new(IST_PAGES, true) else {
+            kprintln!("[cet] out of memory for IST shadow stacks");
+            return;
+        };
+        *slot = s.token();
+        core::mem::forget(s); // per-CPU, lives forever
+    }
+    unsafe {
+        Msr::new(IA32_INTERRUPT_SSP_TABLE).write(isst.as_ptr() as u64);
+        Msr::new(IA32_PL0_SSP).write(boot.token());
+    }
+    BOOT_TOKEN.store(boot.token(), Ordering::Relaxed);
+    core::mem::forget(boot);
+    READY.store(true, Ordering::Relaxed);
+    kprintln!("[cet] supervisor shadow stacks ready");
+}
+
+/// Turn shadow stacks on for this CPU. The shadow stack starts empty, so
+/// no frame that was live before this may return: inline only.
+#[inline(always)]
+pub fn activate() {
+    if !enabled() {
+        return;
+    }
+    ACTIVE.store(true, Ordering::Relaxed);
+    unsafe {
+        asm!(
+            "mov {t}, cr4",
+            "or {t}, {cr4cet}",
+            "mov cr4, {t}",
+            "wrmsr",
+            // setssbsy: SSP = IA32_PL0_SSP, token marked busy
+            ".byte 0xf3, 0x0f, 0x01, 0xe8",
+            t = out(reg) _,
+            cr4cet = in(reg) CR4_CET,
+            in("ecx") IA32_S_CET,
+            in("eax") (S_CET_SH_STK_EN | S_CET_WR_SHSTK_EN) as u32,
+            in("edx") 0u32,
+            options(nostack)
+        );
+    }
+}
+
+/// Scheduler side of a switch: the ISR stub stores the outgoing SSP at
+/// `save` (if any) and resumes on `next`.
+pub fn switch_to(save: Option<*mut u64>, next: u64) {
+    if !active() {
+        return;
+    }
+    CET_SAVE_TO.store(save.map_or(0, |p| p as u64), Ordering::Relaxed);
+    CET_NEXT_SSP.store(next, Ordering::Relaxed);
+}
+
+/// Current SSP; 0 while shadow stacks are off.
+pub fn ssp() -> u64 {
+    let mut v: u64 = 0;
+    // rdsspq rax: a no-op, leaving 0, when shadow stacks are off.
+    unsafe {
+        asm!(
+            ".byte 0xf3, 0x48, 0x0f, 0x1e, 0xc8",
+            inout("rax") v,
+            options(nomem, nostack)
+        )
+    };
+    v
+}
@@ -0,0 +1,66 @@
+// src/arch/x86_64/clock.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Where the scheduler tick comes from: the LAPIC timer normally, or the PIT
+// through the 8259 when `timer=pit` is on the command line or the LAPIC timer
+// turns out not to work. Both land on the same handler; `eoi()` acknowledges
+// whichever controller delivered it.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{apic, pic, pit};
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const TICK_HZ: u32 = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Lapic,
+    Pit,
+}
+
+static SOURCE: AtomicU8 = AtomicU8::new(Source::Lapic as u8);
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn source() -> Source {
+    match SOURCE.load(Ordering::Relaxed) {
+        x if x == Source::Pit as u8 => Source::Pit,
+        _ => Source::Lapic,
+    }
+}
+
+/// Start the BSP's tick from the source the command line asks for.
+pub fn init() {
+    match cmdline::value("timer") {
+        Some("pit") => use_pit(),
+        Some(v) if v != "lapic" => {
+            kprintln!("[clock] unknown timer '{}'; using the LAPIC", v);
+            apic::start_timer_hz(TICK_HZ);
+        }
+        _ => apic::start_timer_hz(TICK_HZ),
+    }
+}
+
+/// Stop the LAPIC timer and tick from the PIT instead. BSP only: IRQ 0 is
+/// wired to it alone.
+pub fn use_pit() {
+    apic::stop_timer();
+    apic::lint0_extint();
+    let hz = pit::start_periodic(TICK_HZ);
+    SOURCE.store(Source::Pit as u8, Ordering::Relaxed);
+    pic::unmask(pit::IRQ);
+    kprintln!("[clock] ticking from the PIT at {} Hz", hz);
+}
+
+/// Acknowledge a tick. In PIT mode the APs' LAPIC ticks share the vector
+/// path, so ask the PIC whether IRQ 0 is actually in service.
+pub fn eoi() {
+    if source() == Source::Pit && pic::in_service() & 1 << pit::IRQ != 0 {
+        pic::eoi(pit::IRQ);
+    } else {
+        apic::eoi();
+    }
+}
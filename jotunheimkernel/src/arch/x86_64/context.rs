@@ -1,6 +1,21 @@
 // src/arch/x86_64/context.rs
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+// TrapFrame and the hand-built stack frames the NASM trampolines consume.
+// There is one context-switch mechanism: every ISR stub saves a TrapFrame,
+// the Rust handler may replace it (sched::tick hands back the next task's),
+// and the common epilogue resumes whatever frame it finds.
+// Every offset the assembly relies on has a Rust constant here, checked at
+// compile time against the struct layout; `selftest()` round-trips a frame
+// through the stub macros and `spawn_probe()` checks at boot that a freshly
+// spawned kthread lands in its entry with the expected argument, flags,
+// selector and ABI stack alignment.
+
+use core::arch::{asm, naked_asm};
+use core::mem::{offset_of, size_of};
+
+use crate::arch::x86_64::tables::gdt::kernel_cs;
+use crate::{kprintln, sched};
 
 #[derive(Copy, Clone, Default, Debug)]
 #[repr(C)]
@@ -29,3 +44,244 @@ pub struct TrapFrame {
     pub ss: u64,
 }
 
+/* ---------------------------- TrapFrame offsets ----------------------------- */
+
+// build.rs turns these into NASM %defines in `trapframe.inc` under OUT_DIR,
+// which the ISR stubs %include, so they must stay plain `N * 8` literals.
+pub const TF_R15: usize = 0;
+pub const TF_R14: usize = 8;
+pub const TF_R13: usize = 2 * 8;
+pub const TF_R12: usize = 3 * 8;
+pub const TF_R11: usize = 4 * 8;
+pub const TF_R10: usize = 5 * 8;
+pub const TF_R9: usize = 6 * 8;
+pub const TF_R8: usize = 7 * 8;
+pub const TF_RSI: usize = 8 * 8;
+pub const TF_RDI: usize = 9 * 8;
+pub const TF_RBP: usize = 10 * 8;
+pub const TF_RDX: usize = 11 * 8;
+pub const TF_RCX: usize = 12 * 8;
+pub const TF_RBX: usize = 13 * 8;
+pub const TF_RAX: usize = 14 * 8;
+pub const TF_VEC: usize = 15 * 8;
+pub const TF_ERR: usize = 16 * 8;
+pub const TF_RIP: usize = 17 * 8;
+pub const TF_CS: usize = 18 * 8;
+pub const TF_RFLAGS: usize = 19 * 8;
+pub const TF_RSP: usize = 20 * 8;
+pub const TF_SS: usize = 21 * 8;
+pub const TF_SIZE: usize = 22 * 8;
+
+const _: () = {
+    assert!(offset_of!(TrapFrame, r15) == TF_R15);
+    assert!(offset_of!(TrapFrame, r14) == TF_R14);
+    assert!(offset_of!(TrapFrame, r13) == TF_R13);
+    assert!(offset_of!(TrapFrame, r12) == TF_R12);
+    assert!(offset_of!(TrapFrame, r11) == TF_R11);
+    assert!(offset_of!(TrapFrame, r10) == TF_R10);
+    assert!(offset_of!(TrapFrame, r9) == TF_R9);
+    assert!(offset_of!(TrapFrame, r8) == TF_R8);
+    assert!(offset_of!(TrapFrame, rsi) == TF_RSI);
+    assert!(offset_of!(TrapFrame, rdi) == TF_RDI);
+    assert!(offset_of!(TrapFrame, rbp) == TF_RBP);
+    assert!(offset_of!(TrapFrame, rdx) == TF_RDX);
+    assert!(offset_of!(TrapFrame, rcx) == TF_RCX);
+    assert!(offset_of!(TrapFrame, rbx) == TF_RBX);
+    assert!(offset_of!(TrapFrame, rax) == TF_RAX);
+    assert!(offset_of!(TrapFrame, vec) == TF_VEC);
+    assert!(offset_of!(TrapFrame, err) == TF_ERR);
+    assert!(offset_of!(TrapFrame, rip) == TF_RIP);
+    assert!(offset_of!(TrapFrame, cs) == TF_CS);
+    assert!(offset_of!(TrapFrame, rflags) == TF_RFLAGS);
+    assert!(offset_of!(TrapFrame, rsp) == TF_RSP);
+    assert!(offset_of!(TrapFrame, ss) == TF_SS);
+    assert!(size_of::<TrapFrame>() == TF_SIZE);
+    // WRITE_BACK_HW stores rip/cs/rflags as consecutive iretq slots.
+    assert!(TF_CS == TF_RIP + 8 && TF_RFLAGS == TF_CS + 8);
+};
+
+/* ---------------------------- kthread first frame ---------------------------- */
+
+// A new kthread is first entered through the ISR epilogue: WRITE_BACK_HW
+// stores rip/cs/rflags at TrapFrame.rsp, RESTORE_GPRS_FROM_TF switches to
+// that rsp and iretq pops all five words. kthread_trampoline then pops
+// [arg][entry] and calls entry from a 16-byte aligned rsp.
+//
+//   top -  8  entry
+//   top - 16  arg      <- rsp after iretq
+//   top - 24  ss
+//   top - 32  rsp
+//   top - 40  rflags
+//   top - 48  cs
+//   top - 56  rip      <- TrapFrame.rsp
+pub const KFRAME_ENTRY: u64 = 8;
+pub const KFRAME_ARG: u64 = 16;
+pub const KFRAME_IRET: u64 = KFRAME_ARG + 5 * 8;
+
+const KTHREAD_RFLAGS: u64 = 0x202; // IF
+
+const _: () = {
+    assert!(KFRAME_ENTRY == KFRAME_ARG - 8); // popped in this order
+    assert!(KFRAME_ARG.is_multiple_of(16)); // rsp aligned once both are popped
+};
+
+unsafe extern "C" {
+    unsafe fn kthread_trampoline() -> !;
+}
+
+/// Lay out the first-run frame below `top` (16-byte aligned, exclusive)
+/// and return the TrapFrame that resumes into it.
+///
+/// # Safety
+/// `[top - KFRAME_IRET, top)` must be writable stack owned by the new task.
+pub unsafe fn kthread_frame(top: u64, entry: extern "C" fn(usize) -> !, arg: usize) -> TrapFrame {
+    debug_assert!(top.is_multiple_of(16));
+    let at = |off: u64| (top - off) as *mut u64;
+    let cs = kernel_cs() as u64;
+    let rip = kthread_trampoline as *const () as u64;
+    unsafe {
+        at(KFRAME_ENTRY).write(entry as usize as u64);
+        at(KFRAME_ARG).write(arg as u64);
+        at(KFRAME_ARG + 8).write(0); // ss
+        at(KFRAME_ARG + 16).write(top - KFRAME_ARG); // rsp
+        at(KFRAME_ARG + 24).write(KTHREAD_RFLAGS);
+        at(KFRAME_ARG + 32).write(cs);
+        at(KFRAME_IRET).write(rip);
+    }
+    TrapFrame {
+        rip,
+        rsp: top - KFRAME_IRET,
+        cs,
+        rflags: KTHREAD_RFLAGS,
+        ss: 0,
+        ..TrapFrame::default()
+    }
+}
+
+/* ------------------------------- Self-tests -------------------------------- */
+
+unsafe extern "C" {
+    unsafe fn tf_selftest(src: *const TrapFrame, dst: *mut TrapFrame);
+    unsafe fn tf_save_probe(dst: *mut TrapFrame);
+}
+
+const TF_PROBE: u64 = 0x7E00_0000_0000_0000; // as in isr_stubs.asm
+
+// Each GPR field with the register encoding tf_save_probe stores in it.
+fn by_name(tf: &TrapFrame) -> [(&'static str, u64, u64); 15] {
+    [
+        ("rax", tf.rax, 0),
+        ("rcx", tf.rcx, 1),
+        ("rdx", tf.rdx, 2),
+        ("rbx", tf.rbx, 3),
+        ("rbp", tf.rbp, 5),
+        ("rsi", tf.rsi, 6),
+        ("rdi", tf.rdi, 7),
+        ("r8", tf.r8, 8),
+        ("r9", tf.r9, 9),
+        ("r10", tf.r10, 10),
+        ("r11", tf.r11, 11),
+        ("r12", tf.r12, 12),
+        ("r13", tf.r13, 13),
+        ("r14", tf.r14, 14),
+        ("r15", tf.r15, 15),
+    ]
+}
+
+fn gprs(tf: &TrapFrame) -> [u64; 15] {
+    [
+        tf.r15, tf.r14, tf.r13, tf.r12, tf.r11, tf.r10, tf.r9, tf.r8, tf.rsi, tf.rdi, tf.rbp,
+        tf.rdx, tf.rcx, tf.rbx, tf.rax,
+    ]
+}
+
+/// Push a synthetic frame through RESTORE_GPRS_FROM_TF / SAVE_GPRS_TO_TF
+/// and panic if any register comes back from a different slot, or if
+/// SAVE_GPRS_TO_TF stores a register under another field's name.
+pub fn selftest() {
+    let mut src = TrapFrame::default();
+    let words = unsafe { core::slice::from_raw_parts_mut(&raw mut src as *mut u64, TF_SIZE / 8) };
+    for (i, w) in words.iter_mut().enumerate() {
+        *w = 0x7F00_0000_0000_0000 | (i as u64) << 8 | i as u64;
+    }
+    let mut dst = TrapFrame::default();
+    unsafe { tf_selftest(&src, &mut dst) };
+
+    let (a, b) = (gprs(&src), gprs(&dst));
+    if a != b {
+        for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+            if x != y {
+                kprintln!(
+                    "[context] selftest: gpr slot {} sent {:#x} got {:#x}",
+                    i,
+                    x,
+                    y
+                );
+            }
+        }
+        panic!("TrapFrame offsets drifted between isr_stubs.asm and context.rs");
+    }
+
+    let mut saved = TrapFrame::default();
+    unsafe { tf_save_probe(&mut saved) };
+    let mut bad = false;
+    for (name, got, reg) in by_name(&saved) {
+        if got != TF_PROBE + reg {
+            kprintln!(
+                "[context] selftest: {} holds {:#x}, expected {:#x}",
+                name,
+                got,
+                TF_PROBE + reg
+            );
+            bad = true;
+        }
+    }
+    if bad {
+        panic!("SAVE_GPRS_TO_TF stores registers under the wrong TrapFrame fields");
+    }
+    kprintln!("[context] TrapFrame asm round-trip ok");
+}
+
+/* ------------------------------- Boot probe -------------------------------- */
+
+const PROBE_MAGIC: usize = 0x6B74_6872_6561_6421;
+
+// Captures rsp before any prologue touches it.
+#[unsafe(naked)]
+extern "C" fn probe_entry(_arg: usize) -> ! {
+    naked_asm!("mov rsi, rsp", "jmp {main}", main = sym probe_main)
+}
+
+extern "C" fn probe_main(arg: usize, entry_rsp: u64) -> ! {
+    let (cs, rflags): (u64, u64);
+    unsafe {
+        asm!("mov {0:r}, cs", "pushfq", "pop {1}", out(reg) cs, out(reg) rflags);
+    }
+    let mut bad = 0;
+    if arg != PROBE_MAGIC {
+        kprintln!("[context] probe: arg {:#x}, want {:#x}", arg, PROBE_MAGIC);
+        bad += 1;
+    }
+    if entry_rsp % 16 != 8 {
+        kprintln!("[context] probe: entry rsp {:#x} misaligned", entry_rsp);
+        bad += 1;
+    }
+    if cs != kernel_cs() as u64 {
+        kprintln!("[context] probe: cs {:#x}, want {:#x}", cs, kernel_cs());
+        bad += 1;
+    }
+    if rflags & KTHREAD_RFLAGS != KTHREAD_RFLAGS {
+        kprintln!("[context] probe: rflags {:#x} lacks IF", rflags);
+        bad += 1;
+    }
+    if bad != 0 {
+        panic!("kthread frame layout does not match kthread_trampoline");
+    }
+    kprintln!("[context] kthread frame probe ok");
+    sched::exit_current()
+}
+
+/// Spawn a throwaway kthread that checks its own first-run frame.
+pub fn spawn_probe() {
+    sched::spawn_kthread(probe_entry, PROBE_MAGIC, None, sched::stack::MIN_STACK_SIZE);
+}
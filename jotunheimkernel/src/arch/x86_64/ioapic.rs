@@ -37,3 +37,54 @@ pub unsafe fn mask_all() {
         // (no need to touch high dword to just mask)
     }
 }
+
+const REDIR_MASKED: u32 = 1 << 16;
+const REDIR_LEVEL: u32 = 1 << 15;
+
+/// How a line signals. ISA devices are edge-triggered; PCI INTx lines are
+/// level-triggered, and QEMU's MADT declares them active high.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    Edge,
+    Level,
+}
+
+/// Number of redirection entries (GSIs this IOAPIC serves).
+pub fn entries() -> u32 {
+    (unsafe { mmio_read(0x01) } >> 16 & 0xFF) + 1
+}
+
+/// Point `gsi` at `vector` on `dest_apic`: fixed delivery, physical
+/// destination, active high.
+pub fn route(gsi: u32, vector: u8, dest_apic: u32, trigger: Trigger, masked: bool) {
+    let mut lo = vector as u32 | if masked { REDIR_MASKED } else { 0 };
+    if trigger == Trigger::Level {
+        lo |= REDIR_LEVEL;
+    }
+    unsafe {
+        // Mask first so the entry never fires half-written.
+        mmio_write(0x10 + gsi * 2, REDIR_MASKED);
+        mmio_write(0x11 + gsi * 2, dest_apic << 24);
+        mmio_write(0x10 + gsi * 2, lo);
+    }
+}
+
+/// Move `gsi` to another CPU, keeping its vector and mask.
+pub fn set_dest(gsi: u32, dest_apic: u32) {
+    unsafe { mmio_write(0x11 + gsi * 2, dest_apic << 24) };
+}
+
+pub fn set_masked(gsi: u32, masked: bool) {
+    let reg = 0x10 + gsi * 2;
+    unsafe {
+        let lo = mmio_read(reg);
+        mmio_write(
+            reg,
+            if masked {
+                lo | REDIR_MASKED
+            } else {
+                lo & !REDIR_MASKED
+            },
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// src/arch/x86_64/livepatch.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Function-level live patching: the first 5 bytes of the old function become
+// `jmp rel32` to its replacement. The write goes through the int3 dance so
+// other CPUs never execute a torn instruction: plant 0xCC, write the tail,
+// then the opcode, serializing every CPU between steps. A CPU that hits the
+// int3 meanwhile is sent straight to the replacement by the #BP handler.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use super::smp;
+use crate::debug::breakpoint::with_wp_disabled;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const JMP_REL32: u8 = 0xE9;
+const INT3: u8 = 0xCC;
+const PATCH_LEN: usize = 5;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PatchError {
+    OutOfRange, // replacement not within rel32 reach
+    AlreadyPatched,
+    NotPatched,
+}
+
+struct Patch {
+    site: u64,
+    target: u64,
+    saved: [u8; PATCH_LEN],
+}
+
+static PATCHES: Mutex<Vec<Patch>> = Mutex::new(Vec::new());
+
+// The site being rewritten right now and where its callers should go.
+static POKE_SITE: AtomicU64 = AtomicU64::new(0);
+static POKE_TARGET: AtomicU64 = AtomicU64::new(0);
+
+/* --------------------------------- Poking ----------------------------------- */
+
+fn write_text(addr: u64, bytes: &[u8]) {
+    with_wp_disabled(|| {
+        for (i, &b) in bytes.iter().enumerate() {
+            unsafe { ((addr + i as u64) as *mut u8).write_volatile(b) };
+        }
+    });
+}
+
+// Every CPU executes a serializing instruction before going on.
+fn sync_cores() {
+    smp::call_all(|| {
+        core::arch::x86_64::__cpuid(0);
+    });
+}
+
+/// Replace the `PATCH_LEN` bytes at `site` with `new`. CPUs arriving while
+/// the bytes are in flux are diverted to `detour`.
+fn poke(site: u64, new: &[u8; PATCH_LEN], detour: u64) {
+    POKE_TARGET.store(detour, Ordering::Release);
+    POKE_SITE.store(site, Ordering::Release);
+    write_text(site, &[INT3]);
+    sync_cores();
+    write_text(site + 1, &new[1..]);
+    sync_cores();
+    write_text(site, &new[..1]);
+    sync_cores();
+    POKE_SITE.store(0, Ordering::Release);
+}
+
+/// #BP hook: if `rip` is just past a site under `poke()`, resume in the
+/// replacement instead. Returns false for every other int3.
+pub fn on_int3(rip: &mut u64) -> bool {
+    let site = POKE_SITE.load(Ordering::Acquire);
+    if site == 0 || rip.wrapping_sub(1) != site {
+        return false;
+    }
+    *rip = POKE_TARGET.load(Ordering::Acquire);
+    true
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Redirect every future call of the function at `old` to `new`. Callers
+/// already inside `old` finish in the old code.
+pub fn redirect(old: u64, new: u64) -> Result<(), PatchError> {
+    let rel = (new as i64).wrapping_sub(old as i64 + PATCH_LEN as i64);
+    let rel = i32::try_from(rel).map_err(|_| PatchError::OutOfRange)?;
+    let mut patches = PATCHES.lock();
+    if patches.iter().any(|p| p.site == old) {
+        return Err(PatchError::AlreadyPatched);
+    }
+    let mut saved = [0u8; PATCH_LEN];
+    for (i, b) in saved.iter_mut().enumerate() {
+        *b = unsafe { ((old + i as u64) as *const u8).read_volatile() };
+    }
+    let mut jmp = [JMP_REL32; PATCH_LEN];
+    jmp[1..].copy_from_slice(&rel.to_le_bytes());
+    poke(old, &jmp, new);
+    patches.push(Patch {
+        site: old,
+        target: new,
+        saved,
+    });
+    kprintln!("[livepatch] {:#x} -> {:#x}", old, new);
+    Ok(())
+}
+
+/// Undo `redirect()` on `old`, restoring its original entry bytes.
+pub fn revert(old: u64) -> Result<(), PatchError> {
+    let mut patches = PATCHES.lock();
+    let i = patches
+        .iter()
+        .position(|p| p.site == old)
+        .ok_or(PatchError::NotPatched)?;
+    let p = patches.remove(i);
+    // Mid-revert arrivals still go to the replacement, which is intact.
+    poke(p.site, &p.saved, p.target);
+    kprintln!("[livepatch] {:#x} restored", old);
+    Ok(())
+}
+
+pub fn is_patched(old: u64) -> bool {
+    PATCHES.lock().iter().any(|p| p.site == old)
+}
+
+/* -------------------------------- Self-test --------------------------------- */
+
+#[inline(never)]
+fn probe_old(x: u64) -> u64 {
+    core::hint::black_box(x).wrapping_add(1)
+}
+
+#[inline(never)]
+fn probe_new(x: u64) -> u64 {
+    core::hint::black_box(x).wrapping_mul(3)
+}
+
+/// Redirect a dummy function, call it, and revert it; panics if a call
+/// does not land where the patch says. Needs `smp::call_all`.
+pub fn selftest() {
+    // Called through a pointer the compiler cannot see through.
+    let f: fn(u64) -> u64 = core::hint::black_box(probe_old);
+    let old = probe_old as fn(u64) -> u64 as usize as u64;
+    let new = probe_new as fn(u64) -> u64 as usize as u64;
+    assert_eq!(f(5), 6, "livepatch selftest: before patching");
+    redirect(old, new).expect("livepatch selftest: redirect");
+    assert_eq!(f(5), 15, "livepatch selftest: call not redirected");
+    revert(old).expect("livepatch selftest: revert");
+    assert_eq!(f(5), 6, "livepatch selftest: call not restored");
+    assert!(!is_patched(old));
+    kprintln!("[livepatch] selftest ok");
+}
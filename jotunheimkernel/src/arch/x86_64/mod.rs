@@ -1,14 +1,24 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+pub mod alternatives;
 mod ap_trampoline;
 pub mod apic;
+pub mod cache;
+pub mod cet;
+pub mod clock;
 pub mod context;
 pub mod ioapic;
+pub mod livepatch;
 pub mod mmio_map;
+pub mod pic;
+pub mod pit;
 pub mod serial;
+pub mod shootdown;
 pub mod simd;
 pub mod smp;
+pub mod speaker;
 pub mod tables;
+pub mod tickwatch;
 pub mod tsc;
 use crate::arch::x86_64::tables::isr;
 use crate::bootinfo::BootInfo;
@@ -20,10 +30,12 @@ pub fn init(boot: &BootInfo) {
     unsafe {
         ioapic::mask_all();
     }
+    pic::remap_and_mask();
     apic::early_init();
     isr::init();
     idt::init(gdt::init());
     apic::paging(boot.hhdm_base);
     apic::open_all_irqs();
-    apic::start_timer_hz(1000);
+    tickwatch::init();
+    clock::init();
 }
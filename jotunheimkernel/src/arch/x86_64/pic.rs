@@ -0,0 +1,88 @@
+// src/arch/x86_64/pic.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Legacy 8259 pair. Firmware leaves it on vectors 0x08-0x0F, on top of the
+// CPU exceptions, so it is always remapped to PIC_BASE and fully masked at
+// boot. Only the PIT fallback tick (IRQ 0) is ever unmasked; it reaches the
+// CPU through LINT0 in ExtINT mode and is acknowledged here, not at the LAPIC.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use x86_64::instructions::port::Port;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const PIC_BASE: u8 = 0x20; // IRQ n -> vector PIC_BASE + n
+pub const CASCADE_IRQ: u8 = 2;
+
+const MASTER_CMD: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_CMD: u16 = 0xA0;
+const SLAVE_DATA: u16 = 0xA1;
+
+const ICW1_INIT: u8 = 0x11; // edge, cascade, ICW4 follows
+const ICW4_8086: u8 = 0x01;
+const OCW2_EOI: u8 = 0x20;
+const OCW3_READ_ISR: u8 = 0x0B;
+
+// Current mask, bit n = IRQ n; starts all masked.
+static MASK: AtomicU16 = AtomicU16::new(0xFFFF);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn out(port: u16, v: u8) {
+    unsafe { Port::<u8>::new(port).write(v) };
+    io_wait();
+}
+
+// A write to an unused port gives old PICs time to settle.
+fn io_wait() {
+    unsafe { Port::<u8>::new(0x80).write(0) };
+}
+
+fn write_mask(mask: u16) {
+    MASK.store(mask, Ordering::Relaxed);
+    out(MASTER_DATA, mask as u8);
+    out(SLAVE_DATA, (mask >> 8) as u8);
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Move both PICs to PIC_BASE..PIC_BASE+16 and mask every line.
+pub fn remap_and_mask() {
+    out(MASTER_CMD, ICW1_INIT);
+    out(SLAVE_CMD, ICW1_INIT);
+    out(MASTER_DATA, PIC_BASE);
+    out(SLAVE_DATA, PIC_BASE + 8);
+    out(MASTER_DATA, 1 << CASCADE_IRQ); // slave on IRQ 2
+    out(SLAVE_DATA, CASCADE_IRQ); // cascade identity
+    out(MASTER_DATA, ICW4_8086);
+    out(SLAVE_DATA, ICW4_8086);
+    write_mask(0xFFFF);
+}
+
+pub fn unmask(irq: u8) {
+    let mut m = MASK.load(Ordering::Relaxed) & !(1 << irq);
+    if irq >= 8 {
+        m &= !(1 << CASCADE_IRQ);
+    }
+    write_mask(m);
+}
+
+pub fn eoi(irq: u8) {
+    if irq >= 8 {
+        out(SLAVE_CMD, OCW2_EOI);
+    }
+    out(MASTER_CMD, OCW2_EOI);
+}
+
+/// In-service bits of both PICs; a line with no bit set was spurious.
+pub fn in_service() -> u16 {
+    out(MASTER_CMD, OCW3_READ_ISR);
+    out(SLAVE_CMD, OCW3_READ_ISR);
+    unsafe {
+        let lo = Port::<u8>::new(MASTER_CMD).read() as u16;
+        let hi = Port::<u8>::new(SLAVE_CMD).read() as u16;
+        hi << 8 | lo
+    }
+}
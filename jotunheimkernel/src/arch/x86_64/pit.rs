@@ -0,0 +1,31 @@
+// src/arch/x86_64/pit.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// 8254 PIT channel 0 as a periodic tick on IRQ 0. Its input clock is fixed,
+// so unlike the LAPIC timer it needs no calibration.
+
+use x86_64::instructions::port::Port;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const PIT_HZ: u32 = 1_193_182;
+pub const IRQ: u8 = 0;
+
+const PIT_CH0: u16 = 0x40;
+const PIT_CMD: u16 = 0x43;
+const CMD_CH0_RATE: u8 = 0b0011_0100; // ch0, lo/hi byte, mode 2
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Program channel 0 to fire `hz` times a second; returns the rate actually
+/// achieved, which differs slightly since the divisor is an integer.
+pub fn start_periodic(hz: u32) -> u32 {
+    let div = (PIT_HZ / hz.max(1)).clamp(2, 0xFFFF);
+    unsafe {
+        Port::<u8>::new(PIT_CMD).write(CMD_CH0_RATE);
+        let mut ch0 = Port::<u8>::new(PIT_CH0);
+        ch0.write(div as u8);
+        ch0.write((div >> 8) as u8);
+    }
+    PIT_HZ / div
+}
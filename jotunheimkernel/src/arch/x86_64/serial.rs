@@ -4,15 +4,21 @@
 #![allow(dead_code)]
 
 use core::fmt::{self, Write};
-use spin::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, Once};
 use uart_16550::SerialPort;
 use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
 
 /// Global COM1 handle. It's inside a Mutex to serialize writers.
 /// We store it as Option so the printing path can cheaply no-op if not inited.
 static COM1: Mutex<Option<SerialPort>> = Mutex::new(None);
 /// Dedicated COM2 for the debugger (RSP or secondary console).
 static COM2: Mutex<Option<SerialPort>> = Mutex::new(None);
+/// Extra log sinks (virtio console port, framebuffer console), fed after COM1.
+const MAX_MIRRORS: usize = 4;
+static LOG_MIRRORS: [Once<fn(&str)>; MAX_MIRRORS] = [const { Once::new() }; MAX_MIRRORS];
+static MIRROR_SLOTS: AtomicUsize = AtomicUsize::new(0);
 
 // init_com1 / init_com2: wrap SerialPort::new in an explicit unsafe block
 pub unsafe fn init_com1(_baud: u32) {
@@ -56,6 +62,52 @@ impl Write for Com1Writer {
     }
 }
 
+/// Forwards formatted log text to the registered mirror.
+struct MirrorWriter(fn(&str));
+
+impl Write for MirrorWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        (self.0)(s);
+        Ok(())
+    }
+}
+
+/// Lock-free, heap-free COM1 writer that polls the UART registers directly.
+/// Works before `init_com1` (the loader leaves COM1 at 115200 8N1) and from
+/// contexts where the COM1 mutex may be held by whoever we interrupted, such
+/// as the panic handler. Concurrent users can interleave characters.
+pub struct EarlyConsole;
+
+const COM1_BASE: u16 = 0x3F8;
+const LSR_THR_EMPTY: u8 = 0x20;
+
+impl EarlyConsole {
+    fn putc(b: u8) {
+        let mut lsr = Port::<u8>::new(COM1_BASE + 5);
+        let mut thr = Port::<u8>::new(COM1_BASE);
+        // Bounded: a missing UART must not hang the caller.
+        for _ in 0..100_000 {
+            if unsafe { lsr.read() } & LSR_THR_EMPTY != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        unsafe { thr.write(b) };
+    }
+}
+
+impl Write for EarlyConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            if b == b'\n' {
+                Self::putc(b'\r');
+            }
+            Self::putc(b);
+        }
+        Ok(())
+    }
+}
+
 /// COM2 writer (for debugger messages, optional banner)
 struct Com2Writer;
 
@@ -82,11 +134,43 @@ impl Write for Com2Writer {
 
 #[doc(hidden)]
 pub fn _kprint(args: fmt::Arguments) {
-    // If COM1 isn't ready, silently drop—early boot should not crash on logs.
+    let _ = crate::logring::RingWriter.write_fmt(args);
+    // Before init_com1, fall back to the raw port.
     if !com1_ready() {
+        let _ = EarlyConsole.write_fmt(args);
         return;
     }
     let _ = Com1Writer.write_fmt(args);
+    for &f in LOG_MIRRORS.iter().filter_map(Once::get) {
+        let _ = MirrorWriter(f).write_fmt(args);
+    }
+}
+
+/// One `kprintln!` line: the `klog` prefix, the message, a newline.
+#[doc(hidden)]
+pub fn _kprintln(args: fmt::Arguments) {
+    let mut prefix = heapless::String::<48>::new();
+    let _ = crate::klog::write_prefix(&mut prefix);
+    _kprint(format_args!("{}{}\n", prefix, args));
+}
+
+/// Install another log sink; false once all slots are taken. A sink must
+/// not block: it can be reached from any context that logs, including its
+/// own driver.
+pub fn add_log_mirror(f: fn(&str)) -> bool {
+    let slot = MIRROR_SLOTS.fetch_add(1, Ordering::Relaxed);
+    match LOG_MIRRORS.get(slot) {
+        Some(m) => {
+            m.call_once(|| f);
+            true
+        }
+        None => false,
+    }
+}
+
+#[doc(hidden)]
+pub fn _early_print(args: fmt::Arguments) {
+    let _ = EarlyConsole.write_fmt(args);
 }
 
 #[doc(hidden)]
@@ -175,15 +259,14 @@ macro_rules! kprint {
     }};
 }
 
+/// Print one line to COM1, prefixed as `klog` is configured.
 #[macro_export]
 macro_rules! kprintln {
     () => {{
-        $crate::arch::x86_64::serial::_kprint(core::format_args!("\n"));
+        $crate::arch::x86_64::serial::_kprintln(core::format_args!(""));
     }};
     ($($arg:tt)*) => {{
-        // First print the formatted message, then a newline (both stable).
-        $crate::arch::x86_64::serial::_kprint(core::format_args!($($arg)*));
-        $crate::arch::x86_64::serial::_kprint(core::format_args!("\n"));
+        $crate::arch::x86_64::serial::_kprintln(core::format_args!($($arg)*));
     }};
 }
 
@@ -207,6 +290,16 @@ macro_rules! dprintln {
     }};
 }
 
+/// Print to COM1 through `EarlyConsole`, with newline. Takes no locks and
+/// never allocates: for panic paths and code that may run under COM1's lock.
+#[macro_export]
+macro_rules! early_println {
+    ($($arg:tt)*) => {{
+        $crate::arch::x86_64::serial::_early_print(core::format_args!($($arg)*));
+        $crate::arch::x86_64::serial::_early_print(core::format_args!("\n"));
+    }};
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Small convenience banner helpers (optional)
 
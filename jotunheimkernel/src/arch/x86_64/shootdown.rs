@@ -0,0 +1,120 @@
+// src/arch/x86_64/shootdown.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// TLB shootdown. A CPU that unmaps or changes kernel mappings other CPUs
+// may have cached publishes the range, sets one pending bit per other
+// online CPU and sends each SHOOTDOWN_VECTOR. Each target flushes the range
+// from its own TLB and clears its bit (its acknowledgment); the initiator
+// flushes locally and spins until every bit is clear. Only then may it
+// reuse what the range mapped: frames, VA, or a device window.
+//
+// One shootdown is in flight at a time. Everything that spins with IRQs
+// off waiting on other CPUs (here, the cross-call paths in `smp`) also
+// answers shootdowns while it waits, so two initiators, or an initiator
+// and a cross call, cannot deadlock on each other.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::{Mutex, MutexGuard};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+use super::apic::{self, lapic_id};
+use super::smp;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// Vector for shootdown IPIs.
+pub const SHOOTDOWN_VECTOR: u8 = 0xF1;
+const MAX_CPUS: usize = 256; // indexed by xAPIC id, as in smp
+// Past this many pages a CPU drops its whole TLB instead of INVLPGing each.
+const FLUSH_ALL_PAGES: u64 = 32;
+
+static LOCK: Mutex<()> = Mutex::new(());
+static START: AtomicU64 = AtomicU64::new(0);
+static END: AtomicU64 = AtomicU64::new(0);
+// One bit per target still to flush.
+static PENDING: [AtomicU64; MAX_CPUS / 64] = [const { AtomicU64::new(0) }; MAX_CPUS / 64];
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn flush_local(start: u64, end: u64) {
+    if (end - start) / 0x1000 > FLUSH_ALL_PAGES {
+        // Toggling PGE drops global entries too, which a CR3 reload keeps.
+        let cr4 = Cr4::read();
+        unsafe {
+            Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(cr4);
+        }
+    } else {
+        for p in (start..end).step_by(0x1000) {
+            tlb::flush(VirtAddr::new(p));
+        }
+    }
+}
+
+fn lock() -> MutexGuard<'static, ()> {
+    loop {
+        if let Some(g) = LOCK.try_lock() {
+            return g;
+        }
+        handle_ipi();
+        smp::handle_call_ipi();
+        core::hint::spin_loop();
+    }
+}
+
+fn pending() -> bool {
+    PENDING.iter().any(|w| w.load(Ordering::Acquire) != 0)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Flush this CPU's part of the shootdown in flight, if it has one. Called
+/// from the IPI handler and from loops that wait on other CPUs.
+pub fn handle_ipi() {
+    let me = lapic_id() as usize;
+    if me >= MAX_CPUS {
+        return;
+    }
+    let (w, bit) = (me / 64, 1u64 << (me % 64));
+    if PENDING[w].load(Ordering::Acquire) & bit == 0 {
+        return;
+    }
+    flush_local(START.load(Ordering::Acquire), END.load(Ordering::Acquire));
+    PENDING[w].fetch_and(!bit, Ordering::AcqRel);
+}
+
+/// Flush `[start, end)` from every online CPU's TLB, global mappings
+/// included, and return once all of them have. Must not be called from
+/// IRQ context or while holding a lock another CPU may spin on with IRQs
+/// off: that CPU would never acknowledge.
+pub fn flush_range(start: u64, end: u64) {
+    let start = start & !0xFFF;
+    let end = (end + 0xFFF) & !0xFFF;
+    if end <= start {
+        return;
+    }
+    without_interrupts(|| {
+        let _guard = lock();
+        let me = lapic_id();
+        START.store(start, Ordering::Release);
+        END.store(end, Ordering::Release);
+        let mut sent = 0u64;
+        for cpu in smp::online_cpus().filter(|&c| c != me) {
+            let c = cpu as usize;
+            PENDING[c / 64].fetch_or(1 << (c % 64), Ordering::AcqRel);
+            apic::ipi_fixed(cpu, SHOOTDOWN_VECTOR);
+            sent += 1;
+        }
+        flush_local(start, end);
+        while pending() {
+            smp::handle_call_ipi();
+            core::hint::spin_loop();
+        }
+        crate::counter!("tlb.shootdowns");
+        crate::counter!("tlb.shootdown_ipis", sent);
+    });
+}
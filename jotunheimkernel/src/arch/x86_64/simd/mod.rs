@@ -3,6 +3,8 @@
 // Copyright (C) 2025 The Jotunheim Project
 pub mod caps;
 
+use super::alternatives::Feature;
+
 use core::arch::asm;
 use core::arch::x86_64::{__cpuid, __cpuid_count, _xsetbv};
 
@@ -98,21 +100,14 @@ pub fn init() {
 pub fn save(area: *mut u8) {
     let c = caps::caps();
     if c.has_xsave && c.has_osxsave && (caps::simd_ready()) {
-        // Use XSAVEOPT if available; else XSAVE
+        // XSAVE, patched to XSAVEOPT at boot where available
         let mask_lo = (c.xcr0 & 0xFFFF_FFFF) as u32;
         let mask_hi = (c.xcr0 >> 32) as u32;
-        if c.has_xsaveopt {
-            unsafe {
-                core::arch::asm!("xsaveopt [{buf}]", buf = in(reg) area,
-                             in("eax") mask_lo, in("edx") mask_hi,
-                             options(nostack, preserves_flags));
-            }
-        } else {
-            unsafe {
-                core::arch::asm!("xsave [{buf}]", buf = in(reg) area,
-                             in("eax") mask_lo, in("edx") mask_hi,
-                             options(nostack, preserves_flags));
-            }
+        unsafe {
+            crate::alternative!(["xsave [{buf}]"], ["xsaveopt [{buf}]"], Feature::XsaveOpt,
+                         buf = in(reg) area,
+                         in("eax") mask_lo, in("edx") mask_hi,
+                         options(nostack, preserves_flags));
         }
     } else {
         // Legacy fallback
@@ -8,25 +8,86 @@ extern crate alloc;
 use core::{
     arch::asm,
     ptr,
-    sync::atomic::{Ordering, compiler_fence},
+    sync::atomic::{
+        AtomicBool, AtomicPtr, AtomicU8, AtomicU64, AtomicUsize, Ordering, compiler_fence,
+    },
 };
 
-use x86_64::instructions::{hlt, interrupts::without_interrupts};
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::{hlt, interrupts, interrupts::without_interrupts};
 
 use crate::{
-    acpi::madt,
+    acpi::topology,
     arch::x86_64::{
         apic::{self, lapic_id},
+        clock,
         tables::{self},
     },
     bootinfo::BootInfo,
-    kprintln, mem,
+    cmdline, kprintln, mem,
 };
 
-use crate::arch::x86_64::ap_trampoline;
+use crate::arch::x86_64::{ap_trampoline, cet, shootdown};
 
 static mut HHDM_BASE: u64 = 0;
 
+/// Vector for cross-CPU function calls.
+pub const CALL_VECTOR: u8 = 0xF0;
+const MAX_CPUS: usize = 256; // indexed by xAPIC id
+
+// A call in flight. Lives on the caller's stack; the caller does not return
+// until every target has run it, which is what makes the raw pointers sound.
+struct CallRequest {
+    func: *const (dyn Fn() + Sync),
+    pending: AtomicUsize,
+}
+
+static MAILBOX: [AtomicPtr<CallRequest>; MAX_CPUS] =
+    [const { AtomicPtr::new(ptr::null_mut()) }; MAX_CPUS];
+static ONLINE: [AtomicU64; MAX_CPUS / 64] = [const { AtomicU64::new(0) }; MAX_CPUS / 64];
+// One cross call at a time keeps the mailboxes single-slot.
+static CALL_LOCK: Mutex<()> = Mutex::new(());
+// Last ApStage each AP reached, by APIC id.
+static AP_STAGE: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(0) }; MAX_CPUS];
+
+/// How far an AP got in bring-up. RealMode..=Jump are written by the
+/// trampoline (ap_trampoline.asm, STAGE*), the rest by `ap_entry`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum ApStage {
+    NotStarted = 0,
+    RealMode = 1,  // running the trampoline's first instructions
+    Gdt = 2,       // temporary GDT loaded
+    Protected = 3, // in 32-bit protected mode
+    Cr3 = 4,       // PAE on, kernel CR3 loaded
+    Efer = 5,      // EFER.LME set
+    Paging = 6,    // paging on (compatibility mode)
+    LongMode = 7,  // in 64-bit code
+    Jump = 8,      // on the kernel stack, jumping to ap_entry
+    Entry = 9,     // in ap_entry, local APIC set up
+    Tables = 10,   // own GDT and IDT loaded
+    Online = 11,   // taking cross calls
+}
+
+impl ApStage {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => Self::RealMode,
+            2 => Self::Gdt,
+            3 => Self::Protected,
+            4 => Self::Cr3,
+            5 => Self::Efer,
+            6 => Self::Paging,
+            7 => Self::LongMode,
+            8 => Self::Jump,
+            9 => Self::Entry,
+            10 => Self::Tables,
+            11 => Self::Online,
+            _ => Self::NotStarted,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C, align(16))]
 pub struct ApBoot {
@@ -40,6 +101,21 @@ pub struct ApBoot {
     pub hhdm: u64,
 }
 
+// Offsets ap_trampoline.asm reads ApBoot through.
+const APBOOT_CR3: usize = 0x08;
+const APBOOT_STACK_TOP: usize = 0x20;
+const APBOOT_ENTRY64: usize = 0x28;
+// stack_top sits this far below the stack end; the trampoline pops the
+// ApBoot pointer from it and jumps to ap_entry with rsp % 16 == 8.
+const AP_FRAME_ARG: u64 = 16;
+
+const _: () = {
+    assert!(core::mem::offset_of!(ApBoot, cr3) == APBOOT_CR3);
+    assert!(core::mem::offset_of!(ApBoot, stack_top) == APBOOT_STACK_TOP);
+    assert!(core::mem::offset_of!(ApBoot, entry64) == APBOOT_ENTRY64);
+    assert!(AP_FRAME_ARG.is_multiple_of(16));
+};
+
 /// Bring all enabled APs online (one-by-one to avoid sharing the same trampoline page)
 /// Requires:
 ///   - paging/GDT/IDT are ready on BSP
@@ -47,10 +123,11 @@ pub struct ApBoot {
 ///   - low identity map for `TRAMP_PHYS` page exists
 pub fn boot_all_aps(boot: &BootInfo) {
     unsafe { HHDM_BASE = boot.hhdm_base };
-    let Some(m) = madt::discover(boot) else {
-        kprintln!("[SMP] No MADT; cannot boot APs.");
+    mark_online(lapic_id());
+    if topology::is_uniprocessor() {
+        kprintln!("[SMP] Uniprocessor mode; not starting APs.");
         return;
-    };
+    }
 
     // --- 1) Trampoline: copy once to low physical page (e.g., 0x8000) ---
     const TRAMP_PHYS: u64 = 0x1000; // 32KiB, <1MiB, 4KiB aligned
@@ -66,6 +143,7 @@ pub fn boot_all_aps(boot: &BootInfo) {
         core::ptr::copy_nonoverlapping(blob.as_ptr(), dst, blob.len());
     }
     let tramp_virt = boot.hhdm_base + TRAMP_PHYS;
+    let stage_ptr = (tramp_virt + ap_trampoline::stage_offset() as u64) as *mut u8;
     let vector: u8 = ((TRAMP_PHYS >> 12) & 0xFF) as u8;
 
     // --- 2) Warm-reset vector (some firmware requires it) ---
@@ -108,8 +186,8 @@ pub fn boot_all_aps(boot: &BootInfo) {
         loop {}
     }
 
-    for c in m.cpus.iter().filter(|c| c.enabled) {
-        if c.apic_id == bsp_id {
+    for apic_id in topology::application_processors() {
+        if apic_id == bsp_id {
             continue;
         }
 
@@ -118,7 +196,7 @@ pub fn boot_all_aps(boot: &BootInfo) {
         let stk =
             crate::mem::vmap_alloc_pages(AP_STACK_PAGES).expect("[SMP] vmap stack alloc failed");
         let stk_va = stk as u64;
-        let stk_top = stk_va + (AP_STACK_PAGES as u64) * 4096 - 0x08;
+        let stk_top = stk_va + (AP_STACK_PAGES as u64) * 4096 - AP_FRAME_ARG;
         if stk_va == 0 {
             continue;
         }
@@ -135,8 +213,8 @@ pub fn boot_all_aps(boot: &BootInfo) {
             hhdm: boot.hhdm_base, // for HHDM conversions on AP if needed
         };
 
-        let frame = (stk_top) as *mut u64; // space for [arg][entry]
-        unsafe { core::ptr::write(frame.add(0), &raw mut *ab_ref as u64) };
+        let frame = (stk_top) as *mut u64; // [arg] for the trampoline's pop
+        unsafe { core::ptr::write(frame, &raw mut *ab_ref as u64) };
 
         // (d) Patch trampoline with **physical** address of ApBoot
         unsafe {
@@ -145,18 +223,74 @@ pub fn boot_all_aps(boot: &BootInfo) {
             compiler_fence(Ordering::SeqCst);
         }
 
+        unsafe { stage_ptr.write_volatile(0) };
+        if let Some(s) = AP_STAGE.get(apic_id as usize) {
+            s.store(ApStage::NotStarted as u8, Ordering::Release);
+        }
+
         // (e) Kick the AP: INIT → SIPI → SIPI
         without_interrupts(|| {
-            apic::send_init(c.apic_id);
+            apic::send_init(apic_id);
             spin_delay_us(10_000);
-            apic::send_startup(c.apic_id, vector);
+            apic::send_startup(apic_id, vector);
             spin_delay_us(200);
-            apic::send_startup(c.apic_id, vector);
+            apic::send_startup(apic_id, vector);
         });
 
         // (f) Wait for trampoline to set ready_flag = 1
         if !wait_ready(&ab_ref.ready_flag as *const u32, 4_000) {
-            kprintln!("[SMP] apic_id {} did not signal ready in time", c.apic_id);
+            set_stage(
+                apic_id,
+                ApStage::from_u8(unsafe { stage_ptr.read_volatile() }),
+            );
+            kprintln!(
+                "[SMP] apic_id {} did not signal ready in time (reached {:?})",
+                apic_id,
+                ap_stage(apic_id)
+            );
+        }
+    }
+    report_stalled(bsp_id);
+    kprintln!(
+        "[SMP] {} of {} CPUs online",
+        online_cpus().count(),
+        topology::cpu_count()
+    );
+}
+
+// Stages only move forward: a late AP may pass what the BSP read.
+fn set_stage(apic_id: u32, stage: ApStage) {
+    if let Some(s) = AP_STAGE.get(apic_id as usize) {
+        s.fetch_max(stage as u8, Ordering::AcqRel);
+    }
+}
+
+/// Last bring-up stage AP `apic_id` reported.
+pub fn ap_stage(apic_id: u32) -> ApStage {
+    AP_STAGE
+        .get(apic_id as usize)
+        .map_or(ApStage::NotStarted, |s| {
+            ApStage::from_u8(s.load(Ordering::Acquire))
+        })
+}
+
+// Give APs that reached ap_entry a moment to finish, then name the stage
+// every AP that is still not online stopped at.
+fn report_stalled(bsp_id: u32) {
+    let pending = || {
+        topology::application_processors()
+            .filter(|&id| id != bsp_id && !is_online(id))
+            .any(|id| ap_stage(id) >= ApStage::Entry)
+    };
+    for _ in 0..1_000 {
+        if !pending() {
+            break;
+        }
+        hlt();
+    }
+    for id in topology::application_processors() {
+        if id != bsp_id && !is_online(id) {
+            kprintln!("[SMP] apic_id {} stalled at {:?}", id, ap_stage(id));
         }
     }
 }
@@ -193,12 +327,177 @@ pub extern "C" fn ap_entry(apboot: &mut ApBoot) -> ! {
             options(nostack, preserves_flags));
         }
         apic::ap_init(boot.hhdm);
+        set_stage(lapic_id(), ApStage::Entry);
+        crate::stats::init_cpu();
+        crate::debug::watch::load();
         kprintln!("Hello from {}", lapic_id());
         tables::ap_init();
+        set_stage(lapic_id(), ApStage::Tables);
         kprintln!("Loaded GDT and IDT");
+        if let Some(n) = cmdline::value("trace_ap").and_then(|v| v.parse().ok()) {
+            crate::debug::trace::start(n);
+        }
+        apic::set_svr(apic::SPURIOUS_VECTOR, true);
+        apic::open_all_irqs();
+        mark_online(lapic_id());
+        set_stage(lapic_id(), ApStage::Online);
+        // CET_SAVE_TO/CET_NEXT_SSP are global and only the BSP has shadow
+        // stacks: with CET on, APs stay out of scheduling.
+        if !cmdline::flag("nosmpsched") && !cet::enabled() && crate::sched::init_ap() {
+            apic::start_timer_hz(clock::TICK_HZ);
+        }
     });
 
+    // Idle with IRQs on so cross-CPU calls get serviced; with the timer
+    // running, the first tick switches into a task and never comes back.
+    interrupts::enable();
     loop {
         x86_64::instructions::hlt();
     }
 }
+
+/* ---------------------------- Cross-CPU calls ---------------------------- */
+
+fn mark_online(apic_id: u32) {
+    if (apic_id as usize) < MAX_CPUS {
+        ONLINE[apic_id as usize / 64].fetch_or(1 << (apic_id % 64), Ordering::Release);
+    }
+}
+
+pub fn is_online(apic_id: u32) -> bool {
+    (apic_id as usize) < MAX_CPUS
+        && ONLINE[apic_id as usize / 64].load(Ordering::Acquire) & (1 << (apic_id % 64)) != 0
+}
+
+/// APIC ids of every CPU that can take cross calls.
+pub fn online_cpus() -> impl Iterator<Item = u32> + Clone {
+    (0..MAX_CPUS as u32).filter(|&id| is_online(id))
+}
+
+/// Run this CPU's pending call, if any. Called from the IPI handler, and by
+/// CPUs spinning on cross-call state so two callers cannot deadlock.
+pub fn handle_call_ipi() {
+    let me = lapic_id() as usize;
+    if me >= MAX_CPUS {
+        return;
+    }
+    let req = MAILBOX[me].swap(ptr::null_mut(), Ordering::AcqRel);
+    if req.is_null() {
+        return;
+    }
+    unsafe {
+        (*(*req).func)();
+        (*req).pending.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn lock_calls() -> MutexGuard<'static, ()> {
+    loop {
+        if let Some(g) = CALL_LOCK.try_lock() {
+            return g;
+        }
+        handle_call_ipi();
+        shootdown::handle_ipi();
+        core::hint::spin_loop();
+    }
+}
+
+impl CallRequest {
+    fn new(f: &(dyn Fn() + Sync), targets: usize) -> Self {
+        // Erase the borrow's lifetime; see CallRequest.
+        let func: *const (dyn Fn() + Sync) = unsafe { core::mem::transmute(f) };
+        Self {
+            func,
+            pending: AtomicUsize::new(targets),
+        }
+    }
+
+    // Post to each target and kick it. Caller holds CALL_LOCK.
+    fn post(&self, targets: impl Iterator<Item = u32>) {
+        for cpu in targets {
+            MAILBOX[cpu as usize].store(self as *const _ as *mut _, Ordering::Release);
+            apic::ipi_fixed(cpu, CALL_VECTOR);
+        }
+    }
+
+    fn wait(&self) {
+        while self.pending.load(Ordering::Acquire) != 0 {
+            handle_call_ipi();
+            shootdown::handle_ipi();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// Post `f` to each target, kick them, and wait until all of them ran it.
+fn dispatch(targets: impl Iterator<Item = u32> + Clone, f: &(dyn Fn() + Sync)) -> usize {
+    let _guard = lock_calls();
+    let n = targets.clone().count();
+    let req = CallRequest::new(f, n);
+    req.post(targets);
+    req.wait();
+    n
+}
+
+/// Run `f` on CPU `apic_id` and wait for it to finish. On the calling CPU it
+/// runs directly with IRQs off. Returns false if that CPU is not online.
+pub fn call_on(apic_id: u32, f: impl Fn() + Sync) -> bool {
+    if apic_id == lapic_id() {
+        without_interrupts(&f);
+        return true;
+    }
+    if !is_online(apic_id) {
+        return false;
+    }
+    dispatch(core::iter::once(apic_id), &f) == 1
+}
+
+/// Run `f` on every online CPU, this one included (IRQs off on each), and
+/// wait for all of them. Returns how many CPUs ran it.
+pub fn call_all(f: impl Fn() + Sync) -> usize {
+    let me = lapic_id();
+    let others = dispatch(online_cpus().filter(move |&c| c != me), &f);
+    without_interrupts(&f);
+    others + 1
+}
+
+/* ------------------------------ Stop-machine ------------------------------ */
+
+/// Quiesce every other online CPU, run `f` on this one, then let them go.
+/// The others spin with IRQs off inside the call IPI for the duration, so
+/// `f` may rewrite code, IDT entries or live mappings they could be using.
+/// Keep `f` short, and never call it with a lock another CPU may hold while
+/// IRQs are off: that CPU would never reach the safe point.
+pub fn stop_machine<R>(f: impl FnOnce() -> R) -> R {
+    without_interrupts(|| {
+        let _guard = lock_calls();
+        let me = lapic_id();
+        let others = online_cpus().filter(move |&c| c != me);
+        let n = others.clone().count();
+
+        let arrived = AtomicUsize::new(0);
+        let release = AtomicBool::new(false);
+        let park = || {
+            arrived.fetch_add(1, Ordering::AcqRel);
+            while !release.load(Ordering::Acquire) {
+                // `f` may unmap something and shoot it down.
+                shootdown::handle_ipi();
+                core::hint::spin_loop();
+            }
+            // Serialize: `f` may have modified code this CPU will run next.
+            core::arch::x86_64::__cpuid(0);
+        };
+        let req = CallRequest::new(&park, n);
+        req.post(others);
+        while arrived.load(Ordering::Acquire) != n {
+            core::hint::spin_loop();
+        }
+
+        let r = f();
+        // Whatever `f` patched must be visible before anyone resumes.
+        compiler_fence(Ordering::SeqCst);
+        release.store(true, Ordering::Release);
+        req.wait();
+        r
+    })
+}
@@ -0,0 +1,134 @@
+// src/arch/x86_64/speaker.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// PC speaker: PIT channel 2 in square-wave mode, gated through port 0x61.
+// Timing is a TSC busy-wait so beeps work with IRQs off (panic path). The
+// default beep and the mute switch are under /config/speaker.
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use super::tsc;
+use crate::config::{self, Key};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const PIT_HZ: u32 = 1_193_182;
+const PIT_CH2: u16 = 0x42;
+const PIT_CMD: u16 = 0x43;
+const PORT_B: u16 = 0x61;
+const GATE_SPEAKER: u8 = 0b11; // bit 0: ch2 gate, bit 1: speaker data
+
+/// A note: frequency in Hz (0 = rest) and duration in ms.
+pub type Note = (u32, u32);
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static DEFAULT_HZ: AtomicU32 = AtomicU32::new(880);
+static DEFAULT_MS: AtomicU32 = AtomicU32::new(100);
+
+/// Keys under `/config/speaker`.
+pub const CONFIG_KEYS: &[Key] = &[
+    Key {
+        name: "enabled",
+        get: || ENABLED.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 0, 1),
+        apply: |v| set_enabled(v != 0),
+    },
+    Key {
+        name: "hz",
+        get: || DEFAULT_HZ.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 20, 20_000),
+        apply: |v| DEFAULT_HZ.store(v as u32, Ordering::Relaxed),
+    },
+    Key {
+        name: "ms",
+        get: || DEFAULT_MS.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 1, 5_000),
+        apply: |v| DEFAULT_MS.store(v as u32, Ordering::Relaxed),
+    },
+];
+
+// Three short high beeps, then one long low one; repeated by the panic handler.
+const PANIC_PATTERN: [Note; 7] = [
+    (1000, 150),
+    (0, 100),
+    (1000, 150),
+    (0, 100),
+    (1000, 150),
+    (0, 250),
+    (500, 600),
+];
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn delay_ms(ms: u32) {
+    let end = tsc::rdtsc() + tsc::tsc_hz_estimate() / 1000 * ms as u64;
+    while tsc::rdtsc() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Start a continuous tone. `hz == 0` silences the speaker.
+pub fn tone(hz: u32) {
+    if hz == 0 || !ENABLED.load(Ordering::Relaxed) {
+        off();
+        return;
+    }
+    let div = (PIT_HZ / hz).clamp(1, 0xFFFF) as u16;
+    unsafe {
+        Port::<u8>::new(PIT_CMD).write(0b1011_0110); // ch2, lo/hi, mode 3
+        let mut ch2 = Port::<u8>::new(PIT_CH2);
+        ch2.write(div as u8);
+        ch2.write((div >> 8) as u8);
+        let mut b = Port::<u8>::new(PORT_B);
+        let v = b.read();
+        b.write(v | GATE_SPEAKER);
+    }
+}
+
+pub fn off() {
+    unsafe {
+        let mut b = Port::<u8>::new(PORT_B);
+        let v = b.read();
+        b.write(v & !GATE_SPEAKER);
+    }
+}
+
+/// Blocking beep; spins for the whole duration.
+pub fn beep(hz: u32, ms: u32) {
+    tone(hz);
+    delay_ms(ms);
+    off();
+}
+
+/// Beep with the configured default pitch and length.
+pub fn beep_default() {
+    beep(
+        DEFAULT_HZ.load(Ordering::Relaxed),
+        DEFAULT_MS.load(Ordering::Relaxed),
+    );
+}
+
+pub fn play(notes: &[Note]) {
+    for &(hz, ms) in notes {
+        beep(hz, ms);
+    }
+}
+
+/// Mute (or unmute) every beep, the panic signal included.
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+    if !on {
+        off();
+    }
+}
+
+/// Audible crash signal; called from the panic handler with IRQs off.
+pub fn panic_signal(repeats: u32) {
+    for _ in 0..repeats {
+        play(&PANIC_PATTERN);
+        delay_ms(1000);
+    }
+}
@@ -122,8 +122,9 @@ pub fn init(sel: Selectors) {
     }
     access_mut(|isr| {
         if let (Some(vec), Some(stub)) = (isr.vector, isr.stub) {
+            // IST slots are numbered from 1 in a gate; 0 means no switch.
             let index = match isr.index {
-                Some(index) => index,
+                Some(index) => index + 1,
                 None => 0,
             };
             idt.set_gate(vec as usize, stub, index as u8, 0, sel);
@@ -142,8 +143,9 @@ pub fn ap_init(sel: Selectors) {
     }
     access_mut(|isr| {
         if let (Some(vec), Some(stub)) = (isr.vector, isr.stub) {
+            // IST slots are numbered from 1 in a gate; 0 means no switch.
             let index = match isr.index {
-                Some(index) => index,
+                Some(index) => index + 1,
                 None => 0,
             };
             idt.set_gate(vec as usize, stub, index as u8, 0, sel);
@@ -1,13 +1,22 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+use super::selftest;
 use crate::{
-    arch::x86_64::tables::ISR,
-    debug::{self, Outcome, TrapFrame, breakpoint},
+    arch::x86_64::{livepatch, tables::ISR},
+    debug::{self, Outcome, TrapFrame, breakpoint, trace},
 };
 use x86_64::instructions::interrupts::without_interrupts;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_db_rust(tf: *mut TrapFrame) {
+    // A data watchpoint firing, not a debugger stop.
+    if debug::watch::on_debug(unsafe { &*tf }) {
+        return;
+    }
+    // A step of `debug::trace`.
+    if debug::trace::on_step(unsafe { &mut *tf }) {
+        return;
+    }
     without_interrupts(|| {
         let last_hit = {
             let t = unsafe { &mut *tf };
@@ -19,6 +28,7 @@ pub extern "C" fn isr_db_rust(tf: *mut TrapFrame) {
             Outcome::Continue => {
                 // re-arm the bp if GDB continued
                 breakpoint::on_resume_continue(last_hit);
+                trace::on_continue(unsafe { &mut *tf });
             }
             Outcome::SingleStep => {
                 // defer re-arming until the #DB we’ll get after this step
@@ -31,6 +41,14 @@ pub extern "C" fn isr_db_rust(tf: *mut TrapFrame) {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_bp_rust(tf: *mut TrapFrame) {
+    crate::counter!("bp.traps");
+    if selftest::on_trap(tf) {
+        return;
+    }
+    // A function mid-patch, not a debugger breakpoint.
+    if livepatch::on_int3(unsafe { &mut (*tf).rip }) {
+        return;
+    }
     without_interrupts(|| {
         let last_hit = {
             let t = unsafe { &mut *tf };
@@ -42,6 +60,7 @@ pub extern "C" fn isr_bp_rust(tf: *mut TrapFrame) {
             Outcome::Continue => {
                 // re-arm the bp if GDB continued
                 breakpoint::on_resume_continue(last_hit);
+                trace::on_continue(unsafe { &mut *tf });
             }
             Outcome::SingleStep => {
                 // defer re-arming until the #DB we’ll get after this step
@@ -0,0 +1,38 @@
+// src/arch/x86_64/tables/isr/device.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Device lines routed through the IOAPIC. Each vector hands off to its
+// driver, which does as little as it can here; see irq::thread and
+// irq::poll. A level-triggered line must be quiet again before the EOI.
+use crate::{
+    arch::x86_64::{apic, tables::ISR},
+    debug::{TrapFrame, histo, irqalloc},
+    input::ps2,
+    virtio::console,
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_kbd_rust(_tf: *mut TrapFrame) {
+    crate::counter!("irq.kbd");
+    histo::isr(ps2::VECTOR as u64, || irqalloc::irq_context(ps2::on_irq));
+    apic::eoi();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_virtio_con_rust(_tf: *mut TrapFrame) {
+    crate::counter!("irq.virtio_con");
+    histo::isr(console::VECTOR as u64, || {
+        irqalloc::irq_context(console::on_irq)
+    });
+    apic::eoi();
+}
+
+unsafe extern "C" {
+    unsafe fn isr_kbd_stub();
+    unsafe fn isr_virtio_con_stub();
+}
+
+pub fn init() {
+    ISR::registrate_without_stack(ps2::VECTOR as u16, isr_kbd_stub);
+    ISR::registrate_without_stack(console::VECTOR as u16, isr_virtio_con_stub);
+}
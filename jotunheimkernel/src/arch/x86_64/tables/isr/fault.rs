@@ -1,16 +1,21 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
 use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::registers::control::Cr2;
 
+use super::selftest;
 use crate::{
     arch::x86_64::tables::ISR,
     debug::{self, Outcome, TrapFrame, breakpoint},
     kprintln,
+    mem::pagefault::{self, PfError},
     sched::exit_current,
 };
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_gp_rust(tf: *mut TrapFrame) {
+    crate::counter!("gp.faults");
+    debug::faults::record(unsafe { &*tf });
     kprintln!("GP");
     if cfg!(debug_assertions) {
         without_interrupts(|| {
@@ -47,7 +52,23 @@ pub extern "C" fn isr_gp_rust(tf: *mut TrapFrame) {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_pf_rust(tf: *mut TrapFrame) {
-    kprintln!("PF");
+    crate::counter!("pf.faults");
+    if selftest::on_trap(tf) {
+        return;
+    }
+    let cr2 = Cr2::read_raw();
+    let err = PfError(unsafe { (*tf).err });
+    if pagefault::fix(cr2, err) {
+        return;
+    }
+    debug::faults::record(unsafe { &*tf });
+    {
+        let t = unsafe { &*tf };
+        // tf.rsp points at the hardware frame; the interrupted RSP is its
+        // fourth slot.
+        let rsp = unsafe { *((t.rsp + 24) as *const u64) };
+        pagefault::report(cr2, err, t.rip, rsp);
+    }
     if cfg!(debug_assertions) {
         without_interrupts(|| {
             let last_hit = {
@@ -66,23 +87,13 @@ pub extern "C" fn isr_pf_rust(tf: *mut TrapFrame) {
             }
         })
     } else {
-        let tf = unsafe { &*tf };
-        kprintln!(
-            "[#PF] vec={} err={:#x}\n  rip={:#018x} rsp={:#018x} rflags={:#018x}\n  cs={:#06x} ss={:#06x}",
-            tf.vec,
-            tf.err,
-            tf.rip,
-            tf.rsp,
-            tf.rflags,
-            tf.cs as u16,
-            tf.ss as u16
-        );
-        exit_current()
+        panic!("page fault: {} at {:#x}", err, cr2);
     }
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_df_rust(tf: *mut TrapFrame) {
+    debug::faults::record(unsafe { &*tf });
     kprintln!("DF");
     if cfg!(debug_assertions) {
         without_interrupts(|| {
@@ -116,7 +127,71 @@ pub extern "C" fn isr_df_rust(tf: *mut TrapFrame) {
         exit_current()
     }
 }
+// #CP error code: which check failed.
+fn cp_kind(err: u64) -> &'static str {
+    match err & 0x7fff {
+        1 => "near RET",
+        2 => "far RET/IRET",
+        3 => "ENDBRANCH",
+        4 => "RSTORSSP",
+        5 => "SETSSBSY",
+        _ => "unknown",
+    }
+}
+
+/// Control-protection fault. `frame_ssp` is the SSP at entry, pointing at
+/// the [SSP, LIP, CS] record the fault pushed onto the shadow stack.
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_cp_rust(tf: *mut TrapFrame, frame_ssp: u64) {
+    crate::counter!("cp.faults");
+    debug::faults::record(unsafe { &*tf });
+    {
+        let t = unsafe { &*tf };
+        kprintln!(
+            "[#CP] {} at rip={:#018x} err={:#x}",
+            cp_kind(t.err),
+            t.rip,
+            t.err
+        );
+        if t.err & 0x7fff == 1 && frame_ssp != 0 {
+            // Faulted on the RET itself: compare what each stack holds.
+            let ssp = unsafe { *(frame_ssp as *const u64) };
+            // tf.rsp points at the hardware frame; the interrupted RSP is
+            // its fourth slot.
+            let rsp = unsafe { *((t.rsp + 24) as *const u64) };
+            let stack = unsafe { *(rsp as *const u64) };
+            let shadow = unsafe { *(ssp as *const u64) };
+            kprintln!(
+                "  return address {:#018x}, shadow stack {:#018x}",
+                stack,
+                shadow
+            );
+        }
+    }
+    if cfg!(debug_assertions) {
+        without_interrupts(|| {
+            let last_hit = {
+                let t = unsafe { &mut *tf };
+                breakpoint::on_breakpoint_enter(&mut t.rip)
+            };
+
+            match debug::rsp::serve(tf) {
+                Outcome::Continue => {
+                    breakpoint::on_resume_continue(last_hit);
+                }
+                Outcome::SingleStep => {
+                    breakpoint::on_resume_step(last_hit);
+                }
+                Outcome::KillTask => exit_current(),
+            }
+        })
+    } else {
+        exit_current()
+    }
+}
+
 unsafe extern "C" {
+    unsafe fn isr_cp_stub();
     unsafe fn isr_gp_stub();
     unsafe fn isr_pf_stub();
     unsafe fn isr_df_stub();
@@ -125,4 +200,6 @@ pub fn init() {
     ISR::registrate(0x0D, isr_gp_stub);
     ISR::registrate(0x0E, isr_pf_stub);
     ISR::registrate(0x08, isr_df_stub);
+    // #CP runs on the faulting stack: the IST slots are all taken.
+    ISR::registrate_without_stack(0x15, isr_cp_stub);
 }
@@ -0,0 +1,33 @@
+// src/arch/x86_64/tables/isr/ipi.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+use crate::{
+    arch::x86_64::{apic, shootdown, smp, tables::ISR},
+    debug::{TrapFrame, histo, irqalloc},
+};
+
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_call_ipi_rust(_tf: *mut TrapFrame) {
+    crate::counter!("irq.call_ipi");
+    histo::isr(smp::CALL_VECTOR as u64, || {
+        irqalloc::irq_context(smp::handle_call_ipi)
+    });
+    apic::eoi();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_shootdown_ipi_rust(_tf: *mut TrapFrame) {
+    crate::counter!("irq.shootdown_ipi");
+    histo::isr(shootdown::SHOOTDOWN_VECTOR as u64, shootdown::handle_ipi);
+    apic::eoi();
+}
+
+unsafe extern "C" {
+    unsafe fn isr_call_ipi_stub();
+    unsafe fn isr_shootdown_ipi_stub();
+}
+
+pub fn init() {
+    ISR::registrate_without_stack(smp::CALL_VECTOR as u16, isr_call_ipi_stub);
+    ISR::registrate_without_stack(shootdown::SHOOTDOWN_VECTOR as u16, isr_shootdown_ipi_stub);
+}
@@ -2,10 +2,27 @@ use x86_64::instructions::hlt;
 
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
-use crate::{arch::x86_64::tables::ISR, kprintln, sched};
+use super::selftest;
+use crate::{arch::x86_64::tables::ISR, debug::TrapFrame, kprintln, sched};
 
 #[unsafe(no_mangle)]
-pub extern "C" fn isr_ud_rust() -> ! {
+pub extern "C" fn isr_de_rust(tf: *mut TrapFrame) {
+    crate::counter!("de.faults");
+    if selftest::on_trap(tf) {
+        return;
+    }
+    crate::debug::faults::record(unsafe { &*tf });
+    kprintln!("[#DE] divide error at {:#018x}", unsafe { (*tf).rip });
+    sched::exit_current();
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn isr_ud_rust(tf: *mut TrapFrame) {
+    crate::counter!("ud.faults");
+    if selftest::on_trap(tf) {
+        return;
+    }
+    crate::debug::faults::record(unsafe { &*tf });
     kprintln!("[#UD] undefined");
     sched::exit_current();
 }
@@ -20,9 +37,11 @@ pub extern "C" fn isr_bad_iret_frame_rust() -> ! {
 
 
 unsafe extern "C" {
+    unsafe fn isr_de_stub();
     unsafe fn isr_ud_stub();
 }
 
 pub fn init() {
+    ISR::registrate_without_stack(0x00, isr_de_stub);
     ISR::registrate_without_stack(0x06, isr_ud_stub);
 }
@@ -2,8 +2,11 @@
 // Copyright (C) 2025 The Jotunheim Project
 
 pub mod debug;
+pub mod device;
 pub mod fault;
+pub mod ipi;
 pub mod misc;
+pub mod selftest;
 pub mod timer;
 
 pub fn init() {
@@ -11,4 +14,6 @@ pub fn init() {
     debug::init();
     fault::init();
     misc::init();
+    ipi::init();
+    device::init();
 }
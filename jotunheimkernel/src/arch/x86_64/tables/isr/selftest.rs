@@ -0,0 +1,299 @@
+// src/arch/x86_64/tables/isr/selftest.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Exception self-tests, run at the end of boot with `traptest` on the
+// command line. On every online CPU in turn, raise #BP, #UD, #PF (reading a
+// page that is never mapped) and #DE, and check that:
+//   - the handler for that vector ran, on that CPU, with the faulting RIP
+//     (#PF: also the right error code and CR2);
+//   - it ran on the vector's IST stack for that CPU, or, for vectors
+//     without one, right below the interrupted stack;
+//   - the vector's fault counter moved.
+// Before each test the vector is armed with a fixup address; the handler
+// offers the trap to `on_trap` first, which records it and resumes at the
+// fixup. A handler that is wired wrong never gets there, so a broken
+// vector shows up as its normal fault path (or a hang), not as a pass.
+// Failures are listed and then panic: nothing else should run on a CPU
+// whose exception entry is broken.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::registers::control::Cr2;
+
+use crate::acpi::cpuid::CpuId;
+use crate::arch::x86_64::apic::lapic_id;
+use crate::arch::x86_64::smp;
+use crate::arch::x86_64::tables::ist_range;
+use crate::debug::TrapFrame;
+use crate::{cmdline, kprintln, mem, stats};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DISARMED: u32 = u32::MAX;
+const PAGE: u64 = 4096;
+// Hardware frame plus the stub's TrapFrame, with slack for alignment.
+const ENTRY_FRAME_MAX: u64 = 512;
+const PF_ERR_PUW: u64 = 0b111; // present, write, user
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Trap {
+    Bp,
+    Ud,
+    Pf,
+    De,
+}
+
+impl Trap {
+    const ALL: [Trap; 4] = [Trap::Bp, Trap::Ud, Trap::Pf, Trap::De];
+
+    fn vector(self) -> u64 {
+        match self {
+            Trap::Bp => 0x03,
+            Trap::Ud => 0x06,
+            Trap::Pf => 0x0E,
+            Trap::De => 0x00,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Trap::Bp => "#BP",
+            Trap::Ud => "#UD",
+            Trap::Pf => "#PF",
+            Trap::De => "#DE",
+        }
+    }
+
+    fn counter(self) -> &'static str {
+        match self {
+            Trap::Bp => "bp.traps",
+            Trap::Ud => "ud.faults",
+            Trap::Pf => "pf.faults",
+            Trap::De => "de.faults",
+        }
+    }
+}
+
+/// What the handler saw.
+#[derive(Copy, Clone, Debug)]
+struct Hit {
+    cpu: u32,
+    err: u64,
+    cr2: u64,
+    rip: u64,
+    frame: u64, // address of the TrapFrame: which stack the handler ran on
+    rsp: u64,   // interrupted RSP
+}
+
+static ARMED_CPU: AtomicU32 = AtomicU32::new(DISARMED);
+static ARMED_VEC: AtomicU64 = AtomicU64::new(0);
+// Written by the trigger itself, next to the faulting instruction.
+static FIXUP: AtomicU64 = AtomicU64::new(0);
+static EXPECT_RIP: AtomicU64 = AtomicU64::new(0);
+static HIT: Mutex<Option<Hit>> = Mutex::new(None);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// Each trigger stores the address of its faulting instruction and of the
+// instruction after it, then executes it. #BP is a trap, so its frame
+// already points past the int3.
+fn trigger(t: Trap, hole: u64) {
+    let fixup = FIXUP.as_ptr();
+    let at = EXPECT_RIP.as_ptr();
+    unsafe {
+        match t {
+            Trap::Bp => asm!(
+                "lea {t}, [rip + 2f]",
+                "mov [{fixup}], {t}",
+                "mov [{at}], {t}",
+                "int3",
+                "2:",
+                t = out(reg) _,
+                fixup = in(reg) fixup,
+                at = in(reg) at,
+            ),
+            Trap::Ud => asm!(
+                "lea {t}, [rip + 2f]",
+                "mov [{fixup}], {t}",
+                "lea {t}, [rip + 3f]",
+                "mov [{at}], {t}",
+                "3: ud2",
+                "2:",
+                t = out(reg) _,
+                fixup = in(reg) fixup,
+                at = in(reg) at,
+            ),
+            Trap::Pf => asm!(
+                "lea {t}, [rip + 2f]",
+                "mov [{fixup}], {t}",
+                "lea {t}, [rip + 3f]",
+                "mov [{at}], {t}",
+                "3: mov {t}, [{hole}]",
+                "2:",
+                t = out(reg) _,
+                fixup = in(reg) fixup,
+                at = in(reg) at,
+                hole = in(reg) hole,
+            ),
+            Trap::De => asm!(
+                "lea {t}, [rip + 2f]",
+                "mov [{fixup}], {t}",
+                "lea {t}, [rip + 3f]",
+                "mov [{at}], {t}",
+                "xor edx, edx",
+                "3: div {zero}",
+                "2:",
+                t = out(reg) _,
+                fixup = in(reg) fixup,
+                at = in(reg) at,
+                zero = in(reg) 0u64,
+                inout("rax") 1u64 => _,
+                out("rdx") _,
+            ),
+        }
+    }
+}
+
+// Raise `t` on `cpu` and return what its handler recorded.
+fn raise(t: Trap, cpu: u32, hole: u64) -> Option<Hit> {
+    *HIT.lock() = None;
+    ARMED_VEC.store(t.vector(), Ordering::Relaxed);
+    ARMED_CPU.store(cpu, Ordering::Release);
+    smp::call_on(cpu, || trigger(t, hole));
+    ARMED_CPU.store(DISARMED, Ordering::Release);
+    FIXUP.store(0, Ordering::Relaxed);
+    HIT.lock().take()
+}
+
+// Check one hit; print and count what is wrong with it.
+fn check(t: Trap, cpu: u32, hole: u64, hit: Option<Hit>) -> usize {
+    let Some(h) = hit else {
+        kprintln!("[traptest] cpu {}: {} handler did not run", cpu, t.name());
+        return 1;
+    };
+    let mut bad = 0;
+    let expect = EXPECT_RIP.load(Ordering::Relaxed);
+    if h.cpu != cpu {
+        kprintln!(
+            "[traptest] cpu {}: {} handled on cpu {}",
+            cpu,
+            t.name(),
+            h.cpu
+        );
+        bad += 1;
+    }
+    if h.rip != expect {
+        kprintln!(
+            "[traptest] cpu {}: {} rip {:#x}, want {:#x}",
+            cpu,
+            t.name(),
+            h.rip,
+            expect
+        );
+        bad += 1;
+    }
+    if t == Trap::Pf && (h.cr2 != hole || h.err & PF_ERR_PUW != 0) {
+        kprintln!(
+            "[traptest] cpu {}: #PF cr2 {:#x} err {:#x}, want cr2 {:#x} err 0",
+            cpu,
+            h.cr2,
+            h.err,
+            hole
+        );
+        bad += 1;
+    }
+    match ist_range(t.vector() as u16, CpuId::of(cpu)) {
+        Some((low, high)) if !(low..high).contains(&h.frame) => {
+            kprintln!(
+                "[traptest] cpu {}: {} frame {:#x} outside its IST stack {:#x}..{:#x}",
+                cpu,
+                t.name(),
+                h.frame,
+                low,
+                high
+            );
+            bad += 1;
+        }
+        None if h.frame >= h.rsp || h.rsp - h.frame > ENTRY_FRAME_MAX => {
+            kprintln!(
+                "[traptest] cpu {}: {} frame {:#x} not on the interrupted stack {:#x}",
+                cpu,
+                t.name(),
+                h.frame,
+                h.rsp
+            );
+            bad += 1;
+        }
+        _ => {}
+    }
+    bad
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    cmdline::flag("traptest")
+}
+
+/// Called first thing by the #BP, #UD, #PF and #DE handlers. True if the
+/// trap was raised by a test: it has been recorded and `tf` resumes at the
+/// fixup.
+pub fn on_trap(tf: *mut TrapFrame) -> bool {
+    let armed = ARMED_CPU.load(Ordering::Acquire);
+    if armed == DISARMED || armed != lapic_id() {
+        return false;
+    }
+    let t = unsafe { &mut *tf };
+    if t.vec != ARMED_VEC.load(Ordering::Relaxed) {
+        return false;
+    }
+    let fixup = FIXUP.swap(0, Ordering::Relaxed);
+    if fixup == 0 {
+        return false;
+    }
+    ARMED_CPU.store(DISARMED, Ordering::Release);
+    // tf.rsp points at the hardware frame: [RIP][CS][RFLAGS][RSP][SS].
+    let rsp = unsafe { *((t.rsp + 24) as *const u64) };
+    *HIT.lock() = Some(Hit {
+        cpu: armed,
+        err: t.err,
+        cr2: if t.vec == 0x0E { Cr2::read_raw() } else { 0 },
+        rip: t.rip,
+        frame: tf as u64,
+        rsp,
+    });
+    t.rip = fixup;
+    true
+}
+
+/// Run every test on every online CPU; panic if any failed.
+pub fn run() {
+    // The page below a guarded allocation is never mapped.
+    let Ok(page) = mem::vmap_alloc_guarded(1) else {
+        kprintln!("[traptest] no memory for the sacrificial page; skipped");
+        return;
+    };
+    let hole = page as u64 - PAGE + 0x80;
+    let mut failed = 0;
+    for cpu in smp::online_cpus() {
+        let mut bad = 0;
+        for t in Trap::ALL {
+            let before = stats::get(t.counter()).unwrap_or(0);
+            bad += check(t, cpu, hole, raise(t, cpu, hole));
+            if stats::get(t.counter()).unwrap_or(0) == before {
+                kprintln!("[traptest] cpu {}: {} not counted", cpu, t.name());
+                bad += 1;
+            }
+        }
+        if bad == 0 {
+            kprintln!("[traptest] cpu {}: #BP #UD #PF #DE ok", cpu);
+        }
+        failed += bad;
+    }
+    mem::vmap_free_guarded(page, 1);
+    if failed != 0 {
+        panic!("exception self-test: {} check(s) failed", failed);
+    }
+}
@@ -1,13 +1,30 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
 use crate::{
-    arch::x86_64::{apic, tables::ISR}, debug::TrapFrame, kprintln, sched
+    arch::x86_64::{apic, clock, pic, tables::ISR, tickwatch},
+    debug::{TrapFrame, histo, irqalloc, latency},
+    kprintln, sched,
 };
 
 #[unsafe(no_mangle)]
 pub extern "C" fn isr_timer_rust(tf: *mut TrapFrame) {
-    unsafe { *tf = sched::tick(*tf ) };
-    apic::eoi();
+    let t0 = histo::start();
+    // `tick` may hand back another task's frame.
+    let from = unsafe { (*tf).rsp };
+    irqalloc::irq_context(|| {
+        latency::irq_section(|| {
+            tickwatch::on_tick();
+            crate::counter!("irq.timer");
+            unsafe { *tf = sched::tick(*tf) };
+            sched::executor::on_tick(sched::ticks());
+            clock::eoi();
+        })
+    });
+    if unsafe { (*tf).rsp } != from {
+        histo::resumed(t0);
+    }
+    // PIT ticks too: the stub does not say which source it was.
+    histo::isr_end(apic::TIMER_VECTOR as u64, t0);
 }
 
 #[unsafe(no_mangle)]
@@ -20,5 +37,8 @@ unsafe extern "C" {
 
 pub fn init() {
     ISR::registrate(0x40, isr_timer_stub);
+    // PIT fallback tick, IRQ 0 after the 8259 remap. Never both ticking at
+    // once, and the TSS has only seven IST slots, so no stack of its own.
+    ISR::registrate_without_stack(pic::PIC_BASE as u16, isr_timer_stub);
     ISR::registrate(0xFF, isr_spurious_stub);
 }
@@ -138,6 +138,21 @@ where
     }
 }
 
+/// The IST stack `vector` runs on for `cpu`, as [low, high), if it has one.
+pub fn ist_range(vector: u16, cpu: CpuId) -> Option<(u64, u64)> {
+    let mut range = None;
+    access_mut(|isr| {
+        if isr.vector != Some(vector) {
+            return;
+        }
+        if let Some(stack) = isr.stack.as_ref().and_then(|s| s.me(cpu)) {
+            let low = stack.dump.as_ptr() as u64;
+            range = Some((low, low + stack.dump.len() as u64));
+        }
+    });
+    range
+}
+
 pub fn ap_init() {
     load_temp_gdt(|| {
         load_bsp_idt(|| {
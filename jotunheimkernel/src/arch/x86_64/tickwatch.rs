@@ -0,0 +1,170 @@
+// src/arch/x86_64/tickwatch.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Scheduler clock health. Every tick stamps the TSC for its CPU; the gap to
+// the previous tick, measured in tick periods, shows ticks that never came
+// (a gap of several periods) and ticks that came in a burst (a gap far under
+// one). A CPU whose ticks stop altogether shows up as `stalled` in
+// `for_each()`. Findings are counted, logged at most once a second per CPU,
+// and with `tickfix` on the command line the timer is re-programmed; a BSP
+// whose LAPIC tick keeps misbehaving moves to the PIT.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use spin::Once;
+
+use super::apic::{self, MAX_CPUS, cpu_index, lapic_id};
+use super::clock::{self, Source, TICK_HZ};
+use super::tsc;
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const LOST_PERIODS: u64 = 3; // a gap this many periods long lost ticks
+const BURST_DIVISOR: u64 = 4; // a gap under period/4 is a burst
+const STALE_PERIODS: u64 = 100; // no tick for this long: stalled
+const SWITCH_AFTER: u32 = 3; // repairs before the BSP gives up on the LAPIC
+
+struct CpuTicks {
+    last_tsc: AtomicU64,
+    ticks: AtomicU64,
+    lost: AtomicU64,
+    bursts: AtomicU64,
+    last_report: AtomicU64,
+    repairs: AtomicU32,
+}
+
+/// Snapshot of one CPU's tick accounting.
+#[derive(Copy, Clone, Debug)]
+pub struct TickStats {
+    pub apic_id: u32,
+    pub ticks: u64,
+    pub lost: u64,
+    pub bursts: u64,
+    pub idle_us: u64,  // since the last tick
+    pub stalled: bool, // no tick for STALE_PERIODS
+}
+
+static CPUS: [CpuTicks; MAX_CPUS] = [const {
+    CpuTicks {
+        last_tsc: AtomicU64::new(0),
+        ticks: AtomicU64::new(0),
+        lost: AtomicU64::new(0),
+        bursts: AtomicU64::new(0),
+        last_report: AtomicU64::new(0),
+        repairs: AtomicU32::new(0),
+    }
+}; MAX_CPUS];
+
+static PERIOD: Once<u64> = Once::new(); // TSC cycles per tick
+static BSP: AtomicU32 = AtomicU32::new(u32::MAX);
+static REPAIR: AtomicBool = AtomicBool::new(false);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn period() -> u64 {
+    *PERIOD.call_once(|| (tsc::tsc_hz_estimate() / TICK_HZ as u64).max(1))
+}
+
+fn us(cycles: u64) -> u64 {
+    (cycles as u128 * 1_000_000 / tsc::tsc_hz_estimate().max(1) as u128) as u64
+}
+
+// One log line per CPU per second at most, whatever the tick is doing.
+fn may_report(c: &CpuTicks, now: u64) -> bool {
+    let last = c.last_report.load(Ordering::Relaxed);
+    let gap = period() * TICK_HZ as u64;
+    if last != 0 && now.wrapping_sub(last) < gap {
+        return false;
+    }
+    c.last_report.store(now, Ordering::Relaxed);
+    true
+}
+
+fn repair(c: &CpuTicks, id: u32) {
+    if !REPAIR.load(Ordering::Relaxed) {
+        return;
+    }
+    let n = c.repairs.fetch_add(1, Ordering::Relaxed) + 1;
+    match clock::source() {
+        Source::Lapic if id == BSP.load(Ordering::Relaxed) && n >= SWITCH_AFTER => {
+            kprintln!("[tick] cpu {}: LAPIC timer keeps misbehaving", id);
+            clock::use_pit();
+        }
+        Source::Lapic => apic::start_timer_hz(TICK_HZ),
+        Source::Pit => {}
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Read `tickfix` and note the BSP. Call on the BSP before the first tick.
+pub fn init() {
+    BSP.store(lapic_id(), Ordering::Relaxed);
+    REPAIR.store(cmdline::flag("tickfix"), Ordering::Relaxed);
+    period();
+}
+
+/// Account one tick on this CPU. Called first thing in the timer handler.
+pub fn on_tick() {
+    let id = cpu_index() as u32;
+    let Some(c) = CPUS.get(id as usize) else {
+        return;
+    };
+    let now = tsc::rdtsc();
+    let prev = c.last_tsc.swap(now, Ordering::Relaxed);
+    c.ticks.fetch_add(1, Ordering::Relaxed);
+    if prev == 0 {
+        return;
+    }
+    let p = period();
+    let gap = now.wrapping_sub(prev);
+    if gap >= p * LOST_PERIODS {
+        let lost = gap / p - 1;
+        c.lost.fetch_add(lost, Ordering::Relaxed);
+        crate::counter!("clock.lost_ticks", lost);
+        if may_report(c, now) {
+            kprintln!(
+                "[tick] cpu {}: {} ticks lost ({} us gap, expected {} us)",
+                id,
+                lost,
+                us(gap),
+                us(p)
+            );
+        }
+        repair(c, id);
+    } else if gap < p / BURST_DIVISOR {
+        c.bursts.fetch_add(1, Ordering::Relaxed);
+        crate::counter!("clock.bursts");
+        if may_report(c, now) {
+            kprintln!(
+                "[tick] cpu {}: tick burst ({} us apart, expected {} us)",
+                id,
+                us(gap),
+                us(p)
+            );
+        }
+        repair(c, id);
+    }
+}
+
+/// Per-CPU tick accounting for every CPU that has ticked.
+pub fn for_each(mut f: impl FnMut(TickStats)) {
+    let now = tsc::rdtsc();
+    let stale = period() * STALE_PERIODS;
+    for (id, c) in CPUS.iter().enumerate() {
+        let last = c.last_tsc.load(Ordering::Relaxed);
+        if last == 0 {
+            continue;
+        }
+        let idle = now.wrapping_sub(last);
+        f(TickStats {
+            apic_id: id as u32,
+            ticks: c.ticks.load(Ordering::Relaxed),
+            lost: c.lost.load(Ordering::Relaxed),
+            bursts: c.bursts.load(Ordering::Relaxed),
+            idle_us: us(idle),
+            stalled: idle > stale,
+        });
+    }
+}
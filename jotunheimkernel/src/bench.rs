@@ -0,0 +1,223 @@
+// src/bench.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// In-kernel micro-benchmarks, run at the end of boot with `benchmarks` on the
+// command line. Each result is one line on the serial console,
+//   BENCH <name> <value> <unit> [key=value ...]
+// framed by `BENCH begin` and `BENCH end`, so a host script can grep a boot
+// log and compare runs. Times come from the TSC, converted with the
+// estimated TSC frequency (printed in the begin line). A benchmark that
+// cannot run on this machine or in this tree says `skipped`.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::hint::black_box;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::native::apic::lapic_id;
+use crate::arch::native::smp;
+use crate::arch::native::tsc::{rdtsc, tsc_hz_estimate};
+use crate::sched;
+use crate::{cmdline, kprintln, mem};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const SWITCH_ROUNDS: u64 = 50; // each round waits for ticks; keep it short
+const IPI_ROUNDS: u64 = 1000;
+const LOCK_ROUNDS: u64 = 10_000; // per CPU
+const COPY_BYTES: usize = 1 << 20;
+const COPY_ROUNDS: u64 = 16;
+const ALLOC_ROUNDS: u64 = 10_000;
+const FRAME_ROUNDS: u64 = 1000;
+
+struct Clock {
+    hz: u64,
+}
+
+impl Clock {
+    fn ns(&self, cycles: u64) -> u64 {
+        (cycles as u128 * 1_000_000_000 / self.hz as u128) as u64
+    }
+
+    /// MiB/s for `bytes` moved in `cycles`.
+    fn mib_s(&self, bytes: u64, cycles: u64) -> u64 {
+        ((bytes as u128 * self.hz as u128 / cycles.max(1) as u128) >> 20) as u64
+    }
+}
+
+fn result(name: &str, value: u64, unit: &str, extra: core::fmt::Arguments) {
+    kprintln!("BENCH {} {} {} {}", name, value, unit, extra);
+}
+
+fn skipped(name: &str, why: &str) {
+    kprintln!("BENCH {} skipped reason={}", name, why);
+}
+
+/* ------------------------------- Benchmarks --------------------------------- */
+
+static SWITCH_MAIN: AtomicU64 = AtomicU64::new(0);
+static SWITCH_WOKEN_AT: AtomicU64 = AtomicU64::new(0);
+static SWITCH_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+// Ping-pong with a peer thread through block/wake. Measures wake-to-run: the
+// time from `wake()` until the woken thread runs, which includes waiting
+// for the tick that switches to it.
+fn context_switch(c: &Clock) {
+    let Some(me) = sched::current_id() else {
+        return skipped("ctx_switch", "no_current_task");
+    };
+    SWITCH_MAIN.store(me, Ordering::Release);
+    SWITCH_TOTAL.store(0, Ordering::Relaxed);
+    let peer = sched::spawn_with_stack_size(0, || {
+        let main = SWITCH_MAIN.load(Ordering::Acquire);
+        for _ in 0..SWITCH_ROUNDS {
+            sched::block_current();
+            let dt = rdtsc() - SWITCH_WOKEN_AT.load(Ordering::Acquire);
+            SWITCH_TOTAL.fetch_add(dt, Ordering::Relaxed);
+            sched::wake(main);
+        }
+    });
+    for _ in 0..SWITCH_ROUNDS {
+        SWITCH_WOKEN_AT.store(rdtsc(), Ordering::Release);
+        sched::wake(peer);
+        sched::block_current();
+    }
+    let avg = c.ns(SWITCH_TOTAL.load(Ordering::Relaxed) / SWITCH_ROUNDS);
+    result(
+        "ctx_switch",
+        avg,
+        "ns",
+        format_args!("rounds={} tick_driven=1", SWITCH_ROUNDS),
+    );
+}
+
+fn ipi_round_trip(c: &Clock) {
+    let me = lapic_id();
+    let Some(peer) = smp::online_cpus().find(|&id| id != me) else {
+        return skipped("ipi_rtt", "single_cpu");
+    };
+    let t0 = rdtsc();
+    for _ in 0..IPI_ROUNDS {
+        smp::call_on(peer, || {});
+    }
+    let avg = c.ns((rdtsc() - t0) / IPI_ROUNDS);
+    result(
+        "ipi_rtt",
+        avg,
+        "ns",
+        format_args!("rounds={} cpu={}", IPI_ROUNDS, peer),
+    );
+}
+
+static CONTENDED: Mutex<u64> = Mutex::new(0);
+
+// Every CPU takes the same lock LOCK_ROUNDS times at once.
+fn spinlock_contention(c: &Clock) {
+    *CONTENDED.lock() = 0;
+    let t0 = rdtsc();
+    let cpus = smp::call_all(|| {
+        for _ in 0..LOCK_ROUNDS {
+            *CONTENDED.lock() += 1;
+        }
+    });
+    let cycles = rdtsc() - t0;
+    let total = *CONTENDED.lock();
+    result(
+        "spinlock",
+        c.ns(cycles) / total.max(1),
+        "ns/acquire",
+        format_args!("cpus={} acquires={}", cpus, total),
+    );
+}
+
+fn memcpy_throughput(c: &Clock) {
+    let pages = COPY_BYTES / 4096;
+    let (Ok(src), Ok(dst)) = (mem::vmap_alloc_pages(pages), mem::vmap_alloc_pages(pages)) else {
+        return skipped("memcpy", "out_of_memory");
+    };
+    unsafe { core::ptr::write_bytes(src, 0x5A, COPY_BYTES) };
+    // Touch the destination once so the first round is not special.
+    unsafe { core::ptr::copy_nonoverlapping(src, dst, COPY_BYTES) };
+    let t0 = rdtsc();
+    for _ in 0..COPY_ROUNDS {
+        unsafe { core::ptr::copy_nonoverlapping(black_box(src), black_box(dst), COPY_BYTES) };
+    }
+    let cycles = rdtsc() - t0;
+    mem::vmap_free_pages(src, pages);
+    mem::vmap_free_pages(dst, pages);
+    result(
+        "memcpy",
+        c.mib_s(COPY_BYTES as u64 * COPY_ROUNDS, cycles),
+        "MiB/s",
+        format_args!("size={} rounds={}", COPY_BYTES, COPY_ROUNDS),
+    );
+}
+
+fn allocator_throughput(c: &Clock) {
+    for size in [64usize, 4096] {
+        let t0 = rdtsc();
+        for _ in 0..ALLOC_ROUNDS {
+            let v: Vec<u8> = Vec::with_capacity(black_box(size));
+            black_box(&v);
+        }
+        let avg = c.ns((rdtsc() - t0) / ALLOC_ROUNDS);
+        result(
+            "heap_alloc_free",
+            avg,
+            "ns",
+            format_args!("size={} rounds={}", size, ALLOC_ROUNDS),
+        );
+    }
+
+    // Held, then freed, so the heap has to search rather than reuse one slot.
+    let t0 = rdtsc();
+    let held: Vec<Box<[u8; 64]>> = (0..ALLOC_ROUNDS).map(|_| Box::new([0u8; 64])).collect();
+    drop(black_box(held));
+    let avg = c.ns((rdtsc() - t0) / ALLOC_ROUNDS);
+    result(
+        "heap_alloc_batch",
+        avg,
+        "ns",
+        format_args!("size=64 count={}", ALLOC_ROUNDS),
+    );
+
+    let t0 = rdtsc();
+    for _ in 0..FRAME_ROUNDS {
+        let Ok(pa) = mem::alloc_frame() else {
+            return skipped("frame_alloc_free", "out_of_memory");
+        };
+        mem::give_back_frame(pa);
+    }
+    let avg = c.ns((rdtsc() - t0) / FRAME_ROUNDS);
+    result(
+        "frame_alloc_free",
+        avg,
+        "ns",
+        format_args!("rounds={}", FRAME_ROUNDS),
+    );
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    cmdline::flag("benchmarks")
+}
+
+/// Run every benchmark on the calling (kernel main) thread.
+pub fn run() {
+    let c = Clock {
+        hz: tsc_hz_estimate(),
+    };
+    kprintln!("BENCH begin tsc_hz={}", c.hz);
+    context_switch(&c);
+    ipi_round_trip(&c);
+    spinlock_contention(&c);
+    memcpy_throughput(&c);
+    // Every kernel mapping is populated up front; nothing resolves a fault.
+    skipped("page_fault", "no_demand_paging");
+    allocator_throughput(&c);
+    kprintln!("BENCH end");
+}
@@ -0,0 +1,219 @@
+// src/blockdev/bio.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Request/completion model: a BioRequest owns its buffer, travels to the
+// driver, and comes back through a Completion (callback, waker or sleeper).
+// Ordering: a Flush completes only after every request submitted before it,
+// and nothing submitted after it is started first. A FUA write is durable when
+// it completes but orders nothing else.
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{BlockDevice, BlockError};
+use crate::sched::{self, TaskId};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BioOp {
+    Read,
+    Write,
+    Flush, // empty buffer; see BlockDevice::flush
+}
+
+pub type BioResult = Result<Vec<u8>, BlockError>;
+type Callback = Box<dyn FnOnce(BioResult) + Send>;
+
+struct CompState {
+    result: Option<BioResult>,
+    waker: Option<Waker>,
+    sleeper: Option<TaskId>,
+    callback: Option<Callback>,
+}
+
+struct Completion {
+    state: Mutex<CompState>,
+}
+
+impl Completion {
+    // Drivers may complete from IRQ context; keep IRQs off while we hold it.
+    fn with<R>(&self, f: impl FnOnce(&mut CompState) -> R) -> R {
+        without_interrupts(|| f(&mut self.state.lock()))
+    }
+}
+
+/// One block transfer. `buf` is moved in and handed back on completion, so
+/// drivers never copy and the submitter needs no borrow across the wait.
+pub struct BioRequest {
+    pub op: BioOp,
+    pub lba: u64,
+    pub buf: Vec<u8>,
+    pub fua: bool, // writes only: durable on completion
+    done: Arc<Completion>,
+}
+
+/// Submitter's side of a request: `wait()` from a thread, `.await` from a future.
+pub struct BioHandle {
+    done: Arc<Completion>,
+}
+
+/* -------------------------------- Requests ---------------------------------- */
+
+impl BioRequest {
+    fn new(op: BioOp, lba: u64, buf: Vec<u8>) -> (Self, BioHandle) {
+        let done = Arc::new(Completion {
+            state: Mutex::new(CompState {
+                result: None,
+                waker: None,
+                sleeper: None,
+                callback: None,
+            }),
+        });
+        let h = BioHandle { done: done.clone() };
+        let req = Self {
+            op,
+            lba,
+            buf,
+            fua: false,
+            done,
+        };
+        (req, h)
+    }
+
+    /// Read `blocks` blocks of `dev` starting at `lba` into a fresh buffer.
+    pub fn read(dev: &dyn BlockDevice, lba: u64, blocks: usize) -> (Self, BioHandle) {
+        Self::new(BioOp::Read, lba, vec![0u8; blocks * dev.block_size()])
+    }
+
+    pub fn write(lba: u64, buf: Vec<u8>) -> (Self, BioHandle) {
+        Self::new(BioOp::Write, lba, buf)
+    }
+
+    /// Cache flush and ordering barrier.
+    pub fn flush() -> (Self, BioHandle) {
+        Self::new(BioOp::Flush, 0, Vec::new())
+    }
+
+    /// Mark a write force-unit-access.
+    pub fn with_fua(mut self) -> Self {
+        self.fua = true;
+        self
+    }
+
+    /// Deliver the result to `f` (run in the completing context, possibly an
+    /// IRQ) instead of a waiter. The handle then only reports `Io`.
+    pub fn on_complete(self, f: impl FnOnce(BioResult) + Send + 'static) -> Self {
+        self.done.with(|s| s.callback = Some(Box::new(f)));
+        self
+    }
+
+    /// Driver side: finish the request. Safe to call from an IRQ handler.
+    pub fn complete(self, res: Result<(), BlockError>) {
+        let mut result = Some(res.map(|()| self.buf));
+        let (cb, waker, sleeper) = self.done.with(|s| {
+            let cb = s.callback.take();
+            s.result = match cb {
+                Some(_) => Some(Err(BlockError::Io)),
+                None => result.take(),
+            };
+            (cb, s.waker.take(), s.sleeper.take())
+        });
+        if let (Some(cb), Some(r)) = (cb, result) {
+            cb(r);
+        }
+        if let Some(w) = waker {
+            w.wake();
+        }
+        if let Some(id) = sleeper {
+            sched::wake(id);
+        }
+    }
+
+    /// Synchronous fallback used by drivers without a request queue.
+    pub fn execute_on<D: BlockDevice + ?Sized>(mut self, dev: &D) {
+        let r = match self.op {
+            BioOp::Read => dev.read_blocks(self.lba, &mut self.buf),
+            BioOp::Write => dev.write_blocks(self.lba, &self.buf),
+            BioOp::Flush => dev.flush(),
+        };
+        let r = match r {
+            Ok(()) if self.fua && self.op == BioOp::Write => dev.flush(),
+            r => r,
+        };
+        self.complete(r);
+    }
+}
+
+/* --------------------------------- Waiting ---------------------------------- */
+
+impl BioHandle {
+    pub fn is_done(&self) -> bool {
+        self.done.with(|s| s.result.is_some())
+    }
+
+    /// Sleep the current task until the request completes.
+    pub fn wait(self) -> BioResult {
+        let me = sched::current_id();
+        loop {
+            let r = self.done.with(|s| {
+                let r = s.result.take();
+                if r.is_none() {
+                    s.sleeper = me;
+                }
+                r
+            });
+            if let Some(r) = r {
+                return r;
+            }
+            if me.is_some() {
+                sched::block_current();
+            } else {
+                core::hint::spin_loop(); // before the scheduler is up
+            }
+        }
+    }
+}
+
+impl Future for BioHandle {
+    type Output = BioResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BioResult> {
+        self.done.with(|s| match s.result.take() {
+            Some(r) => Poll::Ready(r),
+            None => {
+                s.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+}
+
+/* --------------------------------- Batching --------------------------------- */
+
+/// Queue every request on `dev` before waiting on any, so drivers with a real
+/// queue can overlap/merge them. Results come back in submission order.
+pub fn submit_batch(dev: &dyn BlockDevice, reqs: Vec<(BioRequest, BioHandle)>) -> Vec<BioResult> {
+    let mut handles = Vec::with_capacity(reqs.len());
+    for (req, h) in reqs {
+        dev.submit(req);
+        handles.push(h);
+    }
+    handles.into_iter().map(BioHandle::wait).collect()
+}
+
+/// Flush `dev` through its request queue and wait: returns once everything
+/// submitted before the call is on stable storage.
+pub fn sync(dev: &dyn BlockDevice) -> Result<(), BlockError> {
+    let (req, h) = BioRequest::flush();
+    dev.submit(req);
+    h.wait().map(|_| ())
+}
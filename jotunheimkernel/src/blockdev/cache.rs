@@ -0,0 +1,150 @@
+// src/blockdev/cache.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Page cache in front of a block device. Reads are served from 4 KiB pages
+// kept in the kernel-wide page LRU (mem::lru), filled from the device on a
+// miss. Writes go straight through to the device and then update whatever
+// pages are cached, so every cached page is clean and the LRU can drop any
+// of them under memory pressure without I/O. Devices whose block size does
+// not divide a page are passed through uncached.
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+use super::{BlockDevice, BlockError, check_io};
+use crate::mem::lru::{self, OwnerId, Page};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const PAGE: usize = core::mem::size_of::<Page>();
+
+pub struct PageCache {
+    dev: Arc<dyn BlockDevice>,
+    owner: OwnerId,
+    per_page: u64, // blocks per page; 0 when uncached
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl PageCache {
+    pub fn new(dev: Arc<dyn BlockDevice>) -> Self {
+        let bs = dev.block_size();
+        let per_page = if bs <= PAGE && PAGE.is_multiple_of(bs) {
+            (PAGE / bs) as u64
+        } else {
+            0
+        };
+        Self {
+            dev,
+            owner: lru::new_owner(),
+            per_page,
+        }
+    }
+
+    // Read page `index` from the device into `page`; blocks past the end of
+    // the device read as zeroes.
+    fn fill(&self, index: u64, page: &mut Page) -> bool {
+        let bs = self.dev.block_size();
+        let lba = index * self.per_page;
+        let n = self.per_page.min(self.dev.block_count() - lba) as usize;
+        page[n * bs..].fill(0);
+        self.dev.read_blocks(lba, &mut page[..n * bs]).is_ok()
+    }
+
+    // Split [lba, lba + len bytes) into per-page pieces:
+    // (page index, byte offset in page, byte offset in buffer, length).
+    fn pieces(&self, lba: u64, len: usize) -> impl Iterator<Item = (u64, usize, usize, usize)> {
+        let bs = self.dev.block_size() as u64;
+        let start = lba * bs;
+        let end = start + len as u64;
+        let mut pos = start;
+        core::iter::from_fn(move || {
+            if pos >= end {
+                return None;
+            }
+            let index = pos / PAGE as u64;
+            let off = (pos % PAGE as u64) as usize;
+            let n = (PAGE - off).min((end - pos) as usize);
+            let piece = (index, off, (pos - start) as usize, n);
+            pos += n as u64;
+            Some(piece)
+        })
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        lru::drop_owner(self.owner);
+    }
+}
+
+/* ------------------------------- BlockDevice -------------------------------- */
+
+impl BlockDevice for PageCache {
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.dev.block_count()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        if self.per_page == 0 {
+            return self.dev.read_blocks(lba, buf);
+        }
+        check_io(self, lba, buf.len())?;
+        for (index, off, at, n) in self.pieces(lba, buf.len()) {
+            let out = &mut buf[at..at + n];
+            let copy = |p: &Page| out.copy_from_slice(&p[off..off + n]);
+            if lru::read(self.owner, index, copy) {
+                crate::counter!("cache.hits");
+                continue;
+            }
+            crate::counter!("cache.misses");
+            let out = &mut buf[at..at + n];
+            let mut ok = false;
+            let cached = lru::insert(self.owner, index, |p| {
+                ok = self.fill(index, p);
+                if ok {
+                    out.copy_from_slice(&p[off..off + n]);
+                }
+                ok
+            });
+            if !cached && !ok {
+                // No frame to cache into, or the device failed: read the
+                // piece directly so the error (if any) is the device's.
+                let bs = self.dev.block_size();
+                let first = (index * PAGE as u64 + off as u64) / bs as u64;
+                self.dev.read_blocks(first, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        if self.per_page == 0 {
+            return self.dev.write_blocks(lba, buf);
+        }
+        check_io(self, lba, buf.len())?;
+        let res = self.dev.write_blocks(lba, buf);
+        for (index, off, at, n) in self.pieces(lba, buf.len()) {
+            if res.is_ok() {
+                let src = &buf[at..at + n];
+                lru::update(self.owner, index, |p| p[off..off + n].copy_from_slice(src));
+            } else {
+                // Unknown what reached the disk.
+                lru::invalidate(self.owner, index);
+            }
+        }
+        res
+    }
+
+    fn read_only(&self) -> bool {
+        self.dev.read_only()
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        self.dev.flush()
+    }
+}
@@ -0,0 +1,301 @@
+// src/blockdev/iosched.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Deadline I/O scheduler. Wraps a device; `submit()`ted requests are kept
+// sorted by LBA and a worker thread serves them in one-way elevator order
+// (C-LOOK), merging runs of adjacent same-direction requests into one device
+// transfer. Every request also gets a deadline (reads sooner than writes);
+// once the oldest has expired it is served next, so a busy region of the disk
+// cannot starve the rest. Requests queued together are independent: callers
+// wait for a write before reading the same blocks back. A Flush is a barrier:
+// it and everything submitted after it wait in `held` until the sorted queue
+// has drained, then the flush runs and the next epoch is released.
+
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::bio::BioOp;
+use super::{BioRequest, BlockDevice, BlockError};
+use crate::config::{self, Key};
+use crate::sched::{self, TaskId, stack::DEFAULT_STACK_SIZE};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+// Defaults, in scheduler ticks (1 ms); tunable as bio.read_expire_ms and
+// bio.write_expire_ms.
+pub const READ_EXPIRE: u64 = 500;
+pub const WRITE_EXPIRE: u64 = 5000;
+static READ_EXPIRE_TICKS: AtomicU64 = AtomicU64::new(READ_EXPIRE);
+static WRITE_EXPIRE_TICKS: AtomicU64 = AtomicU64::new(WRITE_EXPIRE);
+
+pub const CONFIG_KEYS: &[Key] = &[
+    Key {
+        name: "read_expire_ms",
+        get: || READ_EXPIRE_TICKS.load(Ordering::Relaxed),
+        validate: |v| config::in_range(v, 1, 60_000),
+        apply: |v| READ_EXPIRE_TICKS.store(v, Ordering::Relaxed),
+    },
+    Key {
+        name: "write_expire_ms",
+        get: || WRITE_EXPIRE_TICKS.load(Ordering::Relaxed),
+        validate: |v| config::in_range(v, 1, 60_000),
+        apply: |v| WRITE_EXPIRE_TICKS.store(v, Ordering::Relaxed),
+    },
+];
+/// Largest merged transfer.
+pub const MAX_MERGE_BYTES: usize = 128 * 1024;
+
+struct Pending {
+    req: BioRequest,
+    deadline: u64,
+}
+
+struct Queue {
+    pending: Vec<Pending>,   // sorted by LBA, FIFO among equal LBAs
+    held: VecDeque<Pending>, // behind a Flush, which is at the front
+    head: u64,               // LBA just past the last dispatch
+    worker: Option<TaskId>,
+}
+
+pub struct DeadlineScheduler {
+    dev: Arc<dyn BlockDevice>,
+    queue: Mutex<Queue>,
+    me: Weak<DeadlineScheduler>,
+    started: Once<()>,
+}
+
+/* --------------------------------- Queueing --------------------------------- */
+
+impl DeadlineScheduler {
+    pub fn new(dev: Arc<dyn BlockDevice>) -> Arc<Self> {
+        Arc::new_cyclic(|me| Self {
+            dev,
+            queue: Mutex::new(Queue {
+                pending: Vec::new(),
+                held: VecDeque::new(),
+                head: 0,
+                worker: None,
+            }),
+            me: me.clone(),
+            started: Once::new(),
+        })
+    }
+
+    /// Requests waiting for dispatch.
+    pub fn queued(&self) -> usize {
+        without_interrupts(|| {
+            let q = self.queue.lock();
+            q.pending.len() + q.held.len()
+        })
+    }
+
+    fn start_worker(&self) {
+        self.started.call_once(|| {
+            let me = self.me.clone();
+            let id = sched::spawn_with_stack_size(DEFAULT_STACK_SIZE, move || {
+                if let Some(s) = me.upgrade() {
+                    s.worker_loop();
+                }
+            });
+            without_interrupts(|| self.queue.lock().worker = Some(id));
+        });
+    }
+
+    // Pull the next transfer off the queue: the expired request with the
+    // earliest deadline if any, else the next one up from `head` (wrapping),
+    // plus every adjacent same-direction request that can ride along.
+    fn next_batch(q: &mut Queue, now: u64, block_size: usize) -> Vec<BioRequest> {
+        if q.pending.is_empty() {
+            return Self::release(q);
+        }
+        let Some(oldest) = q
+            .pending
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, p)| p.deadline)
+            .map(|(i, p)| (i, p.deadline))
+        else {
+            return Vec::new();
+        };
+        let start = if oldest.1 <= now {
+            crate::counter!("bio.expired");
+            oldest.0
+        } else {
+            let i = q.pending.partition_point(|p| p.req.lba < q.head);
+            if i == q.pending.len() { 0 } else { i }
+        };
+
+        let op = q.pending[start].req.op;
+        let mut end = start + 1;
+        let mut next_lba = q.pending[start].req.lba + blocks(&q.pending[start].req, block_size);
+        let mut bytes = q.pending[start].req.buf.len();
+        while let Some(p) = q.pending.get(end)
+            && p.req.op == op
+            && p.req.lba == next_lba
+            && bytes + p.req.buf.len() <= MAX_MERGE_BYTES
+        {
+            next_lba += blocks(&p.req, block_size);
+            bytes += p.req.buf.len();
+            end += 1;
+        }
+        if end - start > 1 {
+            crate::counter!("bio.merged", end - start - 1);
+        }
+        q.head = next_lba;
+        q.pending.drain(start..end).map(|p| p.req).collect()
+    }
+
+    // The sorted queue is empty: start the flush at the front of `held` and
+    // admit what was submitted after it, up to the next flush.
+    fn release(q: &mut Queue) -> Vec<BioRequest> {
+        let Some(first) = q.held.pop_front() else {
+            return Vec::new();
+        };
+        while let Some(p) = q.held.front()
+            && p.req.op != BioOp::Flush
+        {
+            let p = q.held.pop_front().unwrap();
+            insert(&mut q.pending, p);
+        }
+        if first.req.op == BioOp::Flush {
+            vec![first.req]
+        } else {
+            insert(&mut q.pending, first);
+            Self::release(q)
+        }
+    }
+
+    fn worker_loop(&self) {
+        let bs = self.dev.block_size();
+        loop {
+            let batch = without_interrupts(|| {
+                let mut q = self.queue.lock();
+                Self::next_batch(&mut q, sched::ticks(), bs)
+            });
+            if batch.is_empty() {
+                sched::block_current(); // submit() wakes us
+                continue;
+            }
+            self.dispatch(batch);
+        }
+    }
+
+    // One device transfer for a run of adjacent requests.
+    fn dispatch(&self, mut batch: Vec<BioRequest>) {
+        crate::counter!("bio.dispatched");
+        if batch.len() == 1 {
+            let req = batch.pop().unwrap();
+            return req.execute_on(&*self.dev);
+        }
+        let lba = batch[0].lba;
+        let total = batch.iter().map(|r| r.buf.len()).sum();
+        match batch[0].op {
+            BioOp::Read => {
+                let mut big = vec![0u8; total];
+                let r = self.dev.read_blocks(lba, &mut big);
+                let mut at = 0;
+                for mut req in batch {
+                    let n = req.buf.len();
+                    if r.is_ok() {
+                        req.buf.copy_from_slice(&big[at..at + n]);
+                    }
+                    at += n;
+                    req.complete(r);
+                }
+            }
+            BioOp::Write => {
+                let mut big = Vec::with_capacity(total);
+                for req in batch.iter() {
+                    big.extend_from_slice(&req.buf);
+                }
+                let mut r = self.dev.write_blocks(lba, &big);
+                if r.is_ok() && batch.iter().any(|req| req.fua) {
+                    r = self.dev.flush();
+                }
+                for req in batch {
+                    req.complete(r);
+                }
+            }
+            BioOp::Flush => unreachable!("flushes are never merged"),
+        }
+    }
+}
+
+fn insert(pending: &mut Vec<Pending>, p: Pending) {
+    let at = pending.partition_point(|x| x.req.lba <= p.req.lba);
+    pending.insert(at, p);
+}
+
+fn blocks(req: &BioRequest, block_size: usize) -> u64 {
+    (req.buf.len() / block_size) as u64
+}
+
+/* ------------------------------- BlockDevice -------------------------------- */
+
+// Synchronous reads and writes go straight to the device; only `submit()`
+// is scheduled.
+impl BlockDevice for DeadlineScheduler {
+    fn block_size(&self) -> usize {
+        self.dev.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.dev.block_count()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.dev.read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        self.dev.write_blocks(lba, buf)
+    }
+
+    fn read_only(&self) -> bool {
+        self.dev.read_only()
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        self.dev.flush()
+    }
+
+    fn submit(&self, req: BioRequest) {
+        if req.op != BioOp::Flush
+            && let Err(e) = super::check_io(self, req.lba, req.buf.len())
+        {
+            return req.complete(Err(e));
+        }
+        if req.op == BioOp::Write && self.read_only() {
+            return req.complete(Err(BlockError::ReadOnly));
+        }
+        self.start_worker();
+        let expire = match req.op {
+            BioOp::Read => &READ_EXPIRE_TICKS,
+            BioOp::Write | BioOp::Flush => &WRITE_EXPIRE_TICKS,
+        }
+        .load(Ordering::Relaxed);
+        let p = Pending {
+            deadline: sched::ticks() + expire,
+            req,
+        };
+        let worker = without_interrupts(|| {
+            let mut q = self.queue.lock();
+            if p.req.op == BioOp::Flush || !q.held.is_empty() {
+                q.held.push_back(p);
+            } else {
+                insert(&mut q.pending, p);
+            }
+            q.worker
+        });
+        if let Some(id) = worker {
+            sched::wake(id);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// src/blockdev/loopback.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+extern crate alloc;
+use alloc::sync::Arc;
+
+use super::{BlockDevice, BlockError, check_io};
+
+pub const LOOP_BLOCK_SIZE: usize = 512;
+
+/// Byte-addressed storage a loop device can sit on (a file on some filesystem).
+pub trait BackingFile: Send + Sync {
+    fn len(&self) -> u64;
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> bool;
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Push buffered writes down to whatever holds the file.
+    fn sync(&self) -> bool {
+        true
+    }
+}
+
+/// Exposes a file as a disk. A trailing partial block is ignored.
+pub struct LoopDevice {
+    file: Arc<dyn BackingFile>,
+    blocks: u64,
+}
+
+impl LoopDevice {
+    pub fn new(file: Arc<dyn BackingFile>) -> Self {
+        let blocks = file.len() / LOOP_BLOCK_SIZE as u64;
+        Self { file, blocks }
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn block_size(&self) -> usize {
+        LOOP_BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        if self.file.read_at(lba * LOOP_BLOCK_SIZE as u64, buf) {
+            Ok(())
+        } else {
+            Err(BlockError::Io)
+        }
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        if !self.file.writable() {
+            return Err(BlockError::ReadOnly);
+        }
+        check_io(self, lba, buf.len())?;
+        if self.file.write_at(lba * LOOP_BLOCK_SIZE as u64, buf) {
+            Ok(())
+        } else {
+            Err(BlockError::Io)
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        !self.file.writable()
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        if self.file.sync() {
+            Ok(())
+        } else {
+            Err(BlockError::Io)
+        }
+    }
+}
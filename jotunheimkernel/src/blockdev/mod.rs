@@ -0,0 +1,160 @@
+// src/blockdev/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+
+pub mod bio;
+pub mod cache;
+pub mod iosched;
+pub mod loopback;
+pub mod part;
+pub mod parttab;
+pub mod ram;
+pub mod selftest;
+
+extern crate alloc;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+pub use bio::BioRequest;
+pub use cache::PageCache;
+pub use iosched::DeadlineScheduler;
+pub use loopback::{BackingFile, LoopDevice};
+pub use part::Partition;
+pub use ram::RamDisk;
+
+use crate::kobject::{self, KObjError, KObject, KRef};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BlockError {
+    OutOfRange, // lba/len past the end of the device
+    BadBuffer,  // buffer length not a multiple of the block size
+    ReadOnly,   // write to a read-only device
+    Io,         // device reported an error
+}
+
+/// A whole-disk (or sub-disk) device addressed in fixed-size blocks.
+/// Methods take `&self`; drivers serialize internally so a device can be
+/// shared between tasks behind an `Arc`.
+pub trait BlockDevice: Send + Sync {
+    /// Bytes per block (512 for disks, 2048 for optical media).
+    fn block_size(&self) -> usize;
+
+    /// Total number of blocks on the device.
+    fn block_count(&self) -> u64;
+
+    /// Read `buf.len() / block_size()` blocks starting at `lba`.
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Write `buf.len() / block_size()` blocks starting at `lba`.
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Make every completed write durable. Devices without a volatile write
+    /// cache have nothing to do.
+    fn flush(&self) -> Result<(), BlockError> {
+        Ok(())
+    }
+
+    /// Queue a request; completion is signalled through the request itself.
+    /// Drivers with a hardware queue override this; the default runs it inline.
+    fn submit(&self, req: BioRequest) {
+        req.execute_on(self);
+    }
+}
+
+/// Bounds/shape check shared by drivers: returns the number of blocks in `len`.
+pub fn check_io(dev: &dyn BlockDevice, lba: u64, len: usize) -> Result<u64, BlockError> {
+    let bs = dev.block_size();
+    if len == 0 || !len.is_multiple_of(bs) {
+        return Err(BlockError::BadBuffer);
+    }
+    let n = (len / bs) as u64;
+    match lba.checked_add(n) {
+        Some(end) if end <= dev.block_count() => Ok(n),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+/* ------------------------------- Constructors -------------------------------- */
+
+/// Zero-filled in-memory disk of at least `len` bytes (rounded up to a block).
+pub fn ram(len: usize) -> Arc<RamDisk> {
+    Arc::new(RamDisk::new(len))
+}
+
+/// Disk backed by a file, e.g. an image shipped on the boot medium.
+pub fn loopback(file: Arc<dyn BackingFile>) -> Arc<LoopDevice> {
+    Arc::new(LoopDevice::new(file))
+}
+
+/// Put a page cache in front of `dev`.
+pub fn cached(dev: Arc<dyn BlockDevice>) -> Arc<PageCache> {
+    Arc::new(PageCache::new(dev))
+}
+
+/// Put a deadline I/O scheduler in front of `dev`.
+pub fn deadline(dev: Arc<dyn BlockDevice>) -> Arc<DeadlineScheduler> {
+    DeadlineScheduler::new(dev)
+}
+
+/* --------------------------------- Registry --------------------------------- */
+
+type DevRef = KRef<Arc<dyn BlockDevice>>;
+
+/// A registered disk and the partitions found on it. Everything stays
+/// registered while this (or a clone of it) is held.
+#[derive(Clone)]
+pub struct BlockRef {
+    parts: Vec<(Arc<Partition>, DevRef)>,
+    disk: DevRef,
+}
+
+impl BlockRef {
+    pub fn device(&self) -> &Arc<dyn BlockDevice> {
+        &self.disk
+    }
+
+    /// The partitions, as `/devices/block/<name>/p<n>`.
+    pub fn partitions(&self) -> Vec<Arc<Partition>> {
+        self.parts.iter().map(|(p, _)| p.clone()).collect()
+    }
+}
+
+/// Publish `dev` as `/devices/block/<name>`, scan it for a partition table
+/// and publish each partition beneath it.
+pub fn register(name: &str, dev: Arc<dyn BlockDevice>) -> Result<BlockRef, KObjError> {
+    let dir = kobject::dir(&kobject::root("devices"), "block")?;
+    let disk = KObject::create(name, &dir, dev.clone())?;
+    let mut parts = Vec::new();
+    for p in part::scan(&dev) {
+        let n = p.info.index;
+        kprintln!(
+            "[block] {}/p{}: lba={} len={} label='{}' {}",
+            name,
+            n,
+            p.start_lba(),
+            p.block_count(),
+            p.info.label,
+            p.info.kind
+        );
+        let as_dev: Arc<dyn BlockDevice> = p.clone();
+        match KObject::create(&format!("p{}", n), disk.object(), as_dev) {
+            Ok(r) => parts.push((p, r)),
+            Err(e) => kprintln!("[block] {}/p{}: not registered: {:?}", name, n, e),
+        }
+    }
+    Ok(BlockRef { parts, disk })
+}
+
+/// A registered device by name: `pmem0`, or `pmem0/p1` for a partition.
+pub fn open(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    let obj = kobject::lookup(&format!("/devices/block/{}", name))?;
+    KObject::downcast::<Arc<dyn BlockDevice>>(&obj).map(|d| Arc::clone(&d))
+}
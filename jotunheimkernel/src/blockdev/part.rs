@@ -0,0 +1,263 @@
+// src/blockdev/part.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// GPT (primary, then backup header) with MBR/EBR fallback.
+extern crate alloc;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::bio::BioOp;
+use super::parttab::{self, MBR_EXTENDED, MBR_PROTECTIVE, MbrEntry};
+use super::{BioRequest, BlockDevice, BlockError, check_io};
+use crate::kprintln;
+
+pub use super::parttab::Guid;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_LOGICAL: usize = 64; // EBR chain guard
+
+#[derive(Copy, Clone, Debug)]
+pub enum PartKind {
+    Gpt { type_guid: Guid, unique_guid: Guid },
+    Mbr { system_id: u8, bootable: bool },
+}
+
+impl fmt::Display for PartKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartKind::Gpt {
+                type_guid,
+                unique_guid,
+            } => write!(f, "type {} uuid {}", type_guid, unique_guid),
+            PartKind::Mbr {
+                system_id,
+                bootable,
+            } => write!(
+                f,
+                "id {:#04x}{}",
+                system_id,
+                if *bootable { " boot" } else { "" }
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PartInfo {
+    pub index: usize, // 1-based, as users count them
+    pub kind: PartKind,
+    pub label: String, // GPT name; empty for MBR
+}
+
+/// A window `[start, start+count)` of the parent device.
+pub struct Partition {
+    parent: Arc<dyn BlockDevice>,
+    start: u64,
+    count: u64,
+    pub info: PartInfo,
+}
+
+impl Partition {
+    pub fn start_lba(&self) -> u64 {
+        self.start
+    }
+}
+
+impl BlockDevice for Partition {
+    fn block_size(&self) -> usize {
+        self.parent.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.count
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        self.parent.read_blocks(self.start + lba, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        self.parent.write_blocks(self.start + lba, buf)
+    }
+
+    fn read_only(&self) -> bool {
+        self.parent.read_only()
+    }
+
+    // The parent's cache is shared, so this flushes the whole disk.
+    fn flush(&self) -> Result<(), BlockError> {
+        self.parent.flush()
+    }
+
+    fn submit(&self, mut req: BioRequest) {
+        if req.op == BioOp::Flush {
+            return self.parent.submit(req);
+        }
+        if let Err(e) = check_io(self, req.lba, req.buf.len()) {
+            return req.complete(Err(e));
+        }
+        req.lba += self.start;
+        self.parent.submit(req);
+    }
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn read_lba(dev: &dyn BlockDevice, lba: u64) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; dev.block_size()];
+    dev.read_blocks(lba, &mut buf).ok()?;
+    Some(buf)
+}
+
+fn window(
+    dev: &Arc<dyn BlockDevice>,
+    start: u64,
+    count: u64,
+    info: PartInfo,
+) -> Option<Arc<Partition>> {
+    let end = start.checked_add(count)?;
+    if start == 0 || count == 0 || end > dev.block_count() {
+        kprintln!("[part] p{} out of range, skipped", info.index);
+        return None;
+    }
+    Some(Arc::new(Partition {
+        parent: dev.clone(),
+        start,
+        count,
+        info,
+    }))
+}
+
+/* ----------------------------------- GPT ------------------------------------ */
+
+fn gpt_at(dev: &Arc<dyn BlockDevice>, hdr_lba: u64) -> Option<Vec<Arc<Partition>>> {
+    let hdr = parttab::gpt_header(&read_lba(dev.as_ref(), hdr_lba)?, hdr_lba)?;
+    let bs = dev.block_size();
+    let mut table = vec![0u8; hdr.table_bytes().div_ceil(bs) * bs];
+    dev.read_blocks(hdr.entries_lba, &mut table).ok()?;
+
+    let mut out = Vec::new();
+    for e in hdr.entries(&table)? {
+        let info = PartInfo {
+            index: e.index,
+            kind: PartKind::Gpt {
+                type_guid: e.type_guid,
+                unique_guid: e.unique_guid,
+            },
+            label: e.label,
+        };
+        if let Some(p) = window(dev, e.first, e.last - e.first + 1, info) {
+            out.push(p);
+        }
+    }
+    Some(out)
+}
+
+fn scan_gpt(dev: &Arc<dyn BlockDevice>) -> Option<Vec<Arc<Partition>>> {
+    if let Some(v) = gpt_at(dev, 1) {
+        return Some(v);
+    }
+    let last = dev.block_count().checked_sub(1)?;
+    let v = gpt_at(dev, last)?;
+    kprintln!("[part] primary GPT damaged; using backup header");
+    Some(v)
+}
+
+/* ----------------------------------- MBR ------------------------------------ */
+
+fn scan_mbr(dev: &Arc<dyn BlockDevice>, table: &[MbrEntry; 4]) -> Vec<Arc<Partition>> {
+    let mut out = Vec::new();
+    let mut ext_base = None;
+
+    for (i, e) in table.iter().enumerate() {
+        if e.system_id == 0 || e.count == 0 {
+            continue;
+        }
+        if MBR_EXTENDED.contains(&e.system_id) {
+            ext_base = Some(e.start);
+            continue;
+        }
+        let info = PartInfo {
+            index: i + 1,
+            kind: PartKind::Mbr {
+                system_id: e.system_id,
+                bootable: e.bootable,
+            },
+            label: String::new(),
+        };
+        if let Some(p) = window(dev, e.start, e.count, info) {
+            out.push(p);
+        }
+    }
+
+    // Logical partitions: EBR chain, links relative to the extended partition.
+    let Some(base) = ext_base else {
+        return out;
+    };
+    let mut ebr = base;
+    for n in 0..MAX_LOGICAL {
+        let Some(t) = read_lba(dev.as_ref(), ebr).and_then(|s| parttab::mbr_entries(&s)) else {
+            break;
+        };
+        if t[0].system_id != 0 && t[0].count != 0 {
+            let info = PartInfo {
+                index: 5 + n,
+                kind: PartKind::Mbr {
+                    system_id: t[0].system_id,
+                    bootable: t[0].bootable,
+                },
+                label: String::new(),
+            };
+            if let Some(p) = window(dev, ebr + t[0].start, t[0].count, info) {
+                out.push(p);
+            }
+        }
+        if t[1].system_id == 0 || t[1].start == 0 {
+            break;
+        }
+        ebr = base + t[1].start;
+    }
+    out
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Scan `dev` for partitions: GPT first (a protective MBR is expected but not
+/// required), then a classic MBR. Returns an empty list for unpartitioned disks.
+pub fn scan(dev: &Arc<dyn BlockDevice>) -> Vec<Arc<Partition>> {
+    let mbr = read_lba(dev.as_ref(), 0).and_then(|s| parttab::mbr_entries(&s));
+    let protective = mbr
+        .as_ref()
+        .is_some_and(|t| t.iter().any(|e| e.system_id == MBR_PROTECTIVE));
+
+    if let Some(v) = scan_gpt(dev) {
+        if !protective {
+            kprintln!("[part] GPT without protective MBR");
+        }
+        return v;
+    }
+
+    match mbr {
+        Some(t) if !protective => scan_mbr(dev, &t),
+        _ => Vec::new(),
+    }
+}
+
+/// Mount-by-label helper over a scan result.
+pub fn find_by_label(parts: &[Arc<Partition>], label: &str) -> Option<Arc<Partition>> {
+    parts.iter().find(|p| p.info.label == label).cloned()
+}
+
+/// Mount-by-PARTUUID helper over a scan result (GPT only).
+pub fn find_by_guid(parts: &[Arc<Partition>], guid: Guid) -> Option<Arc<Partition>> {
+    parts
+        .iter()
+        .find(|p| matches!(p.info.kind, PartKind::Gpt { unique_guid, .. } if unique_guid == guid))
+        .cloned()
+}
@@ -0,0 +1,179 @@
+// src/blockdev/parttab.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// On-disk partition table formats: the MBR sector, GPT header and GPT entry
+// array. Pure byte parsing with no device access, so part.rs does the I/O
+// and the host tests (hosttest/) exercise these directly.
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::util::crc32::crc32;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MBR_SIG: [u8; 2] = [0x55, 0xAA];
+pub const MBR_TABLE: usize = 446;
+pub const MBR_PROTECTIVE: u8 = 0xEE;
+pub const MBR_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+
+pub const GPT_SIG: &[u8; 8] = b"EFI PART";
+pub const GPT_MAX_ENTRIES: u32 = 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub const ZERO: Guid = Guid([0; 16]);
+}
+
+// Mixed-endian textual form: first three groups are little-endian on disk.
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-",
+            g[3], g[2], g[1], g[0], g[5], g[4], g[7], g[6], g[8], g[9]
+        )?;
+        for b in &g[10..16] {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+pub struct MbrEntry {
+    pub bootable: bool,
+    pub system_id: u8,
+    pub start: u64,
+    pub count: u64,
+}
+
+/// The fields of a GPT header that locate and check the entry array.
+pub struct GptHeader {
+    pub entries_lba: u64,
+    pub count: u32,
+    pub entry_size: usize,
+    entries_crc: u32,
+}
+
+pub struct GptEntry {
+    pub index: usize, // 1-based slot in the array
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first: u64,
+    pub last: u64, // inclusive
+    pub label: String,
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn le32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+fn le64(b: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(b[off..off + 8].try_into().unwrap())
+}
+
+fn utf16_label(raw: &[u8]) -> String {
+    let units = raw
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .map(|c| u16::from_le_bytes(*c))
+        .take_while(|&u| u != 0);
+    char::decode_utf16(units)
+        .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// The four primary entries of an MBR (or EBR) sector.
+pub fn mbr_entries(sector: &[u8]) -> Option<[MbrEntry; 4]> {
+    if sector.len() < 512 || sector[510..512] != MBR_SIG {
+        return None;
+    }
+    Some(core::array::from_fn(|i| {
+        let e = &sector[MBR_TABLE + i * 16..MBR_TABLE + (i + 1) * 16];
+        MbrEntry {
+            bootable: e[0] == 0x80,
+            system_id: e[4],
+            start: le32(e, 8) as u64,
+            count: le32(e, 12) as u64,
+        }
+    }))
+}
+
+/// Validate the GPT header in `block`, which was read from `lba`.
+pub fn gpt_header(block: &[u8], lba: u64) -> Option<GptHeader> {
+    if block.len() < 92 || &block[0..8] != GPT_SIG {
+        return None;
+    }
+    let hdr_size = le32(block, 12) as usize;
+    if !(92..=block.len()).contains(&hdr_size) {
+        return None;
+    }
+    let mut h = block[..hdr_size].to_vec();
+    h[16..20].fill(0); // CRC is computed with its own field zeroed
+    if crc32(&h) != le32(block, 16) || le64(block, 24) != lba {
+        return None;
+    }
+
+    let count = le32(block, 80);
+    let entry_size = le32(block, 84) as usize;
+    if count == 0 || count > GPT_MAX_ENTRIES || entry_size < 128 || !entry_size.is_multiple_of(8) {
+        return None;
+    }
+    Some(GptHeader {
+        entries_lba: le64(block, 72),
+        count,
+        entry_size,
+        entries_crc: le32(block, 88),
+    })
+}
+
+impl GptHeader {
+    /// Size of the entry array in bytes.
+    pub fn table_bytes(&self) -> usize {
+        self.count as usize * self.entry_size
+    }
+
+    /// The used entries of `table`, or None if its CRC does not match.
+    /// Unused slots and entries with `last < first` are skipped.
+    pub fn entries(&self, table: &[u8]) -> Option<Vec<GptEntry>> {
+        let table = table.get(..self.table_bytes())?;
+        if crc32(table) != self.entries_crc {
+            return None;
+        }
+        let out = table
+            .chunks_exact(self.entry_size)
+            .enumerate()
+            .filter_map(|(i, e)| {
+                let type_guid = Guid(e[0..16].try_into().unwrap());
+                let (first, last) = (le64(e, 32), le64(e, 40));
+                if type_guid == Guid::ZERO || last < first {
+                    return None;
+                }
+                Some(GptEntry {
+                    index: i + 1,
+                    type_guid,
+                    unique_guid: Guid(e[16..32].try_into().unwrap()),
+                    first,
+                    last,
+                    label: utf16_label(&e[56..128]),
+                })
+            })
+            .collect();
+        Some(out)
+    }
+}
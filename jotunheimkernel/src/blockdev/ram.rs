@@ -0,0 +1,51 @@
+// src/blockdev/ram.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::vec;
+use spin::Mutex;
+
+use super::{BlockDevice, BlockError, check_io};
+
+pub const RAM_BLOCK_SIZE: usize = 512;
+
+/// Heap-backed disk; contents start zeroed and vanish with the last reference.
+pub struct RamDisk {
+    data: Mutex<Box<[u8]>>,
+    blocks: u64,
+}
+
+impl RamDisk {
+    pub fn new(len: usize) -> Self {
+        let blocks = len.div_ceil(RAM_BLOCK_SIZE);
+        Self {
+            data: Mutex::new(vec![0u8; blocks * RAM_BLOCK_SIZE].into_boxed_slice()),
+            blocks: blocks as u64,
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        RAM_BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        let off = lba as usize * RAM_BLOCK_SIZE;
+        buf.copy_from_slice(&self.data.lock()[off..off + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        let off = lba as usize * RAM_BLOCK_SIZE;
+        self.data.lock()[off..off + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+// src/blockdev/selftest.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Block-layer test, run at the end of boot with `blktest` on the command
+// line. A RAM disk gets a protective MBR and a GPT with two partitions, then:
+//   - registering it publishes both partitions, found again by label, by
+//     unique GUID and by path, and I/O outside one fails;
+//   - partition "iso" gets a small ISO9660 volume with Rock Ridge names and
+//     an El Torito catalog. Its file reads back whole, and again through a
+//     (read-only) loop device on top of it;
+//   - partition "data", behind a page cache and the deadline scheduler,
+//     takes a batch of adjacent writes (the last one FUA) and a flush, and
+//     reads them back twice, the second time from the cache. A request
+//     with a completion callback finishes too;
+//   - dropping the registration withdraws the disk and its partitions.
+// Failures are listed and then panic.
+
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::bio::{self, BioRequest};
+use super::part::{self, Guid, Partition};
+use super::parttab::{GPT_SIG, MBR_PROTECTIVE, MBR_SIG, MBR_TABLE};
+use super::ram::RAM_BLOCK_SIZE;
+use super::{BlockDevice, BlockError};
+use crate::fs::iso9660::{Iso9660, SECTOR_SIZE};
+use crate::mem::lru;
+use crate::sched::{current_id, sleep_ms, ticks};
+use crate::util::crc32::crc32;
+use crate::{cmdline, kprintln, stats};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const BS: usize = RAM_BLOCK_SIZE;
+const NAME: &str = "blktest0";
+const DISK_BLOCKS: u64 = 2048;
+
+// GPT: header at LBA 1, one block of entries at LBA 2.
+const ENTRIES: u32 = 4;
+const ENTRY_SIZE: usize = 128;
+// Basic data partition, in its on-disk byte order.
+const TYPE_GUID: Guid = Guid([
+    0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+]);
+const ISO_GUID: Guid = Guid([0x15; 16]);
+const DATA_GUID: Guid = Guid([0xDA; 16]);
+// (label, unique GUID, first LBA, last LBA)
+const PARTS: [(&str, Guid, u64, u64); 2] =
+    [("iso", ISO_GUID, 64, 191), ("data", DATA_GUID, 1024, 2039)];
+
+// ISO9660 layout, in 2048-byte sectors.
+const PVD: u32 = 16;
+const BOOT: u32 = 17;
+const TERM: u32 = 18;
+const ROOT: u32 = 19;
+const CATALOG: u32 = 20;
+const FILE: u32 = 21;
+const ISO_SECTORS: usize = FILE as usize + 1;
+const VOLUME: &str = "BLKTEST";
+const FILE_NAME: &str = "Hello.txt"; // Rock Ridge; the ISO name is HELLO.TXT;1
+const FILE_LEN: usize = 2 * BS;
+
+// Data partition traffic: BATCH adjacent requests of CHUNK blocks each.
+const BATCH: u64 = 4;
+const CHUNK: u64 = 8;
+const CALLBACK_TIMEOUT: u64 = 1000; // ticks
+
+static CALLED_BACK: AtomicBool = AtomicBool::new(false);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn put16(b: &mut [u8], at: usize, v: u16) {
+    b[at..at + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put32(b: &mut [u8], at: usize, v: u32) {
+    b[at..at + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put64(b: &mut [u8], at: usize, v: u64) {
+    b[at..at + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+// ISO9660 both-byte-order fields: little-endian, then big-endian.
+fn both16(b: &mut [u8], at: usize, v: u16) {
+    put16(b, at, v);
+    b[at + 2..at + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+fn both32(b: &mut [u8], at: usize, v: u32) {
+    put32(b, at, v);
+    b[at + 4..at + 8].copy_from_slice(&v.to_be_bytes());
+}
+
+fn check(ok: bool, what: &str, bad: &mut u32) {
+    if !ok {
+        kprintln!("[blktest] {}", what);
+        *bad += 1;
+    }
+}
+
+fn file_data() -> Vec<u8> {
+    (0..FILE_LEN).map(|i| (i * 7 + 3) as u8).collect()
+}
+
+fn chunk_data(i: u64) -> Vec<u8> {
+    (0..CHUNK as usize * BS)
+        .map(|j| (j as u8) ^ (i as u8 * 0x5B))
+        .collect()
+}
+
+/* ---------------------------------- Images ---------------------------------- */
+
+// Protective MBR, GPT header and entry array over the first three blocks.
+fn write_gpt(dev: &dyn BlockDevice) -> Result<(), BlockError> {
+    let blocks = dev.block_count();
+    let mut img = vec![0u8; 3 * BS];
+
+    let mbr = &mut img[..BS];
+    mbr[MBR_TABLE + 4] = MBR_PROTECTIVE;
+    put32(mbr, MBR_TABLE + 8, 1);
+    put32(mbr, MBR_TABLE + 12, (blocks - 1) as u32);
+    mbr[510..512].copy_from_slice(&MBR_SIG);
+
+    let table = &mut img[2 * BS..];
+    for (i, &(label, guid, first, last)) in PARTS.iter().enumerate() {
+        let e = &mut table[i * ENTRY_SIZE..(i + 1) * ENTRY_SIZE];
+        e[0..16].copy_from_slice(&TYPE_GUID.0);
+        e[16..32].copy_from_slice(&guid.0);
+        put64(e, 32, first);
+        put64(e, 40, last);
+        for (j, u) in label.encode_utf16().enumerate() {
+            put16(e, 56 + j * 2, u);
+        }
+    }
+    let table_crc = crc32(&img[2 * BS..2 * BS + ENTRIES as usize * ENTRY_SIZE]);
+
+    let hdr = &mut img[BS..2 * BS];
+    hdr[0..8].copy_from_slice(GPT_SIG);
+    put32(hdr, 8, 0x0001_0000); // revision 1.0
+    put32(hdr, 12, 92);
+    put64(hdr, 24, 1);
+    put64(hdr, 32, blocks - 1); // backup header, not written
+    put64(hdr, 40, 3);
+    put64(hdr, 48, blocks - 2);
+    put64(hdr, 72, 2);
+    put32(hdr, 80, ENTRIES);
+    put32(hdr, 84, ENTRY_SIZE as u32);
+    put32(hdr, 88, table_crc);
+    let crc = crc32(&hdr[..92]);
+    put32(hdr, 16, crc);
+
+    dev.write_blocks(0, &img)
+}
+
+// One directory record into `out`; returns its length. `su` is the System
+// Use area (Rock Ridge entries).
+fn dir_record(out: &mut [u8], extent: u32, size: u32, dir: bool, name: &[u8], su: &[u8]) -> usize {
+    let at = 33 + name.len() + (name.len() + 1) % 2;
+    let len = (at + su.len()).next_multiple_of(2);
+    out[0] = len as u8;
+    both32(out, 2, extent);
+    both32(out, 10, size);
+    out[25] = if dir { 1 << 1 } else { 0 };
+    both16(out, 28, 1); // volume sequence number
+    out[32] = name.len() as u8;
+    out[33..33 + name.len()].copy_from_slice(name);
+    out[at..at + su.len()].copy_from_slice(su);
+    len
+}
+
+fn descriptor(img: &mut [u8], sector: u32, kind: u8) -> &mut [u8] {
+    let d = &mut img[sector as usize * SECTOR_SIZE..][..SECTOR_SIZE];
+    d[0] = kind;
+    d[1..6].copy_from_slice(b"CD001");
+    d[6] = 1;
+    d
+}
+
+fn iso_image() -> Vec<u8> {
+    let mut img = vec![0u8; ISO_SECTORS * SECTOR_SIZE];
+    let dir = SECTOR_SIZE as u32; // every directory is one sector
+
+    let pvd = descriptor(&mut img, PVD, 1);
+    pvd[40..72].fill(b' ');
+    pvd[40..40 + VOLUME.len()].copy_from_slice(VOLUME.as_bytes());
+    both32(pvd, 80, ISO_SECTORS as u32);
+    both16(pvd, 128, SECTOR_SIZE as u16);
+    dir_record(&mut pvd[156..190], ROOT, dir, true, &[0], &[]);
+
+    let boot = descriptor(&mut img, BOOT, 0);
+    boot[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+    put32(boot, 0x47, CATALOG);
+
+    descriptor(&mut img, TERM, 255);
+
+    // "." carries the SUSP "SP" entry that announces Rock Ridge.
+    let sp = [b'S', b'P', 7, 1, 0xBE, 0xEF, 0];
+    let mut nm = vec![b'N', b'M', (5 + FILE_NAME.len()) as u8, 1, 0];
+    nm.extend_from_slice(FILE_NAME.as_bytes());
+    let root = &mut img[ROOT as usize * SECTOR_SIZE..][..SECTOR_SIZE];
+    let mut at = dir_record(root, ROOT, dir, true, &[0], &sp);
+    at += dir_record(&mut root[at..], ROOT, dir, true, &[1], &[]);
+    dir_record(
+        &mut root[at..],
+        FILE,
+        FILE_LEN as u32,
+        false,
+        b"HELLO.TXT;1",
+        &nm,
+    );
+
+    // Validation entry (words sum to zero), then a bootable initial entry.
+    let cat = &mut img[CATALOG as usize * SECTOR_SIZE..][..64];
+    cat[0] = 0x01;
+    cat[30] = 0x55;
+    cat[31] = 0xAA;
+    let sum = (0..16).fold(0u16, |a, i| {
+        a.wrapping_add(u16::from_le_bytes([cat[i * 2], cat[i * 2 + 1]]))
+    });
+    put16(cat, 28, sum.wrapping_neg());
+    cat[32] = 0x88;
+    put16(cat, 38, 4);
+    put32(cat, 40, FILE);
+
+    img[FILE as usize * SECTOR_SIZE..][..FILE_LEN].copy_from_slice(&file_data());
+    img
+}
+
+/* ---------------------------------- Checks ---------------------------------- */
+
+fn iso_checks(part: Arc<Partition>) -> u32 {
+    let mut bad = 0;
+    if part.write_blocks(0, &iso_image()).is_err() {
+        kprintln!("[blktest] iso: could not write the image");
+        return 1;
+    }
+    let fs = match Iso9660::mount(part) {
+        Ok(fs) => Arc::new(fs),
+        Err(e) => {
+            kprintln!("[blktest] iso: mount failed: {}", e);
+            return 1;
+        }
+    };
+    check(fs.volume_id() == VOLUME, "iso: wrong volume id", &mut bad);
+    check(
+        fs.has_rock_ridge(),
+        "iso: Rock Ridge not detected",
+        &mut bad,
+    );
+    let boot_ok = fs.boot_entry().is_some_and(|b| {
+        b.bootable
+            && b.media == 0
+            && b.load_segment == 0
+            && b.sector_count == 4
+            && b.load_rba == FILE
+    });
+    check(boot_ok, "iso: wrong El Torito entry", &mut bad);
+    let listed = fs
+        .read_dir(fs.root())
+        .is_ok_and(|v| v.len() == 1 && v[0].name == FILE_NAME && !v[0].is_dir);
+    check(listed, "iso: wrong root directory", &mut bad);
+
+    let path = alloc::format!("/{}", FILE_NAME);
+    let whole = fs.lookup(&path).and_then(|e| fs.read_all(&e));
+    check(
+        whole == Ok(file_data()),
+        "iso: file reads back wrong",
+        &mut bad,
+    );
+
+    match fs.open(&path) {
+        Ok(file) => {
+            let lo = super::loopback(file);
+            let mut buf = vec![0u8; FILE_LEN];
+            let read = lo.read_blocks(0, &mut buf);
+            check(
+                read.is_ok() && buf == file_data(),
+                "loop: reads back wrong",
+                &mut bad,
+            );
+            let refused = lo.write_blocks(0, &buf[..BS]) == Err(BlockError::ReadOnly);
+            check(
+                lo.read_only() && refused,
+                "loop: writable on a read-only file",
+                &mut bad,
+            );
+        }
+        Err(e) => {
+            kprintln!("[blktest] iso: open failed: {}", e);
+            bad += 1;
+        }
+    }
+    bad
+}
+
+fn io_checks(part: Arc<Partition>) -> u32 {
+    let mut bad = 0;
+    let dev = super::deadline(super::cached(part));
+
+    // Adjacent, so the scheduler can merge them.
+    let writes = (0..BATCH)
+        .map(|i| {
+            let (req, h) = BioRequest::write(i * CHUNK, chunk_data(i));
+            let req = if i == BATCH - 1 { req.with_fua() } else { req };
+            (req, h)
+        })
+        .collect();
+    let res = bio::submit_batch(dev.as_ref(), writes);
+    check(res.iter().all(Result::is_ok), "bio: write failed", &mut bad);
+    check(
+        bio::sync(dev.as_ref()).is_ok(),
+        "bio: flush failed",
+        &mut bad,
+    );
+
+    let hits = stats::get("cache.hits").unwrap_or(0);
+    for pass in 0..2 {
+        let reads = (0..BATCH)
+            .map(|i| BioRequest::read(dev.as_ref(), i * CHUNK, CHUNK as usize))
+            .collect();
+        let res = bio::submit_batch(dev.as_ref(), reads);
+        for (i, r) in res.into_iter().enumerate() {
+            if r != Ok(chunk_data(i as u64)) {
+                kprintln!("[blktest] bio: pass {} chunk {} reads back wrong", pass, i);
+                bad += 1;
+            }
+        }
+    }
+    let cached = stats::get("cache.hits").unwrap_or(0) > hits;
+    check(cached, "cache: second pass missed", &mut bad);
+
+    CALLED_BACK.store(false, Ordering::Relaxed);
+    let (req, h) = BioRequest::read(dev.as_ref(), 0, 1);
+    dev.submit(req.on_complete(|r| CALLED_BACK.store(r.is_ok(), Ordering::Release)));
+    let end = ticks() + CALLBACK_TIMEOUT;
+    while !h.is_done() && ticks() < end {
+        sleep_ms(1);
+    }
+    check(
+        CALLED_BACK.load(Ordering::Acquire),
+        "bio: no completion callback",
+        &mut bad,
+    );
+    check(dev.queued() == 0, "iosched: requests left queued", &mut bad);
+    bad
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    cmdline::flag("blktest")
+}
+
+/// Run the checks on the calling thread; panic if any failed.
+pub fn run() {
+    if current_id().is_none() {
+        kprintln!("[blktest] not running as a task; skipped");
+        return;
+    }
+    let disk: Arc<dyn BlockDevice> = super::ram(DISK_BLOCKS as usize * BS);
+    if write_gpt(disk.as_ref()).is_err() {
+        panic!("block self-test: could not write the partition table");
+    }
+    let r = match super::register(NAME, disk) {
+        Ok(r) => r,
+        Err(e) => panic!("block self-test: register: {:?}", e),
+    };
+    let mut bad = 0;
+    let parts = r.partitions();
+    check(
+        r.device().block_count() == DISK_BLOCKS,
+        "wrong disk size",
+        &mut bad,
+    );
+    check(
+        parts.len() == PARTS.len(),
+        "wrong partition count",
+        &mut bad,
+    );
+    let (Some(iso), Some(data)) = (
+        part::find_by_label(&parts, PARTS[0].0),
+        part::find_by_guid(&parts, DATA_GUID),
+    ) else {
+        panic!("block self-test: partitions not found");
+    };
+    let published = super::open(&alloc::format!("{}/p2", NAME))
+        .is_some_and(|d| d.block_count() == PARTS[1].3 - PARTS[1].2 + 1);
+    check(published, "p2 not published", &mut bad);
+
+    let mut blk = vec![0u8; BS];
+    let past = data.read_blocks(data.block_count(), &mut blk);
+    check(
+        past == Err(BlockError::OutOfRange),
+        "read past the end",
+        &mut bad,
+    );
+    let short = data.read_blocks(0, &mut blk[..BS / 2]);
+    check(
+        short == Err(BlockError::BadBuffer),
+        "partial-block read",
+        &mut bad,
+    );
+
+    bad += iso_checks(iso);
+    bad += io_checks(data);
+
+    drop(r);
+    let gone = super::open(NAME).is_none() && super::open(&alloc::format!("{}/p1", NAME)).is_none();
+    check(gone, "still registered after the last handle", &mut bad);
+
+    if bad != 0 {
+        panic!("block self-test: {} check(s) failed", bad);
+    }
+    let lru = lru::stats();
+    kprintln!(
+        "[blktest] ok: {} partitions; lru {} pages, {} inserted, {} evicted",
+        parts.len(),
+        lru.pages,
+        lru.inserted,
+        lru.evicted
+    );
+}
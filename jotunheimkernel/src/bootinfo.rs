@@ -1,5 +1,8 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+/// Must match jotunboot's MAX_FRAMEBUFFERS.
+pub const MAX_FRAMEBUFFERS: usize = 4;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Framebuffer {
@@ -7,10 +10,19 @@ pub struct Framebuffer {
     pub width: u32,
     pub height: u32,
     pub pitch: u32,        // bytes per scanline
-    pub bpp: u32,          // bits per pixel (commonly 32)
-    pub pixel_format: u32, // kernel enum/discriminant
+    pub bpp: u32,          // bits per pixel: 32, 24 or 16
+    pub pixel_format: u32, // PIXEL_FORMAT_*
+    pub red_mask: u32,     // channel bits within a little-endian pixel
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
 }
 
+pub const PIXEL_FORMAT_RGB: u32 = 0; // bytes R, G, B, reserved
+pub const PIXEL_FORMAT_BGR: u32 = 1; // bytes B, G, R, reserved
+pub const PIXEL_FORMAT_BITMASK: u32 = 2; // layout given by the masks
+pub const PIXEL_FORMAT_BLT_ONLY: u32 = 3; // no linear framebuffer
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct MemoryRegion {
@@ -21,6 +33,21 @@ pub struct MemoryRegion {
     pub attr: u64, // attribute bits
 }
 
+/// `MemoryRegion::typ` of EFI persistent memory (NVDIMM); see mem::pmem.
+pub const MEMORY_PERSISTENT: u32 = 9;
+
+/// Pages jotunboot set up for the handoff; see mem::handoff.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LoaderRange {
+    pub phys: u64,
+    pub pages: u64,
+    pub kind: u32, // LOADER_RANGE_*
+}
+
+pub const LOADER_RANGE_PAGE_TABLES: u32 = 1;
+pub const LOADER_RANGE_TRAMPOLINE: u32 = 2;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct BootInfo {
@@ -35,4 +62,19 @@ pub struct BootInfo {
     pub hhdm_base: u64,
     pub low32_pool_paddr: u64,
     pub low32_pool_len: u64,
+    pub cmdline: *const u8, // ASCII, not NUL-terminated
+    pub cmdline_len: usize,
+    pub loader_log: *const u8, // loader's COM1 output up to the handoff
+    pub loader_log_len: usize,
+    pub framebuffers: [Framebuffer; MAX_FRAMEBUFFERS], // [0] == framebuffer
+    pub framebuffer_count: usize,
+    pub loader_ranges: *const LoaderRange, // every page-table page + trampoline
+    pub loader_ranges_len: usize,
+}
+
+impl BootInfo {
+    /// Every GOP framebuffer the loader found; the primary one first.
+    pub fn framebuffers(&self) -> &[Framebuffer] {
+        &self.framebuffers[..self.framebuffer_count.min(MAX_FRAMEBUFFERS)]
+    }
 }
@@ -0,0 +1,71 @@
+// src/bootlog.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// jotunboot's COM1 output up to ExitBootServices, handed over through
+// BootInfo and copied into the kernel image before the loader's pages can be
+// reclaimed. Loader `[step]` lines carry a TSC stamp (`@tsc=N`), which
+// `print_steps()` turns into per-step timings.
+
+use heapless::Vec;
+use spin::Once;
+
+use crate::arch::x86_64::tsc;
+use crate::bootinfo::BootInfo;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_LEN: usize = 16 * 1024; // jotunboot's LOG_CAP
+
+static LOG: Once<Vec<u8, MAX_LEN>> = Once::new();
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Copy the loader log out of BootInfo. Must run while the loader's identity
+/// mapping is still live; later calls are no-ops.
+pub fn init(boot: &BootInfo) {
+    LOG.call_once(|| {
+        let mut v = Vec::new();
+        if !boot.loader_log.is_null() {
+            let raw = unsafe { core::slice::from_raw_parts(boot.loader_log, boot.loader_log_len) };
+            let _ = v.extend_from_slice(&raw[..raw.len().min(MAX_LEN)]);
+        }
+        v
+    });
+}
+
+/// The whole loader log; invalid UTF-8 is cut at the first bad byte.
+pub fn get() -> &'static str {
+    let Some(v) = LOG.get() else {
+        return "";
+    };
+    match core::str::from_utf8(v) {
+        Ok(s) => s,
+        Err(e) => unsafe { core::str::from_utf8_unchecked(&v[..e.valid_up_to()]) },
+    }
+}
+
+pub fn lines() -> impl Iterator<Item = &'static str> {
+    get().lines()
+}
+
+/// `[step] <msg> @tsc=<n>` -> (msg, n)
+fn parse_step(line: &str) -> Option<(&str, u64)> {
+    let rest = line.strip_prefix("[step] ")?;
+    let (msg, stamp) = rest.rsplit_once(" @tsc=")?;
+    Some((msg, stamp.parse().ok()?))
+}
+
+/// Log each loader step with the time since the first one.
+pub fn print_steps() {
+    let hz = tsc::tsc_hz_estimate().max(1);
+    let mut first = None;
+    for (msg, t) in lines().filter_map(parse_step) {
+        let t0 = *first.get_or_insert(t);
+        let us = (t.saturating_sub(t0) as u128 * 1_000_000 / hz as u128) as u64;
+        kprintln!("[loader] +{}.{:03} ms {}", us / 1000, us % 1000, msg);
+    }
+    if first.is_none() {
+        kprintln!("[loader] no step timings ({} bytes of log)", get().len());
+    }
+}
@@ -0,0 +1,63 @@
+// src/cmdline.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Kernel command line: the loader's own UEFI load options, handed over in the
+// BootInfo page. Copied into the kernel image before the loader's memory can
+// be reclaimed. Whitespace-separated `flag` and `key=value` tokens.
+
+use heapless::Vec;
+use spin::Once;
+
+use crate::bootinfo::BootInfo;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_LEN: usize = 1024;
+
+static CMDLINE: Once<Vec<u8, MAX_LEN>> = Once::new();
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Copy the command line out of BootInfo. Must run while the loader's
+/// identity mapping is still live; later calls are no-ops.
+pub fn init(boot: &BootInfo) {
+    CMDLINE.call_once(|| {
+        let mut v = Vec::new();
+        if !boot.cmdline.is_null() {
+            let raw = unsafe { core::slice::from_raw_parts(boot.cmdline, boot.cmdline_len) };
+            let _ = v.extend_from_slice(&raw[..raw.len().min(MAX_LEN)]);
+        }
+        v
+    });
+    kprintln!("[cmdline] \"{}\"", get());
+}
+
+/// The whole command line; empty before `init()` or when none was given.
+pub fn get() -> &'static str {
+    CMDLINE
+        .get()
+        .and_then(|v| core::str::from_utf8(v).ok())
+        .unwrap_or("")
+}
+
+pub fn tokens() -> impl Iterator<Item = &'static str> {
+    get().split_ascii_whitespace()
+}
+
+/// Value of the last `key=value` token for `key`.
+pub fn value(key: &str) -> Option<&'static str> {
+    tokens()
+        .filter_map(|t| t.split_once('='))
+        .filter(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+        .last()
+}
+
+/// True for a bare `name` token, or `name=` anything other than 0/off/no.
+pub fn flag(name: &str) -> bool {
+    if let Some(v) = value(name) {
+        return !matches!(v, "0" | "off" | "no");
+    }
+    tokens().any(|t| t == name)
+}
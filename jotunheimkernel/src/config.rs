@@ -0,0 +1,239 @@
+// src/config.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Runtime configuration. Subsystems register numeric keys with a validation
+// callback and an apply callback; each subsystem's keys are published as
+// `key=value` lines in the ramfs file /config/<subsystem>. A watcher thread
+// re-reads a file whenever it changes and applies every key whose value
+// differs and validates; rejected values are logged and the old value stays.
+// A deleted file is written back with the live values.
+// `set()` edits one line of a file, which reloads through the same path.
+
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::fs::notify::{self, EventKind};
+use crate::fs::ramfs;
+use crate::kprintln;
+use crate::sched;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const DIR: &str = "/config";
+
+/// One tunable. `validate` sees the parsed value before `apply` does.
+pub struct Key {
+    pub name: &'static str,
+    pub get: fn() -> u64,
+    pub validate: fn(u64) -> Result<(), &'static str>,
+    pub apply: fn(u64),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConfigError {
+    NoSubsystem,
+    NoKey,
+    BadValue(&'static str),
+}
+
+struct Subsystem {
+    name: &'static str,
+    keys: &'static [Key],
+}
+
+static SUBSYSTEMS: Mutex<Vec<Subsystem>> = Mutex::new(Vec::new());
+static STARTED: Once<()> = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+/// Validator for a plain inclusive range; most keys need nothing more.
+pub fn in_range(v: u64, lo: u64, hi: u64) -> Result<(), &'static str> {
+    if (lo..=hi).contains(&v) {
+        Ok(())
+    } else {
+        Err("out of range")
+    }
+}
+
+fn path_of(subsystem: &str) -> String {
+    format!("{}/{}", DIR, subsystem)
+}
+
+fn keys_of(subsystem: &str) -> Option<&'static [Key]> {
+    without_interrupts(|| {
+        SUBSYSTEMS
+            .lock()
+            .iter()
+            .find(|s| s.name == subsystem)
+            .map(|s| s.keys)
+    })
+}
+
+fn render(keys: &[Key]) -> String {
+    let mut s = String::new();
+    for k in keys {
+        let _ = writeln!(s, "{}={}", k.name, (k.get)());
+    }
+    s
+}
+
+fn parse(v: &str) -> Option<u64> {
+    let v = v.trim();
+    match v.strip_prefix("0x") {
+        Some(h) => u64::from_str_radix(h, 16).ok(),
+        None => v.parse().ok(),
+    }
+}
+
+fn apply_one(subsystem: &str, key: &Key, raw: &str) -> Result<(), ConfigError> {
+    let v = parse(raw).ok_or(ConfigError::BadValue("not a number"))?;
+    if v == (key.get)() {
+        return Ok(());
+    }
+    (key.validate)(v).map_err(ConfigError::BadValue)?;
+    (key.apply)(v);
+    kprintln!("[config] {}.{} = {}", subsystem, key.name, v);
+    Ok(())
+}
+
+/* --------------------------------- Reload ---------------------------------- */
+
+// Apply a subsystem's file. Blank lines and `#` comments are skipped;
+// unknown keys and bad values are reported and ignored.
+fn reload(subsystem: &str) {
+    let Some(keys) = keys_of(subsystem) else {
+        return;
+    };
+    let Ok(data) = ramfs::read(&path_of(subsystem)) else {
+        return;
+    };
+    let text = core::str::from_utf8(&data).unwrap_or("");
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, raw)) = line.split_once('=') else {
+            kprintln!("[config] {}: bad line '{}'", subsystem, line);
+            continue;
+        };
+        let name = name.trim();
+        match keys.iter().find(|k| k.name == name) {
+            Some(k) => {
+                if let Err(ConfigError::BadValue(why)) = apply_one(subsystem, k, raw) {
+                    kprintln!(
+                        "[config] {}.{}: rejected '{}' ({})",
+                        subsystem,
+                        name,
+                        raw.trim(),
+                        why
+                    );
+                }
+            }
+            None => kprintln!("[config] {}: unknown key '{}'", subsystem, name),
+        }
+    }
+}
+
+// Rewrite a subsystem's file from its live values.
+fn publish(subsystem: &str) {
+    if let Some(keys) = keys_of(subsystem) {
+        let _ = ramfs::write(&path_of(subsystem), render(keys).as_bytes());
+    }
+}
+
+fn reload_all() {
+    let names: Vec<&'static str> =
+        without_interrupts(|| SUBSYSTEMS.lock().iter().map(|s| s.name).collect());
+    names.into_iter().for_each(reload);
+}
+
+fn watch_loop() {
+    let w = notify::watch(DIR, notify::ALL);
+    // Anything written before the watch existed.
+    reload_all();
+    loop {
+        let ev = w.recv();
+        let name = ev.path.strip_prefix(DIR).and_then(|p| p.strip_prefix('/'));
+        match (ev.kind, name) {
+            (EventKind::Overflow, _) => reload_all(),
+            (EventKind::Delete, Some(name)) => publish(name),
+            (_, Some(name)) => reload(name),
+            (_, None) => {}
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Start the reload thread. Needs the scheduler.
+pub fn init() {
+    STARTED.call_once(|| {
+        sched::spawn(watch_loop);
+        kprintln!("[config] watching {}", DIR);
+    });
+}
+
+/// Publish `keys` under /config/<subsystem> with their current values.
+pub fn register(subsystem: &'static str, keys: &'static [Key]) {
+    without_interrupts(|| {
+        SUBSYSTEMS.lock().push(Subsystem {
+            name: subsystem,
+            keys,
+        })
+    });
+    publish(subsystem);
+}
+
+/// Current value of `subsystem.key`.
+pub fn get(subsystem: &str, key: &str) -> Option<u64> {
+    let k = keys_of(subsystem)?.iter().find(|k| k.name == key)?;
+    Some((k.get)())
+}
+
+/// Rewrite the `key=` line of /config/<subsystem>. The value is checked
+/// here so callers get an answer; the watcher then applies it.
+pub fn set(subsystem: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+    let keys = keys_of(subsystem).ok_or(ConfigError::NoSubsystem)?;
+    let k = keys
+        .iter()
+        .find(|k| k.name == key)
+        .ok_or(ConfigError::NoKey)?;
+    let v = parse(value).ok_or(ConfigError::BadValue("not a number"))?;
+    (k.validate)(v).map_err(ConfigError::BadValue)?;
+
+    let path = path_of(subsystem);
+    let old = ramfs::read(&path).unwrap_or_default();
+    let old = core::str::from_utf8(&old).unwrap_or("");
+    let mut text = String::new();
+    let mut found = false;
+    for line in old.lines() {
+        if line.split_once('=').is_some_and(|(n, _)| n.trim() == key) {
+            let _ = writeln!(text, "{}={}", key, v);
+            found = true;
+        } else {
+            let _ = writeln!(text, "{}", line);
+        }
+    }
+    if !found {
+        let _ = writeln!(text, "{}={}", key, v);
+    }
+    let _ = ramfs::write(&path, text.as_bytes());
+    Ok(())
+}
+
+/// Call `f` with every registered `subsystem.key` and its live value.
+pub fn for_each(mut f: impl FnMut(&'static str, &'static str, u64)) {
+    let subs: Vec<(&'static str, &'static [Key])> =
+        without_interrupts(|| SUBSYSTEMS.lock().iter().map(|s| (s.name, s.keys)).collect());
+    for (name, keys) in subs {
+        for k in keys {
+            f(name, k.name, (k.get)());
+        }
+    }
+}
@@ -3,6 +3,7 @@
 // Copyright (C) 2025 The Jotunheim Project
 #![allow(unsafe_op_in_unsafe_fn)]
 use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::registers::control::{Cr0, Cr0Flags};
 
 #[derive(Copy, Clone)]
@@ -26,21 +27,25 @@ unsafe fn read_byte(addr: u64) -> u8 {
     (addr as *const u8).read_volatile()
 }
 
-// Temporarily clear CR0.WP so supervisor can patch RO text safely.
-fn with_wp_disabled<F: FnOnce()>(f: F) {
-    let old = Cr0::read();
-    // If WP is already clear, just run f().
-    if !old.contains(Cr0Flags::WRITE_PROTECT) {
-        f();
-        return;
-    }
-    unsafe {
-        Cr0::write(old - Cr0Flags::WRITE_PROTECT);
-    }
-    f();
-    unsafe {
-        Cr0::write(old);
-    }
+/// Run `f` with CR0.WP clear, so supervisor writes reach read-only text.
+/// Interrupts stay off throughout: no other task may run with text
+/// writable, nor resume on another CPU and write this one's CR0 there.
+pub fn with_wp_disabled<R>(f: impl FnOnce() -> R) -> R {
+    without_interrupts(|| {
+        let old = Cr0::read();
+        // If WP is already clear, just run f().
+        if !old.contains(Cr0Flags::WRITE_PROTECT) {
+            return f();
+        }
+        unsafe {
+            Cr0::write(old - Cr0Flags::WRITE_PROTECT);
+        }
+        let r = f();
+        unsafe {
+            Cr0::write(old);
+        }
+        r
+    })
 }
 
 fn find_slot(addr: u64, tbl: &mut [Option<Bp>; MAX_BP]) -> Option<usize> {
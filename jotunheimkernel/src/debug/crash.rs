@@ -0,0 +1,199 @@
+// src/debug/crash.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Crash record built by the panic handler: message, location, CPU, tick and a
+// frame-pointer backtrace (the kernel is built with frame pointers). It packs
+// into a small little-endian binary record, and into `JOTUN1:<base64url>` text
+// for the panic screen's QR code; the text form LZ4-compresses the record
+// (`JCZ1` envelope) whenever that comes out shorter. Everything here is
+// heap-free and lock-free.
+
+use core::arch::x86_64::__cpuid;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::ptr::addr_of;
+
+use heapless::{String, Vec};
+
+use crate::sched;
+use crate::util::lz4;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_FRAMES: usize = 16;
+pub const MSG_MAX: usize = 256;
+const FILE_MAX: usize = 96;
+const MAGIC: &[u8; 4] = b"JCR1";
+const ZMAGIC: &[u8; 4] = b"JCZ1"; // + raw length u16 + LZ4 block
+pub const TEXT_PREFIX: &str = "JOTUN1:";
+// Sanity bound on one frame's size; larger hops mean rbp is garbage.
+const MAX_FRAME_BYTES: u64 = 0x10_0000;
+
+/// Upper bound on `encode()` output.
+pub const RECORD_MAX: usize = 4 + 4 + 8 + 1 + MAX_FRAMES * 8 + 4 + 2 + 1 + FILE_MAX + 2 + MSG_MAX;
+const PACKED_MAX: usize = 6 + lz4::bound(RECORD_MAX);
+/// Upper bound on `to_text()` output.
+pub const TEXT_MAX: usize = TEXT_PREFIX.len() + PACKED_MAX.div_ceil(3) * 4;
+
+pub struct CrashRecord {
+    pub cpu: u32, // initial APIC id
+    pub tick: u64,
+    pub frames: Vec<u64, MAX_FRAMES>, // return addresses, panic runtime first
+    pub file: String<FILE_MAX>,
+    pub line: u32,
+    pub col: u16,
+    pub msg: String<MSG_MAX>, // truncated
+}
+
+unsafe extern "C" {
+    unsafe static __text_start: u8;
+    unsafe static __text_end: u8;
+}
+
+/* -------------------------------- Backtrace -------------------------------- */
+
+fn in_text(addr: u64) -> bool {
+    let lo = addr_of!(__text_start) as u64;
+    let hi = addr_of!(__text_end) as u64;
+    (lo..hi).contains(&addr)
+}
+
+/// Walk the rbp chain into `out`, leaving out the innermost `skip` return
+/// addresses (0 starts with the return into our caller).
+#[inline(never)]
+pub fn backtrace(out: &mut Vec<u64, MAX_FRAMES>, skip: usize) {
+    let rbp: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp) };
+    backtrace_from(rbp, out, skip);
+}
+
+/// `backtrace` starting from frame pointer `rbp`, e.g. an interrupted
+/// context's.
+pub fn backtrace_from(mut rbp: u64, out: &mut Vec<u64, MAX_FRAMES>, mut skip: usize) {
+    while !out.is_full() && rbp != 0 && rbp.is_multiple_of(8) {
+        let (next, ret) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+        if !in_text(ret) {
+            break;
+        }
+        if skip == 0 {
+            let _ = out.push(ret);
+        } else {
+            skip -= 1;
+        }
+        // Callers live higher up the same stack.
+        if next <= rbp || next - rbp > MAX_FRAME_BYTES {
+            break;
+        }
+        rbp = next;
+    }
+}
+
+/* ------------------------------ Crash records ------------------------------ */
+
+// Truncating writer: keeps what fits and never fails.
+struct Trunc<'a, const N: usize>(&'a mut String<N>);
+
+impl<const N: usize> Write for Trunc<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CrashRecord {
+    /// Describe the panic in progress. Call from the panic handler itself so
+    /// the backtrace starts at the panicking code.
+    pub fn capture(info: &PanicInfo) -> Self {
+        let mut rec = Self {
+            cpu: __cpuid(1).ebx >> 24,
+            tick: sched::ticks(),
+            frames: Vec::new(),
+            file: String::new(),
+            line: 0,
+            col: 0,
+            msg: String::new(),
+        };
+        backtrace(&mut rec.frames, 1); // not into capture() itself
+        if let Some(loc) = info.location() {
+            let _ = Trunc(&mut rec.file).write_str(loc.file());
+            rec.line = loc.line();
+            rec.col = loc.column() as u16;
+        }
+        let _ = write!(Trunc(&mut rec.msg), "{}", info.message());
+        rec
+    }
+
+    /// Binary form: magic, cpu u32, tick u64, nframes u8, frames u64 each,
+    /// line u32, col u16, file (len u8), msg (len u16). Little-endian.
+    pub fn encode(&self, out: &mut [u8; RECORD_MAX]) -> usize {
+        let mut n = 0;
+        let mut put = |b: &[u8]| {
+            out[n..n + b.len()].copy_from_slice(b);
+            n += b.len();
+        };
+        put(MAGIC);
+        put(&self.cpu.to_le_bytes());
+        put(&self.tick.to_le_bytes());
+        put(&[self.frames.len() as u8]);
+        for f in self.frames.iter() {
+            put(&f.to_le_bytes());
+        }
+        put(&self.line.to_le_bytes());
+        put(&self.col.to_le_bytes());
+        put(&[self.file.len() as u8]);
+        put(self.file.as_bytes());
+        put(&(self.msg.len() as u16).to_le_bytes());
+        put(self.msg.as_bytes());
+        n
+    }
+
+    /// The binary record, wrapped as `JCZ1` + length + LZ4 block when that
+    /// is smaller.
+    pub fn pack(&self, out: &mut [u8; PACKED_MAX]) -> usize {
+        let mut bin = [0u8; RECORD_MAX];
+        let len = self.encode(&mut bin);
+        if let Ok(z) = lz4::compress(&bin[..len], &mut out[6..])
+            && z + 6 < len
+        {
+            out[..4].copy_from_slice(ZMAGIC);
+            out[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+            return z + 6;
+        }
+        out[..len].copy_from_slice(&bin[..len]);
+        len
+    }
+
+    /// `JOTUN1:` + base64url(pack()); returns the length written. `out`
+    /// must hold TEXT_MAX bytes.
+    pub fn to_text(&self, out: &mut [u8]) -> usize {
+        let mut bin = [0u8; PACKED_MAX];
+        let len = self.pack(&mut bin);
+        let p = TEXT_PREFIX.len();
+        out[..p].copy_from_slice(TEXT_PREFIX.as_bytes());
+        p + base64url(&bin[..len], &mut out[p..])
+    }
+}
+
+/// Base64 with the URL-safe alphabet and no padding. `out` must hold
+/// `len.div_ceil(3) * 4` bytes.
+pub fn base64url(data: &[u8], out: &mut [u8]) -> usize {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut n = 0;
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let v = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..chunk.len() + 1 {
+            out[n] = ALPHABET[(v >> (18 - 6 * i) & 0x3F) as usize];
+            n += 1;
+        }
+    }
+    n
+}
@@ -0,0 +1,63 @@
+// src/debug/faults.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// The last few CPU exceptions, for `faults` in the debug shell. The fault
+// handlers call `record` before deciding what to do with the fault; the
+// per-vector totals are the `*.faults` counters. Heap-free, and a record
+// that finds the ring locked (a fault inside `for_each`) is dropped rather
+// than waited for.
+
+use x86_64::registers::control::Cr2;
+
+use super::TrapFrame;
+use crate::sched;
+use crate::util::ring::OverwriteRing;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const KEEP: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Fault {
+    pub vec: u64,
+    pub err: u64,
+    pub rip: u64,
+    pub cr2: u64, // faulting address for #PF, else 0
+    pub tick: u64,
+    pub cpu: u32, // initial APIC id
+}
+
+static RING: OverwriteRing<Fault, KEEP> = OverwriteRing::new();
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Short mnemonic for exception vector `vec`.
+pub fn name(vec: u64) -> &'static str {
+    match vec {
+        0 => "#DE",
+        6 => "#UD",
+        8 => "#DF",
+        13 => "#GP",
+        14 => "#PF",
+        21 => "#CP",
+        _ => "exception",
+    }
+}
+
+/// Remember the fault `tf` describes, evicting the oldest.
+pub fn record(tf: &TrapFrame) {
+    let f = Fault {
+        vec: tf.vec,
+        err: tf.err,
+        rip: tf.rip,
+        cr2: if tf.vec == 14 { Cr2::read_raw() } else { 0 },
+        tick: sched::ticks(),
+        cpu: core::arch::x86_64::__cpuid(1).ebx >> 24,
+    };
+    let _ = RING.try_push(f);
+}
+
+/// Recorded faults, oldest first. False if the ring was busy.
+pub fn for_each(f: impl FnMut(&Fault)) -> bool {
+    RING.try_for_each(f)
+}
@@ -0,0 +1,208 @@
+// src/debug/histo.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Latency histograms for interrupt paths. Where the latency tracer keeps
+// only the worst IRQ-off region, these keep the whole distribution, so the
+// jitter long critical sections add to interrupt handling shows up as a
+// tail that can be measured and compared:
+//   - resume: timer ticks that switch tasks, from handler entry to handing
+//     the next task's frame back. Waiting on the run-queue lock, which
+//     other CPUs hold with IRQs off, lands here.
+//   - isr: time spent in each vector's handler, per vector.
+//
+// Buckets are log2 of nanoseconds: bucket 0 holds 0, bucket b holds
+// [2^(b-1), 2^b). Recording is a few relaxed atomics, safe from any
+// context. Off by default; `irqhist` on the command line or the shell's
+// `irqhist on` turns it on.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::arch::native::tsc;
+use crate::cmdline;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const BUCKETS: usize = 32; // the last one also takes everything above 2^30 ns
+const VECTORS: usize = 256;
+const BAR: u64 = 40; // widest bar in a dump
+
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum: AtomicU64, // ns
+    max: AtomicU64, // ns
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// ns per TSC cycle, as 32.32 fixed point; 0 until `set_enabled` first runs.
+static NS_PER_CYCLE: AtomicU64 = AtomicU64::new(0);
+
+pub static RESUME: Histogram = Histogram::new();
+static ISR: [Histogram; VECTORS] = [const { Histogram::new() }; VECTORS];
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn ns(cycles: u64) -> u64 {
+    ((cycles as u128 * NS_PER_CYCLE.load(Ordering::Relaxed) as u128) >> 32) as u64
+}
+
+fn bucket(ns: u64) -> usize {
+    ((u64::BITS - ns.leading_zeros()) as usize).min(BUCKETS - 1)
+}
+
+/// A time in nanoseconds, printed in the largest unit that keeps it whole.
+struct Ns(u64);
+
+impl fmt::Display for Ns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Through a buffer so width and alignment apply.
+        let mut s = heapless::String::<24>::new();
+        let _ = match self.0 {
+            n if n < 10_000 => write!(s, "{}ns", n),
+            n if n < 10_000_000 => write!(s, "{}us", n / 1000),
+            n => write!(s, "{}ms", n / 1_000_000),
+        };
+        f.pad(&s)
+    }
+}
+
+/* --------------------------------- Histogram -------------------------------- */
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, ns: u64) {
+        self.buckets[bucket(ns)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(ns, Ordering::Relaxed);
+        self.max.fetch_max(ns, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        for b in self.buckets.iter() {
+            b.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+
+    /// A summary line, then one line per non-empty bucket with a bar.
+    pub fn dump(&self, name: &str, out: &mut dyn Write) -> fmt::Result {
+        let n = self.count();
+        let avg = self.sum.load(Ordering::Relaxed) / n.max(1);
+        let max = self.max.load(Ordering::Relaxed);
+        writeln!(
+            out,
+            "{}: {} samples, avg {}, max {}",
+            name,
+            n,
+            Ns(avg),
+            Ns(max)
+        )?;
+        let counts = self.buckets.each_ref().map(|b| b.load(Ordering::Relaxed));
+        let top = counts.iter().copied().max().unwrap_or(0).max(1);
+        for (b, &c) in counts.iter().enumerate().filter(|(_, c)| **c != 0) {
+            let lo = if b == 0 { 0 } else { 1 << (b - 1) };
+            write!(out, "  >= {:7} {:9} ", Ns(lo), c)?;
+            for _ in 0..(c * BAR).div_ceil(top) {
+                out.write_char('#')?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn init() {
+    if cmdline::flag("irqhist") {
+        set_enabled(true);
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    if on && NS_PER_CYCLE.load(Ordering::Relaxed) == 0 {
+        // CPUID is slow (and exits under a hypervisor): estimate once.
+        let hz = tsc::tsc_hz_estimate().max(1);
+        NS_PER_CYCLE.store((1_000_000_000u64 << 32) / hz, Ordering::Relaxed);
+    }
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+/// TSC at the start of a timed path, or 0 when recording is off.
+#[inline]
+pub fn start() -> u64 {
+    if enabled() { tsc::rdtsc() } else { 0 }
+}
+
+/// A handler for `vec` that began at `t0` (from `start`) is done.
+#[inline]
+pub fn isr_end(vec: u64, t0: u64) {
+    if t0 != 0
+        && let Some(h) = ISR.get(vec as usize)
+    {
+        h.record(ns(tsc::rdtsc() - t0));
+    }
+}
+
+/// A tick that began at `t0` is about to resume a different task.
+#[inline]
+pub fn resumed(t0: u64) {
+    if t0 != 0 {
+        RESUME.record(ns(tsc::rdtsc() - t0));
+    }
+}
+
+/// Time `f` as the handler for `vec`.
+#[inline]
+pub fn isr<R>(vec: u64, f: impl FnOnce() -> R) -> R {
+    let t0 = start();
+    let r = f();
+    isr_end(vec, t0);
+    r
+}
+
+/// Handler time for `vec`.
+pub fn for_vector(vec: u8) -> &'static Histogram {
+    &ISR[vec as usize]
+}
+
+/// Dump the resume histogram and every vector that has samples.
+pub fn dump(out: &mut dyn Write) -> fmt::Result {
+    RESUME.dump("resume", out)?;
+    for (v, h) in ISR.iter().enumerate().filter(|(_, h)| h.count() != 0) {
+        let mut name = heapless::String::<16>::new();
+        let _ = write!(name, "isr {:#04x}", v);
+        h.dump(&name, out)?;
+    }
+    Ok(())
+}
+
+pub fn reset() {
+    RESUME.reset();
+    ISR.iter().for_each(Histogram::reset);
+}
@@ -0,0 +1,374 @@
+// src/debug/insn.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// x86-64 instruction length decoder, with a mnemonic for the common
+// opcodes: enough to print an instruction trace, not a disassembler.
+// 64-bit mode only. Handles legacy and REX prefixes, the one-byte, 0F,
+// 0F38 and 0F3A maps, VEX and EVEX (length only), ModRM/SIB/displacement
+// and immediates. 3DNow! and XOP are not decoded.
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_LEN: usize = 15;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Insn {
+    pub len: usize,
+    pub mnemonic: &'static str, // or only the group: "sse", "x87", ...
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    Truncated, // the bytes ran out before the instruction did
+    Invalid,   // not an instruction in 64-bit mode
+    TooLong,   // over MAX_LEN bytes
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Map {
+    One,
+    Of,
+    Of38,
+    Of3a,
+}
+
+// Immediate operand sizes.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Imm {
+    None,
+    B,     // 8
+    W,     // 16
+    Z,     // 16 with 0x66, else 32
+    V,     // 16, 32 or 64 with REX.W (mov r, imm)
+    Rel32, // call/jmp/jcc: 32 in 64-bit mode regardless of 0x66
+    Enter, // 16 + 8
+    Moffs, // 64, or 32 with 0x67
+}
+
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFT: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+const GRP3: [&str; 8] = ["test", "test", "not", "neg", "mul", "imul", "div", "idiv"];
+const GRP5: [&str; 8] = ["inc", "dec", "call", "callf", "jmp", "jmpf", "push", "?"];
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+struct Cursor<'a> {
+    b: &'a [u8],
+    i: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&mut self) -> Result<u8, DecodeError> {
+        let v = *self.b.get(self.i).ok_or(DecodeError::Truncated)?;
+        self.i += 1;
+        Ok(v)
+    }
+
+    fn peek(&self) -> Result<u8, DecodeError> {
+        self.b.get(self.i).copied().ok_or(DecodeError::Truncated)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), DecodeError> {
+        if self.i + n > self.b.len() {
+            return Err(DecodeError::Truncated);
+        }
+        self.i += n;
+        Ok(())
+    }
+}
+
+fn is_legacy_prefix(b: u8) -> bool {
+    matches!(
+        b,
+        0x66 | 0x67 | 0xf0 | 0xf2 | 0xf3 | 0x2e | 0x36 | 0x3e | 0x26 | 0x64 | 0x65
+    )
+}
+
+// ModRM, then SIB and displacement as it asks for. Returns the reg field.
+fn modrm(c: &mut Cursor) -> Result<u8, DecodeError> {
+    let m = c.next()?;
+    let (md, reg, rm) = (m >> 6, (m >> 3) & 7, m & 7);
+    if md == 3 {
+        return Ok(reg);
+    }
+    let mut disp = match md {
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    if rm == 4 {
+        let sib = c.next()?;
+        if md == 0 && sib & 7 == 5 {
+            disp = 4;
+        }
+    } else if md == 0 && rm == 5 {
+        disp = 4; // rip-relative
+    }
+    c.skip(disp)?;
+    Ok(reg)
+}
+
+// (has ModRM, immediate, mnemonic) for a one-byte opcode. Invalid opcodes
+// give None.
+fn one_byte(op: u8) -> Option<(bool, Imm, &'static str)> {
+    let alu = ALU[(op >> 3) as usize & 7];
+    Some(match op {
+        0x00..=0x3f => match op & 7 {
+            0..=3 => (true, Imm::None, alu),
+            4 => (false, Imm::B, alu),
+            5 => (false, Imm::Z, alu),
+            _ => return None,
+        },
+        0x50..=0x57 => (false, Imm::None, "push"),
+        0x58..=0x5f => (false, Imm::None, "pop"),
+        0x63 => (true, Imm::None, "movsxd"),
+        0x68 => (false, Imm::Z, "push"),
+        0x69 => (true, Imm::Z, "imul"),
+        0x6a => (false, Imm::B, "push"),
+        0x6b => (true, Imm::B, "imul"),
+        0x6c | 0x6d => (false, Imm::None, "ins"),
+        0x6e | 0x6f => (false, Imm::None, "outs"),
+        0x70..=0x7f => (false, Imm::B, "jcc"),
+        0x80 | 0x83 => (true, Imm::B, "grp1"),
+        0x81 => (true, Imm::Z, "grp1"),
+        0x84 | 0x85 => (true, Imm::None, "test"),
+        0x86 | 0x87 => (true, Imm::None, "xchg"),
+        0x88..=0x8b => (true, Imm::None, "mov"),
+        0x8c | 0x8e => (true, Imm::None, "mov"),
+        0x8d => (true, Imm::None, "lea"),
+        0x8f => (true, Imm::None, "pop"),
+        0x90 => (false, Imm::None, "nop"),
+        0x91..=0x97 => (false, Imm::None, "xchg"),
+        0x98 => (false, Imm::None, "cbw"),
+        0x99 => (false, Imm::None, "cwd"),
+        0x9b => (false, Imm::None, "fwait"),
+        0x9c => (false, Imm::None, "pushf"),
+        0x9d => (false, Imm::None, "popf"),
+        0x9e => (false, Imm::None, "sahf"),
+        0x9f => (false, Imm::None, "lahf"),
+        0xa0..=0xa3 => (false, Imm::Moffs, "mov"),
+        0xa4 | 0xa5 => (false, Imm::None, "movs"),
+        0xa6 | 0xa7 => (false, Imm::None, "cmps"),
+        0xa8 => (false, Imm::B, "test"),
+        0xa9 => (false, Imm::Z, "test"),
+        0xaa | 0xab => (false, Imm::None, "stos"),
+        0xac | 0xad => (false, Imm::None, "lods"),
+        0xae | 0xaf => (false, Imm::None, "scas"),
+        0xb0..=0xb7 => (false, Imm::B, "mov"),
+        0xb8..=0xbf => (false, Imm::V, "mov"),
+        0xc0 | 0xc1 => (true, Imm::B, "shift"),
+        0xc2 => (false, Imm::W, "ret"),
+        0xc3 => (false, Imm::None, "ret"),
+        0xc6 | 0xc7 => (true, if op == 0xc6 { Imm::B } else { Imm::Z }, "mov"),
+        0xc8 => (false, Imm::Enter, "enter"),
+        0xc9 => (false, Imm::None, "leave"),
+        0xca => (false, Imm::W, "retf"),
+        0xcb => (false, Imm::None, "retf"),
+        0xcc => (false, Imm::None, "int3"),
+        0xcd => (false, Imm::B, "int"),
+        0xcf => (false, Imm::None, "iret"),
+        0xd0..=0xd3 => (true, Imm::None, "shift"),
+        0xd7 => (false, Imm::None, "xlat"),
+        0xd8..=0xdf => (true, Imm::None, "x87"),
+        0xe0..=0xe2 => (false, Imm::B, "loop"),
+        0xe3 => (false, Imm::B, "jrcxz"),
+        0xe4 | 0xe5 => (false, Imm::B, "in"),
+        0xe6 | 0xe7 => (false, Imm::B, "out"),
+        0xe8 => (false, Imm::Rel32, "call"),
+        0xe9 => (false, Imm::Rel32, "jmp"),
+        0xeb => (false, Imm::B, "jmp"),
+        0xec | 0xed => (false, Imm::None, "in"),
+        0xee | 0xef => (false, Imm::None, "out"),
+        0xf1 => (false, Imm::None, "int1"),
+        0xf4 => (false, Imm::None, "hlt"),
+        0xf5 => (false, Imm::None, "cmc"),
+        0xf6 | 0xf7 => (true, Imm::None, "grp3"), // immediate depends on reg
+        0xf8 => (false, Imm::None, "clc"),
+        0xf9 => (false, Imm::None, "stc"),
+        0xfa => (false, Imm::None, "cli"),
+        0xfb => (false, Imm::None, "sti"),
+        0xfc => (false, Imm::None, "cld"),
+        0xfd => (false, Imm::None, "std"),
+        0xfe | 0xff => (true, Imm::None, "grp5"),
+        _ => return None,
+    })
+}
+
+// Same for the 0F map.
+fn two_byte(op: u8) -> Option<(bool, Imm, &'static str)> {
+    Some(match op {
+        0x00 => (true, Imm::None, "grp6"),
+        0x01 => (true, Imm::None, "grp7"),
+        0x02 => (true, Imm::None, "lar"),
+        0x03 => (true, Imm::None, "lsl"),
+        0x05 => (false, Imm::None, "syscall"),
+        0x06 => (false, Imm::None, "clts"),
+        0x07 => (false, Imm::None, "sysret"),
+        0x08 => (false, Imm::None, "invd"),
+        0x09 => (false, Imm::None, "wbinvd"),
+        0x0b => (false, Imm::None, "ud2"),
+        0x0d => (true, Imm::None, "prefetch"),
+        0x18..=0x1e => (true, Imm::None, "hint"),
+        0x1f => (true, Imm::None, "nop"),
+        0x20 | 0x22 => (true, Imm::None, "mov cr"),
+        0x21 | 0x23 => (true, Imm::None, "mov dr"),
+        0x10..=0x17 | 0x28..=0x2f => (true, Imm::None, "sse"),
+        0x30 => (false, Imm::None, "wrmsr"),
+        0x31 => (false, Imm::None, "rdtsc"),
+        0x32 => (false, Imm::None, "rdmsr"),
+        0x33 => (false, Imm::None, "rdpmc"),
+        0x34 => (false, Imm::None, "sysenter"),
+        0x35 => (false, Imm::None, "sysexit"),
+        0x37 => (false, Imm::None, "getsec"),
+        0x40..=0x4f => (true, Imm::None, "cmov"),
+        0x50..=0x6f | 0x74..=0x76 | 0x78..=0x7f => (true, Imm::None, "sse"),
+        0x70..=0x73 => (true, Imm::B, "sse"),
+        0x77 => (false, Imm::None, "emms"),
+        0x80..=0x8f => (false, Imm::Rel32, "jcc"),
+        0x90..=0x9f => (true, Imm::None, "setcc"),
+        0xa0 | 0xa8 => (false, Imm::None, "push"),
+        0xa1 | 0xa9 => (false, Imm::None, "pop"),
+        0xa2 => (false, Imm::None, "cpuid"),
+        0xa3 | 0xab | 0xb3 | 0xbb => (true, Imm::None, "bt"),
+        0xa4 | 0xac => (true, Imm::B, "shd"),
+        0xa5 | 0xad => (true, Imm::None, "shd"),
+        0xaa => (false, Imm::None, "rsm"),
+        0xae => (true, Imm::None, "grp15"),
+        0xaf => (true, Imm::None, "imul"),
+        0xb0 | 0xb1 => (true, Imm::None, "cmpxchg"),
+        0xb2 | 0xb4 | 0xb5 => (true, Imm::None, "lseg"),
+        0xb6 | 0xb7 => (true, Imm::None, "movzx"),
+        0xb8 => (true, Imm::None, "popcnt"),
+        0xb9 => (true, Imm::None, "ud1"),
+        0xba => (true, Imm::B, "bt"),
+        0xbc => (true, Imm::None, "bsf"),
+        0xbd => (true, Imm::None, "bsr"),
+        0xbe | 0xbf => (true, Imm::None, "movsx"),
+        0xc0 | 0xc1 => (true, Imm::None, "xadd"),
+        0xc2 | 0xc4..=0xc6 => (true, Imm::B, "sse"),
+        0xc3 => (true, Imm::None, "movnti"),
+        0xc7 => (true, Imm::None, "grp9"),
+        0xc8..=0xcf => (false, Imm::None, "bswap"),
+        0xd0..=0xfe => (true, Imm::None, "sse"),
+        0xff => (true, Imm::None, "ud0"),
+        _ => return None,
+    })
+}
+
+fn imm_len(imm: Imm, opsize16: bool, addr32: bool, rex_w: bool) -> usize {
+    match imm {
+        Imm::None => 0,
+        Imm::B => 1,
+        Imm::W => 2,
+        Imm::Z if opsize16 => 2,
+        Imm::Z | Imm::Rel32 => 4,
+        Imm::V if rex_w => 8,
+        Imm::V if opsize16 => 2,
+        Imm::V => 4,
+        Imm::Enter => 3,
+        Imm::Moffs if addr32 => 4,
+        Imm::Moffs => 8,
+    }
+}
+
+// The rest of a VEX/EVEX instruction once its prefix has given the map.
+fn vex_body(c: &mut Cursor, map: Map) -> Result<(), DecodeError> {
+    let op = c.next()?;
+    modrm(c)?;
+    let imm8 = match map {
+        Map::Of3a => true,
+        Map::Of => matches!(op, 0x70..=0x73 | 0xc2 | 0xc4..=0xc6),
+        _ => false,
+    };
+    c.skip(imm8 as usize)
+}
+
+fn finish(len: usize, mnemonic: &'static str) -> Result<Insn, DecodeError> {
+    if len > MAX_LEN {
+        return Err(DecodeError::TooLong);
+    }
+    Ok(Insn { len, mnemonic })
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Decode the instruction at the start of `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Insn, DecodeError> {
+    let mut c = Cursor { b: bytes, i: 0 };
+    let (mut opsize16, mut addr32, mut rep) = (false, false, false);
+    while is_legacy_prefix(c.peek()?) {
+        match c.next()? {
+            0x66 => opsize16 = true,
+            0x67 => addr32 = true,
+            0xf3 => rep = true,
+            _ => {}
+        }
+    }
+    let mut rex_w = false;
+    if let 0x40..=0x4f = c.peek()? {
+        rex_w = c.next()? & 8 != 0;
+    }
+
+    let op = c.next()?;
+    let (has_modrm, mut imm, mut mnemonic, map) = match op {
+        0xc5 => {
+            // two-byte VEX: one payload byte, map 0F
+            c.skip(1)?;
+            vex_body(&mut c, Map::Of)?;
+            return finish(c.i, "vex");
+        }
+        0xc4 | 0x62 => {
+            let p0 = c.next()?;
+            let map = match p0 & if op == 0x62 { 0x07 } else { 0x1f } {
+                1 => Map::Of,
+                2 => Map::Of38,
+                3 => Map::Of3a,
+                5 | 6 if op == 0x62 => Map::Of38, // AVX512-FP16 maps: no immediate
+                _ => return Err(DecodeError::Invalid),
+            };
+            c.skip(if op == 0x62 { 2 } else { 1 })?;
+            vex_body(&mut c, map)?;
+            return finish(c.i, if op == 0x62 { "evex" } else { "vex" });
+        }
+        0x0f => match c.next()? {
+            0x38 => {
+                c.next()?;
+                (true, Imm::None, "sse", Map::Of38)
+            }
+            0x3a => {
+                c.next()?;
+                (true, Imm::B, "sse", Map::Of3a)
+            }
+            op2 => {
+                let (m, i, n) = two_byte(op2).ok_or(DecodeError::Invalid)?;
+                (m, i, n, Map::Of)
+            }
+        },
+        _ => {
+            let (m, i, n) = one_byte(op).ok_or(DecodeError::Invalid)?;
+            (m, i, n, Map::One)
+        }
+    };
+
+    if has_modrm {
+        let reg = modrm(&mut c)? as usize;
+        if map == Map::One {
+            match op {
+                0x80..=0x83 => mnemonic = ALU[reg],
+                0xc0 | 0xc1 | 0xd0..=0xd3 => mnemonic = SHIFT[reg],
+                0xf6 | 0xf7 => {
+                    mnemonic = GRP3[reg];
+                    if reg < 2 {
+                        imm = if op == 0xf6 { Imm::B } else { Imm::Z };
+                    }
+                }
+                0xfe | 0xff => mnemonic = GRP5[reg],
+                _ => {}
+            }
+        }
+    } else if map == Map::One && op == 0x90 && rep {
+        mnemonic = "pause";
+    }
+    c.skip(imm_len(imm, opsize16, addr32, rex_w))?;
+    finish(c.i, mnemonic)
+}
@@ -0,0 +1,100 @@
+// src/debug/irqalloc.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Heap use from interrupt context. The heap lock is taken with IRQs on, so
+// an interrupt handler that allocates (or frees) on a CPU whose interrupted
+// code holds it spins forever. Handlers mark themselves with `irq_context`;
+// the global allocator calls `check` on every allocation and free. With
+// `irqalloc` on the command line each hit is counted (`mem.irq_allocs`) and
+// the first few are logged; with `irqalloc=panic` the first one panics, so
+// the backtrace shows the offending path. Off by default: `check` is then
+// one relaxed load.
+
+use core::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+
+use crate::arch::native::apic::{MAX_CPUS, cpu_index, lapic_id};
+use crate::{cmdline, early_println};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const LOG_LIMIT: u32 = 16;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Off,
+    Log,
+    Panic,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Off as u8);
+static DEPTH: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(0) }; MAX_CPUS];
+static LOGGED: AtomicU32 = AtomicU32::new(0);
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn init() {
+    match cmdline::value("irqalloc") {
+        Some("panic") => set_mode(Mode::Panic),
+        _ if cmdline::flag("irqalloc") => set_mode(Mode::Log),
+        _ => {}
+    }
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => Mode::Log,
+        2 => Mode::Panic,
+        _ => Mode::Off,
+    }
+}
+
+pub fn set_mode(m: Mode) {
+    MODE.store(m as u8, Ordering::Relaxed);
+}
+
+/// Run an interrupt handler's body. Nests (an exception inside a handler).
+#[inline]
+pub fn irq_context<R>(f: impl FnOnce() -> R) -> R {
+    let d = DEPTH.get(cpu_index());
+    if let Some(d) = d {
+        d.fetch_add(1, Ordering::Relaxed);
+    }
+    let r = f();
+    if let Some(d) = d {
+        d.fetch_sub(1, Ordering::Relaxed);
+    }
+    r
+}
+
+/// Is this CPU inside an interrupt handler?
+pub fn in_irq() -> bool {
+    DEPTH
+        .get(cpu_index())
+        .is_some_and(|d| d.load(Ordering::Relaxed) != 0)
+}
+
+/// Called by the global allocator before it takes the heap lock.
+#[inline]
+pub fn check(op: &str, size: usize) {
+    if MODE.load(Ordering::Relaxed) == Mode::Off as u8 || !in_irq() {
+        return;
+    }
+    crate::counter!("mem.irq_allocs");
+    // Heap-free from here on: we may be about to deadlock on it.
+    match mode() {
+        Mode::Panic => {
+            // The panic path may allocate too.
+            set_mode(Mode::Off);
+            panic!("{} of {} bytes in IRQ context", op, size)
+        }
+        _ if LOGGED.fetch_add(1, Ordering::Relaxed) < LOG_LIMIT => {
+            early_println!(
+                "[irqalloc] cpu {}: {} of {} bytes in IRQ context",
+                lapic_id(),
+                op,
+                size
+            );
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,197 @@
+// src/debug/latency.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Interrupts-off latency tracer. `without_interrupts` here is a drop-in for
+// the x86_64 crate's: when tracing is on, it times the outermost IRQ-off
+// region and remembers, per CPU, the longest one and the call site that
+// opened it. Regions over THRESHOLD_US are also kept in a small table of
+// offenders, worst first. Interrupt handlers, which run with IRQs off from
+// the start, time themselves with `irq_section`. The kernel has no
+// preempt_disable: preemption only happens on the tick, so IRQ-off is
+// preempt-off.
+//
+// Off by default; `latency` on the command line or the `latency on` monitor
+// command turns it on. Only the hot lock wrappers (run queue, page tables)
+// and the timer interrupt go through here; other callers are not traced.
+
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+
+use heapless::Vec;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+
+use crate::arch::native::apic::{MAX_CPUS, cpu_index};
+use crate::arch::native::tsc;
+use crate::cmdline;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_OFFENDERS: usize = 16;
+pub const THRESHOLD_US: u64 = 100;
+
+type Site = &'static Location<'static>;
+
+struct CpuWorst {
+    cycles: AtomicU64,
+    site: AtomicPtr<Location<'static>>,
+    sections: AtomicU64,
+}
+
+/// The longest IRQ-off region seen on one CPU.
+#[derive(Copy, Clone, Debug)]
+pub struct Worst {
+    pub apic_id: u32,
+    pub us: u64,
+    pub site: Option<Site>,
+    pub sections: u64,
+}
+
+/// A call site that held IRQs off past the threshold.
+#[derive(Copy, Clone, Debug)]
+pub struct Offender {
+    pub site: Site,
+    pub max_us: u64,
+    pub hits: u64,
+}
+
+static CPUS: [CpuWorst; MAX_CPUS] = [const {
+    CpuWorst {
+        cycles: AtomicU64::new(0),
+        site: AtomicPtr::new(core::ptr::null_mut()),
+        sections: AtomicU64::new(0),
+    }
+}; MAX_CPUS];
+
+static OFFENDERS: Mutex<Vec<Offender, MAX_OFFENDERS>> = Mutex::new(Vec::new());
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HZ: Once<u64> = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// CPUID is slow (and exits under a hypervisor): estimate once.
+fn us(cycles: u64) -> u64 {
+    let hz = *HZ.call_once(|| tsc::tsc_hz_estimate().max(1));
+    (cycles as u128 * 1_000_000 / hz as u128) as u64
+}
+
+// Called with IRQs still off, at the end of a region.
+fn record(site: Site, cycles: u64) {
+    let Some(c) = CPUS.get(cpu_index()) else {
+        return;
+    };
+    c.sections.fetch_add(1, Ordering::Relaxed);
+    if cycles > c.cycles.fetch_max(cycles, Ordering::Relaxed) {
+        c.site.store(site as *const _ as *mut _, Ordering::Relaxed);
+    }
+    let t = us(cycles);
+    if t < THRESHOLD_US {
+        return;
+    }
+    crate::counter!("latency.over_threshold");
+    // Never wait here: another CPU may be updating the table.
+    let Some(mut tab) = OFFENDERS.try_lock() else {
+        return;
+    };
+    if let Some(o) = tab.iter_mut().find(|o| core::ptr::eq(o.site, site)) {
+        o.max_us = o.max_us.max(t);
+        o.hits += 1;
+    } else {
+        let o = Offender {
+            site,
+            max_us: t,
+            hits: 1,
+        };
+        if tab.push(o).is_err() {
+            // Full: replace the mildest if this one is worse.
+            let (i, m) = tab
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, o)| o.max_us)
+                .unwrap();
+            if m.max_us < t {
+                tab[i] = o;
+            }
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn init() {
+    if cmdline::flag("latency") {
+        set_enabled(true);
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+/// Like `x86_64::instructions::interrupts::without_interrupts`, timing the
+/// region when it is the one that turned IRQs off.
+#[track_caller]
+#[inline]
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    if !enabled() || !interrupts::are_enabled() {
+        return interrupts::without_interrupts(f);
+    }
+    let site = Location::caller();
+    interrupts::without_interrupts(|| {
+        let t0 = tsc::rdtsc();
+        let r = f();
+        record(site, tsc::rdtsc() - t0);
+        r
+    })
+}
+
+/// Time a region that already runs with IRQs off, e.g. an interrupt handler.
+#[track_caller]
+#[inline]
+pub fn irq_section<R>(f: impl FnOnce() -> R) -> R {
+    if !enabled() {
+        return f();
+    }
+    let site = Location::caller();
+    let t0 = tsc::rdtsc();
+    let r = f();
+    record(site, tsc::rdtsc() - t0);
+    r
+}
+
+/// Each traced CPU's longest region.
+pub fn for_each_cpu(mut f: impl FnMut(Worst)) {
+    for (id, c) in CPUS.iter().enumerate() {
+        let sections = c.sections.load(Ordering::Relaxed);
+        if sections == 0 {
+            continue;
+        }
+        let site = c.site.load(Ordering::Relaxed);
+        f(Worst {
+            apic_id: id as u32,
+            us: us(c.cycles.load(Ordering::Relaxed)),
+            site: unsafe { site.as_ref() },
+            sections,
+        });
+    }
+}
+
+/// Offenders past THRESHOLD_US, worst first.
+pub fn offenders() -> Vec<Offender, MAX_OFFENDERS> {
+    let mut v = interrupts::without_interrupts(|| OFFENDERS.lock().clone());
+    v.sort_unstable_by_key(|o| core::cmp::Reverse(o.max_us));
+    v
+}
+
+pub fn reset() {
+    for c in CPUS.iter() {
+        c.cycles.store(0, Ordering::Relaxed);
+        c.site.store(core::ptr::null_mut(), Ordering::Relaxed);
+        c.sections.store(0, Ordering::Relaxed);
+    }
+    interrupts::without_interrupts(|| OFFENDERS.lock().clear());
+}
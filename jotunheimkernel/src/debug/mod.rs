@@ -4,8 +4,20 @@
 #![allow(clippy::identity_op)]
 
 use spin::Mutex;
+use x86_64::registers::debug::{Dr6, Dr6Flags};
 
 pub mod breakpoint;
+pub mod crash;
+pub mod faults;
+pub mod histo;
+pub mod insn;
+pub mod irqalloc;
+pub mod latency;
+pub mod pstore;
+pub mod replay;
+pub mod shell;
+pub mod trace;
+pub mod watch;
 
 pub use crate::arch::native::context::TrapFrame;
 use crate::kprintln;
@@ -28,6 +40,14 @@ pub fn set_tf(tf: &mut TrapFrame) {
     tf.rflags |= 1 << 8;
 }
 
+/// Acknowledge the #DB causes in `bits`; the CPU never clears DR6 itself.
+pub fn clear_dr6(bits: Dr6Flags) {
+    let v = Dr6::read_raw() & !bits.bits();
+    unsafe {
+        core::arch::asm!("mov dr6, {}", in(reg) v, options(nomem, nostack, preserves_flags));
+    }
+}
+
 pub fn setup() {
     if cfg!(debug_assertions) {
         kprintln!("[JOTUNHEIM] Waiting a debugger.");
@@ -49,7 +69,8 @@ pub mod rsp {
     use crate::debug::rsp::arch_x86_64::X86_64Core;
     use crate::debug::rsp::core::RspServer;
     use crate::debug::rsp::memory::SectionMemory;
-    use crate::debug::rsp::transport::Com2Transport;
+    use crate::debug::rsp::transport::{Com2Transport, VirtioTransport};
+    use crate::virtio::console::{DEBUG_PORT, has_port};
 
     pub fn serve(tf: *mut TrapFrame) -> Outcome {
         {
@@ -60,11 +81,14 @@ pub mod rsp {
             *active = true;
         }
 
-        let t = Com2Transport;
         let a = X86_64Core;
         let m = SectionMemory;
 
-        let out = RspServer::run(t, a, m, tf);
+        let out = if has_port(DEBUG_PORT) {
+            RspServer::run(VirtioTransport, a, m, tf)
+        } else {
+            RspServer::run(Com2Transport, a, m, tf)
+        };
 
         *ACTIVE.lock() = false;
         out
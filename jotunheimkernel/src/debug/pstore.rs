@@ -0,0 +1,68 @@
+// src/debug/pstore.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Crash log in persistent memory. With `pstore` on the command line the
+// panic handler also writes its crash record (the same `JOTUN1:` text as the
+// panic screen's QR code) to the area `mem::pmem` keeps at the end of pmem0,
+// and the next boot prints it once and clears it.
+//
+// Layout: magic u32 "JPS1", text length u32, then the text. The text is
+// committed before the header, so a reset part-way through leaves no magic
+// rather than a torn record. Saving is lock-free and heap-free.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::debug::crash::{CrashRecord, TEXT_MAX};
+use crate::kprintln;
+use crate::mem::pmem;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAGIC: &[u8; 4] = b"JPS1";
+const HDR_LEN: usize = 8;
+
+static SAVED: AtomicBool = AtomicBool::new(false);
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Store `rec` for the next boot. Only the first panic is kept; without a
+/// pstore area this does nothing.
+pub fn save(rec: &CrashRecord) {
+    let Some(area) = pmem::pstore_area() else {
+        return;
+    };
+    if SAVED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let mut text = [0u8; TEXT_MAX];
+    let len = rec.to_text(&mut text);
+    let mut hdr = [0u8; HDR_LEN];
+    hdr[..4].copy_from_slice(MAGIC);
+    hdr[4..].copy_from_slice(&(len as u32).to_le_bytes());
+    let _ = area
+        .write_commit(HDR_LEN as u64, &text[..len])
+        .and_then(|_| area.write_commit(0, &hdr));
+}
+
+/// Report the record a previous boot left, then clear it. Runs after `pmem`.
+pub fn init() {
+    let Some(area) = pmem::pstore_area() else {
+        return;
+    };
+    let mut hdr = [0u8; HDR_LEN];
+    if area.read(0, &mut hdr).is_err() || &hdr[..4] != MAGIC {
+        kprintln!("[pstore] no saved crash");
+        return;
+    }
+    let len = u32::from_le_bytes(hdr[4..].try_into().unwrap()) as usize;
+    let mut text = [0u8; TEXT_MAX];
+    if len <= TEXT_MAX && area.read(HDR_LEN as u64, &mut text[..len]).is_ok() {
+        match core::str::from_utf8(&text[..len]) {
+            Ok(s) => kprintln!("[pstore] previous boot crashed: {}", s),
+            Err(_) => kprintln!("[pstore] saved crash is corrupt"),
+        }
+    } else {
+        kprintln!("[pstore] saved crash is corrupt ({} bytes)", len);
+    }
+    let _ = area.write_commit(0, &[0u8; HDR_LEN]);
+}
@@ -0,0 +1,141 @@
+// src/debug/replay.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Hooks for a host-side QEMU harness. Interesting points (boot, panic,
+// scheduler anomalies) are announced as one-line markers on the debugcon
+// port (`-debugcon chardev:...`, iobase 0xE9); the harness watches for them
+// and takes a `savevm` snapshot or records the icount for record/replay.
+// `replay` on the command line marks a replay run: anything that would seed
+// itself from the TSC asks `seed()` instead and gets a fixed value.
+// `checkpoint <n>` in the debug shell emits a marker on demand.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use super::shell;
+use crate::arch::x86_64::tsc;
+use crate::{cmdline, kprintln, sched};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DEBUGCON: u16 = 0xE9;
+const DEFAULT_SEED: u64 = 0x4A6F_7475_6E68_6569; // "Jotunhei"
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Marker {
+    Boot,
+    Panic,
+    DeadlineMiss, // arg = task id
+    Checkpoint,   // arg = caller-chosen
+}
+
+impl Marker {
+    fn name(self) -> &'static str {
+        match self {
+            Marker::Boot => "boot",
+            Marker::Panic => "panic",
+            Marker::DeadlineMiss => "deadline-miss",
+            Marker::Checkpoint => "checkpoint",
+        }
+    }
+}
+
+// 0 = not probed, 1 = absent, 2 = present
+static PRESENT: AtomicU8 = AtomicU8::new(0);
+static REPLAY: AtomicBool = AtomicBool::new(false);
+// Keeps markers from two CPUs on separate lines.
+static LINE: Mutex<()> = Mutex::new(());
+
+/* --------------------------------- Debugcon --------------------------------- */
+
+struct Debugcon;
+
+impl Write for Debugcon {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut p = Port::<u8>::new(DEBUGCON);
+        for b in s.bytes() {
+            unsafe { p.write(b) };
+        }
+        Ok(())
+    }
+}
+
+// QEMU's isa-debugcon reads back its own iobase; an empty port floats to 0xFF.
+fn present() -> bool {
+    match PRESENT.load(Ordering::Relaxed) {
+        0 => {
+            let v = unsafe { Port::<u8>::new(DEBUGCON).read() } == DEBUGCON as u8;
+            PRESENT.store(1 + v as u8, Ordering::Relaxed);
+            v
+        }
+        s => s == 2,
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Read `replay` / `replay_seed=` from the command line and announce boot.
+pub fn init() {
+    REPLAY.store(cmdline::flag("replay"), Ordering::Relaxed);
+    if deterministic() {
+        kprintln!("[replay] deterministic mode, seed {:#x}", seed());
+    }
+    mark(Marker::Boot, 0);
+    shell::register(
+        "checkpoint",
+        "<n>: mark a replay checkpoint",
+        cmd_checkpoint,
+    );
+}
+
+pub fn deterministic() -> bool {
+    REPLAY.load(Ordering::Relaxed)
+}
+
+/// Seed for anything that wants entropy: the TSC normally, a fixed value
+/// (`replay_seed=` or a built-in default) in replay mode.
+pub fn seed() -> u64 {
+    if deterministic() {
+        return cmdline::value("replay_seed")
+            .and_then(|v| u64::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+            .unwrap_or(DEFAULT_SEED);
+    }
+    tsc::rdtsc()
+}
+
+/// Emit `@@jotun <marker> arg=<arg> tick=<tick>` on debugcon. Never blocks
+/// for long: a panicking CPU may have been interrupted mid-line.
+pub fn mark(m: Marker, arg: u64) {
+    if !present() {
+        return;
+    }
+    let mut guard = None;
+    for _ in 0..100_000 {
+        guard = LINE.try_lock();
+        if guard.is_some() {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    let _ = writeln!(
+        Debugcon,
+        "@@jotun {} arg={:#x} tick={}",
+        m.name(),
+        arg,
+        sched::ticks()
+    );
+    drop(guard);
+}
+
+fn cmd_checkpoint(args: &str, out: &mut dyn Write) -> fmt::Result {
+    match args.trim().parse::<u64>() {
+        Ok(n) => {
+            mark(Marker::Checkpoint, n);
+            Ok(())
+        }
+        Err(_) => writeln!(out, "checkpoint: expected a number"),
+    }
+}
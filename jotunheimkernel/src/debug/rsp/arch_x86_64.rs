@@ -205,3 +205,77 @@ pub unsafe fn read_g(tf: *mut TrapFrame, payload: &[u8]) -> bool {
 
     true
 }
+
+/// Size in bytes of register `n` (gdb numbering) in the `g` layout above;
+/// None past the end.
+pub fn reg_size(n: usize) -> Option<usize> {
+    match n {
+        0..=16 => Some(8),   // GPRs, rip
+        17..=23 => Some(4),  // eflags, cs/ss/ds/es/fs/gs
+        24..=31 => Some(10), // st0..st7
+        32..=35 | 37 | 39 => Some(2),
+        36 | 38 => Some(4),
+        40 | 41 => Some(8), // fs_base, gs_base
+        _ => None,
+    }
+}
+
+fn gpr(t: &mut TrapFrame, n: usize) -> Option<&mut u64> {
+    Some(match n {
+        0 => &mut t.rax,
+        1 => &mut t.rbx,
+        2 => &mut t.rcx,
+        3 => &mut t.rdx,
+        4 => &mut t.rsi,
+        5 => &mut t.rdi,
+        6 => &mut t.rbp,
+        7 => &mut t.rsp,
+        8 => &mut t.r8,
+        9 => &mut t.r9,
+        10 => &mut t.r10,
+        11 => &mut t.r11,
+        12 => &mut t.r12,
+        13 => &mut t.r13,
+        14 => &mut t.r14,
+        15 => &mut t.r15,
+        16 => &mut t.rip,
+        _ => return None,
+    })
+}
+
+/// Register `n` as little-endian bytes in `out`; returns its size. Reads
+/// back as `g` does: zero for what the trap frame does not hold.
+pub fn read_reg(t: &mut TrapFrame, n: usize, out: &mut [u8; 10]) -> Option<usize> {
+    let size = reg_size(n)?;
+    let v = match n {
+        17 => t.rflags & 0xFFFF_FFFF,
+        18 => t.cs,
+        19 => t.ss,
+        _ => gpr(t, n).map_or(0, |r| *r),
+    };
+    *out = [0; 10];
+    out[..8].copy_from_slice(&v.to_le_bytes());
+    Some(size)
+}
+
+/// Set register `n` from its little-endian bytes; false if `bytes` is not
+/// its size. As with `G`, writes to registers the trap frame does not
+/// restore (segments, x87, fs/gs base) are accepted and dropped.
+pub fn write_reg(t: &mut TrapFrame, n: usize, bytes: &[u8]) -> bool {
+    if reg_size(n) != Some(bytes.len()) {
+        return false;
+    }
+    let mut le = [0u8; 8];
+    let k = bytes.len().min(8);
+    le[..k].copy_from_slice(&bytes[..k]);
+    let v = u64::from_le_bytes(le);
+    match n {
+        17 => t.rflags = (t.rflags & !0xFFFF_FFFF) | v,
+        _ => {
+            if let Some(r) = gpr(t, n) {
+                *r = v;
+            }
+        }
+    }
+    true
+}
@@ -10,7 +10,11 @@ use super::arch_x86_64 as arch;
 use super::memory::Memory;
 use super::transport::Transport;
 
-use crate::debug::{BKPT, Outcome, TrapFrame, breakpoint, clear_tf, set_tf};
+use crate::arch::x86_64::tickwatch;
+use crate::debug::{BKPT, Outcome, TrapFrame, breakpoint, clear_tf, latency, set_tf, shell};
+use crate::mem::aspace::AddressSpace;
+use crate::mem::{hotplug, ptcheck, vmmap};
+use crate::{config, irq, kobject, logring, stats};
 
 // ─────────────────────────── Buffers (all in .bss) ───────────────────────────
 
@@ -72,6 +76,27 @@ fn parse_addr_len(off: usize, total: usize) -> Option<(usize, usize, usize)> {
     Some((addr, len, ua + 1 + ul))
 }
 
+/// Undo binary escaping (`}` followed by the byte XOR 0x20) in
+/// INBUF[off..total], in place. Returns the decoded length; None if the
+/// data ends inside an escape.
+fn unescape(off: usize, total: usize) -> Option<usize> {
+    let (mut r, mut w) = (off, off);
+    while r < total {
+        let mut b = unsafe { INBUF[r] };
+        if b == b'}' {
+            r += 1;
+            if r >= total {
+                return None;
+            }
+            b = unsafe { INBUF[r] } ^ 0x20;
+        }
+        unsafe { INBUF[w] = b };
+        r += 1;
+        w += 1;
+    }
+    Some(w - off)
+}
+
 fn starts_with(off: usize, total: usize, pat: &[u8]) -> bool {
     if pat.len() > total.saturating_sub(off) {
         return false;
@@ -100,6 +125,363 @@ fn send_pkt<T: Transport>(tx: &T, payload: &[u8]) {
     tx.putc(hex4(cks & 0xF));
 }
 
+/// `lead` (`m` or `l`) then `data` with binary escaping, as qXfer replies
+/// are sent.
+fn send_binary<T: Transport>(tx: &T, lead: u8, data: &[u8]) {
+    tx.putc(b'$');
+    let mut cks: u8 = 0;
+    let mut put = |c: u8| {
+        tx.putc(c);
+        cks = cks.wrapping_add(c);
+    };
+    put(lead);
+    for &b in data {
+        if matches!(b, b'#' | b'$' | b'}' | b'*') {
+            put(b'}');
+            put(b ^ 0x20);
+        } else {
+            put(b);
+        }
+    }
+    tx.putc(b'#');
+    tx.putc(hex4((cks >> 4) & 0xF));
+    tx.putc(hex4(cks & 0xF));
+}
+
+// ─────────────────────────── Auxiliary objects ───────────────────────────────
+
+// `qXfer:jotunheim-log:read::OFF,LEN`: where the in-memory log is, in the
+// shape of an auxv (u64 tag/value pairs, little endian, ending with tag 0),
+// so a debugger can find and dump it without symbols.
+const LOG_AT_HEADER: u64 = 1;
+const LOG_AT_DATA: u64 = 2;
+const LOG_AT_SIZE: u64 = 3;
+
+fn xfer_log<T: Transport>(tx: &T, len: usize) {
+    let off = b"qXfer:jotunheim-log:read::".len();
+    let Some((at, want, used)) = parse_addr_len(off, len) else {
+        send_pkt(tx, b"E00");
+        return;
+    };
+    if off + used != len {
+        send_pkt(tx, b"E00");
+        return;
+    }
+    let mut obj = [0u8; 64];
+    let pairs = [
+        (LOG_AT_HEADER, logring::header_addr()),
+        (LOG_AT_DATA, logring::data_addr()),
+        (LOG_AT_SIZE, logring::SIZE as u64),
+        (0, 0),
+    ];
+    for (i, (tag, val)) in pairs.into_iter().enumerate() {
+        obj[16 * i..16 * i + 8].copy_from_slice(&tag.to_le_bytes());
+        obj[16 * i + 8..16 * i + 16].copy_from_slice(&val.to_le_bytes());
+    }
+    let start = at.min(obj.len());
+    let end = start + want.min(obj.len() - start);
+    let lead = if end < obj.len() { b'm' } else { b'l' };
+    send_binary(tx, lead, &obj[start..end]);
+}
+
+// ─────────────────────────── Monitor commands ────────────────────────────────
+
+const MONITOR_LINE: usize = 96;
+const PTCHECK_LINES: usize = 64; // violations listed by `ptcheck`
+
+/// Text for gdb's console, as an `O<hex>` packet.
+fn send_console<T: Transport>(tx: &T, text: &[u8]) {
+    let mut buf = [0u8; 1 + 2 * MONITOR_LINE];
+    buf[0] = b'O';
+    let text = &text[..text.len().min(MONITOR_LINE)];
+    for (i, &b) in text.iter().enumerate() {
+        buf[1 + 2 * i] = hex4(b >> 4);
+        buf[2 + 2 * i] = hex4(b & 0xF);
+    }
+    send_pkt(tx, &buf[..1 + 2 * text.len()]);
+}
+
+/// Collects text into lines for `send_console`, for debug-shell commands.
+struct ConsoleWriter<'a, T: Transport> {
+    tx: &'a T,
+    line: heapless::String<MONITOR_LINE>,
+}
+
+impl<T: Transport> ConsoleWriter<'_, T> {
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            send_console(self.tx, self.line.as_bytes());
+            self.line.clear();
+        }
+    }
+}
+
+impl<T: Transport> core::fmt::Write for ConsoleWriter<'_, T> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.line.push(c).is_err() {
+                self.flush();
+                let _ = self.line.push(c);
+            }
+            if c == '\n' {
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `qRcmd,<hex>` (gdb `monitor <cmd>`): output goes back as `O` packets,
+/// then `OK`. Commands not handled here go to the debug shell; ones it does
+/// not know either get the empty reply.
+fn monitor<T: Transport>(tx: &T, len: usize) {
+    use core::fmt::Write;
+
+    let mut cmd = [0u8; 64];
+    let mut n = 0;
+    let mut i = b"qRcmd,".len();
+    while i + 1 < len && n < cmd.len() {
+        let (hi, lo) = unsafe { (from_hex(INBUF[i]), from_hex(INBUF[i + 1])) };
+        let (Some(hi), Some(lo)) = (hi, lo) else {
+            break;
+        };
+        cmd[n] = hi << 4 | lo;
+        n += 1;
+        i += 2;
+    }
+    match &cmd[..n] {
+        b"counters" => {
+            stats::for_each(|name, v| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(line, "{} {}", name, v);
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        b"counters reset" => {
+            stats::reset_all();
+            send_pkt(tx, b"OK");
+        }
+        b"ticks" => {
+            tickwatch::for_each(|s| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(
+                    line,
+                    "cpu {} ticks={} lost={} bursts={} last={}us ago{}",
+                    s.apic_id,
+                    s.ticks,
+                    s.lost,
+                    s.bursts,
+                    s.idle_us,
+                    if s.stalled { " STALLED" } else { "" }
+                );
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        b"latency" => {
+            latency::for_each_cpu(|w| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = write!(line, "cpu {} worst {}us ", w.apic_id, w.us);
+                match w.site {
+                    Some(s) => {
+                        let _ = writeln!(
+                            line,
+                            "at {}:{} ({} sections)",
+                            s.file(),
+                            s.line(),
+                            w.sections
+                        );
+                    }
+                    None => {
+                        let _ = writeln!(line, "({} sections)", w.sections);
+                    }
+                }
+                send_console(tx, line.as_bytes());
+            });
+            for o in latency::offenders() {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(
+                    line,
+                    "{}us max x{} {}:{}",
+                    o.max_us,
+                    o.hits,
+                    o.site.file(),
+                    o.site.line()
+                );
+                send_console(tx, line.as_bytes());
+            }
+            send_pkt(tx, b"OK");
+        }
+        b"latency on" | b"latency off" => {
+            latency::set_enabled(cmd[..n].ends_with(b"on"));
+            send_pkt(tx, b"OK");
+        }
+        b"latency reset" => {
+            latency::reset();
+            send_pkt(tx, b"OK");
+        }
+        // `vmmap` or `vmmap <owner>`, e.g. `vmmap heap`
+        c if c == b"vmmap" || c.starts_with(b"vmmap ") => {
+            let only = c.get(b"vmmap ".len()..).unwrap_or(b"");
+            vmmap::regions(|r| {
+                if !only.is_empty() && r.owner.as_bytes() != only {
+                    return;
+                }
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(line, "{}", r);
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        b"ptcheck" => {
+            let mut shown = 0;
+            let rep = ptcheck::check(|v| {
+                if shown < PTCHECK_LINES {
+                    let mut line = heapless::String::<MONITOR_LINE>::new();
+                    let _ = writeln!(line, "{}", v);
+                    send_console(tx, line.as_bytes());
+                    shown += 1;
+                }
+            });
+            let mut line = heapless::String::<MONITOR_LINE>::new();
+            let _ = writeln!(line, "{} leaves, {} violations", rep.leaves, rep.total());
+            send_console(tx, line.as_bytes());
+            send_pkt(tx, b"OK");
+        }
+        b"irq" => {
+            irq::for_each(|i| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = write!(
+                    line,
+                    "gsi {} vec {:#x} {} cpu {} mask {:#x}{} count {}",
+                    i.gsi,
+                    i.vector,
+                    i.name,
+                    i.target,
+                    i.affinity,
+                    if i.pinned { " pinned" } else { "" },
+                    i.total
+                );
+                if let Some(t) = i.thread {
+                    let _ = write!(line, " thread {}", t);
+                }
+                let _ = writeln!(line);
+                send_console(tx, line.as_bytes());
+            });
+            irq::poll::for_each(|p| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(
+                    line,
+                    "poll {}{} irqs {} passes {} done {}",
+                    p.name,
+                    if p.hw_moderation { " (hw)" } else { "" },
+                    p.interrupts,
+                    p.passes,
+                    p.completions
+                );
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        // `irq pin <gsi> <cpu>` / `irq unpin <gsi>`
+        c if c.starts_with(b"irq ") => {
+            let mut args = core::str::from_utf8(&c[b"irq ".len()..])
+                .unwrap_or("")
+                .split_ascii_whitespace();
+            let (op, gsi, cpu) = (args.next(), args.next(), args.next());
+            let gsi = gsi.and_then(|g| g.parse().ok());
+            let cpu = cpu.and_then(|c| c.parse().ok());
+            let r = match (op, gsi, cpu) {
+                (Some("pin"), Some(g), Some(c)) => irq::pin(g, c).is_ok(),
+                (Some("unpin"), Some(g), None) => irq::unpin(g).is_ok(),
+                _ => false,
+            };
+            send_pkt(tx, if r { b"OK" } else { b"E01" });
+        }
+        b"kobj" => {
+            kobject::for_each(|o| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(
+                    line,
+                    "{:1$}{2} #{3} refs {4} {5}",
+                    "",
+                    2 * o.depth,
+                    o.name,
+                    o.id,
+                    o.refs,
+                    o.kind
+                );
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        b"config" => {
+            config::for_each(|sub, key, v| {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(line, "{}.{}={}", sub, key, v);
+                send_console(tx, line.as_bytes());
+            });
+            send_pkt(tx, b"OK");
+        }
+        // `config <subsystem>.<key>` reads one value, `...=<value>` sets it
+        c if c.starts_with(b"config ") => {
+            let arg = core::str::from_utf8(&c[b"config ".len()..]).unwrap_or("");
+            let (name, value) = match arg.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (arg.trim(), None),
+            };
+            let reply: &[u8] = match (name.split_once('.'), value) {
+                (Some((sub, key)), Some(v)) if config::set(sub, key, v).is_ok() => b"OK",
+                (Some((sub, key)), None) => match config::get(sub, key) {
+                    Some(v) => {
+                        let mut line = heapless::String::<MONITOR_LINE>::new();
+                        let _ = writeln!(line, "{}.{}={}", sub, key, v);
+                        send_console(tx, line.as_bytes());
+                        b"OK"
+                    }
+                    None => b"E01",
+                },
+                _ => b"E01",
+            };
+            send_pkt(tx, reply);
+        }
+        // `memadd <pa> <len>`, both hex: hot-add a RAM range
+        c if c.starts_with(b"memadd ") => {
+            let mut args = core::str::from_utf8(&c[b"memadd ".len()..])
+                .unwrap_or("")
+                .split_ascii_whitespace()
+                .map(|a| u64::from_str_radix(a.trim_start_matches("0x"), 16).ok());
+            let r = match (args.next(), args.next(), args.next()) {
+                (Some(Some(pa)), Some(Some(len)), None) => {
+                    hotplug::add(pa, len, hotplug::Source::Monitor)
+                }
+                _ => Err(hotplug::HotplugError::Empty),
+            };
+            if let Err(e) = r {
+                let mut line = heapless::String::<MONITOR_LINE>::new();
+                let _ = writeln!(line, "memadd: {:?}", e);
+                send_console(tx, line.as_bytes());
+            }
+            send_pkt(tx, if r.is_ok() { b"OK" } else { b"E01" });
+        }
+        c => {
+            let mut out = ConsoleWriter {
+                tx,
+                line: heapless::String::new(),
+            };
+            let r = shell::run(core::str::from_utf8(c).unwrap_or(""), &mut out);
+            out.flush();
+            let reply: &[u8] = match r {
+                Some(Ok(())) => b"OK",
+                Some(Err(_)) => b"E01",
+                None => b"",
+            };
+            send_pkt(tx, reply);
+        }
+    }
+}
+
 unsafe fn send_pkt_raw<T: Transport>(tx: &T, ptr: *const u8, len: usize) {
     tx.putc(b'$');
     let mut cks: u8 = 0;
@@ -215,7 +597,11 @@ impl RspServer {
                 b'q' => {
                     if starts_with(0, len, b"qSupported") {
                         // PacketSize is HEX per RSP (no 0x prefix). Keep features minimal.
-                        send_pkt(&tx, b"PacketSize=4000;QStartNoAckMode+");
+                        // No larger than INBUF: gdb fills `X` packets up to it.
+                        send_pkt(
+                            &tx,
+                            b"PacketSize=2000;QStartNoAckMode+;qXfer:jotunheim-log:read+",
+                        );
                     } else if starts_with(0, len, b"qAttached") {
                         send_pkt(&tx, b"1"); // attached to a live target
                     } else if starts_with(0, len, b"qfThreadInfo") {
@@ -224,6 +610,10 @@ impl RspServer {
                         send_pkt(&tx, b"l"); // end of list
                     } else if starts_with(0, len, b"qC") {
                         send_pkt(&tx, b"QC1"); // current thread id
+                    } else if starts_with(0, len, b"qXfer:jotunheim-log:read::") {
+                        xfer_log(&tx, len);
+                    } else if starts_with(0, len, b"qRcmd,") {
+                        monitor(&tx, len);
                     } else if starts_with(0, len, b"qTStatus") {
                         send_pkt(&tx, b""); // not tracing
                     } else if starts_with(0, len, b"vCont?") {
@@ -270,6 +660,59 @@ impl RspServer {
                     send_pkt(&tx, if ok { b"OK" } else { b"E00" });
                 }
 
+                // Read one register: pN
+                b'p' => match parse_hex_usize(1, len) {
+                    Some((n, used)) if 1 + used == len => {
+                        let mut raw = [0u8; 10];
+                        let Some(size) = arch::read_reg(unsafe { &mut *tf }, n, &mut raw) else {
+                            send_pkt(&tx, b"E01");
+                            continue;
+                        };
+                        let mut hex = [0u8; 20];
+                        for (i, &b) in raw[..size].iter().enumerate() {
+                            hex[2 * i] = hex4(b >> 4);
+                            hex[2 * i + 1] = hex4(b & 0xF);
+                        }
+                        send_pkt(&tx, &hex[..2 * size]);
+                    }
+                    _ => send_pkt(&tx, b"E00"),
+                },
+                // Write one register: PN=HEX (target byte order)
+                b'P' => {
+                    let Some((n, used)) = parse_hex_usize(1, len) else {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    };
+                    let hex_off = 1 + used + 1;
+                    if hex_off > len || unsafe { INBUF[1 + used] } != b'=' {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    }
+                    let Some(size) = arch::reg_size(n) else {
+                        send_pkt(&tx, b"E01");
+                        continue;
+                    };
+                    if len - hex_off != 2 * size {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    }
+                    let mut raw = [0u8; 10];
+                    let mut ok = true;
+                    for (i, b) in raw[..size].iter_mut().enumerate() {
+                        let (hi, lo) = unsafe {
+                            (
+                                from_hex(INBUF[hex_off + 2 * i]),
+                                from_hex(INBUF[hex_off + 2 * i + 1]),
+                            )
+                        };
+                        match (hi, lo) {
+                            (Some(h), Some(l)) => *b = (h << 4) | l,
+                            _ => ok = false,
+                        }
+                    }
+                    let ok = ok && arch::write_reg(unsafe { &mut *tf }, n, &raw[..size]);
+                    send_pkt(&tx, if ok { b"OK" } else { b"E00" });
+                }
                 // Read memory: mADDR,LEN
                 b'm' => {
                     if let Some((addr, rlen, _used)) = parse_addr_len(1, len) {
@@ -293,17 +736,31 @@ impl RspServer {
                             continue;
                         }
 
-                        unsafe {
-                            let src = addr as *const u8;
-                            let out = addr_of_mut!(OUTBUF) as *mut u8;
-                            let mut w = 0usize;
-                            for i in 0..rlen {
-                                let v = src.add(i).read(); // will fault if truly unmapped
-                                out.add(w).write(hex4((v >> 4) & 0xF));
-                                out.add(w + 1).write(hex4(v & 0xF));
+                        // Through the page tables, so an unmapped page is an
+                        // error reply rather than a fault in the stub.
+                        let space = AddressSpace::current();
+                        let mut chunk = [0u8; 64];
+                        let out = addr_of_mut!(OUTBUF) as *mut u8;
+                        let mut w = 0usize;
+                        let mut failed = false;
+                        for off in (0..rlen).step_by(chunk.len()) {
+                            let n = (rlen - off).min(chunk.len());
+                            if space.read((addr + off) as u64, &mut chunk[..n]).is_err() {
+                                failed = true;
+                                break;
+                            }
+                            for &v in &chunk[..n] {
+                                unsafe {
+                                    out.add(w).write(hex4((v >> 4) & 0xF));
+                                    out.add(w + 1).write(hex4(v & 0xF));
+                                }
                                 w += 2;
                             }
-                            send_pkt_raw(&tx, out as *const u8, w);
+                        }
+                        if failed {
+                            send_pkt(&tx, b"E01");
+                        } else {
+                            unsafe { send_pkt_raw(&tx, out as *const u8, w) };
                         }
                     } else {
                         send_pkt(&tx, b"E00");
@@ -351,6 +808,37 @@ impl RspServer {
                     }
                 }
 
+                // Binary write: XADDR,LEN:DATA, with `}`, `#`, `$` and `*`
+                // escaped in DATA
+                b'X' => {
+                    let Some((addr, wlen, used)) = parse_addr_len(1, len) else {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    };
+                    if 1 + used >= len || unsafe { INBUF[1 + used] } != b':' {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    }
+                    // gdb probes for `X` support with an empty write.
+                    if wlen == 0 {
+                        send_pkt(&tx, b"OK");
+                        continue;
+                    }
+                    if !m.can_write(addr, wlen) {
+                        send_pkt(&tx, b"E01");
+                        continue;
+                    }
+                    let data_off = 1 + used + 1;
+                    if unescape(data_off, len) != Some(wlen) {
+                        send_pkt(&tx, b"E00");
+                        continue;
+                    }
+                    unsafe {
+                        let src = (addr_of_mut!(INBUF) as *const u8).add(data_off);
+                        copy_nonoverlapping(src, addr as *mut u8, wlen);
+                    }
+                    send_pkt(&tx, b"OK");
+                }
                 // SW breakpoints: Z0/z0
                 b'Z' if starts_with(0, len, b"Z0,") => {
                     if let Some((addr, _used)) = parse_hex_usize(3, len) {
@@ -5,6 +5,24 @@ pub trait Transport {
     fn putc(&self, b: u8);
 }
 
+/// virtio-console debug port; preferred over COM2 when the VM provides one.
+pub struct VirtioTransport;
+
+impl Transport for VirtioTransport {
+    fn putc(&self, b: u8) {
+        crate::virtio::console::write(crate::virtio::console::DEBUG_PORT, &[b]);
+    }
+
+    fn getc_block(&self) -> u8 {
+        loop {
+            if let Some(b) = crate::virtio::console::read_byte(crate::virtio::console::DEBUG_PORT) {
+                return b;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
 /// COM2 backend; keep COM1 for human logs.
 pub struct Com2Transport;
 
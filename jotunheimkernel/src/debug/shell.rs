@@ -0,0 +1,363 @@
+// src/debug/shell.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Debug-shell command registry. A command gets the rest of its line and
+// writes text to a `fmt::Write`; where that text goes is up to the front
+// end. The RSP monitor is one (`monitor tasks` in gdb streams each line back
+// as an `O` packet) and falls back here for anything it does not handle
+// itself. Subsystems add their own commands with `register`.
+//
+// Commands can run with the rest of the kernel stopped under the debugger,
+// interrupts off: they must not wait on locks (use try_lock and say when
+// something was busy) and should not allocate.
+
+use core::fmt::{self, Write};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{faults, histo};
+use crate::arch::native::speaker;
+use crate::mem::{self, vmmap::Size};
+use crate::sched::{self, prio::Priority};
+use crate::{input, logring, stats};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_COMMANDS: usize = 32;
+
+pub type Run = fn(args: &str, out: &mut dyn Write) -> fmt::Result;
+
+#[derive(Copy, Clone)]
+pub struct Command {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub run: Run,
+}
+
+static COMMANDS: Mutex<Vec<Command, MAX_COMMANDS>> = Mutex::new(Vec::new());
+
+const BUILTINS: &[Command] = &[
+    Command {
+        name: "help",
+        help: "list shell commands",
+        run: help,
+    },
+    Command {
+        name: "tasks",
+        help: "list tasks",
+        run: tasks,
+    },
+    Command {
+        name: "mem",
+        help: "heap and frame usage",
+        run: mem_usage,
+    },
+    Command {
+        name: "faults",
+        help: "fault counts and the last few faults",
+        run: faults,
+    },
+    Command {
+        name: "dmesg",
+        help: "the kernel log held in memory",
+        run: dmesg,
+    },
+    Command {
+        name: "irqhist",
+        help: "interrupt latency histograms [on|off|reset|<vector>]",
+        run: irqhist,
+    },
+    Command {
+        name: "maps",
+        help: "mapped kernel VA ranges",
+        run: maps,
+    },
+    Command {
+        name: "input",
+        help: "input devices and queued events",
+        run: input_devices,
+    },
+    Command {
+        name: "beep",
+        help: "sound the PC speaker (see /config/speaker)",
+        run: beep,
+    },
+];
+
+/* --------------------------------- Builtins --------------------------------- */
+
+fn help(_: &str, out: &mut dyn Write) -> fmt::Result {
+    let mut r = Ok(());
+    for_each(|c| r = r.and_then(|_| writeln!(out, "{:10} {}", c.name, c.help)));
+    r
+}
+
+fn tasks(_: &str, out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "  id state     class      group   cpu  maxwait")?;
+    let mut r = Ok(());
+    let done = sched::for_each_task(|t| {
+        let mut class = heapless::String::<16>::new();
+        let _ = match (t.idle, t.deadline, t.prio) {
+            (true, ..) => write!(class, "idle"),
+            (_, Some(d), _) => write!(class, "dl@{}", d),
+            (_, _, Priority::Fifo(l)) => write!(class, "fifo {}", l),
+            (_, _, Priority::Normal(l)) => write!(class, "normal {}", l),
+        };
+        r = r.and_then(|_| {
+            writeln!(
+                out,
+                "{:4} {:9} {:10} {:5} {:5} {:8}",
+                t.id,
+                // Derived Debug ignores the width.
+                match t.state {
+                    sched::TaskState::Ready => "ready",
+                    sched::TaskState::Running => "running",
+                    sched::TaskState::Blocked => "blocked",
+                    sched::TaskState::Sleeping => "sleeping",
+                    sched::TaskState::Throttled => "throttled",
+                    sched::TaskState::Dead => "dead",
+                },
+                class,
+                t.group,
+                t.cpu_ticks,
+                t.max_wait
+            )
+        });
+    });
+    if !done {
+        writeln!(out, "runqueue busy")?;
+    }
+    r
+}
+
+fn mem_usage(_: &str, out: &mut dyn Write) -> fmt::Result {
+    let u = mem::usage();
+    match mem::heap_stats() {
+        Some(h) => writeln!(
+            out,
+            "heap: {} used of {}, {} mapped",
+            Size(h.used),
+            Size(h.size),
+            Size(h.mapped)
+        )?,
+        None => writeln!(out, "heap: busy")?,
+    }
+    match u.frames {
+        Some(f) => writeln!(
+            out,
+            "frames: {} free of {} in {} zones",
+            Size(f.free * 4096),
+            Size(f.total * 4096),
+            f.zones
+        )?,
+        None => writeln!(out, "frames: busy")?,
+    }
+    let mut r = Ok(());
+    let done = mem::slab::for_each(|c| {
+        r = r.and_then(|_| {
+            writeln!(
+                out,
+                "slab {}: {} of {} objects in use, {} bytes each, {} slabs",
+                c.name,
+                c.objects.saturating_sub(c.free),
+                c.objects,
+                c.object_size,
+                c.slabs
+            )
+        });
+    });
+    r?;
+    if !done {
+        writeln!(out, "slab: busy")?;
+    }
+    let (mut ranges, mut pages) = (0, 0);
+    let done = mem::pin::for_each(|p| {
+        ranges += 1;
+        pages += (p.end - p.start) / 4096;
+    });
+    if done {
+        writeln!(out, "pinned: {} pages in {} ranges", pages, ranges)?;
+    } else {
+        writeln!(out, "pinned: busy")?;
+    }
+    let mut r = Ok(());
+    let done = mem::hotplug::for_each(|s, e, src| {
+        r = r.and_then(|_| {
+            writeln!(
+                out,
+                "hot-added: {:#x}..{:#x} {} ({:?})",
+                s,
+                e,
+                Size(e - s),
+                src
+            )
+        });
+    });
+    r?;
+    if !done {
+        writeln!(out, "hot-added: busy")?;
+    }
+    let (vmap, mmio) = mem::va_stats();
+    for (name, v) in [("vmap", vmap), ("mmio", mmio)] {
+        writeln!(
+            out,
+            "{} va: {} free, largest {}, {} holes",
+            name,
+            Size(v.free),
+            Size(v.largest),
+            v.holes
+        )?;
+    }
+    Ok(())
+}
+
+fn faults(_: &str, out: &mut dyn Write) -> fmt::Result {
+    let mut r = Ok(());
+    stats::for_each(|name, v| {
+        if name.ends_with(".faults") && v != 0 {
+            r = r.and_then(|_| writeln!(out, "{} {}", name, v));
+        }
+    });
+    r?;
+    let done = faults::for_each(|f| {
+        r = r.and_then(|_| {
+            writeln!(
+                out,
+                "@{} cpu {} {} err={:#x} rip={:#x} cr2={:#x}",
+                f.tick,
+                f.cpu,
+                faults::name(f.vec),
+                f.err,
+                f.rip,
+                f.cr2
+            )
+        });
+    });
+    if !done {
+        writeln!(out, "fault log busy")?;
+    }
+    r
+}
+
+fn dmesg(_: &str, out: &mut dyn Write) -> fmt::Result {
+    let mut r = Ok(());
+    logring::for_each_chunk(|mut b| {
+        // The oldest line may start mid-character; skip what is not UTF-8.
+        while !b.is_empty() && r.is_ok() {
+            match core::str::from_utf8(b) {
+                Ok(s) => {
+                    r = out.write_str(s);
+                    break;
+                }
+                Err(e) => {
+                    let ok = e.valid_up_to();
+                    let s = unsafe { core::str::from_utf8_unchecked(&b[..ok]) };
+                    r = out.write_str(s);
+                    b = &b[ok + e.error_len().unwrap_or(b.len() - ok)..];
+                }
+            }
+        }
+    });
+    r
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Add the built-in commands.
+pub fn init() {
+    for c in BUILTINS {
+        register(c.name, c.help, c.run);
+    }
+}
+
+/// Add a command; false if the name is taken or the table is full.
+pub fn register(name: &'static str, help: &'static str, run: Run) -> bool {
+    without_interrupts(|| {
+        let mut cmds = COMMANDS.lock();
+        if cmds.iter().any(|c| c.name == name) {
+            return false;
+        }
+        cmds.push(Command { name, help, run }).is_ok()
+    })
+}
+
+/// Run `line` (`<name> [args]`). None if there is no such command (or the
+/// table was busy).
+pub fn run(line: &str, out: &mut dyn Write) -> Option<fmt::Result> {
+    let line = line.trim();
+    let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+    let cmd = without_interrupts(|| {
+        COMMANDS
+            .try_lock()
+            .and_then(|cmds| cmds.iter().find(|c| c.name == name).copied())
+    })?;
+    Some((cmd.run)(args.trim_start(), out))
+}
+
+/// Every registered command, in registration order.
+pub fn for_each(mut f: impl FnMut(&Command)) {
+    let cmds = without_interrupts(|| COMMANDS.try_lock().map(|c| c.clone()));
+    for c in cmds.iter().flatten() {
+        f(c);
+    }
+}
+
+fn irqhist(args: &str, out: &mut dyn Write) -> fmt::Result {
+    match args.trim() {
+        "" => {
+            if !histo::enabled() {
+                writeln!(out, "recording off")?;
+            }
+            histo::dump(out)
+        }
+        "on" => {
+            histo::set_enabled(true);
+            Ok(())
+        }
+        "off" => {
+            histo::set_enabled(false);
+            Ok(())
+        }
+        "reset" => {
+            histo::reset();
+            Ok(())
+        }
+        v => {
+            let vec = match v.strip_prefix("0x") {
+                Some(hex) => u8::from_str_radix(hex, 16),
+                None => v.parse(),
+            };
+            match vec {
+                Ok(vec) => {
+                    let mut name = heapless::String::<16>::new();
+                    let _ = write!(name, "isr {:#04x}", vec);
+                    histo::for_vector(vec).dump(&name, out)
+                }
+                Err(_) => writeln!(out, "usage: irqhist [on|off|reset|<vector>]"),
+            }
+        }
+    }
+}
+
+fn maps(_: &str, out: &mut dyn Write) -> fmt::Result {
+    mem::dump_mappings(out)
+}
+
+fn input_devices(_: &str, out: &mut dyn Write) -> fmt::Result {
+    writeln!(out, "  id name         queued dropped")?;
+    let mut r = Ok(());
+    let done = input::for_each_device(|id, name, queued, dropped| {
+        r = r.and_then(|_| writeln!(out, "{:4} {:12} {:6} {}", id, name, queued, dropped));
+    });
+    if !done {
+        writeln!(out, "input registry busy")?;
+    }
+    r
+}
+
+fn beep(_: &str, _: &mut dyn Write) -> fmt::Result {
+    speaker::beep_default();
+    Ok(())
+}
@@ -0,0 +1,145 @@
+// src/debug/trace.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Instruction tracer: single-steps N instructions, printing each one's
+// address, bytes and mnemonic (`debug::insn`) from the #DB handler, then
+// lets the code run on. For code gdb cannot stop in, or where stopping
+// changes what happens (AP bring-up, code running with interrupts off).
+//
+// Two ways in: `start(n)` from the code itself traces what follows the
+// call on this CPU (`trace_ap=<n>` does this on each AP once its IDT is
+// up); `trace <n>` in the debug shell (gdb `monitor trace 100`) traces
+// from wherever gdb next continues. The CPU needs an IDT with
+// the #DB vector loaded, and the code must be 64-bit: the real-mode and
+// compatibility-mode parts of the AP trampoline cannot be traced.
+// Interrupt handlers run with TF clear, so they are not traced; the step
+// after one resumes where it left off. Lines go out through
+// `early_println!` (no locks), one per step.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use x86_64::registers::debug::{Dr6, Dr6Flags};
+
+use super::insn::{self, MAX_LEN};
+use super::{TrapFrame, clear_dr6, clear_tf, set_tf, shell};
+use crate::early_println;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+static LEFT: AtomicU32 = AtomicU32::new(0); // steps still to log
+static TOTAL: AtomicU32 = AtomicU32::new(0); // steps in the current trace
+static ARMED: AtomicU32 = AtomicU32::new(0); // steps to trace at the next continue
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn begin(n: u32) {
+    TOTAL.store(n, Ordering::Relaxed);
+    LEFT.store(n, Ordering::Relaxed);
+}
+
+// Print the instruction at `rip`. It is about to run, so its first page is
+// mapped; the next one may not be, so bytes past the page end are not read.
+fn log(step: u32, rip: u64) {
+    let avail = (4096 - (rip & 0xfff) as usize).min(MAX_LEN);
+    let bytes = unsafe { core::slice::from_raw_parts(rip as *const u8, avail) };
+    let (shown, what) = match insn::decode(bytes) {
+        Ok(i) => (i.len, Ok(i.mnemonic)),
+        Err(e) => (avail.min(8), Err(e)),
+    };
+    let mut hex = heapless::String::<{ MAX_LEN * 3 }>::new();
+    for b in &bytes[..shown] {
+        let _ = write!(hex, "{:02x} ", b);
+    }
+    match what {
+        Ok(m) => early_println!("[trace] {:5} {:#018x}: {:45} {}", step, rip, hex, m),
+        Err(e) => early_println!("[trace] {:5} {:#018x}: {:45} ({:?})", step, rip, hex, e),
+    }
+}
+
+fn cmd_trace(args: &str, out: &mut dyn Write) -> fmt::Result {
+    match args.trim() {
+        "" => writeln!(
+            out,
+            "tracing: {} of {} steps left, {} armed for the next continue",
+            LEFT.load(Ordering::Relaxed),
+            TOTAL.load(Ordering::Relaxed),
+            ARMED.load(Ordering::Relaxed)
+        ),
+        "off" => {
+            stop();
+            Ok(())
+        }
+        n => match n.parse::<u32>() {
+            Ok(n) => {
+                ARMED.store(n, Ordering::Relaxed);
+                writeln!(out, "tracing {} steps from the next continue", n)
+            }
+            Err(_) => writeln!(out, "trace: expected a step count or 'off'"),
+        },
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Register the `trace` shell command.
+pub fn init() {
+    shell::register("trace", "[<steps>|off]: single-step trace", cmd_trace);
+}
+
+/// Trace the next `n` instructions on this CPU, starting just after the
+/// call returns.
+#[inline(never)]
+pub fn start(n: u32) {
+    if n == 0 {
+        return;
+    }
+    begin(n);
+    unsafe {
+        core::arch::asm!("pushfq", "or qword ptr [rsp], 0x100", "popfq");
+    }
+}
+
+/// End any trace at its next step, and disarm `trace <n>`.
+pub fn stop() {
+    ARMED.store(0, Ordering::Relaxed);
+    LEFT.store(0, Ordering::Relaxed);
+}
+
+/// The debugger is resuming `tf` with a continue: start an armed trace.
+pub fn on_continue(tf: &mut TrapFrame) {
+    let n = ARMED.swap(0, Ordering::Relaxed);
+    if n != 0 {
+        begin(n);
+        set_tf(tf);
+    }
+}
+
+/// Called by the #DB handler. True if this was a traced step, logged and
+/// resumed; false leaves the trap to the debugger.
+pub fn on_step(tf: &mut TrapFrame) -> bool {
+    if !Dr6::read().contains(Dr6Flags::STEP) {
+        return false;
+    }
+    let Ok(left) = LEFT.try_update(Ordering::Relaxed, Ordering::Relaxed, |l| l.checked_sub(1))
+    else {
+        // Not ours, unless a `stop` just ended it: drop the stray TF.
+        if TOTAL.swap(0, Ordering::Relaxed) != 0 {
+            clear_tf(tf);
+            clear_dr6(Dr6Flags::STEP);
+            return true;
+        }
+        return false;
+    };
+    crate::counter!("debug.trace_steps");
+    log(TOTAL.load(Ordering::Relaxed) - left + 1, tf.rip);
+    if left == 1 {
+        TOTAL.store(0, Ordering::Relaxed);
+        clear_tf(tf);
+        early_println!("[trace] done");
+    } else {
+        set_tf(tf);
+    }
+    clear_dr6(Dr6Flags::STEP);
+    true
+}
@@ -0,0 +1,289 @@
+// src/debug/watch.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Hardware data watchpoints. The four debug address registers (DR0-DR3)
+// are handed out as slots; `set` fills one and reloads DR0-3/DR7 on every
+// online CPU (APs that come up later load them in `ap_entry`). A hit raises
+// a #DB trap after the access, which `on_debug` logs with the accessing
+// rip, the value now there and a backtrace, then resumes: no debugger
+// needed. The first LOG_HITS hits per slot are logged, the rest only
+// counted (`debug.watch_hits`).
+//
+// From the debug shell: `watch` lists slots, `watch <name|addr> [size] [w|rw]`
+// arms one (size 1, 2, 4 or 8, aligned; writes by default) and
+// `unwatch <slot|all>` clears. There is no kernel symbol table, so names
+// are the globals subsystems `export`, such as `sched.ticks`.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::registers::debug::{
+    BreakpointCondition, BreakpointSize, DebugAddressRegister, DebugAddressRegisterNumber, Dr0,
+    Dr1, Dr2, Dr3, Dr6, Dr6Flags, Dr7, Dr7Flags, Dr7Value,
+};
+
+use super::TrapFrame;
+use super::crash::{self, MAX_FRAMES};
+use super::shell;
+use crate::arch::x86_64::smp;
+use crate::early_println;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const SLOTS: usize = 4;
+const LOG_HITS: u32 = 8;
+const MAX_EXPORTS: usize = 32;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Access {
+    Write,
+    ReadWrite,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Watch {
+    pub addr: u64,
+    pub len: usize,
+    pub access: Access,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WatchError {
+    BadSize,   // not 1, 2, 4 or 8
+    Unaligned, // addr not a multiple of the size
+    Full,      // all four slots in use
+}
+
+static TABLE: Mutex<[Option<Watch>; SLOTS]> = Mutex::new([None; SLOTS]);
+static HITS: [AtomicU32; SLOTS] = [const { AtomicU32::new(0) }; SLOTS];
+// (name, addr, len) of globals that can be watched by name.
+static EXPORTS: Mutex<Vec<(&'static str, u64, usize), MAX_EXPORTS>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn dr(i: usize) -> DebugAddressRegisterNumber {
+    DebugAddressRegisterNumber::new(i as u8).unwrap()
+}
+
+fn write_addr(i: usize, addr: u64) {
+    match i {
+        0 => Dr0::write(addr),
+        1 => Dr1::write(addr),
+        2 => Dr2::write(addr),
+        _ => Dr3::write(addr),
+    }
+}
+
+// Program this CPU's debug registers from `table`.
+fn load_from(table: &[Option<Watch>; SLOTS]) {
+    let mut v = Dr7Value::from(Dr7Flags::empty());
+    for (i, w) in table.iter().enumerate() {
+        let Some(w) = w else {
+            continue;
+        };
+        write_addr(i, w.addr);
+        let cond = match w.access {
+            Access::Write => BreakpointCondition::DataWrites,
+            Access::ReadWrite => BreakpointCondition::DataReadsWrites,
+        };
+        v.set_condition(dr(i), cond);
+        v.set_size(dr(i), BreakpointSize::new(w.len).unwrap());
+        v.insert_flags(Dr7Flags::global_breakpoint_enable(dr(i)));
+    }
+    Dr7::write(v);
+}
+
+fn reload_all() {
+    let table = without_interrupts(|| *TABLE.lock());
+    smp::call_all(|| load_from(&table));
+}
+
+fn read_value(addr: u64, len: usize) -> u64 {
+    unsafe {
+        match len {
+            1 => (addr as *const u8).read_volatile() as u64,
+            2 => (addr as *const u16).read_volatile() as u64,
+            4 => (addr as *const u32).read_volatile() as u64,
+            _ => (addr as *const u64).read_volatile(),
+        }
+    }
+}
+
+/* ------------------------------- Shell commands ------------------------------ */
+
+fn parse_target(s: &str) -> Option<(u64, Option<usize>)> {
+    if let Some(&(_, addr, len)) = without_interrupts(|| {
+        EXPORTS
+            .try_lock()
+            .and_then(|e| e.iter().find(|x| x.0 == s).copied())
+    })
+    .as_ref()
+    {
+        return Some((addr, Some(len)));
+    }
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    Some((u64::from_str_radix(hex, 16).ok()?, None))
+}
+
+fn cmd_watch(args: &str, out: &mut dyn Write) -> fmt::Result {
+    let mut a = args.split_ascii_whitespace();
+    let Some(target) = a.next() else {
+        let mut r = Ok(());
+        for_each(|i, w| {
+            r = r.and_then(|_| {
+                writeln!(
+                    out,
+                    "{} {:#x} len {} {:?} hits {}",
+                    i,
+                    w.addr,
+                    w.len,
+                    w.access,
+                    HITS[i].load(Ordering::Relaxed)
+                )
+            });
+        });
+        return r;
+    };
+    let Some((addr, exported_len)) = parse_target(target) else {
+        return writeln!(out, "watch: no global or address '{}'", target);
+    };
+    let mut len = exported_len.unwrap_or(8).min(8);
+    let mut access = Access::Write;
+    for x in a {
+        match x {
+            "w" => access = Access::Write,
+            "rw" => access = Access::ReadWrite,
+            n => match n.parse() {
+                Ok(n) => len = n,
+                Err(_) => return writeln!(out, "watch: bad argument '{}'", n),
+            },
+        }
+    }
+    match set(addr, len, access) {
+        Ok(i) => writeln!(out, "slot {}: {:#x} len {} {:?}", i, addr, len, access),
+        Err(e) => writeln!(out, "watch: {:?}", e),
+    }
+}
+
+fn cmd_unwatch(args: &str, out: &mut dyn Write) -> fmt::Result {
+    match args.trim() {
+        "all" => {
+            clear_all();
+            Ok(())
+        }
+        s => match s.parse() {
+            Ok(i) if clear(i) => Ok(()),
+            _ => writeln!(out, "unwatch: no slot '{}'", s),
+        },
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Register the shell commands.
+pub fn init() {
+    shell::register(
+        "watch",
+        "[<name|addr> [size] [w|rw]]: data watchpoint",
+        cmd_watch,
+    );
+    shell::register("unwatch", "<slot|all>: clear watchpoints", cmd_unwatch);
+}
+
+/// Make `len` bytes at `addr` watchable by `name`.
+pub fn export(name: &'static str, addr: u64, len: usize) {
+    let _ = without_interrupts(|| EXPORTS.lock().push((name, addr, len)));
+}
+
+/// Trap `access`es to `[addr, addr + len)` on every CPU; returns the slot.
+pub fn set(addr: u64, len: usize, access: Access) -> Result<usize, WatchError> {
+    if BreakpointSize::new(len).is_none() {
+        return Err(WatchError::BadSize);
+    }
+    if !addr.is_multiple_of(len as u64) {
+        return Err(WatchError::Unaligned);
+    }
+    let slot = without_interrupts(|| {
+        let mut t = TABLE.lock();
+        let i = t.iter().position(Option::is_none)?;
+        t[i] = Some(Watch { addr, len, access });
+        HITS[i].store(0, Ordering::Relaxed);
+        Some(i)
+    })
+    .ok_or(WatchError::Full)?;
+    reload_all();
+    Ok(slot)
+}
+
+/// Free `slot`; false if it was not in use.
+pub fn clear(slot: usize) -> bool {
+    let was = without_interrupts(|| TABLE.lock().get_mut(slot).and_then(Option::take));
+    if was.is_some() {
+        reload_all();
+    }
+    was.is_some()
+}
+
+pub fn clear_all() {
+    without_interrupts(|| *TABLE.lock() = [None; SLOTS]);
+    reload_all();
+}
+
+pub fn for_each(mut f: impl FnMut(usize, &Watch)) {
+    let table = without_interrupts(|| *TABLE.lock());
+    for (i, w) in table.iter().enumerate() {
+        if let Some(w) = w {
+            f(i, w);
+        }
+    }
+}
+
+/// Program this CPU's debug registers; for CPUs coming online.
+pub fn load() {
+    let table = without_interrupts(|| *TABLE.lock());
+    load_from(&table);
+}
+
+/// Called first by the #DB handler. Logs watchpoint hits and clears their
+/// DR6 bits; true if that was all the trap was for, and execution resumes.
+pub fn on_debug(tf: &TrapFrame) -> bool {
+    let dr6 = Dr6::read();
+    let hit = dr6 & Dr6Flags::TRAP;
+    if hit.is_empty() {
+        return false;
+    }
+    // The table may be locked by whatever we interrupted.
+    let table = TABLE.try_lock().map(|t| *t);
+    for i in 0..SLOTS {
+        if !hit.contains(Dr6Flags::trap(dr(i))) {
+            continue;
+        }
+        crate::counter!("debug.watch_hits");
+        let n = HITS[i].fetch_add(1, Ordering::Relaxed);
+        let Some(Some(w)) = table.map(|t| t[i]) else {
+            continue;
+        };
+        if n >= LOG_HITS {
+            continue;
+        }
+        early_println!(
+            "[watch] slot {} {:#x}: {:?} at rip={:#x}, now {:#x}",
+            i,
+            w.addr,
+            w.access,
+            tf.rip,
+            read_value(w.addr, w.len)
+        );
+        let mut frames: Vec<u64, MAX_FRAMES> = Vec::new();
+        crash::backtrace_from(tf.rbp, &mut frames, 0);
+        for f in frames.iter() {
+            early_println!("[watch]   {:#018x}", f);
+        }
+    }
+    super::clear_dr6(hit);
+    // A single step taken at the same time is still the debugger's.
+    !dr6.contains(Dr6Flags::STEP)
+}
@@ -0,0 +1,157 @@
+// src/error.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// One error type for kernel APIs that can fail. Allocation, lookup and
+// scheduling calls return `KResult<T>` instead of an Option or a bool, so
+// a caller can tell "out of memory" from "no such thing" and pass either
+// up with `?`.
+//
+// Subsystems whose callers need more detail keep their own error enum
+// (BlockError, UsbError, CopyError, ...). Each converts into a KError, so
+// code that only needs the broad kind propagates them with `?` as well.
+
+use core::fmt;
+
+use crate::blockdev::BlockError;
+use crate::config::ConfigError;
+use crate::irq::IrqError;
+use crate::kobject::KObjError;
+use crate::mem::aspace::CopyError;
+use crate::mem::hotplug::HotplugError;
+use crate::mem::pin::PinError;
+use crate::sched::edf::AdmissionError;
+use crate::usb::UsbError;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KError {
+    OutOfMemory, // no frames, heap or VA space left
+    InvalidArg,  // bad size, priority, path, ...
+    NotFound,    // no such task, file, IRQ, ...
+    Exists,      // the name or range is taken
+    Busy,        // in use, a table is full, or over a budget
+    IoError,     // the device or medium failed
+    ReadOnly,    // write to something that is not writable
+    TooLarge,    // over a size limit
+    BadAddress,  // an address with no usable mapping
+    Timeout,
+    Unsupported, // a format or feature this kernel does not handle
+}
+
+pub type KResult<T> = Result<T, KError>;
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl KError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KError::OutOfMemory => "out of memory",
+            KError::InvalidArg => "invalid argument",
+            KError::NotFound => "not found",
+            KError::Exists => "already exists",
+            KError::Busy => "busy",
+            KError::IoError => "I/O error",
+            KError::ReadOnly => "read-only",
+            KError::TooLarge => "too large",
+            KError::BadAddress => "bad address",
+            KError::Timeout => "timed out",
+            KError::Unsupported => "unsupported",
+        }
+    }
+}
+
+impl fmt::Display for KError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/* ------------------------------ Subsystem errors ----------------------------- */
+
+impl From<BlockError> for KError {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::OutOfRange | BlockError::BadBuffer => KError::InvalidArg,
+            BlockError::ReadOnly => KError::ReadOnly,
+            BlockError::Io => KError::IoError,
+        }
+    }
+}
+
+impl From<UsbError> for KError {
+    fn from(e: UsbError) -> Self {
+        match e {
+            UsbError::Timeout => KError::Timeout,
+            UsbError::NoMemory => KError::OutOfMemory,
+            UsbError::NoDevice => KError::NotFound,
+            UsbError::Stall | UsbError::Controller(_) => KError::IoError,
+        }
+    }
+}
+
+impl From<IrqError> for KError {
+    fn from(e: IrqError) -> Self {
+        match e {
+            IrqError::NoSuchIrq => KError::NotFound,
+            IrqError::NoCpu => KError::InvalidArg,
+        }
+    }
+}
+
+impl From<CopyError> for KError {
+    fn from(e: CopyError) -> Self {
+        match e {
+            CopyError::ReadOnly(_) => KError::ReadOnly,
+            CopyError::NotMapped(_) | CopyError::NotRam(_) => KError::BadAddress,
+        }
+    }
+}
+
+impl From<PinError> for KError {
+    fn from(e: PinError) -> Self {
+        match e {
+            PinError::Empty => KError::InvalidArg,
+            PinError::NotMapped => KError::BadAddress,
+            PinError::NotPinned => KError::NotFound,
+        }
+    }
+}
+
+impl From<HotplugError> for KError {
+    fn from(e: HotplugError) -> Self {
+        match e {
+            HotplugError::Empty | HotplugError::TooHigh => KError::InvalidArg,
+            HotplugError::Overlaps => KError::Exists,
+            HotplugError::Full => KError::Busy,
+        }
+    }
+}
+
+impl From<AdmissionError> for KError {
+    fn from(e: AdmissionError) -> Self {
+        match e {
+            AdmissionError::Invalid => KError::InvalidArg,
+            AdmissionError::Overloaded => KError::Busy,
+            AdmissionError::NoTask => KError::NotFound,
+        }
+    }
+}
+
+impl From<KObjError> for KError {
+    fn from(e: KObjError) -> Self {
+        match e {
+            KObjError::BadName => KError::InvalidArg,
+            KObjError::Exists => KError::Exists,
+        }
+    }
+}
+
+impl From<ConfigError> for KError {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            ConfigError::NoSubsystem | ConfigError::NoKey => KError::NotFound,
+            ConfigError::BadValue(_) => KError::InvalidArg,
+        }
+    }
+}
@@ -0,0 +1,449 @@
+// src/fs/iso9660.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Read-only ISO9660 (ECMA-119) with Rock Ridge names and El Torito boot catalog.
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::blockdev::{BackingFile, BlockDevice};
+use crate::error::{KError, KResult};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const SECTOR_SIZE: usize = 2048;
+
+const VD_START: u32 = 16; // volume descriptors begin after the 32 KiB system area
+const VD_MAX: u32 = 64; // give up if no terminator shows up by then
+const VD_BOOT: u8 = 0;
+const VD_PRIMARY: u8 = 1;
+const VD_TERMINATOR: u8 = 255;
+
+// `read_all` refuses files bigger than this rather than risk the heap.
+const READ_ALL_MAX: usize = 64 << 20;
+
+const DIR_FLAG_DIRECTORY: u8 = 1 << 1;
+
+// Rock Ridge NM flags
+const NM_CURRENT: u8 = 1 << 1;
+const NM_PARENT: u8 = 1 << 2;
+
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub extent: u32, // first logical sector
+    pub size: u32,   // bytes
+    pub is_dir: bool,
+}
+
+/// El Torito initial/default entry from the boot catalog.
+#[derive(Copy, Clone, Debug)]
+pub struct BootEntry {
+    pub bootable: bool,
+    pub media: u8,         // 0 = no emulation, 1..3 = floppy, 4 = hard disk
+    pub load_segment: u16, // 0 means the BIOS default (0x7C0)
+    pub sector_count: u16, // virtual 512-byte sectors
+    pub load_rba: u32,     // logical sector of the boot image
+}
+
+pub struct Iso9660 {
+    dev: Arc<dyn BlockDevice>,
+    volume_id: String,
+    volume_blocks: u32,
+    root: DirEntry,
+    rock_ridge: bool,
+    susp_skip: usize, // SUSP "SP" len_skp: bytes to skip in every System Use area
+    boot_catalog: Option<u32>,
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn le16(b: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([b[off], b[off + 1]])
+}
+
+fn le32(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+fn trim_ascii(b: &[u8]) -> String {
+    let mut end = b.len();
+    while end > 0 && (b[end - 1] == b' ' || b[end - 1] == 0) {
+        end -= 1;
+    }
+    b[..end].iter().map(|&c| c as char).collect()
+}
+
+/// Plain ISO9660 identifier -> display name: drop ";1", a trailing '.', lowercase.
+fn iso_name(raw: &[u8]) -> String {
+    let mut end = raw.iter().position(|&c| c == b';').unwrap_or(raw.len());
+    if end > 0 && raw[end - 1] == b'.' {
+        end -= 1;
+    }
+    raw[..end]
+        .iter()
+        .map(|&c| c.to_ascii_lowercase() as char)
+        .collect()
+}
+
+/// Read one 2048-byte logical sector regardless of the device block size.
+fn read_sector(dev: &dyn BlockDevice, sector: u32, buf: &mut [u8]) -> bool {
+    let bs = dev.block_size();
+    if bs == 0 || bs > SECTOR_SIZE || !SECTOR_SIZE.is_multiple_of(bs) {
+        return false;
+    }
+    let per = (SECTOR_SIZE / bs) as u64;
+    dev.read_blocks(sector as u64 * per, &mut buf[..SECTOR_SIZE])
+        .is_ok()
+}
+
+/* --------------------------------- Mounting --------------------------------- */
+
+impl Iso9660 {
+    /// Probe `dev` for an ISO9660 volume. Unsupported if no PVD is found.
+    pub fn mount(dev: Arc<dyn BlockDevice>) -> KResult<Self> {
+        let mut sec = vec![0u8; SECTOR_SIZE];
+        let mut pvd: Option<Vec<u8>> = None;
+        let mut boot_catalog = None;
+
+        for s in VD_START..VD_START + VD_MAX {
+            if !read_sector(dev.as_ref(), s, &mut sec) {
+                return Err(KError::IoError);
+            }
+            if &sec[1..6] != b"CD001" {
+                return Err(KError::Unsupported);
+            }
+            match sec[0] {
+                VD_PRIMARY if pvd.is_none() => pvd = Some(sec.clone()),
+                VD_BOOT if sec[7..30].starts_with(b"EL TORITO SPECIFICATION") => {
+                    boot_catalog = Some(le32(&sec, 0x47));
+                }
+                VD_TERMINATOR => break,
+                _ => {}
+            }
+        }
+
+        let pvd = pvd.ok_or(KError::Unsupported)?;
+        if le16(&pvd, 128) as usize != SECTOR_SIZE {
+            kprintln!(
+                "[iso9660] unsupported logical block size {}",
+                le16(&pvd, 128)
+            );
+            return Err(KError::Unsupported);
+        }
+
+        let root_rec = &pvd[156..156 + 34];
+        let root = DirEntry {
+            name: String::from("/"),
+            extent: le32(root_rec, 2),
+            size: le32(root_rec, 10),
+            is_dir: true,
+        };
+
+        let mut fs = Self {
+            dev,
+            volume_id: trim_ascii(&pvd[40..72]),
+            volume_blocks: le32(&pvd, 80),
+            root,
+            rock_ridge: false,
+            susp_skip: 0,
+            boot_catalog,
+        };
+        fs.detect_rock_ridge();
+
+        kprintln!(
+            "[iso9660] volume '{}' {} sectors{}{}",
+            fs.volume_id,
+            fs.volume_blocks,
+            if fs.rock_ridge { " +RR" } else { "" },
+            if fs.boot_catalog.is_some() {
+                " +El Torito"
+            } else {
+                ""
+            }
+        );
+        Ok(fs)
+    }
+
+    /// The root "." record carries the SUSP "SP" entry when Rock Ridge is in use.
+    fn detect_rock_ridge(&mut self) {
+        let mut sec = vec![0u8; SECTOR_SIZE];
+        if !read_sector(self.dev.as_ref(), self.root.extent, &mut sec) {
+            return;
+        }
+        let len = sec[0] as usize;
+        if len < 34 {
+            return;
+        }
+        let name_len = sec[32] as usize;
+        let su = 33 + name_len + (name_len + 1) % 2;
+        if su + 7 <= len && &sec[su..su + 2] == b"SP" && sec[su + 4] == 0xBE && sec[su + 5] == 0xEF
+        {
+            self.rock_ridge = true;
+            self.susp_skip = sec[su + 6] as usize;
+        }
+    }
+
+    pub fn volume_id(&self) -> &str {
+        &self.volume_id
+    }
+
+    pub fn root(&self) -> &DirEntry {
+        &self.root
+    }
+
+    pub fn has_rock_ridge(&self) -> bool {
+        self.rock_ridge
+    }
+}
+
+/* ------------------------------- Directories -------------------------------- */
+
+impl Iso9660 {
+    /// List a directory (without "." and "..").
+    pub fn read_dir(&self, dir: &DirEntry) -> KResult<Vec<DirEntry>> {
+        if !dir.is_dir {
+            return Err(KError::InvalidArg);
+        }
+        let mut out = Vec::new();
+        let mut sec = vec![0u8; SECTOR_SIZE];
+        let sectors = (dir.size as usize).div_ceil(SECTOR_SIZE) as u32;
+
+        for s in 0..sectors {
+            // A corrupt record can put the extent at the top of the range.
+            let sector = dir.extent.checked_add(s).ok_or(KError::IoError)?;
+            if !read_sector(self.dev.as_ref(), sector, &mut sec) {
+                return Err(KError::IoError);
+            }
+            let mut off = 0usize;
+            // Records never straddle a sector; a zero length byte pads to the next one.
+            while off < SECTOR_SIZE {
+                let len = sec[off] as usize;
+                if len == 0 || off + len > SECTOR_SIZE || len < 34 {
+                    break;
+                }
+                if let Some(e) = self.parse_record(&sec[off..off + len]) {
+                    out.push(e);
+                }
+                off += len;
+            }
+        }
+        Ok(out)
+    }
+
+    fn parse_record(&self, rec: &[u8]) -> Option<DirEntry> {
+        let name_len = rec[32] as usize;
+        if 33 + name_len > rec.len() {
+            return None;
+        }
+        let raw = &rec[33..33 + name_len];
+        if name_len == 1 && (raw[0] == 0 || raw[0] == 1) {
+            return None; // "." / ".."
+        }
+
+        let mut name = None;
+        if self.rock_ridge {
+            let su = 33 + name_len + (name_len + 1) % 2 + self.susp_skip;
+            if su < rec.len() {
+                name = self.rock_ridge_name(&rec[su..]);
+            }
+        }
+
+        Some(DirEntry {
+            name: name.unwrap_or_else(|| iso_name(raw)),
+            extent: le32(rec, 2),
+            size: le32(rec, 10),
+            is_dir: rec[25] & DIR_FLAG_DIRECTORY != 0,
+        })
+    }
+
+    /// Collect the (possibly split) Rock Ridge NM name from a System Use area,
+    /// following a single CE continuation chain.
+    fn rock_ridge_name(&self, su: &[u8]) -> Option<String> {
+        let mut name = String::new();
+        let mut found = false;
+        let mut area: Vec<u8> = su.to_vec();
+        let mut hops = 0;
+
+        loop {
+            let mut next: Option<(u32, usize, usize)> = None;
+            let mut off = 0usize;
+            while off + 4 <= area.len() {
+                let len = area[off + 2] as usize;
+                if len < 4 || off + len > area.len() {
+                    break;
+                }
+                let e = &area[off..off + len];
+                match &e[0..2] {
+                    b"NM" if len >= 5 => {
+                        let flags = e[4];
+                        if flags & (NM_CURRENT | NM_PARENT) == 0 {
+                            name.extend(e[5..].iter().map(|&c| c as char));
+                            found = true;
+                        }
+                    }
+                    b"CE" if len >= 28 => {
+                        next = Some((le32(e, 4), le32(e, 12) as usize, le32(e, 20) as usize));
+                    }
+                    b"ST" => break,
+                    _ => {}
+                }
+                off += len;
+            }
+
+            let Some((block, coff, clen)) = next else {
+                break;
+            };
+            hops += 1;
+            if hops > 8 || coff + clen > SECTOR_SIZE {
+                break;
+            }
+            let mut sec = vec![0u8; SECTOR_SIZE];
+            if !read_sector(self.dev.as_ref(), block, &mut sec) {
+                break;
+            }
+            area = sec[coff..coff + clen].to_vec();
+        }
+
+        if found && !name.is_empty() {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Resolve an absolute path like "/EFI/BOOT/BOOTX64.EFI".
+    /// Plain ISO names compare case-insensitively; Rock Ridge names exactly.
+    pub fn lookup(&self, path: &str) -> KResult<DirEntry> {
+        let mut cur = self.root.clone();
+        for comp in path.split('/').filter(|c| !c.is_empty()) {
+            if !cur.is_dir {
+                return Err(KError::NotFound);
+            }
+            let entries = self.read_dir(&cur)?;
+            cur = entries
+                .into_iter()
+                .find(|e| {
+                    if self.rock_ridge {
+                        e.name == comp
+                    } else {
+                        e.name.eq_ignore_ascii_case(comp)
+                    }
+                })
+                .ok_or(KError::NotFound)?;
+        }
+        Ok(cur)
+    }
+}
+
+/* ---------------------------------- Files ----------------------------------- */
+
+impl Iso9660 {
+    /// Read up to `buf.len()` bytes of `file` starting at `offset`.
+    /// Returns the number of bytes copied (0 at EOF).
+    pub fn read(&self, file: &DirEntry, offset: u64, buf: &mut [u8]) -> KResult<usize> {
+        let size = file.size as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let want = core::cmp::min(buf.len() as u64, size - offset) as usize;
+        let mut sec = vec![0u8; SECTOR_SIZE];
+        let mut done = 0usize;
+
+        while done < want {
+            let pos = offset + done as u64;
+            let s = u32::try_from(pos / SECTOR_SIZE as u64)
+                .ok()
+                .and_then(|i| file.extent.checked_add(i))
+                .ok_or(KError::IoError)?;
+            let in_sec = (pos % SECTOR_SIZE as u64) as usize;
+            if !read_sector(self.dev.as_ref(), s, &mut sec) {
+                return Err(KError::IoError);
+            }
+            let n = core::cmp::min(SECTOR_SIZE - in_sec, want - done);
+            buf[done..done + n].copy_from_slice(&sec[in_sec..in_sec + n]);
+            done += n;
+        }
+        Ok(done)
+    }
+
+    /// Convenience: read the whole file into a Vec. TooLarge past
+    /// `READ_ALL_MAX`; bigger files are read piecewise with `read`.
+    pub fn read_all(&self, file: &DirEntry) -> KResult<Vec<u8>> {
+        let size = file.size as usize;
+        if size > READ_ALL_MAX {
+            return Err(KError::TooLarge);
+        }
+        let mut v = Vec::new();
+        v.try_reserve_exact(size).map_err(|_| KError::OutOfMemory)?;
+        v.resize(size, 0);
+        let n = self.read(file, 0, &mut v)?;
+        v.truncate(n);
+        Ok(v)
+    }
+}
+
+/// A regular file pinned to its volume; usable as a loop device backing.
+pub struct IsoFile {
+    fs: Arc<Iso9660>,
+    entry: DirEntry,
+}
+
+impl Iso9660 {
+    /// InvalidArg if `path` is a directory.
+    pub fn open(self: &Arc<Self>, path: &str) -> KResult<Arc<IsoFile>> {
+        let entry = self.lookup(path)?;
+        if entry.is_dir {
+            return Err(KError::InvalidArg);
+        }
+        Ok(Arc::new(IsoFile {
+            fs: self.clone(),
+            entry,
+        }))
+    }
+}
+
+impl BackingFile for IsoFile {
+    fn len(&self) -> u64 {
+        self.entry.size as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> bool {
+        self.fs.read(&self.entry, offset, buf) == Ok(buf.len())
+    }
+}
+
+/* -------------------------------- El Torito --------------------------------- */
+
+impl Iso9660 {
+    /// Initial/default entry of the El Torito boot catalog, if present and valid.
+    pub fn boot_entry(&self) -> Option<BootEntry> {
+        let cat = self.boot_catalog?;
+        let mut sec = vec![0u8; SECTOR_SIZE];
+        if !read_sector(self.dev.as_ref(), cat, &mut sec) {
+            return None;
+        }
+
+        // Validation entry: header 0x01, key 55 AA, words sum to zero.
+        if sec[0] != 0x01 || sec[30] != 0x55 || sec[31] != 0xAA {
+            return None;
+        }
+        let sum = (0..16).fold(0u16, |acc, i| acc.wrapping_add(le16(&sec, i * 2)));
+        if sum != 0 {
+            return None;
+        }
+
+        let e = &sec[32..64];
+        Some(BootEntry {
+            bootable: e[0] == 0x88,
+            media: e[1] & 0x0F,
+            load_segment: le16(e, 2),
+            sector_count: le16(e, 6),
+            load_rba: le32(e, 8),
+        })
+    }
+}
@@ -0,0 +1,5 @@
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+pub mod iso9660;
+pub mod notify;
+pub mod ramfs;
@@ -0,0 +1,198 @@
+// src/fs/notify.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Change notification, inotify-style. A `Watcher` on a path receives events
+// for that path and, when it is a directory, for its direct children.
+// Filesystems report changes with `notify(kind, path)`; every matching
+// watcher gets a copy in its own bounded queue. A full queue drops the event
+// and reports one `Overflow` instead, after which the reader should rescan.
+// Dropping a `Watcher` unregisters it.
+
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::sched::{self, TaskId};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const QUEUE_LEN: usize = 64;
+
+// Watch masks
+pub const CREATE: u8 = 1 << 0;
+pub const MODIFY: u8 = 1 << 1;
+pub const DELETE: u8 = 1 << 2;
+pub const ALL: u8 = CREATE | MODIFY | DELETE;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    Create,
+    Modify,
+    Delete,
+    Overflow, // events were dropped; `path` is the watched path
+}
+
+impl EventKind {
+    fn mask(self) -> u8 {
+        match self {
+            EventKind::Create => CREATE,
+            EventKind::Modify => MODIFY,
+            EventKind::Delete => DELETE,
+            EventKind::Overflow => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub path: String,
+}
+
+struct State {
+    events: VecDeque<Event>,
+    overflowed: bool,
+    sleeper: Option<TaskId>,
+}
+
+struct Watch {
+    path: String,
+    mask: u8,
+    state: Mutex<State>,
+}
+
+/// Receiving end of a watch.
+pub struct Watcher {
+    watch: Arc<Watch>,
+}
+
+static WATCHES: Mutex<Vec<Weak<Watch>>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// "/a/b/" and "/a/b" name the same thing.
+fn normalize(path: &str) -> &str {
+    let p = path.trim_end_matches('/');
+    if p.is_empty() { "/" } else { p }
+}
+
+fn parent(path: &str) -> Option<&str> {
+    let i = path.rfind('/')?;
+    Some(if i == 0 { "/" } else { &path[..i] })
+}
+
+impl Watch {
+    fn matches(&self, path: &str) -> bool {
+        path == self.path || parent(path) == Some(self.path.as_str())
+    }
+
+    // IRQs stay off while held: notify() may run in any context.
+    fn with<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    fn push(&self, ev: Event) {
+        let sleeper = self.with(|s| {
+            if s.events.len() < QUEUE_LEN {
+                s.events.push_back(ev);
+            } else {
+                s.overflowed = true;
+            }
+            s.sleeper.take()
+        });
+        if let Some(id) = sleeper {
+            sched::wake(id);
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Watch `path` for the event kinds in `mask`.
+pub fn watch(path: &str, mask: u8) -> Watcher {
+    let watch = Arc::new(Watch {
+        path: String::from(normalize(path)),
+        mask,
+        state: Mutex::new(State {
+            events: VecDeque::new(),
+            overflowed: false,
+            sleeper: None,
+        }),
+    });
+    without_interrupts(|| {
+        let mut w = WATCHES.lock();
+        w.retain(|x| x.strong_count() > 0);
+        w.push(Arc::downgrade(&watch));
+    });
+    Watcher { watch }
+}
+
+/// Report a change to `path`. Called by filesystems after the change is
+/// visible, so a woken watcher sees the new state.
+pub fn notify(kind: EventKind, path: &str) {
+    let path = normalize(path);
+    let targets: Vec<Arc<Watch>> = without_interrupts(|| {
+        WATCHES
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|w| w.mask & kind.mask() != 0 && w.matches(path))
+            .collect()
+    });
+    for w in targets {
+        w.push(Event {
+            kind,
+            path: String::from(path),
+        });
+    }
+}
+
+impl Watcher {
+    /// Next event if one is queued. An overflow is reported once queued
+    /// events have been read.
+    pub fn try_recv(&self) -> Option<Event> {
+        let path = &self.watch.path;
+        self.watch.with(|s| {
+            s.events.pop_front().or_else(|| {
+                if !s.overflowed {
+                    return None;
+                }
+                s.overflowed = false;
+                Some(Event {
+                    kind: EventKind::Overflow,
+                    path: path.clone(),
+                })
+            })
+        })
+    }
+
+    /// Sleep the current task until an event arrives.
+    pub fn recv(&self) -> Event {
+        let me = sched::current_id();
+        loop {
+            if let Some(ev) = self.try_recv() {
+                return ev;
+            }
+            let idle = self.watch.with(|s| {
+                let idle = s.events.is_empty() && !s.overflowed;
+                if idle {
+                    s.sleeper = me;
+                }
+                idle
+            });
+            if !idle {
+                continue;
+            }
+            if me.is_some() {
+                sched::block_current();
+            } else {
+                core::hint::spin_loop(); // before the scheduler is up
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// src/fs/ramfs.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Kernel-wide in-memory file store: absolute paths to byte contents, with
+// directories implied by the paths beneath them. Every change is reported
+// through `fs::notify`. Meant for small control files such as /config.
+
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::notify::{self, EventKind};
+use crate::error::{KError, KResult};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_FILE: usize = 64 * 1024;
+
+static FILES: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/* -------------------------------- Public API -------------------------------- */
+
+// InvalidArg if `path` is not absolute or names a directory.
+fn check(path: &str) -> KResult<()> {
+    if !path.starts_with('/') || path.ends_with('/') || path.contains("//") {
+        return Err(KError::InvalidArg);
+    }
+    Ok(())
+}
+
+/// Create or replace `path`.
+pub fn write(path: &str, data: &[u8]) -> KResult<()> {
+    check(path)?;
+    if data.len() > MAX_FILE {
+        return Err(KError::TooLarge);
+    }
+    let existed = without_interrupts(|| {
+        FILES
+            .lock()
+            .insert(String::from(path), data.to_vec())
+            .is_some()
+    });
+    let kind = if existed {
+        EventKind::Modify
+    } else {
+        EventKind::Create
+    };
+    notify::notify(kind, path);
+    Ok(())
+}
+
+pub fn read(path: &str) -> KResult<Vec<u8>> {
+    without_interrupts(|| FILES.lock().get(path).cloned()).ok_or(KError::NotFound)
+}
+
+// Nothing deletes control files yet; the config watcher already handles it.
+#[allow(dead_code)]
+pub fn remove(path: &str) -> KResult<()> {
+    without_interrupts(|| FILES.lock().remove(path)).ok_or(KError::NotFound)?;
+    notify::notify(EventKind::Delete, path);
+    Ok(())
+}
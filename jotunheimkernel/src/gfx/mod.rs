@@ -0,0 +1,284 @@
+// src/gfx/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Small 2D drawing layer for kernel UIs (framebuffer console, boot splash,
+// panic screen). A `Surface` is anything pixels can be stored into: a mapped
+// `video::Screen` or an in-memory `Canvas`. `Painter` draws on one through a
+// clip rectangle. Colours are 0xRRGGBB throughout; surfaces convert to their
+// own layout on store.
+
+pub mod font;
+pub mod qr;
+
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::video::Screen;
+pub use font::Font;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const BLACK: u32 = 0x000000;
+pub const WHITE: u32 = 0xFFFFFF;
+pub const GREY: u32 = 0xC0C0C0;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+/// How `Painter::text` draws: font, integer scale, colours. A `bg` of None
+/// leaves the pixels between strokes alone.
+#[derive(Copy, Clone)]
+pub struct TextStyle {
+    pub font: &'static Font,
+    pub scale: usize,
+    pub fg: u32,
+    pub bg: Option<u32>,
+}
+
+pub trait Surface {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// Store `rgb` at (x, y), which the caller has already clipped.
+    fn set(&mut self, x: usize, y: usize, rgb: u32);
+
+    /// Store `len` pixels of `rgb` from (x, y) rightwards, already clipped.
+    fn span(&mut self, x: usize, y: usize, len: usize, rgb: u32) {
+        for i in 0..len {
+            self.set(x + i, y, rgb);
+        }
+    }
+}
+
+/// Off-screen surface; also the source for `Painter::blit`.
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+pub struct Painter<'a, S: Surface + ?Sized> {
+    surface: &'a mut S,
+    clip: Rect,
+}
+
+/* ---------------------------------- Rects ----------------------------------- */
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.h
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w <= 0 || self.h <= 0
+    }
+
+    pub fn intersect(&self, o: &Rect) -> Rect {
+        let x = self.x.max(o.x);
+        let y = self.y.max(o.y);
+        Rect {
+            x,
+            y,
+            w: (self.right().min(o.right()) - x).max(0),
+            h: (self.bottom().min(o.bottom()) - y).max(0),
+        }
+    }
+
+    /// `self` shrunk by `by` pixels on every side.
+    pub fn inset(&self, by: i32) -> Rect {
+        Rect::new(self.x + by, self.y + by, self.w - 2 * by, self.h - 2 * by)
+    }
+}
+
+impl TextStyle {
+    pub const fn new(font: &'static Font, fg: u32) -> Self {
+        Self {
+            font,
+            scale: 1,
+            fg,
+            bg: None,
+        }
+    }
+
+    pub const fn scaled(self, scale: usize) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub const fn on(self, bg: u32) -> Self {
+        Self {
+            bg: Some(bg),
+            ..self
+        }
+    }
+
+    /// Size of one character cell.
+    pub fn cell(&self) -> (i32, i32) {
+        let s = self.scale.max(1);
+        ((self.font.width * s) as i32, (self.font.height * s) as i32)
+    }
+
+    /// Pixel size of `s` drawn on one line.
+    pub fn size(&self, s: &str) -> (i32, i32) {
+        let (w, h) = self.cell();
+        (s.len() as i32 * w, h)
+    }
+}
+
+/* --------------------------------- Surfaces --------------------------------- */
+
+impl Surface for &Screen {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn set(&mut self, x: usize, y: usize, rgb: u32) {
+        self.put(x, y, self.color(rgb));
+    }
+
+    fn span(&mut self, x: usize, y: usize, len: usize, rgb: u32) {
+        self.fill(x, y, len, 1, self.color(rgb));
+    }
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize, rgb: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![rgb; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn painter(&mut self) -> Painter<'_, Self> {
+        Painter::new(self)
+    }
+}
+
+impl Surface for Canvas {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn set(&mut self, x: usize, y: usize, rgb: u32) {
+        self.pixels[y * self.width + x] = rgb;
+    }
+
+    fn span(&mut self, x: usize, y: usize, len: usize, rgb: u32) {
+        let at = y * self.width + x;
+        self.pixels[at..at + len].fill(rgb);
+    }
+}
+
+/* --------------------------------- Painting --------------------------------- */
+
+impl<'a, S: Surface + ?Sized> Painter<'a, S> {
+    pub fn new(surface: &'a mut S) -> Self {
+        let clip = Rect::new(0, 0, surface.width() as i32, surface.height() as i32);
+        Self { surface, clip }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            0,
+            0,
+            self.surface.width() as i32,
+            self.surface.height() as i32,
+        )
+    }
+
+    /// Restrict drawing to `r` (always within the surface).
+    pub fn set_clip(&mut self, r: Rect) {
+        self.clip = r.intersect(&self.bounds());
+    }
+
+    pub fn fill(&mut self, r: Rect, rgb: u32) {
+        let r = r.intersect(&self.clip);
+        if r.is_empty() {
+            return;
+        }
+        for y in r.y..r.bottom() {
+            self.surface
+                .span(r.x as usize, y as usize, r.w as usize, rgb);
+        }
+    }
+
+    /// Outline of `r`, `t` pixels thick, drawn inside it.
+    pub fn frame(&mut self, r: Rect, t: i32, rgb: u32) {
+        self.fill(Rect::new(r.x, r.y, r.w, t), rgb);
+        self.fill(Rect::new(r.x, r.bottom() - t, r.w, t), rgb);
+        self.fill(Rect::new(r.x, r.y + t, t, r.h - 2 * t), rgb);
+        self.fill(Rect::new(r.right() - t, r.y + t, t, r.h - 2 * t), rgb);
+    }
+
+    /// Copy `src_r` of `src` so its top-left lands on (x, y).
+    pub fn blit(&mut self, src: &Canvas, src_r: Rect, x: i32, y: i32) {
+        let src_r = src_r.intersect(&Rect::new(0, 0, src.width as i32, src.height as i32));
+        let dst = Rect::new(x, y, src_r.w, src_r.h).intersect(&self.clip);
+        for dy in dst.y..dst.bottom() {
+            for dx in dst.x..dst.right() {
+                let sx = (src_r.x + dx - x) as usize;
+                let sy = (src_r.y + dy - y) as usize;
+                self.surface.set(dx as usize, dy as usize, src.get(sx, sy));
+            }
+        }
+    }
+
+    /// One character at (x, y), each font pixel drawn as a `scale` square.
+    pub fn glyph(&mut self, x: i32, y: i32, c: u8, st: &TextStyle) {
+        let s = st.scale.max(1) as i32;
+        let (w, h) = st.cell();
+        let vis = Rect::new(x, y, w, h).intersect(&self.clip);
+        if vis.is_empty() {
+            return;
+        }
+        let rows = st.font.glyph(c);
+        for py in vis.y..vis.bottom() {
+            let bits = rows[((py - y) / s) as usize];
+            for px in vis.x..vis.right() {
+                let on = bits & (0x80 >> ((px - x) / s)) != 0;
+                match (on, st.bg) {
+                    (true, _) => self.surface.set(px as usize, py as usize, st.fg),
+                    (false, Some(b)) => self.surface.set(px as usize, py as usize, b),
+                    (false, None) => {}
+                }
+            }
+        }
+    }
+
+    /// Draw a single line of text; returns the x just past it. Control
+    /// characters are not interpreted.
+    pub fn text(&mut self, x: i32, y: i32, s: &str, st: &TextStyle) -> i32 {
+        let adv = st.cell().0;
+        let mut cx = x;
+        for b in s.bytes() {
+            self.glyph(cx, y, b, st);
+            cx += adv;
+        }
+        cx
+    }
+}
@@ -0,0 +1,543 @@
+// src/gfx/qr.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// QR Code (ISO/IEC 18004) encoder for the panic screen: byte mode, ECC level
+// L or M, versions 1..=MAX_VERSION. Heap-free so it can run from the panic
+// handler; a `QrCode` is a few KiB, so callers keep one in a static. The
+// smallest version that fits is chosen and all eight masks are scored with
+// the standard penalty rules.
+
+use super::{Painter, Rect, Surface};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_VERSION: usize = 25;
+pub const MAX_SIZE: usize = MAX_VERSION * 4 + 17; // modules per side, <= 128
+const MAX_CODEWORDS: usize = raw_data_modules(MAX_VERSION) / 8;
+const MAX_ECC_LEN: usize = 30;
+
+const _: () = assert!(MAX_SIZE <= 128); // rows are u128 bitmaps
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Ecc {
+    Low,    // ~7% recoverable
+    Medium, // ~15% recoverable
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum QrError {
+    TooLong,
+}
+
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    ecc: Ecc,
+    dark: [u128; MAX_SIZE],
+    function: [u128; MAX_SIZE], // finder/timing/alignment/format/version
+}
+
+// Indexed by version; entry 0 unused.
+#[rustfmt::skip]
+const ECC_PER_BLOCK: [[u8; 41]; 2] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28,
+     28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26,
+     26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+];
+
+#[rustfmt::skip]
+const ECC_BLOCKS: [[u8; 41]; 2] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8,
+     8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16,
+     17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+];
+
+impl Ecc {
+    fn row(self) -> usize {
+        match self {
+            Ecc::Low => 0,
+            Ecc::Medium => 1,
+        }
+    }
+
+    fn format_bits(self) -> u32 {
+        match self {
+            Ecc::Low => 1,
+            Ecc::Medium => 0,
+        }
+    }
+}
+
+/* ------------------------------- Capacities -------------------------------- */
+
+/// Data + ECC modules left once all function patterns are placed.
+const fn raw_data_modules(ver: usize) -> usize {
+    let mut n = (16 * ver + 128) * ver + 64;
+    if ver >= 2 {
+        let align = ver / 7 + 2;
+        n -= (25 * align - 10) * align - 55;
+        if ver >= 7 {
+            n -= 36;
+        }
+    }
+    n
+}
+
+fn data_codewords(ver: usize, ecc: Ecc) -> usize {
+    let r = ecc.row();
+    raw_data_modules(ver) / 8 - ECC_PER_BLOCK[r][ver] as usize * ECC_BLOCKS[r][ver] as usize
+}
+
+/// Largest byte-mode payload that fits in `ver` at `ecc`.
+pub fn capacity(ver: usize, ecc: Ecc) -> usize {
+    let count_bits = if ver <= 9 { 8 } else { 16 };
+    (data_codewords(ver, ecc) * 8 - 4 - count_bits) / 8
+}
+
+/* ------------------------------ Reed-Solomon ------------------------------- */
+
+fn gf_mul(mut x: u8, mut y: u8) -> u8 {
+    let mut z = 0u8;
+    while y != 0 {
+        if y & 1 != 0 {
+            z ^= x;
+        }
+        let carry = x & 0x80 != 0;
+        x <<= 1;
+        if carry {
+            x ^= 0x1D; // x^8 + x^4 + x^3 + x^2 + 1
+        }
+        y >>= 1;
+    }
+    z
+}
+
+fn rs_divisor(degree: usize, out: &mut [u8; MAX_ECC_LEN]) {
+    out.fill(0);
+    out[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            out[j] = gf_mul(out[j], root);
+            if j + 1 < degree {
+                out[j] ^= out[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+}
+
+fn rs_remainder(data: &[u8], div: &[u8], out: &mut [u8]) {
+    out.fill(0);
+    let n = out.len();
+    for &b in data {
+        let factor = b ^ out[0];
+        out.copy_within(1.., 0);
+        out[n - 1] = 0;
+        for i in 0..n {
+            out[i] ^= gf_mul(div[i], factor);
+        }
+    }
+}
+
+/* --------------------------------- Encoding --------------------------------- */
+
+struct Bits<'a> {
+    buf: &'a mut [u8],
+    len: usize, // in bits
+}
+
+impl Bits<'_> {
+    fn push(&mut self, val: u32, n: usize) {
+        for i in (0..n).rev() {
+            if val >> i & 1 != 0 {
+                self.buf[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+impl QrCode {
+    pub const fn new() -> Self {
+        Self {
+            version: 0,
+            size: 0,
+            ecc: Ecc::Low,
+            dark: [0; MAX_SIZE],
+            function: [0; MAX_SIZE],
+        }
+    }
+
+    /// Modules per side, without the quiet zone.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.dark[y] >> x & 1 != 0
+    }
+
+    /// Encode `data` in the smallest version that holds it at `ecc`.
+    pub fn encode(&mut self, data: &[u8], ecc: Ecc) -> Result<(), QrError> {
+        let ver = (1..=MAX_VERSION)
+            .find(|&v| capacity(v, ecc) >= data.len())
+            .ok_or(QrError::TooLong)?;
+        self.version = ver;
+        self.size = ver * 4 + 17;
+        self.ecc = ecc;
+        self.dark = [0; MAX_SIZE];
+        self.function = [0; MAX_SIZE];
+
+        let mut cw = [0u8; MAX_CODEWORDS];
+        let ndata = data_codewords(ver, ecc);
+        let mut bits = Bits {
+            buf: &mut cw[..ndata],
+            len: 0,
+        };
+        bits.push(0b0100, 4); // byte mode
+        bits.push(data.len() as u32, if ver <= 9 { 8 } else { 16 });
+        for &b in data {
+            bits.push(b as u32, 8);
+        }
+        let cap = ndata * 8;
+        bits.push(0, (cap - bits.len).min(4));
+        bits.len = bits.len.next_multiple_of(8);
+        for pad in [0xEC, 0x11].iter().cycle() {
+            if bits.len >= cap {
+                break;
+            }
+            bits.push(*pad, 8);
+        }
+
+        let mut all = [0u8; MAX_CODEWORDS];
+        let total = self.interleave(&cw[..ndata], &mut all);
+
+        self.draw_function_patterns();
+        self.draw_codewords(&all[..total]);
+
+        let mut best = (u32::MAX, 0);
+        for mask in 0..8 {
+            self.apply_mask(mask);
+            self.draw_format(mask);
+            let p = self.penalty();
+            if p < best.0 {
+                best = (p, mask);
+            }
+            self.apply_mask(mask); // XOR undoes it
+        }
+        self.apply_mask(best.1);
+        self.draw_format(best.1);
+        Ok(())
+    }
+
+    // Split into blocks, append each block's ECC and interleave (7.6).
+    fn interleave(&self, data: &[u8], out: &mut [u8]) -> usize {
+        let r = self.ecc.row();
+        let nblocks = ECC_BLOCKS[r][self.version] as usize;
+        let ecc_len = ECC_PER_BLOCK[r][self.version] as usize;
+        let raw = raw_data_modules(self.version) / 8;
+        let nshort = nblocks - raw % nblocks;
+        let short_len = raw / nblocks; // data + ecc of a short block
+
+        let mut div = [0u8; MAX_ECC_LEN];
+        rs_divisor(ecc_len, &mut div);
+
+        let mut at = 0;
+        let mut k = 0;
+        for b in 0..nblocks {
+            let dlen = short_len - ecc_len + (b >= nshort) as usize;
+            let block = &data[k..k + dlen];
+            k += dlen;
+            let mut ecc = [0u8; MAX_ECC_LEN];
+            rs_remainder(block, &div[..ecc_len], &mut ecc[..ecc_len]);
+            // Data codeword i of block b lands at i * nblocks + b, except the
+            // extra last codeword of long blocks, which all follow the rest.
+            for (i, &d) in block.iter().enumerate() {
+                let pos = if i < short_len - ecc_len {
+                    i * nblocks + b
+                } else {
+                    (short_len - ecc_len) * nblocks + (b - nshort)
+                };
+                out[pos] = d;
+                at += 1;
+            }
+            let dtotal = data.len();
+            for (i, &e) in ecc[..ecc_len].iter().enumerate() {
+                out[dtotal + i * nblocks + b] = e;
+                at += 1;
+            }
+        }
+        at
+    }
+
+    /* ------------------------------- Modules ------------------------------- */
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        let bit = 1u128 << x;
+        self.function[y] |= bit;
+        if dark {
+            self.dark[y] |= bit;
+        } else {
+            self.dark[y] &= !bit;
+        }
+    }
+
+    fn is_function(&self, x: usize, y: usize) -> bool {
+        self.function[y] >> x & 1 != 0
+    }
+
+    fn draw_function_patterns(&mut self) {
+        let n = self.size;
+        for i in 0..n {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        self.draw_finder(3, 3);
+        self.draw_finder(n - 4, 3);
+        self.draw_finder(3, n - 4);
+
+        let (pos, count) = self.alignment_positions();
+        for i in 0..count {
+            for j in 0..count {
+                let corner =
+                    (i == 0 && j == 0) || (i == 0 && j == count - 1) || (i == count - 1 && j == 0);
+                if !corner {
+                    self.draw_alignment(pos[i], pos[j]);
+                }
+            }
+        }
+
+        self.draw_format(0); // reserve; real bits come with the mask
+        self.draw_version();
+    }
+
+    fn draw_finder(&mut self, cx: usize, cy: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                if x < 0 || y < 0 || x >= self.size as i32 || y >= self.size as i32 {
+                    continue;
+                }
+                let dist = dx.abs().max(dy.abs());
+                self.set_function(x as usize, y as usize, dist != 2 && dist != 4);
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, cx: usize, cy: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                let dist = dx.abs().max(dy.abs());
+                let (x, y) = ((cx as i32 + dx) as usize, (cy as i32 + dy) as usize);
+                self.set_function(x, y, dist != 1);
+            }
+        }
+    }
+
+    fn alignment_positions(&self) -> ([usize; 7], usize) {
+        let mut pos = [0; 7];
+        if self.version == 1 {
+            return (pos, 0);
+        }
+        let count = self.version / 7 + 2;
+        let step = if self.version == 32 {
+            26
+        } else {
+            (self.version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+        };
+        pos[0] = 6;
+        let mut p = self.size - 7;
+        for i in (1..count).rev() {
+            pos[i] = p;
+            p -= step;
+        }
+        (pos, count)
+    }
+
+    fn draw_format(&mut self, mask: u32) {
+        let data = self.ecc.format_bits() << 3 | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: usize| bits >> i & 1 != 0;
+        let n = self.size;
+
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(n - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, n - 15 + i, bit(i));
+        }
+        self.set_function(8, n - 8, true); // always dark
+    }
+
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let mut rem = self.version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = (self.version as u32) << 12 | rem;
+        for i in 0..18 {
+            let dark = bits >> i & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    // Zig-zag two-module columns from the bottom right (7.7.3).
+    fn draw_codewords(&mut self, cw: &[u8]) {
+        let n = self.size;
+        let mut i = 0;
+        let mut right = n as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..n {
+                for j in 0..2 {
+                    let x = right as usize - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { n - 1 - vert } else { vert };
+                    if !self.is_function(x, y) && i < cw.len() * 8 {
+                        if cw[i / 8] >> (7 - i % 8) & 1 != 0 {
+                            self.dark[y] |= 1 << x;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if flip && !self.is_function(x, y) {
+                    self.dark[y] ^= 1 << x;
+                }
+            }
+        }
+    }
+
+    /* ------------------------------- Scoring ------------------------------- */
+
+    fn line_penalty(&self, get: impl Fn(usize) -> bool) -> u32 {
+        let n = self.size;
+        let mut p = 0;
+        // N1: runs of five or more
+        let mut run = 1;
+        for i in 1..n {
+            if get(i) == get(i - 1) {
+                run += 1;
+                if run == 5 {
+                    p += 3;
+                } else if run > 5 {
+                    p += 1;
+                }
+            } else {
+                run = 1;
+            }
+        }
+        // N3: 1:1:3:1:1 finder look-alikes with four light modules either side
+        const PAT: [bool; 7] = [true, false, true, true, true, false, true];
+        for i in 0..n.saturating_sub(6) {
+            if (0..7).all(|k| get(i + k) == PAT[k]) {
+                let light =
+                    |a: i32, b: i32| (a..b).all(|j| j < 0 || j >= n as i32 || !get(j as usize));
+                if light(i as i32 - 4, i as i32) || light(i as i32 + 7, i as i32 + 11) {
+                    p += 40;
+                }
+            }
+        }
+        p
+    }
+
+    fn penalty(&self) -> u32 {
+        let n = self.size;
+        let mut p = 0;
+        for y in 0..n {
+            p += self.line_penalty(|x| self.get(x, y));
+        }
+        for x in 0..n {
+            p += self.line_penalty(|y| self.get(x, y));
+        }
+        // N2: 2x2 blocks of one colour
+        for y in 0..n - 1 {
+            for x in 0..n - 1 {
+                let c = self.get(x, y);
+                if c == self.get(x + 1, y) && c == self.get(x, y + 1) && c == self.get(x + 1, y + 1)
+                {
+                    p += 3;
+                }
+            }
+        }
+        // N4: dark/light balance, 10 per 5% away from half
+        let dark: u32 = self.dark[..n].iter().map(|r| r.count_ones()).sum();
+        let total = (n * n) as u32;
+        let k = (dark * 20)
+            .abs_diff(total * 10)
+            .div_ceil(total)
+            .saturating_sub(1);
+        p + k * 10
+    }
+
+    /* ------------------------------- Drawing ------------------------------- */
+
+    /// Draw at (x, y) with `scale`-pixel modules and a four-module light
+    /// quiet zone; returns the rectangle covered.
+    pub fn draw<S: Surface + ?Sized>(
+        &self,
+        p: &mut Painter<'_, S>,
+        x: i32,
+        y: i32,
+        scale: i32,
+        dark: u32,
+        light: u32,
+    ) -> Rect {
+        let quiet = 4 * scale;
+        let side = self.size as i32 * scale + 2 * quiet;
+        let all = Rect::new(x, y, side, side);
+        p.fill(all, light);
+        for my in 0..self.size {
+            for mx in 0..self.size {
+                if self.get(mx, my) {
+                    let px = x + quiet + mx as i32 * scale;
+                    let py = y + quiet + my as i32 * scale;
+                    p.fill(Rect::new(px, py, scale, scale), dark);
+                }
+            }
+        }
+        all
+    }
+}
@@ -0,0 +1,192 @@
+// src/init.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Staged boot. Each subsystem is an `Initcall` with a stage and the names of
+// the initcalls it needs. The table is checked once before anything runs
+// (unknown names, dependencies on a later stage, cycles) so a wrong order
+// fails loudly at boot instead of as a crash inside some init. Within a
+// stage initcalls run in dependency order, ties in table order. Each one is
+// timed; one that fails takes everything depending on it down with it.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use heapless::Vec;
+use spin::Once;
+
+use crate::arch::x86_64::tsc;
+use crate::bootinfo::BootInfo;
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_INITCALLS: usize = 64;
+
+/// Boot stages in run order. `Early..=Sched` run from `_start` with
+/// interrupts off; the rest run on the kernel main thread.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    Early,    // copy what the loader handed over
+    Memory,   // frames, paging, heap
+    Arch,     // CPU tables, APIC, per-CPU state
+    Sched,    // scheduler and what hooks into it
+    Services, // kernel threads and executors
+    Devices,  // buses and drivers
+    Smp,      // application processors
+    Late,     // cleanup once everything is up
+}
+
+pub type InitResult = Result<(), &'static str>;
+
+pub struct Initcall {
+    pub name: &'static str,
+    pub stage: Stage,
+    pub deps: &'static [&'static str],
+    pub run: fn(&BootInfo) -> InitResult,
+}
+
+// Per-initcall outcome, indexed like the table.
+const PENDING: u8 = 0;
+const OK: u8 = 1;
+const FAILED: u8 = 2;
+const SKIPPED: u8 = 3;
+
+static TABLE: Once<&'static [Initcall]> = Once::new();
+static ORDER: Once<Vec<u8, MAX_INITCALLS>> = Once::new();
+static STATUS: [AtomicU8; MAX_INITCALLS] = [const { AtomicU8::new(PENDING) }; MAX_INITCALLS];
+
+/* -------------------------------- Ordering --------------------------------- */
+
+fn index_of(table: &[Initcall], name: &str) -> Option<usize> {
+    table.iter().position(|c| c.name == name)
+}
+
+// Kahn's algorithm, always taking the ready initcall with the lowest
+// (stage, table index), so the result never goes back a stage.
+fn sort(table: &[Initcall]) -> Vec<u8, MAX_INITCALLS> {
+    assert!(table.len() <= MAX_INITCALLS, "init: too many initcalls");
+    for c in table {
+        for &d in c.deps {
+            let Some(i) = index_of(table, d) else {
+                panic!("init: {} depends on unknown '{}'", c.name, d);
+            };
+            if table[i].stage > c.stage {
+                panic!(
+                    "init: {} ({:?}) depends on {} from later stage {:?}",
+                    c.name, c.stage, d, table[i].stage
+                );
+            }
+        }
+    }
+
+    let mut done = [false; MAX_INITCALLS];
+    let mut order = Vec::new();
+    while order.len() < table.len() {
+        let next = (0..table.len())
+            .filter(|&i| !done[i])
+            .filter(|&i| {
+                table[i]
+                    .deps
+                    .iter()
+                    .all(|d| index_of(table, d).is_some_and(|j| done[j]))
+            })
+            .min_by_key(|&i| (table[i].stage, i));
+        let Some(i) = next else {
+            let stuck = (0..table.len()).find(|&i| !done[i]).unwrap();
+            panic!("init: dependency cycle through {}", table[stuck].name);
+        };
+        done[i] = true;
+        let _ = order.push(i as u8);
+    }
+    order
+}
+
+/* --------------------------------- Running ---------------------------------- */
+
+fn ms(cycles: u64, hz: u64) -> (u64, u64) {
+    let us = (cycles as u128 * 1_000_000 / hz as u128) as u64;
+    (us / 1000, us % 1000)
+}
+
+fn run_one(table: &[Initcall], i: usize, boot: &BootInfo, hz: u64) {
+    let c = &table[i];
+    let blocked = c
+        .deps
+        .iter()
+        .filter_map(|d| index_of(table, d))
+        .find(|&j| STATUS[j].load(Ordering::Relaxed) != OK);
+    if let Some(j) = blocked {
+        STATUS[i].store(SKIPPED, Ordering::Relaxed);
+        kprintln!("[init] {:<20} skipped (needs {})", c.name, table[j].name);
+        return;
+    }
+    let t0 = tsc::rdtsc();
+    let r = (c.run)(boot);
+    let (whole, frac) = ms(tsc::rdtsc().wrapping_sub(t0), hz);
+    match r {
+        Ok(()) => {
+            STATUS[i].store(OK, Ordering::Relaxed);
+            kprintln!("[init] {:<20} ok     {}.{:03} ms", c.name, whole, frac);
+        }
+        Err(why) => {
+            STATUS[i].store(FAILED, Ordering::Relaxed);
+            kprintln!(
+                "[init] {:<20} FAILED {}.{:03} ms: {}",
+                c.name,
+                whole,
+                frac,
+                why
+            );
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Install and check the initcall table. Panics on a malformed graph.
+pub fn register(table: &'static [Initcall]) {
+    TABLE.call_once(|| table);
+    ORDER.call_once(|| sort(table));
+}
+
+/// Run every initcall of `stage` that has not run yet.
+pub fn run_stage(stage: Stage, boot: &BootInfo) {
+    let (Some(table), Some(order)) = (TABLE.get(), ORDER.get()) else {
+        return;
+    };
+    let hz = tsc::tsc_hz_estimate().max(1);
+    let t0 = tsc::rdtsc();
+    let mut n = 0;
+    for &i in order.iter() {
+        let i = i as usize;
+        if table[i].stage == stage && STATUS[i].load(Ordering::Relaxed) == PENDING {
+            run_one(table, i, boot, hz);
+            n += 1;
+        }
+    }
+    let (whole, frac) = ms(tsc::rdtsc().wrapping_sub(t0), hz);
+    kprintln!(
+        "[init] stage {:?}: {} initcalls, {}.{:03} ms",
+        stage,
+        n,
+        whole,
+        frac
+    );
+}
+
+/// Run stages `from..=to` in order.
+pub fn run_stages(from: Stage, to: Stage, boot: &BootInfo) {
+    for s in STAGES.iter().filter(|&&s| (from..=to).contains(&s)) {
+        run_stage(*s, boot);
+    }
+}
+
+pub const STAGES: [Stage; 8] = [
+    Stage::Early,
+    Stage::Memory,
+    Stage::Arch,
+    Stage::Sched,
+    Stage::Services,
+    Stage::Devices,
+    Stage::Smp,
+    Stage::Late,
+];
@@ -0,0 +1,167 @@
+// src/input/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Input events from every source (PS/2 and USB HID today; virtio-input later)
+// go through `report()`: they are timestamped, queued per device, and handed
+// to subscribers. Consumers either subscribe or drain queues at their pace.
+// Nothing consumes events yet besides the shell's `input` listing, so the
+// consumer half and the pointer kinds are allowed to sit unused.
+
+pub mod ps2;
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::sched;
+use crate::util::ring::OverwriteRing;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const QUEUE_LEN: usize = 64;
+
+pub type DeviceId = u16;
+pub type SubId = u32;
+type Subscriber = (SubId, fn(&InputEvent));
+
+/// Key codes are PC scan code set 1 make codes; extended keys carry 0xE0 in
+/// the high byte (e.g. right arrow is 0xE04D).
+pub type KeyCode = u16;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    Key { code: KeyCode, pressed: bool },
+    RelPointer { dx: i32, dy: i32, buttons: u8 },
+    AbsPointer { x: u32, y: u32, buttons: u8 },
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct InputEvent {
+    pub time: u64, // scheduler ticks (ms)
+    pub device: DeviceId,
+    pub kind: EventKind,
+}
+
+struct Device {
+    id: DeviceId,
+    name: &'static str,
+    queue: OverwriteRing<InputEvent, QUEUE_LEN>,
+}
+
+struct Registry {
+    devices: Vec<Device>,
+    subs: Vec<Subscriber>,
+    next_dev: DeviceId,
+    next_sub: SubId,
+}
+
+// Reported from IRQ handlers too, so always taken with IRQs off.
+static INPUT: Mutex<Registry> = Mutex::new(Registry {
+    devices: Vec::new(),
+    subs: Vec::new(),
+    next_dev: 0,
+    next_sub: 0,
+});
+
+fn with<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    without_interrupts(|| f(&mut INPUT.lock()))
+}
+
+/* -------------------------------- Producers --------------------------------- */
+
+pub fn register_device(name: &'static str) -> DeviceId {
+    with(|r| {
+        let id = r.next_dev;
+        r.next_dev += 1;
+        r.devices.push(Device {
+            id,
+            name,
+            queue: OverwriteRing::new(),
+        });
+        id
+    })
+}
+
+/// Queue an event for `dev` and notify subscribers. A full queue drops its
+/// oldest event: stale input is worth less than fresh input.
+pub fn report(dev: DeviceId, kind: EventKind) {
+    let ev = InputEvent {
+        time: sched::ticks(),
+        device: dev,
+        kind,
+    };
+    let mut subs = [None; 8];
+    with(|r| {
+        if let Some(d) = r.devices.iter_mut().find(|d| d.id == dev) {
+            d.queue.push(ev);
+        }
+        for (slot, &(_, f)) in subs.iter_mut().zip(r.subs.iter()) {
+            *slot = Some(f);
+        }
+    });
+    // Called outside the lock so subscribers may drain queues themselves.
+    for f in subs.into_iter().flatten() {
+        f(&ev);
+    }
+}
+
+/* -------------------------------- Consumers --------------------------------- */
+
+/// Call `f` for every future event (at most 8 subscribers are served).
+#[allow(dead_code)]
+pub fn subscribe(f: fn(&InputEvent)) -> SubId {
+    with(|r| {
+        let id = r.next_sub;
+        r.next_sub += 1;
+        r.subs.push((id, f));
+        id
+    })
+}
+
+#[allow(dead_code)]
+pub fn unsubscribe(id: SubId) {
+    with(|r| r.subs.retain(|&(s, _)| s != id));
+}
+
+/// Oldest queued event of one device.
+#[allow(dead_code)]
+pub fn poll(dev: DeviceId) -> Option<InputEvent> {
+    with(|r| r.devices.iter().find(|d| d.id == dev)?.queue.pop())
+}
+
+/// Oldest queued event across all devices.
+#[allow(dead_code)]
+pub fn poll_any() -> Option<InputEvent> {
+    with(|r| {
+        let d = r
+            .devices
+            .iter()
+            .filter(|d| !d.queue.is_empty())
+            .min_by_key(|d| d.queue.front().map(|e| e.time))?;
+        d.queue.pop()
+    })
+}
+
+#[allow(dead_code)]
+pub fn device_name(dev: DeviceId) -> Option<&'static str> {
+    with(|r| r.devices.iter().find(|d| d.id == dev).map(|d| d.name))
+}
+
+/// Call `f` with each device's id, name, queued event count and how many
+/// events its full queue has dropped. Skips the walk (returns false) if the
+/// registry is busy.
+pub fn for_each_device(mut f: impl FnMut(DeviceId, &'static str, usize, usize)) -> bool {
+    without_interrupts(|| {
+        let Some(r) = INPUT.try_lock() else {
+            return false;
+        };
+        for d in r.devices.iter() {
+            f(d.id, d.name, d.queue.len(), d.queue.dropped());
+        }
+        true
+    })
+}
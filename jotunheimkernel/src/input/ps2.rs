@@ -0,0 +1,128 @@
+// src/input/ps2.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// PS/2 keyboard on the i8042. IRQ 1 goes through the IOAPIC to an interrupt
+// thread that drains the controller; if the line cannot be had, an executor
+// task polls it instead. Scan code set 1 is what the controller translates
+// to by default.
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::port::Port;
+
+use super::{DeviceId, EventKind, KeyCode, register_device, report};
+use crate::irq::{self, Trigger, thread::IrqThread};
+use crate::kprintln;
+use crate::sched::executor;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DATA: u16 = 0x60;
+const STATUS: u16 = 0x64;
+const ST_OUTPUT_FULL: u8 = 1 << 0;
+const ST_AUX: u8 = 1 << 5; // byte came from the mouse port
+
+const POLL_TICKS: u64 = 10;
+
+pub const VECTOR: u8 = 0x50;
+const GSI: u32 = 1; // ISA IRQ 1; QEMU's MADT overrides only IRQ 0
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+static DECODER: Mutex<Decoder> = Mutex::new(Decoder { extended: false });
+static THREAD: Once<&'static IrqThread> = Once::new();
+
+/* --------------------------------- Decoding --------------------------------- */
+
+struct Decoder {
+    extended: bool,
+}
+
+impl Decoder {
+    fn feed(&mut self, b: u8) -> Option<(KeyCode, bool)> {
+        if b == 0xE0 {
+            self.extended = true;
+            return None;
+        }
+        let ext = core::mem::take(&mut self.extended);
+        if b == 0xE1 || b == 0xFA || b == 0xFE {
+            return None; // pause prefix, ACK, resend
+        }
+        let code = (b & 0x7F) as KeyCode | if ext { 0xE000 } else { 0 };
+        Some((code, b & 0x80 == 0))
+    }
+}
+
+fn read_byte() -> Option<(u8, bool)> {
+    let st = unsafe { Port::<u8>::new(STATUS).read() };
+    if st & ST_OUTPUT_FULL == 0 {
+        return None;
+    }
+    let b = unsafe { Port::<u8>::new(DATA).read() };
+    Some((b, st & ST_AUX != 0))
+}
+
+// Everything the controller has. Task context only: the interrupt thread
+// or the polling task, never both.
+fn drain(dev: DeviceId) {
+    let mut dec = DECODER.lock();
+    while let Some((b, aux)) = read_byte() {
+        if aux {
+            continue; // no mouse support yet
+        }
+        if let Some((code, pressed)) = dec.feed(b) {
+            report(dev, EventKind::Key { code, pressed });
+        }
+    }
+}
+
+fn threaded(dev: DeviceId) -> bool {
+    let line = match irq::register("ps2-kbd", GSI, VECTOR, Trigger::Edge) {
+        Ok(l) => l,
+        Err(e) => {
+            kprintln!("[ps2] no IRQ {} ({:?}); polling", GSI, e);
+            return false;
+        }
+    };
+    match irq::thread::request(line, move || drain(dev)) {
+        Ok(t) => {
+            THREAD.call_once(|| t);
+            // A byte that arrived before the thread existed raised its only
+            // edge already: collect it now.
+            t.hard_irq();
+            true
+        }
+        Err(e) => {
+            let _ = irq::unregister(GSI);
+            kprintln!("[ps2] no interrupt thread ({:?}); polling", e);
+            false
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// IRQ 1 handler: hand off to the interrupt thread.
+pub fn on_irq() {
+    match THREAD.get() {
+        Some(t) => t.hard_irq(),
+        None => crate::counter!("ps2.early_irq"),
+    }
+}
+
+/// Register the keyboard and take its interrupt, or poll it. Safe to call
+/// more than once. Needs the scheduler.
+pub fn init() {
+    if STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let dev: DeviceId = register_device("ps2-kbd");
+    if threaded(dev) {
+        return;
+    }
+    executor::spawn(async move {
+        loop {
+            drain(dev);
+            executor::sleep_ticks(POLL_TICKS).await;
+        }
+    });
+}
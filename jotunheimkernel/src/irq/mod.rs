@@ -0,0 +1,265 @@
+// src/irq/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Device interrupt lines routed through the IOAPIC, with per-IRQ CPU
+// affinity. Drivers `register()` a GSI and vector and call `note()` from
+// their handler; every line starts on the BSP. A balancer on the executor
+// wakes once a second, compares how many interrupts each CPU took, and moves
+// the busiest unpinned line off the busiest CPU to the quietest CPU its
+// affinity allows. `pin()` fixes a line to one CPU and keeps the balancer
+// away from it. A line with an interrupt thread (`thread`) takes the
+// thread along when it moves.
+
+pub mod poll;
+pub mod thread;
+
+extern crate alloc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::arch::x86_64::apic::lapic_id;
+use crate::arch::x86_64::{ioapic, smp};
+use crate::kprintln;
+use crate::sched::{self, TaskId, executor};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+// Affinity masks are bit-per-APIC-id; CPUs above MAX_CPUS are not used.
+pub use crate::arch::x86_64::apic::MAX_CPUS;
+pub use crate::arch::x86_64::ioapic::Trigger;
+pub const ALL_CPUS: u64 = u64::MAX;
+
+const BALANCE_TICKS: u64 = 1000;
+// Rebalance only when the busiest CPU took at least this many interrupts in
+// the last period and twice as many as the quietest.
+const MIN_LOAD: u64 = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum IrqError {
+    NoSuchIrq,
+    NoCpu, // mask names no online CPU
+}
+
+pub struct Irq {
+    pub name: &'static str,
+    pub gsi: u32,
+    pub vector: u8,
+    affinity: AtomicU64,
+    target: AtomicU32,
+    pinned: AtomicBool,
+    counts: [AtomicU64; MAX_CPUS], // total per CPU
+    seen: [AtomicU64; MAX_CPUS],   // counts at the last balance pass
+    thread: Once<TaskId>,          // interrupt thread, bound to `target`
+}
+
+/// One line's state, for listing.
+#[derive(Copy, Clone, Debug)]
+pub struct IrqInfo {
+    pub name: &'static str,
+    pub gsi: u32,
+    pub vector: u8,
+    pub target: u32,
+    pub affinity: u64,
+    pub pinned: bool,
+    pub total: u64,
+    pub thread: Option<TaskId>,
+}
+
+static IRQS: Mutex<Vec<&'static Irq>> = Mutex::new(Vec::new());
+static BALANCER: Once<()> = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn find(gsi: u32) -> Option<&'static Irq> {
+    without_interrupts(|| IRQS.lock().iter().find(|i| i.gsi == gsi).copied())
+}
+
+fn online_in(mask: u64) -> impl Iterator<Item = u32> {
+    smp::online_cpus().filter(move |&id| (id as usize) < MAX_CPUS && mask & 1 << id != 0)
+}
+
+impl Irq {
+    fn move_to(&self, cpu: u32) {
+        let old = self.target.swap(cpu, Ordering::Relaxed);
+        if old != cpu {
+            ioapic::set_dest(self.gsi, cpu);
+            if let Some(&id) = self.thread.get() {
+                let _ = sched::bind(id, Some(cpu));
+            }
+            kprintln!(
+                "[irq] {} (gsi {}): cpu {} -> {}",
+                self.name,
+                self.gsi,
+                old,
+                cpu
+            );
+        }
+    }
+
+    fn info(&self) -> IrqInfo {
+        IrqInfo {
+            name: self.name,
+            gsi: self.gsi,
+            vector: self.vector,
+            target: self.target.load(Ordering::Relaxed),
+            affinity: self.affinity.load(Ordering::Relaxed),
+            pinned: self.pinned.load(Ordering::Relaxed),
+            total: self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum(),
+            thread: self.thread.get().copied(),
+        }
+    }
+}
+
+/* --------------------------------- Balancer --------------------------------- */
+
+// One pass: per-CPU load over the last period, then at most one move.
+fn balance() {
+    let irqs: Vec<&'static Irq> = without_interrupts(|| IRQS.lock().clone());
+    let mut load = [0u64; MAX_CPUS];
+    let mut delta: Vec<u64> = Vec::with_capacity(irqs.len());
+    for irq in &irqs {
+        let mut d = 0;
+        for (cpu, (c, s)) in irq.counts.iter().zip(irq.seen.iter()).enumerate() {
+            let now = c.load(Ordering::Relaxed);
+            let n = now - s.swap(now, Ordering::Relaxed);
+            load[cpu] += n;
+            d += n;
+        }
+        delta.push(d);
+    }
+
+    let online: Vec<u32> = online_in(ALL_CPUS).collect();
+    let Some(&busy) = online.iter().max_by_key(|&&c| load[c as usize]) else {
+        return;
+    };
+    if load[busy as usize] < MIN_LOAD {
+        return;
+    }
+    // Heaviest movable line on the busy CPU, and the quietest CPU it may use.
+    let candidate = irqs
+        .iter()
+        .zip(delta.iter())
+        .filter(|(i, _)| {
+            i.target.load(Ordering::Relaxed) == busy && !i.pinned.load(Ordering::Relaxed)
+        })
+        .max_by_key(|(_, d)| **d);
+    let Some((irq, &d)) = candidate else {
+        return;
+    };
+    let Some(quiet) = online_in(irq.affinity.load(Ordering::Relaxed))
+        .filter(|&c| c != busy)
+        .min_by_key(|&c| load[c as usize])
+    else {
+        return;
+    };
+    // Worth it only if the move does not just swap which CPU is busy.
+    let (b, q) = (load[busy as usize], load[quiet as usize]);
+    if b >= 2 * q.max(1) && q + d < b {
+        crate::counter!("irq.rebalanced");
+        irq.move_to(quiet);
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Route `gsi` to `vector` on the BSP and start tracking it. The returned
+/// handle is what the handler passes to `note()`. Fails for a GSI the
+/// IOAPIC does not serve or one that is already registered.
+pub fn register(
+    name: &'static str,
+    gsi: u32,
+    vector: u8,
+    trigger: Trigger,
+) -> Result<&'static Irq, IrqError> {
+    if gsi >= ioapic::entries() || find(gsi).is_some() {
+        return Err(IrqError::NoSuchIrq);
+    }
+    let bsp = lapic_id();
+    let irq: &'static Irq = alloc::boxed::Box::leak(alloc::boxed::Box::new(Irq {
+        name,
+        gsi,
+        vector,
+        affinity: AtomicU64::new(ALL_CPUS),
+        target: AtomicU32::new(bsp),
+        pinned: AtomicBool::new(false),
+        counts: [const { AtomicU64::new(0) }; MAX_CPUS],
+        seen: [const { AtomicU64::new(0) }; MAX_CPUS],
+        thread: Once::new(),
+    }));
+    // Unmask only once the line is tracked, so the first interrupt counts.
+    ioapic::route(gsi, vector, bsp, trigger, true);
+    without_interrupts(|| IRQS.lock().push(irq));
+    ioapic::set_masked(gsi, false);
+    Ok(irq)
+}
+
+/// Mask `gsi` and stop tracking it, for a device that is gone. The handle
+/// stays valid (it is never freed), so a handler already running may still
+/// `note()` it.
+pub fn unregister(gsi: u32) -> Result<(), IrqError> {
+    find(gsi).ok_or(IrqError::NoSuchIrq)?;
+    ioapic::set_masked(gsi, true);
+    without_interrupts(|| IRQS.lock().retain(|i| i.gsi != gsi));
+    Ok(())
+}
+
+/// Count one interrupt on this CPU. Call from the handler.
+#[inline]
+pub fn note(irq: &Irq) {
+    if let Some(c) = irq.counts.get(lapic_id() as usize) {
+        c.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Restrict `gsi` to the CPUs in `mask`, moving it if its current CPU is
+/// no longer allowed.
+pub fn set_affinity(gsi: u32, mask: u64) -> Result<(), IrqError> {
+    let irq = find(gsi).ok_or(IrqError::NoSuchIrq)?;
+    let first = online_in(mask).next().ok_or(IrqError::NoCpu)?;
+    irq.affinity.store(mask, Ordering::Relaxed);
+    if mask & 1 << irq.target.load(Ordering::Relaxed) == 0 {
+        irq.move_to(first);
+    }
+    Ok(())
+}
+
+/// Put `gsi` on `cpu` and keep it there.
+pub fn pin(gsi: u32, cpu: u32) -> Result<(), IrqError> {
+    if cpu as usize >= MAX_CPUS {
+        return Err(IrqError::NoCpu);
+    }
+    set_affinity(gsi, 1 << cpu)?;
+    find(gsi)
+        .ok_or(IrqError::NoSuchIrq)?
+        .pinned
+        .store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Let the balancer move `gsi` again, anywhere.
+pub fn unpin(gsi: u32) -> Result<(), IrqError> {
+    let irq = find(gsi).ok_or(IrqError::NoSuchIrq)?;
+    irq.pinned.store(false, Ordering::Relaxed);
+    irq.affinity.store(ALL_CPUS, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn for_each(mut f: impl FnMut(IrqInfo)) {
+    let irqs: Vec<&'static Irq> = without_interrupts(|| IRQS.lock().clone());
+    irqs.iter().for_each(|i| f(i.info()));
+}
+
+/// Start the balancer. Needs the executor.
+pub fn init() {
+    BALANCER.call_once(|| {
+        executor::spawn(async {
+            loop {
+                executor::sleep_ticks(BALANCE_TICKS).await;
+                balance();
+            }
+        });
+    });
+}
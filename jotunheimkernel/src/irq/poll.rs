@@ -0,0 +1,207 @@
+// src/irq/poll.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Interrupt mitigation for network and block drivers, in the style of NAPI.
+// A driver's interrupt handler only calls `Poller::schedule()`: that turns
+// the device's interrupts off and wakes a polling future on the executor.
+// The future drains up to `budget` completions per pass and yields between
+// passes; once a pass comes up short it turns interrupts back on. Under a
+// burst the device is serviced by polling alone, with no interrupt per
+// completion. Devices with hardware moderation (virtio EVENT_IDX, NVMe
+// aggregation) are told the frame/time thresholds; for the rest the poller
+// waits out the time threshold itself before its first pass.
+
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::config::{self, Key};
+use crate::sched::executor::{self, IrqEvent};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// When a device should interrupt: after `frames` completions or `usecs`
+/// after the first one, whichever comes first. Zero disables a threshold.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Moderation {
+    pub frames: u32,
+    pub usecs: u32,
+}
+
+/// What a driver provides to be polled.
+pub trait PollDevice: Send + Sync {
+    /// Handle at most `budget` completions; returns how many were handled.
+    fn poll(&self, budget: usize) -> usize;
+    /// Stop the device interrupting. Called from the interrupt handler.
+    fn irq_disable(&self);
+    /// Let it interrupt again. Returns false if work arrived while
+    /// interrupts were off, which the device will not interrupt for.
+    fn irq_enable(&self) -> bool;
+    /// Program hardware moderation; false if the device has none.
+    fn moderate(&self, _m: Moderation) -> bool {
+        false
+    }
+}
+
+pub struct Poller {
+    pub name: &'static str,
+    dev: Arc<dyn PollDevice>,
+    ev: IrqEvent,
+    scheduled: AtomicBool, // interrupts off, a pass is coming
+    hw: AtomicBool,        // device moderates in hardware
+    interrupts: AtomicU64,
+    passes: AtomicU64,
+    done: AtomicU64,
+}
+
+/// One poller's counters, for listing.
+#[derive(Copy, Clone, Debug)]
+pub struct PollerInfo {
+    pub name: &'static str,
+    pub hw_moderation: bool,
+    pub interrupts: u64,
+    pub passes: u64,
+    pub completions: u64,
+}
+
+static BUDGET: AtomicU32 = AtomicU32::new(64);
+static FRAMES: AtomicU32 = AtomicU32::new(0);
+static USECS: AtomicU32 = AtomicU32::new(0);
+
+static POLLERS: Mutex<Vec<Arc<Poller>>> = Mutex::new(Vec::new());
+
+/* ------------------------------- Configuration ------------------------------ */
+
+/// Keys under `/config/napi`.
+pub const CONFIG_KEYS: &[Key] = &[
+    Key {
+        name: "budget",
+        get: || BUDGET.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 1, 4096),
+        apply: |v| BUDGET.store(v as u32, Ordering::Relaxed),
+    },
+    Key {
+        name: "coalesce_frames",
+        get: || FRAMES.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 0, 256),
+        apply: |v| {
+            FRAMES.store(v as u32, Ordering::Relaxed);
+            remoderate();
+        },
+    },
+    Key {
+        name: "coalesce_us",
+        get: || USECS.load(Ordering::Relaxed) as u64,
+        validate: |v| config::in_range(v, 0, 100_000),
+        apply: |v| {
+            USECS.store(v as u32, Ordering::Relaxed);
+            remoderate();
+        },
+    },
+];
+
+pub fn moderation() -> Moderation {
+    Moderation {
+        frames: FRAMES.load(Ordering::Relaxed),
+        usecs: USECS.load(Ordering::Relaxed),
+    }
+}
+
+fn remoderate() {
+    let m = moderation();
+    let all: Vec<Arc<Poller>> = without_interrupts(|| POLLERS.lock().clone());
+    for p in all {
+        p.hw.store(p.dev.moderate(m), Ordering::Relaxed);
+    }
+}
+
+/* ---------------------------------- Poller ---------------------------------- */
+
+impl Poller {
+    /// Interrupt side: mask the device and queue a polling pass. Further
+    /// interrupts before that pass runs are only counted.
+    pub fn schedule(&self) {
+        self.interrupts.fetch_add(1, Ordering::Relaxed);
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            crate::counter!("napi.coalesced");
+            return;
+        }
+        self.dev.irq_disable();
+        self.ev.signal();
+    }
+
+    fn info(&self) -> PollerInfo {
+        PollerInfo {
+            name: self.name,
+            hw_moderation: self.hw.load(Ordering::Relaxed),
+            interrupts: self.interrupts.load(Ordering::Relaxed),
+            passes: self.passes.load(Ordering::Relaxed),
+            completions: self.done.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn run(self: Arc<Self>) {
+        loop {
+            self.ev.wait().await;
+            let us = USECS.load(Ordering::Relaxed) as u64;
+            if !self.hw.load(Ordering::Relaxed) && us >= 1000 {
+                // Software coalescing; the tick is 1 ms, so that is the grain.
+                executor::sleep_ticks(us / 1000).await;
+            }
+            loop {
+                let budget = BUDGET.load(Ordering::Relaxed) as usize;
+                let n = self.dev.poll(budget);
+                self.passes.fetch_add(1, Ordering::Relaxed);
+                self.done.fetch_add(n as u64, Ordering::Relaxed);
+                if n >= budget {
+                    // Still busy: stay in polling mode but let others run.
+                    crate::counter!("napi.budget_exhausted");
+                    executor::yield_now().await;
+                    continue;
+                }
+                // Clear first so an interrupt right after enabling
+                // schedules a fresh pass.
+                self.scheduled.store(false, Ordering::Release);
+                if self.dev.irq_enable() {
+                    break;
+                }
+                // Work slipped in unannounced. Take it unless an interrupt
+                // already rescheduled us.
+                if self.scheduled.swap(true, Ordering::AcqRel) {
+                    break;
+                }
+                self.dev.irq_disable();
+            }
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Put `dev` under a poller; its interrupt handler calls `schedule()` on the
+/// result. Needs the executor.
+pub fn register(name: &'static str, dev: Arc<dyn PollDevice>) -> Arc<Poller> {
+    let hw = dev.moderate(moderation());
+    let p = Arc::new(Poller {
+        name,
+        dev,
+        ev: IrqEvent::new(),
+        scheduled: AtomicBool::new(false),
+        hw: AtomicBool::new(hw),
+        interrupts: AtomicU64::new(0),
+        passes: AtomicU64::new(0),
+        done: AtomicU64::new(0),
+    });
+    without_interrupts(|| POLLERS.lock().push(p.clone()));
+    executor::spawn(p.clone().run());
+    p
+}
+
+pub fn for_each(mut f: impl FnMut(PollerInfo)) {
+    let all: Vec<Arc<Poller>> = without_interrupts(|| POLLERS.lock().clone());
+    all.iter().for_each(|p| f(p.info()));
+}
@@ -0,0 +1,91 @@
+// src/irq/thread.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Threaded interrupt handlers. A driver that cannot do its work with
+// interrupts off (it sleeps, takes a KMutex, allocates a lot) asks for an
+// interrupt thread with `request()` and calls only `IrqThread::hard_irq()`
+// from its handler. That masks the line at the IOAPIC and wakes the thread;
+// the thread runs the driver's handler in task context, then unmasks.
+//
+// Each line gets its own kernel thread in the Fifo class at PRIO, above
+// every normal task, bound to the CPU the line is routed to so the handler
+// runs where the interrupt arrived. When the balancer or `set_affinity`
+// moves the line, the thread is rebound with it.
+//
+// While the line is masked a level-triggered device keeps its interrupt
+// pending and raises it again on unmask; an edge-triggered one's is lost.
+// So the handler drains everything the device has, as with `poll`.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Once;
+
+use super::{Irq, note};
+use crate::arch::x86_64::ioapic;
+use crate::error::KResult;
+use crate::sched::{self, TaskId, prio::Priority};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// Level of every interrupt thread.
+pub const PRIO: Priority = Priority::Fifo(50);
+
+pub struct IrqThread {
+    pub irq: &'static Irq,
+    handler: Box<dyn Fn() + Send + Sync>,
+    task: Once<TaskId>,
+    pending: AtomicBool, // hard half ran, the thread has not yet
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl IrqThread {
+    fn run(&self) -> ! {
+        loop {
+            // A wake before we block is kept, so none is missed here.
+            while !self.pending.swap(false, Ordering::AcqRel) {
+                sched::block_current();
+            }
+            (self.handler)();
+            ioapic::set_masked(self.irq.gsi, false);
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl IrqThread {
+    /// Interrupt side: mask the line and wake the thread. Never blocks.
+    /// The handler still sends the EOI.
+    pub fn hard_irq(&self) {
+        ioapic::set_masked(self.irq.gsi, true);
+        note(self.irq);
+        if self.pending.swap(true, Ordering::AcqRel) {
+            crate::counter!("irq.thread_coalesced");
+        }
+        if let Some(&id) = self.task.get() {
+            sched::wake(id);
+        }
+    }
+}
+
+/// Give `irq` an interrupt thread running `handler`; the line's handler
+/// calls `hard_irq()` on the result. Needs the scheduler.
+pub fn request(
+    irq: &'static Irq,
+    handler: impl Fn() + Send + Sync + 'static,
+) -> KResult<&'static IrqThread> {
+    let t: &'static IrqThread = Box::leak(Box::new(IrqThread {
+        irq,
+        handler: Box::new(handler),
+        task: Once::new(),
+        pending: AtomicBool::new(false),
+    }));
+    let id = sched::spawn_with_priority(PRIO, move || t.run())?;
+    t.task.call_once(|| id);
+    irq.thread.call_once(|| id);
+    sched::bind(id, Some(irq.target.load(Ordering::Relaxed)))?;
+    Ok(t)
+}
@@ -0,0 +1,131 @@
+// src/klog.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Log line prefixes. Every `kprintln!` line starts with
+//   [    1.234567] c0 t5 <message>
+// the time since the first log line (TSC), the APIC id of the CPU that
+// printed it, and the task that CPU was running (`idle`, or `-` before the
+// scheduler's first switch there). Each part can be turned off: on the
+// command line with `logprefix=time,cpu,task` (any subset, or `none`), and at
+// runtime through the `time`, `cpu` and `task` keys of /config/log.
+//
+// The prefix is built on the stack from lock-free reads, so it is safe
+// wherever `kprintln!` is: ISRs, under the runqueue lock, before the heap.
+// Tasks have no names yet; the id is all there is to print.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use spin::Once;
+
+use crate::arch::x86_64::tsc;
+use crate::config::{self, Key};
+use crate::sched::{self, OnCpu};
+use crate::{cmdline, stats};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const TIME: u8 = 1 << 0;
+pub const CPU: u8 = 1 << 1;
+pub const TASK: u8 = 1 << 2;
+const ALL: u8 = TIME | CPU | TASK;
+
+static FLAGS: AtomicU8 = AtomicU8::new(ALL);
+static T0: AtomicU64 = AtomicU64::new(0); // TSC at the first prefixed line
+static HZ: Once<u64> = Once::new();
+
+pub const CONFIG_KEYS: &[Key] = &[
+    Key {
+        name: "time",
+        get: || has(TIME) as u64,
+        validate: |v| config::in_range(v, 0, 1),
+        apply: |v| set(TIME, v != 0),
+    },
+    Key {
+        name: "cpu",
+        get: || has(CPU) as u64,
+        validate: |v| config::in_range(v, 0, 1),
+        apply: |v| set(CPU, v != 0),
+    },
+    Key {
+        name: "task",
+        get: || has(TASK) as u64,
+        validate: |v| config::in_range(v, 0, 1),
+        apply: |v| set(TASK, v != 0),
+    },
+];
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn has(flag: u8) -> bool {
+    FLAGS.load(Ordering::Relaxed) & flag != 0
+}
+
+fn set(flag: u8, on: bool) {
+    if on {
+        FLAGS.fetch_or(flag, Ordering::Relaxed);
+    } else {
+        FLAGS.fetch_and(!flag, Ordering::Relaxed);
+    }
+}
+
+/// APIC id without touching the APIC: `lapic_id()` may enable it, and the
+/// first lines print before it is set up.
+fn cpu() -> u32 {
+    stats::cpu_tag().unwrap_or_else(|| core::arch::x86_64::__cpuid(1).ebx >> 24)
+}
+
+fn micros() -> u64 {
+    let now = tsc::rdtsc();
+    let t0 = match T0.compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => now,
+        Err(t0) => t0,
+    };
+    let hz = *HZ.call_once(|| tsc::tsc_hz_estimate().max(1));
+    (now.saturating_sub(t0) as u128 * 1_000_000 / hz as u128) as u64
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Apply `logprefix=` from the command line.
+pub fn init() {
+    let Some(v) = cmdline::value("logprefix") else {
+        return;
+    };
+    let mut flags = 0;
+    for part in v.split(',') {
+        match part {
+            "time" => flags |= TIME,
+            "cpu" => flags |= CPU,
+            "task" => flags |= TASK,
+            "none" | "" => {}
+            _ => crate::kprintln!("[klog] logprefix: unknown part '{}'", part),
+        }
+    }
+    FLAGS.store(flags, Ordering::Relaxed);
+}
+
+pub fn flags() -> u8 {
+    FLAGS.load(Ordering::Relaxed)
+}
+
+/// Write the prefix for a line printed now, on this CPU.
+pub fn write_prefix(w: &mut impl Write) -> fmt::Result {
+    let flags = flags();
+    if flags & TIME != 0 {
+        let us = micros();
+        write!(w, "[{:5}.{:06}] ", us / 1_000_000, us % 1_000_000)?;
+    }
+    let cpu = cpu();
+    if flags & CPU != 0 {
+        write!(w, "c{} ", cpu)?;
+    }
+    if flags & TASK != 0 {
+        match sched::on_cpu(cpu) {
+            Some(OnCpu::Task(id)) => write!(w, "t{} ", id)?,
+            Some(OnCpu::Idle) => w.write_str("idle ")?,
+            None => w.write_str("- ")?,
+        }
+    }
+    Ok(())
+}
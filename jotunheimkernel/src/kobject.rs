@@ -0,0 +1,274 @@
+// src/kobject.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Reference-counted kernel objects. A `KObject` is an `Arc` around a name, a
+// parent and a payload of any type; `KRef<T>` is a strong, typed handle to
+// one (it derefs to the payload). Objects form a tree under permanent roots
+// (`root("devices")`), are found by path (`/devices/pci/00:03.0`) and listed
+// with `for_each`.
+//
+// Lifetimes: a child holds a strong reference to its parent and its parent
+// only a weak one back, so a parent always outlives its children. When the
+// last strong reference goes, the object is unlinked from its parent, then
+// its payload is dropped, and only then its reference to the parent: a
+// destructor may still use the parent (a driver's device, a file's mount).
+//
+// Cloning and dropping handles is lock-free and fine in ISRs, but dropping
+// the last reference runs the destructor right there: objects whose
+// payloads free memory or take locks must not die in interrupt context.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::{Any, type_name};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KObjError {
+    BadName, // empty or contains '/'
+    Exists,  // the parent already has a live child of that name
+}
+
+pub struct KObject {
+    id: u64,
+    name: String,
+    kind: &'static str, // payload type, for listings
+    children: Mutex<Vec<Weak<KObject>>>,
+    // Dropped in this order; see `Drop`.
+    data: Option<Box<dyn Any + Send + Sync>>,
+    parent: Option<Arc<KObject>>,
+}
+
+/// Strong handle to an object whose payload is a `T`.
+pub struct KRef<T> {
+    obj: Arc<KObject>,
+    _t: PhantomData<fn() -> T>,
+}
+
+/// One object, for listing.
+#[derive(Clone, Debug)]
+pub struct KObjInfo {
+    pub id: u64,
+    pub depth: usize,
+    pub name: String,
+    pub kind: &'static str,
+    pub refs: usize,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+// Top-level objects. Never released.
+static ROOTS: Mutex<Vec<Arc<KObject>>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn live_children(list: &[Weak<KObject>]) -> Vec<Arc<KObject>> {
+    list.iter().filter_map(Weak::upgrade).collect()
+}
+
+fn walk(objs: Vec<Arc<KObject>>, depth: usize, f: &mut impl FnMut(&KObjInfo)) {
+    for o in objs {
+        f(&KObjInfo {
+            id: o.id,
+            depth,
+            name: o.name.clone(),
+            kind: o.kind,
+            // Less the one `walk` holds.
+            refs: Arc::strong_count(&o) - 1,
+        });
+        let kids = without_interrupts(|| live_children(&o.children.lock()));
+        walk(kids, depth + 1, f);
+    }
+}
+
+/* --------------------------------- Objects ---------------------------------- */
+
+impl KObject {
+    fn alloc<T: Any + Send + Sync>(
+        name: &str,
+        parent: Option<&Arc<KObject>>,
+        data: T,
+    ) -> Result<Arc<KObject>, KObjError> {
+        if name.is_empty() || name.contains('/') {
+            return Err(KObjError::BadName);
+        }
+        crate::counter!("kobj.created");
+        Ok(Arc::new(KObject {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: String::from(name),
+            kind: type_name::<T>(),
+            children: Mutex::new(Vec::new()),
+            data: Some(Box::new(data)),
+            parent: parent.cloned(),
+        }))
+    }
+
+    /// Create `name` under `parent` holding `data`.
+    pub fn create<T: Any + Send + Sync>(
+        name: &str,
+        parent: &Arc<KObject>,
+        data: T,
+    ) -> Result<KRef<T>, KObjError> {
+        let obj = Self::alloc(name, Some(parent), data)?;
+        let (siblings, r) = without_interrupts(|| {
+            let mut list = parent.children.lock();
+            list.retain(|w| w.strong_count() > 0);
+            let siblings = live_children(&list);
+            let r = if siblings.iter().any(|o| o.name == name) {
+                Err(KObjError::Exists)
+            } else {
+                list.push(Arc::downgrade(&obj));
+                Ok(())
+            };
+            (siblings, r)
+        });
+        // Outside the lock: one of these may be the last reference.
+        drop(siblings);
+        r?;
+        Ok(KRef {
+            obj,
+            _t: PhantomData,
+        })
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.data.as_ref().is_some_and(|d| d.is::<T>())
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.data.as_ref()?.downcast_ref()
+    }
+
+    /// A typed handle to `obj`, if its payload is a `T`.
+    pub fn downcast<T: Any>(obj: &Arc<KObject>) -> Option<KRef<T>> {
+        obj.is::<T>().then(|| KRef {
+            obj: obj.clone(),
+            _t: PhantomData,
+        })
+    }
+
+    /// Live child called `name`.
+    pub fn child(&self, name: &str) -> Option<Arc<KObject>> {
+        without_interrupts(|| live_children(&self.children.lock()))
+            .into_iter()
+            .find(|o| o.name == name)
+    }
+
+    /// Full path, e.g. `/devices/pci/00:03.0`.
+    pub fn path(&self) -> String {
+        let mut names = Vec::new();
+        let mut o = Some(self);
+        while let Some(x) = o {
+            names.push(x.name.as_str());
+            o = x.parent.as_deref();
+        }
+        let mut p = String::new();
+        for n in names.iter().rev() {
+            p.push('/');
+            p.push_str(n);
+        }
+        p
+    }
+}
+
+impl Drop for KObject {
+    fn drop(&mut self) {
+        // Our own weak entry is dead by now; sweep it (and any others).
+        if let Some(p) = self.parent.as_ref() {
+            without_interrupts(|| p.children.lock().retain(|w| w.strong_count() > 0));
+        }
+        // Payload first, while the parent is still held.
+        drop(self.data.take());
+        drop(self.parent.take());
+        crate::counter!("kobj.released");
+    }
+}
+
+impl fmt::Debug for KObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}, #{})", self.path(), self.kind, self.id)
+    }
+}
+
+/* ---------------------------------- Handles --------------------------------- */
+
+impl<T: Any> KRef<T> {
+    pub fn object(&self) -> &Arc<KObject> {
+        &self.obj
+    }
+}
+
+impl<T: Any> Deref for KRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Checked when the handle was made.
+        self.obj.downcast_ref().unwrap()
+    }
+}
+
+impl<T> Clone for KRef<T> {
+    fn clone(&self) -> Self {
+        Self {
+            obj: self.obj.clone(),
+            _t: PhantomData,
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Object at `path` (absolute, `/`-separated).
+pub fn lookup(path: &str) -> Option<Arc<KObject>> {
+    let mut parts = path.strip_prefix('/')?.split('/');
+    let first = parts.next()?;
+    let mut o = without_interrupts(|| ROOTS.lock().iter().find(|o| o.name == first).cloned())?;
+    for name in parts {
+        o = o.child(name)?;
+    }
+    Some(o)
+}
+
+/// Top-level object `name`, created empty the first time. Roots live
+/// forever. Panics on a name with a '/'.
+pub fn root(name: &str) -> Arc<KObject> {
+    without_interrupts(|| {
+        let mut roots = ROOTS.lock();
+        if let Some(o) = roots.iter().find(|o| o.name == name) {
+            return o.clone();
+        }
+        let o = KObject::alloc(name, None, ()).expect("kobject: bad root name");
+        roots.push(o.clone());
+        o
+    })
+}
+
+/// Empty object `name` under `parent`, created if missing. It lives as
+/// long as something holds it or anything beneath it.
+pub fn dir(parent: &Arc<KObject>, name: &str) -> Result<Arc<KObject>, KObjError> {
+    loop {
+        if let Some(o) = parent.child(name) {
+            return Ok(o);
+        }
+        match KObject::create(name, parent, ()) {
+            Ok(r) => return Ok(r.object().clone()),
+            Err(KObjError::Exists) => continue, // raced with another creator
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Every live object, depth first.
+pub fn for_each(mut f: impl FnMut(&KObjInfo)) {
+    let roots = without_interrupts(|| ROOTS.lock().clone());
+    walk(roots, 0, &mut f);
+}
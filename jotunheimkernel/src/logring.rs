@@ -0,0 +1,118 @@
+// src/logring.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// In-memory copy of the kernel log. Everything `kprint!` writes also goes
+// into a fixed ring here, so the log can be read back after the fact: with
+// the shell's `dmesg`, and from outside the kernel when it is wedged. The
+// ring lives at the unmangled symbol `JOTUNHEIM_LOG`, which a GDB script
+// (.gdb/dmesg.py) finds by name, and the RSP stub also reports its address
+// through `qXfer:jotunheim-log:read`, for a debugger without the ELF.
+//
+// Layout (#[repr(C)], little endian), stable for outside readers:
+//   0  magic  [u8; 8]  "JOTNLOG1"
+//   8  size   u64      ring bytes, a power of two
+//  16  head   u64      bytes ever written; the next one goes to head % size
+//  24  tail   u64      oldest byte still held (head - size once wrapped)
+//  32  data   [u8; size]
+//
+// Writers claim space with one fetch_add and copy in without a lock, so any
+// context that logs can feed the ring. Two writers more than a ring apart
+// can still overwrite each other's bytes; readers may see a torn line at
+// the oldest end. Both are fine for a post-mortem log. This is a byte
+// stream with a layout fixed for outside readers, so it does not use
+// util::ring, whose rings hold typed entries.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAGIC: [u8; 8] = *b"JOTNLOG1";
+pub const SIZE: usize = 64 * 1024;
+const MASK: u64 = SIZE as u64 - 1;
+
+#[repr(C)]
+pub struct LogRing {
+    magic: [u8; 8],
+    size: u64,
+    head: AtomicU64,
+    tail: AtomicU64,
+    data: UnsafeCell<[u8; SIZE]>,
+}
+
+// The data bytes are only written at offsets a writer has claimed.
+unsafe impl Sync for LogRing {}
+
+#[unsafe(no_mangle)]
+pub static JOTUNHEIM_LOG: LogRing = LogRing {
+    magic: MAGIC,
+    size: SIZE as u64,
+    head: AtomicU64::new(0),
+    tail: AtomicU64::new(0),
+    data: UnsafeCell::new([0; SIZE]),
+};
+
+/// `fmt::Write` into the ring.
+pub struct RingWriter;
+
+impl Write for RingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        append(s.as_bytes());
+        Ok(())
+    }
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn data() -> *mut u8 {
+    JOTUNHEIM_LOG.data.get() as *mut u8
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Add `bytes` to the ring, dropping the oldest as needed.
+pub fn append(bytes: &[u8]) {
+    // Only the last SIZE bytes of an oversized write can survive anyway.
+    let bytes = &bytes[bytes.len().saturating_sub(SIZE)..];
+    let n = bytes.len() as u64;
+    if n == 0 {
+        return;
+    }
+    let pos = JOTUNHEIM_LOG.head.fetch_add(n, Ordering::AcqRel);
+    let at = (pos & MASK) as usize;
+    let first = bytes.len().min(SIZE - at);
+    unsafe {
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), data().add(at), first);
+        core::ptr::copy_nonoverlapping(bytes[first..].as_ptr(), data(), bytes.len() - first);
+    }
+    JOTUNHEIM_LOG
+        .tail
+        .fetch_max((pos + n).saturating_sub(SIZE as u64), Ordering::AcqRel);
+}
+
+/// Address of the ring's header, for debuggers.
+pub fn header_addr() -> u64 {
+    &JOTUNHEIM_LOG as *const LogRing as u64
+}
+
+/// Address of the ring's first data byte.
+pub fn data_addr() -> u64 {
+    data() as u64
+}
+
+/// The held log, oldest first, as at most two slices (the ring may wrap).
+pub fn for_each_chunk(mut f: impl FnMut(&[u8])) {
+    let head = JOTUNHEIM_LOG.head.load(Ordering::Acquire);
+    let tail = JOTUNHEIM_LOG
+        .tail
+        .load(Ordering::Acquire)
+        .max(head.saturating_sub(SIZE as u64));
+    let (start, len) = ((tail & MASK) as usize, (head - tail) as usize);
+    let first = len.min(SIZE - start);
+    let ring = unsafe { core::slice::from_raw_parts(data() as *const u8, SIZE) };
+    f(&ring[start..start + first]);
+    if len > first {
+        f(&ring[..len - first]);
+    }
+}
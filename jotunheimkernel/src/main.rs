@@ -5,16 +5,41 @@
 
 mod acpi;
 mod arch;
+mod bench;
+mod blockdev;
 mod bootinfo;
+mod bootlog;
+mod cmdline;
+mod config;
 mod debug;
+mod error;
+mod fs;
+mod gfx;
+mod init;
+mod input;
+mod irq;
+mod klog;
+mod kobject;
+mod logring;
 mod mem;
+mod net;
+mod pci;
 mod sched;
+mod stats;
+mod usb;
 mod util;
+mod video;
+mod virtio;
 
 extern crate alloc;
 
 use crate::{
-    arch::{native::smp::boot_all_aps, x86_64::apic}, bootinfo::BootInfo, mem::reserved, sched::exec, util::zero_bss,
+    arch::{native::smp::boot_all_aps, x86_64::apic},
+    bootinfo::BootInfo,
+    init::{Initcall, Stage},
+    mem::reserved,
+    sched::exec,
+    util::zero_bss,
 };
 
 use core::panic::PanicInfo;
@@ -25,6 +50,479 @@ use x86_64::instructions::{
 
 use crate::arch::native::{self, mmio_map, serial};
 
+// Every subsystem brought up at boot, in no particular order: `init` sorts
+// by stage and dependencies.
+static INITCALLS: &[Initcall] = &[
+    /* Early: before the loader's memory may be reused */
+    Initcall {
+        name: "cmdline",
+        stage: Stage::Early,
+        deps: &[],
+        run: |b| {
+            cmdline::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "bootlog",
+        stage: Stage::Early,
+        deps: &[],
+        run: |b| {
+            bootlog::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "latency",
+        stage: Stage::Early,
+        deps: &["cmdline"],
+        run: |_| {
+            debug::latency::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "irqhist",
+        stage: Stage::Early,
+        deps: &["cmdline"],
+        run: |_| {
+            debug::histo::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "klog",
+        stage: Stage::Early,
+        deps: &["cmdline"],
+        run: |_| {
+            klog::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "debug-shell",
+        stage: Stage::Early,
+        deps: &[],
+        run: |_| {
+            debug::shell::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "watch",
+        stage: Stage::Early,
+        deps: &["debug-shell"],
+        run: |_| {
+            debug::watch::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "trace",
+        stage: Stage::Early,
+        deps: &["debug-shell"],
+        run: |_| {
+            debug::trace::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "irqalloc",
+        stage: Stage::Early,
+        deps: &["cmdline"],
+        run: |_| {
+            debug::irqalloc::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "handoff",
+        stage: Stage::Early,
+        deps: &[],
+        run: |b| {
+            mem::handoff::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "loader-steps",
+        stage: Stage::Early,
+        deps: &["bootlog"],
+        run: |_| {
+            bootlog::print_steps();
+            Ok(())
+        },
+    },
+    /* Memory */
+    Initcall {
+        name: "reserved",
+        stage: Stage::Memory,
+        deps: &[],
+        run: |b| {
+            reserved::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "mem",
+        stage: Stage::Memory,
+        deps: &["reserved"],
+        run: |b| {
+            mem::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "frames",
+        stage: Stage::Memory,
+        deps: &["mem"],
+        run: |b| {
+            mem::frames::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "heap",
+        stage: Stage::Memory,
+        deps: &["frames"],
+        run: |_| {
+            mem::init_heap();
+            Ok(())
+        },
+    },
+    /* Arch */
+    Initcall {
+        name: "video",
+        stage: Stage::Arch,
+        deps: &["heap", "cmdline"],
+        run: |b| {
+            video::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "apic-mmio",
+        stage: Stage::Arch,
+        deps: &["mem"],
+        run: |_| {
+            mmio_map::enforce_apic_mmio_flags();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "arch",
+        stage: Stage::Arch,
+        deps: &["heap", "apic-mmio"],
+        run: |b| {
+            native::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "cpus",
+        stage: Stage::Arch,
+        deps: &["heap", "arch"],
+        run: |b| {
+            acpi::topology::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "stats",
+        stage: Stage::Arch,
+        deps: &["arch"],
+        run: |_| {
+            stats::init_cpu();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "alternatives",
+        stage: Stage::Arch,
+        deps: &["arch"],
+        run: |_| {
+            native::alternatives::apply();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "context-test",
+        stage: Stage::Arch,
+        deps: &["arch", "alternatives"],
+        run: |_| {
+            native::context::selftest();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "cet",
+        stage: Stage::Arch,
+        deps: &["arch"],
+        run: |_| {
+            native::cet::init();
+            Ok(())
+        },
+    },
+    /* Sched */
+    Initcall {
+        name: "sched",
+        stage: Stage::Sched,
+        deps: &["heap", "arch", "context-test", "cet"],
+        run: |_| {
+            sched::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "replay",
+        stage: Stage::Sched,
+        deps: &["sched"],
+        run: |_| {
+            debug::replay::init();
+            Ok(())
+        },
+    },
+    /* Services: on the main thread from here on */
+    Initcall {
+        name: "context-probe",
+        stage: Stage::Services,
+        deps: &["sched"],
+        run: |_| {
+            native::context::spawn_probe();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "exec",
+        stage: Stage::Services,
+        deps: &["sched"],
+        run: |_| {
+            exec::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "executor",
+        stage: Stage::Services,
+        deps: &["sched"],
+        run: |_| {
+            // The BSP's worker; "executor-smp" adds one per AP.
+            sched::executor::init(1);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "config",
+        stage: Stage::Services,
+        deps: &["sched"],
+        run: |_| {
+            config::init();
+            config::register("sched", sched::CONFIG_KEYS);
+            config::register("bio", blockdev::iosched::CONFIG_KEYS);
+            config::register("napi", irq::poll::CONFIG_KEYS);
+            config::register("log", klog::CONFIG_KEYS);
+            config::register("speaker", native::speaker::CONFIG_KEYS);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "heap-shrink",
+        stage: Stage::Services,
+        deps: &["sched"],
+        run: |_| {
+            mem::heapmap::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "irq-balance",
+        stage: Stage::Services,
+        deps: &["executor"],
+        run: |_| {
+            irq::init();
+            Ok(())
+        },
+    },
+    /* Devices */
+    Initcall {
+        name: "ivshmem",
+        stage: Stage::Devices,
+        deps: &[],
+        run: |_| {
+            pci::ivshmem::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "virtio-console",
+        stage: Stage::Devices,
+        deps: &["sched", "executor"],
+        run: |_| {
+            virtio::console::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "ps2",
+        stage: Stage::Devices,
+        deps: &["sched"],
+        run: |_| {
+            input::ps2::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "xhci",
+        stage: Stage::Devices,
+        deps: &[],
+        run: |_| {
+            usb::xhci::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "usb-hid",
+        stage: Stage::Devices,
+        deps: &["xhci"],
+        run: |_| {
+            usb::hid::init();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "pmem",
+        stage: Stage::Devices,
+        deps: &[],
+        run: |b| {
+            mem::pmem::init(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "pstore",
+        stage: Stage::Devices,
+        deps: &["pmem"],
+        run: |_| {
+            debug::pstore::init();
+            Ok(())
+        },
+    },
+    // After the drivers, so the first scan knows what they bound.
+    Initcall {
+        name: "pci-hotplug",
+        stage: Stage::Devices,
+        deps: &["executor", "ivshmem", "virtio-console", "xhci"],
+        run: |_| {
+            pci::hotplug::init();
+            Ok(())
+        },
+    },
+    /* SMP and cleanup */
+    Initcall {
+        name: "aps",
+        stage: Stage::Smp,
+        deps: &["cpus", "sched"],
+        run: |b| {
+            boot_all_aps(b);
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "executor-smp",
+        stage: Stage::Smp,
+        deps: &["aps", "executor"],
+        run: |_| {
+            sched::executor::init(native::smp::online_cpus().count());
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "livepatch-test",
+        stage: Stage::Smp,
+        deps: &["aps"],
+        run: |_| {
+            native::livepatch::selftest();
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "reclaim",
+        stage: Stage::Late,
+        deps: &["handoff", "aps"],
+        run: |_| {
+            mem::handoff::reclaim();
+            Ok(())
+        },
+    },
+    // Last, so it sees the tables as they stay.
+    Initcall {
+        name: "ptcheck",
+        stage: Stage::Late,
+        deps: &[],
+        run: |_| {
+            if mem::ptcheck::enabled() {
+                mem::ptcheck::run("boot");
+            }
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "traptest",
+        stage: Stage::Late,
+        deps: &["aps"],
+        run: |_| {
+            if native::tables::isr::selftest::enabled() {
+                native::tables::isr::selftest::run();
+            }
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "schedtest",
+        stage: Stage::Late,
+        deps: &["aps"],
+        run: |_| {
+            if sched::selftest::enabled() {
+                sched::selftest::run();
+            }
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "blktest",
+        stage: Stage::Late,
+        deps: &["aps"],
+        run: |_| {
+            if blockdev::selftest::enabled() {
+                blockdev::selftest::run();
+            }
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "nettest",
+        stage: Stage::Late,
+        deps: &["aps"],
+        run: |_| {
+            if net::selftest::enabled() {
+                net::selftest::run();
+            }
+            Ok(())
+        },
+    },
+    Initcall {
+        name: "benchmarks",
+        stage: Stage::Late,
+        deps: &["aps", "ptcheck", "traptest"],
+        run: |_| {
+            if bench::enabled() {
+                bench::run();
+            }
+            Ok(())
+        },
+    },
+];
+
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".text._start")]
 pub extern "C" fn _start(boot: &BootInfo) -> ! {
@@ -35,22 +533,21 @@ pub extern "C" fn _start(boot: &BootInfo) -> ! {
             serial::init_com2(115_200);
         }
         kprintln!("[JOTUNHEIM] Loaded the kernel.");
-
-        reserved::init(&boot);
-        mem::init(&boot);
-        mem::seed_usable_from_mmap(&boot);
-        mem::init_heap();
-        mmio_map::enforce_apic_mmio_flags();
-        native::init(&boot);
-        sched::init();
+        init::register(INITCALLS);
+        init::run_stages(Stage::Early, Stage::Sched, boot);
         sched::spawn(|| {
             kprintln!("[JOTUNHEIM] Started the kernel main thread.");
-            exec::init();
-            boot_all_aps(boot);
+            let rest = [Stage::Services, Stage::Devices, Stage::Smp, Stage::Late];
+            for (i, stage) in rest.into_iter().enumerate() {
+                init::run_stage(stage, boot);
+                video::splash::progress(i + 1, rest.len());
+            }
             kprintln!("[JOTUNHEIM] Ended the kernel main thread.");
         });
         debug::setup();
     });
+    // Inlined: the boot shadow stack starts empty and this frame never returns.
+    native::cet::activate();
     interrupts::enable();
     loop {
         hlt();
@@ -59,10 +556,26 @@ pub extern "C" fn _start(boot: &BootInfo) -> ! {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    kprintln!("\n*** KERNEL PANIC ***\n{}", info);
+    debug::replay::mark(debug::replay::Marker::Panic, 0);
+    // The panicking code may hold COM1's lock; go around it.
+    early_println!("\n*** KERNEL PANIC ***\n{}", info);
+    let rec = debug::crash::CrashRecord::capture(info);
+    for (i, f) in rec.frames.iter().enumerate() {
+        early_println!("  #{:<2} {:#018x}", i, f);
+    }
+    if native::cet::active() {
+        early_println!("  ssp {:#018x}", native::cet::ssp());
+    }
+    debug::pstore::save(&rec);
+    if cmdline::flag("panicmaps") {
+        let _ = mem::dump_mappings(&mut serial::EarlyConsole);
+    }
+    video::panic::show(&rec);
     if cfg!(debug_assertions) {
         interrupts::int3();
     }
+    interrupts::disable();
+    native::speaker::panic_signal(3);
     loop {
         x86_64::instructions::hlt();
     }
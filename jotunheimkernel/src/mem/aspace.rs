@@ -0,0 +1,119 @@
+// src/mem/aspace.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Address spaces, and copies between them. An address space is named by the
+// physical address of its top-level page table; today there is only the
+// kernel's, but exec (argument passing), coredumps and the debugger
+// (inspecting a process that is not running) all need to move bytes to or
+// from one that is not loaded in CR3.
+//
+// Nothing here switches CR3. Each side is translated page by page by
+// walking its tables through the HHDM (`vmmap::lookup`), and the frames
+// are reached through the HHDM too, mapped there first if they lie past
+// what the loader covered. Only write-back RAM is copied: a device window
+// in the other space has no safe cached alias. The walk takes no lock, so
+// the caller keeps the spaces from being changed under the copy (the
+// target stopped, or not yet started).
+
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::PageTableFlags as F;
+
+use super::hhdm_map;
+use super::vmmap::{self, Cache, Leaf};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AddressSpace {
+    root: u64, // physical address of the PML4
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CopyError {
+    NotMapped(u64), // VA with no mapping in its space
+    ReadOnly(u64),  // destination VA not writable
+    NotRam(u64),    // VA maps something other than write-back RAM
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl AddressSpace {
+    // The page under `va`, checked for RAM and, with `write`, writability.
+    fn page(&self, va: u64, write: bool) -> Result<Leaf, CopyError> {
+        let l = vmmap::lookup(self.root, va).ok_or(CopyError::NotMapped(va))?;
+        if l.cache != Cache::Wb {
+            return Err(CopyError::NotRam(va));
+        }
+        if write && !l.flags.contains(F::WRITABLE) {
+            return Err(CopyError::ReadOnly(va));
+        }
+        Ok(l)
+    }
+}
+
+// HHDM address of `va`'s byte in leaf `l`, and how many of the `want`
+// bytes from there the leaf holds.
+fn reach(l: &Leaf, va: u64, want: u64) -> (u64, u64) {
+    let off = va - l.va;
+    let n = want.min(l.size - off);
+    (hhdm_map(l.pa + off, n as usize, F::empty()), n)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl AddressSpace {
+    /// The space loaded in CR3 on this CPU.
+    pub fn current() -> Self {
+        Self {
+            root: Cr3::read().0.start_address().as_u64(),
+        }
+    }
+
+    /// The space whose PML4 is at physical `root`.
+    ///
+    /// # Safety
+    /// `root` must be a live top-level page table, and stay one while the
+    /// value is used.
+    #[allow(dead_code)] // no second space exists yet
+    pub unsafe fn from_root(root: u64) -> Self {
+        Self { root }
+    }
+
+    /// Read `buf.len()` bytes at `va` in this space.
+    pub fn read(&self, va: u64, buf: &mut [u8]) -> Result<(), CopyError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = va + done as u64;
+            let want = (buf.len() - done) as u64;
+            let (p, n) = reach(&self.page(at, false)?, at, want);
+            let n = n as usize;
+            let dst = buf[done..].as_mut_ptr();
+            unsafe { core::ptr::copy_nonoverlapping(p as *const u8, dst, n) };
+            done += n;
+        }
+        Ok(())
+    }
+}
+
+/// Copy `len` bytes from `src_va` in `src` to `dst_va` in `dst`, frame to
+/// frame through the HHDM; neither space has to be the current one. On
+/// error, the bytes before the failing address have been copied.
+#[allow(dead_code)] // no second space exists yet
+pub fn copy_between(
+    dst: &AddressSpace,
+    dst_va: u64,
+    src: &AddressSpace,
+    src_va: u64,
+    len: usize,
+) -> Result<(), CopyError> {
+    let mut done = 0u64;
+    while done < len as u64 {
+        let (s_at, d_at) = (src_va + done, dst_va + done);
+        let (s, n) = reach(&src.page(s_at, false)?, s_at, len as u64 - done);
+        let (d, n) = reach(&dst.page(d_at, true)?, d_at, n);
+        // The two may be the same frame: copy, not copy_nonoverlapping.
+        unsafe { core::ptr::copy(s as *const u8, d as *mut u8, n as usize) };
+        done += n;
+    }
+    Ok(())
+}
@@ -0,0 +1,421 @@
+// src/mem/frames.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Physical frame allocator. Every range of RAM we own is a zone with a
+// bitmap, one bit per 4 KiB frame (set = free), kept in the RAM itself and
+// reached through the HHDM. Nothing here touches the heap, so it works
+// before the heap exists and from the heap's own growth path.
+//
+// Seeded at boot from the whole memory map: every conventional range,
+// minus reserved pages, plus the loader's early heap and low-32 pool. The
+// loader took its map snapshot before allocating the map copy, its log and
+// the page tables, so those sit in "conventional" ranges and are marked
+// used here; `handoff::reclaim` frees the page tables later. Boot-services
+// memory is left alone for now. Hot-added RAM becomes one more zone, its
+// bitmap at the front of the range. With `mempoison`, every frame left
+// free at boot or hot-added is filled (`poison`) before it is handed out.
+//
+// Single frames come from the highest zone with one free, next-fit within
+// it, so memory below 4 GiB is the last to go and stays for 32-bit DMA.
+// Runs of contiguous frames are first-fit under a caller-given limit, at
+// a caller-given alignment (2 MiB for large pages).
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::vmmap::hhdm;
+use super::{PAGE_SIZE, align_down, align_up, handoff, poison, reserved};
+use crate::bootinfo::{BootInfo, MemoryRegion};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_ZONES: usize = 160;
+const PAGE: u64 = PAGE_SIZE as u64;
+const TYPE_CONVENTIONAL: u32 = 1;
+
+/// Frames below this are usable for 32-bit DMA.
+pub const LOW32_LIMIT: u64 = 1 << 32;
+
+/// Frame counts, for reports.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameStats {
+    pub zones: usize,
+    pub total: u64, // frames the allocator manages, free or not
+    pub free: u64,
+}
+
+struct Zone {
+    start: u64, // physical address of the first frame
+    pages: u64,
+    map: u64,  // HHDM address of the bitmap
+    free: u64, // set bits in the bitmap
+    hint: u64, // word to start the next single-frame search at
+}
+
+static ZONES: Mutex<Vec<Zone, MAX_ZONES>> = Mutex::new(Vec::new());
+static READY: AtomicBool = AtomicBool::new(false);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// Bytes of bitmap for `pages` frames, in whole words.
+fn map_bytes(pages: u64) -> u64 {
+    pages.div_ceil(64) * 8
+}
+
+impl Zone {
+    fn end(&self) -> u64 {
+        self.start + self.pages * PAGE
+    }
+
+    fn contains(&self, pa: u64) -> bool {
+        (self.start..self.end()).contains(&pa)
+    }
+
+    fn words(&mut self) -> &mut [u64] {
+        let n = self.pages.div_ceil(64) as usize;
+        unsafe { core::slice::from_raw_parts_mut(self.map as *mut u64, n) }
+    }
+
+    fn is_free(&mut self, i: u64) -> bool {
+        self.words()[(i / 64) as usize] & (1 << (i % 64)) != 0
+    }
+
+    // Mark frame `i` free or used. False if it already was.
+    fn set(&mut self, i: u64, free: bool) -> bool {
+        let bit = 1u64 << (i % 64);
+        let w = &mut self.words()[(i / 64) as usize];
+        if (*w & bit != 0) == free {
+            return false;
+        }
+        *w ^= bit;
+        if free {
+            self.free += 1;
+        } else {
+            self.free -= 1;
+        }
+        true
+    }
+
+    // Mark every frame of [s, e) that lies in this zone.
+    fn set_range(&mut self, s: u64, e: u64, free: bool) {
+        let s = align_up(s.max(self.start), PAGE);
+        let e = align_down(e.min(self.end()), PAGE);
+        let mut pa = s;
+        while pa < e {
+            self.set((pa - self.start) / PAGE, free);
+            pa += PAGE;
+        }
+    }
+
+    fn take_one(&mut self) -> Option<u64> {
+        if self.free == 0 {
+            return None;
+        }
+        let n = self.pages.div_ceil(64);
+        for k in 0..n {
+            let wi = (self.hint + k) % n;
+            let w = self.words()[wi as usize];
+            if w != 0 {
+                let i = wi * 64 + w.trailing_zeros() as u64;
+                self.set(i, false);
+                self.hint = wi;
+                return Some(self.start + i * PAGE);
+            }
+        }
+        None
+    }
+
+    // Call `f` with each run of free frames as a physical [start, end).
+    fn free_runs(&mut self, mut f: impl FnMut(u64, u64)) {
+        let mut i = 0;
+        while i < self.pages {
+            if i % 64 == 0 && self.words()[(i / 64) as usize] == 0 {
+                i += 64;
+                continue;
+            }
+            if !self.is_free(i) {
+                i += 1;
+                continue;
+            }
+            let first = i;
+            while i < self.pages && self.is_free(i) {
+                i += 1;
+            }
+            f(self.start + first * PAGE, self.start + i * PAGE);
+        }
+    }
+
+    // First run of `n` free frames ending at or below `limit`, starting at
+    // a multiple of `align` bytes.
+    fn take_run(&mut self, n: u64, limit: u64, align: u64) -> Option<u64> {
+        if self.free < n || limit <= self.start {
+            return None;
+        }
+        let max = (limit.min(self.end()) - self.start) / PAGE;
+        let (mut i, mut run) = (0, 0);
+        while i < max {
+            if i % 64 == 0 && self.words()[(i / 64) as usize] == 0 {
+                (i, run) = (i + 64, 0);
+                continue;
+            }
+            if !self.is_free(i) || run == 0 && !(self.start + i * PAGE).is_multiple_of(align) {
+                (i, run) = (i + 1, 0);
+                continue;
+            }
+            run += 1;
+            i += 1;
+            if run == n {
+                let first = i - n;
+                for j in first..i {
+                    self.set(j, false);
+                }
+                return Some(self.start + first * PAGE);
+            }
+        }
+        None
+    }
+}
+
+fn with_zones<R>(f: impl FnOnce(&mut Vec<Zone, MAX_ZONES>) -> R) -> R {
+    without_interrupts(|| f(&mut ZONES.lock()))
+}
+
+// Add a zone for [s, e) with its bitmap at HHDM address `map`, every frame
+// used. Zones stay sorted by address.
+fn add_zone(zones: &mut Vec<Zone, MAX_ZONES>, s: u64, e: u64, map: u64) -> bool {
+    let pages = (e - s) / PAGE;
+    let z = Zone {
+        start: s,
+        pages,
+        map,
+        free: 0,
+        hint: 0,
+    };
+    unsafe { core::ptr::write_bytes(map as *mut u8, 0, map_bytes(pages) as usize) };
+    let at = zones
+        .iter()
+        .position(|z| z.start > s)
+        .unwrap_or(zones.len());
+    zones.insert(at, z).is_ok()
+}
+
+fn mark(zones: &mut Vec<Zone, MAX_ZONES>, s: u64, e: u64, free: bool) {
+    for z in zones.iter_mut().filter(|z| s < z.end() && e > z.start) {
+        z.set_range(s, e, free);
+    }
+}
+
+fn collect(zones: &Vec<Zone, MAX_ZONES>) -> FrameStats {
+    FrameStats {
+        zones: zones.len(),
+        total: zones.iter().map(|z| z.pages).sum(),
+        free: zones.iter().map(|z| z.free).sum(),
+    }
+}
+
+// The ranges to seed from: conventional RAM, the early heap, the low-32 pool.
+fn seed_ranges(boot: &BootInfo) -> Vec<(u64, u64, bool), MAX_ZONES> {
+    let mm: &[MemoryRegion] =
+        unsafe { core::slice::from_raw_parts(boot.memory_map, boot.memory_map_len) };
+    let mut v: Vec<(u64, u64, bool), MAX_ZONES> = Vec::new();
+    let owned = [
+        (boot.early_heap_paddr, boot.early_heap_len),
+        (boot.low32_pool_paddr, boot.low32_pool_len),
+    ];
+    let ranges = mm
+        .iter()
+        .filter(|r| r.typ == TYPE_CONVENTIONAL)
+        .map(|r| (r.phys_start, r.len, false))
+        .chain(owned.iter().map(|&(s, l)| (s, l, true)));
+    for (s, len, owned) in ranges {
+        let (s, e) = (align_up(s, PAGE), align_down(s + len, PAGE));
+        if e > s && v.push((s, e, owned)).is_err() {
+            kprintln!("[frames] too many ranges; dropping {:#x}..{:#x}", s, e);
+        }
+    }
+    v
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Build the zones from the boot memory map. Runs once, after `reserved`
+/// and `handoff` have their ranges and `mem::init` has the HHDM offset.
+pub fn init(boot: &BootInfo) {
+    let ranges = seed_ranges(boot);
+    let need = align_up(
+        ranges
+            .iter()
+            .map(|&(s, e, _)| map_bytes((e - s) / PAGE))
+            .sum(),
+        PAGE,
+    );
+    // Bitmaps go at the front of a range that is free from its first page:
+    // the early heap if it fits, else the largest conventional one that does.
+    let carve = ranges
+        .iter()
+        .filter(|&&(s, e, owned)| e - s > need && (owned || !reserved::is_reserved_range(s, need)))
+        .max_by_key(|&&(s, e, owned)| (owned && s == boot.early_heap_paddr, e - s))
+        .map(|&(s, ..)| s)
+        .expect("frames: no room for the bitmaps");
+
+    with_zones(|zones| {
+        let mut map = carve + hhdm();
+        for &(s, e, _) in ranges.iter() {
+            add_zone(zones, s, e, map);
+            map += map_bytes((e - s) / PAGE);
+        }
+        for &(s, e, owned) in ranges.iter() {
+            if owned {
+                mark(zones, s, e, true);
+            } else {
+                reserved::for_each_gap(s, e, |s, e| mark(zones, s, e, true));
+            }
+        }
+        // In conventional ranges, but put there after the map was taken.
+        let map_len = (boot.memory_map_len * size_of::<MemoryRegion>()) as u64;
+        let taken = [
+            (carve, need),
+            (boot.memory_map as u64, map_len),
+            (boot.loader_log as u64, boot.loader_log_len as u64),
+        ];
+        for (s, len) in taken {
+            mark(zones, align_down(s, PAGE), align_up(s + len, PAGE), false);
+        }
+        for r in handoff::ranges() {
+            mark(zones, r.phys, r.phys + r.pages * PAGE, false);
+        }
+        if let Some(byte) = poison::pattern() {
+            let mut bytes = 0;
+            for z in zones.iter_mut() {
+                z.free_runs(|s, e| {
+                    poison::fill(s + hhdm(), (e - s) as usize, byte);
+                    bytes += e - s;
+                });
+            }
+            kprintln!("[frames] poisoned {} MiB with {:#04x}", bytes >> 20, byte);
+        }
+    });
+    READY.store(true, Ordering::Release);
+    let st = stats();
+    kprintln!(
+        "[frames] {} zones, {} of {} MiB free, {} KiB of bitmaps",
+        st.zones,
+        (st.free * PAGE) >> 20,
+        (st.total * PAGE) >> 20,
+        need >> 10
+    );
+}
+
+/// Whether `init` has run.
+pub fn ready() -> bool {
+    READY.load(Ordering::Acquire)
+}
+
+/// Whether `add_range(s, e)` would take `[s, e)`: it holds more than its
+/// bitmap, and the zone table has a free slot. Lets a caller check before
+/// mapping the range.
+pub fn room_for(s: u64, e: u64) -> bool {
+    e - s > align_up(map_bytes((e - s) / PAGE), PAGE) && with_zones(|zones| !zones.is_full())
+}
+
+/// Hand `[s, e)` (page-aligned, mapped in the HHDM) to the allocator as a
+/// new zone. Its first pages hold the bitmap. False if the range is too
+/// small to hold anything past that, or the zone table is full.
+pub fn add_range(s: u64, e: u64) -> bool {
+    let map_len = align_up(map_bytes((e - s) / PAGE), PAGE);
+    if e - s <= map_len {
+        return false;
+    }
+    with_zones(|zones| {
+        if zones.is_full() || !add_zone(zones, s, e, s + hhdm()) {
+            return false;
+        }
+        if let Some(byte) = poison::pattern() {
+            poison::fill(s + map_len + hhdm(), (e - s - map_len) as usize, byte);
+        }
+        mark(zones, s + map_len, e, true);
+        true
+    })
+}
+
+/// One free frame's physical address.
+pub fn alloc_frame() -> Option<u64> {
+    let pa = with_zones(|zones| zones.iter_mut().rev().find_map(|z| z.take_one()));
+    if pa.is_some() {
+        crate::counter!("mem.frames_allocated");
+    }
+    pa
+}
+
+/// Give back a frame from `alloc_frame` (or any frame a zone covers, such
+/// as the loader's page tables). Frames outside every zone, and frames
+/// that are already free, are counted and otherwise ignored.
+pub fn free_frame(pa: u64) {
+    let pa = align_down(pa, PAGE);
+    let freed = with_zones(|zones| {
+        let z = zones.iter_mut().find(|z| z.contains(pa))?;
+        Some(z.set((pa - z.start) / PAGE, true))
+    });
+    match freed {
+        Some(true) => crate::counter!("mem.frames_freed"),
+        Some(false) => crate::counter!("mem.frames_double_freed"),
+        None => crate::counter!("mem.frames_untracked"),
+    }
+}
+
+/// `pages` physically contiguous frames, all below `limit` (LOW32_LIMIT
+/// for 32-bit DMA, u64::MAX for anywhere). Returns the first one's address.
+pub fn alloc_contig(pages: usize, limit: u64) -> Option<u64> {
+    if pages == 0 {
+        return None;
+    }
+    let pa = with_zones(|zones| {
+        zones
+            .iter_mut()
+            .find_map(|z| z.take_run(pages as u64, limit, PAGE))
+    });
+    if pa.is_some() {
+        crate::counter!("mem.frames_allocated", pages);
+    }
+    pa
+}
+
+/// `alloc_contig` with the first frame at a multiple of `align` bytes (a
+/// power of two), e.g. for a 2 MiB page. Like single frames, these come
+/// from the highest zone that has such a run.
+pub fn alloc_contig_aligned(pages: usize, align: u64, limit: u64) -> Option<u64> {
+    if pages == 0 {
+        return None;
+    }
+    let align = align.max(PAGE);
+    let pa = with_zones(|zones| {
+        zones
+            .iter_mut()
+            .rev()
+            .find_map(|z| z.take_run(pages as u64, limit, align))
+    });
+    if pa.is_some() {
+        crate::counter!("mem.frames_allocated", pages);
+    }
+    pa
+}
+
+/// Give back a run from `alloc_contig`.
+pub fn free_contig(pa: u64, pages: usize) {
+    for i in 0..pages as u64 {
+        free_frame(pa + i * PAGE);
+    }
+}
+
+pub fn stats() -> FrameStats {
+    with_zones(|zones| collect(zones))
+}
+
+/// `stats` without waiting for the lock, for reports from any context.
+pub fn try_stats() -> Option<FrameStats> {
+    ZONES.try_lock().map(|z| collect(&z))
+}
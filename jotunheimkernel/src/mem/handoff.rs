@@ -0,0 +1,91 @@
+// src/mem/handoff.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Pages jotunboot built for the handoff: every page-table page and the entry
+// trampoline, as recorded in BootInfo. They are LOADER_DATA/CODE, so the
+// frame allocator never sees them. `reclaim()` returns them once nothing can
+// reach them: the trampoline as soon as we run, the page tables only after
+// CR3 has moved to tables of our own. Until then (or for a kexec-style soft
+// reboot) `page_tables()` says exactly which pages they are.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use heapless::Vec;
+use spin::Once;
+use x86_64::registers::control::Cr3;
+
+use crate::bootinfo::{BootInfo, LOADER_RANGE_PAGE_TABLES, LOADER_RANGE_TRAMPOLINE, LoaderRange};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_RANGES: usize = 170; // one loader page of LoaderRange
+
+static RANGES: Once<Vec<LoaderRange, MAX_RANGES>> = Once::new();
+static TRAMP_FREED: AtomicBool = AtomicBool::new(false);
+static TABLES_FREED: AtomicBool = AtomicBool::new(false);
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Copy the range list out of BootInfo. Must run while the loader's identity
+/// mapping is still live; later calls are no-ops.
+pub fn init(boot: &BootInfo) {
+    let v = RANGES.call_once(|| {
+        let mut v = Vec::new();
+        if !boot.loader_ranges.is_null() {
+            let raw =
+                unsafe { core::slice::from_raw_parts(boot.loader_ranges, boot.loader_ranges_len) };
+            let _ = v.extend_from_slice(&raw[..raw.len().min(MAX_RANGES)]);
+        }
+        v
+    });
+    let pt: u64 = page_tables().map(|r| r.pages).sum();
+    kprintln!(
+        "[handoff] {} loader ranges, {} page-table pages",
+        v.len(),
+        pt
+    );
+}
+
+pub fn ranges() -> &'static [LoaderRange] {
+    RANGES.get().map(|v| v.as_slice()).unwrap_or(&[])
+}
+
+pub fn page_tables() -> impl Iterator<Item = &'static LoaderRange> {
+    ranges()
+        .iter()
+        .filter(|r| r.kind == LOADER_RANGE_PAGE_TABLES)
+}
+
+/// Is `pa` one of the loader's page-table pages?
+pub fn is_loader_table(pa: u64) -> bool {
+    page_tables().any(|r| (r.phys..r.phys + r.pages * 4096).contains(&pa))
+}
+
+fn free(kind: u32) -> u64 {
+    let mut n = 0;
+    for r in ranges().iter().filter(|r| r.kind == kind) {
+        for i in 0..r.pages {
+            super::give_back_frame(r.phys + i * 4096);
+        }
+        n += r.pages;
+    }
+    n
+}
+
+/// Give back whatever the loader left that is no longer reachable. Safe to
+/// call repeatedly; each range is freed at most once. Returns pages freed.
+pub fn reclaim() -> u64 {
+    let mut n = 0;
+    if !TRAMP_FREED.swap(true, Ordering::AcqRel) {
+        n += free(LOADER_RANGE_TRAMPOLINE);
+    }
+    let root = Cr3::read().0.start_address().as_u64();
+    if is_loader_table(root) {
+        kprintln!("[handoff] still on the loader's page tables; keeping them");
+    } else if !TABLES_FREED.swap(true, Ordering::AcqRel) {
+        n += free(LOADER_RANGE_PAGE_TABLES);
+    }
+    kprintln!("[handoff] reclaimed {} pages", n);
+    n
+}
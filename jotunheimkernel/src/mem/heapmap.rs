@@ -0,0 +1,242 @@
+// src/mem/heapmap.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Backing for the kernel heap window. The heap is mapped as it is touched
+// (allocations map what they return, the #PF handler maps the rest) and is
+// unmapped again when too much of what is mapped holds nothing.
+//
+// The heap counts live (allocated) bytes per page, under its lock. An empty
+// page may still hold a free-list node: linked_list_allocator keeps each
+// hole's header in the hole's first bytes, and a hole starts right after
+// an allocated byte. So a page can go only when it and the page before it
+// are both empty; then no hole can start in it.
+//
+// Giving pages back takes three steps, because the TLB shootdown must not
+// run under the heap lock (other CPUs spin on it with IRQs off):
+//   1. under the heap and page-table locks, unmap reclaimable pages and park
+//      their frames on the retired list;
+//   2. with no lock held, shoot the range down on every CPU;
+//   3. under the page-table lock, free the frames still on the list.
+// Between 1 and 3 a page can be allocated again and touched. Whoever maps
+// it (`map_page`) takes its retired frame back, so a write another CPU made
+// through a stale TLB entry is not lost.
+//
+// A large enough allocation over a 2 MiB stretch of the window that is
+// wholly unmapped gets one 2 MiB page (`map_huge`). Giving back a page of
+// it splits it into 4 KiB pages first.
+//
+// A kernel thread looks every SCAN_MS and shrinks once more than HIGH_WATER
+// bytes are mapped but free, down to LOW_WATER. `noheapshrink` on the
+// command line turns it off.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags as F, PhysFrame, Size4KiB,
+    Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::vmmap::SIZE_2M;
+use super::{KHEAP_SIZE, KHEAP_START, PAGE_SIZE, frames, huge};
+use crate::{cmdline, kprintln, sched};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const PAGES: usize = KHEAP_SIZE / PAGE_SIZE;
+/// Mapped-but-free bytes that start a shrink, and what it leaves.
+pub const HIGH_WATER: u64 = 4 << 20;
+pub const LOW_WATER: u64 = 1 << 20;
+const SCAN_MS: u64 = 1000;
+const MAX_RETIRED: usize = 512; // pages given back per pass
+
+/// Live bytes in each heap page. Updated under the heap lock.
+pub(super) struct PageUse {
+    live: [u16; PAGES],
+}
+
+static MAPPED: AtomicU64 = AtomicU64::new(0);
+// (va, pa) of pages unmapped by a shrink still waiting for its shootdown.
+// Only touched under PT_LOCK.
+static RETIRED: Mutex<Vec<(u64, u64), MAX_RETIRED>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl PageUse {
+    pub(super) const fn new() -> Self {
+        Self { live: [0; PAGES] }
+    }
+
+    // `f(page index, bytes)` for each page `[p, p + len)` touches.
+    fn each(p: u64, len: usize, mut f: impl FnMut(usize, u16)) {
+        let mut a = p - KHEAP_START;
+        let end = a + len as u64;
+        while a < end {
+            let n = ((a | (PAGE_SIZE as u64 - 1)) + 1).min(end) - a;
+            f((a / PAGE_SIZE as u64) as usize, n as u16);
+            a += n;
+        }
+    }
+
+    /// A block `[p, p + len)` was handed out.
+    pub(super) fn add(&mut self, p: u64, len: usize) {
+        Self::each(p, len, |i, n| self.live[i] += n);
+    }
+
+    /// A block `[p, p + len)` was freed.
+    pub(super) fn sub(&mut self, p: u64, len: usize) {
+        Self::each(p, len, |i, n| self.live[i] -= n);
+    }
+
+    // Page 0 always may hold the first hole's header.
+    fn reclaimable(&self, i: usize) -> bool {
+        i > 0 && self.live[i] == 0 && self.live[i - 1] == 0
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Bytes of the heap window backed by frames.
+pub fn mapped() -> u64 {
+    MAPPED.load(Ordering::Relaxed)
+}
+
+/// Back heap page `va` if it is not: with its retired frame if a shrink
+/// just took it, else a fresh one from `fa`. False if no frame could be
+/// had. Caller holds PT_LOCK.
+pub(super) fn map_page(
+    mapper: &mut OffsetPageTable<'static>,
+    fa: &mut impl FrameAllocator<Size4KiB>,
+    va: u64,
+) -> bool {
+    let va = va & !(PAGE_SIZE as u64 - 1);
+    if mapper.translate_addr(VirtAddr::new(va)).is_some() {
+        return true;
+    }
+    let retired = {
+        let mut r = RETIRED.lock();
+        let i = r.iter().position(|&(v, _)| v == va);
+        i.map(|i| r.swap_remove(i).1)
+    };
+    let pa = match retired {
+        Some(pa) => {
+            crate::counter!("mem.heap_unretired");
+            pa
+        }
+        None => match fa.allocate_frame() {
+            Some(f) => f.start_address().as_u64(),
+            None => return false,
+        },
+    };
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(va));
+    let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(pa));
+    let flags = F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE;
+    let parent = F::PRESENT | F::WRITABLE;
+    match unsafe { mapper.map_to_with_table_flags(page, frame, flags, parent, fa) } {
+        Ok(flush) => flush.flush(),
+        Err(_) => {
+            // A retired frame may still be in some TLB: leave it to `release`.
+            match retired {
+                Some(pa) => {
+                    let _ = RETIRED.lock().push((va, pa));
+                }
+                None => frames::free_frame(pa),
+            }
+            return false;
+        }
+    }
+    MAPPED.fetch_add(PAGE_SIZE as u64, Ordering::Relaxed);
+    true
+}
+
+/// Back `[va, va + 2 MiB)` with one 2 MiB page, if `left` bytes from `va`
+/// are to be mapped and none of that stretch is mapped or retired. False
+/// otherwise, or if no aligned run of frames is free. Caller holds PT_LOCK.
+pub(super) fn map_huge(
+    mapper: &mut OffsetPageTable<'static>,
+    fa: &mut impl FrameAllocator<Size4KiB>,
+    va: u64,
+    left: u64,
+) -> bool {
+    if !huge::fits(va, left) || !huge::slot_free(va) {
+        return false;
+    }
+    // A retired frame there may still be written through a stale TLB entry.
+    if RETIRED
+        .lock()
+        .iter()
+        .any(|&(v, _)| v & !(SIZE_2M - 1) == va)
+    {
+        return false;
+    }
+    let n = (SIZE_2M / PAGE_SIZE as u64) as usize;
+    let Some(pa) = frames::alloc_contig_aligned(n, SIZE_2M, u64::MAX) else {
+        return false;
+    };
+    let flags = F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE;
+    if !huge::map_2m(mapper, va, pa, flags, fa) {
+        frames::free_contig(pa, n);
+        return false;
+    }
+    MAPPED.fetch_add(SIZE_2M, Ordering::Relaxed);
+    true
+}
+
+/// Unmap up to `want` reclaimable pages and retire their frames. Returns
+/// the VA range to shoot down, if any page went. Caller holds the heap
+/// lock (so `pages` is current) and PT_LOCK.
+pub(super) fn retire(
+    mapper: &mut OffsetPageTable<'static>,
+    fa: &mut impl FrameAllocator<Size4KiB>,
+    pages: &PageUse,
+    want: usize,
+) -> Option<(u64, u64)> {
+    let mut r = RETIRED.lock();
+    let (mut lo, mut hi) = (u64::MAX, 0);
+    for i in 1..PAGES {
+        if r.len() >= want.min(MAX_RETIRED) {
+            break;
+        }
+        if !pages.reclaimable(i) {
+            continue;
+        }
+        let va = KHEAP_START + (i * PAGE_SIZE) as u64;
+        // One page at a time: a 2 MiB page is split around it.
+        let Some((pa, _)) = huge::unmap(mapper, fa, va, va + PAGE_SIZE as u64) else {
+            continue;
+        };
+        let _ = r.push((va, pa));
+        MAPPED.fetch_sub(PAGE_SIZE as u64, Ordering::Relaxed);
+        lo = lo.min(va);
+        hi = hi.max(va + PAGE_SIZE as u64);
+    }
+    (hi != 0).then_some((lo, hi))
+}
+
+/// Free the frames still retired, once no TLB can hold them. Returns how
+/// many. Caller holds PT_LOCK.
+pub(super) fn release() -> usize {
+    let mut r = RETIRED.lock();
+    let n = r.len();
+    for &(_, pa) in r.iter() {
+        frames::free_frame(pa);
+    }
+    r.clear();
+    n
+}
+
+/// Start the shrink thread. Needs the scheduler.
+pub fn init() {
+    if cmdline::flag("noheapshrink") {
+        kprintln!("[heap] shrinking off");
+        return;
+    }
+    sched::spawn(|| {
+        loop {
+            sched::sleep_ms(SCAN_MS);
+            super::heap_shrink();
+        }
+    });
+}
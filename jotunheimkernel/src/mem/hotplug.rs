@@ -0,0 +1,109 @@
+// src/mem/hotplug.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Physical memory hot-add. A new RAM range reported at runtime goes through
+// `add()`: it is checked against the boot memory map, earlier additions and
+// reserved ranges, mapped write-back into the HHDM and handed to the frame
+// allocator as a zone of its own. The shell's `mem` lists what was added.
+//
+// Sources: the `memadd <pa> <len>` debug monitor command for testing. ACPI
+// memory devices (PNP0C80) get a `Source` of their own once there is an
+// AML interpreter to deliver their Notify. Removal is not supported:
+// frames handed out from an added range may be anywhere.
+
+use core::sync::atomic::Ordering;
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::PageTableFlags as F;
+
+use super::{HHDM_END, PAGE_SIZE, align_down, align_up, frames, hhdm_map, reserved};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_RANGES: usize = 32;
+const PA_LIMIT: u64 = 1 << 52;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HotplugError {
+    Empty,    // less than one whole page
+    TooHigh,  // beyond the 52-bit physical address space
+    Overlaps, // boot memory, an earlier addition, or a reserved range
+    Full,     // range or allocator table full
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Monitor,
+}
+
+struct State {
+    ranges: Vec<(u64, u64, Source), MAX_RANGES>, // [(start, end, source))
+}
+
+static STATE: Mutex<State> = Mutex::new(State { ranges: Vec::new() });
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn overlaps(s: u64, e: u64, st: &State) -> bool {
+    // Everything below the end of the boot memory map was described at boot.
+    s < HHDM_END.load(Ordering::Acquire)
+        || st.ranges.iter().any(|&(rs, re, _)| s < re && e > rs)
+        || reserved::is_reserved_range(s, e - s)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Bring `[pa, pa + len)` online as RAM. Partial pages at either end are
+/// dropped.
+pub fn add(pa: u64, len: u64, source: Source) -> Result<(), HotplugError> {
+    let s = align_up(pa, PAGE_SIZE as u64);
+    let e = align_down(pa.saturating_add(len), PAGE_SIZE as u64);
+    if e <= s {
+        return Err(HotplugError::Empty);
+    }
+    if e > PA_LIMIT {
+        return Err(HotplugError::TooHigh);
+    }
+    without_interrupts(|| {
+        let mut st = STATE.lock();
+        if overlaps(s, e, &st) {
+            return Err(HotplugError::Overlaps);
+        }
+        // Checked before mapping, so a range the allocator turns down is not
+        // left in the HHDM. After boot, zones are only added here.
+        if st.ranges.is_full() || !frames::room_for(s, e) {
+            return Err(HotplugError::Full);
+        }
+        // Map before the allocator can hand the frames out.
+        hhdm_map(s, (e - s) as usize, F::empty());
+        if !frames::add_range(s, e) {
+            return Err(HotplugError::Full);
+        }
+        let _ = st.ranges.push((s, e, source));
+        Ok(())
+    })?;
+    crate::counter!("mem.hotplug_pages", (e - s) / PAGE_SIZE as u64);
+    kprintln!(
+        "[hotplug] {:#x}..{:#x} online ({} KiB, {:?})",
+        s,
+        e,
+        (e - s) >> 10,
+        source
+    );
+    Ok(())
+}
+
+/// Call `f` with every range added so far, as (start, end, source). False
+/// (and no calls) if the table is busy.
+pub fn for_each(mut f: impl FnMut(u64, u64, Source)) -> bool {
+    without_interrupts(|| match STATE.try_lock() {
+        Some(st) => {
+            st.ranges.iter().for_each(|&(s, e, src)| f(s, e, src));
+            true
+        }
+        None => false,
+    })
+}
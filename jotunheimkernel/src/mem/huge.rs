@@ -0,0 +1,166 @@
+// src/mem/huge.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// 2 MiB pages for kernel mappings. `map_mmio`, vmap and the heap map a
+// 2 MiB page wherever the VA (and, for MMIO, the PA) is 2 MiB aligned and
+// the range covers the whole page, and 4 KiB pages elsewhere. One TLB
+// entry then covers what would take 512.
+//
+// Whatever later needs a single 4 KiB page out of a 2 MiB one (unmapping
+// part of it, the heap giving back one page) splits it first with
+// `split_huge_2m`: a new page table maps the same 512 frames with the same
+// flags and replaces the large entry. The translation does not change, so
+// other CPUs may keep the large entry in their TLBs until the caller shoots
+// down what it changes next.
+//
+// `nohugepages` on the command line keeps every mapping at 4 KiB.
+
+use spin::Once;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags as F, PhysFrame,
+    Size2MiB, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::vmmap::{SIZE_2M, SIZE_4K};
+use super::{PHYS_TO_VIRT_OFFSET, active_level4_table_virt};
+use crate::cmdline;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const PAT_HUGE: u64 = 1 << 12; // PAT bit of a 2 MiB entry; bit 7 in a 4 KiB one
+
+static ENABLED: Once<bool> = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// The page-directory entry covering `va`, if the tables down to it exist.
+fn l2_entry(va: u64) -> Option<&'static mut PageTableEntry> {
+    let off = unsafe { PHYS_TO_VIRT_OFFSET };
+    let mut t: &'static mut PageTable = active_level4_table_virt();
+    for shift in [39, 30] {
+        let e = &t[((va >> shift) & 0x1FF) as usize];
+        let fl = e.flags();
+        if !fl.contains(F::PRESENT) || fl.contains(F::HUGE_PAGE) {
+            return None;
+        }
+        t = unsafe { &mut *((e.addr().as_u64() + off) as *mut PageTable) };
+    }
+    Some(&mut t[((va >> 21) & 0x1FF) as usize])
+}
+
+fn is_huge(e: &PageTableEntry) -> bool {
+    e.flags().contains(F::PRESENT | F::HUGE_PAGE)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    *ENABLED.call_once(|| !cmdline::flag("nohugepages"))
+}
+
+/// A 2 MiB page can start at `va` with `left` bytes of the range to map.
+pub fn fits(va: u64, left: u64) -> bool {
+    enabled() && va.is_multiple_of(SIZE_2M) && left >= SIZE_2M
+}
+
+/// Nothing is mapped in the 2 MiB at `va`, not even a page table.
+/// Caller holds PT_LOCK.
+pub fn slot_free(va: u64) -> bool {
+    l2_entry(va).is_none_or(|e| e.is_unused())
+}
+
+/// Map `[va, va + 2 MiB)` to `pa` with one 2 MiB page. False if something
+/// is mapped there already or no frame could be had for a table. Caller
+/// holds PT_LOCK.
+pub fn map_2m(
+    mapper: &mut OffsetPageTable<'static>,
+    va: u64,
+    pa: u64,
+    flags: F,
+    fa: &mut impl FrameAllocator<Size4KiB>,
+) -> bool {
+    let (Ok(page), Ok(frame)) = (
+        Page::<Size2MiB>::from_start_address(VirtAddr::new(va)),
+        PhysFrame::<Size2MiB>::from_start_address(PhysAddr::new(pa)),
+    ) else {
+        return false;
+    };
+    let parent = F::PRESENT | F::WRITABLE;
+    match unsafe { mapper.map_to_with_table_flags(page, frame, flags, parent, fa) } {
+        Ok(flush) => {
+            flush.flush();
+            crate::counter!("mem.huge_mapped");
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Turn the 2 MiB page around `va` into a table of 4 KiB pages mapping the
+/// same frames with the same flags. True if `va` is mapped by 4 KiB pages
+/// afterwards (already, or now); false if it is not mapped at all or no
+/// frame could be had for the table. Caller holds PT_LOCK.
+pub fn split_huge_2m(fa: &mut impl FrameAllocator<Size4KiB>, va: u64) -> bool {
+    let Some(e) = l2_entry(va) else {
+        return false;
+    };
+    let fl = e.flags();
+    if !fl.contains(F::PRESENT) {
+        return false;
+    }
+    if !fl.contains(F::HUGE_PAGE) {
+        return true;
+    }
+    let Some(table) = fa.allocate_frame() else {
+        return false;
+    };
+    let tpa = table.start_address().as_u64();
+    let raw = e.addr().as_u64();
+    let base = raw & !(SIZE_2M - 1);
+    let mut small = fl - F::HUGE_PAGE;
+    if raw & PAT_HUGE != 0 {
+        small |= F::HUGE_PAGE;
+    }
+    let t = unsafe { &mut *((tpa + PHYS_TO_VIRT_OFFSET) as *mut PageTable) };
+    for (i, pte) in t.iter_mut().enumerate() {
+        pte.set_addr(PhysAddr::new(base + i as u64 * SIZE_4K), small);
+    }
+    let parent = F::PRESENT | F::WRITABLE | (fl & F::USER_ACCESSIBLE);
+    e.set_addr(PhysAddr::new(tpa), parent);
+    tlb::flush(VirtAddr::new(va & !(SIZE_2M - 1)));
+    crate::counter!("mem.huge_split");
+    true
+}
+
+/// Unmap the page at `va` from a range ending at `end`: a whole 2 MiB page
+/// if one starts at `va` and the range covers it, else 4 KiB, splitting a
+/// 2 MiB page around `va` first. Returns what was unmapped as (pa, bytes);
+/// None if nothing was mapped at `va` or the split failed. Flushes only
+/// this CPU's TLB. Caller holds PT_LOCK.
+pub fn unmap(
+    mapper: &mut OffsetPageTable<'static>,
+    fa: &mut impl FrameAllocator<Size4KiB>,
+    va: u64,
+    end: u64,
+) -> Option<(u64, u64)> {
+    if let Some(e) = l2_entry(va)
+        && is_huge(e)
+    {
+        if va.is_multiple_of(SIZE_2M) && end - va >= SIZE_2M {
+            let pa = e.addr().as_u64() & !(SIZE_2M - 1);
+            e.set_unused();
+            tlb::flush(VirtAddr::new(va));
+            return Some((pa, SIZE_2M));
+        }
+        if !split_huge_2m(fa, va) {
+            return None;
+        }
+    }
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(va));
+    let (frame, flush) = mapper.unmap(page).ok()?;
+    flush.flush();
+    Some((frame.start_address().as_u64(), SIZE_4K))
+}
@@ -0,0 +1,215 @@
+// src/mem/lru.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// One LRU over every clean, reclaimable page in the kernel: block-cache
+// pages today, file mappings later. Each page belongs to an owner (a cache)
+// and sits at an index within it. Pages are whole frames read through the
+// HHDM, so evicting one hands a frame straight back to the allocator.
+//
+// The LRU registers itself as an OOM shrinker: under memory pressure the
+// least recently used pages are dropped, skipping any that are pinned
+// (mem::pin). Owners never hold a reference to a page across calls; they
+// read and write it inside `read`/`update`, under the LRU lock, so a page
+// cannot be evicted while in use. Only clean pages live here: owners write
+// through before updating their copy.
+
+extern crate alloc;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{PAGE_SIZE, give_back_frame, oom, pin, vmmap};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub type OwnerId = u32;
+pub type Page = [u8; PAGE_SIZE];
+
+type Key = (OwnerId, u64);
+
+struct Entry {
+    pa: u64,
+    stamp: u64,
+}
+
+struct Lru {
+    pages: BTreeMap<Key, Entry>,
+    order: BTreeMap<u64, Key>, // stamp -> page, oldest first
+    next_stamp: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LruStats {
+    pub pages: u64,
+    pub inserted: u64,
+    pub evicted: u64,
+}
+
+static LRU: Mutex<Lru> = Mutex::new(Lru {
+    pages: BTreeMap::new(),
+    order: BTreeMap::new(),
+    next_stamp: 0,
+});
+static NEXT_OWNER: AtomicU32 = AtomicU32::new(1);
+static INSERTED: AtomicU64 = AtomicU64::new(0);
+static EVICTED: AtomicU64 = AtomicU64::new(0);
+static SHRINKER: Once = Once::new();
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn page_at(pa: u64) -> &'static mut Page {
+    unsafe { &mut *((pa + vmmap::hhdm()) as *mut Page) }
+}
+
+impl Lru {
+    fn touch(&mut self, key: Key) -> Option<u64> {
+        let stamp = self.next_stamp;
+        let e = self.pages.get_mut(&key)?;
+        self.order.remove(&e.stamp);
+        e.stamp = stamp;
+        self.order.insert(stamp, key);
+        self.next_stamp += 1;
+        Some(e.pa)
+    }
+
+    fn remove(&mut self, key: Key) -> Option<u64> {
+        let e = self.pages.remove(&key)?;
+        self.order.remove(&e.stamp);
+        Some(e.pa)
+    }
+}
+
+fn locked<R>(f: impl FnOnce(&mut Lru) -> R) -> R {
+    without_interrupts(|| f(&mut LRU.lock()))
+}
+
+// OOM shrinker: drop up to `n` of the oldest pages. Skips the pass rather
+// than wait if the LRU is busy (the allocation may come from inside it).
+fn shrink(n: usize) -> usize {
+    without_interrupts(|| {
+        let Some(mut lru) = LRU.try_lock() else {
+            return 0;
+        };
+        let mut freed = 0;
+        // Collect victims in fixed batches: a shrinker must not allocate,
+        // and removing from the maps only frees.
+        while freed < n {
+            let mut batch: heapless::Vec<(u64, Key, u64), 32> = heapless::Vec::new();
+            for (&stamp, &key) in lru.order.iter() {
+                if batch.is_full() || freed + batch.len() >= n {
+                    break;
+                }
+                let pa = lru.pages[&key].pa;
+                if !pin::is_pinned(pa + vmmap::hhdm(), PAGE_SIZE) {
+                    let _ = batch.push((stamp, key, pa));
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+            for (stamp, key, pa) in batch {
+                lru.order.remove(&stamp);
+                lru.pages.remove(&key);
+                give_back_frame(pa);
+                freed += 1;
+            }
+        }
+        EVICTED.fetch_add(freed as u64, Ordering::Relaxed);
+        crate::counter!("mem.lru_evicted", freed);
+        freed
+    })
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// A fresh owner id for a cache.
+pub fn new_owner() -> OwnerId {
+    SHRINKER.call_once(|| oom::register("lru", shrink));
+    NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Run `f` on the page at `(owner, index)` and mark it recently used.
+/// False if the page is not cached.
+pub fn read(owner: OwnerId, index: u64, f: impl FnOnce(&Page)) -> bool {
+    locked(|lru| match lru.touch((owner, index)) {
+        Some(pa) => {
+            f(page_at(pa));
+            true
+        }
+        None => false,
+    })
+}
+
+/// Modify a cached page in place (the owner has already written the change
+/// through). False if the page is not cached.
+pub fn update(owner: OwnerId, index: u64, f: impl FnOnce(&mut Page)) -> bool {
+    locked(|lru| match lru.touch((owner, index)) {
+        Some(pa) => {
+            f(page_at(pa));
+            true
+        }
+        None => false,
+    })
+}
+
+/// Cache a page filled by `fill`. `fill` runs before the page is visible,
+/// with no lock held, and may fail (false) to abandon the insert. Returns
+/// false if there was no frame to spare or `fill` failed. A page someone
+/// else cached meanwhile wins.
+pub fn insert(owner: OwnerId, index: u64, fill: impl FnOnce(&mut Page) -> bool) -> bool {
+    let Ok(pa) = super::alloc_frame() else {
+        return false;
+    };
+    if !fill(page_at(pa)) {
+        give_back_frame(pa);
+        return false;
+    }
+    let key = (owner, index);
+    let dup = locked(|lru| {
+        if lru.pages.contains_key(&key) {
+            return true;
+        }
+        let stamp = lru.next_stamp;
+        lru.next_stamp += 1;
+        lru.pages.insert(key, Entry { pa, stamp });
+        lru.order.insert(stamp, key);
+        false
+    });
+    if dup {
+        give_back_frame(pa);
+    } else {
+        INSERTED.fetch_add(1, Ordering::Relaxed);
+    }
+    true
+}
+
+/// Forget one page, e.g. after a write that could not update it.
+pub fn invalidate(owner: OwnerId, index: u64) {
+    if let Some(pa) = locked(|lru| lru.remove((owner, index))) {
+        give_back_frame(pa);
+    }
+}
+
+/// Drop every page of `owner`; the cache is going away.
+pub fn drop_owner(owner: OwnerId) {
+    loop {
+        let next = locked(|lru| {
+            let key = *lru.pages.range((owner, 0)..=(owner, u64::MAX)).next()?.0;
+            lru.remove(key)
+        });
+        match next {
+            Some(pa) => give_back_frame(pa),
+            None => break,
+        }
+    }
+}
+
+pub fn stats() -> LruStats {
+    LruStats {
+        pages: locked(|lru| lru.pages.len() as u64),
+        inserted: INSERTED.load(Ordering::Relaxed),
+        evicted: EVICTED.load(Ordering::Relaxed),
+    }
+}
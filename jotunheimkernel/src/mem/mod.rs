@@ -1,17 +1,33 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+pub mod aspace;
+pub mod frames;
+pub mod handoff;
+pub mod heapmap;
+pub mod hotplug;
+pub mod huge;
+pub mod lru;
+pub mod oom;
+pub mod pagefault;
+pub mod pin;
+pub mod pmem;
+pub mod poison;
+pub mod ptcheck;
 pub mod reserved;
-pub mod simple_alloc;
+pub mod slab;
+pub mod vaspace;
+pub mod vmmap;
 
 extern crate alloc;
-use core::sync::atomic::{AtomicU64, Ordering, fence};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{
     alloc::{GlobalAlloc, Layout},
     sync::atomic::AtomicBool,
 };
-use heapless::Vec as HVec;
 use linked_list_allocator::Heap as LlHeap;
-use spin::{Mutex, MutexGuard};
+use linked_list_allocator::hole::HoleList;
+use spin::Mutex;
 use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::registers::control::Cr0Flags;
 use x86_64::structures::paging::{PageTableFlags as F, Translate};
@@ -26,23 +42,34 @@ use x86_64::{
 static PT_LOCK: Mutex<()> = Mutex::new(());
 
 use crate::bootinfo::BootInfo;
+use crate::debug::{irqalloc, latency};
+use crate::error::{KError, KResult};
 use crate::kprintln;
+use crate::mem::vaspace::VaSpace;
 
 const PAGE_SIZE: usize = 4096;
 const VMAP_BASE: u64 = 0xffff_e000_0000_0000;
+const VA_WINDOW: u64 = 1 << 44; // each of the vmap and MMIO windows
 
-static NEXT_VMAP: AtomicU64 = AtomicU64::new(VMAP_BASE);
+static VMAP_VA: VaSpace = VaSpace::new("vmap", VMAP_BASE, VA_WINDOW);
 static mut PHYS_TO_VIRT_OFFSET: u64 = 0;
 static HEAP_READY: AtomicBool = AtomicBool::new(false);
-static FRAME_ALLOC: Mutex<Option<simple_alloc::TinyBump>> = Mutex::new(None);
 
 // ── Heap window (separate from HHDM!) ────────────────────────────────────────
 pub const KHEAP_START: u64 = 0xffff_c000_0000_0000; // moved out of HHDM
 pub const KHEAP_SIZE: usize = 32 * 1024 * 1024;
+// Mapped by `init_heap`; the rest on first touch (`heap_fault`).
+const KHEAP_PREMAP: usize = 1024 * 1024;
+// How long the #PF handler tries for the page-table lock before giving up.
+const PT_FAULT_SPINS: u32 = 1 << 24;
 
 // ── MMIO window (separate VA space; 4 KiB mappings with NO_CACHE) ──────────
 const MMIO_BASE: u64 = 0xffff_d000_0000_0000;
-static NEXT_MMIO_VA: AtomicU64 = AtomicU64::new(MMIO_BASE);
+static MMIO_VA: VaSpace = VaSpace::new("mmio", MMIO_BASE, VA_WINDOW);
+
+// The loader's HHDM covers [0, HHDM_END): up to the end of the highest
+// range in the boot memory map. Past it, `ensure_hhdm` maps on demand.
+static HHDM_END: AtomicU64 = AtomicU64::new(0);
 
 fn align_down(x: u64, a: u64) -> u64 {
     x & !(a - 1)
@@ -52,11 +79,12 @@ fn align_up(x: u64, a: u64) -> u64 {
     (x + (a - 1)) & !(a - 1)
 }
 
+#[track_caller]
 fn pt_locked<F, R>(f: F) -> R
 where
     F: FnOnce() -> R,
 {
-    without_interrupts(|| {
+    latency::without_interrupts(|| {
         let g = PT_LOCK.lock();
         let r: R = f();
         drop(g);
@@ -73,16 +101,10 @@ pub fn init(boot: &BootInfo) {
     unsafe {
         PHYS_TO_VIRT_OFFSET = off;
     }
+    let mm = unsafe { core::slice::from_raw_parts(boot.memory_map, boot.memory_map_len) };
+    let top = mm.iter().map(|r| r.phys_start + r.len).max().unwrap_or(0);
+    HHDM_END.store(align_up(top, PAGE_SIZE as u64), Ordering::Release);
 
-    let start = align_down(boot.early_heap_paddr, 0x1000);
-    let end = align_up(boot.early_heap_paddr + boot.early_heap_len, 0x1000);
-    *FRAME_ALLOC.lock() = Some(simple_alloc::TinyBump::new(start, end));
-
-    if boot.low32_pool_len >= 0x1000 {
-        let lstart = align_down(boot.low32_pool_paddr, 0x1000);
-        let lend = align_up(boot.low32_pool_paddr + boot.low32_pool_len, 0x1000);
-        *LOW32_ALLOC.lock() = Some(simple_alloc::TinyBump::new(lstart, lend));
-    }
     use x86_64::registers::control::Cr0;
     unsafe { Cr0::write(Cr0::read() | Cr0Flags::WRITE_PROTECT) }
 }
@@ -128,41 +150,130 @@ const fn pa_mask_52(x: u64) -> u64 {
     x & 0x000F_FFFF_FFFF_FFFF
 }
 
-/// Map a physical MMIO region at a dedicated VA (not inside HHDM), 4 KiB pages, NO_CACHE.
+/// Make `[pa, pa + len)` reachable at `hhdm + pa`. The loader's HHDM stops
+/// at the end of the boot memory map, so MMIO above it (64-bit PCI BARs)
+/// and memory added later are missing; their pages are mapped here,
+/// uncached, 4 KiB at a time. Pages already mapped are left as they are.
+/// Returns the HHDM address of `pa`.
+pub fn ensure_hhdm(pa: u64, len: usize) -> u64 {
+    hhdm_map(pa, len, F::WRITE_THROUGH | F::NO_CACHE)
+}
+
+// `ensure_hhdm` with the given caching flags: none for RAM (write-back),
+// WRITE_THROUGH | NO_CACHE for MMIO.
+fn hhdm_map(pa: u64, len: usize, cache: F) -> u64 {
+    let off = unsafe { PHYS_TO_VIRT_OFFSET };
+    let end = align_up(pa_mask_52(pa + len as u64), PAGE_SIZE as u64);
+    if end <= HHDM_END.load(Ordering::Acquire) {
+        return pa + off;
+    }
+    let added = pt_locked(|| {
+        let mut mapper = active_mapper();
+        let mut fa = FrameSource::new().expect("ensure_hhdm: no frames");
+        let flags = F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE | cache;
+        let mut added = 0u64;
+        let mut p = align_down(pa_mask_52(pa), PAGE_SIZE as u64);
+        while p < end {
+            let va = VirtAddr::new(p + off);
+            if mapper.translate_addr(va).is_none() {
+                let page = Page::<Size4KiB>::containing_address(va);
+                let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(p));
+                unsafe { mapper.map_to(page, frame, flags, &mut fa).unwrap().flush() };
+                added += PAGE_SIZE as u64;
+            }
+            p += PAGE_SIZE as u64;
+        }
+        added
+    });
+    if added != 0 {
+        crate::counter!("mem.hhdm_pages", added / PAGE_SIZE as u64);
+        ptcheck::after_change("hhdm", added);
+    }
+    pa + off
+}
+
+/// Map a physical MMIO region at a dedicated VA (not inside HHDM), NO_CACHE,
+/// with 2 MiB pages where the region is 2 MiB aligned and 4 KiB elsewhere.
+/// The range is also made reachable through the HHDM (`ensure_hhdm`).
 /// Returns the VA base address.
 pub fn map_mmio(pa: u64, len: usize) -> u64 {
-    pt_locked(|| {
+    ensure_hhdm(pa, len);
+    let va = pt_locked(|| {
         let pa0 = pa_mask_52(pa) & !0xFFF;
         let pend = pa_mask_52(pa + len as u64 + 0xFFF) & !0xFFF;
         let size = pend - pa0;
         let off = pa - pa0;
 
-        let va0 = NEXT_MMIO_VA.fetch_add(size, Ordering::SeqCst);
+        let va0 = if huge::fits(pa0, size) {
+            MMIO_VA.alloc_aligned(size, vmmap::SIZE_2M)
+        } else {
+            MMIO_VA.alloc(size)
+        };
+        let va0 = va0.expect("map_mmio: MMIO window full");
 
         let mut mapper = active_mapper();
-        let mut fa = TinyAllocGuard::new().expect("map_mmio: no frames");
+        let mut fa = FrameSource::new().expect("map_mmio: no frames");
         let flags = F::PRESENT | F::WRITABLE | F::NO_CACHE | F::NO_EXECUTE;
 
         let mut pa_cur = pa0;
         let mut va_cur = va0;
         while pa_cur < pend {
-            // SAFETY: pa_cur is masked to 52 bits and 4K aligned
-            let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(pa_cur));
-            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(va_cur));
-            unsafe {
-                mapper.map_to(page, frame, flags, &mut fa).unwrap().flush();
-            }
-            pa_cur += 0x1000;
-            va_cur += 0x1000;
+            let step = if huge::fits(va_cur, pend - pa_cur)
+                && pa_cur.is_multiple_of(vmmap::SIZE_2M)
+                && huge::map_2m(&mut mapper, va_cur, pa_cur, flags, &mut fa)
+            {
+                vmmap::SIZE_2M
+            } else {
+                // SAFETY: pa_cur is masked to 52 bits and 4K aligned
+                let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(pa_cur));
+                let page = Page::<Size4KiB>::containing_address(VirtAddr::new(va_cur));
+                unsafe {
+                    mapper.map_to(page, frame, flags, &mut fa).unwrap().flush();
+                }
+                0x1000
+            };
+            pa_cur += step;
+            va_cur += step;
         }
         va0 + off
-    })
+    });
+    ptcheck::after_change("map_mmio", len as u64);
+    va
+}
+
+/// Tear down a `map_mmio` mapping (`va` and `len` as it returned and was
+/// given), on every CPU, and return its VA range for reuse. Device frames
+/// are not ours to free; the HHDM alias stays.
+pub fn unmap_mmio(va: u64, len: usize) {
+    let va0 = va & !0xFFF;
+    let vend = align_up(va + len as u64, PAGE_SIZE as u64);
+    // Checked before the unmap, not just by `free` after it.
+    assert!(MMIO_VA.contains(va0), "unmap_mmio: {:#x} is not MMIO", va);
+    pt_locked(|| {
+        let mut mapper = active_mapper();
+        let mut fa = FrameSource::new().expect("unmap_mmio: no frames");
+        let mut p = va0;
+        while p < vend {
+            p += huge::unmap(&mut mapper, &mut fa, p, vend).map_or(PAGE_SIZE as u64, |(_, n)| n);
+        }
+    });
+    flush_range_all_cpus(va0, vend - va0);
+    MMIO_VA.free(va0, vend - va0);
+    crate::counter!("mem.mmio_unmapped_pages", (vend - va0) / PAGE_SIZE as u64);
+}
+
+/// Drop `[va, va + len)` from every CPU's TLB, global entries included,
+/// and wait until all have. Call after unmapping or changing the flags of
+/// kernel mappings other CPUs may have used, before reusing what they
+/// mapped; outside `pt_locked`, and never from IRQ context.
+pub fn flush_range_all_cpus(va: u64, len: u64) {
+    crate::arch::x86_64::shootdown::flush_range(va, va + len);
 }
 
 pub fn map_identity_4k(phys: u64) {
     pt_locked(|| {
         let mut mapper = active_mapper();
-        let mut fa = TinyAllocGuard::new().expect("idmap4k: no frames");
+        let mut fa = FrameSource::new().expect("idmap4k: no frames");
         let page = Page::<Size4KiB>::containing_address(VirtAddr::new(phys));
         let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(phys));
         unsafe {
@@ -176,54 +287,287 @@ pub fn map_identity_4k(phys: u64) {
 }
 
 pub fn alloc_one_phys_page_hhdm() -> (u64, u64) {
-    let mut guard = LOW32_ALLOC.lock();
-    let bump = guard.as_mut().expect("low32 allocator not seeded");
-    let pf = bump.allocate_frame().expect("no low32 frame available");
-    let pa = pf.start_address().as_u64();
+    try_alloc_one_phys_page_hhdm().expect("no low32 frame available")
+}
+
+/// Like `alloc_one_phys_page_hhdm`, but reports exhaustion instead of panicking.
+/// Pages come from the <4 GiB pool, so they are usable for 32-bit DMA.
+pub fn try_alloc_one_phys_page_hhdm() -> KResult<(u64, u64)> {
+    let pa = frames::alloc_contig(1, frames::LOW32_LIMIT).ok_or(KError::OutOfMemory)?;
     let va = pa + unsafe { PHYS_TO_VIRT_OFFSET };
     unsafe { core::ptr::write_bytes(va as *mut u8, 0, 4096) };
-    (va, pa)
+    Ok((va, pa))
+}
+
+/// `pages` physically contiguous, zeroed low (<4 GiB) pages, e.g. a virtqueue.
+/// Give them back with `frames::free_contig`.
+pub fn try_alloc_low32_contig(pages: usize) -> KResult<(u64, u64)> {
+    if pages == 0 {
+        return Err(KError::InvalidArg);
+    }
+    let start = frames::alloc_contig(pages, frames::LOW32_LIMIT).ok_or(KError::OutOfMemory)?;
+    let va = start + unsafe { PHYS_TO_VIRT_OFFSET };
+    unsafe { core::ptr::write_bytes(va as *mut u8, 0, pages * 4096) };
+    Ok((va, start))
 }
 
 pub fn init_heap() {
-    let bytes = KHEAP_SIZE;
+    let bytes = KHEAP_PREMAP;
     let mut mapper = active_mapper(); // safe here: call init_heap() only after mem::init()
-    let mut fa = TinyAllocGuard::new().expect("premap_kheap_head: TinyBump not ready");
-
-    let pages = ((bytes + 4095) / 4096).max(1);
-    for i in 0..pages {
-        let va = KHEAP_START + (i as u64) * 4096;
-        let pf = fa
-            .allocate_frame()
-            .expect("premap_kheap_head: out of frames");
-        map_4k(
-            &mut mapper,
-            va,
-            pf.start_address().as_u64(),
-            F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE,
-            &mut fa,
-        );
-    }
+    let mut fa = FrameSource::new().expect("premap_kheap_head: no frame allocator");
+
+    pt_locked(|| {
+        for va in (KHEAP_START..KHEAP_START + bytes as u64).step_by(PAGE_SIZE) {
+            let ok = heapmap::map_page(&mut mapper, &mut fa, va);
+            assert!(ok, "premap_kheap_head: out of frames");
+        }
+    });
     unsafe {
         GLOBAL_ALLOC.init(KHEAP_START as *mut u8, KHEAP_SIZE);
     }
     HEAP_READY.store(true, Ordering::SeqCst);
+    ptcheck::after_change("heap", bytes as u64);
+}
+
+/// Back the heap page under `va` with a frame, for the #PF handler: the
+/// heap is mapped as it is touched, and linked_list_allocator writes hole
+/// headers into free memory no allocation mapped. False if `va` is not in
+/// the heap, or no frame or page-table lock could be had; the fault is then
+/// a real one.
+pub fn heap_fault(va: u64) -> bool {
+    let heap = KHEAP_START..KHEAP_START + KHEAP_SIZE as u64;
+    if !HEAP_READY.load(Ordering::Acquire) || !heap.contains(&va) {
+        return false;
+    }
+    // A fault taken under the lock on this CPU would spin forever.
+    let Some(_g) = (0..PT_FAULT_SPINS).find_map(|_| {
+        core::hint::spin_loop();
+        PT_LOCK.try_lock()
+    }) else {
+        return false;
+    };
+    let Some(mut fa) = FrameSource::new() else {
+        return false;
+    };
+    // Another CPU may have got here first; then this maps nothing.
+    if !heapmap::map_page(&mut active_mapper(), &mut fa, va) {
+        return false;
+    }
+    crate::counter!("mem.heap_faults");
+    true
+}
+
+/// Give back heap pages that hold nothing, once more than
+/// `heapmap::HIGH_WATER` bytes are mapped but free. Returns how many went.
+/// Shoots down TLBs: call with no lock held, not from IRQ context.
+pub fn heap_shrink() -> usize {
+    let Some((lo, hi)) = GLOBAL_ALLOC.inner.lock().retire_free() else {
+        return 0;
+    };
+    flush_range_all_cpus(lo, hi - lo);
+    let n = pt_locked(heapmap::release);
+    crate::counter!("mem.heap_shrunk_pages", n);
+    n
+}
+
+/// Heap size, what is backed by frames, and what is allocated, in bytes.
+#[derive(Copy, Clone, Debug)]
+pub struct HeapStats {
+    pub size: u64,
+    pub mapped: u64,
+    pub used: u64,
+}
+
+/// None if the heap is locked.
+pub fn heap_stats() -> Option<HeapStats> {
+    let (used, size) = GLOBAL_ALLOC.inner.try_lock().and_then(|h| h.usage())?;
+    Some(HeapStats {
+        size: size as u64,
+        mapped: heapmap::mapped(),
+        used: used as u64,
+    })
+}
+
+// Bytes in `pages` pages. Zero pages, or a count that overflows, is invalid.
+fn vmap_bytes(pages: usize) -> KResult<u64> {
+    match pages.checked_mul(PAGE_SIZE) {
+        Some(b) if b != 0 => Ok(b as u64),
+        _ => Err(KError::InvalidArg),
+    }
 }
 
 /// VMAP-backed anonymous pages outside KHEAP. Does its own VA reservation + PFN mapping.
 /// Never calls the heap allocator.
-pub fn vmap_alloc_pages(pages: usize) -> Option<*mut u8> {
-    let bytes = pages.checked_mul(PAGE_SIZE)? as u64;
-    let base = NEXT_VMAP.fetch_add(bytes, Ordering::SeqCst);
+pub fn vmap_alloc_pages(pages: usize) -> KResult<*mut u8> {
+    let bytes = vmap_bytes(pages)?;
+    crate::counter!("mem.vmap_pages", pages);
+    let base = if huge::enabled() && bytes >= vmmap::SIZE_2M {
+        VMAP_VA.alloc_aligned(bytes, vmmap::SIZE_2M)
+    } else {
+        VMAP_VA.alloc(bytes)
+    }
+    .ok_or(KError::OutOfMemory)?;
+    let p = vmap_map(base, bytes);
+    if p.is_none() {
+        vmap_release(base, bytes, 0);
+    }
+    ptcheck::after_change("vmap", bytes);
+    p.ok_or(KError::OutOfMemory)
+}
+
+/// Like `vmap_alloc_pages`, with one page left unmapped right below the
+/// returned base: a stack that runs off its end faults instead of
+/// scribbling over its neighbour. Free it with `vmap_free_guarded`.
+pub fn vmap_alloc_guarded(pages: usize) -> KResult<*mut u8> {
+    let bytes = vmap_bytes(pages)?;
+    crate::counter!("mem.vmap_pages", pages);
+    let guard = VMAP_VA
+        .alloc(bytes + PAGE_SIZE as u64)
+        .ok_or(KError::OutOfMemory)?;
+    let p = vmap_map(guard + PAGE_SIZE as u64, bytes);
+    if p.is_none() {
+        vmap_release(guard + PAGE_SIZE as u64, bytes, PAGE_SIZE as u64);
+    }
+    ptcheck::after_change("vmap", bytes);
+    p.ok_or(KError::OutOfMemory)
+}
+
+/// CET shadow-stack pages below a guard page: read-only + dirty, the
+/// encoding the CPU reserves for shadow stacks. They are zeroed, and with
+/// `token` the top slot holds a supervisor shadow-stack token, written
+/// through the HHDM since the mapping itself takes no ordinary stores.
+/// Free it with `vmap_free_guarded`.
+pub fn vmap_alloc_shadow(pages: usize, token: bool) -> KResult<*mut u8> {
+    let bytes = vmap_bytes(pages)?;
+    crate::counter!("mem.vmap_pages", pages);
+    let guard = VMAP_VA
+        .alloc(bytes + PAGE_SIZE as u64)
+        .ok_or(KError::OutOfMemory)?;
+    let base = guard + PAGE_SIZE as u64;
+    let p = vmap_map_shadow(base, bytes, token);
+    if p.is_none() {
+        vmap_release(base, bytes, PAGE_SIZE as u64);
+    }
+    p.ok_or(KError::OutOfMemory)
+}
 
+fn vmap_map_shadow(base: u64, bytes: u64, token: bool) -> Option<*mut u8> {
     let mut mapper = active_mapper();
-    let mut fa = TinyAllocGuard::new()?;
+    let mut fa = FrameSource::new()?;
+    let flags = F::PRESENT | F::DIRTY | F::GLOBAL | F::NO_EXECUTE;
+
+    let mut off = 0u64;
+    while off < bytes {
+        let pa = frame_or_oom(&mut fa, "vmap")?.start_address().as_u64();
+        let hhdm = (pa + unsafe { PHYS_TO_VIRT_OFFSET }) as *mut u8;
+        unsafe { core::ptr::write_bytes(hhdm, 0, PAGE_SIZE) };
+        if token && off + PAGE_SIZE as u64 == bytes {
+            // A supervisor token holds its own address, busy bit clear.
+            let slot = base + bytes - 8;
+            unsafe { (hhdm.add(PAGE_SIZE - 8) as *mut u64).write(slot) };
+        }
+        map_4k(&mut mapper, base + off, pa, flags, &mut fa);
+        off += PAGE_SIZE as u64;
+    }
+    Some(base as *mut u8)
+}
+
+/// One 4 KiB frame, reached through the HHDM, for memory that is never
+/// mapped elsewhere (cache pages). Goes through the OOM path when frames
+/// run out. Free it with `give_back_frame`.
+pub fn alloc_frame() -> KResult<u64> {
+    let mut fa = FrameSource::new().ok_or(KError::OutOfMemory)?;
+    let frame = frame_or_oom(&mut fa, "frame").ok_or(KError::OutOfMemory)?;
+    Ok(frame.start_address().as_u64())
+}
+
+/// Unmap pages from `vmap_alloc_pages`, flush them from every CPU's TLB,
+/// and recycle their frames and VA range. Takes the page-table lock and
+/// waits for the other CPUs: not from IRQ context, nor with a lock they
+/// may spin on with IRQs off.
+pub fn vmap_free_pages(base: *mut u8, pages: usize) {
+    vmap_release(base as u64, (pages * PAGE_SIZE) as u64, 0);
+}
 
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::GLOBAL;
+/// `vmap_free_pages` for `vmap_alloc_guarded` and `vmap_alloc_shadow`,
+/// which also returns the guard page's VA.
+pub fn vmap_free_guarded(base: *mut u8, pages: usize) {
+    vmap_release(base as u64, (pages * PAGE_SIZE) as u64, PAGE_SIZE as u64);
+}
+
+/// Every mapped range of the active page tables, one line each as
+/// `start-end size pa perms cache pagesize owner`, then the totals. Takes
+/// no lock and never allocates, so the debugger and the panic path can
+/// call it with anything held.
+pub fn dump_mappings(out: &mut dyn Write) -> fmt::Result {
+    let (mut n, mut bytes) = (0u64, 0u64);
+    let mut r = Ok(());
+    vmmap::regions(|reg| {
+        n += 1;
+        bytes += reg.len;
+        r = r.and_then(|_| writeln!(out, "{}", reg));
+    });
+    r?;
+    writeln!(out, "{} regions, {} mapped", n, vmmap::Size(bytes))
+}
+
+/// Free space in the vmap and MMIO windows.
+pub fn va_stats() -> (vaspace::VaStats, vaspace::VaStats) {
+    (VMAP_VA.stats(), MMIO_VA.stats())
+}
+
+// Unmap `[base, base + bytes)` (what is mapped of it), then give back the
+// VA from `guard` bytes below `base`. Frames go back to the allocator only
+// after the range is shot down everywhere, so no stale TLB entry can reach
+// a frame's next owner; batches keep the frame list on the stack.
+fn vmap_release(base: u64, bytes: u64, guard: u64) {
+    const BATCH: usize = 64;
+    assert!(VMAP_VA.contains(base), "vmap: free of {:#x}", base);
+    let end = base + bytes;
+    let mut va = base;
+    while va < end {
+        let mut freed = [(0u64, 0u64); BATCH]; // (pa, bytes)
+        let (n, stop) = pt_locked(|| {
+            let mut mapper = active_mapper();
+            let mut fa = FrameSource::new().expect("vmap: no frames");
+            let (mut n, mut p) = (0, va);
+            while p < end && n < BATCH {
+                match huge::unmap(&mut mapper, &mut fa, p, end) {
+                    Some((pa, len)) => {
+                        freed[n] = (pa, len);
+                        n += 1;
+                        p += len;
+                    }
+                    None => p += PAGE_SIZE as u64,
+                }
+            }
+            (n, p)
+        });
+        flush_range_all_cpus(va, stop - va);
+        for &(pa, len) in &freed[..n] {
+            frames::free_contig(pa, (len / PAGE_SIZE as u64) as usize);
+        }
+        va = stop;
+    }
+    VMAP_VA.free(base - guard, bytes + guard);
+}
+
+fn vmap_map(base: u64, bytes: u64) -> Option<*mut u8> {
+    let mut mapper = active_mapper();
+    let mut fa = FrameSource::new()?;
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::GLOBAL
+        | PageTableFlags::NO_EXECUTE;
 
     let mut off = 0u64;
     while off < bytes {
-        let pf = fa.allocate_frame()?;
+        if huge::fits(base + off, bytes - off) && vmap_map_2m(&mut mapper, base + off, flags) {
+            off += vmmap::SIZE_2M;
+            continue;
+        }
+        let pf = frame_or_oom(&mut fa, "vmap")?;
         map_4k(
             &mut mapper,
             base + off,
@@ -236,27 +580,63 @@ pub fn vmap_alloc_pages(pages: usize) -> Option<*mut u8> {
     Some(base as *mut u8)
 }
 
-struct TinyAllocGuard<'a> {
-    lock: MutexGuard<'a, Option<simple_alloc::TinyBump>>,
+// Back `[va, va + 2 MiB)` with one 2 MiB page of fresh frames. False if no
+// aligned run is free; the caller falls back to 4 KiB pages.
+fn vmap_map_2m(mapper: &mut OffsetPageTable<'static>, va: u64, flags: F) -> bool {
+    let pages = (vmmap::SIZE_2M / PAGE_SIZE as u64) as usize;
+    let Some(pa) = frames::alloc_contig_aligned(pages, vmmap::SIZE_2M, u64::MAX) else {
+        return false;
+    };
+    let ok = pt_locked(|| {
+        let mut fa = FrameSource::new()?;
+        Some(huge::map_2m(mapper, va, pa, flags, &mut fa))
+    });
+    if ok != Some(true) {
+        frames::free_contig(pa, pages);
+        return false;
+    }
+    true
+}
+
+// A frame, going through the OOM path when the allocator is empty. Callers
+// hold no page-table or heap lock, so shrinkers can free into it.
+fn frame_or_oom(fa: &mut FrameSource, what: &str) -> Option<PhysFrame<Size4KiB>> {
+    fa.allocate_frame().or_else(|| oom::reclaim_frame(what))
 }
-impl<'a> TinyAllocGuard<'a> {
+
+// `frames` as a page-table FrameAllocator; None before it is seeded.
+struct FrameSource;
+
+impl FrameSource {
     fn new() -> Option<Self> {
-        let lock = FRAME_ALLOC.lock();
-        if lock.is_some() {
-            Some(Self { lock })
-        } else {
-            None
-        }
+        frames::ready().then_some(Self)
     }
 }
-unsafe impl<'a> FrameAllocator<Size4KiB> for TinyAllocGuard<'a> {
+
+unsafe impl FrameAllocator<Size4KiB> for FrameSource {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        if let Some(a) = self.lock.as_mut() {
-            if let Some(pf) = a.allocate_frame() {
-                return Some(pf);
-            }
-        }
-        fallback_take_frame()
+        let pa = frames::alloc_frame()?;
+        Some(PhysFrame::containing_address(PhysAddr::new(pa)))
+    }
+}
+
+/// Hand a 4 KiB physical page that nothing references any more to the
+/// frame allocator.
+pub fn give_back_frame(pa: u64) {
+    frames::free_frame(pa);
+}
+
+/// Memory in use, for reports. Heap-free; takes no lock it could wait on.
+#[derive(Copy, Clone, Debug)]
+pub struct MemUsage {
+    pub heap: Option<(usize, usize)>, // (used, size) in bytes; None if busy
+    pub frames: Option<frames::FrameStats>, // None if busy
+}
+
+pub fn usage() -> MemUsage {
+    MemUsage {
+        heap: GLOBAL_ALLOC.inner.try_lock().and_then(|h| h.usage()),
+        frames: frames::try_stats(),
     }
 }
 
@@ -273,81 +653,98 @@ impl MutexHeap {
             inner: Mutex::new(PagingHeap::empty()),
         }
     }
+
+    // Run `f` on the heap; if it fails, reclaim memory for `size` bytes and
+    // try once more. The heap lock is dropped in between: shrinkers may
+    // free into it.
+    fn with_oom(&self, size: usize, f: impl Fn(&PagingHeap) -> *mut u8) -> *mut u8 {
+        let p = f(&self.inner.lock());
+        if !p.is_null() {
+            return p;
+        }
+        if oom::reclaim(size.div_ceil(PAGE_SIZE)) > 0 {
+            let p = f(&self.inner.lock());
+            if !p.is_null() {
+                return p;
+            }
+        }
+        oom::report("heap", size as u64);
+        p
+    }
 }
 
 unsafe impl GlobalAlloc for MutexHeap {
-    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        unsafe { self.inner.lock().alloc_zeroed(layout) }
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        irqalloc::check("alloc", layout.size());
+        self.with_oom(layout.size(), |h| unsafe { h.alloc(layout) })
     }
 
-    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        unsafe { self.inner.lock().realloc(ptr, layout, new_size) }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        irqalloc::check("alloc", layout.size());
+        self.with_oom(layout.size(), |h| unsafe { h.alloc_zeroed(layout) })
     }
 
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        unsafe { self.inner.lock().alloc(layout) }
+    // A failed realloc leaves the old block alone, so retrying is safe.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        irqalloc::check("realloc", new_size);
+        self.with_oom(new_size, |h| unsafe { h.realloc(ptr, layout, new_size) })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        irqalloc::check("free", layout.size());
         unsafe { self.inner.lock().dealloc(ptr, layout) }
     }
 }
 
 struct PagingHeap {
     inner: Mutex<LlHeap>,
-    mapped_end: AtomicU64, // [KHEAP_START .. mapped_end) is backed by frames
+    pages: Mutex<heapmap::PageUse>, // taken under `inner`
+    mapped_end: AtomicU64,          // [KHEAP_START .. mapped_end) is backed by frames
+}
+
+// Bytes the heap really hands out for `layout`.
+fn block_size(layout: Layout) -> usize {
+    HoleList::align_layout(layout).map_or(layout.size(), |l| l.size())
 }
 
 impl PagingHeap {
     pub const fn empty() -> Self {
         Self {
             inner: Mutex::new(LlHeap::empty()),
+            pages: Mutex::new(heapmap::PageUse::new()),
             mapped_end: AtomicU64::new(0),
         }
     }
     fn ensure_mapped_span(&self, start: u64, end: u64) {
         pt_locked(|| {
             let mut mapper = active_mapper();
-            let mut fa = TinyAllocGuard::new().expect("heap map: TinyBump not ready");
-
-            let mut va = start & !0xfff;
-            let end_al = (end + 0xfff) & !0xfff;
-            while va < end_al {
-                if mapper.translate_addr(VirtAddr::new(va)).is_none() {
-                    let pf = fa.allocate_frame().expect("heap map: out of frames");
-                    unsafe {
-                        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(va));
-                        match mapper.map_to_with_table_flags(
-                            page,
-                            pf,
-                            F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE,
-                            F::PRESENT | F::WRITABLE,
-                            &mut fa,
-                        ) {
-                            Ok(flush) => {
-                                flush.flush();
-                                fence(Ordering::SeqCst);
-                            }
-                            Err(
-                                x86_64::structures::paging::mapper::MapToError::PageAlreadyMapped(
-                                    _,
-                                ),
-                            ) => {
-                                // Another thread mapped it since our translate; just ensure flags.
-                                mapper
-                                    .update_flags(
-                                        page,
-                                        F::PRESENT | F::WRITABLE | F::GLOBAL | F::NO_EXECUTE,
-                                    )
-                                    .unwrap()
-                                    .flush();
-                            }
-                            Err(e) => panic!("heap map_to failed @va={:#x}: {:?}", va, e),
-                        }
-                    }
+            let mut fa = FrameSource::new().expect("heap map: no frame allocator");
+            let (mut va, end) = (start & !0xfff, (end + 0xfff) & !0xfff);
+            while va < end {
+                if heapmap::map_huge(&mut mapper, &mut fa, va, end - va) {
+                    va += vmmap::SIZE_2M;
+                    continue;
                 }
-                va += 4096;
+                let ok = heapmap::map_page(&mut mapper, &mut fa, va);
+                assert!(ok, "heap map: out of frames @va={:#x}", va);
+                va += PAGE_SIZE as u64;
+            }
+        })
+    }
+
+    // Step 1 of a shrink (see `heapmap`): unmap pages that hold nothing
+    // until LOW_WATER bytes are mapped but free.
+    fn retire_free(&self) -> Option<(u64, u64)> {
+        without_interrupts(|| {
+            let heap = self.inner.lock();
+            let pages = self.pages.lock();
+            let free = heapmap::mapped().saturating_sub(heap.used() as u64);
+            if free <= heapmap::HIGH_WATER {
+                return None;
             }
+            let want = ((free - heapmap::LOW_WATER) / PAGE_SIZE as u64) as usize;
+            let mut fa = FrameSource::new()?;
+            pt_locked(|| heapmap::retire(&mut active_mapper(), &mut fa, &pages, want))
         })
     }
 
@@ -355,6 +752,12 @@ impl PagingHeap {
         unsafe { self.inner.lock().init(start, size) };
         self.mapped_end.store(KHEAP_START, Ordering::SeqCst);
     }
+
+    /// (used, size) in bytes; None if the heap is locked.
+    fn usage(&self) -> Option<(usize, usize)> {
+        let h = self.inner.try_lock()?;
+        Some((h.used(), h.size()))
+    }
 }
 
 unsafe impl GlobalAlloc for PagingHeap {
@@ -363,6 +766,7 @@ unsafe impl GlobalAlloc for PagingHeap {
             let mut heap = self.inner.lock();
             if let Ok(nn) = heap.allocate_first_fit(layout) {
                 let p = nn.as_ptr();
+                self.pages.lock().add(p as u64, block_size(layout));
                 let size = layout.size().max(1);
                 // map exactly what the caller will touch: [p, p+size)
                 self.ensure_mapped_span(p as u64, (p as u64).saturating_add(size as u64));
@@ -380,6 +784,7 @@ unsafe impl GlobalAlloc for PagingHeap {
             match heap.allocate_first_fit(layout) {
                 Ok(nn) => {
                     let p = nn.as_ptr();
+                    self.pages.lock().add(p as u64, block_size(layout));
                     let size = layout.size().max(1);
                     self.ensure_mapped_span(p as u64, (p as u64).saturating_add(size as u64));
                     p
@@ -389,57 +794,65 @@ unsafe impl GlobalAlloc for PagingHeap {
         })
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        without_interrupts(|| unsafe {
-            self.inner
-                .lock()
-                .deallocate(core::ptr::NonNull::new_unchecked(ptr), layout)
-        })
+    // Zeroed after `alloc` has mapped the block, so the stores cannot fault.
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let p = unsafe { self.alloc(layout) };
+        if !p.is_null() {
+            unsafe { core::ptr::write_bytes(p, 0, layout.size()) };
+        }
+        p
     }
-}
-
-#[global_allocator]
-static GLOBAL_ALLOC: MutexHeap = MutexHeap::new();
-static LOW32_ALLOC: spin::Mutex<Option<simple_alloc::TinyBump>> = Mutex::new(None);
-
-const MAX_USABLE: usize = 256;
-static USABLE: Mutex<HVec<(u64, u64), MAX_USABLE>> = Mutex::new(HVec::new()); // [(start,end))
 
-pub fn seed_usable_from_mmap(boot: &BootInfo) {
-    let mm = unsafe { core::slice::from_raw_parts(boot.memory_map, boot.memory_map_len) };
-    let mut v = USABLE.lock();
-    *v = HVec::new();
-    for mr in mm {
-        if mr.typ != 1 {
-            continue;
-        } // only usable RAM
-        let s = (mr.phys_start + 0xfff) & !0xfff;
-        let e = (mr.phys_start + mr.len) & !0xfff;
-        if e <= s {
-            continue;
-        }
-        // skip reserved holes inside
-        // we’ll clip simple overlaps out by stepping 4KiB at allocation time
-        v.push((s, e)).ok();
-    }
-}
-
-// Take one 4KiB frame from the USABLE list, skipping reserved pages.
-fn fallback_take_frame() -> Option<PhysFrame<Size4KiB>> {
-    let mut v = USABLE.lock();
-    while let Some((mut s, e)) = v.pop() {
-        while s + 0x1000 <= e {
-            let cand = s;
-            s += 0x1000;
-            if !crate::mem::reserved::is_reserved_page(cand) {
-                // put back remainder
-                if s < e {
-                    let _ = v.push((s, e));
+    // The heap hands out blocks of `HoleList::align_layout(layout).size()`
+    // bytes. A new size that rounds to the same block stays where it is; a
+    // smaller one also stays and gives its tail back when the tail can hold
+    // a free-list node. Anything else moves: linked_list_allocator cannot
+    // grow a block into the hole after it.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let (Ok(old_blk), Ok(new_blk)) =
+            (HoleList::align_layout(layout), HoleList::align_layout(new))
+        else {
+            return core::ptr::null_mut();
+        };
+        let (old_blk, new_blk) = (old_blk.size(), new_blk.size());
+        let tail = old_blk.saturating_sub(new_blk);
+        if new_blk <= old_blk && (tail == 0 || tail >= HoleList::min_size()) {
+            without_interrupts(|| {
+                let mut heap = self.inner.lock();
+                if tail != 0 {
+                    let at = unsafe { core::ptr::NonNull::new_unchecked(ptr.add(new_blk)) };
+                    let tl = unsafe { Layout::from_size_align_unchecked(tail, 1) };
+                    unsafe { heap.deallocate(at, tl) };
+                    self.pages.lock().sub(at.as_ptr() as u64, tail);
                 }
-                return Some(PhysFrame::containing_address(PhysAddr::new(cand)));
+                self.ensure_mapped_span(ptr as u64, ptr as u64 + new_size.max(1) as u64);
+            });
+            crate::counter!("mem.realloc_in_place");
+            return ptr;
+        }
+
+        // The new block is mapped by `alloc`; the old one is freed only once
+        // the copy is done, so a failure leaves it intact.
+        let p = unsafe { self.alloc(new) };
+        if !p.is_null() {
+            unsafe {
+                core::ptr::copy_nonoverlapping(ptr, p, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
             }
+            crate::counter!("mem.realloc_moved");
         }
-        // exhausted this range; continue to next
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| {
+            let mut heap = self.inner.lock();
+            unsafe { heap.deallocate(core::ptr::NonNull::new_unchecked(ptr), layout) };
+            self.pages.lock().sub(ptr as u64, block_size(layout));
+        })
     }
-    None
 }
+
+#[global_allocator]
+static GLOBAL_ALLOC: MutexHeap = MutexHeap::new();
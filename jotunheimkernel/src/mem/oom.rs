@@ -0,0 +1,129 @@
+// src/mem/oom.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// The out-of-memory path. When the frame allocator or the heap comes up
+// empty, allocation paths that hold no locks call `reclaim`: registered
+// shrinkers (caches that can drop what they hold) are asked for pages and
+// the allocation is retried. If that frees nothing, `report` logs where the
+// memory went and the caller fails the allocation; the heap then ends in
+// the usual allocation-failure panic. Once user processes exist, killing
+// the largest non-critical one goes between those two steps.
+//
+// Shrinkers run on allocation paths: they must not allocate, and must take
+// their own locks with try_lock, skipping the cache if it is busy.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::PhysAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
+
+use super::frames;
+use super::vmmap::Size;
+use crate::early_println;
+use crate::sched::group;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAX_SHRINKERS: usize = 16;
+/// Pages asked for per reclaim pass when the caller needs just one.
+const BATCH: usize = 64;
+
+/// A cache that can give memory back. `scan(n)` frees up to `n` pages and
+/// returns how many it freed.
+#[derive(Copy, Clone)]
+pub struct Shrinker {
+    pub name: &'static str,
+    pub scan: fn(usize) -> usize,
+}
+
+static SHRINKERS: Mutex<Vec<Shrinker, MAX_SHRINKERS>> = Mutex::new(Vec::new());
+static IN_RECLAIM: AtomicBool = AtomicBool::new(false);
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn register(name: &'static str, scan: fn(usize) -> usize) {
+    let ok = without_interrupts(|| SHRINKERS.lock().push(Shrinker { name, scan }).is_ok());
+    if !ok {
+        early_println!("[oom] shrinker table full, dropping {}", name);
+    }
+}
+
+/// Ask the shrinkers for `pages` pages; returns how many came back. A
+/// reclaim that recurses (a shrinker that allocates) gets nothing.
+pub fn reclaim(pages: usize) -> usize {
+    if IN_RECLAIM.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    crate::counter!("mem.oom_reclaims");
+    let all = without_interrupts(|| SHRINKERS.lock().clone());
+    let mut freed = 0;
+    for s in all.iter() {
+        if freed >= pages {
+            break;
+        }
+        freed += (s.scan)(pages - freed);
+    }
+    crate::counter!("mem.oom_reclaimed_pages", freed);
+    IN_RECLAIM.store(false, Ordering::Release);
+    freed
+}
+
+/// A frame after reclaim, for frame-allocator callers that found every
+/// source empty. Reports and returns None if reclaim got nothing back.
+pub(super) fn reclaim_frame(what: &str) -> Option<PhysFrame<Size4KiB>> {
+    reclaim(BATCH);
+    let Some(pa) = frames::alloc_frame() else {
+        report(what, 4096);
+        return None;
+    };
+    Some(PhysFrame::containing_address(PhysAddr::new(pa)))
+}
+
+/// Log what memory is in use, for an allocation of `bytes` that is about
+/// to fail. Heap-free; safe to call with the heap exhausted.
+pub fn report(what: &str, bytes: u64) {
+    crate::counter!("mem.oom");
+    early_println!(
+        "[oom] {}: cannot allocate {} bytes, nothing left to reclaim",
+        what,
+        bytes
+    );
+    let u = super::usage();
+    match u.heap {
+        Some((used, size)) => early_println!(
+            "[oom]   heap: {} used of {}",
+            Size(used as u64),
+            Size(size as u64)
+        ),
+        None => early_println!("[oom]   heap: busy"),
+    }
+    match u.frames {
+        Some(f) => early_println!(
+            "[oom]   frames: {} free of {}",
+            Size(f.free * 4096),
+            Size(f.total * 4096)
+        ),
+        None => early_println!("[oom]   frames: busy"),
+    }
+    // Shrinker names only: calling them here could recurse into the
+    // allocator.
+    if let Some(s) = SHRINKERS.try_lock() {
+        for x in s.iter() {
+            early_println!("[oom]   shrinker {}", x.name);
+        }
+    }
+    let _ = group::for_each(|id, g| {
+        if g.mem_bytes != 0 {
+            early_println!(
+                "[oom]   group {} {}: {} in {} tasks",
+                id,
+                g.name,
+                Size(g.mem_bytes),
+                g.tasks
+            );
+        }
+    });
+}
@@ -0,0 +1,150 @@
+// src/mem/pagefault.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Page faults. The #PF handler passes CR2 and the error code here, first to
+// `fix`, which resolves the faults that are part of normal operation, then,
+// if that fails, to `report`, which says as much as can be said about a real
+// one before the handler escalates.
+//
+// The only fault resolved today is a not-present kernel read or write in
+// the heap window: the heap is mapped as it is touched (`mem::heap_fault`).
+// Everything else is a bug. The report decodes the error code, names the
+// window the address is in, shows what the page tables hold there (for a
+// protection fault, the mapping and its effective permissions), flags a
+// null dereference or a likely stack overflow, and for HHDM addresses says
+// whether the frame is in a reserved range.
+
+use core::fmt;
+
+use x86_64::registers::control::Cr3;
+
+use super::vmmap::{self, Size};
+use super::{heap_fault, reserved};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// A #PF error code.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PfError(pub u64);
+
+impl PfError {
+    pub const PRESENT: u64 = 1 << 0; // protection violation, not a missing page
+    pub const WRITE: u64 = 1 << 1;
+    pub const USER: u64 = 1 << 2;
+    pub const RESERVED: u64 = 1 << 3; // reserved bit set in a paging entry
+    pub const FETCH: u64 = 1 << 4;
+    pub const PKEY: u64 = 1 << 5;
+    pub const SHADOW_STACK: u64 = 1 << 6;
+
+    pub fn has(self, bit: u64) -> bool {
+        self.0 & bit != 0
+    }
+
+    fn access(self) -> &'static str {
+        if self.has(Self::FETCH) {
+            "fetch"
+        } else if self.has(Self::WRITE) {
+            "write"
+        } else {
+            "read"
+        }
+    }
+}
+
+impl fmt::Display for PfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            if self.has(Self::PRESENT) {
+                "protection"
+            } else {
+                "not-present"
+            },
+            if self.has(Self::USER) {
+                "user"
+            } else {
+                "kernel"
+            },
+            self.access()
+        )?;
+        for (bit, what) in [
+            (Self::RESERVED, "reserved bit"),
+            (Self::PKEY, "protection key"),
+            (Self::SHADOW_STACK, "shadow stack"),
+        ] {
+            if self.has(bit) {
+                write!(f, ", {}", what)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A fault this close below the stack pointer is taken to be an overflow
+// into the guard page.
+const STACK_SLACK: u64 = 2 * vmmap::SIZE_4K;
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn report_hhdm(va: u64) {
+    let pa = va - vmmap::hhdm();
+    match reserved::try_find(pa, 1) {
+        Some(Some(r)) => kprintln!(
+            "  pa {:#x} is reserved ({:?}, {:#x}..{:#x})",
+            pa,
+            r.kind,
+            r.start,
+            r.end
+        ),
+        Some(None) => kprintln!("  pa {:#x}: not reserved", pa),
+        None => kprintln!("  pa {:#x}: reserved table busy", pa),
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Resolve a fault that is not a bug. True if the access can be retried.
+pub fn fix(cr2: u64, err: PfError) -> bool {
+    let bad = PfError::PRESENT | PfError::USER | PfError::RESERVED | PfError::FETCH;
+    if err.0 & bad != 0 {
+        return false;
+    }
+    heap_fault(cr2)
+}
+
+/// Everything known about a fault `fix` could not resolve. `rsp` is the
+/// stack pointer at the fault.
+pub fn report(cr2: u64, err: PfError, rip: u64, rsp: u64) {
+    let owner = vmmap::owner(cr2);
+    kprintln!(
+        "[#PF] {} at {:#x} ({}), rip={:#x} rsp={:#x} err={:#x}",
+        err,
+        cr2,
+        owner,
+        rip,
+        rsp,
+        err.0
+    );
+    if cr2 < vmmap::SIZE_4K {
+        kprintln!("  null pointer dereference");
+    } else if cr2 < rsp && rsp - cr2 <= STACK_SLACK {
+        kprintln!("  {} bytes below rsp: stack overflow?", rsp - cr2);
+    }
+    let root = Cr3::read().0.start_address().as_u64();
+    match vmmap::lookup(root, cr2) {
+        Some(l) => kprintln!(
+            "  mapped: {:#x} -> {:#x}, {} page, {:?}, {:?}",
+            l.va,
+            l.pa,
+            Size(l.size),
+            l.flags,
+            l.cache
+        ),
+        None => kprintln!("  no mapping"),
+    }
+    if owner == "hhdm" {
+        report_hhdm(cr2);
+    }
+}
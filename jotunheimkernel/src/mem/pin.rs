@@ -0,0 +1,112 @@
+// src/mem/pin.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Pinned memory. A driver about to DMA into a buffer, or a realtime task
+// that cannot afford to wait for a page, pins the range: while pinned, the
+// reclaimer leaves every page in it alone (and, once there is swap, so will
+// swap-out). Pins are kept per range with a count, so the same range can be
+// pinned by several users; unpinning takes the exact range that was pinned.
+// Only mapped memory can be pinned.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::Translate;
+
+use super::{PAGE_SIZE, active_mapper, align_down, align_up};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PinError {
+    Empty,     // zero-length range
+    NotMapped, // some page in the range has no mapping
+    NotPinned, // unpin of a range that was never pinned
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Pin {
+    pub start: u64, // page-aligned
+    pub end: u64,
+    pub count: u32,
+}
+
+static PINS: Mutex<Vec<Pin>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn bounds(va: u64, len: usize) -> Result<(u64, u64), PinError> {
+    if len == 0 {
+        return Err(PinError::Empty);
+    }
+    let start = align_down(va, PAGE_SIZE as u64);
+    let end = align_up(va + len as u64, PAGE_SIZE as u64);
+    Ok((start, end))
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Keep `[va, va + len)` resident until the matching `unpin_range`.
+pub fn pin_range(va: u64, len: usize) -> Result<(), PinError> {
+    let (start, end) = bounds(va, len)?;
+    let mapper = active_mapper();
+    let mapped = (start..end)
+        .step_by(PAGE_SIZE)
+        .all(|p| mapper.translate_addr(VirtAddr::new(p)).is_some());
+    if !mapped {
+        return Err(PinError::NotMapped);
+    }
+    without_interrupts(|| {
+        let mut pins = PINS.lock();
+        match pins.iter_mut().find(|p| p.start == start && p.end == end) {
+            Some(p) => p.count += 1,
+            None => pins.push(Pin {
+                start,
+                end,
+                count: 1,
+            }),
+        }
+    });
+    crate::counter!("mem.pins");
+    Ok(())
+}
+
+/// Drop one pin of exactly the range passed to `pin_range`.
+pub fn unpin_range(va: u64, len: usize) -> Result<(), PinError> {
+    let (start, end) = bounds(va, len)?;
+    without_interrupts(|| {
+        let mut pins = PINS.lock();
+        let i = pins
+            .iter()
+            .position(|p| p.start == start && p.end == end)
+            .ok_or(PinError::NotPinned)?;
+        pins[i].count -= 1;
+        if pins[i].count == 0 {
+            pins.swap_remove(i);
+        }
+        Ok(())
+    })
+}
+
+/// Does any pin cover part of `[va, va + len)`? For the reclaimer: if the
+/// pin list is busy the answer is yes, so a page is never taken by mistake.
+pub fn is_pinned(va: u64, len: usize) -> bool {
+    without_interrupts(|| match PINS.try_lock() {
+        Some(pins) => pins.iter().any(|p| va < p.end && va + len as u64 > p.start),
+        None => true,
+    })
+}
+
+/// Every pinned range. False if the list was busy.
+pub fn for_each(mut f: impl FnMut(&Pin)) -> bool {
+    without_interrupts(|| match PINS.try_lock() {
+        Some(pins) => {
+            pins.iter().for_each(&mut f);
+            true
+        }
+        None => false,
+    })
+}
@@ -0,0 +1,265 @@
+// src/mem/pmem.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Persistent memory (NVDIMM). Regions come from the EFI memory map
+// (persistent-memory descriptors, MEMORY_PERSISTENT) and from the ACPI
+// NFIT's persistent-memory SPA ranges; a region both describe is one
+// device. They are reserved like any non-conventional range, so the frame
+// allocator never hands them out.
+//
+// Each region becomes a `PmemDevice`, mapped write-back in the HHDM and
+// used in place (DAX). Stores sit in the CPU cache until written back, so a
+// writer that needs them to survive a reset calls `commit` (CLWB/CLFLUSHOPT
+// over the range, then SFENCE) before it depends on them; `write_commit`
+// does both. The device is also a 512-byte block device,
+// `/devices/block/pmemN`, whose writes are durable when they return. The
+// `pmem` shell command lists the devices.
+//
+// With `pstore` on the command line, the last PSTORE_LEN bytes of pmem0 are
+// kept out of its block device for the crash log (`debug::pstore`).
+
+extern crate alloc;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec as AVec;
+use core::fmt::{self, Write};
+
+use heapless::Vec;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::PageTableFlags as F;
+
+use super::{PAGE_SIZE, align_down, align_up, hhdm_map};
+use crate::acpi::nfit;
+use crate::arch::x86_64::{alternatives, cache};
+use crate::blockdev::{self, BlockDevice, BlockError, BlockRef, check_io};
+use crate::bootinfo::{BootInfo, MEMORY_PERSISTENT, MemoryRegion};
+use crate::debug::shell;
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_DEVICES: usize = 8;
+pub const BLOCK_SIZE: usize = 512;
+/// Bytes of pmem0 set aside for the crash log with `pstore`.
+pub const PSTORE_LEN: u64 = 64 * 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Efi,  // EFI memory map
+    Nfit, // ACPI NFIT only
+    Both,
+}
+
+pub struct PmemDevice {
+    base: u64, // physical
+    len: u64,
+    va: u64, // HHDM address of `base`
+    source: Source,
+    numa: Option<u32>,
+}
+
+static DEVICES: Once<Vec<Arc<PmemDevice>, MAX_DEVICES>> = Once::new();
+static PSTORE: Once<Option<PmemDevice>> = Once::new();
+static REGISTERED: Mutex<AVec<BlockRef>> = Mutex::new(AVec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// Page-aligned regions from both sources, merged where they overlap.
+fn regions(boot: &BootInfo) -> Vec<(u64, u64, Source, Option<u32>), MAX_DEVICES> {
+    let mm: &[MemoryRegion] =
+        unsafe { core::slice::from_raw_parts(boot.memory_map, boot.memory_map_len) };
+    let efi = mm
+        .iter()
+        .filter(|r| r.typ == MEMORY_PERSISTENT)
+        .map(|r| (r.phys_start, r.len, Source::Efi, None));
+    let acpi = nfit::pmem_ranges(boot);
+    let acpi = acpi
+        .iter()
+        .map(|r| (r.base, r.len, Source::Nfit, r.proximity));
+
+    let mut v: Vec<(u64, u64, Source, Option<u32>), MAX_DEVICES> = Vec::new();
+    for (pa, len, src, numa) in efi.chain(acpi) {
+        let s = align_up(pa, PAGE_SIZE as u64);
+        let e = align_down(pa + len, PAGE_SIZE as u64);
+        if e <= s {
+            continue;
+        }
+        match v.iter_mut().find(|r| s < r.1 && e > r.0) {
+            Some(r) => {
+                // Firmware splits the same region differently; keep the union.
+                (r.0, r.1) = (r.0.min(s), r.1.max(e));
+                r.3 = r.3.or(numa);
+                if r.2 != src {
+                    r.2 = Source::Both;
+                }
+            }
+            None => {
+                if v.push((s, e, src, numa)).is_err() {
+                    kprintln!("[pmem] too many regions; ignoring {:#x}..{:#x}", s, e);
+                }
+            }
+        }
+    }
+    v
+}
+
+impl PmemDevice {
+    fn new(base: u64, len: u64, source: Source, numa: Option<u32>) -> Self {
+        Self {
+            base,
+            len,
+            va: hhdm_map(base, len as usize, F::empty()),
+            source,
+            numa,
+        }
+    }
+
+    fn check(&self, off: u64, len: usize) -> Result<(), BlockError> {
+        match off.checked_add(len as u64) {
+            Some(end) if end <= self.len => Ok(()),
+            _ => Err(BlockError::OutOfRange),
+        }
+    }
+}
+
+fn cmd_pmem(_: &str, out: &mut dyn Write) -> fmt::Result {
+    for (i, d) in devices().iter().enumerate() {
+        write!(
+            out,
+            "pmem{}: {:#x}..{:#x} ({} MiB) from {:?}",
+            i,
+            d.phys(),
+            d.phys() + d.len(),
+            d.len() >> 20,
+            d.source()
+        )?;
+        match d.numa() {
+            Some(n) => writeln!(out, ", node {}", n)?,
+            None => writeln!(out)?,
+        }
+    }
+    if let Some(p) = pstore_area() {
+        writeln!(out, "pstore: {:#x}..{:#x}", p.phys(), p.phys() + p.len())?;
+    }
+    Ok(())
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl PmemDevice {
+    pub fn phys(&self) -> u64 {
+        self.base
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn source(&self) -> Source {
+        self.source
+    }
+
+    /// NUMA proximity domain from the NFIT, if it gave one.
+    pub fn numa(&self) -> Option<u32> {
+        self.numa
+    }
+
+    pub fn read(&self, off: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.check(off, buf.len())?;
+        let src = (self.va + off) as *const u8;
+        unsafe { alternatives::copy_bytes(buf.as_mut_ptr(), src, buf.len()) };
+        Ok(())
+    }
+
+    /// Copy `data` in at `off`. Not durable until `commit`.
+    pub fn write(&self, off: u64, data: &[u8]) -> Result<(), BlockError> {
+        self.check(off, data.len())?;
+        let dst = (self.va + off) as *mut u8;
+        unsafe { alternatives::copy_bytes(dst, data.as_ptr(), data.len()) };
+        Ok(())
+    }
+
+    /// Write back every cache line of `[off, off + len)` to the media and
+    /// fence: stores there before the call survive a reset after it.
+    pub fn commit(&self, off: u64, len: usize) -> Result<(), BlockError> {
+        self.check(off, len)?;
+        cache::wb_range((self.va + off) as *const u8, len);
+        Ok(())
+    }
+
+    /// `write` then `commit` the same range.
+    pub fn write_commit(&self, off: u64, data: &[u8]) -> Result<(), BlockError> {
+        self.write(off, data)?;
+        self.commit(off, data.len())
+    }
+}
+
+impl BlockDevice for PmemDevice {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.len / BLOCK_SIZE as u64
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        self.read(lba * BLOCK_SIZE as u64, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_io(self, lba, buf.len())?;
+        self.write_commit(lba * BLOCK_SIZE as u64, buf)
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        cache::sfence();
+        Ok(())
+    }
+}
+
+/// Find the persistent-memory regions, map them, and register their block
+/// devices. Needs the heap and ACPI tables; later calls do nothing.
+pub fn init(boot: &BootInfo) {
+    DEVICES.call_once(|| {
+        let pstore = cmdline::flag("pstore");
+        let mut devs = Vec::new();
+        for (i, &(s, e, src, numa)) in regions(boot).iter().enumerate() {
+            let mut len = e - s;
+            if i == 0 && pstore && len > PSTORE_LEN {
+                len -= PSTORE_LEN;
+                PSTORE.call_once(|| Some(PmemDevice::new(s + len, PSTORE_LEN, src, numa)));
+            }
+            let dev = Arc::new(PmemDevice::new(s, len, src, numa));
+            kprintln!(
+                "[pmem] pmem{}: {:#x}..{:#x} ({} MiB, {:?})",
+                i,
+                s,
+                s + len,
+                len >> 20,
+                src
+            );
+            match blockdev::register(&format!("pmem{}", i), dev.clone()) {
+                Ok(r) => without_interrupts(|| REGISTERED.lock().push(r)),
+                Err(e) => kprintln!("[pmem] pmem{}: not registered: {:?}", i, e),
+            }
+            let _ = devs.push(dev);
+        }
+        devs
+    });
+    PSTORE.call_once(|| None);
+    shell::register("pmem", "persistent-memory devices", cmd_pmem);
+}
+
+/// Every persistent-memory device, pmem0 first.
+pub fn devices() -> &'static [Arc<PmemDevice>] {
+    DEVICES.get().map(|v| v.as_slice()).unwrap_or(&[])
+}
+
+/// The crash-log area, with `pstore` on the command line and a pmem device
+/// to hold it. Lock-free: usable from the panic handler.
+pub fn pstore_area() -> Option<&'static PmemDevice> {
+    PSTORE.get()?.as_ref()
+}
@@ -0,0 +1,88 @@
+// src/mem/poison.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Boot-time poisoning of free RAM. With `mempoison` on the command line the
+// frame allocator fills every frame it starts out with before handing out
+// the first one: `mempoison=zero` zeroes them, `mempoison=0xNN` fills with
+// that byte, and a bare `mempoison` uses 0xCC (INT3, so a jump into
+// poisoned memory traps). Code that reads memory it never wrote then sees
+// the pattern rather than what firmware left there, and leaked pages stand
+// out in a memory dump.
+//
+// The fill uses SSE2 non-temporal stores, which keep gigabytes of pattern
+// from sweeping the cache. The frame allocator runs before `simd::init`,
+// so this checks CR0/CR4 itself: UEFI hands over with SSE enabled, and
+// nothing uses XMM registers that early. Later (hot-added RAM) the same
+// rule as the checksum code applies: XMM state is saved across task
+// switches but not IRQ entry, so with IRQs off it falls back to
+// `write_bytes`.
+
+use core::arch::x86_64::{__m128i, _mm_set1_epi8, _mm_sfence, _mm_stream_si128};
+
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+use crate::arch::x86_64::simd::caps::simd_ready;
+use crate::cmdline;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DEFAULT_PATTERN: u8 = 0xCC;
+const STREAM_CHUNK: usize = 64; // bytes per unrolled loop step
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn sse_usable() -> bool {
+    (!simd_ready() || interrupts::are_enabled())
+        && Cr4::read().contains(Cr4Flags::OSFXSR)
+        && !Cr0::read().intersects(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED)
+}
+
+// `p` 16-byte aligned, `len` a multiple of STREAM_CHUNK.
+#[target_feature(enable = "sse2")]
+unsafe fn fill_sse2(p: *mut u8, len: usize, byte: u8) {
+    let v = _mm_set1_epi8(byte as i8);
+    let mut q = p as *mut __m128i;
+    for _ in 0..len / STREAM_CHUNK {
+        unsafe {
+            _mm_stream_si128(q, v);
+            _mm_stream_si128(q.add(1), v);
+            _mm_stream_si128(q.add(2), v);
+            _mm_stream_si128(q.add(3), v);
+            q = q.add(4);
+        }
+    }
+    // Streaming stores are weakly ordered; publish them before returning.
+    _mm_sfence();
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// The fill byte `mempoison` asks for, if any.
+pub fn pattern() -> Option<u8> {
+    if !cmdline::flag("mempoison") {
+        return None;
+    }
+    Some(match cmdline::value("mempoison") {
+        Some("zero") => 0,
+        Some(v) => v
+            .strip_prefix("0x")
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+            .unwrap_or(DEFAULT_PATTERN),
+        None => DEFAULT_PATTERN,
+    })
+}
+
+/// Fill `len` bytes at `va` with `byte`.
+pub fn fill(va: u64, len: usize, byte: u8) {
+    let p = va as *mut u8;
+    let fast = if va.is_multiple_of(16) && sse_usable() {
+        len - len % STREAM_CHUNK
+    } else {
+        0
+    };
+    if fast != 0 {
+        unsafe { fill_sse2(p, fast, byte) };
+    }
+    unsafe { core::ptr::write_bytes(p.add(fast), byte, len - fast) };
+}
@@ -0,0 +1,226 @@
+// src/mem/ptcheck.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Page-table consistency checker. Walks the active tables (see vmmap) and
+// flags mappings that break the kernel's rules: writable and executable at
+// once, a writable alias of kernel text or rodata, executable HHDM, firmware
+// ranges mapped writable, and large-page entries the CPU would reject (PS in
+// a PML4 entry, a 2M/1G frame that is not size-aligned). Runs on demand
+// from the monitor, and in debug builds or with `ptcheck` on the command
+// line after every large mapping change.
+
+use core::fmt;
+use core::ptr::addr_of;
+
+use heapless::Vec;
+use x86_64::structures::paging::PageTableFlags as F;
+
+use super::reserved::{self, ResvKind};
+use super::vmmap::{self, Leaf, SIZE_4K, Size};
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// Mapping changes at least this large trigger a check when enabled.
+pub const LARGE: u64 = 1 << 20;
+const LOG_MAX: usize = 16; // violations logged per run; the rest are counted
+const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+const PAT_HUGE: u64 = 1 << 12;
+
+unsafe extern "C" {
+    unsafe static __text_start: u8;
+    unsafe static __rodata_end: u8;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Rule {
+    WriteExec,
+    WritableText,
+    ExecHhdm,
+    WritableReserved,
+    HugeMisaligned,
+    HugeInPml4,
+}
+
+const RULES: usize = 6;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Violation {
+    pub rule: Rule,
+    pub va: u64,
+    pub pa: u64,
+    pub size: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Report {
+    pub leaves: u64,
+    pub counts: [u64; RULES],
+}
+
+impl Report {
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// Physical ranges behind the kernel's text and rodata, merged.
+fn text_phys() -> Vec<(u64, u64), 16> {
+    let ts = addr_of!(__text_start) as u64;
+    let te = addr_of!(__rodata_end) as u64;
+    let mut out: Vec<(u64, u64), 16> = Vec::new();
+    vmmap::leaves(|l| {
+        let (s, e) = (l.va.max(ts), (l.va + l.size).min(te));
+        if s >= e {
+            return;
+        }
+        let (ps, pe) = (l.pa + (s - l.va), l.pa + (e - l.va));
+        match out.last_mut() {
+            Some(last) if last.1 == ps => last.1 = pe,
+            _ => {
+                let _ = out.push((ps, pe));
+            }
+        }
+    });
+    out
+}
+
+fn overlaps(ranges: &[(u64, u64)], pa: u64, size: u64) -> bool {
+    ranges.iter().any(|&(s, e)| pa < e && pa + size > s)
+}
+
+fn check_leaf(l: &Leaf, text: &[(u64, u64)], mut hit: impl FnMut(Rule)) {
+    let w = l.flags.contains(F::WRITABLE);
+    let x = !l.flags.contains(F::NO_EXECUTE);
+    if w && x {
+        hit(Rule::WriteExec);
+    }
+    if w && overlaps(text, l.pa, l.size) {
+        hit(Rule::WritableText);
+    }
+    if x && vmmap::owner(l.va) == "hhdm" {
+        hit(Rule::ExecHhdm);
+    }
+    // Device windows are writable by nature; type 0 is the kernel's own
+    // low-memory carve-out, not a firmware claim.
+    if w && vmmap::owner(l.va) != "mmio"
+        && let Some(r) = reserved::find(l.pa, l.size)
+        && matches!(r.kind, ResvKind::Firmware(t) if t != 0)
+    {
+        hit(Rule::WritableReserved);
+    }
+    // Below the frame address only the PAT bit may be set in a large page.
+    if l.size != SIZE_4K && l.raw & ADDR_MASK & (l.size - 1) & !PAT_HUGE != 0 {
+        hit(Rule::HugeMisaligned);
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Walk everything and report each violation to `f`.
+pub fn check(mut f: impl FnMut(&Violation)) -> Report {
+    use x86_64::registers::control::Cr3;
+    let mut rep = Report::default();
+    let mut hit = |rep: &mut Report, v: Violation| {
+        rep.counts[v.rule as usize] += 1;
+        f(&v);
+    };
+
+    let l4 = vmmap::table(Cr3::read().0.start_address().as_u64());
+    for (i, e) in l4.iter().enumerate() {
+        let fl = e.flags();
+        if fl.contains(F::PRESENT) && fl.contains(F::HUGE_PAGE) {
+            let va = (((i as u64) << 39 << 16) as i64 >> 16) as u64;
+            let v = Violation {
+                rule: Rule::HugeInPml4,
+                va,
+                pa: e.addr().as_u64(),
+                size: 1 << 39,
+            };
+            hit(&mut rep, v);
+        }
+    }
+
+    let text = text_phys();
+    vmmap::leaves(|l| {
+        rep.leaves += 1;
+        check_leaf(&l, &text, |rule| {
+            let v = Violation {
+                rule,
+                va: l.va,
+                pa: l.pa,
+                size: l.size,
+            };
+            hit(&mut rep, v);
+        });
+    });
+    rep
+}
+
+/// Check now and log the findings. Returns the number of violations.
+pub fn run(why: &str) -> u64 {
+    let mut logged = 0;
+    let rep = check(|v| {
+        if logged < LOG_MAX {
+            kprintln!("[ptcheck] {}", v);
+            logged += 1;
+        }
+    });
+    let total = rep.total();
+    crate::counter!("mem.ptcheck_runs");
+    crate::counter!("mem.ptcheck_violations", total);
+    if total == 0 {
+        kprintln!("[ptcheck] {}: {} leaves, clean", why, rep.leaves);
+    } else {
+        kprintln!(
+            "[ptcheck] {}: {} leaves, {} violations ({} not shown)",
+            why,
+            rep.leaves,
+            total,
+            total.saturating_sub(logged as u64)
+        );
+    }
+    total
+}
+
+/// Debug builds, or `ptcheck` on the command line.
+pub fn enabled() -> bool {
+    cfg!(debug_assertions) || cmdline::flag("ptcheck")
+}
+
+/// Mapping code calls this after changing `bytes` of mappings.
+pub fn after_change(what: &str, bytes: u64) {
+    if bytes >= LARGE && enabled() {
+        run(what);
+    }
+}
+
+/* -------------------------------- Formatting -------------------------------- */
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rule::WriteExec => "W+X",
+            Rule::WritableText => "writable kernel text/rodata",
+            Rule::ExecHhdm => "executable HHDM",
+            Rule::WritableReserved => "writable firmware range",
+            Rule::HugeMisaligned => "misaligned large page",
+            Rule::HugeInPml4 => "PS set in PML4",
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: va {:#x} pa {:#x} ({})",
+            self.rule,
+            self.va,
+            self.pa,
+            Size(self.size)
+        )
+    }
+}
@@ -77,10 +77,53 @@ pub fn is_reserved_range(phys: u64, len: u64) -> bool {
     false
 }
 
+/// The first reserved range overlapping [phys, phys+len), if any.
+pub fn find(phys: u64, len: u64) -> Option<Resv> {
+    let s = align_down(phys, 0x1000);
+    let e = align_up(phys + len, 0x1000);
+    RESV.lock()
+        .iter()
+        .find(|r| s < r.end && e > r.start)
+        .copied()
+}
+
+/// `find` for fault handlers: None if the table is locked.
+pub fn try_find(phys: u64, len: u64) -> Option<Option<Resv>> {
+    let s = align_down(phys, 0x1000);
+    let e = align_up(phys + len, 0x1000);
+    let v = RESV.try_lock()?;
+    Some(v.iter().find(|r| s < r.end && e > r.start).copied())
+}
+
 pub fn is_reserved_page(phys: u64) -> bool {
     is_reserved_range(phys, 0x1000)
 }
 
+/// Call `f(start, end)` for each piece of `[start, end)` that no reserved
+/// range overlaps, in address order.
+pub fn for_each_gap(start: u64, end: u64, mut f: impl FnMut(u64, u64)) {
+    let v = RESV.lock();
+    let mut p = start;
+    while p < end {
+        let next = v
+            .iter()
+            .filter(|r| r.start < end && r.end > p)
+            .min_by_key(|r| r.start);
+        match next {
+            Some(r) => {
+                if r.start > p {
+                    f(p, r.start);
+                }
+                p = r.end;
+            }
+            None => {
+                f(p, end);
+                break;
+            }
+        }
+    }
+}
+
 pub fn init(boot: &BootInfo) {
     reset();
 
@@ -107,10 +150,12 @@ pub fn init(boot: &BootInfo) {
         }
     }
 
-    // 1.b) framebuffer
-    if boot.framebuffer.addr != 0 && boot.framebuffer.pitch != 0 {
-        let fb_len = (boot.framebuffer.pitch as u64) * (boot.framebuffer.height as u64);
-        let _ = reserve_range(boot.framebuffer.addr, fb_len, ResvKind::Framebuffer);
+    // 1.b) framebuffers
+    for fb in boot.framebuffers() {
+        if fb.addr != 0 && fb.pitch != 0 {
+            let fb_len = (fb.pitch as u64) * (fb.height as u64);
+            let _ = reserve_range(fb.addr, fb_len, ResvKind::Framebuffer);
+        }
     }
 
     let _ = reserve_range(0, boot.low32_pool_paddr, ResvKind::Firmware(0));
@@ -0,0 +1,286 @@
+// src/mem/slab.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Object caches for fixed-size kernel objects. A `Cache<T>` carves slabs
+// (a few heap pages at a time) into T-sized slots, so objects that come and
+// go often stop punching holes of their size all over the heap, and most
+// allocations never touch the heap lock.
+//
+// Free slots live in two places:
+//   - a magazine per CPU, a short stack of slots taken and returned with
+//     interrupts off and only that CPU's (uncontended) lock held;
+//   - the depot, one free list shared by all CPUs, threaded through the
+//     free slots themselves.
+// An empty magazine is refilled with half a magazine from the depot, and a
+// full one hands half back, so a CPU goes to the depot at most once per
+// MAG / 2 operations. The depot grows by a slab when it runs dry.
+//
+// Slabs are never given back to the heap: a cache stays at its peak size.
+// Lock order: magazine, then depot.
+
+extern crate alloc;
+use alloc::alloc::{Layout, alloc};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use heapless::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::PAGE_SIZE;
+use crate::arch::x86_64::apic::{MAX_CPUS, cpu_index};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MAG: usize = 16; // slots per magazine
+const MIN_SLAB: usize = 16 * 1024;
+const MIN_OBJECTS: usize = 8; // per slab
+const MAX_CACHES: usize = 16; // listed by `for_each`
+
+struct Depot {
+    head: usize, // first free slot, 0 if none; each holds the next
+    free: usize,
+    slabs: usize,
+}
+
+/// A cache of `T` slots.
+pub struct Cache<T> {
+    name: &'static str,
+    depot: Mutex<Depot>,
+    mags: [Mutex<Vec<usize, MAG>>; MAX_CPUS],
+    listed: AtomicBool,
+    _t: PhantomData<T>,
+}
+
+// Slots are handed from CPU to CPU, and with them the T in them.
+unsafe impl<T: Send> Sync for Cache<T> {}
+unsafe impl<T: Send> Send for Cache<T> {}
+
+/// One cache's state, for listing.
+#[derive(Copy, Clone, Debug)]
+pub struct CacheInfo {
+    pub name: &'static str,
+    pub object_size: usize, // slot size, padded for alignment
+    pub slabs: usize,
+    pub objects: usize, // slots in all slabs
+    pub free: usize,    // in the depot and in magazines not busy
+}
+
+trait Listed: Sync {
+    fn info(&self) -> Option<CacheInfo>;
+}
+
+/// A `T` in a slot of its cache, freed back to it on drop.
+pub struct SlabBox<T: 'static> {
+    ptr: NonNull<T>,
+    cache: &'static Cache<T>,
+}
+
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+static CACHES: Mutex<Vec<&'static dyn Listed, MAX_CACHES>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl Depot {
+    fn push(&mut self, p: usize) {
+        unsafe { (p as *mut usize).write(self.head) };
+        self.head = p;
+        self.free += 1;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.head == 0 {
+            return None;
+        }
+        let p = self.head;
+        self.head = unsafe { (p as *const usize).read() };
+        self.free -= 1;
+        Some(p)
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+impl<T> Cache<T> {
+    // Slots hold a free-list link when free, so at least a word each.
+    const ALIGN: usize = max(align_of::<T>(), 8);
+    const STRIDE: usize = max(size_of::<T>(), 8).next_multiple_of(Self::ALIGN);
+    const SLAB: usize = max(Self::STRIDE * MIN_OBJECTS, MIN_SLAB).next_multiple_of(PAGE_SIZE);
+    const PER_SLAB: usize = Self::SLAB / Self::STRIDE;
+
+    // Add a slab's slots to the depot. False if the heap is out.
+    fn grow(&self, d: &mut Depot) -> bool {
+        let align = Self::ALIGN.max(PAGE_SIZE);
+        let Ok(layout) = Layout::from_size_align(Self::SLAB, align) else {
+            return false;
+        };
+        let base = unsafe { alloc(layout) } as usize;
+        if base == 0 {
+            return false;
+        }
+        crate::counter!("slab.grows");
+        for i in (0..Self::PER_SLAB).rev() {
+            d.push(base + i * Self::STRIDE);
+        }
+        d.slabs += 1;
+        true
+    }
+
+    fn take(&self, d: &mut Depot) -> Option<usize> {
+        if d.head == 0 && !self.grow(d) {
+            return None;
+        }
+        d.pop()
+    }
+
+    fn get(&self) -> Option<usize> {
+        without_interrupts(|| {
+            let Some(mag) = self.mags.get(cpu_index()) else {
+                return self.take(&mut self.depot.lock());
+            };
+            let mut m = mag.lock();
+            if let Some(p) = m.pop() {
+                return Some(p);
+            }
+            crate::counter!("slab.refills");
+            let mut d = self.depot.lock();
+            let p = self.take(&mut d)?;
+            while m.len() < MAG / 2 {
+                match d.pop() {
+                    Some(q) => {
+                        let _ = m.push(q);
+                    }
+                    None => break,
+                }
+            }
+            Some(p)
+        })
+    }
+
+    fn put(&self, p: usize) {
+        without_interrupts(|| {
+            let Some(mag) = self.mags.get(cpu_index()) else {
+                self.depot.lock().push(p);
+                return;
+            };
+            let mut m = mag.lock();
+            if m.push(p).is_ok() {
+                return;
+            }
+            crate::counter!("slab.flushes");
+            let mut d = self.depot.lock();
+            while m.len() > MAG / 2 {
+                d.push(m.pop().unwrap());
+            }
+            let _ = m.push(p);
+        })
+    }
+}
+
+impl<T: Send + 'static> Listed for Cache<T> {
+    fn info(&self) -> Option<CacheInfo> {
+        let (slabs, depot) = {
+            let d = self.depot.try_lock()?;
+            (d.slabs, d.free)
+        };
+        let mags: usize = self
+            .mags
+            .iter()
+            .filter_map(|m| m.try_lock().map(|m| m.len()))
+            .sum();
+        Some(CacheInfo {
+            name: self.name,
+            object_size: Self::STRIDE,
+            slabs,
+            objects: slabs * Self::PER_SLAB,
+            free: depot + mags,
+        })
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl<T: Send + 'static> Cache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            depot: Mutex::new(Depot {
+                head: 0,
+                free: 0,
+                slabs: 0,
+            }),
+            mags: [const { Mutex::new(Vec::new()) }; MAX_CPUS],
+            listed: AtomicBool::new(false),
+            _t: PhantomData,
+        }
+    }
+
+    /// Move `v` into a slot. None if the cache is empty and the heap out.
+    pub fn alloc(&'static self, v: T) -> Option<SlabBox<T>> {
+        let p = self.get()? as *mut T;
+        unsafe { p.write(v) };
+        if !self.listed.swap(true, Ordering::AcqRel) {
+            let _ = without_interrupts(|| CACHES.lock().push(self));
+        }
+        Some(SlabBox {
+            ptr: unsafe { NonNull::new_unchecked(p) },
+            cache: self,
+        })
+    }
+}
+
+impl<T> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> AsRef<T> for SlabBox<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsMut<T> for SlabBox<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SlabBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe { core::ptr::drop_in_place(self.ptr.as_ptr()) };
+        self.cache.put(self.ptr.as_ptr() as usize);
+    }
+}
+
+/// Every cache that has handed out an object. False if the list was busy;
+/// a cache whose counts were busy is skipped.
+pub fn for_each(mut f: impl FnMut(CacheInfo)) -> bool {
+    let Some(caches) = CACHES.try_lock() else {
+        return false;
+    };
+    caches.iter().filter_map(|c| c.info()).for_each(&mut f);
+    true
+}
@@ -0,0 +1,206 @@
+// src/mem/vaspace.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Kernel virtual address ranges. Each window (vmap, MMIO) hands out
+// page-aligned ranges first-fit from a sorted list of free ranges, and
+// takes them back with neighbours merged, so VA freed by `vmap_free_pages`
+// or `unmap_mmio` is reused. Only addresses are managed here: callers map
+// and unmap, and return a range only once no CPU can still reach it.
+//
+// The list is a fixed array so the vmap path never calls the heap. If it
+// fills up (very fragmented windows), a freed range is dropped and counted
+// in `mem.va_leaked_pages` rather than failing the free.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::PAGE_SIZE;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MAX_HOLES: usize = 256;
+
+#[derive(Copy, Clone, Debug)]
+struct Hole {
+    start: u64,
+    end: u64,
+}
+
+struct Holes {
+    list: [Hole; MAX_HOLES], // sorted by start, never adjacent
+    len: usize,
+}
+
+pub struct VaSpace {
+    name: &'static str,
+    base: u64,
+    end: u64,
+    holes: Mutex<Holes>,
+}
+
+/// Free space in a window, for reports.
+#[derive(Copy, Clone, Debug)]
+pub struct VaStats {
+    pub free: u64,    // bytes
+    pub largest: u64, // bytes, in one range
+    pub holes: usize,
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl Holes {
+    fn insert(&mut self, at: usize, h: Hole) -> bool {
+        if self.len == MAX_HOLES {
+            return false;
+        }
+        self.list.copy_within(at..self.len, at + 1);
+        self.list[at] = h;
+        self.len += 1;
+        true
+    }
+
+    fn remove(&mut self, at: usize) {
+        self.list.copy_within(at + 1..self.len, at);
+        self.len -= 1;
+    }
+
+    fn as_slice(&self) -> &[Hole] {
+        &self.list[..self.len]
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl VaSpace {
+    /// The window `[base, base + len)`, all free.
+    pub const fn new(name: &'static str, base: u64, len: u64) -> Self {
+        let mut list = [Hole { start: 0, end: 0 }; MAX_HOLES];
+        list[0] = Hole {
+            start: base,
+            end: base + len,
+        };
+        Self {
+            name,
+            base,
+            end: base + len,
+            holes: Mutex::new(Holes { list, len: 1 }),
+        }
+    }
+
+    pub fn contains(&self, va: u64) -> bool {
+        (self.base..self.end).contains(&va)
+    }
+
+    /// A free range of `bytes` (rounded up to pages), or None if no hole is
+    /// big enough.
+    pub fn alloc(&self, bytes: u64) -> Option<u64> {
+        let bytes = bytes.checked_next_multiple_of(PAGE_SIZE as u64)?;
+        if bytes == 0 {
+            return None;
+        }
+        without_interrupts(|| {
+            let mut h = self.holes.lock();
+            let i = h.as_slice().iter().position(|r| r.end - r.start >= bytes)?;
+            let start = h.list[i].start;
+            h.list[i].start += bytes;
+            if h.list[i].start == h.list[i].end {
+                h.remove(i);
+            }
+            Some(start)
+        })
+    }
+
+    /// `alloc` with the range starting at a multiple of `align` (a power of
+    /// two, at least a page), e.g. for 2 MiB pages. None also if carving it
+    /// out would need a hole the list has no room for.
+    pub fn alloc_aligned(&self, bytes: u64, align: u64) -> Option<u64> {
+        let bytes = bytes.checked_next_multiple_of(PAGE_SIZE as u64)?;
+        if bytes == 0 {
+            return None;
+        }
+        without_interrupts(|| {
+            let mut h = self.holes.lock();
+            let fits = |r: &Hole| {
+                let a = r.start.next_multiple_of(align);
+                a.checked_add(bytes).is_some_and(|e| e <= r.end)
+            };
+            let i = h.as_slice().iter().position(fits)?;
+            let Hole { start, end } = h.list[i];
+            let a = start.next_multiple_of(align);
+            let tail = Hole {
+                start: a + bytes,
+                end,
+            };
+            match (a > start, tail.start < end) {
+                (false, true) => h.list[i].start = tail.start,
+                (false, false) => h.remove(i),
+                (true, false) => h.list[i].end = a,
+                (true, true) => {
+                    if !h.insert(i + 1, tail) {
+                        return None;
+                    }
+                    h.list[i].end = a;
+                }
+            }
+            Some(a)
+        })
+    }
+
+    /// Give back `[va, va + bytes)`, as `alloc` returned it (or any part of
+    /// such a range). Freeing a range twice panics.
+    pub fn free(&self, va: u64, bytes: u64) {
+        let bytes = bytes.next_multiple_of(PAGE_SIZE as u64);
+        if bytes == 0 {
+            return;
+        }
+        let end = va + bytes;
+        assert!(
+            va.is_multiple_of(PAGE_SIZE as u64) && self.base <= va && end <= self.end,
+            "{}: free of {:#x}..{:#x} outside the window",
+            self.name,
+            va,
+            end
+        );
+        let leaked = without_interrupts(|| {
+            let mut h = self.holes.lock();
+            let i = h.as_slice().partition_point(|r| r.start < va);
+            let prev = i.checked_sub(1).map(|p| h.list[p]);
+            let next = h.as_slice().get(i).copied();
+            assert!(
+                prev.is_none_or(|p| p.end <= va) && next.is_none_or(|n| end <= n.start),
+                "{}: double free of {:#x}..{:#x}",
+                self.name,
+                va,
+                end
+            );
+            match (
+                prev.is_some_and(|p| p.end == va),
+                next.is_some_and(|n| n.start == end),
+            ) {
+                (true, true) => {
+                    h.list[i - 1].end = h.list[i].end;
+                    h.remove(i);
+                }
+                (true, false) => h.list[i - 1].end = end,
+                (false, true) => h.list[i].start = va,
+                (false, false) => return !h.insert(i, Hole { start: va, end }),
+            }
+            false
+        });
+        if leaked {
+            crate::counter!("mem.va_leaked_pages", bytes / PAGE_SIZE as u64);
+        }
+    }
+
+    pub fn stats(&self) -> VaStats {
+        without_interrupts(|| {
+            let h = self.holes.lock();
+            let sizes = h.as_slice().iter().map(|r| r.end - r.start);
+            VaStats {
+                free: sizes.clone().sum(),
+                largest: sizes.max().unwrap_or(0),
+                holes: h.len,
+            }
+        })
+    }
+}
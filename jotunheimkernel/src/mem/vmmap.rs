@@ -0,0 +1,349 @@
+// src/mem/vmmap.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Walks the live page tables (through the HHDM) and reports what is mapped
+// as runs of contiguous virtual memory: same physical contiguity, same
+// effective permissions, same page size, same owner. Permissions are the
+// effective ones: writable only if every level allows it, executable only
+// if no level sets NX. The cache type is resolved through IA32_PAT. The
+// walk reads the tables directly from any root, without OffsetPageTable,
+// and takes no lock and never allocates: it is safe from the panic path,
+// and a mapping changed mid-walk may show either way.
+
+use core::fmt;
+use core::ptr::addr_of;
+
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{PageTable, PageTableFlags as F};
+
+use super::{KHEAP_SIZE, KHEAP_START, MMIO_BASE, PHYS_TO_VIRT_OFFSET, VMAP_BASE};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const IA32_PAT: u32 = 0x277;
+const PAT_4K: F = F::HUGE_PAGE; // bit 7 is PAT in a 4 KiB PTE
+const PAT_HUGE: u64 = 1 << 12; // and bit 12 in a 2 MiB / 1 GiB entry
+
+pub const SIZE_4K: u64 = 1 << 12;
+pub const SIZE_2M: u64 = 1 << 21;
+pub const SIZE_1G: u64 = 1 << 30;
+
+// Window sizes are not recorded anywhere else; generous upper bounds.
+const WINDOW: u64 = 1 << 44;
+
+unsafe extern "C" {
+    unsafe static __kernel_start: u8;
+    unsafe static __kernel_end: u8;
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cache {
+    Uc,
+    Wc,
+    Wt,
+    Wp,
+    Wb,
+    UcMinus,
+    Reserved,
+}
+
+/// One mapped page as the hardware sees it.
+#[derive(Copy, Clone, Debug)]
+pub struct Leaf {
+    pub va: u64,
+    pub pa: u64,
+    pub size: u64, // SIZE_4K, SIZE_2M or SIZE_1G
+    pub raw: u64,  // the entry as stored (address and flag bits)
+    /// Leaf flags, with WRITABLE/USER_ACCESSIBLE/NO_EXECUTE made effective.
+    pub flags: F,
+    pub cache: Cache,
+}
+
+/// A run of leaves that map contiguous VA to contiguous PA the same way.
+#[derive(Copy, Clone, Debug)]
+pub struct Region {
+    pub start: u64,
+    pub len: u64,
+    pub pa: u64,
+    pub page_size: u64,
+    pub flags: F,
+    pub cache: Cache,
+    pub owner: &'static str,
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+pub(super) fn hhdm() -> u64 {
+    unsafe { PHYS_TO_VIRT_OFFSET }
+}
+
+pub(super) fn table(pa: u64) -> &'static PageTable {
+    unsafe { &*((pa + hhdm()) as *const PageTable) }
+}
+
+fn active_root() -> u64 {
+    Cr3::read().0.start_address().as_u64()
+}
+
+fn canonical(va: u64) -> u64 {
+    (((va << 16) as i64) >> 16) as u64
+}
+
+fn pat() -> u64 {
+    unsafe { Msr::new(IA32_PAT).read() }
+}
+
+const ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+fn cache_of(pat: u64, flags: F, raw: u64, huge: bool) -> Cache {
+    let pat_bit = if huge {
+        raw & PAT_HUGE != 0
+    } else {
+        flags.contains(PAT_4K)
+    };
+    let idx = (pat_bit as u64) << 2
+        | (flags.contains(F::NO_CACHE) as u64) << 1
+        | flags.contains(F::WRITE_THROUGH) as u64;
+    match (pat >> (idx * 8)) & 0x7 {
+        0 => Cache::Uc,
+        1 => Cache::Wc,
+        4 => Cache::Wt,
+        5 => Cache::Wp,
+        6 => Cache::Wb,
+        7 => Cache::UcMinus,
+        _ => Cache::Reserved,
+    }
+}
+
+// Fold the permission bits of a non-leaf level into what is inherited.
+fn inherit(parent: F, entry: F) -> F {
+    let mut f = parent;
+    f.set(
+        F::WRITABLE,
+        parent.contains(F::WRITABLE) && entry.contains(F::WRITABLE),
+    );
+    f.set(
+        F::USER_ACCESSIBLE,
+        parent.contains(F::USER_ACCESSIBLE) && entry.contains(F::USER_ACCESSIBLE),
+    );
+    f.set(
+        F::NO_EXECUTE,
+        parent.contains(F::NO_EXECUTE) || entry.contains(F::NO_EXECUTE),
+    );
+    f
+}
+
+fn leaf(va: u64, size: u64, inherited: F, e: &PageTableEntry, pat: u64) -> Leaf {
+    let flags = e.flags();
+    let raw = e.addr().as_u64() | flags.bits();
+    let eff = inherit(inherited, flags);
+    let mut f = flags;
+    f.set(F::WRITABLE, eff.contains(F::WRITABLE));
+    f.set(F::USER_ACCESSIBLE, eff.contains(F::USER_ACCESSIBLE));
+    f.set(F::NO_EXECUTE, eff.contains(F::NO_EXECUTE));
+    Leaf {
+        va,
+        pa: raw & ADDR_MASK & !(size - 1),
+        size,
+        raw,
+        flags: f,
+        cache: cache_of(pat, flags, raw, size != SIZE_4K),
+    }
+}
+
+/// What a kernel VA is for, judging by the window it falls in.
+pub fn owner(va: u64) -> &'static str {
+    let (ks, ke) = (
+        addr_of!(__kernel_start) as u64,
+        addr_of!(__kernel_end) as u64,
+    );
+    let hhdm = hhdm();
+    if (ks..ke).contains(&va) {
+        "kernel"
+    } else if (KHEAP_START..KHEAP_START + KHEAP_SIZE as u64).contains(&va) {
+        "heap"
+    } else if (MMIO_BASE..MMIO_BASE + WINDOW).contains(&va) {
+        "mmio"
+    } else if (VMAP_BASE..VMAP_BASE + WINDOW).contains(&va) {
+        "vmap"
+    } else if hhdm != 0 && (hhdm..hhdm + WINDOW).contains(&va) {
+        "hhdm"
+    } else if va < 1 << 47 {
+        "low"
+    } else {
+        "-"
+    }
+}
+
+/* --------------------------------- Walking ---------------------------------- */
+
+/// Every present leaf of the active page tables, in VA order.
+pub fn leaves(f: impl FnMut(Leaf)) {
+    walk(active_root(), f)
+}
+
+/// Every present leaf of the page tables rooted at physical `root`, in VA
+/// order.
+pub fn walk(root: u64, mut f: impl FnMut(Leaf)) {
+    let pat = pat();
+    let top = F::WRITABLE | F::USER_ACCESSIBLE;
+    let l4 = table(root);
+    for (i4, e4) in l4.iter().enumerate() {
+        if !e4.flags().contains(F::PRESENT) {
+            continue;
+        }
+        let va4 = canonical((i4 as u64) << 39);
+        let f4 = inherit(top, e4.flags());
+        for (i3, e3) in table(e4.addr().as_u64()).iter().enumerate() {
+            let fl3 = e3.flags();
+            if !fl3.contains(F::PRESENT) {
+                continue;
+            }
+            let va3 = va4 + ((i3 as u64) << 30);
+            if fl3.contains(F::HUGE_PAGE) {
+                f(leaf(va3, SIZE_1G, f4, e3, pat));
+                continue;
+            }
+            let f3 = inherit(f4, fl3);
+            for (i2, e2) in table(e3.addr().as_u64()).iter().enumerate() {
+                let fl2 = e2.flags();
+                if !fl2.contains(F::PRESENT) {
+                    continue;
+                }
+                let va2 = va3 + ((i2 as u64) << 21);
+                if fl2.contains(F::HUGE_PAGE) {
+                    f(leaf(va2, SIZE_2M, f3, e2, pat));
+                    continue;
+                }
+                let f2 = inherit(f3, fl2);
+                for (i1, e1) in table(e2.addr().as_u64()).iter().enumerate() {
+                    if e1.flags().contains(F::PRESENT) {
+                        f(leaf(va2 + ((i1 as u64) << 12), SIZE_4K, f2, e1, pat));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The leaf mapping `va` in the page tables rooted at physical `root`
+/// (any address space, not just the active one), or None if unmapped or
+/// not canonical.
+pub fn lookup(root: u64, va: u64) -> Option<Leaf> {
+    if canonical(va) != va {
+        return None;
+    }
+    let mut t = table(root);
+    let mut inherited = F::WRITABLE | F::USER_ACCESSIBLE;
+    for level in (0..4).rev() {
+        let shift = 12 + 9 * level;
+        let e = &t[((va >> shift) & 0x1FF) as usize];
+        let fl = e.flags();
+        if !fl.contains(F::PRESENT) {
+            return None;
+        }
+        let size = 1u64 << shift;
+        if level == 0 || (level < 3 && fl.contains(F::HUGE_PAGE)) {
+            return Some(leaf(va & !(size - 1), size, inherited, e, pat()));
+        }
+        inherited = inherit(inherited, fl);
+        t = table(e.addr().as_u64());
+    }
+    None
+}
+
+/// The active mappings merged into regions, in VA order.
+pub fn regions(f: impl FnMut(&Region)) {
+    regions_in(active_root(), f)
+}
+
+/// The mappings of the page tables rooted at physical `root` merged into
+/// regions, in VA order.
+pub fn regions_in(root: u64, mut f: impl FnMut(&Region)) {
+    // Accessed/dirty differ page by page and say nothing about the mapping.
+    let key = |fl: F| fl - (F::ACCESSED | F::DIRTY);
+    let mut cur: Option<Region> = None;
+    walk(root, |l| {
+        let owner = owner(l.va);
+        if let Some(r) = cur.as_mut() {
+            if r.start + r.len == l.va
+                && r.pa + r.len == l.pa
+                && r.page_size == l.size
+                && key(r.flags) == key(l.flags)
+                && r.cache == l.cache
+                && r.owner == owner
+            {
+                r.len += l.size;
+                return;
+            }
+            f(r);
+        }
+        cur = Some(Region {
+            start: l.va,
+            len: l.size,
+            pa: l.pa,
+            page_size: l.size,
+            flags: l.flags,
+            cache: l.cache,
+            owner,
+        });
+    });
+    if let Some(r) = cur.as_ref() {
+        f(r);
+    }
+}
+
+/* -------------------------------- Formatting -------------------------------- */
+
+pub struct Size(pub u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.0;
+        if n.is_multiple_of(SIZE_1G) {
+            write!(f, "{}G", n / SIZE_1G)
+        } else if n.is_multiple_of(1 << 20) {
+            write!(f, "{}M", n >> 20)
+        } else {
+            write!(f, "{}K", n >> 10)
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    /// `start-end size pa perms cache pagesize owner`, e.g.
+    /// `ffff800000000000-ffff800040000000 1G 0x0 rw-G WB 2M hhdm`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fl = self.flags;
+        write!(
+            f,
+            "{:016x}-{:016x} {} {:#x} r{}{}{} {} {} {}",
+            self.start,
+            self.start + self.len,
+            Size(self.len),
+            self.pa,
+            if fl.contains(F::WRITABLE) { 'w' } else { '-' },
+            if fl.contains(F::NO_EXECUTE) { '-' } else { 'x' },
+            if fl.contains(F::GLOBAL) { 'G' } else { '-' },
+            self.cache,
+            Size(self.page_size),
+            self.owner
+        )
+    }
+}
+
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cache::Uc => "UC",
+            Cache::Wc => "WC",
+            Cache::Wt => "WT",
+            Cache::Wp => "WP",
+            Cache::Wb => "WB",
+            Cache::UcMinus => "UC-",
+            Cache::Reserved => "??",
+        })
+    }
+}
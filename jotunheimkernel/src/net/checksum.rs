@@ -0,0 +1,100 @@
+// src/net/checksum.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// RFC 1071 Internet checksum. Partial sums are kept over little-endian words
+// (one's-complement addition is byte-order agnostic) and swapped once at the end.
+use core::arch::x86_64::{
+    __m128i, _mm_add_epi32, _mm_loadu_si128, _mm_setzero_si128, _mm_storeu_si128,
+    _mm_unpackhi_epi16, _mm_unpacklo_epi16,
+};
+use x86_64::instructions::interrupts;
+
+use crate::arch::x86_64::simd::caps::simd_ready;
+
+const SIMD_MIN: usize = 256; // below this the scalar loop wins
+const SIMD_FLUSH: usize = 4096; // 16-byte blocks per lane flush (no u32 overflow)
+
+/* --------------------------------- Scalar ----------------------------------- */
+
+fn sum_scalar(data: &[u8], mut acc: u64) -> u64 {
+    let (words, rest) = data.as_chunks::<8>();
+    for w in words {
+        let v = u64::from_le_bytes(*w);
+        acc += (v & 0xFFFF_FFFF) + (v >> 32);
+    }
+    let (pairs, tail) = rest.as_chunks::<2>();
+    for p in pairs {
+        acc += u16::from_le_bytes(*p) as u64;
+    }
+    if let [b] = tail {
+        acc += *b as u64; // odd byte is the high half of a network word
+    }
+    acc
+}
+
+/* ---------------------------------- SSE2 ------------------------------------ */
+
+#[target_feature(enable = "sse2")]
+unsafe fn sum_sse2(data: &[u8], acc: u64) -> u64 {
+    let (blocks, rest) = data.as_chunks::<16>();
+    let mut total = acc;
+    for chunk in blocks.chunks(SIMD_FLUSH) {
+        let zero = _mm_setzero_si128();
+        let mut v = _mm_setzero_si128();
+        for b in chunk {
+            let x = unsafe { _mm_loadu_si128(b.as_ptr() as *const __m128i) };
+            v = _mm_add_epi32(v, _mm_unpacklo_epi16(x, zero));
+            v = _mm_add_epi32(v, _mm_unpackhi_epi16(x, zero));
+        }
+        let mut lanes = [0u32; 4];
+        unsafe { _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, v) };
+        total += lanes.iter().map(|&l| l as u64).sum::<u64>();
+    }
+    sum_scalar(rest, total)
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Add `data` to a running partial sum. Data must be fed at even offsets
+/// except for the final piece.
+pub fn sum(data: &[u8], acc: u64) -> u64 {
+    // XMM state is only saved across task switches, not across IRQ entry,
+    // so interrupt handlers (IF=0) stay on the scalar path.
+    if data.len() >= SIMD_MIN && simd_ready() && interrupts::are_enabled() {
+        unsafe { sum_sse2(data, acc) }
+    } else {
+        sum_scalar(data, acc)
+    }
+}
+
+/// Fold a partial sum to 16 bits and return the checksum in host order;
+/// store it with `to_be_bytes()`.
+pub fn finish(mut acc: u64) -> u16 {
+    while acc >> 16 != 0 {
+        acc = (acc & 0xFFFF) + (acc >> 16);
+    }
+    !(acc as u16).swap_bytes()
+}
+
+pub fn checksum(data: &[u8]) -> u16 {
+    finish(sum(data, 0))
+}
+
+/// TCP/UDP IPv4 pseudo-header, as a partial sum to continue with `sum()`.
+pub fn pseudo_v4(src: [u8; 4], dst: [u8; 4], proto: u8, len: u16) -> u64 {
+    let mut ph = [0u8; 12];
+    ph[0..4].copy_from_slice(&src);
+    ph[4..8].copy_from_slice(&dst);
+    ph[9] = proto;
+    ph[10..12].copy_from_slice(&len.to_be_bytes());
+    sum_scalar(&ph, 0)
+}
+
+/// Incremental update (RFC 1624) when one 16-bit field changes from `old` to `new`.
+pub fn update(csum: u16, old: u16, new: u16) -> u16 {
+    let mut acc = (!csum) as u32 + (!old) as u32 + new as u32;
+    while acc >> 16 != 0 {
+        acc = (acc & 0xFFFF) + (acc >> 16);
+    }
+    !(acc as u16)
+}
@@ -0,0 +1,243 @@
+// src/net/mbuf.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Fixed-size packet buffers carved from low (<4 GiB) physical pages so NICs
+// can DMA into them directly; the pages stay pinned while the pool lives.
+// Buffers are refcounted: `share()` hands out
+// another view of the same bytes without copying, and the slot returns to
+// the pool when the last view drops. No heap traffic after the pool exists.
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::kprintln;
+use crate::mem::{frames, pin, try_alloc_one_phys_page_hhdm};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const MBUF_SIZE: usize = 2048; // one Ethernet frame + headroom, 2 per page
+pub const MBUF_HEADROOM: usize = 128; // room to prepend link/IP/L4 headers
+const PAGE: usize = 4096;
+const PER_PAGE: usize = PAGE / MBUF_SIZE;
+const DEFAULT_POOL: usize = 256;
+
+struct Slot {
+    va: u64,
+    pa: u64,
+    refs: AtomicU32,
+}
+
+pub struct MbufPool {
+    slots: Vec<Slot>,
+    free: Mutex<Vec<u16>>, // taken with IRQs off: RX paths free from ISRs
+}
+
+/// A view `[head, head+len)` into one pool slot.
+pub struct Mbuf {
+    pool: Arc<MbufPool>,
+    idx: u16,
+    head: u16,
+    len: u16,
+}
+
+/* ----------------------------------- Pool ----------------------------------- */
+
+impl MbufPool {
+    /// Carve up to `count` buffers; stops early if low memory runs out.
+    pub fn new(count: usize) -> Option<Arc<Self>> {
+        let count = count.min(u16::MAX as usize);
+        let mut slots = Vec::with_capacity(count);
+        while slots.len() < count {
+            let Ok((va, pa)) = try_alloc_one_phys_page_hhdm() else {
+                break;
+            };
+            if pin::pin_range(va, PAGE).is_err() {
+                frames::free_contig(pa, 1);
+                break;
+            }
+            // Never past `count`: indices are u16. An odd count wastes the
+            // rest of its last page.
+            for i in 0..PER_PAGE.min(count - slots.len()) {
+                let off = (i * MBUF_SIZE) as u64;
+                slots.push(Slot {
+                    va: va + off,
+                    pa: pa + off,
+                    refs: AtomicU32::new(0),
+                });
+            }
+        }
+        if slots.is_empty() {
+            return None;
+        }
+        if slots.len() < count {
+            kprintln!("[mbuf] low memory: {} of {} buffers", slots.len(), count);
+        }
+        let free = (0..slots.len() as u16).rev().collect();
+        Some(Arc::new(Self {
+            slots,
+            free: Mutex::new(free),
+        }))
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn available(&self) -> usize {
+        without_interrupts(|| self.free.lock().len())
+    }
+
+    /// Take an empty buffer with the default headroom reserved.
+    pub fn alloc(self: &Arc<Self>) -> Option<Mbuf> {
+        let Some(idx) = without_interrupts(|| self.free.lock().pop()) else {
+            crate::counter!("net.mbuf_exhausted");
+            return None;
+        };
+        crate::counter!("net.mbuf_alloc");
+        self.slots[idx as usize].refs.store(1, Ordering::Relaxed);
+        Some(Mbuf {
+            pool: self.clone(),
+            idx,
+            head: MBUF_HEADROOM as u16,
+            len: 0,
+        })
+    }
+}
+
+impl Drop for MbufPool {
+    fn drop(&mut self) {
+        // Every live Mbuf holds the pool, so nothing can still use a page.
+        for s in self.slots.iter().step_by(PER_PAGE) {
+            let _ = pin::unpin_range(s.va, PAGE);
+            frames::free_contig(s.pa, 1);
+        }
+    }
+}
+
+static POOL: Once<Option<Arc<MbufPool>>> = Once::new();
+
+/// Shared default pool, created on first use.
+pub fn pool() -> Option<&'static Arc<MbufPool>> {
+    POOL.call_once(|| MbufPool::new(DEFAULT_POOL)).as_ref()
+}
+
+/* ---------------------------------- Buffers --------------------------------- */
+
+impl Mbuf {
+    fn slot(&self) -> &Slot {
+        &self.pool.slots[self.idx as usize]
+    }
+
+    fn raw(&self) -> *mut u8 {
+        self.slot().va as *mut u8
+    }
+
+    fn unique(&self) -> bool {
+        self.slot().refs.load(Ordering::Acquire) == 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn headroom(&self) -> usize {
+        self.head as usize
+    }
+
+    pub fn tailroom(&self) -> usize {
+        MBUF_SIZE - self.head as usize - self.len as usize
+    }
+
+    /// Physical address of the first data byte, for descriptors.
+    pub fn phys(&self) -> u64 {
+        self.slot().pa + self.head as u64
+    }
+
+    pub fn data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.raw().add(self.head as usize), self.len()) }
+    }
+
+    /// Writable view; `None` while other views share the buffer.
+    pub fn data_mut(&mut self) -> Option<&mut [u8]> {
+        if !self.unique() {
+            return None;
+        }
+        let (h, n) = (self.head as usize, self.len());
+        Some(unsafe { core::slice::from_raw_parts_mut(self.raw().add(h), n) })
+    }
+
+    /// Grow at the front by `n` bytes (prepend a header) and return them.
+    pub fn push(&mut self, n: usize) -> Option<&mut [u8]> {
+        if n > self.headroom() || !self.unique() {
+            return None;
+        }
+        self.head -= n as u16;
+        self.len += n as u16;
+        let h = self.head as usize;
+        Some(unsafe { core::slice::from_raw_parts_mut(self.raw().add(h), n) })
+    }
+
+    /// Drop `n` bytes from the front (strip a parsed header).
+    pub fn pull(&mut self, n: usize) -> bool {
+        if n > self.len() {
+            return false;
+        }
+        self.head += n as u16;
+        self.len -= n as u16;
+        true
+    }
+
+    /// Grow at the back by `n` bytes (append payload) and return them.
+    pub fn put(&mut self, n: usize) -> Option<&mut [u8]> {
+        if n > self.tailroom() || !self.unique() {
+            return None;
+        }
+        let end = self.head as usize + self.len();
+        self.len += n as u16;
+        Some(unsafe { core::slice::from_raw_parts_mut(self.raw().add(end), n) })
+    }
+
+    /// Shrink to `len` bytes (drop padding/trailers).
+    pub fn trim(&mut self, len: usize) {
+        if len < self.len() {
+            self.len = len as u16;
+        }
+    }
+
+    /// Set the data window after a device wrote `len` bytes at `phys()`.
+    pub fn set_len(&mut self, len: usize) -> bool {
+        if len > MBUF_SIZE - self.head as usize {
+            return false;
+        }
+        self.len = len as u16;
+        true
+    }
+
+    /// Another read-only view of the same bytes; no copy.
+    pub fn share(&self) -> Mbuf {
+        self.slot().refs.fetch_add(1, Ordering::Relaxed);
+        Mbuf {
+            pool: self.pool.clone(),
+            idx: self.idx,
+            head: self.head,
+            len: self.len,
+        }
+    }
+}
+
+impl Drop for Mbuf {
+    fn drop(&mut self) {
+        if self.slot().refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let idx = self.idx;
+            without_interrupts(|| self.pool.free.lock().push(idx));
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// src/net/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+
+pub mod checksum;
+pub mod mbuf;
+pub mod selftest;
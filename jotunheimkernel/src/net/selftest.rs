@@ -0,0 +1,197 @@
+// src/net/selftest.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Packet-buffer and checksum test, run at the end of boot with `nettest` on
+// the command line. No NIC is needed: a UDP/IPv4 datagram is built in an
+// mbuf the way a transmit path would, then taken apart the way a receive
+// path would:
+//   - buffers come from below 4 GiB and go back to the pool when the last
+//     view of them drops;
+//   - headers are prepended into the headroom, and a shared view keeps the
+//     bytes read-only until it drops;
+//   - the IPv4 header and UDP (pseudo-header included) checksums verify,
+//     and an incremental TTL update matches a full recompute;
+//   - a padded frame "written by the device" is sized with set_len, its
+//     padding trimmed and its headers pulled off, leaving the payload;
+//   - a private pool pins its pages, and dropping it unpins them.
+// Failures are listed and then panic.
+
+use super::checksum;
+use super::mbuf::{self, MBUF_HEADROOM, MBUF_SIZE, Mbuf, MbufPool};
+use crate::mem::pin;
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const SRC: [u8; 4] = [10, 0, 2, 15];
+const DST: [u8; 4] = [10, 0, 2, 2];
+const SRC_PORT: u16 = 68;
+const DST_PORT: u16 = 67;
+const PROTO_UDP: u8 = 17;
+const TTL: u8 = 64;
+const IP_HDR: usize = 20;
+const UDP_HDR: usize = 8;
+const PAYLOAD: usize = 1000; // even, so the SIMD path sums it
+const PAD: usize = 4; // trailer the "device" leaves after the datagram
+const LOW_LIMIT: u64 = 1 << 32;
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn check(ok: bool, what: &str, bad: &mut u32) {
+    if !ok {
+        kprintln!("[nettest] {}", what);
+        *bad += 1;
+    }
+}
+
+fn payload_byte(i: usize) -> u8 {
+    (i * 13 + 1) as u8
+}
+
+// UDP header and payload, the UDP checksum filled in.
+fn build_udp(m: &mut Mbuf) -> Option<()> {
+    for (i, b) in m.put(PAYLOAD)?.iter_mut().enumerate() {
+        *b = payload_byte(i);
+    }
+    let len = (UDP_HDR + PAYLOAD) as u16;
+    let h = m.push(UDP_HDR)?;
+    h[0..2].copy_from_slice(&SRC_PORT.to_be_bytes());
+    h[2..4].copy_from_slice(&DST_PORT.to_be_bytes());
+    h[4..6].copy_from_slice(&len.to_be_bytes());
+    h[6..8].fill(0);
+    let pseudo = checksum::pseudo_v4(SRC, DST, PROTO_UDP, len);
+    let csum = checksum::finish(checksum::sum(m.data(), pseudo));
+    m.data_mut()?[6..8].copy_from_slice(&csum.to_be_bytes());
+    Some(())
+}
+
+// IPv4 header in front of the UDP datagram.
+fn build_ip(m: &mut Mbuf) -> Option<()> {
+    let total = (IP_HDR + m.len()) as u16;
+    let h = m.push(IP_HDR)?;
+    h.fill(0);
+    h[0] = 0x45; // version 4, 5 words
+    h[2..4].copy_from_slice(&total.to_be_bytes());
+    h[8] = TTL;
+    h[9] = PROTO_UDP;
+    h[12..16].copy_from_slice(&SRC);
+    h[16..20].copy_from_slice(&DST);
+    let csum = checksum::checksum(h);
+    h[10..12].copy_from_slice(&csum.to_be_bytes());
+    Some(())
+}
+
+// Forward the packet: TTL down by one, header checksum patched (RFC 1624).
+// Returns whether the patched checksum equals a full recompute.
+fn forward(m: &mut Mbuf) -> Option<bool> {
+    let h = &mut m.data_mut()?[..IP_HDR];
+    let old = u16::from_be_bytes([h[8], h[9]]);
+    let csum = u16::from_be_bytes([h[10], h[11]]);
+    h[8] -= 1;
+    let patched = checksum::update(csum, old, u16::from_be_bytes([h[8], h[9]]));
+    h[10..12].fill(0);
+    let full = checksum::checksum(h);
+    h[10..12].copy_from_slice(&patched.to_be_bytes());
+    Some(patched == full)
+}
+
+fn pinned_pages() -> u64 {
+    let mut pages = 0;
+    while !pin::for_each(|p| pages += (p.end - p.start) / 4096) {
+        pages = 0;
+        core::hint::spin_loop();
+    }
+    pages
+}
+
+// A pool of three buffers takes two pages; both are pinned until it drops.
+fn pool_checks(bad: &mut u32) {
+    let pins = pinned_pages();
+    let Some(pool) = MbufPool::new(3) else {
+        kprintln!("[nettest] no low memory for a private pool; skipped");
+        return;
+    };
+    check(pool.capacity() == 3, "private pool size", bad);
+    check(pinned_pages() == pins + 2, "pool pages not pinned", bad);
+    drop(pool);
+    check(pinned_pages() == pins, "pool pages still pinned", bad);
+}
+
+fn udp_ok(datagram: &[u8]) -> bool {
+    let pseudo = checksum::pseudo_v4(SRC, DST, PROTO_UDP, datagram.len() as u16);
+    checksum::finish(checksum::sum(datagram, pseudo)) == 0
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    cmdline::flag("nettest")
+}
+
+/// Run the checks on the calling thread; panic if any failed.
+pub fn run() {
+    let Some(pool) = mbuf::pool() else {
+        kprintln!("[nettest] no low memory for mbufs; skipped");
+        return;
+    };
+    let free = pool.available();
+    let mut bad = 0;
+    {
+        let (Some(mut tx), Some(mut rx)) = (pool.alloc(), pool.alloc()) else {
+            panic!("net self-test: mbuf pool empty");
+        };
+        let fresh = tx.is_empty() && tx.headroom() == MBUF_HEADROOM;
+        check(fresh, "fresh mbuf not empty", &mut bad);
+        let low = tx.phys() + MBUF_SIZE as u64 <= LOW_LIMIT;
+        check(low, "mbuf above 4 GiB", &mut bad);
+
+        let built = build_udp(&mut tx).and_then(|_| build_ip(&mut tx));
+        check(built.is_some(), "no room for the headers", &mut bad);
+        let total = tx.len();
+        let sized = total == IP_HDR + UDP_HDR + PAYLOAD;
+        check(sized, "wrong packet size", &mut bad);
+        let ip_ok = checksum::checksum(&tx.data()[..IP_HDR]) == 0;
+        check(ip_ok, "IPv4 header checksum", &mut bad);
+        check(udp_ok(&tx.data()[IP_HDR..]), "UDP checksum", &mut bad);
+        let patched = forward(&mut tx) == Some(true);
+        check(patched, "incremental update differs", &mut bad);
+
+        let view = tx.share();
+        let locked = tx.data_mut().is_none() && tx.push(1).is_none();
+        check(locked, "shared mbuf writable", &mut bad);
+        check(view.data() == tx.data(), "shared view differs", &mut bad);
+        drop(view);
+        check(tx.data_mut().is_some(), "still shared", &mut bad);
+
+        // As if a NIC had DMAed the padded frame to rx.phys().
+        let received = rx.set_len(total + PAD)
+            && rx.data_mut().is_some_and(|d| {
+                d[..total].copy_from_slice(tx.data());
+                d[total..].fill(0);
+                true
+            });
+        check(received, "set_len refused", &mut bad);
+        rx.trim(total);
+        let headers = rx.pull(IP_HDR) && udp_ok(rx.data()) && rx.pull(UDP_HDR);
+        check(headers, "receive path", &mut bad);
+        let body = rx.len() == PAYLOAD
+            && rx
+                .data()
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| b == payload_byte(i));
+        check(body, "payload differs", &mut bad);
+        check(!rx.pull(PAYLOAD + 1), "pulled past the end", &mut bad);
+    }
+    check(pool.available() == free, "buffers not returned", &mut bad);
+    pool_checks(&mut bad);
+
+    if bad != 0 {
+        panic!("net self-test: {} check(s) failed", bad);
+    }
+    kprintln!(
+        "[nettest] ok: {} of {} mbufs free",
+        pool.available(),
+        pool.capacity()
+    );
+}
@@ -0,0 +1,210 @@
+// src/pci/hotplug.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Device hot-add and surprise removal. Drivers `bind()` the function they
+// drive, with a remove callback, which publishes it as a kernel object at
+// `/devices/pci/<bus>:<dev>.<func>`; BARs mapped with `PciDevice::map_bar` and
+// lines handed to `own_irq` are recorded against the function. A task on
+// the executor re-reads the ID of every bound function; one that reads
+// back as all-ones (or as another device) is gone, and is torn down:
+//   1. the driver's remove callback runs: it stops using the device and
+//      drops whatever it handed out that points into it;
+//   2. its BAR mappings are unmapped (`mem::unmap_mmio`);
+//   3. its interrupt lines are masked and unregistered.
+// Every few passes the bus is rescanned; functions that were not there
+// before are offered to the drivers registered with `register_driver`.
+// There is no MSI/MSI-X support yet, so IOAPIC lines are all there is to
+// tear down. Polling stands in for the slot's presence-detect interrupt.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use alloc::format;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{PciAddr, PciDevice, scan};
+use crate::kobject::{self, KObject, KRef};
+use crate::sched::executor;
+use crate::{irq, kprintln, mem};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const POLL_TICKS: u64 = 500;
+const RESCAN_EVERY: u32 = 10; // polls between bus rescans
+
+/// A driver that can take functions that appear after boot. `probe`
+/// returns true if it bound the device.
+#[derive(Copy, Clone)]
+pub struct Driver {
+    pub name: &'static str,
+    pub probe: fn(&PciDevice) -> bool,
+}
+
+struct Binding {
+    dev: PciDevice,
+    driver: &'static str,
+    remove: fn(&PciDevice),
+    irqs: Vec<u32>,
+    obj: Option<KRef<PciDevice>>, // None if the name was taken
+}
+
+struct State {
+    bound: Vec<Binding>,
+    mappings: Vec<(PciAddr, u64, u64)>, // (function, va, len)
+    known: Vec<PciAddr>,                // functions seen by the last scan
+    drivers: Vec<Driver>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    bound: Vec::new(),
+    mappings: Vec::new(),
+    known: Vec::new(),
+    drivers: Vec::new(),
+});
+static WATCHER: Once<()> = Once::new();
+
+fn with<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    without_interrupts(|| f(&mut STATE.lock()))
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn present(dev: &PciDevice) -> bool {
+    let id = dev.addr.read32(super::REG_ID);
+    id == (dev.vendor as u32 | (dev.device as u32) << 16)
+}
+
+fn check_removed() {
+    let bound: Vec<PciDevice> = with(|st| st.bound.iter().map(|b| b.dev).collect());
+    for dev in bound.iter().filter(|d| !present(d)) {
+        remove(dev.addr);
+    }
+}
+
+fn rescan() {
+    let now = scan();
+    let fresh: Vec<PciDevice> = with(|st| {
+        let fresh = now
+            .iter()
+            .filter(|d| !st.known.contains(&d.addr))
+            .copied()
+            .collect();
+        st.known = now.iter().map(|d| d.addr).collect();
+        fresh
+    });
+    let drivers = with(|st| st.drivers.clone());
+    for d in fresh.iter() {
+        crate::counter!("pci.added");
+        kprintln!(
+            "[pci] {:02x}:{:02x}.{} {:04x}:{:04x} added",
+            d.addr.bus,
+            d.addr.dev,
+            d.addr.func,
+            d.vendor,
+            d.device
+        );
+        if let Some(drv) = drivers.iter().find(|drv| (drv.probe)(d)) {
+            kprintln!("[pci] bound by {}", drv.name);
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Record that `driver` drives `dev`; `remove` runs if it disappears.
+pub fn bind(dev: &PciDevice, driver: &'static str, remove: fn(&PciDevice)) {
+    let a = dev.addr;
+    // Outside the lock: dropping it may release the old object.
+    let old = with(|st| {
+        let i = st.bound.iter().position(|b| b.dev.addr == a)?;
+        Some(st.bound.remove(i))
+    });
+    drop(old);
+    let name = format!("{:02x}:{:02x}.{}", a.bus, a.dev, a.func);
+    let obj = kobject::dir(&kobject::root("devices"), "pci")
+        .and_then(|pci| KObject::create(&name, &pci, *dev))
+        .ok();
+    with(|st| {
+        st.bound.push(Binding {
+            dev: *dev,
+            driver,
+            remove,
+            irqs: Vec::new(),
+            obj,
+        })
+    });
+}
+
+/// Tear `gsi` down with the device at `addr`.
+pub fn own_irq(addr: PciAddr, gsi: u32) {
+    with(|st| {
+        if let Some(b) = st.bound.iter_mut().find(|b| b.dev.addr == addr) {
+            b.irqs.push(gsi);
+        }
+    });
+}
+
+/// Called by `map_bar`: unmap `[va, va + len)` when `addr` goes away.
+pub(super) fn note_mapping(addr: PciAddr, va: u64, len: u64) {
+    with(|st| st.mappings.push((addr, va, len)));
+}
+
+/// Offer functions that appear later to `probe`.
+pub fn register_driver(name: &'static str, probe: fn(&PciDevice) -> bool) {
+    with(|st| st.drivers.push(Driver { name, probe }));
+}
+
+/// Unbind and tear down the function at `addr`, as if it had been pulled.
+/// False if nothing was bound there.
+pub fn remove(addr: PciAddr) -> bool {
+    let Some(b) = with(|st| {
+        let i = st.bound.iter().position(|b| b.dev.addr == addr)?;
+        st.known.retain(|&a| a != addr);
+        Some(st.bound.remove(i))
+    }) else {
+        return false;
+    };
+    crate::counter!("pci.removed");
+    kprintln!(
+        "[pci] {:02x}:{:02x}.{} {:04x}:{:04x} removed, unbinding {}",
+        addr.bus,
+        addr.dev,
+        addr.func,
+        b.dev.vendor,
+        b.dev.device,
+        b.driver
+    );
+    (b.remove)(&b.dev);
+    drop(b.obj);
+    let maps: Vec<(PciAddr, u64, u64)> = with(|st| {
+        let (gone, keep) = st.mappings.iter().partition(|m| m.0 == addr);
+        st.mappings = keep;
+        gone
+    });
+    for &(_, va, len) in maps.iter() {
+        mem::unmap_mmio(va, len as usize);
+    }
+    for &gsi in b.irqs.iter() {
+        let _ = irq::unregister(gsi);
+    }
+    true
+}
+
+/// Start watching the bus. Needs the executor.
+pub fn init() {
+    WATCHER.call_once(|| {
+        with(|st| st.known = scan().iter().map(|d| d.addr).collect());
+        executor::spawn(async {
+            let mut n = 0u32;
+            loop {
+                executor::sleep_ticks(POLL_TICKS).await;
+                check_removed();
+                n += 1;
+                if n.is_multiple_of(RESCAN_EVERY) {
+                    rescan();
+                }
+            }
+        });
+    });
+}
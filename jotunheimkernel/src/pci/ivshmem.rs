@@ -0,0 +1,250 @@
+// src/pci/ivshmem.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// QEMU ivshmem (1af4:1110). BAR0 holds the doorbell registers, BAR2 the
+// shared memory. We lay two single-producer rings over BAR2 so a host test
+// harness can push commands and collect results:
+//
+//   0x0000  header  { magic, version, slots, slot_size }
+//   0x0040  cmd ring (host -> guest)   { head (host), tail (guest) } + slots
+//   ....    res ring (guest -> host)   { head (guest), tail (host) } + slots
+//
+// Each slot is `{ len: u32, data[slot_size - 4] }`. Indices only grow; the
+// slot is `index % slots`. The guest writes the header; the host waits for
+// `magic` before touching the rings. If the device is unplugged, `get()`
+// returns None and both BARs are unmapped; a device that is plugged in later
+// is picked up by the hot-add scan.
+extern crate alloc;
+use alloc::boxed::Box;
+
+use core::ptr::{null_mut, read_volatile, write_volatile};
+use core::sync::atomic::{AtomicPtr, Ordering, fence};
+
+use spin::Once;
+
+use super::{Bar, CMD_BUS_MASTER, CMD_MEM, PciDevice, find, hotplug};
+use crate::kprintln;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const VENDOR: u16 = 0x1af4;
+const DEVICE: u16 = 0x1110;
+
+const REG_INTR_MASK: usize = 0x00;
+const REG_IV_POSITION: usize = 0x08;
+
+pub const MAGIC: u32 = 0x4853_544A; // "JTSH"
+pub const VERSION: u32 = 1;
+pub const SLOTS: u32 = 64;
+pub const SLOT_SIZE: u32 = 1024;
+
+const HDR_SIZE: usize = 0x40;
+const RING_HDR: usize = 0x40; // head/tail on their own cache line
+const RING_SIZE: usize = RING_HDR + (SLOTS * SLOT_SIZE) as usize;
+const MAX_MAP: u64 = 16 << 20;
+
+struct Ring {
+    base: u64,
+}
+
+pub struct Ivshmem {
+    regs: u64,
+    shm: u64,
+    cmd: Ring,
+    res: Ring,
+}
+
+// The bound device; null before the first probe and after removal.
+static DEV: AtomicPtr<Ivshmem> = AtomicPtr::new(null_mut());
+static BOOT: Once<()> = Once::new();
+static HOTPLUG: Once<()> = Once::new();
+
+/* ----------------------------------- Rings ---------------------------------- */
+
+impl Ring {
+    fn head(&self) -> *mut u32 {
+        self.base as *mut u32
+    }
+
+    fn tail(&self) -> *mut u32 {
+        (self.base + 4) as *mut u32
+    }
+
+    fn slot(&self, idx: u32) -> *mut u8 {
+        (self.base + RING_HDR as u64 + ((idx % SLOTS) * SLOT_SIZE) as u64) as *mut u8
+    }
+
+    fn reset(&self) {
+        unsafe {
+            write_volatile(self.head(), 0);
+            write_volatile(self.tail(), 0);
+        }
+    }
+
+    /// Consumer side: copy the oldest entry into `buf`.
+    fn pop(&self, buf: &mut [u8]) -> Option<usize> {
+        let (head, tail) = unsafe { (read_volatile(self.head()), read_volatile(self.tail())) };
+        if head == tail {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        let p = self.slot(tail);
+        let len = unsafe { read_volatile(p as *const u32) }.min(SLOT_SIZE - 4) as usize;
+        let n = len.min(buf.len());
+        for (i, b) in buf[..n].iter_mut().enumerate() {
+            *b = unsafe { read_volatile(p.add(4 + i)) };
+        }
+        fence(Ordering::Release);
+        unsafe { write_volatile(self.tail(), tail.wrapping_add(1)) };
+        Some(n)
+    }
+
+    /// Producer side: `false` when the ring is full or `data` won't fit a slot.
+    fn push(&self, data: &[u8]) -> bool {
+        let (head, tail) = unsafe { (read_volatile(self.head()), read_volatile(self.tail())) };
+        if head.wrapping_sub(tail) >= SLOTS || data.len() > (SLOT_SIZE - 4) as usize {
+            return false;
+        }
+        let p = self.slot(head);
+        unsafe {
+            write_volatile(p as *mut u32, data.len() as u32);
+            for (i, &b) in data.iter().enumerate() {
+                write_volatile(p.add(4 + i), b);
+            }
+        }
+        fence(Ordering::Release);
+        unsafe { write_volatile(self.head(), head.wrapping_add(1)) };
+        true
+    }
+}
+
+/* ---------------------------------- Device ---------------------------------- */
+
+impl Ivshmem {
+    fn probe(dev: &PciDevice) -> Option<Self> {
+        let Some(Bar::Mem {
+            base: sbase,
+            size: ssize,
+            ..
+        }) = dev.bar(2)
+        else {
+            return None;
+        };
+        let need = HDR_SIZE + 2 * RING_SIZE;
+        if (ssize as usize) < need {
+            kprintln!(
+                "[ivshmem] shared BAR too small ({:#x} < {:#x})",
+                ssize,
+                need
+            );
+            return None;
+        }
+        let (regs, _) = dev.map_bar(0, 0x100)?;
+        let (shm, shm_len) = dev.map_bar(2, MAX_MAP)?;
+        let shm_len = shm_len as usize;
+        dev.set_command(CMD_MEM | CMD_BUS_MASTER);
+        let cmd = Ring {
+            base: shm + HDR_SIZE as u64,
+        };
+        let res = Ring {
+            base: shm + (HDR_SIZE + RING_SIZE) as u64,
+        };
+        let d = Self {
+            regs,
+            shm,
+            cmd,
+            res,
+        };
+        d.format();
+        hotplug::bind(dev, "ivshmem", removed);
+        kprintln!(
+            "[ivshmem] {:#x} bytes at {:#x}, position {}",
+            shm_len,
+            sbase,
+            d.position()
+        );
+        Some(d)
+    }
+
+    fn hdr(&self, i: usize) -> *mut u32 {
+        (self.shm + 4 * i as u64) as *mut u32
+    }
+
+    fn reg(&self, off: usize) -> *mut u32 {
+        (self.regs + off as u64) as *mut u32
+    }
+
+    // Publish the layout; magic goes last so the host never sees half a header.
+    fn format(&self) {
+        self.cmd.reset();
+        self.res.reset();
+        unsafe {
+            write_volatile(self.hdr(1), VERSION);
+            write_volatile(self.hdr(2), SLOTS);
+            write_volatile(self.hdr(3), SLOT_SIZE);
+            fence(Ordering::Release);
+            write_volatile(self.hdr(0), MAGIC);
+            write_volatile(self.reg(REG_INTR_MASK), 0);
+        }
+    }
+
+    /// Our peer id on the ivshmem server (0 without a server).
+    pub fn position(&self) -> u32 {
+        unsafe { read_volatile(self.reg(REG_IV_POSITION)) }
+    }
+
+    // The harness side of the protocol; nothing in the kernel issues or
+    // answers commands yet.
+
+    /// Next host command, copied into `buf`; returns its length.
+    #[allow(dead_code)]
+    pub fn poll_cmd(&self, buf: &mut [u8]) -> Option<usize> {
+        self.cmd.pop(buf)
+    }
+
+    /// Post one result record for the host.
+    #[allow(dead_code)]
+    pub fn post_result(&self, data: &[u8]) -> bool {
+        self.res.push(data)
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+fn attach(dev: &PciDevice) -> bool {
+    let Some(d) = Ivshmem::probe(dev) else {
+        return false;
+    };
+    DEV.store(Box::into_raw(Box::new(d)), Ordering::Release);
+    true
+}
+
+// Hot-add: take a new ivshmem function if we are not driving one already.
+fn hot_add(dev: &PciDevice) -> bool {
+    dev.vendor == VENDOR
+        && dev.device == DEVICE
+        && DEV.load(Ordering::Acquire).is_null()
+        && attach(dev)
+}
+
+// The handle is leaked, not freed: `get()` callers may still hold it.
+fn removed(_: &PciDevice) {
+    DEV.store(null_mut(), Ordering::Release);
+}
+
+/// Probe on first use and keep the device around until it is unplugged.
+/// Do not hold the reference across a sleep: removal unmaps the BARs.
+pub fn get() -> Option<&'static Ivshmem> {
+    BOOT.call_once(|| {
+        if let Some(dev) = find(VENDOR, DEVICE) {
+            attach(&dev);
+        }
+    });
+    unsafe { DEV.load(Ordering::Acquire).as_ref() }
+}
+
+/// Probe, and take a device that is hot-added later.
+pub fn init() {
+    get();
+    HOTPLUG.call_once(|| hotplug::register_driver("ivshmem", hot_add));
+}
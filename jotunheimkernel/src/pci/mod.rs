@@ -0,0 +1,239 @@
+// src/pci/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Legacy (port 0xCF8/0xCFC) configuration access and a brute-force bus scan.
+// Hot-add and surprise removal are in `hotplug`.
+
+pub mod hotplug;
+pub mod ivshmem;
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+use crate::mem;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const REG_ID: u8 = 0x00;
+const REG_COMMAND: u8 = 0x04;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER: u8 = 0x0C;
+const REG_BAR0: u8 = 0x10;
+const REG_INTERRUPT: u8 = 0x3C; // line, pin
+
+pub const CMD_IO: u16 = 1 << 0;
+pub const CMD_MEM: u16 = 1 << 1;
+pub const CMD_BUS_MASTER: u16 = 1 << 2;
+
+// Address/data are a register pair; a second CPU must not interleave.
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PciAddr {
+    pub bus: u8,
+    pub dev: u8,
+    pub func: u8,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PciDevice {
+    pub addr: PciAddr,
+    pub vendor: u16,
+    pub device: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub header_type: u8,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Bar {
+    Mem { base: u64, size: u64 },
+    Io { port: u16 },
+}
+
+/* ------------------------------ Config access ------------------------------- */
+
+impl PciAddr {
+    fn cfg_addr(&self, off: u8) -> u32 {
+        (1 << 31)
+            | ((self.bus as u32) << 16)
+            | ((self.dev as u32 & 0x1F) << 11)
+            | ((self.func as u32 & 0x7) << 8)
+            | (off as u32 & 0xFC)
+    }
+
+    pub fn read32(&self, off: u8) -> u32 {
+        let a = self.cfg_addr(off);
+        without_interrupts(|| {
+            let _g = CONFIG_LOCK.lock();
+            unsafe {
+                Port::<u32>::new(CONFIG_ADDRESS).write(a);
+                Port::<u32>::new(CONFIG_DATA).read()
+            }
+        })
+    }
+
+    pub fn write32(&self, off: u8, val: u32) {
+        let a = self.cfg_addr(off);
+        without_interrupts(|| {
+            let _g = CONFIG_LOCK.lock();
+            unsafe {
+                Port::<u32>::new(CONFIG_ADDRESS).write(a);
+                Port::<u32>::new(CONFIG_DATA).write(val);
+            }
+        })
+    }
+
+    pub fn read16(&self, off: u8) -> u16 {
+        (self.read32(off) >> ((off & 2) * 8)) as u16
+    }
+
+    // A real 16-bit cycle: a dword read-modify-write of COMMAND would write
+    // STATUS's W1C bits back as ones and clear them.
+    pub fn write16(&self, off: u8, val: u16) {
+        let a = self.cfg_addr(off);
+        without_interrupts(|| {
+            let _g = CONFIG_LOCK.lock();
+            unsafe {
+                Port::<u32>::new(CONFIG_ADDRESS).write(a);
+                Port::<u16>::new(CONFIG_DATA + (off & 2) as u16).write(val);
+            }
+        })
+    }
+}
+
+/* --------------------------------- Devices ---------------------------------- */
+
+impl PciDevice {
+    fn probe(addr: PciAddr) -> Option<Self> {
+        let id = addr.read32(REG_ID);
+        if id & 0xFFFF == 0xFFFF {
+            return None;
+        }
+        let class = addr.read32(REG_CLASS);
+        let hdr = addr.read32(REG_HEADER);
+        Some(Self {
+            addr,
+            vendor: id as u16,
+            device: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            header_type: (hdr >> 16) as u8,
+        })
+    }
+
+    pub fn command(&self) -> u16 {
+        self.addr.read16(REG_COMMAND)
+    }
+
+    pub fn set_command(&self, bits: u16) {
+        self.addr.write16(REG_COMMAND, self.command() | bits);
+    }
+
+    /// The INTx line firmware assigned, taken as the IOAPIC GSI; `None` if
+    /// the function has no interrupt pin or no line was assigned.
+    pub fn irq_line(&self) -> Option<u32> {
+        let r = self.addr.read32(REG_INTERRUPT);
+        let (line, pin) = (r as u8, (r >> 8) as u8);
+        (pin != 0 && line != 0xFF).then_some(line as u32)
+    }
+
+    /// Decode BAR `i` (0..6), sizing it with the usual all-ones probe.
+    /// Returns `None` for unimplemented BARs and the upper half of a 64-bit BAR.
+    pub fn bar(&self, i: u8) -> Option<Bar> {
+        if i >= 6 || self.header_type & 0x7F != 0 {
+            return None;
+        }
+        let off = REG_BAR0 + i * 4;
+        let a = self.addr;
+        let lo = a.read32(off);
+
+        // Decoding off while the BAR briefly holds all-ones.
+        let cmd = self.command();
+        a.write16(REG_COMMAND, cmd & !(CMD_IO | CMD_MEM));
+
+        let bar = if lo & 1 == 1 {
+            a.write32(off, 0xFFFF_FFFF);
+            let mask = a.read32(off) & !0x3;
+            a.write32(off, lo);
+            (mask != 0).then_some(Bar::Io {
+                port: (lo & !0x3) as u16,
+            })
+        } else {
+            let is64 = (lo >> 1) & 0x3 == 0x2;
+            a.write32(off, 0xFFFF_FFFF);
+            let mut mask = (a.read32(off) & !0xF) as u64;
+            a.write32(off, lo);
+            let mut base = (lo & !0xF) as u64;
+            if is64 && i < 5 {
+                let hi = a.read32(off + 4);
+                a.write32(off + 4, 0xFFFF_FFFF);
+                mask |= (a.read32(off + 4) as u64) << 32;
+                a.write32(off + 4, hi);
+                base |= (hi as u64) << 32;
+            } else {
+                mask |= 0xFFFF_FFFF_0000_0000;
+            }
+            (mask & 0xFFFF_FFFF != 0).then(|| Bar::Mem {
+                base,
+                size: !mask + 1,
+            })
+        };
+
+        a.write16(REG_COMMAND, cmd);
+        bar
+    }
+
+    /// Map memory BAR `i` (at most `max_len` bytes of it) uncached, also
+    /// through the HHDM. Returns its virtual address and the mapped length;
+    /// `None` if the BAR is not a memory BAR. The mapping is torn down if
+    /// the device is removed.
+    pub fn map_bar(&self, i: u8, max_len: u64) -> Option<(u64, u64)> {
+        let Some(Bar::Mem { base, size, .. }) = self.bar(i) else {
+            return None;
+        };
+        let len = size.min(max_len);
+        let va = mem::map_mmio(base, len as usize);
+        hotplug::note_mapping(self.addr, va, len);
+        Some((va, len))
+    }
+}
+
+/* -------------------------------- Enumeration ------------------------------- */
+
+/// Every function on every bus. Slow (8K config reads) but boot-time only.
+pub fn scan() -> Vec<PciDevice> {
+    let mut out = Vec::new();
+    for bus in 0..=255u8 {
+        for dev in 0..32u8 {
+            let Some(f0) = PciDevice::probe(PciAddr { bus, dev, func: 0 }) else {
+                continue;
+            };
+            let multi = f0.header_type & 0x80 != 0;
+            out.push(f0);
+            if multi {
+                for func in 1..8u8 {
+                    if let Some(d) = PciDevice::probe(PciAddr { bus, dev, func }) {
+                        out.push(d);
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+pub fn find(vendor: u16, device: u16) -> Option<PciDevice> {
+    scan()
+        .into_iter()
+        .find(|d| d.vendor == vendor && d.device == device)
+}
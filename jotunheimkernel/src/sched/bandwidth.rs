@@ -0,0 +1,71 @@
+// src/sched/bandwidth.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// CPU bandwidth caps: a capped task may run `quota` ticks per `period`.
+// The tick charges the running task; once over quota it is parked in
+// TaskState::Throttled until its period rolls over.
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// All values in scheduler ticks (ms).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CpuLimit {
+    pub quota: u64,
+    pub period: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bandwidth {
+    pub limit: CpuLimit,
+    used: u64,
+    period_end: u64,
+}
+
+/* --------------------------------- Limits ----------------------------------- */
+
+impl CpuLimit {
+    /// `percent` of one CPU over `period` ticks (at least one tick of quota).
+    pub fn percent(percent: u8, period: u64) -> Self {
+        let pct = percent.clamp(1, 100) as u64;
+        Self {
+            quota: (period * pct / 100).max(1),
+            period: period.max(1),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.quota > 0 && self.quota <= self.period
+    }
+}
+
+impl Bandwidth {
+    pub fn new(limit: CpuLimit, now: u64) -> Self {
+        Self {
+            limit,
+            used: 0,
+            period_end: now + limit.period,
+        }
+    }
+
+    /// Roll the period over if due. Returns true when the quota was refilled.
+    pub fn refresh(&mut self, now: u64) -> bool {
+        if now < self.period_end {
+            return false;
+        }
+        // Skip whole periods that passed while the task was not running.
+        let behind = (now - self.period_end) / self.limit.period;
+        self.period_end += (behind + 1) * self.limit.period;
+        self.used = 0;
+        true
+    }
+
+    /// Charge one tick. Returns true when the task is now over quota.
+    pub fn charge(&mut self) -> bool {
+        self.used += 1;
+        self.used >= self.limit.quota
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.used >= self.limit.quota
+    }
+}
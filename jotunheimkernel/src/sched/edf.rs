@@ -0,0 +1,115 @@
+// src/sched/edf.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Earliest-deadline-first class. A deadline task is promised `runtime` ticks
+// of CPU in every `period`, delivered within `deadline` ticks of the period
+// start. While it has budget left it preempts the round-robin class; once the
+// budget is spent it is throttled until the next period. Admission keeps the
+// summed utilisation under UTIL_MAX so round-robin tasks still make progress.
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const UTIL_SCALE: u64 = 1_000_000;
+pub const UTIL_MAX: u64 = UTIL_SCALE * 9 / 10;
+
+/// All values in scheduler ticks (ms).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DeadlineParams {
+    pub runtime: u64,
+    pub deadline: u64,
+    pub period: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AdmissionError {
+    Invalid,    // not 0 < runtime <= deadline <= period
+    Overloaded, // would push total utilisation past UTIL_MAX
+    NoTask,
+}
+
+#[derive(Clone, Debug)]
+pub struct DlEntity {
+    pub params: DeadlineParams,
+    pub abs_deadline: u64,
+    next_period: u64,
+    remaining: u64,
+    miss_reported: bool,
+}
+
+/* --------------------------------- Params ----------------------------------- */
+
+impl DeadlineParams {
+    pub fn validate(&self) -> Result<(), AdmissionError> {
+        let p = self;
+        if p.runtime == 0 || p.runtime > p.deadline || p.deadline > p.period {
+            return Err(AdmissionError::Invalid);
+        }
+        Ok(())
+    }
+
+    /// Share of one CPU, in UTIL_SCALE units.
+    pub fn utilisation(&self) -> u64 {
+        self.runtime * UTIL_SCALE / self.period
+    }
+}
+
+/// Admission test against the utilisation already reserved.
+pub fn admit(reserved: u64, p: &DeadlineParams) -> Result<u64, AdmissionError> {
+    p.validate()?;
+    let total = reserved + p.utilisation();
+    if total > UTIL_MAX {
+        return Err(AdmissionError::Overloaded);
+    }
+    Ok(total)
+}
+
+/* --------------------------------- Entity ----------------------------------- */
+
+impl DlEntity {
+    pub fn new(params: DeadlineParams, now: u64) -> Self {
+        Self {
+            params,
+            abs_deadline: now + params.deadline,
+            next_period: now + params.period,
+            remaining: params.runtime,
+            miss_reported: false,
+        }
+    }
+
+    /// Start a new period if one is due. Returns true when the budget was
+    /// refilled. Periods missed while blocked are skipped, not made up.
+    pub fn replenish(&mut self, now: u64) -> bool {
+        if now < self.next_period {
+            return false;
+        }
+        let start = if now - self.next_period >= self.params.period {
+            now
+        } else {
+            self.next_period
+        };
+        self.abs_deadline = start + self.params.deadline;
+        self.next_period = start + self.params.period;
+        self.remaining = self.params.runtime;
+        self.miss_reported = false;
+        true
+    }
+
+    pub fn has_budget(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// True once per period when the deadline passes with budget unspent.
+    pub fn check_miss(&mut self, now: u64) -> bool {
+        if self.miss_reported || self.remaining == 0 || now < self.abs_deadline {
+            return false;
+        }
+        self.miss_reported = true;
+        true
+    }
+
+    /// Account one tick of CPU. Returns true when the budget just ran out.
+    pub fn charge(&mut self) -> bool {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.remaining == 0
+    }
+}
@@ -2,10 +2,8 @@
 // Copyright (C) 2025 The Jotunheim Project
 // src/sched/exec.rs
 
-use heapless::Deque;
-use spin::Mutex;
-
 use crate::sched;
+use crate::util::ring::BlockingRing;
 
 // Tune as needed
 const QUEUE_CAPACITY: usize = 64; // max pending closures (early AP)
@@ -73,7 +71,7 @@ where
 
 // ===== Global queue + single serving thread =====
 
-static QUEUE: Mutex<Deque<Slot, QUEUE_CAPACITY>> = Mutex::new(Deque::new());
+static QUEUE: BlockingRing<Slot, QUEUE_CAPACITY> = BlockingRing::new(); // server sleeps on it
 
 /// Call once when the scheduler is up (e.g., end of `sched::init()`).
 /// Spawns one server thread that turns queued slots into `sched::spawn(closure)`d threads.
@@ -83,30 +81,24 @@ pub fn init() {
 }
 
 /// Early-AP safe: capture closure into a fixed-size slot and enqueue it.
-/// No `spawn()` here; the server thread will call `spawn()` as soon as it runs.
+/// No `spawn()` here; the server thread, woken if it was waiting, will call it.
 /// Returns `Err(())` if the closure is too large or the queue is full.
 pub fn submit<F>(f: F) -> Result<(), ()>
 where
     F: FnOnce() + Send + 'static,
 {
     let slot = into_slot(f)?;
-    let mut q = QUEUE.lock();
-    if q.push_back(slot).is_err() {
-        return Err(()); // queue full; caller can retry or drop
-    }
-    Ok(())
+    // Queue full: caller can retry or drop.
+    QUEUE.push(slot).map_err(|_| ())
 }
 
 fn server_main() -> ! {
+    let mut rx = QUEUE.consumer().expect("exec: server started twice");
     loop {
-        // Drain everything available; for each slot, spawn a *new* thread.
-        while let Some(slot) = QUEUE.lock().pop_front() {
-            crate::sched::spawn(move || {
-                slot.invoke_and_forget();
-            });
-        }
-        for _ in 0..1_000 {
-            sched::yield_now();
-        }
+        // One slot at a time; for each slot, spawn a *new* thread.
+        let slot = rx.pop_wait();
+        crate::sched::spawn(move || {
+            slot.invoke_and_forget();
+        });
     }
 }
@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// src/sched/executor.rs
+//
+// Small future executor on top of kernel threads. Each worker is an ordinary
+// `sched::spawn`ed thread that polls ready futures and sleeps in
+// `block_current()` when there is nothing to do. Wakers re-queue the future
+// and kick an idle worker; timers and IRQs are just more wakers. Wakers
+// run in IRQ context, so they never allocate: READY always has room for
+// every live future (each is queued at most once).
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::sched::{self, TaskId};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct FutTask {
+    fut: Mutex<Option<BoxFuture>>,
+    queued: AtomicBool, // already in READY; suppresses duplicate wakes
+}
+
+struct Ready {
+    queue: VecDeque<Arc<FutTask>>,
+    idle: Vec<TaskId>, // workers parked in block_current()
+}
+
+// Touched from IRQ context (wakers); always taken with IRQs off.
+static READY: Mutex<Ready> = Mutex::new(Ready {
+    queue: VecDeque::new(),
+    idle: Vec::new(),
+});
+
+struct Timer {
+    deadline: u64,
+    id: u64, // the owning Sleep's, so it can swap its waker in place
+    waker: Waker,
+}
+
+// Unsorted; expiries are scanned on every tick.
+static TIMERS: Mutex<Vec<Timer>> = Mutex::new(Vec::new());
+static NEXT_TIMER: AtomicU64 = AtomicU64::new(1);
+// Live FutTasks: the capacity READY needs.
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+// Worker threads started so far.
+static WORKERS: AtomicUsize = AtomicUsize::new(0);
+// Expired timers taken per lock hold in `on_tick`.
+const FIRE_BATCH: usize = 16;
+
+/* ----------------------------------- Wakers ----------------------------------- */
+
+impl Drop for FutTask {
+    fn drop(&mut self) {
+        LIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Wake for FutTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let worker = without_interrupts(|| {
+            let mut r = READY.lock();
+            r.queue.push_back(self.clone());
+            r.idle.pop()
+        });
+        if let Some(id) = worker {
+            sched::wake(id);
+        }
+    }
+}
+
+/* ----------------------------------- Workers ---------------------------------- */
+
+fn worker_main() -> ! {
+    let me = sched::current_id();
+    loop {
+        let next = without_interrupts(|| {
+            let mut r = READY.lock();
+            let t = r.queue.pop_front();
+            if t.is_none()
+                && let Some(id) = me
+            {
+                r.idle.push(id);
+            }
+            t
+        });
+        let Some(task) = next else {
+            sched::block_current();
+            continue;
+        };
+
+        task.queued.store(false, Ordering::Release);
+        let waker = Waker::from(task.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut slot = task.fut.lock();
+        if let Some(fut) = slot.as_mut()
+            && fut.as_mut().poll(&mut cx).is_ready()
+        {
+            *slot = None; // drop the future; stray wakes find an empty slot
+        }
+    }
+}
+
+/// Add executor threads until there are `workers`, one per CPU: called
+/// for the BSP when the scheduler starts, and with the online CPU count
+/// once the APs are up. Never stops any.
+pub fn init(workers: usize) {
+    let workers = workers.max(1);
+    let have = WORKERS.fetch_max(workers, Ordering::AcqRel);
+    for _ in have..workers {
+        sched::spawn(|| worker_main());
+    }
+}
+
+/// Queue a future; it is first polled by the next free worker.
+pub fn spawn<F>(fut: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let task = Arc::new(FutTask {
+        fut: Mutex::new(Some(Box::pin(fut))),
+        queued: AtomicBool::new(false),
+    });
+    let live = LIVE.fetch_add(1, Ordering::Relaxed) + 1;
+    without_interrupts(|| {
+        let mut r = READY.lock();
+        let len = r.queue.len();
+        if r.queue.capacity() < live {
+            r.queue.reserve(live - len);
+        }
+    });
+    task.wake_by_ref();
+}
+
+/* ------------------------------- Wake sources ------------------------------- */
+
+/// Called from the timer ISR with the current tick; fires expired sleeps.
+/// Wakers are taken off the list a batch at a time, on the stack.
+pub fn on_tick(now: u64) {
+    loop {
+        let mut fired: heapless::Vec<Waker, FIRE_BATCH> = heapless::Vec::new();
+        {
+            let mut t = TIMERS.lock();
+            let mut i = 0;
+            while i < t.len() && !fired.is_full() {
+                if t[i].deadline <= now {
+                    let _ = fired.push(t.swap_remove(i).waker);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        let more = fired.is_full();
+        // Not the last reference: a woken FutTask is now in READY, and a
+        // finished one has no timers left (Sleep deregisters on drop), so
+        // nothing is freed here in IRQ context.
+        for w in &fired {
+            w.wake_by_ref();
+        }
+        if !more {
+            break;
+        }
+    }
+}
+
+/// Future that completes once `ticks` timer ticks have elapsed.
+pub struct Sleep {
+    deadline: u64,
+    id: u64,
+    registered: Option<Waker>, // the waker our TIMERS entry holds
+}
+
+pub fn sleep_ticks(ticks: u64) -> Sleep {
+    Sleep {
+        deadline: sched::ticks() + ticks,
+        id: NEXT_TIMER.fetch_add(1, Ordering::Relaxed),
+        registered: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    // One TIMERS entry per Sleep, however often it is polled; a new waker
+    // replaces the old one only when it would wake something else.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if sched::ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+        if self
+            .registered
+            .as_ref()
+            .is_some_and(|w| w.will_wake(cx.waker()))
+        {
+            return Poll::Pending;
+        }
+        let w = cx.waker().clone();
+        let (deadline, id) = (self.deadline, self.id);
+        without_interrupts(|| {
+            let mut t = TIMERS.lock();
+            match t.iter_mut().find(|e| e.id == id) {
+                Some(e) => e.waker = w.clone(),
+                None => t.push(Timer {
+                    deadline,
+                    id,
+                    waker: w.clone(),
+                }),
+            }
+        });
+        self.registered = Some(w);
+        Poll::Pending
+    }
+}
+
+// A future dropped mid-sleep (finished by something else, or its FutTask
+// done) must not leave a waker in TIMERS for the tick to drop.
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if self.registered.is_some() {
+            let id = self.id;
+            without_interrupts(|| TIMERS.lock().retain(|e| e.id != id));
+        }
+    }
+}
+
+/// Future that lets every other ready future run once before completing.
+pub struct YieldNow {
+    yielded: bool,
+}
+
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        // Re-queues us at the back of READY.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Edge-style event an interrupt handler can `signal()`; futures `wait()` on it.
+pub struct IrqEvent {
+    fired: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl IrqEvent {
+    pub const fn new() -> Self {
+        Self {
+            fired: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        }
+    }
+
+    /// ISR side. Never blocks: the only other holder runs with IRQs off.
+    pub fn signal(&self) {
+        self.fired.store(true, Ordering::Release);
+        if let Some(w) = self.waker.lock().take() {
+            w.wake();
+        }
+    }
+
+    pub fn wait(&self) -> IrqWait<'_> {
+        IrqWait { ev: self }
+    }
+}
+
+pub struct IrqWait<'a> {
+    ev: &'a IrqEvent,
+}
+
+impl Future for IrqWait<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let ev = self.ev;
+        if ev.fired.swap(false, Ordering::AcqRel) {
+            return Poll::Ready(());
+        }
+        without_interrupts(|| *ev.waker.lock() = Some(cx.waker().clone()));
+        // Re-check: the IRQ may have fired before the waker was stored.
+        if ev.fired.swap(false, Ordering::AcqRel) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
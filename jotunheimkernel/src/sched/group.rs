@@ -0,0 +1,289 @@
+// src/sched/group.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Task groups: a tree rooted at ROOT_GROUP. CPU ticks and memory charges are
+// added to a group and all of its ancestors, so every node holds the total
+// for its subtree. A group's weight scales its tasks' round-robin slice, and
+// an optional CPU cap throttles the whole subtree. New threads join their
+// spawner's group. Groups live in the run queue and share its lock.
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::bandwidth::{Bandwidth, CpuLimit};
+use super::{RQ, RunQueue, TaskId, TaskState, ticks, with_rq_locked};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub type GroupId = u32;
+
+pub const ROOT_GROUP: GroupId = 0;
+pub const DEFAULT_WEIGHT: u32 = 100;
+const MAX_WEIGHT: u32 = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct Group {
+    id: GroupId,
+    name: &'static str,
+    parent: Option<GroupId>,
+    weight: u32,
+    cpu_ticks: u64,
+    mem_bytes: u64,
+    cap: Option<Bandwidth>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GroupStats {
+    pub name: &'static str,
+    pub parent: Option<GroupId>,
+    pub weight: u32,
+    pub cpu_ticks: u64, // subtree total
+    pub mem_bytes: u64, // subtree total
+    pub tasks: usize,   // live tasks directly in this group
+}
+
+impl Group {
+    pub(super) fn root() -> Self {
+        Self {
+            id: ROOT_GROUP,
+            name: "root",
+            parent: None,
+            weight: DEFAULT_WEIGHT,
+            cpu_ticks: 0,
+            mem_bytes: 0,
+            cap: None,
+        }
+    }
+}
+
+/* ------------------------------ Run-queue side ------------------------------ */
+
+impl RunQueue {
+    fn group_mut(&mut self, id: GroupId) -> Option<&mut Group> {
+        self.groups.iter_mut().find(|g| g.id == id)
+    }
+
+    fn parent_of(&self, id: GroupId) -> Option<GroupId> {
+        self.groups.iter().find(|g| g.id == id)?.parent
+    }
+
+    /// Apply `f` to `id` and each of its ancestors.
+    fn for_each_ancestor(&mut self, id: GroupId, mut f: impl FnMut(&mut Group)) {
+        let mut cur = Some(id);
+        while let Some(g) = cur.and_then(|c| self.group_mut(c)) {
+            f(g);
+            cur = g.parent;
+        }
+    }
+
+    fn stats_of(&self, g: &Group) -> GroupStats {
+        GroupStats {
+            name: g.name,
+            parent: g.parent,
+            weight: g.weight,
+            cpu_ticks: g.cpu_ticks,
+            mem_bytes: g.mem_bytes,
+            tasks: self
+                .tasks
+                .iter()
+                .filter(|t| t.group == g.id && t.state != TaskState::Dead)
+                .count(),
+        }
+    }
+
+    /// Is `g` equal to or below `ancestor`?
+    fn in_subtree(&self, mut g: GroupId, ancestor: GroupId) -> bool {
+        loop {
+            if g == ancestor {
+                return true;
+            }
+            match self.parent_of(g) {
+                Some(p) => g = p,
+                None => return false,
+            }
+        }
+    }
+
+    /// Any group on the path to the root over its CPU cap?
+    fn group_throttled(&self, mut g: GroupId) -> bool {
+        loop {
+            let Some(grp) = self.groups.iter().find(|x| x.id == g) else {
+                return false;
+            };
+            if grp.cap.as_ref().is_some_and(|b| b.exhausted()) {
+                return true;
+            }
+            match grp.parent {
+                Some(p) => g = p,
+                None => return false,
+            }
+        }
+    }
+
+    /// Round-robin slice for a task in group `g`, scaled by its weight.
+    pub(super) fn slice_for(&self, g: GroupId) -> u32 {
+        let w = self
+            .groups
+            .iter()
+            .find(|x| x.id == g)
+            .map_or(DEFAULT_WEIGHT, |x| x.weight);
+        (super::slice() * w / DEFAULT_WEIGHT).max(1)
+    }
+
+    pub(super) fn charge_mem(&mut self, g: GroupId, delta: i64) {
+        self.for_each_ancestor(g, |x| {
+            x.mem_bytes = x.mem_bytes.saturating_add_signed(delta);
+        });
+    }
+}
+
+/// Per-tick group work: CPU accounting and cap charging for this CPU's task;
+/// on the keeper, cap refresh and moving tasks in and out of Throttled as
+/// their groups cross the cap. Other CPUs see their task throttled at their
+/// next tick.
+pub(super) fn tick_groups(rq: &mut RunQueue, now: u64, keeper: bool) {
+    for g in rq.groups.iter_mut().filter(|_| keeper) {
+        if let Some(b) = g.cap.as_mut() {
+            b.refresh(now);
+        }
+    }
+    if let Some(cur) = rq.current {
+        let g = rq.tasks[cur].group;
+        rq.for_each_ancestor(g, |x| {
+            x.cpu_ticks += 1;
+            if let Some(b) = x.cap.as_mut() {
+                b.charge();
+            }
+        });
+    }
+    if !keeper {
+        return;
+    }
+    for i in 0..rq.tasks.len() {
+        let t = &rq.tasks[i];
+        let own_over = t.cap.as_ref().is_some_and(|b| b.exhausted());
+        let group_over = rq.group_throttled(t.group);
+        let t = rq.tasks[i].as_mut();
+        match t.state {
+            TaskState::Ready | TaskState::Running if group_over => {
+                if Some(i) == rq.current {
+                    rq.need_resched = true;
+                }
+                t.state = TaskState::Throttled;
+            }
+            TaskState::Throttled if !own_over && !group_over => t.state = TaskState::Ready,
+            _ => {}
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// New empty group under `parent`.
+pub fn create(name: &'static str, parent: GroupId) -> Option<GroupId> {
+    with_rq_locked(|rq| {
+        rq.groups.iter().find(|g| g.id == parent)?;
+        let id = rq.next_group;
+        rq.next_group += 1;
+        rq.groups.push(Group {
+            id,
+            name,
+            parent: Some(parent),
+            ..Group::root()
+        });
+        Some(id)
+    })
+}
+
+pub fn set_weight(g: GroupId, weight: u32) -> bool {
+    with_rq_locked(|rq| {
+        rq.group_mut(g)
+            .map(|x| x.weight = weight.clamp(1, MAX_WEIGHT))
+            .is_some()
+    })
+}
+
+/// Cap the whole subtree of `g` (or lift the cap).
+pub fn set_cpu_limit(g: GroupId, limit: Option<CpuLimit>) -> bool {
+    if limit.is_some_and(|l| !l.is_valid()) {
+        return false;
+    }
+    let now = ticks();
+    with_rq_locked(|rq| {
+        rq.group_mut(g)
+            .map(|x| x.cap = limit.map(|l| Bandwidth::new(l, now)))
+            .is_some()
+    })
+}
+
+pub fn of(task: TaskId) -> Option<GroupId> {
+    with_rq_locked(|rq| rq.tasks.iter().find(|t| t.id == task).map(|t| t.group))
+}
+
+/// Move a task to `g`. Past CPU time stays with the old group.
+pub fn attach(task: TaskId, g: GroupId) -> bool {
+    with_rq_locked(|rq| {
+        if !rq.groups.iter().any(|x| x.id == g) {
+            return false;
+        }
+        let Some(i) = rq.tasks.iter().position(|t| t.id == task) else {
+            return false;
+        };
+        let old = rq.tasks[i].group;
+        let mem = rq.tasks[i].mem_charged as i64;
+        rq.charge_mem(old, -mem);
+        rq.charge_mem(g, mem);
+        rq.tasks[i].group = g;
+        true
+    })
+}
+
+/// Charge (or with a negative `delta`, uncharge) memory to a group.
+pub fn charge_mem(g: GroupId, delta: i64) {
+    with_rq_locked(|rq| rq.charge_mem(g, delta));
+}
+
+pub fn stats(g: GroupId) -> Option<GroupStats> {
+    with_rq_locked(|rq| {
+        let x = rq.groups.iter().find(|x| x.id == g)?;
+        Some(rq.stats_of(x))
+    })
+}
+
+/// Stats of every group, in creation order. Skips the walk and returns
+/// false if the run queue is busy, so it is safe on failure paths that may
+/// have interrupted a run-queue holder.
+pub fn for_each(mut f: impl FnMut(GroupId, &GroupStats)) -> bool {
+    without_interrupts(|| {
+        let Some(guard) = RQ.try_lock() else {
+            return false;
+        };
+        let Some(rq) = guard.as_ref() else {
+            return true;
+        };
+        for x in rq.groups.iter() {
+            f(x.id, &rq.stats_of(x));
+        }
+        true
+    })
+}
+
+/// Kill every task in `g` and its subgroups; returns how many died. The root
+/// group (which holds the idle task) cannot be killed. Locks held by the
+/// victims are not released.
+pub fn kill(g: GroupId) -> usize {
+    if g == ROOT_GROUP {
+        return 0;
+    }
+    with_rq_locked(|rq| {
+        let victims: alloc::vec::Vec<TaskId> = rq
+            .tasks
+            .iter()
+            .filter(|t| t.state != TaskState::Dead && rq.in_subtree(t.group, g))
+            .map(|t| t.id)
+            .collect();
+        for &id in &victims {
+            rq.kill_task(id);
+        }
+        victims.len()
+    })
+}
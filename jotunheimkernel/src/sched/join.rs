@@ -0,0 +1,129 @@
+// src/sched/join.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Join handles for `spawn`. The task's closure result goes into a slot
+// shared with the handle; `join` blocks on the task's exit queue until the
+// task has finished, then takes it.
+//
+// A task that never returns from its closure (it called `exit_current`,
+// or was killed with its group) completes its handle too, with no result:
+// the reaper finishes the exit of every dead task it comes across, outside
+// the runqueue lock. Dropping a handle detaches the task; whichever side
+// goes last frees the shared state.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::waitq::WaitQueue;
+use super::{TaskId, current_id, exit_current};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+/// Exit notification, shared by a task and its handle.
+#[derive(Debug)]
+pub(super) struct Exit {
+    done: AtomicBool,
+    waiters: WaitQueue,
+}
+
+pub struct JoinHandle<T> {
+    id: TaskId,
+    exit: Arc<Exit>,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+// What `join_main` gets: the closure and where its result goes.
+pub(super) struct JoinFn<F, T> {
+    func: F,
+    exit: Arc<Exit>,
+    result: Arc<Mutex<Option<T>>>,
+}
+
+/* ----------------------------------- Exit ----------------------------------- */
+
+impl Exit {
+    pub(super) fn new() -> Self {
+        Self {
+            done: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Mark the task finished and wake its joiners. Not under the runqueue
+    /// lock: waking takes it.
+    pub(super) fn finish(&self) {
+        self.done.store(true, Ordering::Release);
+        self.waiters.notify_all();
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+impl<F, T> JoinFn<F, T>
+where
+    F: FnOnce() -> T,
+{
+    /// Box `func` for `join_main`, and the handle that will see its result.
+    pub(super) fn new(func: F) -> (Box<Self>, Arc<Exit>, Arc<Mutex<Option<T>>>) {
+        let exit = Arc::new(Exit::new());
+        let result = Arc::new(Mutex::new(None));
+        let f = Box::new(Self {
+            func,
+            exit: exit.clone(),
+            result: result.clone(),
+        });
+        (f, exit, result)
+    }
+}
+
+pub(super) extern "C" fn join_main<F, T>(arg: usize) -> !
+where
+    F: FnOnce() -> T,
+{
+    let JoinFn { func, exit, result } = *unsafe { Box::from_raw(arg as *mut JoinFn<F, T>) };
+    let v = func();
+    without_interrupts(|| *result.lock() = Some(v));
+    drop(result);
+    exit.finish();
+    // exit_current never returns: nothing on this stack is dropped after it.
+    drop(exit);
+    exit_current()
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl<T> JoinHandle<T> {
+    pub(super) fn new(id: TaskId, exit: Arc<Exit>, result: Arc<Mutex<Option<T>>>) -> Self {
+        Self { id, exit, result }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.exit.is_done()
+    }
+
+    /// Block until the task has exited. Its closure's result, or None if it
+    /// exited without returning from it. Panics when a task joins itself.
+    pub fn join(self) -> Option<T> {
+        assert!(
+            current_id() != Some(self.id),
+            "task {} joined itself",
+            self.id
+        );
+        self.exit.waiters.wait_until(|| self.exit.is_done());
+        without_interrupts(|| self.result.lock().take())
+    }
+}
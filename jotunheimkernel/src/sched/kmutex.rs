@@ -0,0 +1,203 @@
+// src/sched/kmutex.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Sleeping mutex for thread context. Contended lockers block instead of
+// spinning, and lend their deadline and their class and level to the holder
+// (priority inheritance), so a low holder cannot be starved while a
+// deadline or Fifo task waits on it. A holder of several contended locks
+// keeps the most urgent of what their waiters lend until it lets go of each.
+// Inheritance is one level deep: a boosted holder that itself blocks on
+// another KMutex does not pass the boost along.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::prio::Priority;
+use super::{
+    TaskId, block_current, current_id, effective_deadline, effective_priority, set_boost, wake,
+};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+struct State {
+    owner: Option<TaskId>,
+    waiters: Vec<TaskId>,
+}
+
+pub struct KMutex<T> {
+    state: Mutex<State>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for KMutex<T> {}
+unsafe impl<T: Send> Sync for KMutex<T> {}
+
+pub struct KMutexGuard<'a, T> {
+    m: &'a KMutex<T>,
+}
+
+// What one held, contended KMutex lends its owner.
+struct Lend {
+    lock: usize, // the KMutex's address
+    owner: TaskId,
+    deadline: Option<u64>,
+    prio: Option<Priority>,
+}
+
+// Taken inside a KMutex's state lock, with IRQs off.
+static LENDS: Mutex<Vec<Lend>> = Mutex::new(Vec::new());
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+// Record that `lock`'s waiters lend `owner` their earliest deadline and
+// highest priority (nothing, with none left), then re-boost `owner`.
+fn boost(lock: usize, owner: TaskId, waiters: &[TaskId]) {
+    let deadline = waiters.iter().filter_map(|&w| effective_deadline(w)).min();
+    let prio = waiters
+        .iter()
+        .filter_map(|&w| effective_priority(w))
+        .max_by_key(Priority::rank);
+    let mut lends = LENDS.lock();
+    lends.retain(|l| l.lock != lock);
+    if deadline.is_some() || prio.is_some() {
+        lends.push(Lend {
+            lock,
+            owner,
+            deadline,
+            prio,
+        });
+    }
+    settle(owner, &lends);
+}
+
+// `lock` is no longer `owner`'s: keep only what its other locks lend.
+fn release(lock: usize, owner: TaskId) {
+    let mut lends = LENDS.lock();
+    lends.retain(|l| l.lock != lock);
+    settle(owner, &lends);
+}
+
+// Boost `owner` by the most urgent lend across every lock it still holds.
+fn settle(owner: TaskId, lends: &[Lend]) {
+    let mine = || lends.iter().filter(|l| l.owner == owner);
+    let deadline = mine().filter_map(|l| l.deadline).min();
+    let prio = mine().filter_map(|l| l.prio).max_by_key(Priority::rank);
+    set_boost(owner, deadline, prio);
+}
+
+/* --------------------------------- Locking ---------------------------------- */
+
+impl<T> KMutex<T> {
+    pub const fn new(v: T) -> Self {
+        Self {
+            state: Mutex::new(State {
+                owner: None,
+                waiters: Vec::new(),
+            }),
+            data: UnsafeCell::new(v),
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<KMutexGuard<'_, T>> {
+        let me = current_id()?;
+        without_interrupts(|| {
+            let mut s = self.state.lock();
+            if s.owner.is_some() {
+                return None;
+            }
+            s.owner = Some(me);
+            boost(self.key(), me, &s.waiters);
+            Some(KMutexGuard { m: self })
+        })
+    }
+
+    /// Before the scheduler runs there is nobody to block for; spin instead.
+    pub fn lock(&self) -> KMutexGuard<'_, T> {
+        let Some(me) = current_id() else {
+            loop {
+                let mut s = self.state.lock();
+                if s.owner.is_none() {
+                    s.owner = Some(TaskId::MAX);
+                    return KMutexGuard { m: self };
+                }
+                drop(s);
+                core::hint::spin_loop();
+            }
+        };
+        loop {
+            let acquired = without_interrupts(|| {
+                let mut s = self.state.lock();
+                match s.owner {
+                    None => {
+                        s.owner = Some(me);
+                        s.waiters.retain(|&w| w != me);
+                        // Waiters still queued keep the new owner boosted.
+                        boost(self.key(), me, &s.waiters);
+                        true
+                    }
+                    Some(owner) => {
+                        if !s.waiters.contains(&me) {
+                            s.waiters.push(me);
+                        }
+                        boost(self.key(), owner, &s.waiters);
+                        false
+                    }
+                }
+            });
+            if acquired {
+                return KMutexGuard { m: self };
+            }
+            block_current();
+        }
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn unlock(&self) {
+        without_interrupts(|| {
+            let mut s = self.state.lock();
+            if let Some(owner) = s.owner.take()
+                && owner != TaskId::MAX
+            {
+                release(self.key(), owner);
+            }
+            // Hand the wakeup to the most urgent waiter; it retries the lock.
+            let next = s.waiters.iter().copied().min_by_key(|&w| {
+                let prio = effective_priority(w).map_or(0, |p| p.rank());
+                (effective_deadline(w).unwrap_or(u64::MAX), u16::MAX - prio)
+            });
+            if let Some(w) = next {
+                wake(w);
+            }
+        });
+    }
+}
+
+/* ---------------------------------- Guard ----------------------------------- */
+
+impl<T> Deref for KMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.m.data.get() }
+    }
+}
+
+impl<T> DerefMut for KMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.m.data.get() }
+    }
+}
+
+impl<T> Drop for KMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.m.unlock();
+    }
+}
@@ -1,23 +1,47 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+pub mod bandwidth;
+pub mod edf;
 pub mod exec;
+pub mod executor;
+pub mod group;
+pub mod join;
+pub mod kmutex;
+pub mod prio;
 pub mod sched_simd;
+pub mod selftest;
+pub mod stack;
+pub mod waitq;
 
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use core::u32;
 
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
 use spin::Mutex;
-use x86_64::instructions::hlt;
-use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::{hlt, interrupts};
 
 extern crate alloc;
 
+use crate::arch::native::alternatives;
+use crate::arch::native::apic::{MAX_CPUS, cpu_index};
+use crate::arch::native::cet;
+use crate::arch::native::context::kthread_frame;
 use crate::arch::native::simd::{restore, save};
-use crate::arch::x86_64::tables::gdt::kernel_cs;
 use crate::debug::TrapFrame;
+use crate::debug::latency;
+use crate::debug::replay::{self, Marker};
+use crate::error::{KError, KResult};
+use crate::mem::slab::{Cache, SlabBox};
+use crate::sched::bandwidth::{Bandwidth, CpuLimit};
+use crate::sched::edf::{AdmissionError, DeadlineParams, DlEntity};
+use crate::sched::group::{Group, GroupId, ROOT_GROUP};
+use crate::sched::join::{Exit, JoinFn, JoinHandle, join_main};
+use crate::sched::prio::{Priority, RT_PERIOD};
 use crate::sched::sched_simd::SimdArea;
+use crate::sched::stack::{DEFAULT_STACK_SIZE, StackUsage, ThreadStack};
 
 /* ------------------------------- Types & consts ------------------------------- */
 
@@ -25,139 +49,416 @@ use crate::sched::sched_simd::SimdArea;
 pub enum TaskState {
     Ready,
     Running,
+    Blocked,
+    Sleeping,  // in `sleep_ms` until its deadline tick
+    Throttled, // over its CPU quota until the period refreshes
     Dead,
 }
 
 pub type TaskId = u64;
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Task {
     id: TaskId,
     state: TaskState,
     simd: SimdArea,
     time_slice: u32,
+    wake_pending: bool,
+    dl: Option<DlEntity>,         // deadline class; None = round-robin
+    boost: Option<u64>,           // deadline inherited from a KMutex waiter
+    prio: Priority,               // class and level outside the deadline class
+    prio_boost: Option<Priority>, // level inherited from a KMutex waiter
+    cap: Option<Bandwidth>,
+    group: GroupId,
+    mem_charged: u64,     // bytes charged to `group` on this task's behalf
+    cpu_ticks: u64,       // ticks spent Running
+    waiting: u64,         // ticks Ready since it last ran
+    max_wait: u64,        // longest `waiting` so far
+    bound: Option<usize>, // the only CPU that may run it
     trap: TrapFrame,
-    _stack: Box<ThreadStack>,
+    stack: Box<ThreadStack>,
+    exit: Option<Arc<Exit>>, // a JoinHandle's, finished by the reaper if still set
 }
 
 pub const DEFAULT_SLICE: u32 = 5; // 5ms at 1 kHz
+const IDLE_SLICE: u32 = u32::MAX; // marks the idle task: never sliced
+const STACK_CHECK_TICKS: u64 = 5_000;
+
+// Round-robin slice in ticks; the `sched.slice_ms` config key.
+static SLICE: AtomicU32 = AtomicU32::new(DEFAULT_SLICE);
+
+pub const CONFIG_KEYS: &[crate::config::Key] = &[
+    crate::config::Key {
+        name: "slice_ms",
+        get: || slice() as u64,
+        validate: |v| crate::config::in_range(v, 1, 1000),
+        apply: |v| set_slice(v as u32),
+    },
+    crate::config::Key {
+        name: "rt_runtime_ms",
+        get: prio::rt_runtime,
+        validate: |v| crate::config::in_range(v, 1, RT_PERIOD),
+        apply: prio::set_rt_runtime,
+    },
+];
+
+pub fn slice() -> u32 {
+    SLICE.load(Ordering::Relaxed)
+}
+
+/// New slice length; running tasks pick it up at their next refill.
+pub fn set_slice(ticks: u32) {
+    SLICE.store(ticks.max(1), Ordering::Relaxed);
+}
+/// Per-task scheduling counters, for tests and diagnostics.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TaskStats {
+    pub cpu_ticks: u64,
+    pub max_wait: u64, // longest run of ticks spent Ready without running
+}
+
+/// One task, for listings.
+#[derive(Copy, Clone, Debug)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub state: TaskState,
+    pub prio: Priority,
+    pub deadline: Option<u64>, // effective deadline, if in the deadline class
+    pub group: GroupId,
+    pub cpu_ticks: u64,
+    pub max_wait: u64,
+    pub idle: bool,
+}
 
 /* ----------------------------- Runqueue container ----------------------------- */
 
+// One runqueue shared by every CPU. `current` and `need_resched` are the
+// calling CPU's: `with_rq_locked` loads them from `cpus`/`resched` for
+// `cpu` and stores them back, so code under the lock reads like
+// uniprocessor code. A task only runs where it was picked until it next
+// switches; idle tasks are per CPU.
 struct RunQueue {
-    tasks: Vec<Box<Task>>,
+    tasks: Vec<SlabBox<Task>>,
     current: Option<usize>,
     next_id: TaskId,
     need_resched: bool,
+    cpu: usize,                       // the calling CPU's index
+    cpus: [Option<usize>; MAX_CPUS],  // what each CPU is running
+    resched: u64,                     // CPUs owed a re-pick, bit per index
+    idle: [Option<TaskId>; MAX_CPUS], // each CPU's idle task
+    dl_util: u64,                     // reserved deadline-class utilisation (edf::UTIL_SCALE)
+    groups: Vec<Group>,
+    next_group: GroupId,
+    next_stack_check: u64, // tick of the next stack high-water scan
+    rt_used: u64,          // Fifo-class ticks in the current RT period
+    rt_period_end: u64,
+    sleepers: Vec<(u64, TaskId)>, // (wake tick, task), latest first
 }
 
 static RQ: Mutex<Option<Box<RunQueue>>> = Mutex::new(None);
+// Task structs, a few KiB each with their SIMD area, come and go with
+// every spawn; they get their own cache instead of the general heap.
+static TASKS: Cache<Task> = Cache::new("task");
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// The CPU whose tick advances TICKS and does the global tick work (the
+// BSP); until `init` names it, whichever CPU ticks.
+static KEEPER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// What a CPU is running, as seen by `on_cpu`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OnCpu {
+    Idle,
+    Task(TaskId),
+}
+
+const ON_CPU_IDLE: u64 = u64::MAX;
+// Per CPU, for readers that cannot take RQ: 0 before its first switch,
+// ON_CPU_IDLE for the idle task, else the task id + 1.
+static ON_CPU: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+impl Task {
+    // Deadline tasks never run from the round-robin class, so a throttled
+    // one stays off the CPU until its budget is refilled.
+    fn rr_ready(&self) -> bool {
+        self.state == TaskState::Ready && self.dl.is_none()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.time_slice == IDLE_SLICE
+    }
+
+    /// Deadline EDF orders this task by: its own while it has budget, or an
+    /// inherited one if earlier.
+    fn effective_deadline(&self) -> Option<u64> {
+        let own = self
+            .dl
+            .as_ref()
+            .filter(|d| d.has_budget())
+            .map(|d| d.abs_deadline);
+        match (own, self.boost) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Class and level outside the deadline class: its own, or an
+    /// inherited one if higher.
+    fn effective_prio(&self) -> Priority {
+        match self.prio_boost {
+            Some(b) if b.rank() > self.prio.rank() => b,
+            _ => self.prio,
+        }
+    }
+}
 
 impl RunQueue {
-    fn pick_next(&self) -> Option<usize> {
+    /// Runnable deadline task with budget and the earliest deadline. The
+    /// current task still counts while it is Running.
+    fn pick_deadline(&self) -> Option<usize> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter(|(i, t)| self.runnable(*i) && t.effective_deadline().is_some())
+            .min_by_key(|(_, t)| t.effective_deadline())
+            .map(|(i, _)| i)
+    }
+
+    /// Best runnable task outside the deadline class with `keep(t)`, by
+    /// `key` (highest wins). Ties go to the first one after the current
+    /// task, so equals take turns and the current one goes last.
+    fn pick_by(&self, keep: impl Fn(&Task) -> bool, key: impl Fn(&Task) -> u64) -> Option<usize> {
         let n = self.tasks.len();
-        if n == 0 {
+        let start = self.current.map_or(0, |c| c + 1);
+        let mut best: Option<(usize, u64)> = None;
+        for i in (0..n).map(|k| (start + k) % n) {
+            let t = &self.tasks[i];
+            if !self.runnable(i) || t.dl.is_some() || t.is_idle() || !keep(t) {
+                continue;
+            }
+            let k = key(t);
+            if best.is_none_or(|(_, b)| k > b) {
+                best = Some((i, k));
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    fn rt_throttled(&self) -> bool {
+        self.rt_used >= prio::rt_runtime()
+    }
+
+    /// Deadline first; then the highest Fifo level, unless real-time is over
+    /// its budget for this period; then the normal task with the highest
+    /// aged level; then a throttled Fifo task rather than nothing; idle
+    /// only when nothing else can run.
+    fn pick_next(&self) -> Option<usize> {
+        if self.tasks.is_empty() {
             return None;
         }
-        if let Some(current) = self.current {
-            let start = (current + 1) % n;
-            let mut i = start;
-            loop {
-                if i != current && matches!(self.tasks[i].state, TaskState::Ready) {
-                    return Some(i);
-                }
-                i = (i + 1) % n;
-                if i == start {
-                    break;
-                }
+        if let Some(i) = self.pick_deadline() {
+            return Some(i);
+        }
+        let fifo = || {
+            self.pick_by(
+                |t| t.effective_prio().is_fifo(),
+                |t| t.effective_prio().level() as u64,
+            )
+        };
+        if !self.rt_throttled()
+            && let Some(i) = fifo()
+        {
+            return Some(i);
+        }
+        let normal = |t: &Task| !t.effective_prio().is_fifo();
+        if let Some(i) = self.pick_by(normal, |t| t.effective_prio().aged(t.waiting)) {
+            return Some(i);
+        }
+        if let Some(i) = fifo() {
+            return Some(i);
+        }
+        let idle = self.idle.get(self.cpu).copied().flatten()?;
+        let i = self.tasks.iter().position(|t| t.id == idle)?;
+        self.runnable(i).then_some(i)
+    }
+
+    /// A ready Fifo task outranks what is running now.
+    fn rt_preempts(&self) -> bool {
+        if self.rt_throttled() {
+            return false;
+        }
+        let floor = match self.current.map(|c| &self.tasks[c]) {
+            Some(t) if t.state == TaskState::Running && t.dl.is_some() => return false,
+            Some(t) if t.state == TaskState::Running && !t.is_idle() => match t.effective_prio() {
+                Priority::Fifo(l) => Some(l),
+                Priority::Normal(_) => None,
+            },
+            _ => None,
+        };
+        self.tasks.iter().any(|t| {
+            t.state == TaskState::Ready
+                && t.dl.is_none()
+                && self.may_run(t)
+                && matches!(t.effective_prio(), Priority::Fifo(l) if floor.is_none_or(|f| l > f))
+        })
+    }
+
+    /// Put `t` first, keeping every CPU's index of its current task valid.
+    fn insert_task(&mut self, t: SlabBox<Task>) {
+        self.tasks.insert(0, t);
+        for c in self.cpus.iter_mut().chain([&mut self.current]).flatten() {
+            *c += 1;
+        }
+    }
+
+    /// Drop `tasks[i]`, which no CPU may be running.
+    fn remove_task(&mut self, i: usize) -> SlabBox<Task> {
+        let task = self.tasks.remove(i);
+        for c in self.cpus.iter_mut().chain([&mut self.current]).flatten() {
+            if *c > i {
+                *c -= 1;
             }
-        } else {
-            for i in 0..n {
-                if matches!(self.tasks[i].state, TaskState::Ready) {
-                    return Some(i);
-                }
+        }
+        task
+    }
+
+    /// Take the lock's view for `cpu`: its current task and resched flag.
+    /// CPUs past MAX_CPUS never run tasks, and see none.
+    fn enter(&mut self, cpu: usize) {
+        self.cpu = cpu;
+        self.current = self.cpus.get(cpu).copied().flatten();
+        self.need_resched = cpu < MAX_CPUS && self.resched & (1 << cpu) != 0;
+    }
+
+    fn leave(&mut self) {
+        let cpu = self.cpu;
+        if let Some(slot) = self.cpus.get_mut(cpu) {
+            *slot = self.current;
+            if self.need_resched {
+                self.resched |= 1 << cpu;
+            } else {
+                self.resched &= !(1 << cpu);
             }
         }
-        let t0 = &self.tasks[0];
-        if matches!(t0.state, TaskState::Ready) {
-            return Some(0);
+    }
+
+    fn on_some_cpu(&self, i: usize) -> bool {
+        self.current == Some(i) || self.cpus.contains(&Some(i))
+    }
+
+    /// `tasks[i]` is still some other CPU's current task: blocked or woken
+    /// on its way off, but still on that CPU's stack until it switches.
+    fn elsewhere(&self, i: usize) -> bool {
+        let cpu = self.cpu;
+        self.cpus
+            .iter()
+            .enumerate()
+            .any(|(c, &x)| c != cpu && x == Some(i))
+    }
+
+    /// `t` is not bound to some other CPU.
+    fn may_run(&self, t: &Task) -> bool {
+        t.bound.is_none_or(|c| c == self.cpu)
+    }
+
+    /// Whether this CPU may pick `tasks[i]`: Ready, not bound elsewhere and
+    /// not still on another CPU, or its own current task still Running.
+    fn runnable(&self, i: usize) -> bool {
+        let t = &self.tasks[i];
+        if !self.may_run(t) {
+            false
+        } else if Some(i) == self.current {
+            t.state == TaskState::Running || t.state == TaskState::Ready
+        } else {
+            t.state == TaskState::Ready && !self.elsewhere(i)
         }
-        None
     }
-}
 
-/* Thread Stack */
-#[derive(Clone, Debug)]
-struct ThreadStack {
-    dump: Box<[u8]>,
-}
+    /// Have every CPU pick again at its next tick: for changes that may
+    /// matter to a CPU other than this one.
+    fn resched_all(&mut self) {
+        self.resched = u64::MAX;
+        self.need_resched = true;
+    }
 
-impl ThreadStack {
-    fn new() -> Self {
-        const STACK_SIZE: usize = 0x4_0000;
-        let dump = vec![0u8; STACK_SIZE].into_boxed_slice();
-        ThreadStack { dump }
+    /// Any task other than the current one that round-robin would pick.
+    fn others_ready(&self) -> bool {
+        self.tasks.iter().enumerate().any(|(i, t)| {
+            Some(i) != self.current
+                && t.rr_ready()
+                && !t.is_idle()
+                && self.may_run(t)
+                && !self.elsewhere(i)
+        })
     }
 }
 
 /* --------------------------------- Utilities --------------------------------- */
 
+fn new_task(t: Task) -> SlabBox<Task> {
+    TASKS.alloc(t).expect("task: out of memory")
+}
+
 extern "C" fn idle_main(_arg: usize) -> ! {
     loop {
-        hlt();
+        alternatives::idle_wait();
     }
 }
 
 /* --------------------------------- Init path --------------------------------- */
 
-unsafe extern "C" {
-    unsafe fn kthread_trampoline() -> !;
-}
-
-pub fn init() {
-    let mut stack = Box::new(ThreadStack::new());
-    let dump = stack.as_mut().dump.as_mut();
-    let stack_ptr: *mut u8 = &raw mut dump[dump.len() - 1];
-    let top_aligned = ((stack_ptr as usize) & !0xF) as u64; // 16-align
-    let frame = (top_aligned - 16) as *mut u64; // space for [arg][entry]
-    unsafe {
-        core::ptr::write(frame.add(0), 0 as u64);
-        core::ptr::write(frame.add(1), idle_main as u64);
-    }
+// Give the calling CPU its idle task, which runs there and nowhere else.
+fn spawn_idle() -> TaskId {
+    let mut stack = Box::new(ThreadStack::new(DEFAULT_STACK_SIZE).expect("idle stack"));
+    let trap = unsafe { kthread_frame(stack.top(), idle_main, 0) };
+    let stack_bytes = stack.size() as u64;
     with_rq_locked(|rq| {
         let id = rq.next_id;
         rq.next_id += 1;
-        rq.tasks.insert(
-            0,
-            Box::new(Task {
-                id,
-                state: TaskState::Ready,
-                simd: SimdArea {
-                    dump: [0; sched_simd::SIZE],
-                },
-                trap: TrapFrame {
-                    rip: kthread_trampoline as u64,
-                    rsp: frame as u64,
-                    cs: kernel_cs() as u64,
-                    rflags: 0x202,
-                    ss: 0,
-                    ..TrapFrame::default()
-                },
-                time_slice: DEFAULT_SLICE,
-                _stack: stack,
-            }),
-        );
-    });
+        rq.charge_mem(ROOT_GROUP, stack_bytes as i64);
+        rq.insert_task(new_task(Task {
+            id,
+            state: TaskState::Ready,
+            simd: SimdArea {
+                dump: [0; sched_simd::SIZE],
+            },
+            trap,
+            time_slice: IDLE_SLICE,
+            wake_pending: false,
+            dl: None,
+            boost: None,
+            prio: Priority::DEFAULT,
+            prio_boost: None,
+            cap: None,
+            group: ROOT_GROUP,
+            mem_charged: stack_bytes,
+            cpu_ticks: 0,
+            waiting: 0,
+            max_wait: 0,
+            bound: None,
+            stack,
+            exit: None,
+        }));
+        if let Some(slot) = rq.idle.get_mut(rq.cpu) {
+            *slot = Some(id);
+        }
+        id
+    })
+}
+
+pub fn init() {
+    KEEPER.store(cpu_index(), Ordering::Relaxed);
+    spawn_idle();
+    crate::debug::watch::export("sched.ticks", TICKS.as_ptr() as u64, 8);
     spawn(|| {
         loop {
-            for _ in 0..1000 {
-                yield_now();
-            }
-            with_rq_locked(|rq| {
-                let tasks: &mut Vec<Box<Task>> = rq.tasks.as_mut();
+            yield_now();
+            let (exits, reaped) = with_rq_locked(|rq| {
                 let mut deads = Vec::<u64>::new();
-                for task in tasks.iter_mut() {
+                let mut exits = Vec::new();
+                let mut reaped = Vec::new();
+                for task in rq.tasks.iter_mut() {
                     if task.state == TaskState::Dead {
+                        exits.extend(task.exit.take());
                         if task.time_slice == 0 {
                             deads.insert(0, task.id);
                         } else {
@@ -166,17 +467,42 @@ pub fn init() {
                     }
                 }
                 for id in deads {
-                    let mut i = 0;
-                    while id == tasks[i].id {
-                        i += 1;
+                    let Some(i) = rq.tasks.iter().position(|t| t.id == id) else {
+                        continue;
+                    };
+                    // Still on its way off another CPU: next time round.
+                    if !rq.on_some_cpu(i) {
+                        reaped.push(rq.remove_task(i));
                     }
-                    tasks.remove(i);
                 }
+                if ticks() >= rq.next_stack_check {
+                    rq.next_stack_check = ticks() + STACK_CHECK_TICKS;
+                    stack::check_all(rq);
+                }
+                (exits, reaped)
             });
+            // Freeing a stack shoots its pages down on every CPU, which
+            // cannot wait under the runqueue lock.
+            drop(reaped);
+            // Joiners of tasks that died without returning; waking takes RQ.
+            for e in exits {
+                e.finish();
+            }
         }
     });
 }
 
+/// Let the calling AP run tasks from the shared runqueue: give it an idle
+/// task, and its timer ticks switch it into whatever is ready. False on a
+/// CPU past MAX_CPUS, which stays out of scheduling.
+pub fn init_ap() -> bool {
+    if cpu_index() >= MAX_CPUS {
+        return false;
+    }
+    spawn_idle();
+    true
+}
+
 struct ThreadFn<F>
 where
     F: FnOnce() -> (),
@@ -195,39 +521,191 @@ where
 
 /* ------------------------------- Public API ---------------------------------- */
 
-pub fn spawn<F>(func: F)
+/// Start `func` on a new thread; the handle can wait for its result.
+/// Dropping the handle lets the thread run on, detached.
+pub fn spawn<F, T>(func: F) -> JoinHandle<T>
 where
-    F: FnOnce() -> (),
+    F: FnOnce() -> T,
+{
+    let (arg, exit, result) = JoinFn::new(func);
+    let id = spawn_kthread(
+        join_main::<F, T>,
+        Box::into_raw(arg) as usize,
+        None,
+        DEFAULT_STACK_SIZE,
+    );
+    // Killed before we got here: nobody else will finish it.
+    let attached = with_rq_locked(|rq| {
+        let t = rq
+            .tasks
+            .iter_mut()
+            .find(|t| t.id == id && t.state != TaskState::Dead)?;
+        t.exit = Some(exit.clone());
+        Some(())
+    });
+    if attached.is_none() {
+        exit.finish();
+    }
+    JoinHandle::new(id, exit, result)
+}
+
+/// `spawn` with a stack of `bytes` (rounded to pages and clamped to
+/// MIN_STACK_SIZE..=MAX_STACK_SIZE) instead of DEFAULT_STACK_SIZE.
+pub fn spawn_with_stack_size<F>(bytes: usize, func: F) -> TaskId
+where
+    F: FnOnce(),
+{
+    let arg = Box::new(ThreadFn { func });
+    spawn_kthread(
+        thread_main::<F>,
+        Box::into_raw(arg) as usize,
+        None,
+        stack::clamp_size(bytes),
+    )
+}
+
+/// Spawn a thread in the deadline class. Fails without spawning if the
+/// parameters are invalid or the CPU is already fully reserved.
+pub fn spawn_deadline<F>(params: DeadlineParams, func: F) -> Result<TaskId, AdmissionError>
+where
+    F: FnOnce(),
+{
+    with_rq_locked(|rq| {
+        rq.dl_util = edf::admit(rq.dl_util, &params)?;
+        Ok(())
+    })?;
+    let arg = Box::new(ThreadFn { func });
+    Ok(spawn_kthread(
+        thread_main::<F>,
+        Box::into_raw(arg) as usize,
+        Some(params),
+        DEFAULT_STACK_SIZE,
+    ))
+}
+
+/// `spawn` at `prio`. InvalidArg on an invalid priority.
+pub fn spawn_with_priority<F>(prio: Priority, func: F) -> KResult<TaskId>
+where
+    F: FnOnce(),
 {
+    if !prio.is_valid() {
+        return Err(KError::InvalidArg);
+    }
     let arg = Box::new(ThreadFn { func });
-    spawn_kthread(thread_main::<F>, Box::into_raw(arg) as usize);
+    Ok(spawn_task(
+        thread_main::<F>,
+        Box::into_raw(arg) as usize,
+        None,
+        prio,
+        DEFAULT_STACK_SIZE,
+    ))
 }
 
-fn spawn_kthread(entry: extern "C" fn(usize) -> !, arg: usize) -> TaskId {
-    let mut stack = Box::new(ThreadStack::new());
-    let dump = stack.as_mut().dump.as_mut();
-    let stack_ptr: *mut u8 = &raw mut dump[dump.len() - 1];
-    let top_aligned = ((stack_ptr as usize) & !0xF) as u64;
-    let frame = (top_aligned - 16) as *mut u64;
-    unsafe {
-        core::ptr::write(frame.add(0), arg as u64);
-        core::ptr::write(frame.add(1), entry as u64);
+/// Change task `id`'s class or level. InvalidArg if the priority is
+/// invalid, NotFound if there is no such task. Deadline tasks keep it for
+/// when they leave that class.
+pub fn set_priority(id: TaskId, prio: Priority) -> KResult<()> {
+    if !prio.is_valid() {
+        return Err(KError::InvalidArg);
     }
-    let mut element = Box::new(Task {
+    with_rq_locked(|rq| {
+        let t = rq
+            .tasks
+            .iter_mut()
+            .find(|t| t.id == id && t.state != TaskState::Dead && !t.is_idle())
+            .ok_or(KError::NotFound)?;
+        t.prio = prio;
+        rq.resched_all();
+        Ok(())
+    })
+}
+
+/// Keep task `id` on CPU `cpu` (an APIC id), or let it run anywhere again
+/// with `None`. A running task moves at its CPU's next tick. NotFound if
+/// there is no such task.
+pub fn bind(id: TaskId, cpu: Option<u32>) -> KResult<()> {
+    with_rq_locked(|rq| {
+        let t = rq
+            .tasks
+            .iter_mut()
+            .find(|t| t.id == id && t.state != TaskState::Dead && !t.is_idle())
+            .ok_or(KError::NotFound)?;
+        t.bound = cpu.map(|c| c as usize);
+        rq.resched_all();
+        Ok(())
+    })
+}
+
+/// Class and level of task `id`.
+pub fn priority(id: TaskId) -> Option<Priority> {
+    with_rq_locked(|rq| rq.tasks.iter().find(|t| t.id == id).map(|t| t.prio))
+}
+
+/// Move task `id` into the deadline class (`Some`) or back to round-robin.
+pub fn set_deadline(id: TaskId, params: Option<DeadlineParams>) -> Result<(), AdmissionError> {
+    let now = ticks();
+    with_rq_locked(|rq| {
+        let i = rq
+            .tasks
+            .iter()
+            .position(|t| t.id == id && t.state != TaskState::Dead)
+            .ok_or(AdmissionError::NoTask)?;
+        let old = rq.tasks[i]
+            .dl
+            .as_ref()
+            .map_or(0, |d| d.params.utilisation());
+        let util = match &params {
+            Some(p) => edf::admit(rq.dl_util - old, p)?,
+            None => rq.dl_util - old,
+        };
+        rq.dl_util = util;
+        rq.tasks[i].dl = params.map(|p| DlEntity::new(p, now));
+        rq.resched_all();
+        Ok(())
+    })
+}
+
+// `dl` must already have been admitted; `stack_size` must be page-rounded.
+pub(crate) fn spawn_kthread(
+    entry: extern "C" fn(usize) -> !,
+    arg: usize,
+    dl: Option<DeadlineParams>,
+    stack_size: usize,
+) -> TaskId {
+    spawn_task(entry, arg, dl, Priority::DEFAULT, stack_size)
+}
+
+fn spawn_task(
+    entry: extern "C" fn(usize) -> !,
+    arg: usize,
+    dl: Option<DeadlineParams>,
+    prio: Priority,
+    stack_size: usize,
+) -> TaskId {
+    let mut stack = Box::new(ThreadStack::new(stack_size).expect("kthread stack: out of memory"));
+    let trap = unsafe { kthread_frame(stack.top(), entry, arg) };
+    let stack_bytes = stack.size() as u64;
+    let mut element = new_task(Task {
         state: TaskState::Ready,
         simd: SimdArea {
             dump: [0; sched_simd::SIZE],
         },
-        trap: TrapFrame {
-            rip: kthread_trampoline as u64,
-            rsp: frame as u64,
-            cs: kernel_cs() as u64,
-            rflags: 0x202,
-            ss: 0,
-            ..TrapFrame::default()
-        },
-        time_slice: DEFAULT_SLICE,
-        _stack: stack,
+        trap,
+        time_slice: slice(),
+        wake_pending: false,
+        dl: dl.map(|p| DlEntity::new(p, ticks())),
+        boost: None,
+        prio,
+        prio_boost: None,
+        cap: None,
+        group: ROOT_GROUP,
+        mem_charged: stack_bytes,
+        cpu_ticks: 0,
+        waiting: 0,
+        max_wait: 0,
+        bound: None,
+        stack,
+        exit: None,
         id: 0,
     });
 
@@ -235,46 +713,381 @@ fn spawn_kthread(entry: extern "C" fn(usize) -> !, arg: usize) -> TaskId {
         let id = rq.next_id;
         element.id = id;
         rq.next_id += 1;
-        rq.tasks.insert(0, element);
-        if let Some(current) = rq.current {
-            *rq.current.as_mut().unwrap() = current + 1;
+        element.group = rq.current.map_or(ROOT_GROUP, |c| rq.tasks[c].group);
+        rq.charge_mem(element.group, stack_bytes as i64);
+        rq.insert_task(element);
+        if dl.is_some() || prio.is_fifo() {
+            rq.resched_all();
         }
         id
     })
 }
 
-pub fn yield_now() {}
+/// Give up the rest of the slice if another task is ready to run. Returns
+/// once this task has been switched back in (or at the next tick, if the
+/// others went away meanwhile).
+pub fn yield_now() {
+    // Nothing could switch us back in.
+    if !interrupts::are_enabled() {
+        return;
+    }
+    let yielded = with_rq_locked(|rq| {
+        if rq.current.is_none() || !rq.others_ready() {
+            return false;
+        }
+        rq.need_resched = true;
+        true
+    });
+    if yielded {
+        crate::counter!("sched.yields");
+        hlt();
+    }
+}
+
+pub fn current_id() -> Option<TaskId> {
+    with_rq_locked(|rq| rq.current.map(|c| rq.tasks[c].id))
+}
+
+/// What the CPU with APIC id `cpu` is running, without taking the runqueue
+/// lock: usable from ISRs and under RQ (the log prefix). None before its
+/// first switch.
+pub fn on_cpu(cpu: u32) -> Option<OnCpu> {
+    match ON_CPU.get(cpu as usize)?.load(Ordering::Relaxed) {
+        0 => None,
+        ON_CPU_IDLE => Some(OnCpu::Idle),
+        n => Some(OnCpu::Task(n - 1)),
+    }
+}
+
+/// Sleep until `wake()` is called for the current task. A wake that arrived
+/// before we got here is consumed instead. Callers re-check their condition.
+pub fn block_current() {
+    let Some(id) = with_rq_locked(|rq| {
+        let cur = rq.current?;
+        let t = rq.tasks[cur].as_mut();
+        if core::mem::take(&mut t.wake_pending) {
+            return None;
+        }
+        t.state = TaskState::Blocked;
+        rq.need_resched = true;
+        Some(t.id)
+    }) else {
+        return;
+    };
+    // The next tick switches away; we only get past here once woken.
+    while task_state(id) == Some(TaskState::Blocked) {
+        hlt();
+    }
+}
+
+/// Sleep for at least `ms` milliseconds (scheduler ticks). Other tasks run
+/// meanwhile; the tick wakes us once the deadline has passed. `wake()` does
+/// not cut a sleep short. Before the scheduler runs tasks, waits in `hlt`.
+pub fn sleep_ms(ms: u64) {
+    if ms == 0 {
+        yield_now();
+        return;
+    }
+    let deadline = ticks() + ms;
+    let Some(id) = with_rq_locked(|rq| {
+        let cur = rq.current?;
+        let t = rq.tasks[cur].as_mut();
+        if t.is_idle() {
+            return None;
+        }
+        t.state = TaskState::Sleeping;
+        let id = t.id;
+        // Latest first, so the tick pops expired sleepers off the end; the
+        // tick never has to grow this.
+        let at = rq.sleepers.partition_point(|&(d, _)| d > deadline);
+        rq.sleepers.insert(at, (deadline, id));
+        rq.need_resched = true;
+        Some(id)
+    }) else {
+        while ticks() < deadline {
+            hlt();
+        }
+        return;
+    };
+    crate::counter!("sched.sleeps");
+    while task_state(id) == Some(TaskState::Sleeping) {
+        hlt();
+    }
+}
 
+/// Make a blocked task runnable (IRQ-safe). If it is not blocked yet, the
+/// wake is remembered so its next `block_current()` returns immediately.
+pub fn wake(id: TaskId) {
+    with_rq_locked(|rq| {
+        if let Some(t) = rq.tasks.iter_mut().find(|t| t.id == id) {
+            match t.state {
+                TaskState::Blocked => t.state = TaskState::Ready,
+                TaskState::Dead => {}
+                _ => t.wake_pending = true,
+            }
+        }
+    });
+}
+
+/// Deadline the task is currently scheduled by, if it is in (or boosted
+/// into) the deadline class.
+pub fn effective_deadline(id: TaskId) -> Option<u64> {
+    with_rq_locked(|rq| rq.tasks.iter().find(|t| t.id == id)?.effective_deadline())
+}
+
+/// Class and level the task is scheduled by outside the deadline class,
+/// an inherited one included.
+pub fn effective_priority(id: TaskId) -> Option<Priority> {
+    with_rq_locked(|rq| {
+        rq.tasks
+            .iter()
+            .find(|t| t.id == id)
+            .map(|t| t.effective_prio())
+    })
+}
+
+/// Priority inheritance hook: schedule `id` by `deadline` and at least at
+/// `prio` (or stop doing so).
+pub fn set_boost(id: TaskId, deadline: Option<u64>, prio: Option<Priority>) {
+    with_rq_locked(|rq| {
+        if let Some(t) = rq.tasks.iter_mut().find(|t| t.id == id)
+            && (t.boost != deadline || t.prio_boost != prio)
+        {
+            t.boost = deadline;
+            t.prio_boost = prio;
+            rq.resched_all();
+        }
+    });
+}
+
+/// Cap task `id` to `limit` (or lift the cap). An invalid limit is ignored.
+pub fn set_cpu_limit(id: TaskId, limit: Option<CpuLimit>) -> bool {
+    if limit.is_some_and(|l| !l.is_valid()) {
+        return false;
+    }
+    let now = ticks();
+    with_rq_locked(|rq| {
+        let Some(t) = rq.tasks.iter_mut().find(|t| t.id == id) else {
+            return false;
+        };
+        t.cap = limit.map(|l| Bandwidth::new(l, now));
+        if limit.is_none() && t.state == TaskState::Throttled {
+            t.state = TaskState::Ready;
+        }
+        true
+    })
+}
+
+/// Deepest stack use task `id` has reached so far.
+pub fn stack_usage(id: TaskId) -> Option<StackUsage> {
+    with_rq_locked(|rq| rq.stack_usage(id))
+}
+
+fn task_state(id: TaskId) -> Option<TaskState> {
+    with_rq_locked(|rq| rq.tasks.iter().find(|t| t.id == id).map(|t| t.state))
+}
+
+/// CPU time and worst wait of task `id` so far.
+pub fn task_stats(id: TaskId) -> Option<TaskStats> {
+    with_rq_locked(|rq| {
+        let t = rq.tasks.iter().find(|t| t.id == id)?;
+        Some(TaskStats {
+            cpu_ticks: t.cpu_ticks,
+            max_wait: t.max_wait,
+        })
+    })
+}
+
+/// Live tasks, not counting idle.
+pub fn task_count() -> usize {
+    with_rq_locked(|rq| {
+        rq.tasks
+            .iter()
+            .filter(|t| t.state != TaskState::Dead && !t.is_idle())
+            .count()
+    })
+}
+
+/// Call `f` for every task, idle included. Does not wait for the runqueue
+/// lock: false if it was busy (from a debugger stop, say).
+pub fn for_each_task(mut f: impl FnMut(&TaskInfo)) -> bool {
+    interrupts::without_interrupts(|| {
+        let Some(guard) = RQ.try_lock() else {
+            return false;
+        };
+        for t in guard.as_ref().map_or(&[][..], |rq| &rq.tasks[..]) {
+            f(&TaskInfo {
+                id: t.id,
+                state: t.state,
+                prio: t.effective_prio(),
+                deadline: t.effective_deadline(),
+                group: t.group,
+                cpu_ticks: t.cpu_ticks,
+                max_wait: t.max_wait,
+                idle: t.is_idle(),
+            });
+        }
+        true
+    })
+}
+
+/// Timer ticks since the scheduler started (1 kHz).
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+// Deadline bookkeeping for one tick: refill budgets (on the keeper), charge
+// the running deadline task, and ask for a switch when EDF would now pick
+// differently.
+fn tick_deadline(rq: &mut RunQueue, now: u64, keeper: bool) {
+    let mut refilled = false;
+    for t in rq.tasks.iter_mut().filter(|_| keeper).map(|t| t.as_mut()) {
+        if let Some(d) = t.dl.as_mut() {
+            if d.check_miss(now) {
+                replay::mark(Marker::DeadlineMiss, t.id);
+            }
+            refilled |= d.replenish(now);
+        }
+    }
+    if let Some(cur) = rq.current
+        && rq.tasks[cur].state == TaskState::Running
+        && let Some(d) = rq.tasks[cur].dl.as_mut()
+        && d.charge()
+    {
+        rq.need_resched = true;
+    }
+    if refilled {
+        rq.resched_all();
+    }
+}
+
+// Per-task accounting for one tick: charge the running task, age the ready
+// ones, and count ticks where idle ran although a task had been waiting
+// since before the previous tick.
+fn tick_accounting(rq: &mut RunQueue) {
+    let mut starved = false;
+    for t in rq.tasks.iter_mut() {
+        match t.state {
+            TaskState::Running => t.cpu_ticks += 1,
+            TaskState::Ready => {
+                t.waiting += 1;
+                t.max_wait = t.max_wait.max(t.waiting);
+                starved |= t.waiting > 1 && t.dl.is_none() && !t.is_idle();
+            }
+            _ => {}
+        }
+    }
+    if starved && rq.current.is_some_and(|c| rq.tasks[c].is_idle()) {
+        crate::counter!("sched.idle_while_ready");
+    }
+}
+
+// Wake sleepers whose deadline has come. Ones that died meanwhile are
+// just dropped.
+fn tick_sleepers(rq: &mut RunQueue, now: u64) {
+    while let Some(&(deadline, id)) = rq.sleepers.last() {
+        if deadline > now {
+            break;
+        }
+        rq.sleepers.pop();
+        if let Some(t) = rq.tasks.iter_mut().find(|t| t.id == id)
+            && t.state == TaskState::Sleeping
+        {
+            t.state = TaskState::Ready;
+        }
+    }
+}
+
+// Real-time throttling for one tick: roll the RT period over (on the
+// keeper), and charge the running Fifo task to it; once the budget is
+// spent, reschedule so waiting normal tasks get the rest of the period.
+fn tick_rt(rq: &mut RunQueue, now: u64, keeper: bool) {
+    if keeper && now >= rq.rt_period_end {
+        rq.rt_period_end = now + RT_PERIOD;
+        rq.rt_used = 0;
+    }
+    if let Some(cur) = rq.current {
+        let t = &rq.tasks[cur];
+        if t.state == TaskState::Running && t.dl.is_none() && t.effective_prio().is_fifo() {
+            rq.rt_used += 1;
+            if rq.rt_throttled() {
+                rq.need_resched = true;
+            }
+        }
+    }
+}
+
+// Bandwidth caps for one tick: refill quotas (on the keeper, un-throttling
+// tasks whose period rolled over) and throttle the running task once it is
+// over quota.
+fn tick_bandwidth(rq: &mut RunQueue, now: u64, keeper: bool) {
+    for t in rq.tasks.iter_mut().filter(|_| keeper) {
+        if let Some(b) = t.cap.as_mut()
+            && b.refresh(now)
+            && t.state == TaskState::Throttled
+        {
+            t.state = TaskState::Ready;
+        }
+    }
+    if let Some(cur) = rq.current {
+        let t = rq.tasks[cur].as_mut();
+        if t.state == TaskState::Running
+            && let Some(b) = t.cap.as_mut()
+            && b.charge()
+        {
+            t.state = TaskState::Throttled;
+            rq.need_resched = true;
+        }
+    }
+}
+
+// Every CPU's timer ends up here. The keeper's tick also advances TICKS and
+// does the work that is once per tick rather than once per CPU: accounting,
+// sleepers, and refilling budgets. Each CPU charges its own current task.
 pub fn tick(tf: TrapFrame) -> TrapFrame {
+    let me = cpu_index();
+    if me >= MAX_CPUS {
+        return tf;
+    }
+    let keeper = KEEPER
+        .try_update(Ordering::Relaxed, Ordering::Relaxed, |k| {
+            (k == usize::MAX).then_some(me)
+        })
+        .map_or_else(|k| k == me, |_| true);
+    let now = if keeper {
+        TICKS.fetch_add(1, Ordering::Relaxed) + 1
+    } else {
+        ticks()
+    };
     let Some(ntf) = with_rq_locked(|rq| {
+        if keeper {
+            tick_accounting(rq);
+            tick_sleepers(rq, now);
+        }
+        tick_deadline(rq, now, keeper);
+        tick_bandwidth(rq, now, keeper);
+        tick_rt(rq, now, keeper);
+        group::tick_groups(rq, now, keeper);
         let extra: bool;
         if let Some(current) = rq.current {
+            // Blocked, killed or throttled from another CPU.
+            if rq.tasks[current].state != TaskState::Running {
+                rq.need_resched = true;
+            }
             {
+                let slice = rq.slice_for(rq.tasks[current].group);
                 let t = rq.tasks[current].as_mut();
-                if t.time_slice != u32::MAX && t.time_slice > 0 {
+                let fifo = t.effective_prio().is_fifo();
+                if t.dl.is_none() && !t.is_idle() && !fifo && t.time_slice > 0 {
                     t.time_slice -= 1;
                     if t.time_slice == 0 {
-                        t.time_slice = DEFAULT_SLICE;
+                        t.time_slice = slice;
                         rq.need_resched = true;
                     }
                 }
             }
 
-            let cur_is_idle;
-            {
-                let t = rq.tasks[current].as_mut();
-                cur_is_idle = t.time_slice == u32::MAX;
-            }
-
-            let some_ready;
-            {
-                some_ready = rq
-                    .tasks
-                    .iter()
-                    .enumerate()
-                    .any(|(i, t)| i != current && t.state == TaskState::Ready)
-            }
-            extra = cur_is_idle && some_ready;
+            extra = (rq.tasks[current].is_idle() && rq.others_ready()) || rq.rt_preempts();
         } else {
             rq.need_resched = true;
             extra = true;
@@ -292,17 +1105,35 @@ pub fn tick(tf: TrapFrame) -> TrapFrame {
                 }
             }
             if let Some(current) = rq.current {
+                let slice = rq.slice_for(rq.tasks[current].group);
                 let t = rq.tasks[current].as_mut();
-                t.state = TaskState::Ready;
-                if t.time_slice != u32::MAX {
-                    t.time_slice = DEFAULT_SLICE;
+                if t.state == TaskState::Running {
+                    t.state = TaskState::Ready;
+                }
+                if !t.is_idle() {
+                    t.time_slice = slice;
                 }
                 save(rq.tasks[current].simd.as_mut_ptr());
                 rq.tasks[current].trap = tf;
             }
             rq.need_resched = false;
+            if rq.current != Some(next_idx) {
+                crate::counter!("sched.switches");
+                if let Some(ssp) = rq.tasks[next_idx].stack.ssp() {
+                    let save = rq.current.and_then(|c| rq.tasks[c].stack.ssp_slot());
+                    cet::switch_to(save, ssp);
+                }
+            }
             rq.tasks[next_idx].as_mut().state = TaskState::Running;
+            rq.tasks[next_idx].as_mut().waiting = 0;
             rq.current = Some(next_idx);
+            if let Some(slot) = ON_CPU.get(cpu_index()) {
+                let t = &rq.tasks[next_idx];
+                slot.store(
+                    if t.is_idle() { ON_CPU_IDLE } else { t.id + 1 },
+                    Ordering::Relaxed,
+                );
+            }
 
             restore(rq.tasks[next_idx].simd.as_mut_ptr());
             Some(rq.tasks[next_idx].trap)
@@ -325,13 +1156,33 @@ pub fn exit_current() -> ! {
 fn kill_current() {
     with_rq_locked(|rq| {
         if let Some(current) = rq.current {
-            let task = rq.tasks[current].as_mut();
-            task.state = TaskState::Dead;
-            task.time_slice = DEFAULT_SLICE * 2;
+            let id = rq.tasks[current].id;
+            rq.kill_task(id);
         }
     });
 }
 
+impl RunQueue {
+    /// Mark a task Dead (the reaper frees it later) and drop what it holds
+    /// in deadline and group accounting.
+    fn kill_task(&mut self, id: TaskId) {
+        let Some(i) = self.tasks.iter().position(|t| t.id == id) else {
+            return;
+        };
+        let task = self.tasks[i].as_mut();
+        task.state = TaskState::Dead;
+        task.time_slice = DEFAULT_SLICE * 2;
+        let (group, mem) = (task.group, core::mem::take(&mut task.mem_charged));
+        if let Some(d) = task.dl.take() {
+            self.dl_util -= d.params.utilisation();
+        }
+        self.charge_mem(group, -(mem as i64));
+        if Some(i) == self.current {
+            self.need_resched = true;
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn sched_exit_current_trampoline() -> ! {
     exit_current()
@@ -339,24 +1190,42 @@ pub extern "C" fn sched_exit_current_trampoline() -> ! {
 
 /* ------------------------------- Helper wrapper ------------------------------ */
 
+// Traced by the latency tracer, charged to our caller.
+#[track_caller]
 fn with_rq_locked<F, R>(f: F) -> R
 where
     F: FnOnce(&mut RunQueue) -> R,
 {
-    without_interrupts(|| {
+    latency::without_interrupts(|| {
         let mut guard = RQ.lock();
         let op = guard.as_mut();
         let ret;
         if let Some(rq) = op {
+            rq.enter(cpu_index());
             ret = f(rq.as_mut());
+            rq.leave();
         } else {
-            *guard = Some(Box::new(RunQueue {
+            let mut rq = Box::new(RunQueue {
                 tasks: Vec::new(),
                 current: None,
                 next_id: 0,
                 need_resched: true,
-            }));
-            ret = f(guard.as_mut().unwrap().as_mut());
+                cpu: 0,
+                cpus: [None; MAX_CPUS],
+                resched: u64::MAX,
+                idle: [None; MAX_CPUS],
+                dl_util: 0,
+                groups: vec![Group::root()],
+                next_group: ROOT_GROUP + 1,
+                next_stack_check: 0,
+                rt_used: 0,
+                rt_period_end: RT_PERIOD,
+                sleepers: Vec::new(),
+            });
+            rq.enter(cpu_index());
+            ret = f(rq.as_mut());
+            rq.leave();
+            *guard = Some(rq);
         }
         drop(guard);
         ret
@@ -0,0 +1,84 @@
+// src/sched/prio.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Priority classes below the deadline class:
+//   - Fifo(1..=99), real-time: the highest ready level runs, without a
+//     slice, until it blocks or yields (to its equals) or a higher level
+//     wakes. All of them together may use `rt_runtime_ms` of every
+//     RT_PERIOD ticks while normal tasks are waiting; past that the normal
+//     class gets the rest of the period.
+//   - Normal(0..=39), round-robin by slice: the ready task with the highest
+//     level plus one per AGE_TICKS it has waited goes next, so a low level
+//     only waits longer, never forever.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const NORMAL_MAX: u8 = 39;
+pub const NORMAL_DEFAULT: u8 = 20;
+pub const FIFO_MAX: u8 = 99;
+pub const RT_PERIOD: u64 = 1000; // ticks
+pub const AGE_TICKS: u64 = 10; // waiting ticks per level of normal-class aging
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Priority {
+    Normal(u8),
+    Fifo(u8),
+}
+
+// Real-time ticks allowed per RT_PERIOD; the `sched.rt_runtime_ms` key.
+static RT_RUNTIME: AtomicU32 = AtomicU32::new(950);
+
+/* --------------------------------- Priority --------------------------------- */
+
+impl Priority {
+    pub const DEFAULT: Priority = Priority::Normal(NORMAL_DEFAULT);
+
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Priority::Normal(l) => l <= NORMAL_MAX,
+            Priority::Fifo(l) => (1..=FIFO_MAX).contains(&l),
+        }
+    }
+
+    pub fn is_fifo(&self) -> bool {
+        matches!(self, Priority::Fifo(_))
+    }
+
+    pub fn level(&self) -> u8 {
+        match *self {
+            Priority::Normal(l) | Priority::Fifo(l) => l,
+        }
+    }
+
+    /// Order across classes: every Fifo level above every Normal one.
+    pub fn rank(&self) -> u16 {
+        match *self {
+            Priority::Normal(l) => l as u16,
+            Priority::Fifo(l) => 0x100 | l as u16,
+        }
+    }
+
+    /// Normal-class order: the level, raised by time spent waiting.
+    pub fn aged(&self, waiting: u64) -> u64 {
+        self.level() as u64 + waiting / AGE_TICKS
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn rt_runtime() -> u64 {
+    RT_RUNTIME.load(Ordering::Relaxed) as u64
+}
+
+/// Real-time budget per RT_PERIOD; RT_PERIOD turns throttling off.
+pub fn set_rt_runtime(ticks: u64) {
+    RT_RUNTIME.store(ticks.clamp(1, RT_PERIOD) as u32, Ordering::Relaxed);
+}
@@ -0,0 +1,592 @@
+// src/sched/selftest.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Scheduler fairness and starvation test, run at the end of boot with
+// `schedtest` on the command line. For DURATION ticks, CPU-bound tasks that
+// never give up the CPU compete with tasks that yield after every bit of
+// work, and with the calling thread, which only yields. Afterwards:
+//   - every task has run, and none sat Ready for longer than one slice per
+//     live task (plus a tick of slack for where in the tick it woke);
+//   - the CPU-bound tasks got roughly equal time (within a factor of two);
+//   - idle never ran while a task was waiting (`sched.idle_while_ready`);
+//   - `sleep_ms` slept at least as long as asked, and not a slice per live
+//     task longer;
+//   - every task's stack high-water mark is inside its stack;
+//   - `join` on a spawned task returns what its closure returned;
+//   - with more than one CPU scheduling, the tasks ran on more than one;
+//   - priority inversion: a Normal task holds a KMutex, a Fifo task blocks
+//     on it and a lower Fifo hog spins. The holder is boosted to the
+//     waiter's level, keeps running past the hog, and drops the boost
+//     when it unlocks;
+//   - a hog capped to CAP_PCT of a CPU gets no more than that (plus a
+//     period), and an invalid cap is refused;
+//   - deadline admission refuses invalid and overloading parameters, and an
+//     admitted task gets no more than its runtime per period;
+//   - a hog moved into a capped subgroup is held to the cap, its time and
+//     memory show up in the parent's totals, and killing the parent group
+//     kills it;
+//   - a wait-queue waiter is woken by `notify_one` and leaves the queue.
+// Failures are listed and then panic.
+
+use core::hint::black_box;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use heapless::Vec;
+
+use super::bandwidth::CpuLimit;
+use super::edf::{AdmissionError, DeadlineParams};
+use super::group::{self, ROOT_GROUP};
+use super::kmutex::KMutex;
+use super::prio::Priority;
+use super::stack::StackUsage;
+use super::waitq::WaitQueue;
+use super::{TaskId, TaskStats, current_id, slice, spawn_with_stack_size, task_count};
+use super::{cpu_index, sleep_ms, spawn, task_stats, ticks, with_rq_locked, yield_now};
+use super::{effective_priority, priority, set_priority, spawn_with_priority};
+use super::{set_cpu_limit, set_deadline, spawn_deadline, stack_usage};
+use crate::{cmdline, kprintln, stats};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DURATION: u64 = 2000; // ticks
+const HOGS: usize = 3;
+const YIELDERS: usize = 3;
+const EXIT_TIMEOUT: u64 = 1000; // ticks to wait for the tasks to finish
+const WORK: u32 = 1000; // spins between a yielder's yields
+const SLEEP: u64 = 50; // ms
+
+// Priority inversion scenario.
+const PI_HOLDER: Priority = Priority::Normal(0);
+const PI_HOG: Priority = Priority::Fifo(10);
+const PI_WAITER: Priority = Priority::Fifo(50);
+const PI_TIMEOUT: u64 = 500; // ticks for each step
+const PI_RUN: u64 = 20; // ms the boosted holder must make progress in
+
+// Caps, deadlines and groups: each runs one hog for RUN ticks.
+const RUN: u64 = 500;
+const CAP_PCT: u8 = 25;
+const CAP_PERIOD: u64 = 100;
+const GROUP_PCT: u8 = 20;
+const GROUP_WEIGHT: u32 = 200;
+const GROUP_MEM: i64 = 4096;
+const DL: DeadlineParams = DeadlineParams {
+    runtime: 5,
+    deadline: 20,
+    period: 20,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Kind {
+    Hog,
+    Yielder,
+}
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static EXITED: AtomicU32 = AtomicU32::new(0);
+static RAN_ON: AtomicU64 = AtomicU64::new(0); // CPU indices the tasks ran on
+
+static PI_LOCK: KMutex<()> = KMutex::new(());
+static PI_HELD: AtomicBool = AtomicBool::new(false);
+static PI_RELEASE: AtomicBool = AtomicBool::new(false);
+static PI_STOP: AtomicBool = AtomicBool::new(false);
+static PI_PROGRESS: AtomicU64 = AtomicU64::new(0); // holder's work while holding
+static PI_AFTER: AtomicU32 = AtomicU32::new(0); // holder's rank after unlock, +1
+static PI_GOT: AtomicBool = AtomicBool::new(false);
+
+static HOG_STOP: AtomicBool = AtomicBool::new(false);
+
+static WQ: WaitQueue = WaitQueue::new();
+static WQ_FLAG: AtomicBool = AtomicBool::new(false);
+static WQ_WOKE: AtomicBool = AtomicBool::new(false);
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn work(n: u32) {
+    for i in 0..n {
+        black_box(i);
+    }
+}
+
+fn spawn_one(kind: Kind) -> TaskId {
+    spawn_with_stack_size(0, move || {
+        while !STOP.load(Ordering::Acquire) {
+            RAN_ON.fetch_or(1 << (cpu_index() % 64), Ordering::Relaxed);
+            match kind {
+                Kind::Hog => work(WORK),
+                Kind::Yielder => {
+                    work(WORK);
+                    yield_now();
+                }
+            }
+        }
+        EXITED.fetch_add(1, Ordering::Release);
+    })
+}
+
+// CPUs with an idle task, i.e. taking tasks from the runqueue.
+fn sched_cpus() -> usize {
+    with_rq_locked(|rq| rq.idle.iter().flatten().count())
+}
+
+fn idle_while_ready() -> u64 {
+    stats::get("sched.idle_while_ready").unwrap_or(0)
+}
+
+// Sleep a tick at a time until `done()` or PI_TIMEOUT ticks pass.
+fn wait_for(done: impl Fn() -> bool) -> bool {
+    let end = ticks() + PI_TIMEOUT;
+    while !done() && ticks() < end {
+        sleep_ms(1);
+    }
+    done()
+}
+
+fn spawn_at(prio: Priority, f: impl FnOnce() + 'static) -> TaskId {
+    spawn_with_priority(prio, f).expect("schedtest: spawn_with_priority")
+}
+
+// The priority inversion scenario. Runs at the top Fifo level so the hog
+// cannot starve it. Returns how many checks failed.
+fn inversion() -> u32 {
+    let Some(me) = current_id() else {
+        return 0;
+    };
+    for f in [&PI_HELD, &PI_RELEASE, &PI_STOP, &PI_GOT] {
+        f.store(false, Ordering::Relaxed);
+    }
+    PI_PROGRESS.store(0, Ordering::Relaxed);
+    PI_AFTER.store(0, Ordering::Relaxed);
+    let mine = priority(me).unwrap_or_default();
+    let _ = set_priority(me, Priority::Fifo(super::prio::FIFO_MAX));
+    let mut bad = 0;
+
+    let holder = spawn_at(PI_HOLDER, || {
+        let me = current_id();
+        let g = PI_LOCK.lock();
+        PI_HELD.store(true, Ordering::Release);
+        while !PI_RELEASE.load(Ordering::Acquire) {
+            work(WORK);
+            PI_PROGRESS.fetch_add(1, Ordering::Relaxed);
+        }
+        drop(g);
+        let after = me
+            .and_then(effective_priority)
+            .map_or(0, |p| p.rank() as u32 + 1);
+        PI_AFTER.store(after, Ordering::Release);
+    });
+    if !wait_for(|| PI_HELD.load(Ordering::Acquire)) {
+        kprintln!("[schedtest] inversion: holder never took the lock");
+        bad += 1;
+    } else if PI_LOCK.try_lock().is_some() {
+        kprintln!("[schedtest] inversion: try_lock took a held lock");
+        bad += 1;
+    }
+    spawn_at(PI_HOG, || {
+        while !PI_STOP.load(Ordering::Acquire) {
+            work(WORK);
+        }
+    });
+    spawn_at(PI_WAITER, || {
+        drop(PI_LOCK.lock());
+        PI_GOT.store(true, Ordering::Release);
+    });
+
+    if !wait_for(|| effective_priority(holder) == Some(PI_WAITER)) {
+        kprintln!(
+            "[schedtest] inversion: holder at {:?}, expected {:?}",
+            effective_priority(holder),
+            PI_WAITER
+        );
+        bad += 1;
+    }
+    let before = PI_PROGRESS.load(Ordering::Relaxed);
+    sleep_ms(PI_RUN);
+    if PI_PROGRESS.load(Ordering::Relaxed) == before {
+        kprintln!("[schedtest] inversion: boosted holder starved by the hog");
+        bad += 1;
+    }
+
+    PI_RELEASE.store(true, Ordering::Release);
+    if !wait_for(|| PI_GOT.load(Ordering::Acquire)) {
+        kprintln!("[schedtest] inversion: waiter never got the lock");
+        bad += 1;
+    }
+    if !wait_for(|| PI_AFTER.load(Ordering::Acquire) != 0) {
+        kprintln!("[schedtest] inversion: holder never unlocked");
+        bad += 1;
+    } else if PI_AFTER.load(Ordering::Acquire) != PI_HOLDER.rank() as u32 + 1 {
+        kprintln!("[schedtest] inversion: holder kept its boost after unlock");
+        bad += 1;
+    }
+    if PI_LOCK.try_lock().is_none() {
+        kprintln!("[schedtest] inversion: lock still held after release");
+        bad += 1;
+    }
+    PI_STOP.store(true, Ordering::Release);
+    let _ = set_priority(me, mine);
+    bad
+}
+
+// A task that spins until HOG_STOP (or until it is killed).
+fn spawn_hog() -> TaskId {
+    HOG_STOP.store(false, Ordering::Relaxed);
+    spawn_with_stack_size(0, || {
+        while !HOG_STOP.load(Ordering::Acquire) {
+            work(WORK);
+        }
+    })
+}
+
+fn cpu_of(id: TaskId) -> u64 {
+    task_stats(id).map_or(0, |s| s.cpu_ticks)
+}
+
+// CPU ticks `id` gets over the next RUN ticks.
+fn cpu_over_run(id: TaskId) -> u64 {
+    let before = cpu_of(id);
+    sleep_ms(RUN);
+    cpu_of(id) - before
+}
+
+// Most a task held to `quota` per `period` may get in RUN ticks: one
+// period of slack for where in the period it started.
+fn quota_bound(quota: u64, period: u64) -> u64 {
+    RUN / period * quota + period
+}
+
+// A capped hog is held to its cap. Returns how many checks failed.
+fn caps() -> u32 {
+    let mut bad = 0;
+    let hog = spawn_hog();
+    if set_cpu_limit(
+        hog,
+        Some(CpuLimit {
+            quota: 0,
+            period: CAP_PERIOD,
+        }),
+    ) {
+        kprintln!("[schedtest] cap: a zero quota was accepted");
+        bad += 1;
+    }
+    let limit = CpuLimit::percent(CAP_PCT, CAP_PERIOD);
+    if !set_cpu_limit(hog, Some(limit)) {
+        kprintln!("[schedtest] cap: {:?} refused", limit);
+        bad += 1;
+    }
+    let got = cpu_over_run(hog);
+    let most = quota_bound(limit.quota, limit.period);
+    if got == 0 || got > most {
+        kprintln!(
+            "[schedtest] cap: hog got {} of {} ticks, cap allows {}",
+            got,
+            RUN,
+            most
+        );
+        bad += 1;
+    }
+    HOG_STOP.store(true, Ordering::Release);
+    let _ = set_cpu_limit(hog, None);
+    bad
+}
+
+// Deadline admission and budget enforcement. Returns how many checks failed.
+fn deadlines() -> u32 {
+    let mut bad = 0;
+    let invalid = DeadlineParams { runtime: 0, ..DL };
+    let heavy = DeadlineParams {
+        runtime: DL.period,
+        deadline: DL.period,
+        period: DL.period,
+    };
+    for (p, want) in [
+        (invalid, AdmissionError::Invalid),
+        (heavy, AdmissionError::Overloaded),
+    ] {
+        match spawn_deadline(p, || {}) {
+            Err(e) if e == want => {}
+            r => {
+                kprintln!(
+                    "[schedtest] deadline: {:?} gave {:?}, expected {:?}",
+                    p,
+                    r,
+                    want
+                );
+                bad += 1;
+            }
+        }
+    }
+    HOG_STOP.store(false, Ordering::Relaxed);
+    let hog = match spawn_deadline(DL, || {
+        while !HOG_STOP.load(Ordering::Acquire) {
+            work(WORK);
+        }
+    }) {
+        Ok(id) => id,
+        Err(e) => {
+            kprintln!("[schedtest] deadline: {:?} refused: {:?}", DL, e);
+            return bad + 1;
+        }
+    };
+    let got = cpu_over_run(hog);
+    let most = quota_bound(DL.runtime, DL.period);
+    if got == 0 || got > most {
+        kprintln!(
+            "[schedtest] deadline: task got {} of {} ticks, budget allows {}",
+            got,
+            RUN,
+            most
+        );
+        bad += 1;
+    }
+    // Back to round-robin, which releases its reservation.
+    if let Err(e) = set_deadline(hog, None) {
+        kprintln!("[schedtest] deadline: leaving the class failed: {:?}", e);
+        bad += 1;
+    }
+    HOG_STOP.store(true, Ordering::Release);
+    if set_deadline(TaskId::MAX, Some(DL)) != Err(AdmissionError::NoTask) {
+        kprintln!("[schedtest] deadline: admitted a task that does not exist");
+        bad += 1;
+    }
+    bad
+}
+
+// A hog in a capped subgroup. Returns how many checks failed.
+fn groups() -> u32 {
+    let mut bad = 0;
+    if group::create("schedtest.bogus", u32::MAX).is_some() {
+        kprintln!("[schedtest] groups: created a group under a missing parent");
+        bad += 1;
+    }
+    let Some((parent, child)) = group::create("schedtest", ROOT_GROUP)
+        .and_then(|p| Some((p, group::create("schedtest.hog", p)?)))
+    else {
+        kprintln!("[schedtest] groups: could not create the groups");
+        return bad + 1;
+    };
+    let limit = CpuLimit::percent(GROUP_PCT, CAP_PERIOD);
+    let _ = group::set_weight(child, GROUP_WEIGHT);
+    let _ = group::set_cpu_limit(parent, Some(limit));
+
+    let hog = spawn_hog();
+    if !group::attach(hog, child) || group::of(hog) != Some(child) {
+        kprintln!("[schedtest] groups: hog is in {:?}", group::of(hog));
+        bad += 1;
+    }
+    let got = cpu_over_run(hog);
+    let most = quota_bound(limit.quota, limit.period);
+    if got == 0 || got > most {
+        kprintln!(
+            "[schedtest] groups: hog got {} of {} ticks, parent cap allows {}",
+            got,
+            RUN,
+            most
+        );
+        bad += 1;
+    }
+    let mem_before = group::stats(parent).map_or(0, |s| s.mem_bytes);
+    group::charge_mem(child, GROUP_MEM);
+    let (p, c) = (group::stats(parent), group::stats(child));
+    group::charge_mem(child, -GROUP_MEM);
+    match (p, c) {
+        (Some(p), Some(c)) => {
+            if c.parent != Some(parent) || c.weight != GROUP_WEIGHT || c.tasks != 1 {
+                kprintln!("[schedtest] groups: subgroup stats {:?}", c);
+                bad += 1;
+            }
+            if p.cpu_ticks < c.cpu_ticks || p.mem_bytes != mem_before + GROUP_MEM as u64 {
+                kprintln!(
+                    "[schedtest] groups: parent totals {:?}, subgroup {:?}",
+                    p,
+                    c
+                );
+                bad += 1;
+            }
+        }
+        r => {
+            kprintln!("[schedtest] groups: no stats: {:?}", r);
+            bad += 1;
+        }
+    }
+    let killed = group::kill(parent);
+    if killed != 1 {
+        kprintln!(
+            "[schedtest] groups: killing the group killed {} task(s)",
+            killed
+        );
+        bad += 1;
+    }
+    HOG_STOP.store(true, Ordering::Release);
+    bad
+}
+
+// One waiter, one `notify_one`. Returns how many checks failed.
+fn waitqueue() -> u32 {
+    WQ_FLAG.store(false, Ordering::Relaxed);
+    WQ_WOKE.store(false, Ordering::Relaxed);
+    spawn_with_stack_size(0, || {
+        while !WQ_FLAG.load(Ordering::Acquire) {
+            WQ.wait();
+        }
+        WQ_WOKE.store(true, Ordering::Release);
+    });
+    let mut bad = 0;
+    if !wait_for(|| !WQ.is_empty()) {
+        kprintln!("[schedtest] waitq: waiter never queued");
+        bad += 1;
+    }
+    WQ_FLAG.store(true, Ordering::Release);
+    if !WQ.notify_one() {
+        kprintln!("[schedtest] waitq: notify_one found nobody waiting");
+        bad += 1;
+    }
+    if !wait_for(|| WQ_WOKE.load(Ordering::Acquire)) || !WQ.is_empty() {
+        kprintln!("[schedtest] waitq: waiter not woken, or left queued");
+        bad += 1;
+    }
+    bad
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+pub fn enabled() -> bool {
+    cmdline::flag("schedtest")
+}
+
+/// Run the scenario on the calling thread; panic if any check failed.
+pub fn run() {
+    if current_id().is_none() {
+        kprintln!("[schedtest] not running as a task; skipped");
+        return;
+    }
+    STOP.store(false, Ordering::Relaxed);
+    EXITED.store(0, Ordering::Relaxed);
+    RAN_ON.store(0, Ordering::Relaxed);
+
+    let mut tasks: Vec<(Kind, TaskId), { HOGS + YIELDERS }> = Vec::new();
+    for _ in 0..HOGS {
+        let _ = tasks.push((Kind::Hog, spawn_one(Kind::Hog)));
+    }
+    for _ in 0..YIELDERS {
+        let _ = tasks.push((Kind::Yielder, spawn_one(Kind::Yielder)));
+    }
+    let bound = task_count() as u64 * slice() as u64 + 1;
+    let idle_before = idle_while_ready();
+
+    let end = ticks() + DURATION;
+    while ticks() < end {
+        yield_now();
+    }
+    // Before STOP: a task that has exited may be reaped at any time.
+    let seen: Vec<(Kind, TaskId, TaskStats, StackUsage), { HOGS + YIELDERS }> = tasks
+        .iter()
+        .map(|&(k, id)| {
+            let stack = stack_usage(id).unwrap_or(StackUsage { used: 0, size: 0 });
+            (k, id, task_stats(id).unwrap_or_default(), stack)
+        })
+        .collect();
+    let idle = idle_while_ready() - idle_before;
+    STOP.store(true, Ordering::Release);
+
+    let mut bad = 0;
+    for &(kind, id, s, stack) in seen.iter() {
+        kprintln!(
+            "[schedtest] {:?} {}: cpu={} max_wait={} stack={}/{}",
+            kind,
+            id,
+            s.cpu_ticks,
+            s.max_wait,
+            stack.used,
+            stack.size
+        );
+        if stack.used == 0 || stack.used > stack.size {
+            kprintln!(
+                "[schedtest] task {} stack use {} of {}",
+                id,
+                stack.used,
+                stack.size
+            );
+            bad += 1;
+        }
+        if s.cpu_ticks == 0 {
+            kprintln!("[schedtest] task {} never ran", id);
+            bad += 1;
+        }
+        if s.max_wait > bound {
+            kprintln!(
+                "[schedtest] task {} waited {} ticks, bound {}",
+                id,
+                s.max_wait,
+                bound
+            );
+            bad += 1;
+        }
+    }
+    let hogs = seen.iter().filter(|(k, ..)| *k == Kind::Hog);
+    let most = hogs
+        .clone()
+        .map(|(_, _, s, _)| s.cpu_ticks)
+        .max()
+        .unwrap_or(0);
+    let least = hogs.map(|(_, _, s, _)| s.cpu_ticks).min().unwrap_or(0);
+    if least * 2 < most {
+        kprintln!("[schedtest] unfair: hogs got {}..{} ticks", least, most);
+        bad += 1;
+    }
+    if idle != 0 {
+        kprintln!("[schedtest] idle ran {} tick(s) with tasks waiting", idle);
+        bad += 1;
+    }
+    let (cpus, ran_on) = (sched_cpus(), RAN_ON.load(Ordering::Relaxed).count_ones());
+    if cpus > 1 && ran_on < 2 {
+        kprintln!(
+            "[schedtest] {} CPUs scheduling, tasks ran on {}",
+            cpus,
+            ran_on
+        );
+        bad += 1;
+    }
+
+    let deadline = ticks() + EXIT_TIMEOUT;
+    while (EXITED.load(Ordering::Acquire) as usize) < tasks.len() && ticks() < deadline {
+        yield_now();
+    }
+    if (EXITED.load(Ordering::Acquire) as usize) < tasks.len() {
+        kprintln!("[schedtest] tasks did not stop");
+        bad += 1;
+    }
+    let start = ticks();
+    sleep_ms(SLEEP);
+    let slept = ticks() - start;
+    let late = task_count() as u64 * slice() as u64 + 1;
+    if slept < SLEEP || slept > SLEEP + late {
+        kprintln!("[schedtest] sleep_ms({}) took {} ticks", SLEEP, slept);
+        bad += 1;
+    }
+    let h = spawn(|| black_box(6u64) * 7);
+    if !wait_for(|| h.is_finished()) {
+        kprintln!("[schedtest] task {} never finished", h.id());
+        bad += 1;
+    }
+    match h.join() {
+        Some(42) => {}
+        r => {
+            kprintln!("[schedtest] join returned {:?}, expected Some(42)", r);
+            bad += 1;
+        }
+    }
+    bad += inversion();
+    bad += caps();
+    bad += deadlines();
+    bad += groups();
+    bad += waitqueue();
+    if bad != 0 {
+        panic!("scheduler self-test: {} check(s) failed", bad);
+    }
+    kprintln!(
+        "[schedtest] ok: {} tasks over {} ticks, wait bound {}",
+        tasks.len(),
+        DURATION,
+        bound
+    );
+}
@@ -0,0 +1,164 @@
+// src/sched/stack.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Kernel thread stacks. Each one is its own VMAP allocation with an
+// unmapped guard page below it, so an overflow faults instead of corrupting
+// whatever sits next to it; freeing unmaps it again. A new stack is filled
+// with STACK_FILL; since stacks grow down, the lowest byte that no longer
+// holds the pattern marks the deepest the task has ever reached (its
+// high-water mark). The reaper thread calls `check_all` periodically and
+// warns once per task past WARN_PERCENT. With CET set up, each stack also
+// carries the task's shadow stack.
+
+use super::{RunQueue, TaskId, TaskState};
+use crate::arch::native::cet::{self, ShadowStack};
+use crate::error::KResult;
+use crate::{kprintln, mem};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const DEFAULT_STACK_SIZE: usize = 0x1_0000;
+pub const MIN_STACK_SIZE: usize = 0x4000;
+pub const MAX_STACK_SIZE: usize = 0x10_0000;
+const PAGE_SIZE: usize = 4096;
+const STACK_FILL: u8 = 0xA5;
+const FILL_WORD: u64 = u64::from_ne_bytes([STACK_FILL; 8]);
+pub const WARN_PERCENT: usize = 75;
+
+#[derive(Debug)]
+pub(super) struct ThreadStack {
+    base: *mut u8,
+    size: usize,
+    warned: bool,
+    shadow: Option<ShadowStack>,
+}
+
+// Owned exclusively by its task; only touched under the run-queue lock.
+unsafe impl Send for ThreadStack {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StackUsage {
+    pub used: usize, // high-water mark in bytes
+    pub size: usize,
+}
+
+impl StackUsage {
+    pub fn percent(&self) -> usize {
+        self.used * 100 / self.size
+    }
+}
+
+/* ---------------------------------- Stacks ---------------------------------- */
+
+/// Round a requested size to whole pages within [MIN, MAX]_STACK_SIZE.
+pub fn clamp_size(bytes: usize) -> usize {
+    bytes
+        .clamp(MIN_STACK_SIZE, MAX_STACK_SIZE)
+        .next_multiple_of(PAGE_SIZE)
+}
+
+impl ThreadStack {
+    /// `size` must come from `clamp_size`.
+    pub(super) fn new(size: usize) -> KResult<Self> {
+        let shadow = if cet::enabled() {
+            Some(ShadowStack::for_stack(size)?)
+        } else {
+            None
+        };
+        let base = mem::vmap_alloc_guarded(size / PAGE_SIZE)?;
+        unsafe { core::ptr::write_bytes(base, STACK_FILL, size) };
+        Ok(Self {
+            base,
+            size,
+            warned: false,
+            shadow,
+        })
+    }
+
+    /// Saved SSP to resume this task with, when it has a shadow stack.
+    pub(super) fn ssp(&self) -> Option<u64> {
+        self.shadow.as_ref().map(|s| s.ssp)
+    }
+
+    /// Where the ISR stub saves this task's SSP when it is switched out.
+    pub(super) fn ssp_slot(&mut self) -> Option<*mut u64> {
+        self.shadow.as_mut().map(|s| &raw mut s.ssp)
+    }
+
+    /// 16-byte aligned initial stack pointer.
+    pub(super) fn top(&mut self) -> u64 {
+        (self.base as u64 + self.size as u64) & !0xF
+    }
+
+    pub(super) fn size(&self) -> usize {
+        self.size
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.base, self.size) }
+    }
+
+    pub(super) fn usage(&self) -> StackUsage {
+        let dump = self.bytes();
+        // Word-wise over the aligned middle; the ragged ends byte-wise.
+        let (head, words, _) = unsafe { dump.align_to::<u64>() };
+        let untouched = match head.iter().position(|&b| b != STACK_FILL) {
+            Some(i) => i,
+            None => {
+                let w = words
+                    .iter()
+                    .position(|&w| w != FILL_WORD)
+                    .unwrap_or(words.len());
+                let at = head.len() + w * 8;
+                at + dump[at..]
+                    .iter()
+                    .position(|&b| b != STACK_FILL)
+                    .unwrap_or(dump.len() - at)
+            }
+        };
+        StackUsage {
+            used: dump.len() - untouched,
+            size: dump.len(),
+        }
+    }
+}
+
+impl Drop for ThreadStack {
+    // Dead tasks are reaped from another task, never while on this stack.
+    fn drop(&mut self) {
+        mem::vmap_free_guarded(self.base, self.size / PAGE_SIZE);
+    }
+}
+
+/* ------------------------------ Run-queue side ------------------------------ */
+
+impl RunQueue {
+    pub(super) fn stack_usage(&self, id: TaskId) -> Option<StackUsage> {
+        let t = self
+            .tasks
+            .iter()
+            .find(|t| t.id == id && t.state != TaskState::Dead)?;
+        Some(t.stack.usage())
+    }
+}
+
+/// Warn about every live task whose stack crossed WARN_PERCENT since the
+/// last check. Scans whole stacks, so callers keep this infrequent.
+pub(super) fn check_all(rq: &mut RunQueue) {
+    for t in rq.tasks.iter_mut() {
+        if t.state == TaskState::Dead || t.stack.warned {
+            continue;
+        }
+        let u = t.stack.usage();
+        if u.percent() > WARN_PERCENT {
+            t.stack.warned = true;
+            kprintln!(
+                "[sched] task {} stack at {}% ({} of {} bytes)",
+                t.id,
+                u.percent(),
+                u.used,
+                u.size
+            );
+        }
+    }
+}
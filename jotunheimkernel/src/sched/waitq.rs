@@ -0,0 +1,113 @@
+// src/sched/waitq.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Wait queues: somewhere for a task to block until another task (or an AP,
+// or an ISR that does not hold the runqueue) says the thing it waits for may
+// have happened. Waiters are woken in arrival order.
+//
+// No wakeup is lost between checking a condition and blocking: `wait` queues
+// the task first, and a notify that lands before it blocks leaves a pending
+// wake that `block_current` consumes. Wakeups can be spurious (any `wake()`
+// of the task ends a wait), so use `wait_until`, or re-check after `wait`.
+
+extern crate alloc;
+use alloc::collections::VecDeque;
+
+use spin::Mutex;
+use x86_64::instructions::hlt;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{TaskId, block_current, current_id, wake};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Debug)]
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<TaskId>>,
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block until notified. Before the scheduler runs there is no task to
+    /// block; waits for the next interrupt instead.
+    pub fn wait(&self) {
+        let Some(me) = current_id() else {
+            hlt();
+            return;
+        };
+        self.enqueue(me);
+        block_current();
+        // Woken by something else: do not leave a stale entry behind.
+        without_interrupts(|| self.waiters.lock().retain(|&w| w != me));
+    }
+
+    /// Block until `cond()` holds, re-checking after every wakeup.
+    pub fn wait_until(&self, mut cond: impl FnMut() -> bool) {
+        loop {
+            if cond() {
+                return;
+            }
+            let Some(me) = current_id() else {
+                hlt();
+                continue;
+            };
+            self.enqueue(me);
+            // Queued first, so a notify from here on is not missed.
+            if cond() {
+                without_interrupts(|| self.waiters.lock().retain(|&w| w != me));
+                return;
+            }
+            block_current();
+            without_interrupts(|| self.waiters.lock().retain(|&w| w != me));
+        }
+    }
+
+    /// Wake the longest waiter; false if nobody was waiting.
+    pub fn notify_one(&self) -> bool {
+        let next = without_interrupts(|| self.waiters.lock().pop_front());
+        if let Some(id) = next {
+            wake(id);
+        }
+        next.is_some()
+    }
+
+    /// Wake every waiter; returns how many there were.
+    pub fn notify_all(&self) -> usize {
+        // Emptied in place under the lock: callable from an ISR, so the
+        // queue's buffer must be neither freed nor grown here.
+        without_interrupts(|| {
+            let mut w = self.waiters.lock();
+            let n = w.len();
+            while let Some(id) = w.pop_front() {
+                wake(id);
+            }
+            n
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        without_interrupts(|| self.waiters.lock().is_empty())
+    }
+
+    fn enqueue(&self, id: TaskId) {
+        without_interrupts(|| {
+            let mut w = self.waiters.lock();
+            if !w.contains(&id) {
+                w.push_back(id);
+            }
+        });
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,161 @@
+// src/stats.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Named event counters. `counter!("pf.cow_faults")` bumps a counter that
+// lives in a static at the call site; it joins the global registry (a
+// lock-free intrusive list) the first time it fires, so counters that never
+// fire cost nothing and never show up. Each counter is sharded by CPU on
+// separate cache lines; reads sum the shards. The CPU index comes from
+// IA32_TSC_AUX via RDTSCP, set up by `init_cpu()` on every CPU.
+
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU8, AtomicU64, Ordering};
+
+use x86_64::registers::model_specific::Msr;
+
+use crate::arch::x86_64::apic::lapic_id;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const SHARDS: usize = 8;
+const IA32_TSC_AUX: u32 = 0xC000_0103;
+
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+pub struct Counter {
+    name: &'static str,
+    shards: [Shard; SHARDS],
+    registered: AtomicBool,
+    next: AtomicPtr<Counter>,
+}
+
+static HEAD: AtomicPtr<Counter> = AtomicPtr::new(ptr::null_mut());
+// 0 = not probed, 1 = no RDTSCP, 2 = RDTSCP with TSC_AUX set
+static RDTSCP: AtomicU8 = AtomicU8::new(0);
+
+/// Bump a named counter by one, or by `$n`.
+#[macro_export]
+macro_rules! counter {
+    ($name:literal) => {
+        $crate::counter!($name, 1)
+    };
+    ($name:literal, $n:expr) => {{
+        static C: $crate::stats::Counter = $crate::stats::Counter::new($name);
+        C.add($n as u64);
+    }};
+}
+
+/* --------------------------------- Per-CPU ---------------------------------- */
+
+fn has_rdtscp() -> bool {
+    core::arch::x86_64::__cpuid(0x8000_0001).edx & (1 << 27) != 0
+}
+
+/// Tag this CPU for shard selection. Call on each CPU during bring-up.
+pub fn init_cpu() {
+    if RDTSCP.load(Ordering::Relaxed) == 0 {
+        RDTSCP.store(if has_rdtscp() { 2 } else { 1 }, Ordering::Relaxed);
+    }
+    if RDTSCP.load(Ordering::Relaxed) == 2 {
+        unsafe { Msr::new(IA32_TSC_AUX).write(lapic_id() as u64) };
+    }
+}
+
+fn shard() -> usize {
+    if RDTSCP.load(Ordering::Relaxed) != 2 {
+        return 0;
+    }
+    let mut aux = 0u32;
+    unsafe { core::arch::x86_64::__rdtscp(&mut aux) };
+    aux as usize % SHARDS
+}
+
+/// This CPU's APIC id as tagged by `init_cpu`, read cheaply with RDTSCP.
+/// None if the CPU has no RDTSCP.
+pub fn cpu_tag() -> Option<u32> {
+    if RDTSCP.load(Ordering::Relaxed) != 2 {
+        return None;
+    }
+    let mut aux = 0u32;
+    unsafe { core::arch::x86_64::__rdtscp(&mut aux) };
+    Some(aux)
+}
+
+/* --------------------------------- Counters --------------------------------- */
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            shards: [const { Shard(AtomicU64::new(0)) }; SHARDS],
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[inline]
+    pub fn add(&'static self, n: u64) {
+        if !self.registered.load(Ordering::Relaxed) {
+            self.register();
+        }
+        self.shards[shard()].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[cold]
+    fn register(&'static self) {
+        if self.registered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let me = self as *const Counter as *mut Counter;
+        let mut head = HEAD.load(Ordering::Acquire);
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+            match HEAD.compare_exchange_weak(head, me, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    /// Sum over all CPUs; not a snapshot against concurrent updates.
+    pub fn value(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|s| s.0.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn reset(&self) {
+        for s in self.shards.iter() {
+            s.0.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Every counter that has fired at least once, newest first.
+pub fn counters() -> impl Iterator<Item = &'static Counter> {
+    let mut cur = HEAD.load(Ordering::Acquire);
+    core::iter::from_fn(move || {
+        let c = unsafe { cur.as_ref()? };
+        cur = c.next.load(Ordering::Acquire);
+        Some(c)
+    })
+}
+
+pub fn get(name: &str) -> Option<u64> {
+    counters().find(|c| c.name == name).map(Counter::value)
+}
+
+pub fn reset_all() {
+    counters().for_each(Counter::reset);
+}
+
+/// Call `f` with each registered counter's name and value.
+pub fn for_each(mut f: impl FnMut(&'static str, u64)) {
+    for c in counters() {
+        f(c.name, c.value());
+    }
+}
@@ -0,0 +1,197 @@
+// src/usb/hid.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// HID boot-protocol keyboards on xHCI. Each keyboard's interrupt IN endpoint
+// is polled from an executor task; 8-byte boot reports are diffed against the
+// previous one and turned into set-1 key events for the input subsystem.
+use super::xhci::{self, Xhci};
+use super::{
+    EndpointDescriptor, RT_CLASS, RT_INTERFACE, SetupPacket, UsbError, for_each_interface,
+};
+use crate::input::{DeviceId, EventKind, KeyCode, register_device, report};
+use crate::kprintln;
+use crate::sched::executor;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const CLASS_HID: u8 = 3;
+const SUBCLASS_BOOT: u8 = 1;
+const PROTOCOL_KEYBOARD: u8 = 1;
+
+const REQ_SET_IDLE: u8 = 0x0A;
+const REQ_SET_PROTOCOL: u8 = 0x0B;
+
+const REPORT_LEN: usize = 8;
+const POLL_TICKS: u64 = 8;
+
+type Report = [u8; REPORT_LEN];
+
+struct Keyboard {
+    idx: usize, // index into the controller's device list
+    dci: u8,
+    input: DeviceId,
+    last: Report,
+}
+
+/* --------------------------------- Decoding --------------------------------- */
+
+// Modifier byte, bit 0 (left ctrl) to bit 7 (right GUI).
+const MODIFIERS: [KeyCode; 8] = [0x1D, 0x2A, 0x38, 0xE05B, 0xE01D, 0x36, 0xE038, 0xE05C];
+
+/// HID usage page 7 (keyboard) to scan code set 1; 0 = unmapped.
+fn usage_to_set1(u: u8) -> KeyCode {
+    const LETTERS: [u8; 26] = [
+        0x1E, 0x30, 0x2E, 0x20, 0x12, 0x21, 0x22, 0x23, 0x17, 0x24, 0x25, 0x26, 0x32, 0x31, 0x18,
+        0x19, 0x10, 0x13, 0x1F, 0x14, 0x16, 0x2F, 0x11, 0x2D, 0x15, 0x2C,
+    ];
+    match u {
+        0x04..=0x1D => LETTERS[(u - 0x04) as usize] as KeyCode,
+        0x1E..=0x27 => (u - 0x1E + 0x02) as KeyCode, // 1..9, 0
+        0x28 => 0x1C,                                // enter
+        0x29 => 0x01,                                // escape
+        0x2A => 0x0E,                                // backspace
+        0x2B => 0x0F,                                // tab
+        0x2C => 0x39,                                // space
+        0x2D => 0x0C,
+        0x2E => 0x0D,
+        0x2F => 0x1A,
+        0x30 => 0x1B,
+        0x31 | 0x32 => 0x2B, // backslash, non-US #
+        0x33 => 0x27,
+        0x34 => 0x28,
+        0x35 => 0x29,
+        0x36 => 0x33,
+        0x37 => 0x34,
+        0x38 => 0x35,
+        0x39 => 0x3A,                                // caps lock
+        0x3A..=0x43 => (u - 0x3A + 0x3B) as KeyCode, // F1..F10
+        0x44 => 0x57,
+        0x45 => 0x58,
+        0x4F => 0xE04D, // right
+        0x50 => 0xE04B, // left
+        0x51 => 0xE050, // down
+        0x52 => 0xE048, // up
+        _ => 0,
+    }
+}
+
+impl Keyboard {
+    fn on_report(&mut self, r: &Report) {
+        let changed = r[0] ^ self.last[0];
+        for (bit, &code) in MODIFIERS.iter().enumerate() {
+            if changed & (1 << bit) != 0 {
+                let pressed = r[0] & (1 << bit) != 0;
+                report(self.input, EventKind::Key { code, pressed });
+            }
+        }
+        // 0x01 = rollover error: keep the old state until it clears.
+        if r[2..].contains(&0x01) {
+            return;
+        }
+        let keys = |rep: &Report, u: u8| u > 0x03 && rep[2..].contains(&u);
+        for &u in &self.last[2..] {
+            if keys(&self.last, u) && !keys(r, u) {
+                self.key(u, false);
+            }
+        }
+        for &u in &r[2..] {
+            if keys(r, u) && !keys(&self.last, u) {
+                self.key(u, true);
+            }
+        }
+        self.last = *r;
+    }
+
+    fn key(&self, usage: u8, pressed: bool) {
+        let code = usage_to_set1(usage);
+        if code != 0 {
+            report(self.input, EventKind::Key { code, pressed });
+        }
+    }
+}
+
+/* ---------------------------------- Setup ----------------------------------- */
+
+fn class_request(request: u8, value: u16, iface: u8) -> SetupPacket {
+    SetupPacket {
+        request_type: RT_CLASS | RT_INTERFACE,
+        request,
+        value,
+        index: iface as u16,
+        length: 0,
+    }
+}
+
+/// Boot keyboard interface of device `idx`: (config value, interface, endpoint).
+fn find_keyboard(x: &mut Xhci, idx: usize) -> Option<(u8, u8, EndpointDescriptor)> {
+    let mut buf = [0u8; 512];
+    let n = x.config_descriptor_at(idx, &mut buf).ok()?;
+    if n < 9 {
+        return None;
+    }
+    let mut found = None;
+    for_each_interface(&buf[..n], |ifc, eps| {
+        let boot_kbd = ifc.class == CLASS_HID
+            && ifc.subclass == SUBCLASS_BOOT
+            && ifc.protocol == PROTOCOL_KEYBOARD;
+        if found.is_none() && boot_kbd {
+            let ep = eps.iter().find(|e| e.is_in() && e.is_interrupt());
+            found = ep.map(|e| (ifc.number, *e));
+        }
+    });
+    found.map(|(iface, ep)| (buf[5], iface, ep))
+}
+
+fn attach(x: &mut Xhci, idx: usize) -> Result<Option<u8>, UsbError> {
+    let Some((config, iface, ep)) = find_keyboard(x, idx) else {
+        return Ok(None);
+    };
+    x.control_at(idx, SetupPacket::set_configuration(config), &mut [])?;
+    x.control_at(idx, class_request(REQ_SET_PROTOCOL, 0, iface), &mut [])?;
+    // Report only on change; some keyboards stall this, which is harmless.
+    let _ = x.control_at(idx, class_request(REQ_SET_IDLE, 0, iface), &mut []);
+    let dci = x.configure_interrupt_in(idx, &ep)?;
+    x.queue_in(idx, dci, REPORT_LEN)?;
+    Ok(Some(dci))
+}
+
+async fn run(mut kbd: Keyboard) {
+    loop {
+        let mut r: Report = [0; REPORT_LEN];
+        let done = xhci::with(|x| {
+            let res = x.poll_transfer(kbd.idx, kbd.dci, &mut r)?;
+            let _ = x.queue_in(kbd.idx, kbd.dci, REPORT_LEN);
+            Some(res)
+        })
+        .flatten();
+        match done {
+            Some(Ok(n)) if n >= 3 => kbd.on_report(&r),
+            Some(Err(e)) => kprintln!("[usb-hid] transfer error: {:?}", e),
+            _ => {}
+        }
+        executor::sleep_ticks(POLL_TICKS).await;
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Bind every boot-protocol keyboard found by `xhci::init()`.
+pub fn init() {
+    let n = xhci::with(|x| x.devices.len()).unwrap_or(0);
+    for idx in 0..n {
+        match xhci::with(|x| attach(x, idx)) {
+            Some(Ok(Some(dci))) => {
+                let port = xhci::with(|x| x.devices[idx].port).unwrap_or(0);
+                kprintln!("[usb-hid] keyboard on port {}", port);
+                executor::spawn(run(Keyboard {
+                    idx,
+                    dci,
+                    input: register_device("usb-kbd"),
+                    last: [0; REPORT_LEN],
+                }));
+            }
+            Some(Err(e)) => kprintln!("[usb-hid] device {}: {:?}", idx, e),
+            _ => {}
+        }
+    }
+}
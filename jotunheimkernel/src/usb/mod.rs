@@ -0,0 +1,203 @@
+// src/usb/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Controller-independent USB definitions: setup packets, standard requests
+// and the descriptors we parse during enumeration.
+
+pub mod hid;
+pub mod xhci;
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const REQ_GET_DESCRIPTOR: u8 = 6;
+pub const REQ_SET_CONFIGURATION: u8 = 9;
+
+pub const DESC_DEVICE: u8 = 1;
+pub const DESC_CONFIGURATION: u8 = 2;
+pub const DESC_INTERFACE: u8 = 4;
+pub const DESC_ENDPOINT: u8 = 5;
+
+// bmRequestType
+pub const RT_DEV_TO_HOST: u8 = 0x80;
+pub const RT_CLASS: u8 = 0x20;
+pub const RT_INTERFACE: u8 = 0x01;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum UsbError {
+    Timeout,
+    Stall,
+    NoMemory,
+    NoDevice,
+    Controller(u8), // xHCI completion code
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Speed {
+    Low,
+    Full,
+    High,
+    Super,
+}
+
+impl Speed {
+    /// Default EP0 max packet size before the device descriptor is read.
+    pub fn ep0_max_packet(self) -> u16 {
+        match self {
+            Speed::Low | Speed::Full => 8,
+            Speed::High => 64,
+            Speed::Super => 512,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    pub fn get_descriptor(kind: u8, index: u8, length: u16) -> Self {
+        Self {
+            request_type: RT_DEV_TO_HOST,
+            request: REQ_GET_DESCRIPTOR,
+            value: ((kind as u16) << 8) | index as u16,
+            index: 0,
+            length,
+        }
+    }
+
+    pub fn set_configuration(value: u8) -> Self {
+        Self {
+            request_type: 0,
+            request: REQ_SET_CONFIGURATION,
+            value: value as u16,
+            index: 0,
+            length: 0,
+        }
+    }
+
+    pub fn is_in(&self) -> bool {
+        self.request_type & RT_DEV_TO_HOST != 0
+    }
+
+    pub fn to_u64(self) -> u64 {
+        (self.request_type as u64)
+            | (self.request as u64) << 8
+            | (self.value as u64) << 16
+            | (self.index as u64) << 32
+            | (self.length as u64) << 48
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DeviceDescriptor {
+    pub usb_version: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub max_packet0: u8,
+    pub vendor: u16,
+    pub product: u16,
+    pub num_configs: u8,
+}
+
+impl DeviceDescriptor {
+    /// Parse at least the first 8 bytes; later fields stay zero if absent.
+    pub fn parse(b: &[u8]) -> Option<Self> {
+        if b.len() < 8 || b[1] != DESC_DEVICE {
+            return None;
+        }
+        let le = |i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+        let mut d = Self {
+            usb_version: le(2),
+            class: b[4],
+            subclass: b[5],
+            protocol: b[6],
+            max_packet0: b[7],
+            ..Self::default()
+        };
+        if b.len() >= 18 {
+            d.vendor = le(8);
+            d.product = le(10);
+            d.num_configs = b[17];
+        }
+        Some(d)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct InterfaceDescriptor {
+    pub number: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EndpointDescriptor {
+    pub address: u8, // bit 7 = IN
+    pub attributes: u8,
+    pub max_packet: u16,
+    pub interval: u8,
+}
+
+impl EndpointDescriptor {
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+
+    pub fn is_interrupt(&self) -> bool {
+        self.attributes & 0x3 == 3
+    }
+}
+
+/// Walk a configuration descriptor blob, yielding each interface together
+/// with the endpoints that follow it (up to 16 per interface).
+pub fn for_each_interface(
+    config: &[u8],
+    mut f: impl FnMut(&InterfaceDescriptor, &[EndpointDescriptor]),
+) {
+    let mut cur: Option<InterfaceDescriptor> = None;
+    let mut eps = [EndpointDescriptor::default(); 16];
+    let mut n = 0;
+    let mut i = 0;
+    while i + 2 <= config.len() {
+        let len = config[i] as usize;
+        if len < 2 || i + len > config.len() {
+            break;
+        }
+        let d = &config[i..i + len];
+        match d[1] {
+            DESC_INTERFACE if len >= 9 => {
+                if let Some(ifc) = &cur {
+                    f(ifc, &eps[..n]);
+                }
+                cur = Some(InterfaceDescriptor {
+                    number: d[2],
+                    class: d[5],
+                    subclass: d[6],
+                    protocol: d[7],
+                });
+                n = 0;
+            }
+            DESC_ENDPOINT if len >= 7 && n < eps.len() => {
+                eps[n] = EndpointDescriptor {
+                    address: d[2],
+                    attributes: d[3],
+                    max_packet: u16::from_le_bytes([d[4], d[5]]) & 0x7FF,
+                    interval: d[6],
+                };
+                n += 1;
+            }
+            _ => {}
+        }
+        i += len;
+    }
+    if let Some(ifc) = &cur {
+        f(ifc, &eps[..n]);
+    }
+}
@@ -0,0 +1,817 @@
+// src/usb/xhci.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// xHCI host controller, polled. One command ring, one event ring (interrupter
+// 0, interrupts masked), and an EP0 transfer ring per device. All rings are
+// single 4 KiB segments closed by a link TRB. Root-hub ports only; hubs and
+// USB3 streams are out of scope. After a hot-unplug the controller reads
+// as absent; its ring pages leak.
+extern crate alloc;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering, fence};
+
+use heapless::Deque;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{
+    DESC_CONFIGURATION, DESC_DEVICE, DeviceDescriptor, EndpointDescriptor, SetupPacket, Speed,
+    UsbError,
+};
+use crate::kprintln;
+use crate::mem::try_alloc_low32_contig;
+use crate::pci::{self, CMD_BUS_MASTER, CMD_MEM};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+// PCI class 0C/03, prog-if 30
+const CLASS_SERIAL: u8 = 0x0C;
+const SUBCLASS_USB: u8 = 0x03;
+const PROGIF_XHCI: u8 = 0x30;
+
+// Capability registers
+const CAP_LENGTH: usize = 0x00;
+const CAP_HCSPARAMS1: usize = 0x04;
+const CAP_HCSPARAMS2: usize = 0x08;
+const CAP_HCCPARAMS1: usize = 0x10;
+const CAP_DBOFF: usize = 0x14;
+const CAP_RTSOFF: usize = 0x18;
+
+// Operational registers
+const OP_USBCMD: usize = 0x00;
+const OP_USBSTS: usize = 0x04;
+const OP_CRCR: usize = 0x18;
+const OP_DCBAAP: usize = 0x30;
+const OP_CONFIG: usize = 0x38;
+const OP_PORTSC: usize = 0x400;
+
+const CMD_RUN: u32 = 1 << 0;
+const CMD_HCRST: u32 = 1 << 1;
+const STS_HCH: u32 = 1 << 0;
+const STS_CNR: u32 = 1 << 11;
+
+const PORTSC_CCS: u32 = 1 << 0;
+const PORTSC_PED: u32 = 1 << 1;
+const PORTSC_PR: u32 = 1 << 4;
+const PORTSC_PP: u32 = 1 << 9;
+const PORTSC_PRC: u32 = 1 << 21;
+const PORTSC_RW1C: u32 = 0x7F << 17; // change bits; writing 1 clears them
+
+// Interrupter 0 (runtime base + 0x20)
+const IR0_IMAN: usize = 0x20;
+const IR0_ERSTSZ: usize = 0x28;
+const IR0_ERSTBA: usize = 0x30;
+const IR0_ERDP: usize = 0x38;
+const ERDP_EHB: u64 = 1 << 3;
+
+// TRB types
+const TRB_NORMAL: u32 = 1;
+const TRB_SETUP: u32 = 2;
+const TRB_DATA: u32 = 3;
+const TRB_STATUS: u32 = 4;
+const TRB_LINK: u32 = 6;
+const TRB_ENABLE_SLOT: u32 = 9;
+const TRB_ADDRESS_DEVICE: u32 = 11;
+const TRB_CONFIGURE_EP: u32 = 12;
+const TRB_EVALUATE_CTX: u32 = 13;
+const EV_TRANSFER: u32 = 32;
+const EV_COMMAND: u32 = 33;
+
+const TRB_CYCLE: u32 = 1 << 0;
+const TRB_TC: u32 = 1 << 1; // link: toggle cycle
+const TRB_ISP: u32 = 1 << 2; // event on short packet
+const TRB_IOC: u32 = 1 << 5;
+const TRB_IDT: u32 = 1 << 6;
+const TRB_DIR_IN: u32 = 1 << 16;
+
+const CC_SUCCESS: u8 = 1;
+const CC_STALL: u8 = 6;
+const CC_SHORT_PACKET: u8 = 13;
+
+const RING_TRBS: usize = 256; // one page
+const SPIN_LIMIT: usize = 10_000_000;
+
+#[derive(Copy, Clone, Debug)]
+struct Trb {
+    param: u64,
+    status: u32,
+    control: u32,
+}
+
+impl Trb {
+    fn kind(&self) -> u32 {
+        (self.control >> 10) & 0x3F
+    }
+
+    fn code(&self) -> u8 {
+        (self.status >> 24) as u8
+    }
+
+    fn slot(&self) -> u8 {
+        (self.control >> 24) as u8
+    }
+
+    /// Transfer events: endpoint (DCI) the event belongs to.
+    fn dci(&self) -> u8 {
+        ((self.control >> 16) & 0x1F) as u8
+    }
+
+    fn residue(&self) -> usize {
+        (self.status & 0xFF_FFFF) as usize
+    }
+}
+
+/// Producer ring (command or transfer).
+struct Ring {
+    va: u64,
+    pa: u64,
+    idx: usize,
+    cycle: bool,
+}
+
+struct EventRing {
+    va: u64,
+    pa: u64,
+    idx: usize,
+    cycle: bool,
+}
+
+/// One addressed device on a root port.
+pub struct UsbDevice {
+    pub slot: u8,
+    pub port: u8,
+    pub speed: Speed,
+    pub desc: DeviceDescriptor,
+    ep0: Ring,
+    input_ctx: (u64, u64),
+    out_ctx: (u64, u64),
+    rings: [Option<(Ring, (u64, u64))>; 32], // by DCI: ring + DMA page
+}
+
+pub struct Xhci {
+    op: u64,
+    rt: u64,
+    db: u64,
+    max_slots: u8,
+    max_ports: u8,
+    ctx_size: usize,
+    dcbaa: (u64, u64),
+    cmd: Ring,
+    events: EventRing,
+    bounce: (u64, u64),      // DMA page for control data stages
+    pending: Deque<Trb, 32>, // transfer events for endpoints nobody waited on
+    pub devices: Vec<UsbDevice>,
+}
+
+// Raw MMIO addresses only; all access happens under the global lock.
+unsafe impl Send for Xhci {}
+
+static XHCI: Once<Option<Mutex<Xhci>>> = Once::new();
+static REMOVED: AtomicBool = AtomicBool::new(false);
+
+/* ----------------------------------- Rings ---------------------------------- */
+
+fn page() -> Result<(u64, u64), UsbError> {
+    try_alloc_low32_contig(1).map_err(|_| UsbError::NoMemory)
+}
+
+fn write_trb(va: u64, t: Trb) {
+    let p = va as *mut u32;
+    unsafe {
+        write_volatile(p as *mut u64, t.param);
+        write_volatile(p.add(2), t.status);
+        fence(Ordering::Release);
+        write_volatile(p.add(3), t.control); // cycle bit hands it over
+    }
+}
+
+impl Ring {
+    fn new() -> Result<Self, UsbError> {
+        let (va, pa) = page()?;
+        Ok(Self {
+            va,
+            pa,
+            idx: 0,
+            cycle: true,
+        })
+    }
+
+    fn cycle_bit(&self) -> u32 {
+        if self.cycle { TRB_CYCLE } else { 0 }
+    }
+
+    /// Enqueue one TRB; returns its physical address.
+    fn push(&mut self, param: u64, status: u32, control: u32) -> u64 {
+        let at = self.idx as u64 * 16;
+        let c = self.cycle_bit();
+        write_trb(
+            self.va + at,
+            Trb {
+                param,
+                status,
+                control: (control & !TRB_CYCLE) | c,
+            },
+        );
+        self.idx += 1;
+        if self.idx == RING_TRBS - 1 {
+            let link = Trb {
+                param: self.pa,
+                status: 0,
+                control: (TRB_LINK << 10) | TRB_TC | self.cycle_bit(),
+            };
+            write_trb(self.va + (RING_TRBS as u64 - 1) * 16, link);
+            self.idx = 0;
+            self.cycle = !self.cycle;
+        }
+        self.pa + at
+    }
+
+    /// Dequeue pointer for contexts/CRCR, with the consumer cycle state.
+    fn dequeue_ptr(&self) -> u64 {
+        (self.pa + self.idx as u64 * 16) | self.cycle_bit() as u64
+    }
+}
+
+impl EventRing {
+    fn pop(&mut self) -> Option<Trb> {
+        let p = (self.va + self.idx as u64 * 16) as *const u32;
+        let control = unsafe { read_volatile(p.add(3)) };
+        if (control & TRB_CYCLE != 0) != self.cycle {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        let t = unsafe {
+            Trb {
+                param: read_volatile(p as *const u64),
+                status: read_volatile(p.add(2)),
+                control,
+            }
+        };
+        self.idx += 1;
+        if self.idx == RING_TRBS {
+            self.idx = 0;
+            self.cycle = !self.cycle;
+        }
+        Some(t)
+    }
+
+    fn dequeue_pa(&self) -> u64 {
+        self.pa + self.idx as u64 * 16
+    }
+}
+
+/* ------------------------------- Controller --------------------------------- */
+
+fn spin_until(mut f: impl FnMut() -> bool) -> Result<(), UsbError> {
+    for _ in 0..SPIN_LIMIT {
+        if f() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(UsbError::Timeout)
+}
+
+fn completion(t: &Trb) -> Result<(), UsbError> {
+    match t.code() {
+        CC_SUCCESS | CC_SHORT_PACKET => Ok(()),
+        CC_STALL => Err(UsbError::Stall),
+        c => Err(UsbError::Controller(c)),
+    }
+}
+
+impl Xhci {
+    fn r32(&self, base: u64, off: usize) -> u32 {
+        unsafe { read_volatile((base + off as u64) as *const u32) }
+    }
+
+    fn w32(&self, base: u64, off: usize, v: u32) {
+        unsafe { write_volatile((base + off as u64) as *mut u32, v) }
+    }
+
+    fn w64(&self, base: u64, off: usize, v: u64) {
+        self.w32(base, off, v as u32);
+        self.w32(base, off + 4, (v >> 32) as u32);
+    }
+
+    fn doorbell(&self, slot: u8, target: u32) {
+        self.w32(self.db, 4 * slot as usize, target);
+    }
+
+    fn portsc(&self, port: u8) -> u32 {
+        self.r32(self.op, OP_PORTSC + 0x10 * (port as usize - 1))
+    }
+
+    fn set_portsc(&self, port: u8, v: u32) {
+        self.w32(self.op, OP_PORTSC + 0x10 * (port as usize - 1), v);
+    }
+
+    fn dcbaa_set(&self, slot: usize, pa: u64) {
+        unsafe { write_volatile((self.dcbaa.0 as *mut u64).add(slot), pa) };
+    }
+
+    // Ask firmware to release the controller (USB Legacy Support capability).
+    fn bios_handoff(cap: u64) {
+        let hcc = unsafe { read_volatile((cap + CAP_HCCPARAMS1 as u64) as *const u32) };
+        let mut off = ((hcc >> 16) as u64) << 2;
+        while off != 0 {
+            let p = (cap + off) as *mut u32;
+            let v = unsafe { read_volatile(p) };
+            if v & 0xFF == 1 {
+                unsafe { write_volatile(p, v | 1 << 24) };
+                let _ = spin_until(|| unsafe { read_volatile(p) } & (1 << 16) == 0);
+                return;
+            }
+            let next = ((v >> 8) & 0xFF) as u64;
+            off = if next == 0 { 0 } else { off + (next << 2) };
+        }
+    }
+
+    fn probe() -> Option<Mutex<Self>> {
+        let dev = pci::scan().into_iter().find(|d| {
+            d.class == CLASS_SERIAL && d.subclass == SUBCLASS_USB && d.prog_if == PROGIF_XHCI
+        })?;
+        let (cap, _) = dev.map_bar(0, 1 << 20)?;
+        dev.set_command(CMD_MEM | CMD_BUS_MASTER);
+        match Self::start(cap) {
+            Ok(x) => {
+                pci::hotplug::bind(&dev, "xhci", removed);
+                kprintln!(
+                    "[xhci] {} slot(s), {} port(s), {} device(s)",
+                    x.max_slots,
+                    x.max_ports,
+                    x.devices.len()
+                );
+                Some(Mutex::new(x))
+            }
+            Err(e) => {
+                kprintln!("[xhci] init failed: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn start(cap: u64) -> Result<Self, UsbError> {
+        Self::bios_handoff(cap);
+        let rd = |off: usize| unsafe { read_volatile((cap + off as u64) as *const u32) };
+        let caplen = rd(CAP_LENGTH) & 0xFF;
+        let hcs1 = rd(CAP_HCSPARAMS1);
+        let hcs2 = rd(CAP_HCSPARAMS2);
+        let hcc1 = rd(CAP_HCCPARAMS1);
+
+        let mut x = Self {
+            op: cap + caplen as u64,
+            rt: cap + (rd(CAP_RTSOFF) & !0x1F) as u64,
+            db: cap + (rd(CAP_DBOFF) & !0x3) as u64,
+            max_slots: hcs1 as u8,
+            max_ports: (hcs1 >> 24) as u8,
+            ctx_size: if hcc1 & (1 << 2) != 0 { 64 } else { 32 },
+            dcbaa: page()?,
+            cmd: Ring::new()?,
+            events: EventRing {
+                va: 0,
+                pa: 0,
+                idx: 0,
+                cycle: true,
+            },
+            bounce: page()?,
+            pending: Deque::new(),
+            devices: Vec::new(),
+        };
+
+        // Halt, then reset.
+        let op = x.op;
+        x.w32(op, OP_USBCMD, x.r32(op, OP_USBCMD) & !CMD_RUN);
+        spin_until(|| x.r32(op, OP_USBSTS) & STS_HCH != 0)?;
+        x.w32(op, OP_USBCMD, CMD_HCRST);
+        spin_until(|| x.r32(op, OP_USBCMD) & CMD_HCRST == 0)?;
+        spin_until(|| x.r32(op, OP_USBSTS) & STS_CNR == 0)?;
+
+        x.w32(op, OP_CONFIG, x.max_slots as u32);
+
+        // Scratchpad buffers the controller may demand for itself.
+        let scratch = (((hcs2 >> 21) & 0x1F) << 5 | (hcs2 >> 27)) as usize;
+        if scratch > 0 {
+            let (arr_va, arr_pa) = page()?;
+            for i in 0..scratch.min(512) {
+                let (_, pa) = page()?;
+                unsafe { write_volatile((arr_va as *mut u64).add(i), pa) };
+            }
+            x.dcbaa_set(0, arr_pa);
+        }
+        x.w64(op, OP_DCBAAP, x.dcbaa.1);
+        x.w64(op, OP_CRCR, x.cmd.dequeue_ptr());
+
+        // Event ring: one segment, described by a one-entry ERST.
+        let (ev_va, ev_pa) = page()?;
+        let (erst_va, erst_pa) = page()?;
+        unsafe {
+            write_volatile(erst_va as *mut u64, ev_pa);
+            write_volatile((erst_va + 8) as *mut u32, RING_TRBS as u32);
+        }
+        x.events.va = ev_va;
+        x.events.pa = ev_pa;
+        let rt = x.rt;
+        x.w32(rt, IR0_IMAN, 0); // polled
+        x.w32(rt, IR0_ERSTSZ, 1);
+        x.w64(rt, IR0_ERDP, ev_pa);
+        x.w64(rt, IR0_ERSTBA, erst_pa);
+
+        x.w32(op, OP_USBCMD, CMD_RUN);
+        spin_until(|| x.r32(op, OP_USBSTS) & STS_HCH == 0)?;
+
+        for port in 1..=x.max_ports {
+            if x.portsc(port) & PORTSC_CCS == 0 {
+                continue;
+            }
+            match x.enumerate(port) {
+                Ok(d) => {
+                    kprintln!(
+                        "[xhci] port {}: slot {} {:?} usb {:x}.{:02x} {:04x}:{:04x} class {:02x}.{:02x}.{:02x}",
+                        port,
+                        d.slot,
+                        d.speed,
+                        d.desc.usb_version >> 8,
+                        d.desc.usb_version & 0xFF,
+                        d.desc.vendor,
+                        d.desc.product,
+                        d.desc.class,
+                        d.desc.subclass,
+                        d.desc.protocol
+                    );
+                    x.devices.push(d);
+                }
+                Err(e) => kprintln!("[xhci] port {}: enumeration failed: {:?}", port, e),
+            }
+        }
+        Ok(x)
+    }
+
+    /* ------------------------------ Event plumbing ------------------------------ */
+
+    fn next_event(&mut self) -> Option<Trb> {
+        let t = self.events.pop()?;
+        let erdp = self.events.dequeue_pa() | ERDP_EHB;
+        self.w64(self.rt, IR0_ERDP, erdp);
+        Some(t)
+    }
+
+    /// Consume events until one matching `want` arrives. Transfer events for
+    /// other endpoints are parked for `poll_transfer`; anything else (port
+    /// status changes) is dropped.
+    fn wait_event(&mut self, want: impl Fn(&Trb) -> bool) -> Result<Trb, UsbError> {
+        for _ in 0..SPIN_LIMIT {
+            match self.next_event() {
+                Some(t) if want(&t) => return Ok(t),
+                Some(t) if t.kind() == EV_TRANSFER => {
+                    let _ = self.pending.push_back(t);
+                }
+                Some(_) => {}
+                None => core::hint::spin_loop(),
+            }
+        }
+        Err(UsbError::Timeout)
+    }
+
+    fn command(&mut self, param: u64, control: u32) -> Result<Trb, UsbError> {
+        self.cmd.push(param, 0, control);
+        self.doorbell(0, 0);
+        let t = self.wait_event(|t| t.kind() == EV_COMMAND)?;
+        completion(&t)?;
+        Ok(t)
+    }
+
+    /* ------------------------------- Enumeration ------------------------------- */
+
+    fn reset_port(&mut self, port: u8) -> Result<Speed, UsbError> {
+        let keep = |v: u32| (v & !PORTSC_RW1C & !PORTSC_PED) | PORTSC_PP;
+        let v = self.portsc(port);
+        self.set_portsc(port, keep(v) | PORTSC_PR);
+        spin_until(|| self.portsc(port) & PORTSC_PRC != 0)?;
+        let v = self.portsc(port);
+        self.set_portsc(port, keep(v) | PORTSC_PRC);
+        if v & PORTSC_PED == 0 {
+            return Err(UsbError::NoDevice);
+        }
+        Ok(match (v >> 10) & 0xF {
+            2 => Speed::Low,
+            3 => Speed::High,
+            4 | 5 => Speed::Super,
+            _ => Speed::Full,
+        })
+    }
+
+    /// Pointer to context `i` (0 = slot, 1 = EP0, ...) inside an input context,
+    /// which is prefixed by the input control context.
+    fn input_ctx(&self, dev: &UsbDevice, i: usize) -> *mut u32 {
+        (dev.input_ctx.0 + ((i + 1) * self.ctx_size) as u64) as *mut u32
+    }
+
+    fn set_add_flags(&self, dev: &UsbDevice, add: u32) {
+        let icc = dev.input_ctx.0 as *mut u32;
+        unsafe {
+            core::ptr::write_bytes(icc as *mut u8, 0, self.ctx_size);
+            write_volatile(icc.add(1), add);
+        }
+    }
+
+    fn enumerate(&mut self, port: u8) -> Result<UsbDevice, UsbError> {
+        let speed = self.reset_port(port)?;
+        let slot = self.command(0, TRB_ENABLE_SLOT << 10)?.slot();
+
+        let mut dev = UsbDevice {
+            slot,
+            port,
+            speed,
+            desc: DeviceDescriptor::default(),
+            ep0: Ring::new()?,
+            input_ctx: page()?,
+            out_ctx: page()?,
+            rings: Default::default(),
+        };
+        self.dcbaa_set(slot as usize, dev.out_ctx.1);
+
+        // Slot + EP0, then Address Device.
+        self.set_add_flags(&dev, 0b11);
+        let speed_id = match speed {
+            Speed::Full => 1,
+            Speed::Low => 2,
+            Speed::High => 3,
+            Speed::Super => 4,
+        };
+        let sc = self.input_ctx(&dev, 0);
+        let ep0 = self.input_ctx(&dev, 1);
+        unsafe {
+            write_volatile(sc, (1 << 27) | (speed_id << 20));
+            write_volatile(sc.add(1), (port as u32) << 16);
+            write_volatile(
+                ep0.add(1),
+                (3 << 1) | (4 << 3) | ((speed.ep0_max_packet() as u32) << 16),
+            );
+            let dq = dev.ep0.dequeue_ptr();
+            write_volatile(ep0.add(2), dq as u32);
+            write_volatile(ep0.add(3), (dq >> 32) as u32);
+            write_volatile(ep0.add(4), 8);
+        }
+        self.command(
+            dev.input_ctx.1,
+            (TRB_ADDRESS_DEVICE << 10) | ((slot as u32) << 24),
+        )?;
+
+        // First 8 bytes tell us EP0's real packet size.
+        let mut buf = [0u8; 18];
+        self.control(
+            &mut dev,
+            SetupPacket::get_descriptor(DESC_DEVICE, 0, 8),
+            &mut buf[..8],
+        )?;
+        let first = DeviceDescriptor::parse(&buf[..8]).ok_or(UsbError::NoDevice)?;
+        let mps = first.max_packet0 as u16;
+        let mps = if speed == Speed::Super {
+            1 << mps.min(9)
+        } else {
+            mps
+        };
+        if mps != 0 && mps != speed.ep0_max_packet() {
+            self.set_add_flags(&dev, 0b10);
+            unsafe {
+                let v = read_volatile(ep0.add(1));
+                write_volatile(ep0.add(1), (v & 0xFFFF) | ((mps as u32) << 16));
+            }
+            self.command(
+                dev.input_ctx.1,
+                (TRB_EVALUATE_CTX << 10) | ((slot as u32) << 24),
+            )?;
+        }
+        let n = self.control(
+            &mut dev,
+            SetupPacket::get_descriptor(DESC_DEVICE, 0, 18),
+            &mut buf,
+        )?;
+        dev.desc = DeviceDescriptor::parse(&buf[..n]).ok_or(UsbError::NoDevice)?;
+        Ok(dev)
+    }
+
+    /* --------------------------- Control transfers --------------------------- */
+
+    /// Run a control transfer on EP0. `data` is the data stage (IN or OUT per
+    /// `setup`), at most one page. Returns the bytes transferred.
+    pub fn control(
+        &mut self,
+        dev: &mut UsbDevice,
+        setup: SetupPacket,
+        data: &mut [u8],
+    ) -> Result<usize, UsbError> {
+        let len = data.len().min(setup.length as usize).min(4096);
+        let dir_in = setup.is_in();
+        let trt = match (len, dir_in) {
+            (0, _) => 0,
+            (_, false) => 2,
+            (_, true) => 3,
+        };
+        if !dir_in && len > 0 {
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), self.bounce.0 as *mut u8, len) };
+        }
+
+        dev.ep0
+            .push(setup.to_u64(), 8, (TRB_SETUP << 10) | TRB_IDT | (trt << 16));
+        if len > 0 {
+            let dir = if dir_in { TRB_DIR_IN } else { 0 };
+            dev.ep0
+                .push(self.bounce.1, len as u32, (TRB_DATA << 10) | TRB_ISP | dir);
+        }
+        // Status stage runs opposite to the data stage (IN when there is none).
+        let status_dir = if len > 0 && dir_in { 0 } else { TRB_DIR_IN };
+        dev.ep0
+            .push(0, 0, (TRB_STATUS << 10) | TRB_IOC | status_dir);
+        self.doorbell(dev.slot, 1);
+
+        let slot = dev.slot;
+        let ep0 = |t: &Trb| t.kind() == EV_TRANSFER && t.slot() == slot && t.dci() == 1;
+        let mut ev = self.wait_event(ep0)?;
+        // A short data stage reports first; the status stage follows.
+        let mut residue = 0;
+        if ev.code() == CC_SHORT_PACKET {
+            residue = ev.residue();
+            ev = self.wait_event(ep0)?;
+        }
+        completion(&ev)?;
+        if dir_in && len > 0 {
+            unsafe {
+                core::ptr::copy_nonoverlapping(self.bounce.0 as *const u8, data.as_mut_ptr(), len)
+            };
+        }
+        Ok(len.saturating_sub(residue))
+    }
+
+    /// `control()` on the `idx`th enumerated device.
+    pub fn control_at(
+        &mut self,
+        idx: usize,
+        setup: SetupPacket,
+        data: &mut [u8],
+    ) -> Result<usize, UsbError> {
+        let mut dev = self.devices.remove(idx);
+        let r = self.control(&mut dev, setup, data);
+        self.devices.insert(idx, dev);
+        r
+    }
+
+    /* --------------------------- Interrupt endpoints --------------------------- */
+
+    /// Add an interrupt IN endpoint to device `idx` (Configure Endpoint).
+    /// Returns its DCI for `queue_in` / `poll_transfer`.
+    pub fn configure_interrupt_in(
+        &mut self,
+        idx: usize,
+        ep: &EndpointDescriptor,
+    ) -> Result<u8, UsbError> {
+        let dci = ((ep.address & 0xF) * 2 + 1) as usize;
+        let ring = Ring::new()?;
+        let buf = page()?;
+        let dev = &self.devices[idx];
+        // bInterval: frames for LS/FS, 2^(n-1) microframes for HS/SS;
+        // the context wants log2 of the period in 125 us units.
+        let interval = match dev.speed {
+            Speed::Low | Speed::Full => (ep.interval.max(1) as u32 * 8).ilog2(),
+            _ => ep.interval.clamp(1, 16) as u32 - 1,
+        };
+        self.set_add_flags(dev, 1 | (1 << dci));
+        let sc = self.input_ctx(dev, 0);
+        let ec = self.input_ctx(dev, dci);
+        let out_sc = dev.out_ctx.0 as *const u32;
+        unsafe {
+            core::ptr::write_bytes(ec as *mut u8, 0, self.ctx_size);
+            for i in 0..4 {
+                write_volatile(sc.add(i), read_volatile(out_sc.add(i)));
+            }
+            let entries = (read_volatile(sc) >> 27).max(dci as u32);
+            write_volatile(sc, (read_volatile(sc) & !(0x1F << 27)) | (entries << 27));
+            write_volatile(ec, interval << 16);
+            write_volatile(
+                ec.add(1),
+                (3 << 1) | (7 << 3) | ((ep.max_packet as u32) << 16),
+            );
+            let dq = ring.dequeue_ptr();
+            write_volatile(ec.add(2), dq as u32);
+            write_volatile(ec.add(3), (dq >> 32) as u32);
+            write_volatile(
+                ec.add(4),
+                (ep.max_packet as u32) << 16 | ep.max_packet as u32,
+            );
+        }
+        let (pa, slot) = (dev.input_ctx.1, dev.slot);
+        self.command(pa, (TRB_CONFIGURE_EP << 10) | ((slot as u32) << 24))?;
+        self.devices[idx].rings[dci] = Some((ring, buf));
+        Ok(dci as u8)
+    }
+
+    /// Post one IN transfer of `len` bytes into the endpoint's DMA page.
+    pub fn queue_in(&mut self, idx: usize, dci: u8, len: usize) -> Result<(), UsbError> {
+        let dev = &mut self.devices[idx];
+        let slot = dev.slot;
+        let (ring, buf) = dev.rings[dci as usize].as_mut().ok_or(UsbError::NoDevice)?;
+        ring.push(
+            buf.1,
+            len.min(4096) as u32,
+            (TRB_NORMAL << 10) | TRB_IOC | TRB_ISP,
+        );
+        self.doorbell(slot, dci as u32);
+        Ok(())
+    }
+
+    /// Non-blocking: has a transfer on (`idx`, `dci`) completed? On success
+    /// the data is copied into `out` and its length returned.
+    pub fn poll_transfer(
+        &mut self,
+        idx: usize,
+        dci: u8,
+        out: &mut [u8],
+    ) -> Option<Result<usize, UsbError>> {
+        let slot = self.devices[idx].slot;
+        let mine = |t: &Trb| t.kind() == EV_TRANSFER && t.slot() == slot && t.dci() == dci;
+        let ev = match self.pending.iter().position(mine) {
+            Some(_) => {
+                // Rotate until the match is at the front; keeps the rest in order.
+                loop {
+                    let t = self.pending.pop_front()?;
+                    if mine(&t) {
+                        break t;
+                    }
+                    let _ = self.pending.push_back(t);
+                }
+            }
+            None => loop {
+                let t = self.next_event()?;
+                if mine(&t) {
+                    break t;
+                }
+                if t.kind() == EV_TRANSFER {
+                    let _ = self.pending.push_back(t);
+                }
+            },
+        };
+        if let Err(e) = completion(&ev) {
+            return Some(Err(e));
+        }
+        let (_, buf) = self.devices[idx].rings[dci as usize].as_ref()?;
+        // Requested length is not in the event; callers ask for `out.len()`.
+        let n = out.len().saturating_sub(ev.residue()).min(4096);
+        unsafe { core::ptr::copy_nonoverlapping(buf.0 as *const u8, out.as_mut_ptr(), n) };
+        Some(Ok(n))
+    }
+
+    /// Full configuration descriptor (interfaces + endpoints), into `buf`.
+    pub fn config_descriptor(
+        &mut self,
+        dev: &mut UsbDevice,
+        buf: &mut [u8],
+    ) -> Result<usize, UsbError> {
+        let mut head = [0u8; 9];
+        self.control(
+            dev,
+            SetupPacket::get_descriptor(DESC_CONFIGURATION, 0, 9),
+            &mut head,
+        )?;
+        let total = (u16::from_le_bytes([head[2], head[3]]) as usize).min(buf.len());
+        self.control(
+            dev,
+            SetupPacket::get_descriptor(DESC_CONFIGURATION, 0, total as u16),
+            &mut buf[..total],
+        )
+    }
+
+    /// `config_descriptor()` on the `idx`th enumerated device.
+    pub fn config_descriptor_at(&mut self, idx: usize, buf: &mut [u8]) -> Result<usize, UsbError> {
+        let mut dev = self.devices.remove(idx);
+        let r = self.config_descriptor(&mut dev, buf);
+        self.devices.insert(idx, dev);
+        r
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Probe the first xHCI controller and enumerate its root ports.
+pub fn init() {
+    XHCI.call_once(Xhci::probe);
+}
+
+/// Run `f` with the controller locked (IRQs off), if one was found.
+pub fn with<R>(f: impl FnOnce(&mut Xhci) -> R) -> Option<R> {
+    let x = XHCI.get()?.as_ref()?;
+    without_interrupts(|| {
+        let mut g = x.lock();
+        // Checked under the lock: `removed` takes it after setting the flag.
+        (!REMOVED.load(Ordering::Acquire)).then(|| f(&mut g))
+    })
+}
+
+// Unplugged: once the current user is done, nobody touches the registers.
+fn removed(_: &pci::PciDevice) {
+    REMOVED.store(true, Ordering::Release);
+    if let Some(x) = XHCI.get().and_then(|x| x.as_ref()) {
+        without_interrupts(|| drop(x.lock()));
+    }
+}
@@ -0,0 +1,341 @@
+// src/util/lz4.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// LZ4 for kernel use: a greedy block compressor small enough for the panic
+// path (crash records), the block decompressor, and the frame format
+// (`lz4` tool output, magic 0x184D2204) for compressed images jotunboot
+// hands over. Heap-free; callers supply the output buffers. Every length and
+// offset in untrusted input is bounds-checked.
+//
+// jotunboot does not pass an image through yet, so only hosttest/ calls the
+// decoders for now; they are marked as such for the kernel build.
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5; // a block always ends in at least this many literals
+const MF_LIMIT: usize = 12; // no match may start in the last MF_LIMIT bytes
+const MAX_OFFSET: usize = 0xFFFF;
+const HASH_LOG: u32 = 10; // 4 KiB of table on the stack
+const SKIP_TRIGGER: u32 = 6; // speed up over incompressible runs
+
+const FRAME_MAGIC: u32 = 0x184D_2204;
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50; // low nibble free
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Lz4Error {
+    OutputFull,
+    Truncated,
+    BadOffset,
+    BadMagic,
+    BadHeader,
+    BadChecksum,
+    Unsupported, // dictionary IDs
+}
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn read_u32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+fn hash(v: u32) -> usize {
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+/// Worst-case compressed size of `len` input bytes.
+pub const fn bound(len: usize) -> usize {
+    len + len / 255 + 16
+}
+
+struct Out<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Out<'_> {
+    fn byte(&mut self, b: u8) -> Result<(), Lz4Error> {
+        *self.buf.get_mut(self.pos).ok_or(Lz4Error::OutputFull)? = b;
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> Result<(), Lz4Error> {
+        let end = self.pos + b.len();
+        self.buf
+            .get_mut(self.pos..end)
+            .ok_or(Lz4Error::OutputFull)?
+            .copy_from_slice(b);
+        self.pos = end;
+        Ok(())
+    }
+
+    // The 255-run length extension after a saturated token nibble.
+    fn length(&mut self, mut n: usize) -> Result<(), Lz4Error> {
+        while n >= 255 {
+            self.byte(255)?;
+            n -= 255;
+        }
+        self.byte(n as u8)
+    }
+
+    fn sequence(&mut self, lit: &[u8], m: Option<(usize, usize)>) -> Result<(), Lz4Error> {
+        let ml = m.map_or(0, |(_, len)| len - MIN_MATCH);
+        self.byte((lit.len().min(15) as u8) << 4 | ml.min(15) as u8)?;
+        if lit.len() >= 15 {
+            self.length(lit.len() - 15)?;
+        }
+        self.bytes(lit)?;
+        if let Some((off, _)) = m {
+            self.bytes(&(off as u16).to_le_bytes())?;
+            if ml >= 15 {
+                self.length(ml - 15)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/* --------------------------------- Blocks ---------------------------------- */
+
+/// Compress `src` as one LZ4 block into `dst`; returns the compressed size.
+/// `dst` of `bound(src.len())` bytes always suffices.
+pub fn compress(src: &[u8], dst: &mut [u8]) -> Result<usize, Lz4Error> {
+    let mut out = Out { buf: dst, pos: 0 };
+    let mut anchor = 0;
+    if src.len() > MF_LIMIT {
+        let mut table = [u32::MAX; 1 << HASH_LOG];
+        let limit = src.len() - MF_LIMIT;
+        let match_end = src.len() - LAST_LITERALS;
+        let mut i = 0;
+        let mut misses = 0u32;
+        while i < limit {
+            let v = read_u32(src, i);
+            let h = hash(v);
+            let cand = table[h] as usize;
+            table[h] = i as u32;
+            if cand < i && i - cand <= MAX_OFFSET && read_u32(src, cand) == v {
+                let mut len = MIN_MATCH;
+                while i + len < match_end && src[cand + len] == src[i + len] {
+                    len += 1;
+                }
+                out.sequence(&src[anchor..i], Some((i - cand, len)))?;
+                i += len;
+                anchor = i;
+                misses = 0;
+            } else {
+                misses += 1;
+                i += 1 + (misses >> SKIP_TRIGGER) as usize;
+            }
+        }
+    }
+    out.sequence(&src[anchor..], None)?;
+    Ok(out.pos)
+}
+
+/// Decompress one block into `dst[pos..]`. Matches may reach back into
+/// `dst[..pos]`, which is how linked frame blocks share history. Returns the
+/// new end of output.
+pub fn decompress_into(src: &[u8], dst: &mut [u8], mut pos: usize) -> Result<usize, Lz4Error> {
+    let mut i = 0;
+    let take_len = |i: &mut usize, mut n: usize| -> Result<usize, Lz4Error> {
+        loop {
+            let b = *src.get(*i).ok_or(Lz4Error::Truncated)?;
+            *i += 1;
+            n += b as usize;
+            if b != 255 {
+                return Ok(n);
+            }
+        }
+    };
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+
+        let mut lit = (token >> 4) as usize;
+        if lit == 15 {
+            lit = take_len(&mut i, lit)?;
+        }
+        let lits = src.get(i..i + lit).ok_or(Lz4Error::Truncated)?;
+        dst.get_mut(pos..pos + lit)
+            .ok_or(Lz4Error::OutputFull)?
+            .copy_from_slice(lits);
+        i += lit;
+        pos += lit;
+        if i == src.len() {
+            break; // the last sequence has no match
+        }
+
+        let off = u16::from_le_bytes([
+            *src.get(i).ok_or(Lz4Error::Truncated)?,
+            *src.get(i + 1).ok_or(Lz4Error::Truncated)?,
+        ]) as usize;
+        i += 2;
+        if off == 0 || off > pos {
+            return Err(Lz4Error::BadOffset);
+        }
+        let mut len = (token & 0xF) as usize;
+        if len == 15 {
+            len = take_len(&mut i, len)?;
+        }
+        len += MIN_MATCH;
+        if pos + len > dst.len() {
+            return Err(Lz4Error::OutputFull);
+        }
+        if off >= len {
+            dst.copy_within(pos - off..pos - off + len, pos);
+        } else {
+            // Overlapping: the match repeats the bytes it is producing.
+            for k in 0..len {
+                dst[pos + k] = dst[pos + k - off];
+            }
+        }
+        pos += len;
+    }
+    Ok(pos)
+}
+
+#[cfg_attr(target_os = "none", allow(dead_code))]
+pub fn decompress(src: &[u8], dst: &mut [u8]) -> Result<usize, Lz4Error> {
+    decompress_into(src, dst, 0)
+}
+
+/* --------------------------------- xxHash32 --------------------------------- */
+
+const P1: u32 = 2_654_435_761;
+const P2: u32 = 2_246_822_519;
+const P3: u32 = 3_266_489_917;
+const P4: u32 = 668_265_263;
+const P5: u32 = 374_761_393;
+
+fn round(acc: u32, lane: u32) -> u32 {
+    acc.wrapping_add(lane.wrapping_mul(P2))
+        .rotate_left(13)
+        .wrapping_mul(P1)
+}
+
+/// xxHash32, which the frame format uses for its checksums.
+pub fn xxh32(data: &[u8], seed: u32) -> u32 {
+    let mut i = 0;
+    let mut h = if data.len() >= 16 {
+        let mut v = [
+            seed.wrapping_add(P1).wrapping_add(P2),
+            seed.wrapping_add(P2),
+            seed,
+            seed.wrapping_sub(P1),
+        ];
+        while i + 16 <= data.len() {
+            for (k, acc) in v.iter_mut().enumerate() {
+                *acc = round(*acc, read_u32(data, i + 4 * k));
+            }
+            i += 16;
+        }
+        v[0].rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18))
+    } else {
+        seed.wrapping_add(P5)
+    };
+    h = h.wrapping_add(data.len() as u32);
+    while i + 4 <= data.len() {
+        h = h.wrapping_add(read_u32(data, i).wrapping_mul(P3));
+        h = h.rotate_left(17).wrapping_mul(P4);
+        i += 4;
+    }
+    for &b in &data[i..] {
+        h = h.wrapping_add((b as u32).wrapping_mul(P5));
+        h = h.rotate_left(11).wrapping_mul(P1);
+    }
+    h ^= h >> 15;
+    h = h.wrapping_mul(P2);
+    h ^= h >> 13;
+    h = h.wrapping_mul(P3);
+    h ^ (h >> 16)
+}
+
+/* --------------------------------- Frames ---------------------------------- */
+
+/// Does `src` start with an LZ4 frame?
+#[cfg_attr(target_os = "none", allow(dead_code))]
+pub fn is_frame(src: &[u8]) -> bool {
+    src.len() >= 4 && read_u32(src, 0) == FRAME_MAGIC
+}
+
+/// Decode a whole LZ4 frame (as written by the `lz4` tool) into `dst`,
+/// checking whichever checksums the frame carries. Skippable frames before
+/// it are passed over. Returns the decompressed size.
+#[cfg_attr(target_os = "none", allow(dead_code))]
+pub fn decompress_frame(src: &[u8], dst: &mut [u8]) -> Result<usize, Lz4Error> {
+    let need = |at: usize, n: usize| src.get(at..at + n).ok_or(Lz4Error::Truncated);
+
+    let mut i = 0;
+    loop {
+        let magic = read_u32(need(i, 4)?, 0);
+        if magic == FRAME_MAGIC {
+            break;
+        }
+        if magic & !0xF != SKIPPABLE_MAGIC {
+            return Err(Lz4Error::BadMagic);
+        }
+        i += 8 + read_u32(need(i + 4, 4)?, 0) as usize;
+    }
+    i += 4;
+
+    let desc = i;
+    let flg = need(i, 1)?[0];
+    let bd = need(i + 1, 1)?[0];
+    i += 2;
+    if flg >> 6 != 0b01 || flg & 0b10 != 0 || bd & 0x8F != 0 {
+        return Err(Lz4Error::BadHeader);
+    }
+    if flg & 0b1 != 0 {
+        return Err(Lz4Error::Unsupported); // dictionary ID
+    }
+    let block_sum = flg & 0x10 != 0;
+    let content_sum = flg & 0x04 != 0;
+    let mut size = None;
+    if flg & 0x08 != 0 {
+        let b = need(i, 8)?;
+        size = Some(u64::from_le_bytes(b.try_into().unwrap()) as usize);
+        i += 8;
+    }
+    let hc = need(i, 1)?[0];
+    if (xxh32(&src[desc..i], 0) >> 8) as u8 != hc {
+        return Err(Lz4Error::BadChecksum);
+    }
+    i += 1;
+
+    let mut pos = 0;
+    loop {
+        let word = read_u32(need(i, 4)?, 0);
+        i += 4;
+        if word == 0 {
+            break; // EndMark
+        }
+        let len = (word & !BLOCK_UNCOMPRESSED) as usize;
+        let data = need(i, len)?;
+        if block_sum && read_u32(need(i + len, 4)?, 0) != xxh32(data, 0) {
+            return Err(Lz4Error::BadChecksum);
+        }
+        // Independent blocks never reach back, so sharing dst is harmless.
+        if word & BLOCK_UNCOMPRESSED != 0 {
+            dst.get_mut(pos..pos + len)
+                .ok_or(Lz4Error::OutputFull)?
+                .copy_from_slice(data);
+            pos += len;
+        } else {
+            pos = decompress_into(data, dst, pos)?;
+        }
+        i += len + if block_sum { 4 } else { 0 };
+    }
+
+    if content_sum && read_u32(need(i, 4)?, 0) != xxh32(&dst[..pos], 0) {
+        return Err(Lz4Error::BadChecksum);
+    }
+    if size.is_some_and(|s| s != pos) {
+        return Err(Lz4Error::Truncated);
+    }
+    Ok(pos)
+}
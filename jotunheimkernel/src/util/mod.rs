@@ -1,5 +1,9 @@
 // SPDX-License-Identifier: JOSSL-1.0
 // Copyright (C) 2025 The Jotunheim Project
+pub mod crc32;
+pub mod lz4;
+pub mod ring;
+
 unsafe extern "C" {
     unsafe static __bss_start: u8;
     unsafe static __bss_end: u8;
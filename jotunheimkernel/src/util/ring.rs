@@ -0,0 +1,388 @@
+// src/util/ring.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Fixed-capacity rings with typed ends.
+//   Ring          lock-free SPSC; `split()` hands out the only Producer/Consumer
+//   MpscRing      any number of producers (IRQ-safe), one Consumer
+//   BlockingRing  MpscRing whose consumer can sleep until data arrives
+//   OverwriteRing full pushes evict the oldest entry (logs, traces)
+// Indices are free-running counters; `tail - head` is the fill level.
+// Parts of the API only hosttest/ uses so far are marked for the kernel build.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use spin::Mutex;
+#[cfg(target_os = "none")]
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::sched::waitq::WaitQueue;
+
+// Built for the host by hosttest/, where there is no IF to clear.
+#[cfg(not(target_os = "none"))]
+fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub struct Ring<T, const N: usize> {
+    buf: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicUsize, // next slot to read
+    tail: AtomicUsize, // next slot to write
+    split: AtomicBool,
+}
+
+// Each slot is owned by exactly one side at a time (see push/pop).
+unsafe impl<T: Send, const N: usize> Sync for Ring<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for Ring<T, N> {}
+
+pub struct Producer<'a, T, const N: usize> {
+    ring: &'a Ring<T, N>,
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    ring: &'a Ring<T, N>,
+}
+
+pub struct MpscRing<T, const N: usize> {
+    ring: Ring<T, N>,
+    push_lock: Mutex<()>,
+}
+
+pub struct BlockingRing<T, const N: usize> {
+    inner: MpscRing<T, N>,
+    readers: WaitQueue, // the consumer, while it sleeps in pop_wait
+}
+
+pub struct BlockingConsumer<'a, T, const N: usize> {
+    ring: &'a BlockingRing<T, N>,
+    rx: Consumer<'a, T, N>,
+}
+
+struct Slots<T, const N: usize> {
+    buf: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+pub struct OverwriteRing<T, const N: usize> {
+    slots: Mutex<Slots<T, N>>,
+    dropped: AtomicUsize,
+}
+
+/* ---------------------------------- SPSC ------------------------------------ */
+
+impl<T, const N: usize> Ring<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// The two ends. Succeeds once per ring, so each end stays unique.
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn split(&self) -> Option<(Producer<'_, T, N>, Consumer<'_, T, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((Producer { ring: self }, Consumer { ring: self }))
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn is_full(&self) -> bool {
+        self.len() >= N
+    }
+
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    // Only the (single) producer calls this.
+    fn push(&self, v: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) >= N {
+            return Err(v);
+        }
+        unsafe { (*self.buf[tail % N].get()).write(v) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    // Only the (single) consumer calls this.
+    fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let v = unsafe { (*self.buf[head % N].get()).assume_init_read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(v)
+    }
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    /// `push` for a ring one owner uses as a plain FIFO, without `split()`.
+    pub fn push_mut(&mut self, v: T) -> Result<(), T> {
+        self.push(v)
+    }
+
+    /// `pop` for a ring one owner uses as a plain FIFO, without `split()`.
+    pub fn pop_mut(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+impl<T, const N: usize> Default for Ring<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Ring<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg_attr(target_os = "none", allow(dead_code))]
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Hands the value back if the ring is full.
+    pub fn push(&mut self, v: T) -> Result<(), T> {
+        self.ring.push(v)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        self.ring.pop()
+    }
+
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Pop everything currently queued.
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        core::iter::from_fn(move || self.ring.pop())
+    }
+}
+
+/* ---------------------------------- MPSC ------------------------------------ */
+
+impl<T, const N: usize> MpscRing<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            ring: Ring::new(),
+            push_lock: Mutex::new(()),
+        }
+    }
+
+    /// Any context, IRQ handlers included.
+    pub fn push(&self, v: T) -> Result<(), T> {
+        without_interrupts(|| {
+            let _g = self.push_lock.lock();
+            self.ring.push(v)
+        })
+    }
+
+    /// The single consumer end; `None` after the first call.
+    pub fn consumer(&self) -> Option<Consumer<'_, T, N>> {
+        if self.ring.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some(Consumer { ring: &self.ring })
+    }
+
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+impl<T, const N: usize> Default for MpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* -------------------------------- Blocking ---------------------------------- */
+
+impl<T, const N: usize> BlockingRing<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            inner: MpscRing::new(),
+            readers: WaitQueue::new(),
+        }
+    }
+
+    /// Push and wake the consumer if it is asleep.
+    pub fn push(&self, v: T) -> Result<(), T> {
+        self.inner.push(v)?;
+        self.readers.notify_one();
+        Ok(())
+    }
+
+    pub fn consumer(&self) -> Option<BlockingConsumer<'_, T, N>> {
+        Some(BlockingConsumer {
+            ring: self,
+            rx: self.inner.consumer()?,
+        })
+    }
+}
+
+impl<T, const N: usize> Default for BlockingRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> BlockingConsumer<'_, T, N> {
+    #[cfg_attr(target_os = "none", allow(dead_code))]
+    pub fn pop(&mut self) -> Option<T> {
+        self.rx.pop()
+    }
+
+    /// Sleep until an item is available. Before the scheduler runs this
+    /// waits for interrupts instead (see `WaitQueue`).
+    pub fn pop_wait(&mut self) -> T {
+        loop {
+            if let Some(v) = self.rx.pop() {
+                return v;
+            }
+            self.ring.readers.wait_until(|| !self.rx.is_empty());
+        }
+    }
+}
+
+/* -------------------------------- Overwrite --------------------------------- */
+
+impl<T, const N: usize> OverwriteRing<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: Mutex::new(Slots {
+                buf: [const { None }; N],
+                head: 0,
+                len: 0,
+            }),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Never fails: when full, the oldest entry is evicted and returned.
+    pub fn push(&self, v: T) -> Option<T> {
+        without_interrupts(|| self.slots.lock().push(v, &self.dropped))
+    }
+
+    /// As `push`, but hands `v` back rather than wait when the ring is
+    /// locked: for fault handlers, which may interrupt `try_for_each`.
+    pub fn try_push(&self, v: T) -> Result<Option<T>, T> {
+        without_interrupts(|| match self.slots.try_lock() {
+            Some(mut s) => Ok(s.push(v, &self.dropped)),
+            None => Err(v),
+        })
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        without_interrupts(|| {
+            let mut s = self.slots.lock();
+            if s.len == 0 {
+                return None;
+            }
+            let head = s.head;
+            s.head = (head + 1) % N;
+            s.len -= 1;
+            s.buf[head].take()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        without_interrupts(|| self.slots.lock().len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entries lost to overwriting since creation.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// A copy of the oldest entry, left in place.
+    pub fn front(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        without_interrupts(|| {
+            let s = self.slots.lock();
+            s.buf[s.head].clone() // None when empty: pops take their slot
+        })
+    }
+
+    /// Visit the held entries, oldest first, without removing them. False
+    /// if the ring was locked (called from a handler that interrupted it).
+    pub fn try_for_each(&self, mut f: impl FnMut(&T)) -> bool {
+        without_interrupts(|| {
+            let Some(s) = self.slots.try_lock() else {
+                return false;
+            };
+            for i in 0..s.len {
+                if let Some(v) = &s.buf[(s.head + i) % N] {
+                    f(v);
+                }
+            }
+            true
+        })
+    }
+}
+
+impl<T, const N: usize> Slots<T, N> {
+    fn push(&mut self, v: T, dropped: &AtomicUsize) -> Option<T> {
+        let idx = (self.head + self.len) % N;
+        if self.len == N {
+            self.head = (self.head + 1) % N;
+            dropped.fetch_add(1, Ordering::Relaxed);
+            self.buf[idx].replace(v)
+        } else {
+            self.len += 1;
+            self.buf[idx] = Some(v);
+            None
+        }
+    }
+}
+
+impl<T, const N: usize> Default for OverwriteRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,169 @@
+// src/video/console.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Text console on the framebuffers `video::outputs()` selects, fed as a log
+// mirror. When mirroring, the grid is sized to the smallest screen so every
+// output shows the same lines. Characters are kept in a cell grid so scrolling
+// only writes to video memory, never reads it back.
+#![allow(dead_code)]
+
+extern crate alloc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::Screen;
+use crate::arch::x86_64::serial;
+use crate::gfx::{self, Painter, TextStyle, font};
+use crate::{cmdline, kprintln};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const FG: u32 = gfx::GREY;
+const BG: u32 = gfx::BLACK;
+const TAB: usize = 8;
+// Screens at least this wide get 2x glyphs.
+const SCALE2_WIDTH: usize = 2560;
+
+struct Console {
+    targets: heapless::Vec<&'static Screen, { crate::bootinfo::MAX_FRAMEBUFFERS }>,
+    style: TextStyle,
+    cols: usize,
+    rows: usize,
+    cells: Vec<u8>,
+    col: usize,
+    row: usize,
+}
+
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+
+/* --------------------------------- Drawing ---------------------------------- */
+
+impl Console {
+    fn new() -> Option<Self> {
+        let mut targets = heapless::Vec::new();
+        for s in super::outputs() {
+            let _ = targets.push(s);
+        }
+        let width = targets.iter().map(|s| s.width).min()?;
+        let height = targets.iter().map(|s| s.height).min()?;
+        // `fbfont=8x8|8x16`
+        let font = cmdline::value("fbfont")
+            .and_then(font::by_name)
+            .unwrap_or(&font::FONT_8X16);
+        let scale = if width >= SCALE2_WIDTH { 2 } else { 1 };
+        let style = TextStyle::new(font, FG).scaled(scale).on(BG);
+        let (cw, ch) = style.cell();
+        let cols = width / cw as usize;
+        let rows = height / ch as usize;
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+        Some(Self {
+            targets,
+            style,
+            cols,
+            rows,
+            cells: vec![b' '; cols * rows],
+            col: 0,
+            row: 0,
+        })
+    }
+
+    fn draw_cell(&self, col: usize, row: usize) {
+        let c = self.cells[row * self.cols + col];
+        let (cw, ch) = self.style.cell();
+        for mut s in self.targets.iter().copied() {
+            Painter::new(&mut s).glyph(col as i32 * cw, row as i32 * ch, c, &self.style);
+        }
+    }
+
+    fn redraw(&self) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                self.draw_cell(col, row);
+            }
+        }
+    }
+
+    fn scroll(&mut self) {
+        self.cells.copy_within(self.cols.., 0);
+        let last = (self.rows - 1) * self.cols;
+        self.cells[last..].fill(b' ');
+        self.row = self.rows - 1;
+        self.redraw();
+    }
+
+    fn newline(&mut self) {
+        self.col = 0;
+        self.row += 1;
+        if self.row == self.rows {
+            self.scroll();
+        }
+    }
+
+    fn putc(&mut self, c: u8) {
+        match c {
+            b'\n' => self.newline(),
+            b'\r' => self.col = 0,
+            b'\t' => {
+                let next = (self.col / TAB + 1) * TAB;
+                while self.col < next.min(self.cols) {
+                    self.putc(b' ');
+                }
+            }
+            _ => {
+                if self.col == self.cols {
+                    self.newline();
+                }
+                self.cells[self.row * self.cols + self.col] = c;
+                self.draw_cell(self.col, self.row);
+                self.col += 1;
+            }
+        }
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Clear the selected screens and start mirroring the kernel log onto them.
+pub fn init() {
+    let Some(c) = Console::new() else {
+        kprintln!("[fbcon] disabled");
+        return;
+    };
+    for s in c.targets.iter() {
+        s.clear(BG);
+    }
+    kprintln!(
+        "[fbcon] {}x{} cells on {} screen(s), font {} x{}",
+        c.cols,
+        c.rows,
+        c.targets.len(),
+        c.style.font.name,
+        c.style.scale
+    );
+    without_interrupts(|| *CONSOLE.lock() = Some(c));
+    serial::add_log_mirror(log_sink);
+}
+
+pub fn write_str(s: &str) {
+    without_interrupts(|| {
+        if let Some(c) = CONSOLE.lock().as_mut() {
+            s.bytes().for_each(|b| c.putc(b));
+        }
+    });
+}
+
+// Log mirror: drops output rather than wait on a console held elsewhere.
+fn log_sink(s: &str) {
+    without_interrupts(|| {
+        if let Some(mut g) = CONSOLE.try_lock()
+            && let Some(c) = g.as_mut()
+        {
+            s.bytes().for_each(|b| c.putc(b));
+        }
+    });
+}
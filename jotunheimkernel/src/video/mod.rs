@@ -0,0 +1,248 @@
+// src/video/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Linear framebuffers handed over by jotunboot, one per GOP instance. Each is
+// mapped once at init; `fb=` on the command line picks which of them carry the
+// text console: `fb=N` for one of them, `fb=mirror` for all, `fb=off` for
+// none. The default is the primary (firmware console) output. Screens left
+// without the console show the boot splash instead. Pixels may be 32, 24 or
+// 16 bits, with channels where the GOP's bitmask puts them.
+
+pub mod console;
+pub mod panic;
+pub mod splash;
+
+use heapless::Vec;
+use spin::Once;
+
+use crate::bootinfo::{
+    BootInfo, Framebuffer, MAX_FRAMEBUFFERS, PIXEL_FORMAT_BGR, PIXEL_FORMAT_BITMASK,
+    PIXEL_FORMAT_BLT_ONLY, PIXEL_FORMAT_RGB,
+};
+use crate::{cmdline, kprintln, mem};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+    Rgb,     // byte order R, G, B, reserved
+    Bgr,     // byte order B, G, R, reserved
+    Bitmask, // channels where the masks say
+}
+
+/// Where one colour channel sits in a native pixel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+/// Which screens the console draws on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Output {
+    Off,
+    One(usize),
+    Mirror,
+}
+
+/// One mapped framebuffer. `stride` is in pixels, `pitch` in bytes.
+pub struct Screen {
+    pub index: usize,
+    pub width: usize,
+    pub height: usize,
+    pub stride: usize,
+    pub bpp: usize,
+    pub format: PixelFormat,
+    pitch: usize,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    base: *mut u8,
+}
+
+// The mapping is never torn down; writers serialize through their own locks.
+unsafe impl Send for Screen {}
+unsafe impl Sync for Screen {}
+
+static SCREENS: Once<Vec<Screen, MAX_FRAMEBUFFERS>> = Once::new();
+
+/* --------------------------------- Screens ---------------------------------- */
+
+impl Channel {
+    fn from_mask(mask: u32) -> Option<Self> {
+        if mask == 0 {
+            return None;
+        }
+        let shift = mask.trailing_zeros();
+        Some(Self {
+            shift,
+            bits: (mask >> shift).trailing_ones(),
+        })
+    }
+
+    // An 8-bit intensity, scaled to this channel and moved into place.
+    fn place(self, v: u32) -> u32 {
+        let v = if self.bits <= 8 {
+            v >> (8 - self.bits)
+        } else {
+            v << (self.bits - 8)
+        };
+        v << self.shift
+    }
+}
+
+impl Screen {
+    fn map(index: usize, fb: &Framebuffer) -> Option<Self> {
+        if fb.addr == 0 || fb.pitch == 0 || fb.width == 0 {
+            return None;
+        }
+        if !matches!(fb.bpp, 16 | 24 | 32) {
+            return None;
+        }
+        // BltOnly (or anything newer) has no pixels we can store to.
+        let (format, masks) = match fb.pixel_format {
+            PIXEL_FORMAT_RGB => (PixelFormat::Rgb, [0xFF, 0xFF00, 0xFF_0000]),
+            PIXEL_FORMAT_BGR => (PixelFormat::Bgr, [0xFF_0000, 0xFF00, 0xFF]),
+            PIXEL_FORMAT_BITMASK => (
+                PixelFormat::Bitmask,
+                [fb.red_mask, fb.green_mask, fb.blue_mask],
+            ),
+            _ => return None,
+        };
+        let [Some(red), Some(green), Some(blue)] = masks.map(Channel::from_mask) else {
+            return None;
+        };
+        let bpp = fb.bpp as usize / 8;
+        let pitch = fb.pitch as usize;
+        let base = mem::map_mmio(fb.addr, pitch * fb.height as usize) as *mut u8;
+        Some(Self {
+            index,
+            width: fb.width as usize,
+            height: fb.height as usize,
+            stride: pitch / bpp,
+            bpp,
+            format,
+            pitch,
+            red,
+            green,
+            blue,
+            base,
+        })
+    }
+
+    /// `0xRRGGBB` in this screen's pixel layout.
+    pub fn color(&self, rgb: u32) -> u32 {
+        self.red.place((rgb >> 16) & 0xFF)
+            | self.green.place((rgb >> 8) & 0xFF)
+            | self.blue.place(rgb & 0xFF)
+    }
+
+    // Store one native pixel at `at`, in as many bytes as the screen uses.
+    unsafe fn store(&self, at: *mut u8, px: u32) {
+        unsafe {
+            match self.bpp {
+                4 => (at as *mut u32).write_volatile(px),
+                3 => {
+                    at.write_volatile(px as u8);
+                    at.add(1).write_volatile((px >> 8) as u8);
+                    at.add(2).write_volatile((px >> 16) as u8);
+                }
+                _ => (at as *mut u16).write_volatile(px as u16),
+            }
+        }
+    }
+
+    /// Store a native pixel; out-of-range coordinates are ignored.
+    pub fn put(&self, x: usize, y: usize, px: u32) {
+        if x < self.width && y < self.height {
+            unsafe { self.store(self.base.add(y * self.pitch + x * self.bpp), px) };
+        }
+    }
+
+    /// Fill a rectangle with a native pixel, clipped to the screen.
+    pub fn fill(&self, x: usize, y: usize, w: usize, h: usize, px: u32) {
+        let x1 = (x + w).min(self.width);
+        let y1 = (y + h).min(self.height);
+        for yy in y.min(y1)..y1 {
+            let row = unsafe { self.base.add(yy * self.pitch) };
+            for xx in x.min(x1)..x1 {
+                unsafe { self.store(row.add(xx * self.bpp), px) };
+            }
+        }
+    }
+
+    pub fn clear(&self, rgb: u32) {
+        self.fill(0, 0, self.width, self.height, self.color(rgb));
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Map every framebuffer from BootInfo and bring up the console on the ones
+/// `fb=` selects. Needs the heap and MMIO mapping.
+pub fn init(boot: &BootInfo) {
+    let screens = SCREENS.call_once(|| {
+        let mut v = Vec::new();
+        for (i, fb) in boot.framebuffers().iter().enumerate() {
+            match Screen::map(i, fb) {
+                Some(s) => {
+                    kprintln!(
+                        "[video] fb{}: {}x{} stride {} {}bpp {:?}",
+                        i,
+                        s.width,
+                        s.height,
+                        s.stride,
+                        s.bpp * 8,
+                        s.format
+                    );
+                    let _ = v.push(s);
+                }
+                None if fb.pixel_format == PIXEL_FORMAT_BLT_ONLY => {
+                    kprintln!("[video] fb{}: BltOnly, no linear framebuffer", i)
+                }
+                None => kprintln!("[video] fb{}: unusable ({:?})", i, fb),
+            }
+        }
+        v
+    });
+    if screens.is_empty() {
+        kprintln!("[video] no usable framebuffer; serial console only");
+        return;
+    }
+    splash::init();
+    console::init();
+}
+
+pub fn screens() -> &'static [Screen] {
+    SCREENS.get().map(|v| v.as_slice()).unwrap_or(&[])
+}
+
+pub fn screen(index: usize) -> Option<&'static Screen> {
+    screens().iter().find(|s| s.index == index)
+}
+
+/// `fb=` from the command line; an unknown index falls back to the primary.
+pub fn output() -> Output {
+    match cmdline::value("fb") {
+        Some("off") | Some("none") => Output::Off,
+        Some("mirror") | Some("all") => Output::Mirror,
+        Some(n) => match n.parse() {
+            Ok(i) if screen(i).is_some() => Output::One(i),
+            _ => {
+                kprintln!("[video] fb={} not found, using fb0", n);
+                Output::One(0)
+            }
+        },
+        None => Output::One(0),
+    }
+}
+
+/// The screens `output()` selects.
+pub fn outputs() -> impl Iterator<Item = &'static Screen> {
+    let out = output();
+    screens().iter().filter(move |s| match out {
+        Output::Off => false,
+        Output::One(i) => s.index == i,
+        Output::Mirror => true,
+    })
+}
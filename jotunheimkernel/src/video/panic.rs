@@ -0,0 +1,140 @@
+// src/video/panic.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Panic screen, drawn straight onto every mapped framebuffer: the message,
+// the location, the backtrace and a QR code of the crash record, so a crash
+// can be reported from a phone when nothing is listening on serial. Runs in
+// the panic handler: no heap, and locks are only ever tried.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use heapless::String;
+use spin::Mutex;
+
+use super::Screen;
+use crate::debug::crash::{CrashRecord, TEXT_MAX};
+use crate::gfx::qr::{Ecc, QrCode};
+use crate::gfx::{self, Painter, Rect, TextStyle, font};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const BG: u32 = 0x800000;
+const FG: u32 = gfx::WHITE;
+const DIM: u32 = 0xF0B0B0;
+const MARGIN: i32 = 16;
+
+static QR: Mutex<QrCode> = Mutex::new(QrCode::new());
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/* --------------------------------- Drawing ---------------------------------- */
+
+// Lines of text top-down from `y`, wrapped at `cols` characters.
+struct Column<'p, 's> {
+    p: &'p mut Painter<'s, &'static Screen>,
+    x: i32,
+    y: i32,
+    cols: usize,
+}
+
+impl Column<'_, '_> {
+    fn line(&mut self, s: &str, st: &TextStyle) {
+        let (_, h) = st.cell();
+        let mut rest = s;
+        loop {
+            let cut = rest.len().min(self.cols);
+            let cut = (0..=cut)
+                .rev()
+                .find(|&i| rest.is_char_boundary(i))
+                .unwrap_or(0);
+            self.p.text(self.x, self.y, &rest[..cut], st);
+            self.y += h + 2;
+            rest = &rest[cut..];
+            if rest.is_empty() || cut == 0 {
+                break;
+            }
+        }
+    }
+}
+
+fn draw(s: &'static Screen, rec: &CrashRecord, qr: Option<&QrCode>) {
+    let mut target = s;
+    let mut p = Painter::new(&mut target);
+    p.fill(p.bounds(), BG);
+
+    // QR on the right, up to half the height, at least 2 px per module.
+    let mut text_w = s.width as i32 - 2 * MARGIN;
+    if let Some(q) = qr {
+        let modules = q.size() as i32 + 8;
+        let scale = (s.height as i32 / 2 / modules).min(s.width as i32 / 3 / modules);
+        if scale >= 2 {
+            let side = modules * scale;
+            let x = s.width as i32 - MARGIN - side;
+            q.draw(&mut p, x, MARGIN, scale, gfx::BLACK, gfx::WHITE);
+            let cap = TextStyle::new(&font::FONT_8X16, FG);
+            p.text(x, MARGIN + side + 4, "scan to report", &cap);
+            text_w = x - 2 * MARGIN;
+        }
+    }
+
+    let scale = if s.width >= 2560 { 2 } else { 1 };
+    let title = TextStyle::new(&font::FONT_8X16, FG).scaled(2 * scale);
+    let body = TextStyle::new(&font::FONT_8X16, FG).scaled(scale);
+    let dim = TextStyle::new(&font::FONT_8X16, DIM).scaled(scale);
+    let cols = (text_w / body.cell().0).max(1) as usize;
+
+    p.set_clip(Rect::new(
+        MARGIN,
+        MARGIN,
+        text_w,
+        s.height as i32 - 2 * MARGIN,
+    ));
+    let mut c = Column {
+        p: &mut p,
+        x: MARGIN,
+        y: MARGIN,
+        cols,
+    };
+    c.line("KERNEL PANIC", &title);
+    c.y += 8;
+    c.line(&rec.msg, &body);
+    c.y += 8;
+
+    let mut l: String<160> = String::new();
+    let _ = write!(l, "at {}:{}:{}", rec.file, rec.line, rec.col);
+    c.line(&l, &dim);
+    l.clear();
+    let _ = write!(l, "cpu {}  tick {}", rec.cpu, rec.tick);
+    c.line(&l, &dim);
+    c.y += 8;
+    c.line("backtrace:", &body);
+    for (i, f) in rec.frames.iter().enumerate() {
+        l.clear();
+        let _ = write!(l, "  #{:<2} {:#018x}", i, f);
+        c.line(&l, &body);
+    }
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Paint the panic screen on every framebuffer. Only the first panic draws;
+/// a nested one leaves it in place.
+pub fn show(rec: &CrashRecord) {
+    if super::screens().is_empty() || SHOWN.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let mut text = [0u8; TEXT_MAX];
+    let len = rec.to_text(&mut text);
+    // Medium error correction copes better with a photographed screen;
+    // fall back to Low when the record does not fit.
+    let mut guard = QR.try_lock();
+    let qr = guard.as_deref_mut().and_then(|q| {
+        q.encode(&text[..len], Ecc::Medium)
+            .or_else(|_| q.encode(&text[..len], Ecc::Low))
+            .ok()
+            .map(|_| &*q)
+    });
+    for s in super::screens() {
+        draw(s, rec, qr);
+    }
+}
@@ -0,0 +1,104 @@
+// src/video/splash.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Boot splash: a title and a progress bar, drawn on every screen the text
+// console is not using (all of them with `fb=off`). `progress()` advances the
+// bar as the main thread brings subsystems up. The title is drawn once
+// into a `Canvas` per scale and blitted, so identical heads share it.
+
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::Screen;
+use crate::bootinfo::MAX_FRAMEBUFFERS;
+use crate::gfx::{Canvas, Painter, Rect, Surface, TextStyle, font};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const BG: u32 = 0x101820;
+const TITLE: u32 = 0xE0E6F0;
+const DIM: u32 = 0x7080A0;
+const BAR: u32 = 0x4C8ED0;
+
+const NAME: &str = "JOTUNHEIM";
+const SUBTITLE: &str = "starting up";
+
+static SCREENS: Mutex<heapless::Vec<&'static Screen, MAX_FRAMEBUFFERS>> =
+    Mutex::new(heapless::Vec::new());
+
+/* --------------------------------- Drawing ---------------------------------- */
+
+// Outer rectangle of the progress bar, below the centre of `s`.
+fn bar_rect(s: &Screen) -> Rect {
+    let w = (s.width / 3) as i32;
+    let h = 12;
+    Rect::new((s.width as i32 - w) / 2, s.height as i32 * 5 / 8, w, h)
+}
+
+// Title with the subtitle centred under it, on the background colour.
+fn banner(scale: usize) -> Canvas {
+    let title = TextStyle::new(&font::FONT_8X16, TITLE).scaled(scale);
+    let sub = TextStyle::new(&font::FONT_8X8, DIM).scaled((scale / 2).max(1));
+    let (tw, th) = title.size(NAME);
+    let (sw, sh) = sub.size(SUBTITLE);
+    let w = tw.max(sw);
+    let mut c = Canvas::new(w as usize, (th + th / 4 + sh) as usize, BG);
+    let mut p = c.painter();
+    p.text((w - tw) / 2, 0, NAME, &title);
+    p.text((w - sw) / 2, th + th / 4, SUBTITLE, &sub);
+    c
+}
+
+fn draw(s: &'static Screen, cache: &mut Option<(usize, Canvas)>) {
+    let mut target = s;
+    let mut p = Painter::new(&mut target);
+    p.fill(p.bounds(), BG);
+
+    // Title about a third of the screen wide.
+    let one = TextStyle::new(&font::FONT_8X16, TITLE).size(NAME).0 as usize;
+    let scale = (s.width / 3 / one).max(1);
+    let b = match cache {
+        Some((sc, b)) if *sc == scale => b,
+        _ => &cache.insert((scale, banner(scale))).1,
+    };
+    let (w, h) = (b.width() as i32, b.height() as i32);
+    let th = TextStyle::new(&font::FONT_8X16, TITLE)
+        .scaled(scale)
+        .cell()
+        .1;
+    p.blit(
+        b,
+        Rect::new(0, 0, w, h),
+        (s.width as i32 - w) / 2,
+        s.height as i32 / 2 - th,
+    );
+
+    p.frame(bar_rect(s), 1, DIM);
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Draw the splash on every mapped screen the console left alone.
+pub fn init() {
+    let mut list = heapless::Vec::new();
+    let mut cache = None;
+    for s in super::screens() {
+        if !super::outputs().any(|o| o.index == s.index) {
+            draw(s, &mut cache);
+            let _ = list.push(s);
+        }
+    }
+    without_interrupts(|| *SCREENS.lock() = list);
+}
+
+/// Fill the progress bar to `done / total`.
+pub fn progress(done: usize, total: usize) {
+    let total = total.max(1);
+    without_interrupts(|| {
+        for mut s in SCREENS.lock().iter().copied() {
+            let inner = bar_rect(s).inset(2);
+            let w = inner.w * done.min(total) as i32 / total as i32;
+            Painter::new(&mut s).fill(Rect { w, ..inner }, BAR);
+        }
+    });
+}
@@ -0,0 +1,411 @@
+// src/virtio/console.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// virtio-console (virtio-serial). Port 0 mirrors the kernel log, port 1
+// carries the RSP stub when present. With MULTIPORT the control queues
+// announce ports; without it only port 0 exists. Received data is taken by
+// an irq::poll poller woken from the device's INTx line; without a line,
+// readers poll. Sends are synchronous and never interrupt.
+// After a hot-unplug the console reads as absent; its queue pages leak.
+extern crate alloc;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{BUF_SIZE, F_EVENT_IDX, ISR_QUEUE, LegacyPci, VENDOR, Virtqueue};
+use crate::arch::x86_64::serial;
+use crate::error::KResult;
+use crate::irq::{self, Trigger, poll};
+use crate::util::ring::Ring;
+use crate::{kprintln, pci};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+const DEVICE_LEGACY: u16 = 0x1003;
+const F_MULTIPORT: u32 = 1 << 1;
+const CFG_MAX_PORTS: u16 = 4;
+
+pub const LOG_PORT: usize = 0;
+pub const DEBUG_PORT: usize = 1;
+const NPORTS: usize = 2;
+
+// Control events (virtio spec 5.3.6.2)
+const DEVICE_READY: u16 = 0;
+const DEVICE_ADD: u16 = 1;
+const PORT_READY: u16 = 3;
+const CONSOLE_PORT: u16 = 4;
+const PORT_OPEN: u16 = 6;
+
+const TX_SPIN: usize = 1_000_000;
+const INBOX: usize = 1024;
+
+pub const VECTOR: u8 = 0x51;
+
+struct Port {
+    rx: Virtqueue,
+    tx: Virtqueue,
+    inbox: Ring<u8, INBOX>,
+    host_open: bool,
+    dead: bool, // device stopped completing TX; drop output from now on
+}
+
+pub struct VirtioConsole {
+    dev: LegacyPci,
+    pci: pci::PciDevice,
+    event_idx: bool,
+    ports: [Option<Port>; NPORTS],
+    ctrl: Option<(Virtqueue, Virtqueue)>, // (rx, tx)
+}
+
+// The RX side under irq::poll.
+struct RxPoll;
+
+static CONSOLE: Once<Option<Mutex<VirtioConsole>>> = Once::new();
+static REMOVED: AtomicBool = AtomicBool::new(false);
+static IO: Once<LegacyPci> = Once::new(); // for the ISR, which takes no lock
+static POLLER: Once<Arc<poll::Poller>> = Once::new();
+static FRAMES: AtomicU32 = AtomicU32::new(0); // moderation, 0 = every buffer
+
+/* --------------------------------- Helpers ---------------------------------- */
+
+fn post_all(dev: &LegacyPci, q: &mut Virtqueue) {
+    for id in 0..q.nbufs() {
+        q.push(id, BUF_SIZE, true);
+    }
+    dev.notify(q);
+}
+
+// Synchronous send through buffer 0; waits for the device to consume it.
+fn send(dev: &LegacyPci, q: &mut Virtqueue, data: &[u8]) -> bool {
+    for chunk in data.chunks(BUF_SIZE) {
+        q.buf(0)[..chunk.len()].copy_from_slice(chunk);
+        q.push(0, chunk.len(), false);
+        dev.notify(q);
+        let mut spins = 0;
+        while q.pop_used().is_none() {
+            spins += 1;
+            if spins == TX_SPIN {
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    true
+}
+
+impl Port {
+    fn new(dev: &LegacyPci, rxq: u16, txq: u16) -> KResult<Self> {
+        Ok(Self {
+            rx: dev.setup_queue(rxq)?,
+            tx: dev.setup_queue(txq)?,
+            inbox: Ring::new(),
+            host_open: false,
+            dead: false,
+        })
+    }
+}
+
+/* ---------------------------------- Device ---------------------------------- */
+
+impl VirtioConsole {
+    fn probe() -> Option<Mutex<Self>> {
+        let pdev = pci::find(VENDOR, DEVICE_LEGACY)?;
+        let dev = LegacyPci::new(&pdev)?;
+        let feat = dev.begin(F_MULTIPORT | F_EVENT_IDX);
+        let multi = feat & F_MULTIPORT != 0;
+        let event_idx = feat & F_EVENT_IDX != 0;
+        let nports = if multi {
+            (dev.config_u32(CFG_MAX_PORTS) as usize).min(NPORTS)
+        } else {
+            1
+        };
+
+        let Ok(p0) = Port::new(&dev, 0, 1) else {
+            dev.fail();
+            return None;
+        };
+        let ctrl = if multi {
+            Some((dev.setup_queue(2).ok()?, dev.setup_queue(3).ok()?))
+        } else {
+            None
+        };
+        // Port n>0 lives on queues 2n+2 / 2n+3.
+        let p1 = (nports > 1).then(|| Port::new(&dev, 4, 5).ok()).flatten();
+
+        let mut c = Self {
+            dev,
+            pci: pdev,
+            event_idx,
+            ports: [Some(p0), p1],
+            ctrl,
+        };
+        for p in c.ports.iter_mut().flatten() {
+            p.rx.set_event_idx(event_idx);
+            p.tx.set_event_idx(event_idx);
+            p.tx.disable_interrupts();
+        }
+        if let Some((rx, tx)) = c.ctrl.as_mut() {
+            rx.set_event_idx(event_idx);
+            tx.set_event_idx(event_idx);
+            tx.disable_interrupts();
+        }
+        c.dev.driver_ok();
+        for p in c.ports.iter_mut().flatten() {
+            post_all(&c.dev, &mut p.rx);
+        }
+        if let Some((rx, _)) = c.ctrl.as_mut() {
+            post_all(&c.dev, rx);
+        }
+        if multi {
+            c.control(0, DEVICE_READY, 1);
+        }
+        c.poll();
+        pci::hotplug::bind(&pdev, "virtio-console", removed);
+        kprintln!(
+            "[virtio-con] ready, {} port(s), multiport={} event_idx={}",
+            c.ports.iter().flatten().count(),
+            multi,
+            event_idx
+        );
+        Some(Mutex::new(c))
+    }
+
+    fn control(&mut self, id: u32, event: u16, value: u16) {
+        let Some((_, tx)) = self.ctrl.as_mut() else {
+            return;
+        };
+        let mut msg = [0u8; 8];
+        msg[0..4].copy_from_slice(&id.to_le_bytes());
+        msg[4..6].copy_from_slice(&event.to_le_bytes());
+        msg[6..8].copy_from_slice(&value.to_le_bytes());
+        send(&self.dev, tx, &msg);
+    }
+
+    fn on_control(&mut self, id: u32, event: u16, value: u16) {
+        let ours = (id as usize) < NPORTS && self.ports[id as usize].is_some();
+        match event {
+            DEVICE_ADD => {
+                self.control(id, PORT_READY, ours as u16);
+                if ours {
+                    self.control(id, PORT_OPEN, 1);
+                }
+            }
+            CONSOLE_PORT if ours => self.control(id, PORT_OPEN, 1),
+            PORT_OPEN if ours => {
+                if let Some(p) = self.ports[id as usize].as_mut() {
+                    p.host_open = value != 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rx_queues(&mut self) -> impl Iterator<Item = &mut Virtqueue> {
+        let ports = self.ports.iter_mut().flatten().map(|p| &mut p.rx);
+        ports.chain(self.ctrl.as_mut().map(|(rx, _)| rx))
+    }
+
+    /// Drain control and RX queues; received bytes land in per-port inboxes.
+    pub fn poll(&mut self) {
+        self.drain(usize::MAX);
+    }
+
+    // At most `budget` RX buffers, plus a few control messages; returns how
+    // many buffers were taken.
+    fn drain(&mut self, budget: usize) -> usize {
+        let mut done = 0;
+        let mut msgs = [(0u32, 0u16, 0u16); 8];
+        let mut n = 0;
+        if let Some((rx, _)) = self.ctrl.as_mut() {
+            while n < msgs.len()
+                && let Some((id, len)) = rx.pop_used()
+            {
+                let b = rx.buf(id);
+                if len >= 8 {
+                    msgs[n] = (
+                        u32::from_le_bytes(b[0..4].try_into().unwrap()),
+                        u16::from_le_bytes([b[4], b[5]]),
+                        u16::from_le_bytes([b[6], b[7]]),
+                    );
+                    n += 1;
+                }
+                rx.push(id, BUF_SIZE, true);
+            }
+            if n > 0 {
+                self.dev.notify(rx);
+            }
+        }
+        for &(id, ev, val) in &msgs[..n] {
+            self.on_control(id, ev, val);
+        }
+
+        for p in self.ports.iter_mut().flatten() {
+            let mut any = false;
+            while done < budget
+                && let Some((id, len)) = p.rx.pop_used()
+            {
+                for &b in &p.rx.buf(id)[..len.min(BUF_SIZE)] {
+                    let _ = p.inbox.push_mut(b); // drop on overflow
+                }
+                p.rx.push(id, BUF_SIZE, true);
+                any = true;
+                done += 1;
+            }
+            if any {
+                self.dev.notify(&p.rx);
+            }
+        }
+        done + n
+    }
+
+    pub fn write(&mut self, port: usize, data: &[u8]) {
+        let Some(p) = self.ports.get_mut(port).and_then(|p| p.as_mut()) else {
+            return;
+        };
+        if !p.dead && !send(&self.dev, &mut p.tx, data) {
+            p.dead = true;
+        }
+    }
+
+    pub fn read_byte(&mut self, port: usize) -> Option<u8> {
+        if let Some(b) = self.ports.get_mut(port)?.as_mut()?.inbox.pop_mut() {
+            return Some(b);
+        }
+        self.poll();
+        self.ports[port].as_mut()?.inbox.pop_mut()
+    }
+}
+
+/* --------------------------------- Interrupts -------------------------------- */
+
+impl poll::PollDevice for RxPoll {
+    fn poll(&self, budget: usize) -> usize {
+        ready().map_or(0, |c| without_interrupts(|| c.lock().drain(budget)))
+    }
+
+    // From the ISR, so only if the console is free: it is just a hint.
+    fn irq_disable(&self) {
+        if let Some(mut c) = ready().and_then(|c| c.try_lock()) {
+            c.rx_queues().for_each(|q| q.disable_interrupts());
+        }
+    }
+
+    fn irq_enable(&self) -> bool {
+        let Some(c) = ready() else {
+            return true;
+        };
+        let n = FRAMES.load(Ordering::Relaxed).max(1) as u16;
+        without_interrupts(|| {
+            // Every queue, even after one reports work.
+            let mut c = c.lock();
+            c.rx_queues()
+                .fold(true, |idle, q| q.interrupt_after(n) & idle)
+        })
+    }
+
+    fn moderate(&self, m: poll::Moderation) -> bool {
+        FRAMES.store(m.frames, Ordering::Relaxed);
+        ready().is_some_and(|c| without_interrupts(|| c.lock().event_idx))
+    }
+}
+
+// Take the INTx line; the line goes with the function on hot-unplug.
+fn take_irq(c: &Mutex<VirtioConsole>) {
+    let (dev, pdev) = without_interrupts(|| {
+        let c = c.lock();
+        (c.dev, c.pci)
+    });
+    let Some(gsi) = pdev.irq_line() else {
+        kprintln!("[virtio-con] no interrupt line; polling");
+        return;
+    };
+    IO.call_once(|| dev);
+    if let Err(e) = irq::register("virtio-console", gsi, VECTOR, Trigger::Level) {
+        kprintln!("[virtio-con] gsi {}: {:?}; polling", gsi, e);
+        return;
+    }
+    pci::hotplug::own_irq(pdev.addr, gsi);
+    let p = POLLER.call_once(|| poll::register("virtio-console", Arc::new(RxPoll)));
+    // Anything that came in before the poller existed was acked unseen.
+    p.schedule();
+}
+
+/* -------------------------------- Public API -------------------------------- */
+
+/// Probe once; on success port 0 starts mirroring the kernel log and the
+/// RX queues are put on the device's interrupt. Needs the executor.
+pub fn init() {
+    if let Some(c) = get() {
+        serial::add_log_mirror(log_sink);
+        take_irq(c);
+    }
+}
+
+/// INTx handler. Reading the ISR status lowers the line; the line may be
+/// shared, so a read showing no queue work is not ours.
+pub fn on_irq() {
+    let Some(dev) = IO.get() else {
+        return;
+    };
+    if dev.isr() & ISR_QUEUE == 0 {
+        crate::counter!("virtio.irq_not_ours");
+        return;
+    }
+    match POLLER.get() {
+        Some(p) => p.schedule(),
+        None => crate::counter!("virtio.early_irq"),
+    }
+}
+
+pub fn get() -> Option<&'static Mutex<VirtioConsole>> {
+    if REMOVED.load(Ordering::Acquire) {
+        return None;
+    }
+    CONSOLE.call_once(VirtioConsole::probe).as_ref()
+}
+
+// Never probes: usable from the debug trap before `init()` has run.
+fn ready() -> Option<&'static Mutex<VirtioConsole>> {
+    if REMOVED.load(Ordering::Acquire) {
+        return None;
+    }
+    CONSOLE.get().and_then(|c| c.as_ref())
+}
+
+// Unplugged: no new users, then wait out the one holding the lock.
+fn removed(_: &pci::PciDevice) {
+    REMOVED.store(true, Ordering::Release);
+    if let Some(c) = CONSOLE.get().and_then(|c| c.as_ref()) {
+        without_interrupts(|| drop(c.lock()));
+    }
+}
+
+pub fn has_port(port: usize) -> bool {
+    ready().is_some_and(|c| {
+        without_interrupts(|| c.lock().ports.get(port).is_some_and(|p| p.is_some()))
+    })
+}
+
+pub fn write(port: usize, data: &[u8]) {
+    if let Some(c) = ready() {
+        without_interrupts(|| c.lock().write(port, data));
+    }
+}
+
+pub fn read_byte(port: usize) -> Option<u8> {
+    ready().and_then(|c| without_interrupts(|| c.lock().read_byte(port)))
+}
+
+// Log mirror: never spin on the lock, the console itself may be logging.
+fn log_sink(s: &str) {
+    let Some(c) = ready() else {
+        return;
+    };
+    without_interrupts(|| {
+        if let Some(mut g) = c.try_lock() {
+            g.write(LOG_PORT, s.as_bytes());
+        }
+    });
+}
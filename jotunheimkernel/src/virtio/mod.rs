@@ -0,0 +1,275 @@
+// src/virtio/mod.rs
+// SPDX-License-Identifier: JOSSL-1.0
+// Copyright (C) 2025 The Jotunheim Project
+// Legacy (0.9.5 / transitional) virtio-pci transport on I/O BAR0 and polled
+// split virtqueues. Each descriptor owns one fixed buffer for its lifetime,
+// so there is no descriptor allocator and no chaining. Drivers that take
+// interrupts can suppress them per queue, or with EVENT_IDX ask for one
+// only every n completions.
+
+pub mod console;
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{Ordering, fence};
+
+use x86_64::instructions::port::Port;
+
+use crate::arch::native::cache;
+use crate::error::{KError, KResult};
+use crate::mem::{frames, try_alloc_low32_contig};
+use crate::pci::{Bar, CMD_BUS_MASTER, CMD_IO, PciDevice};
+
+/* ------------------------------- Types & consts ------------------------------- */
+
+pub const VENDOR: u16 = 0x1af4;
+
+const REG_DEV_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SEL: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+const REG_ISR: u16 = 0x13;
+const REG_CONFIG: u16 = 0x14; // device config when MSI-X is off
+
+/// ISR status bit: a queue has used buffers.
+pub const ISR_QUEUE: u8 = 1;
+
+const STATUS_ACK: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FAILED: u8 = 0x80;
+
+const DESC_F_WRITE: u16 = 2;
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+/// Feature bit: used_event / avail_event fields (virtio 1.0 2.4.7).
+pub const F_EVENT_IDX: u32 = 1 << 29;
+
+pub const BUF_SIZE: usize = 256;
+const MAX_BUFS: u16 = 16; // per queue: one page of buffers
+
+#[repr(C)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[derive(Copy, Clone)]
+pub struct LegacyPci {
+    io: u16,
+}
+
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    nbufs: u16,
+    desc: *mut Desc,
+    avail: *mut u16, // flags, idx, ring[size]
+    used: *mut u8,   // flags, idx, ring[size] of {id: u32, len: u32}
+    bufs_va: u64,
+    bufs_pa: u64,
+    last_used: u16,
+    event_idx: bool, // F_EVENT_IDX accepted: used_event is live
+}
+
+// Only touched under the owning driver's lock.
+unsafe impl Send for Virtqueue {}
+
+/* -------------------------------- Transport --------------------------------- */
+
+impl LegacyPci {
+    pub fn new(dev: &PciDevice) -> Option<Self> {
+        let Some(Bar::Io { port, .. }) = dev.bar(0) else {
+            return None;
+        };
+        dev.set_command(CMD_IO | CMD_BUS_MASTER);
+        Some(Self { io: port })
+    }
+
+    fn r8(&self, r: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.io + r).read() }
+    }
+    fn r16(&self, r: u16) -> u16 {
+        unsafe { Port::<u16>::new(self.io + r).read() }
+    }
+    fn r32(&self, r: u16) -> u32 {
+        unsafe { Port::<u32>::new(self.io + r).read() }
+    }
+    fn w8(&self, r: u16, v: u8) {
+        unsafe { Port::<u8>::new(self.io + r).write(v) }
+    }
+    fn w16(&self, r: u16, v: u16) {
+        unsafe { Port::<u16>::new(self.io + r).write(v) }
+    }
+    fn w32(&self, r: u16, v: u32) {
+        unsafe { Port::<u32>::new(self.io + r).write(v) }
+    }
+
+    /// Reset, acknowledge, and accept `wanted & offered`; returns the accepted set.
+    pub fn begin(&self, wanted: u32) -> u32 {
+        self.w8(REG_STATUS, 0);
+        self.w8(REG_STATUS, STATUS_ACK);
+        self.w8(REG_STATUS, STATUS_ACK | STATUS_DRIVER);
+        let feat = self.r32(REG_DEV_FEATURES) & wanted;
+        self.w32(REG_GUEST_FEATURES, feat);
+        feat
+    }
+
+    pub fn driver_ok(&self) {
+        self.w8(REG_STATUS, STATUS_ACK | STATUS_DRIVER | STATUS_DRIVER_OK);
+    }
+
+    pub fn fail(&self) {
+        self.w8(REG_STATUS, STATUS_FAILED);
+    }
+
+    /// Reading ISR status also acknowledges the interrupt.
+    pub fn isr(&self) -> u8 {
+        self.r8(REG_ISR)
+    }
+
+    pub fn config_u32(&self, off: u16) -> u32 {
+        self.r32(REG_CONFIG + off)
+    }
+
+    pub fn notify(&self, q: &Virtqueue) {
+        self.w16(REG_QUEUE_NOTIFY, q.index);
+    }
+
+    /// Allocate and register queue `index`. NotFound if the device lacks it.
+    pub fn setup_queue(&self, index: u16) -> KResult<Virtqueue> {
+        self.w16(REG_QUEUE_SEL, index);
+        let size = self.r16(REG_QUEUE_SIZE);
+        if size == 0 {
+            return Err(KError::NotFound);
+        }
+        let n = size as usize;
+        // Legacy layout: desc + avail (with used_event after the ring), then
+        // used on the next 4 KiB boundary.
+        let used_off = (16 * n + 6 + 2 * n).next_multiple_of(4096);
+        let used_len = (6 + 8 * n).next_multiple_of(4096);
+        let pages = (used_off + used_len) / 4096;
+        let (va, pa) = try_alloc_low32_contig(pages)?;
+        let (bufs_va, bufs_pa) = match try_alloc_low32_contig(1) {
+            Ok(b) => b,
+            Err(e) => {
+                frames::free_contig(pa, pages);
+                return Err(e);
+            }
+        };
+        self.w32(REG_QUEUE_PFN, (pa >> 12) as u32);
+
+        let nbufs = size.min(MAX_BUFS).min((4096 / BUF_SIZE) as u16);
+        Ok(Virtqueue {
+            index,
+            size,
+            nbufs,
+            desc: va as *mut Desc,
+            avail: (va + 16 * n as u64) as *mut u16,
+            used: (va + used_off as u64) as *mut u8,
+            bufs_va,
+            bufs_pa,
+            last_used: 0,
+            event_idx: false,
+        })
+    }
+}
+
+/* -------------------------------- Virtqueues -------------------------------- */
+
+impl Virtqueue {
+    pub fn nbufs(&self) -> u16 {
+        self.nbufs
+    }
+
+    pub fn buf(&mut self, id: u16) -> &mut [u8; BUF_SIZE] {
+        unsafe { &mut *((self.bufs_va + id as u64 * BUF_SIZE as u64) as *mut [u8; BUF_SIZE]) }
+    }
+
+    /// Hand buffer `id` (its first `len` bytes) to the device.
+    pub fn push(&mut self, id: u16, len: usize, device_writes: bool) {
+        debug_assert!(id < self.nbufs && len <= BUF_SIZE);
+        unsafe {
+            let d = self.desc.add(id as usize);
+            write_volatile(
+                d,
+                Desc {
+                    addr: self.bufs_pa + id as u64 * BUF_SIZE as u64,
+                    len: len as u32,
+                    flags: if device_writes { DESC_F_WRITE } else { 0 },
+                    next: 0,
+                },
+            );
+            let idx = read_volatile(self.avail.add(1));
+            write_volatile(self.avail.add(2 + (idx % self.size) as usize), id);
+            fence(Ordering::Release);
+            write_volatile(self.avail.add(1), idx.wrapping_add(1));
+        }
+    }
+
+    /// Next buffer the device is done with: `(id, bytes written)`.
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let idx = unsafe { read_volatile(self.used.add(2) as *const u16) };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        let e = unsafe { self.used.add(4 + 8 * (self.last_used % self.size) as usize) };
+        let (id, len) = unsafe {
+            (
+                read_volatile(e as *const u32),
+                read_volatile(e.add(4) as *const u32),
+            )
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some((id as u16, len as usize))
+    }
+}
+
+/* --------------------------- Interrupt suppression --------------------------- */
+
+impl Virtqueue {
+    /// Call once the device accepted `F_EVENT_IDX`; the flag is then ignored
+    /// and `interrupt_after()` drives used_event.
+    pub fn set_event_idx(&mut self, on: bool) {
+        self.event_idx = on;
+    }
+
+    fn used_idx(&self) -> u16 {
+        unsafe { read_volatile(self.used.add(2) as *const u16) }
+    }
+
+    fn write_used_event(&mut self, idx: u16) {
+        unsafe { write_volatile(self.avail.add(2 + self.size as usize), idx) };
+    }
+
+    /// Ask the device not to interrupt for this queue. Only a hint: with
+    /// EVENT_IDX it is done by parking used_event half the index space away.
+    pub fn disable_interrupts(&mut self) {
+        if self.event_idx {
+            self.write_used_event(self.last_used.wrapping_add(0x8000));
+        } else {
+            unsafe { write_volatile(self.avail, AVAIL_F_NO_INTERRUPT) };
+        }
+    }
+
+    /// Interrupt again once `n` more buffers have been used; without
+    /// EVENT_IDX, on the next one. Returns false if completions arrived
+    /// while interrupts were off; the caller must poll again, since the
+    /// device will not interrupt for those.
+    pub fn interrupt_after(&mut self, n: u16) -> bool {
+        if self.event_idx {
+            let at = self.last_used.wrapping_add(n.max(1) - 1);
+            self.write_used_event(at);
+        } else {
+            unsafe { write_volatile(self.avail, 0) };
+        }
+        // Store-then-load: the event index must be visible before we look.
+        cache::mfence();
+        self.used_idx() == self.last_used
+    }
+}